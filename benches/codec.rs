@@ -0,0 +1,202 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anki_drive_sdk::advertisement::AnkiVehicleAdv;
+use anki_drive_sdk::error::AnkiError;
+use anki_drive_sdk::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse,
+    AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgOffsetFromRoadCentreUpdate,
+    AnkiVehicleMsgVersionResponse, WIRE_ENDIAN,
+};
+use anki_drive_sdk::AnkiVehicleData;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use scroll::ctx::TryFromCtx;
+use scroll::Pread;
+
+// Counts heap allocations, so a change that starts allocating on the telemetry path shows up
+// next to the timings.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ALLOCATION_RUNS: usize = 1000;
+
+fn report_allocations<F: FnMut()>(name: &str, mut f: F) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ALLOCATION_RUNS {
+        f();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{}: {:.2} allocations per iteration",
+        name,
+        allocations as f64 / ALLOCATION_RUNS as f64
+    );
+}
+
+const VERSION_RESPONSE: &[u8] = &[0x03, 0x19, 0x76, 0x26];
+const BATTERY_LEVEL_RESPONSE: &[u8] = &[0x03, 0x1b, 0xc4, 0x0e];
+const POSITION_UPDATE: &[u8] = &[
+    0x10, 0x27, 0x16, 0x24, 0x00, 0x00, 0x88, 0xc2, 0xf4, 0x01, 0x47, 0x00, 0x00, 0x00, 0x00, 0xf4,
+    0x01,
+];
+const TRANSITION_UPDATE: &[u8] = &[
+    0x11, 0x29, 0x00, 0x00, 0x00, 0x00, 0x88, 0xc2, 0x00, 0x00, 0x00, 0x00, 0xfd, 0x00, 0x00, 0x00,
+    0x16, 0x17,
+];
+const INTERSECTION_UPDATE: &[u8] = &[
+    0x0c, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x50, 0x00, 0x00, 0x00,
+];
+const OFFSET_UPDATE: &[u8] = &[0x06, 0x2d, 0x00, 0x00, 0x32, 0x42, 0x01];
+const ADVERTISEMENT: &[u8] = &[
+    0x12, 0x34, 0x89, 0xAB, 0xCD, 0xEF, 0xAB, 0x56, 0xCD, 0xEF, 0x0, 0xCD, 0xEF, 0x1, 0x2, 0x3,
+    0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a', b'm', b'e', b't', b'e', b's', b't', 0x0,
+    0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+];
+
+// Notifications in the proportions a driving vehicle sends them.
+const TELEMETRY: &[&[u8]] = &[
+    POSITION_UPDATE,
+    POSITION_UPDATE,
+    POSITION_UPDATE,
+    TRANSITION_UPDATE,
+    POSITION_UPDATE,
+    POSITION_UPDATE,
+    INTERSECTION_UPDATE,
+    OFFSET_UPDATE,
+];
+
+fn bench_decoder<'a, T>(c: &mut Criterion, name: &str, frame: &'a [u8])
+where
+    T: TryFromCtx<'a, scroll::Endian, Error = AnkiError>,
+{
+    report_allocations(name, || {
+        black_box(black_box(frame).pread_with::<T>(0, WIRE_ENDIAN).unwrap());
+    });
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function(name, |b| {
+        b.iter(|| black_box(frame).pread_with::<T>(0, WIRE_ENDIAN).unwrap())
+    });
+    group.finish();
+}
+
+fn decoders(c: &mut Criterion) {
+    bench_decoder::<AnkiVehicleMsg>(c, "msg", POSITION_UPDATE);
+    bench_decoder::<AnkiVehicleMsgVersionResponse>(c, "version_response", VERSION_RESPONSE);
+    bench_decoder::<AnkiVehicleMsgBatteryLevelResponse>(
+        c,
+        "battery_level_response",
+        BATTERY_LEVEL_RESPONSE,
+    );
+    bench_decoder::<AnkiVehicleMsgLocalisationPositionUpdate>(
+        c,
+        "position_update",
+        POSITION_UPDATE,
+    );
+    bench_decoder::<AnkiVehicleMsgLocalisationTransitionUpdate>(
+        c,
+        "transition_update",
+        TRANSITION_UPDATE,
+    );
+    bench_decoder::<AnkiVehicleMsgLocalisationIntersectionUpdate>(
+        c,
+        "intersection_update",
+        INTERSECTION_UPDATE,
+    );
+    bench_decoder::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(
+        c,
+        "offset_from_road_centre_update",
+        OFFSET_UPDATE,
+    );
+
+    report_allocations("advertisement", || {
+        black_box(black_box(ADVERTISEMENT).pread_with::<AnkiVehicleAdv>(0, WIRE_ENDIAN)).unwrap();
+    });
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("advertisement", |b| {
+        b.iter(|| {
+            black_box(ADVERTISEMENT)
+                .pread_with::<AnkiVehicleAdv>(0, WIRE_ENDIAN)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn dispatcher(c: &mut Criterion) {
+    let mut vehicle = AnkiVehicleData::default();
+    report_allocations("process_notification", || {
+        for frame in TELEMETRY {
+            black_box(vehicle.process_notification(black_box(frame)).unwrap());
+        }
+    });
+
+    let mut group = c.benchmark_group("process_notification");
+    for (name, frame) in [
+        ("position_update", POSITION_UPDATE),
+        ("transition_update", TRANSITION_UPDATE),
+        ("version_response", VERSION_RESPONSE),
+    ] {
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::from_parameter(name), frame, |b, frame| {
+            b.iter(|| vehicle.process_notification(black_box(frame)).unwrap())
+        });
+    }
+    group.throughput(Throughput::Elements(TELEMETRY.len() as u64));
+    group.bench_function("telemetry", |b| {
+        b.iter(|| {
+            for frame in TELEMETRY {
+                vehicle.process_notification(black_box(frame)).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn encoders(c: &mut Criterion) {
+    report_allocations("set_speed", || {
+        black_box(AnkiVehicleData::set_speed(black_box(500), 0));
+    });
+    let mut vehicle = AnkiVehicleData::default();
+    report_allocations("configure", || {
+        black_box(vehicle.configure());
+    });
+
+    let mut group = c.benchmark_group("encode");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("set_speed", |b| {
+        b.iter(|| AnkiVehicleData::set_speed(black_box(500), black_box(0)))
+    });
+    group.bench_function("change_lane", |b| {
+        b.iter(|| AnkiVehicleData::change_lane(black_box(300), 2500, black_box(-68.0)))
+    });
+    group.throughput(Throughput::Elements(5));
+    group.bench_function("configure", |b| b.iter(|| vehicle.configure()));
+    group.finish();
+}
+
+criterion_group!(benches, decoders, dispatcher, encoders);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(any(feature = "grpc", feature = "protobuf"))]
+    std::env::set_var(
+        "PROTOC",
+        protoc_bin_vendored::protoc_bin_path().expect("No vendored protoc for this platform"),
+    );
+
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .compile_protos(&["proto/anki_drive.proto"], &["proto"])
+        .expect("Failed to compile proto/anki_drive.proto");
+
+    #[cfg(feature = "protobuf")]
+    prost_build::compile_protos(&["proto/telemetry.proto"], &["proto"])
+        .expect("Failed to compile proto/telemetry.proto");
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anki_drive_sdk::command::{CommandBatch, LightPattern, VehicleCommand};
+use anki_drive_sdk::host::FleetHost;
+use anki_drive_sdk::protocol::{
+    AnkiVehicleMsgType, LightChannel, LightEffect, VehicleTurn, VehicleTurnTrigger,
+    ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+};
+use anki_drive_sdk::script::VehicleSnapshot;
+use anki_drive_sdk::sim::host::SimulatedHost;
+use anki_drive_sdk::sim::link::LinkConditions;
+use anki_drive_sdk::sim::vehicle::SimulatedVehicle;
+use anki_drive_sdk::vehicle_id::VehicleId;
+use anki_drive_sdk::AnkiVehicleData;
+use smallvec::smallvec;
+
+// Drives vehicles from the keyboard, one command per line:
+//
+//   cargo run --example drive
+//
+// It runs against two simulated vehicles on the starter kit oval. Everything goes through
+// `FleetHost`, so driving real vehicles is a matter of handing `run` the host that owns the BLE
+// connections instead.
+
+const HELP: &str = "\
+commands:
+  list                       vehicles the host knows about
+  connect <id>               connect and configure a vehicle, it becomes the current one
+  use <id>                   switch the current vehicle
+  disconnect                 disconnect the current vehicle
+  speed <mm/s> [accel]       set the speed, accel defaults to 1000 mm/s²
+  lane <offset mm>           change lane, 0 is the road centre, about ±68 mm per lane
+  uturn                      turn around
+  lights head|brake on|off   switch the headlights or brake lights
+  lights engine <r> <g> <b>  set the engine colour, 0-14 per channel
+  lights throb <r> <g> <b>   throb the engine colour
+  status                     last known state of the current vehicle
+  tail [on|off]              print telemetry as it arrives
+  quit";
+
+// Light ids in the set lights mask, the low nibble says which lights to change and the high nibble
+// what to change them to.
+const LIGHT_HEADLIGHTS: u8 = 0;
+const LIGHT_BRAKELIGHTS: u8 = 1;
+
+const ENGINE_CHANNELS: [LightChannel; 3] =
+    [LightChannel::Red, LightChannel::Green, LightChannel::Blue];
+
+fn main() {
+    let host = Arc::new(
+        SimulatedHost::new()
+            .with_link(LinkConditions::ble())
+            .with_vehicle("skull", "Skull", SimulatedVehicle::new().with_seed(1))
+            .with_vehicle("nuke", "Nuke", SimulatedVehicle::new().with_seed(2)),
+    );
+    let _clock = SimulatedHost::spawn_clock(&host, Duration::from_millis(20));
+    if let Err(e) = run(host, io::stdin().lock()) {
+        eprintln!("{}", e);
+    }
+}
+
+fn run<H: FleetHost, R: BufRead>(host: Arc<H>, input: R) -> io::Result<()> {
+    let vehicles: Arc<Mutex<HashMap<VehicleId, AnkiVehicleData>>> = Arc::default();
+    let tail = Arc::new(AtomicBool::new(false));
+    let notifications = host.subscribe();
+    {
+        let vehicles = vehicles.clone();
+        let tail = tail.clone();
+        thread::spawn(move || {
+            for notification in notifications {
+                let mut vehicles = vehicles.lock().unwrap();
+                let vehicle = vehicles.entry(notification.vehicle.clone()).or_default();
+                match vehicle.process_notification(&notification.data) {
+                    Ok(msg_id) if tail.load(Ordering::Relaxed) => {
+                        print_telemetry(&notification.vehicle, &msg_id, vehicle)
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("{}: bad notification: {}", notification.vehicle, e),
+                }
+            }
+        });
+    }
+
+    println!("{}", HELP);
+    let mut current: Option<String> = None;
+    let mut lines = input.lines();
+    loop {
+        print!("{}> ", current.as_deref().unwrap_or(""));
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(()),
+            ["quit"] | ["exit"] => break,
+            ["help"] => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            ["list"] => {
+                for vehicle in host.vehicles() {
+                    println!(
+                        "  {:<12} {:<12} {}",
+                        vehicle.id,
+                        vehicle.name,
+                        if vehicle.connected { "connected" } else { "" }
+                    );
+                }
+                Ok(())
+            }
+            ["connect", id] => connect(host.as_ref(), &vehicles, id).map(|()| {
+                current = Some(id.to_string());
+            }),
+            ["use", id] => {
+                current = Some(id.to_string());
+                Ok(())
+            }
+            ["tail"] | ["tail", "on"] => {
+                tail.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            ["tail", "off"] => {
+                tail.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => match &current {
+                Some(id) => drive(host.as_ref(), &vehicles, id, &words),
+                None => Err("connect to a vehicle first".to_string()),
+            },
+        };
+        if let Err(e) = result {
+            println!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+fn connect<H: FleetHost>(
+    host: &H,
+    vehicles: &Mutex<HashMap<VehicleId, AnkiVehicleData>>,
+    id: &str,
+) -> Result<(), String> {
+    host.connect(id).map_err(|e| e.to_string())?;
+    let commands = {
+        let mut vehicles = vehicles.lock().unwrap();
+        let vehicle = vehicles.entry(id.into()).or_default();
+        vehicle.set_name(id.to_string());
+        vehicle.configure()
+    };
+    for command in commands {
+        host.send(id, command.into()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn drive<H: FleetHost>(
+    host: &H,
+    vehicles: &Mutex<HashMap<VehicleId, AnkiVehicleData>>,
+    id: &str,
+    words: &[&str],
+) -> Result<(), String> {
+    let commands = match words {
+        ["disconnect"] => return host.disconnect(id).map_err(|e| e.to_string()),
+        ["status"] => {
+            match vehicles.lock().unwrap().get(id) {
+                Some(vehicle) => print_status(id, vehicle),
+                None => println!("nothing heard from {} yet", id),
+            }
+            return Ok(());
+        }
+        ["speed", speed] => smallvec![VehicleCommand::SetSpeed {
+            speed_mm_per_sec: number(speed)?,
+            accel_mm_per_sec2: 1000,
+        }],
+        ["speed", speed, accel] => smallvec![VehicleCommand::SetSpeed {
+            speed_mm_per_sec: number(speed)?,
+            accel_mm_per_sec2: number(accel)?,
+        }],
+        ["lane", offset] => smallvec![VehicleCommand::ChangeLane {
+            horizontal_speed_mm_per_sec: 300,
+            horizontal_accel_mm_per_sec2: 2500,
+            offset_from_road_centre_mm: number(offset)?,
+        }],
+        ["uturn"] => smallvec![VehicleCommand::Turn {
+            turn_type: VehicleTurn::UTurn,
+            trigger: VehicleTurnTrigger::Immediate,
+        }],
+        ["lights", "head", state] => smallvec![set_lights(LIGHT_HEADLIGHTS, switch(state)?)],
+        ["lights", "brake", state] => smallvec![set_lights(LIGHT_BRAKELIGHTS, switch(state)?)],
+        ["lights", effect @ ("engine" | "throb"), r, g, b] => {
+            let effect = match *effect {
+                "throb" => LightEffect::Throb,
+                _ => LightEffect::Steady,
+            };
+            let mut commands = CommandBatch::new();
+            for (channel, level) in ENGINE_CHANNELS.into_iter().zip([r, g, b]) {
+                let level: u8 = number(level)?;
+                commands.push(lights_pattern(
+                    channel,
+                    effect.clone(),
+                    level.min(ANKI_VEHICLE_MAX_LIGHT_INTENSITY),
+                ));
+            }
+            commands
+        }
+        _ => return Err("unknown command, try help".to_string()),
+    };
+    for command in commands {
+        host.send(id, command.into()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn set_lights(light: u8, on: bool) -> VehicleCommand {
+    VehicleCommand::SetLights {
+        light_mask: 1 << light | (on as u8) << (4 + light),
+    }
+}
+
+// Steady holds `level`, throb goes from off up to `level` and back.
+fn lights_pattern(channel: LightChannel, effect: LightEffect, level: u8) -> VehicleCommand {
+    let (start, end) = match effect {
+        LightEffect::Throb => (0, level),
+        _ => (level, level),
+    };
+    VehicleCommand::lights_pattern(LightPattern {
+        channel,
+        effect,
+        start,
+        end,
+        cycles_per_min: 60,
+    })
+}
+
+fn number<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse()
+        .map_err(|_| format!("{} is not a number in range", word))
+}
+
+fn switch(word: &str) -> Result<bool, String> {
+    match word {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got {}", word)),
+    }
+}
+
+fn print_telemetry(id: &str, msg_id: &AnkiVehicleMsgType, vehicle: &AnkiVehicleData) {
+    let vehicle = VehicleSnapshot::from_vehicle(vehicle);
+    match msg_id {
+        AnkiVehicleMsgType::V2CLocalisationPositionUpdate => println!(
+            "{:<8} position    location {:>3}  offset {:>6.1} mm  {:>4} mm/s",
+            id, vehicle.location_id, vehicle.offset_from_road_centre_mm, vehicle.speed_mm_per_sec
+        ),
+        AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => println!(
+            "{:<8} transition  piece {:>3} <- {:>3}",
+            id, vehicle.road_piece_idx, vehicle.road_piece_idx_prev
+        ),
+        AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+            println!("{:<8} battery     {} mV", id, vehicle.battery_level)
+        }
+        AnkiVehicleMsgType::V2CVersionResponse => {
+            println!("{:<8} version     {:#06x}", id, vehicle.version)
+        }
+        _ => println!("{:<8} {:?}", id, msg_id),
+    }
+}
+
+fn print_status(id: &str, data: &AnkiVehicleData) {
+    let vehicle = VehicleSnapshot::from_vehicle(data);
+    println!("  vehicle          {}", id);
+    match data.firmware() {
+        Some(firmware) => println!("  firmware         {}", firmware),
+        None => println!("  firmware         unknown"),
+    }
+    println!("  battery          {} mV", vehicle.battery_level);
+    println!("  speed            {} mm/s", vehicle.speed_mm_per_sec);
+    println!(
+        "  offset           {:.1} mm",
+        vehicle.offset_from_road_centre_mm
+    );
+    println!(
+        "  road piece       {} (from {})",
+        vehicle.road_piece_idx, vehicle.road_piece_idx_prev
+    );
+    println!("  location         {}", vehicle.location_id);
+}
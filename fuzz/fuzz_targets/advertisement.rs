@@ -0,0 +1,14 @@
+#![no_main]
+
+use anki_drive_sdk::advertisement::*;
+use libfuzzer_sys::fuzz_target;
+use scroll::Pread;
+
+fuzz_target!(|data: &[u8]| {
+    for endian in [scroll::LE, scroll::BE] {
+        let _ = data.pread_with::<AnkiVehicleState>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleAdvLocalName>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleAdvMfgData>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleAdv>(0, endian);
+    }
+});
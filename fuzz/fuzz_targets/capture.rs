@@ -0,0 +1,10 @@
+#![no_main]
+
+use anki_drive_sdk::capture::Capture;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(capture) = Capture::parse(data) {
+        let _ = capture.decode();
+    }
+});
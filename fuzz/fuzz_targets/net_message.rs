@@ -0,0 +1,11 @@
+#![no_main]
+
+use anki_drive_sdk::net::{NetMessage, NetRaceEvent, NetVehicleState};
+use libfuzzer_sys::fuzz_target;
+use scroll::Pread;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = data.pread_with::<NetVehicleState>(0, scroll::LE);
+    let _ = data.pread_with::<NetRaceEvent>(0, scroll::LE);
+    let _ = data.pread_with::<NetMessage>(0, scroll::LE);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use anki_drive_sdk::json::JsonMessage;
+use anki_drive_sdk::AnkiVehicleData;
+use libfuzzer_sys::fuzz_target;
+
+// The dispatcher sees everything a vehicle notifies, so feed it the input a frame at a time
+// to build up state between messages.
+fuzz_target!(|data: &[u8]| {
+    let mut vehicle = AnkiVehicleData::default();
+    let mut rest = data;
+    while let Some((&len, tail)) = rest.split_first() {
+        let (frame, tail) = tail.split_at((len as usize).min(tail.len()));
+        let _ = vehicle.process_notification(frame);
+        let _ = JsonMessage::from_bytes(frame);
+        rest = tail;
+    }
+});
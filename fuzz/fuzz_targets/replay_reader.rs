@@ -0,0 +1,14 @@
+#![no_main]
+
+use anki_drive_sdk::replay::ReplayReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reader) = ReplayReader::new(data) {
+        for record in reader {
+            if record.is_err() {
+                break;
+            }
+        }
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use anki_drive_sdk::sim::vehicle::SimulatedVehicle;
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+// Commands going the other way, through the simulator's decoder.
+fuzz_target!(|data: &[u8]| {
+    let mut vehicle = SimulatedVehicle::new();
+    let mut rest = data;
+    while let Some((&len, tail)) = rest.split_first() {
+        let (frame, tail) = tail.split_at((len as usize).min(tail.len()));
+        let _ = vehicle.handle_command(frame);
+        vehicle.advance(Duration::from_millis(50));
+        vehicle.drain_notifications().for_each(drop);
+        rest = tail;
+    }
+});
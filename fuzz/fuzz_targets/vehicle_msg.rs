@@ -0,0 +1,22 @@
+#![no_main]
+
+use anki_drive_sdk::protocol::*;
+use libfuzzer_sys::fuzz_target;
+use scroll::Pread;
+
+// Every vehicle to controller message, whatever the frame says its id is.
+fuzz_target!(|data: &[u8]| {
+    for endian in [scroll::LE, scroll::BE] {
+        let _ = data.pread_with::<AnkiVehicleMsg>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgVersionResponse>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgBatteryLevelResponse>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgSpeedUpdate>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgChargerInfo>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgCollisionDetected>(0, endian);
+        let _ = data.pread_with::<AnkiVehicleMsgCycleOvertime>(0, endian);
+    }
+});
@@ -0,0 +1,95 @@
+/* C bindings for anki-drive-sdk, built with
+ * `cargo rustc --release --features ffi --crate-type cdylib`. */
+#ifndef ANKI_DRIVE_SDK_H
+#define ANKI_DRIVE_SDK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct {
+    uint8_t location_id;
+    uint8_t road_piece_id;
+    float offset_from_road_centre_mm;
+    uint16_t speed_mm_per_sec;
+    uint8_t parsing_flags;
+    uint8_t last_recv_lane_change_cmd_id;
+    uint8_t last_exec_lane_change_cmd_id;
+    uint16_t last_desired_lane_change_speed_mm_per_sec;
+    uint16_t last_desired_speed_mm_per_sec;
+} AnkiVehicleFfiPositionUpdate;
+
+typedef struct {
+    int8_t road_piece_idx;
+    int8_t road_piece_idx_prev;
+    float offset_from_road_centre_mm;
+    uint8_t last_recv_lane_change_id;
+    uint8_t last_exec_lane_change_id;
+    uint16_t last_desired_lane_change_speed_mm_per_sec;
+    int8_t ave_follow_line_drift_pixels;
+    uint8_t had_lane_change_activity;
+    uint8_t uphill_counter;
+    uint8_t downhill_counter;
+    uint8_t left_wheel_dist_cm;
+    uint8_t right_wheel_dist_cm;
+} AnkiVehicleFfiTransitionUpdate;
+
+typedef struct {
+    int8_t road_piece_idx;
+    float offset_from_road_centre_mm;
+    uint8_t intersection_code;
+    uint8_t is_exiting;
+    uint16_t mm_since_last_transition_bar;
+    uint16_t mm_since_last_intersection_code;
+} AnkiVehicleFfiIntersectionUpdate;
+
+typedef struct {
+    float offset_from_road_centre_mm;
+    uint8_t lane_change_id;
+} AnkiVehicleFfiOffsetFromRoadCentreUpdate;
+
+/* Encoders return the number of bytes written to `out`, or -1 on error. */
+int32_t anki_vehicle_encode_set_sdk_mode(uint8_t on, uint8_t flags, uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_set_speed(int16_t speed_mm_per_sec, int16_t accel_mm_per_sec2,
+                                      uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_set_offset_from_road_centre(float offset_mm, uint8_t *out,
+                                                        size_t out_len);
+int32_t anki_vehicle_encode_change_lane(uint16_t horizontal_speed_mm_per_sec,
+                                        uint16_t horizontal_accel_mm_per_sec2,
+                                        float offset_from_road_centre_mm, uint8_t *out,
+                                        size_t out_len);
+int32_t anki_vehicle_encode_turn(uint8_t turn_type, uint8_t trigger, uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_set_lights(uint8_t mask, uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_lights_pattern(uint8_t channel, uint8_t effect, uint8_t start,
+                                           uint8_t end, uint16_t cycles_per_min, uint8_t *out,
+                                           size_t out_len);
+int32_t anki_vehicle_encode_ping(uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_disconnect(uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_get_version(uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_get_battery_level(uint8_t *out, size_t out_len);
+int32_t anki_vehicle_encode_cancel_lane_change(uint8_t *out, size_t out_len);
+
+/* Returns the message id of a notification, 0 if it can't be read. */
+uint8_t anki_vehicle_msg_type(const uint8_t *data, size_t len);
+
+/* Parsers return 0 on success, -1 if the frame is malformed. */
+int32_t anki_vehicle_parse_version_response(const uint8_t *data, size_t len, uint16_t *version);
+int32_t anki_vehicle_parse_battery_level_response(const uint8_t *data, size_t len,
+                                                  uint16_t *battery_level);
+int32_t anki_vehicle_parse_position_update(const uint8_t *data, size_t len,
+                                           AnkiVehicleFfiPositionUpdate *out);
+int32_t anki_vehicle_parse_transition_update(const uint8_t *data, size_t len,
+                                             AnkiVehicleFfiTransitionUpdate *out);
+int32_t anki_vehicle_parse_intersection_update(const uint8_t *data, size_t len,
+                                               AnkiVehicleFfiIntersectionUpdate *out);
+int32_t anki_vehicle_parse_offset_from_road_centre_update(
+    const uint8_t *data, size_t len, AnkiVehicleFfiOffsetFromRoadCentreUpdate *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -1,7 +1,56 @@
 use scroll::ctx::StrCtx;
-use scroll::{self, ctx, Pread};
+use scroll::{self, ctx, Pread, Pwrite};
+
+use crate::error::{check_buffer_len, check_frame_len, AnkiError};
+use crate::protocol::{ProtocolError, WIRE_ENDIAN};
+
+// The state byte of the advertisement as it was sent. Bits the crate doesn't know are kept.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct VehicleStateFlags(u8);
+
+impl VehicleStateFlags {
+    pub const ON_CHARGER: VehicleStateFlags = VehicleStateFlags(0b00000010);
+    pub const FULL_BATTERY: VehicleStateFlags = VehicleStateFlags(0b00000100);
+    pub const LOW_BATTERY: VehicleStateFlags = VehicleStateFlags(0b00001000);
+
+    pub const fn from_bits(bits: u8) -> VehicleStateFlags {
+        VehicleStateFlags(bits)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: VehicleStateFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: VehicleStateFlags) -> VehicleStateFlags {
+        VehicleStateFlags(self.0 | other.0)
+    }
+
+    pub const fn is_on_charger(&self) -> bool {
+        self.contains(VehicleStateFlags::ON_CHARGER)
+    }
+
+    pub const fn is_charged(&self) -> bool {
+        self.contains(VehicleStateFlags::FULL_BATTERY)
+    }
+
+    pub const fn is_low_battery(&self) -> bool {
+        self.contains(VehicleStateFlags::LOW_BATTERY)
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleState {
     pub low_battery: bool,
     pub full_battery: bool,
@@ -10,32 +59,69 @@ pub struct AnkiVehicleState {
 
 pub const ANKI_VEHICLE_STATE_SIZE: usize = 1;
 
+impl AnkiVehicleState {
+    pub fn is_on_charger(&self) -> bool {
+        self.on_charger
+    }
+
+    pub fn is_charged(&self) -> bool {
+        self.full_battery
+    }
+
+    // The state byte these flags are read from, without any bits the crate doesn't know.
+    pub fn flags(&self) -> VehicleStateFlags {
+        let mut flags = VehicleStateFlags::default();
+        if self.on_charger {
+            flags = flags.union(VehicleStateFlags::ON_CHARGER);
+        }
+        if self.full_battery {
+            flags = flags.union(VehicleStateFlags::FULL_BATTERY);
+        }
+        if self.low_battery {
+            flags = flags.union(VehicleStateFlags::LOW_BATTERY);
+        }
+        flags
+    }
+}
+
+impl From<VehicleStateFlags> for AnkiVehicleState {
+    fn from(flags: VehicleStateFlags) -> Self {
+        AnkiVehicleState {
+            low_battery: flags.is_low_battery(),
+            full_battery: flags.is_charged(),
+            on_charger: flags.is_on_charger(),
+        }
+    }
+}
+
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleState {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        // TODO: This might break if a bigger size data is inputted.
-        if data.len() != ANKI_VEHICLE_STATE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_STATE_SIZE)?;
 
         let offset = &mut 0;
-        let state = data.gread_with::<u8>(offset, ctx)?;
-        let low_battery: bool = (state & 0b00001000) > 0;
-        let full_battery: bool = (state & 0b00000100) > 0;
-        let on_charger: bool = (state & 0b00000010) > 0;
+        let flags = VehicleStateFlags::from_bits(data.gread_with::<u8>(offset, ctx)?);
 
-        Ok((
-            AnkiVehicleState {
-                low_battery,
-                full_battery,
-                on_charger,
-            },
-            *offset,
-        ))
+        Ok((AnkiVehicleState::from(flags), *offset))
     }
 }
 
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleState {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_STATE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.flags().bits(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Only serializes, deserialize into `AnkiVehicleAdvLocalNameOwned`.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct AnkiVehicleAdvLocalName<'a> {
     pub state: AnkiVehicleState,
     pub version: u16,
@@ -44,21 +130,74 @@ pub struct AnkiVehicleAdvLocalName<'a> {
 }
 
 pub const ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE: usize = 21;
+const ANKI_VEHICLE_ADV_LOCAL_NAME_RESERVED_SIZE: usize = 5;
+// Room for the name, shorter names are padded with NULs.
+pub const ANKI_VEHICLE_ADV_NAME_SIZE: usize = 13;
+
+impl<'a> AnkiVehicleAdvLocalName<'a> {
+    pub fn new(
+        state: AnkiVehicleState,
+        version: u16,
+        name: &'a str,
+    ) -> AnkiVehicleAdvLocalName<'a> {
+        AnkiVehicleAdvLocalName {
+            state,
+            version,
+            _reserved: &[0; ANKI_VEHICLE_ADV_LOCAL_NAME_RESERVED_SIZE],
+            name,
+        }
+    }
+}
+
+impl<'a> ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleAdvLocalName<'a> {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE)?;
+        if self.name.len() > ANKI_VEHICLE_ADV_NAME_SIZE {
+            return Err(ProtocolError::FieldOutOfRange {
+                field: "name",
+                value: self.name.len() as u32,
+            }
+            .into());
+        }
+        if self._reserved.len() != ANKI_VEHICLE_ADV_LOCAL_NAME_RESERVED_SIZE {
+            return Err(ProtocolError::FieldOutOfRange {
+                field: "reserved",
+                value: self._reserved.len() as u32,
+            }
+            .into());
+        }
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.state.flags().bits(), offset, ctx)?;
+        data.gwrite_with::<u16>(self.version, offset, ctx)?;
+        data.gwrite::<&'a [u8]>(self._reserved, offset)?;
+        data.gwrite::<&'a [u8]>(self.name.as_bytes(), offset)?;
+        data[*offset..].fill(0);
+
+        Ok(ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE)
+    }
+}
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdvLocalName<'a> {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        // TODO: This might break if a bigger size data is inputted.
         if data.len() < ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
+            return Err(ProtocolError::TooShort {
+                expected: ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE,
+                got: data.len(),
+            }
+            .into());
         }
 
         let offset = &mut 0;
         let state: AnkiVehicleState =
             data[..ANKI_VEHICLE_STATE_SIZE].gread_with::<AnkiVehicleState>(offset, ctx)?;
         let version: u16 = data.gread_with::<u16>(offset, ctx)?;
-        let _reserved: &'a [u8] = data.gread_with::<&'a [u8]>(offset, 5)?;
-        let name: &str = data.gread_with::<&str>(offset, StrCtx::Length(13))?;
+        let _reserved: &'a [u8] =
+            data.gread_with::<&'a [u8]>(offset, ANKI_VEHICLE_ADV_LOCAL_NAME_RESERVED_SIZE)?;
+        let name: &str =
+            data.gread_with::<&str>(offset, StrCtx::Length(ANKI_VEHICLE_ADV_NAME_SIZE))?;
 
         Ok((
             AnkiVehicleAdvLocalName {
@@ -72,7 +211,9 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdvLocalName<'a> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleAdvMfgData {
     pub identifier: u32,
     pub model_id: u8,
@@ -82,12 +223,41 @@ pub struct AnkiVehicleAdvMfgData {
 
 pub const ANKI_VEHICLE_ADV_MFG_DATA_SIZE: usize = 8;
 
+impl AnkiVehicleAdvMfgData {
+    pub fn new(identifier: u32, model_id: u8, product_id: u16) -> AnkiVehicleAdvMfgData {
+        AnkiVehicleAdvMfgData {
+            identifier,
+            model_id,
+            _reserved: 0,
+            product_id,
+        }
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleAdvMfgData {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_ADV_MFG_DATA_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u32>(self.identifier, offset, ctx)?;
+        data.gwrite_with::<u8>(self.model_id, offset, ctx)?;
+        data.gwrite_with::<u8>(self._reserved, offset, ctx)?;
+        data.gwrite_with::<u16>(self.product_id, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdvMfgData {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        // TODO: This might break if a bigger size data is inputted.
         if data.len() < ANKI_VEHICLE_ADV_MFG_DATA_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
+            return Err(ProtocolError::TooShort {
+                expected: ANKI_VEHICLE_ADV_MFG_DATA_SIZE,
+                got: data.len(),
+            }
+            .into());
         }
 
         let offset = &mut 0;
@@ -108,7 +278,10 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdvMfgData {
     }
 }
 
+// Like the local name it only serializes, deserialize into `AnkiVehicleAdvOwned`.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct AnkiVehicleAdv<'a> {
     pub flags: u8,
     pub tx_power: u8,
@@ -117,15 +290,43 @@ pub struct AnkiVehicleAdv<'a> {
     pub service_id: &'a [u8],
 }
 
-pub const ANKI_VEHICLE_ADV_SIZE: usize =
-    2 + ANKI_VEHICLE_ADV_MFG_DATA_SIZE + ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE + 16;
+pub const ANKI_VEHICLE_ADV_SERVICE_ID_SIZE: usize = 16;
+pub const ANKI_VEHICLE_ADV_SIZE: usize = 2
+    + ANKI_VEHICLE_ADV_MFG_DATA_SIZE
+    + ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE
+    + ANKI_VEHICLE_ADV_SERVICE_ID_SIZE;
+
+impl<'a> ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleAdv<'a> {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_ADV_SIZE)?;
+        if self.service_id.len() != ANKI_VEHICLE_ADV_SERVICE_ID_SIZE {
+            return Err(ProtocolError::FieldOutOfRange {
+                field: "service_id",
+                value: self.service_id.len() as u32,
+            }
+            .into());
+        }
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.flags, offset, ctx)?;
+        data.gwrite_with::<u8>(self.tx_power, offset, ctx)?;
+        let end = *offset + ANKI_VEHICLE_ADV_MFG_DATA_SIZE;
+        data[*offset..end].pwrite_with(self.mfg_data, 0, ctx)?;
+        *offset = end;
+        let end = *offset + ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE;
+        data[*offset..end].pwrite_with(self.local_name, 0, ctx)?;
+        *offset = end;
+        data.gwrite::<&'a [u8]>(self.service_id, offset)?;
+
+        Ok(*offset)
+    }
+}
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdv<'a> {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_ADV_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_ADV_SIZE)?;
 
         let offset = &mut 0;
         let flags: u8 = data.gread_with::<u8>(offset, ctx)?;
@@ -134,7 +335,8 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdv<'a> {
             data.gread_with::<AnkiVehicleAdvMfgData>(offset, ctx)?;
         let local_name: AnkiVehicleAdvLocalName =
             data.gread_with::<AnkiVehicleAdvLocalName>(offset, ctx)?;
-        let service_id: &'a [u8] = data.gread_with::<&'a [u8]>(offset, 16)?;
+        let service_id: &'a [u8] =
+            data.gread_with::<&'a [u8]>(offset, ANKI_VEHICLE_ADV_SERVICE_ID_SIZE)?;
 
         Ok((
             AnkiVehicleAdv {
@@ -149,18 +351,187 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleAdv<'a> {
     }
 }
 
+// An `AnkiVehicleAdvLocalName` holding its own name and reserved bytes.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleAdvLocalNameOwned {
+    pub state: AnkiVehicleState,
+    pub version: u16,
+    _reserved: Vec<u8>,
+    pub name: String,
+}
+
+impl AnkiVehicleAdvLocalName<'_> {
+    pub fn to_owned(&self) -> AnkiVehicleAdvLocalNameOwned {
+        AnkiVehicleAdvLocalNameOwned {
+            state: self.state.clone(),
+            version: self.version,
+            _reserved: self._reserved.to_vec(),
+            name: self.name.to_string(),
+        }
+    }
+}
+
+impl AnkiVehicleAdvLocalNameOwned {
+    // Borrows the local name back, to encode it.
+    pub fn as_local_name(&self) -> AnkiVehicleAdvLocalName<'_> {
+        AnkiVehicleAdvLocalName {
+            state: self.state.clone(),
+            version: self.version,
+            _reserved: &self._reserved,
+            name: &self.name,
+        }
+    }
+}
+
+// An `AnkiVehicleAdv` that no longer borrows the scan result it was read from, for keeping in a
+// list of vehicles seen.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleAdvOwned {
+    pub flags: u8,
+    pub tx_power: u8,
+    pub mfg_data: AnkiVehicleAdvMfgData,
+    pub local_name: AnkiVehicleAdvLocalNameOwned,
+    pub service_id: Vec<u8>,
+}
+
+impl AnkiVehicleAdv<'_> {
+    pub fn to_owned(&self) -> AnkiVehicleAdvOwned {
+        AnkiVehicleAdvOwned {
+            flags: self.flags,
+            tx_power: self.tx_power,
+            mfg_data: self.mfg_data.clone(),
+            local_name: self.local_name.to_owned(),
+            service_id: self.service_id.to_vec(),
+        }
+    }
+}
+
+impl AnkiVehicleAdvOwned {
+    // Borrows the advertisement back, to encode it.
+    pub fn as_adv(&self) -> AnkiVehicleAdv<'_> {
+        AnkiVehicleAdv {
+            flags: self.flags,
+            tx_power: self.tx_power,
+            mfg_data: self.mfg_data.clone(),
+            local_name: self.local_name.as_local_name(),
+            service_id: &self.service_id,
+        }
+    }
+}
+
+impl From<AnkiVehicleAdv<'_>> for AnkiVehicleAdvOwned {
+    fn from(adv: AnkiVehicleAdv<'_>) -> Self {
+        adv.to_owned()
+    }
+}
+
+// AD structure types of the standard BLE advertising data format that vehicles send.
+pub const AD_TYPE_FLAGS: u8 = 0x01;
+pub const AD_TYPE_INCOMPLETE_128_SERVICE_UUIDS: u8 = 0x06;
+pub const AD_TYPE_COMPLETE_128_SERVICE_UUIDS: u8 = 0x07;
+pub const AD_TYPE_SHORTENED_LOCAL_NAME: u8 = 0x08;
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+pub const AD_TYPE_TX_POWER_LEVEL: u8 = 0x0a;
+pub const AD_TYPE_MANUFACTURER_DATA: u8 = 0xff;
+
+// The local name as it comes in an AD structure, where the name is only as long as it needs to
+// be rather than padded out to the full field.
+fn local_name_from_ad(
+    data: &[u8],
+    ctx: scroll::Endian,
+) -> Result<AnkiVehicleAdvLocalName<'_>, AnkiError> {
+    let name_offset = ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE - ANKI_VEHICLE_ADV_NAME_SIZE;
+    if data.len() < name_offset {
+        return Err(ProtocolError::TooShort {
+            expected: name_offset,
+            got: data.len(),
+        }
+        .into());
+    }
+
+    let offset = &mut 0;
+    let state: AnkiVehicleState =
+        data[..ANKI_VEHICLE_STATE_SIZE].gread_with::<AnkiVehicleState>(offset, ctx)?;
+    let version: u16 = data.gread_with::<u16>(offset, ctx)?;
+    let _reserved: &[u8] =
+        data.gread_with::<&[u8]>(offset, ANKI_VEHICLE_ADV_LOCAL_NAME_RESERVED_SIZE)?;
+    let name_len = (data.len() - *offset).min(ANKI_VEHICLE_ADV_NAME_SIZE);
+    let name: &str = data.gread_with::<&str>(offset, StrCtx::Length(name_len))?;
+
+    Ok(AnkiVehicleAdvLocalName {
+        state,
+        version,
+        _reserved,
+        name,
+    })
+}
+
+impl<'a> AnkiVehicleAdv<'a> {
+    // Reads the advertising data of a scan result, a run of length and type tagged AD
+    // structures in any order. A zero length ends the data early, the rest is padding. The
+    // manufacturer data and local name are required, without flags or a tx power those read
+    // as 0 and without the service UUID `service_id` is empty. AD structures of other types are
+    // skipped.
+    pub fn from_ad_structures(data: &'a [u8]) -> Result<AnkiVehicleAdv<'a>, AnkiError> {
+        let ctx = WIRE_ENDIAN;
+        let mut flags = 0;
+        let mut tx_power = 0;
+        let mut mfg_data = None;
+        let mut local_name = None;
+        let mut service_id: &'a [u8] = &[];
+
+        let offset = &mut 0;
+        while *offset < data.len() {
+            let len = data.gread_with::<u8>(offset, ctx)? as usize;
+            if len == 0 {
+                break;
+            }
+            let structure = data.gread_with::<&'a [u8]>(offset, len)?;
+            let (ad_type, payload) = (structure[0], &structure[1..]);
+            match ad_type {
+                AD_TYPE_FLAGS => flags = payload.pread_with::<u8>(0, ctx)?,
+                AD_TYPE_TX_POWER_LEVEL => tx_power = payload.pread_with::<u8>(0, ctx)?,
+                AD_TYPE_MANUFACTURER_DATA => {
+                    mfg_data = Some(payload.pread_with::<AnkiVehicleAdvMfgData>(0, ctx)?)
+                }
+                // A complete name wins over a shortened one whichever comes first.
+                AD_TYPE_COMPLETE_LOCAL_NAME => local_name = Some(local_name_from_ad(payload, ctx)?),
+                AD_TYPE_SHORTENED_LOCAL_NAME if local_name.is_none() => {
+                    local_name = Some(local_name_from_ad(payload, ctx)?)
+                }
+                AD_TYPE_INCOMPLETE_128_SERVICE_UUIDS | AD_TYPE_COMPLETE_128_SERVICE_UUIDS => {
+                    service_id =
+                        payload.pread_with::<&'a [u8]>(0, ANKI_VEHICLE_ADV_SERVICE_ID_SIZE)?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(AnkiVehicleAdv {
+            flags,
+            tx_power,
+            mfg_data: mfg_data.ok_or(AnkiError::MissingAdField("manufacturer data"))?,
+            local_name: local_name.ok_or(AnkiError::MissingAdField("local name"))?,
+            service_id,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use scroll::{Pread, BE};
+    use scroll::{Pread, Pwrite};
 
     use super::*;
 
     #[test]
     fn anki_vehicle_adv_local_name_struct_test() {
         let data: &[u8; ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE] = &[
-            0x0, 0xCD, 0xEF, 0x1, 0x2, 0x3, 0x4, 0x5, 'l' as u8, 'o' as u8, 'c' as u8, 'a' as u8,
-            'l' as u8, 'n' as u8, 'a' as u8, 'm' as u8, 'e' as u8, 't' as u8, 'e' as u8, 's' as u8,
-            't' as u8,
+            0x0, 0xEF, 0xCD, 0x1, 0x2, 0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a',
+            b'm', b'e', b't', b'e', b's', b't',
         ];
         let local_name: AnkiVehicleAdvLocalName = AnkiVehicleAdvLocalName {
             state: AnkiVehicleState {
@@ -173,16 +544,143 @@ mod tests {
             name: "localnametest",
         };
         let test_local_name = data
-            .gread_with::<AnkiVehicleAdvLocalName>(&mut 0, BE)
+            .gread_with::<AnkiVehicleAdvLocalName>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_local_name, local_name);
         assert_eq!(local_name, test_local_name)
     }
 
+    #[test]
+    fn anki_vehicle_adv_round_trip_test() {
+        let data: &[u8; ANKI_VEHICLE_ADV_SIZE] = &[
+            0x12, 0x34, 0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x56, 0xEF, 0xCD, 0x6, 0xEF, 0xCD, 0x1, 0x2,
+            0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a', b'm', b'e', b't', b'e', b's',
+            b't', 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+        ];
+        let adv = data.pread_with::<AnkiVehicleAdv>(0, WIRE_ENDIAN).unwrap();
+        let mut encoded = [0u8; ANKI_VEHICLE_ADV_SIZE];
+        encoded.pwrite_with(adv, 0, WIRE_ENDIAN).unwrap();
+        assert_eq!(data, &encoded);
+
+        // Shorter names are padded with NULs.
+        let mut data = [0xFFu8; ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE];
+        let local_name = AnkiVehicleAdvLocalName::new(
+            AnkiVehicleState::from(VehicleStateFlags::FULL_BATTERY),
+            0x2676,
+            "Skull",
+        );
+        data.pwrite_with(local_name, 0, WIRE_ENDIAN).unwrap();
+        assert_eq!(
+            [0x04, 0x76, 0x26, 0, 0, 0, 0, 0, b'S', b'k', b'u', b'l', b'l'],
+            data[..13]
+        );
+        assert_eq!([0; 8], data[13..]);
+        let local_name = data
+            .pread_with::<AnkiVehicleAdvLocalName>(0, WIRE_ENDIAN)
+            .unwrap();
+        assert_eq!("Skull", local_name.name.trim_end_matches('\0'));
+
+        let mut data = [0u8; ANKI_VEHICLE_ADV_MFG_DATA_SIZE];
+        data.pwrite_with(
+            AnkiVehicleAdvMfgData::new(0xBEEF, 0x09, 0x1234),
+            0,
+            WIRE_ENDIAN,
+        )
+        .unwrap();
+        assert_eq!([0xEF, 0xBE, 0, 0, 0x09, 0, 0x34, 0x12], data);
+
+        let mut data = [0u8; ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE];
+        assert!(matches!(
+            data.pwrite_with(
+                AnkiVehicleAdvLocalName::new(local_name.state, 0, "Much too long name"),
+                0,
+                WIRE_ENDIAN
+            ),
+            Err(AnkiError::Protocol(ProtocolError::FieldOutOfRange {
+                field: "name",
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn anki_vehicle_adv_from_ad_structures_test() {
+        #[rustfmt::skip]
+        let data = [
+            // Local name, ahead of the manufacturer data and not padded.
+            14, AD_TYPE_COMPLETE_LOCAL_NAME,
+            0x04, 0x76, 0x26, 0, 0, 0, 0, 0, b'S', b'k', b'u', b'l', b'l',
+            9, AD_TYPE_MANUFACTURER_DATA,
+            0xEF, 0xCD, 0xAB, 0x89, 0x09, 0x00, 0xEF, 0xBE,
+            // Unknown types are skipped.
+            3, 0x19, 0x00, 0x00,
+            17, AD_TYPE_COMPLETE_128_SERVICE_UUIDS,
+            0xF4, 0x8D, 0x4D, 0x9C, 0xD8, 0x0B, 0x81, 0x83,
+            0x7E, 0x40, 0x86, 0x61, 0xEF, 0xBE, 0x15, 0xBE,
+            // Padding after the last structure.
+            0, 0, 0,
+        ];
+        let adv = AnkiVehicleAdv::from_ad_structures(&data).unwrap();
+        assert_eq!(0, adv.flags);
+        assert_eq!(
+            AnkiVehicleAdvMfgData::new(0x89ABCDEF, 0x09, 0xBEEF),
+            adv.mfg_data
+        );
+        assert!(adv.local_name.state.is_charged());
+        assert_eq!(0x2676, adv.local_name.version);
+        assert_eq!("Skull", adv.local_name.name);
+        assert_eq!(0xF4, adv.service_id[0]);
+        assert_eq!(ANKI_VEHICLE_ADV_SERVICE_ID_SIZE, adv.service_id.len());
+
+        assert!(matches!(
+            AnkiVehicleAdv::from_ad_structures(&data[..15]),
+            Err(AnkiError::MissingAdField("manufacturer data"))
+        ));
+        // A structure running past the end of the data.
+        assert!(AnkiVehicleAdv::from_ad_structures(&data[..20]).is_err());
+    }
+
+    #[test]
+    fn anki_vehicle_adv_owned_test() {
+        let data: &[u8; ANKI_VEHICLE_ADV_SIZE] = &[
+            0x12, 0x34, 0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x56, 0xEF, 0xCD, 0x6, 0xEF, 0xCD, 0x1, 0x2,
+            0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a', b'm', b'e', b't', b'e', b's',
+            b't', 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+        ];
+        let adv: AnkiVehicleAdvOwned = {
+            let buffer = data.to_vec();
+            AnkiVehicleAdvOwned::from(buffer.pread_with::<AnkiVehicleAdv>(0, WIRE_ENDIAN).unwrap())
+        };
+        assert_eq!("localnametest", adv.local_name.name);
+        assert_eq!(
+            data.pread_with::<AnkiVehicleAdv>(0, WIRE_ENDIAN).unwrap(),
+            adv.as_adv()
+        );
+
+        let mut encoded = [0u8; ANKI_VEHICLE_ADV_SIZE];
+        encoded.pwrite_with(adv.as_adv(), 0, WIRE_ENDIAN).unwrap();
+        assert_eq!(data, &encoded);
+    }
+
+    #[test]
+    fn vehicle_state_flags_test() {
+        let flags = VehicleStateFlags::from_bits(0b10000110);
+        assert!(flags.is_on_charger());
+        assert!(flags.is_charged());
+        assert!(!flags.is_low_battery());
+
+        let state = [flags.bits()]
+            .gread_with::<AnkiVehicleState>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
+        assert!(state.is_on_charger());
+        assert!(state.is_charged());
+        assert_eq!(VehicleStateFlags::from_bits(0b00000110), state.flags());
+    }
+
     #[test]
     fn anki_vehicle_adv_mfg_data_struct_test() {
         let data: &[u8; ANKI_VEHICLE_ADV_MFG_DATA_SIZE] =
-            &[0x89, 0xAB, 0xCD, 0xEF, 0xAB, 0x12, 0xCD, 0xEF];
+            &[0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x12, 0xEF, 0xCD];
         let mfg_data: AnkiVehicleAdvMfgData = AnkiVehicleAdvMfgData {
             identifier: 0x89ABCDEF,
             model_id: 0xAB,
@@ -190,7 +688,7 @@ mod tests {
             product_id: 0xCDEF,
         };
         let test_mfg_data = data
-            .gread_with::<AnkiVehicleAdvMfgData>(&mut 0, BE)
+            .gread_with::<AnkiVehicleAdvMfgData>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_mfg_data, mfg_data);
         assert_eq!(mfg_data, test_mfg_data)
@@ -199,10 +697,9 @@ mod tests {
     #[test]
     fn anki_vehicle_adv_struct_test() {
         let data: &[u8; ANKI_VEHICLE_ADV_SIZE] = &[
-            0x12, 0x34, 0x89, 0xAB, 0xCD, 0xEF, 0xAB, 0x56, 0xCD, 0xEF, 0x0, 0xCD, 0xEF, 0x1, 0x2,
-            0x3, 0x4, 0x5, 'l' as u8, 'o' as u8, 'c' as u8, 'a' as u8, 'l' as u8, 'n' as u8,
-            'a' as u8, 'm' as u8, 'e' as u8, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, 0x0, 0x1,
-            0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+            0x12, 0x34, 0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x56, 0xEF, 0xCD, 0x0, 0xEF, 0xCD, 0x1, 0x2,
+            0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a', b'm', b'e', b't', b'e', b's',
+            b't', 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
         ];
         let adv: AnkiVehicleAdv = AnkiVehicleAdv {
             flags: 0x12,
@@ -227,7 +724,9 @@ mod tests {
                 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
             ],
         };
-        let test_adv = data.gread_with::<AnkiVehicleAdv>(&mut 0, BE).unwrap();
+        let test_adv = data
+            .gread_with::<AnkiVehicleAdv>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
         println!("T:{:?} == G:{:?}", test_adv, adv);
         assert_eq!(adv, test_adv)
     }
@@ -0,0 +1,72 @@
+use crate::protocol::AnkiVehicleMsgBatteryLevelResponse;
+
+// The battery level response is the cell voltage in millivolts. The vehicle raises its full and
+// low battery advertisement flags at these voltages, the same thresholds give the flags for a
+// level read over a connection.
+pub const BATTERY_FULL_MILLIVOLTS: u16 = 4200;
+pub const BATTERY_LOW_MILLIVOLTS: u16 = 3500;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct BatteryFlags(u8);
+
+impl BatteryFlags {
+    pub const FULL: BatteryFlags = BatteryFlags(0b01);
+    pub const LOW: BatteryFlags = BatteryFlags(0b10);
+
+    pub const fn from_millivolts(millivolts: u16) -> BatteryFlags {
+        if millivolts >= BATTERY_FULL_MILLIVOLTS {
+            BatteryFlags::FULL
+        } else if millivolts <= BATTERY_LOW_MILLIVOLTS {
+            BatteryFlags::LOW
+        } else {
+            BatteryFlags(0)
+        }
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: BatteryFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_charged(&self) -> bool {
+        self.contains(BatteryFlags::FULL)
+    }
+
+    pub const fn is_low(&self) -> bool {
+        self.contains(BatteryFlags::LOW)
+    }
+}
+
+impl AnkiVehicleMsgBatteryLevelResponse {
+    pub fn battery_millivolts(&self) -> u16 {
+        self.battery_level
+    }
+
+    pub fn flags(&self) -> BatteryFlags {
+        BatteryFlags::from_millivolts(self.battery_level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_flags_test() {
+        assert!(BatteryFlags::from_millivolts(4210).is_charged());
+        assert!(BatteryFlags::from_millivolts(BATTERY_LOW_MILLIVOLTS).is_low());
+        let flags = BatteryFlags::from_millivolts(3900);
+        assert!(!flags.is_charged());
+        assert!(!flags.is_low());
+        assert_eq!(0, flags.bits());
+    }
+}
@@ -0,0 +1,341 @@
+use bevy_app::{App, Plugin, Startup, Update};
+use bevy_ecs::prelude::*;
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Game engine integration: every vehicle the host knows about becomes an entity with a `Vehicle`
+// and `VehicleTelemetry` component. Telemetry is refreshed from the host's notifications each
+// frame, and inserting or changing `TargetSpeed` / `TargetOffset` on an entity writes the
+// matching command to the vehicle.
+pub struct AnkiDrivePlugin {
+    host: Arc<dyn FleetHost>,
+}
+
+impl AnkiDrivePlugin {
+    pub fn new<H: FleetHost>(host: Arc<H>) -> AnkiDrivePlugin {
+        AnkiDrivePlugin { host }
+    }
+}
+
+impl Plugin for AnkiDrivePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Fleet {
+            host: Arc::clone(&self.host),
+            notifications: Mutex::new(self.host.subscribe()),
+            entities: HashMap::new(),
+        })
+        .add_event::<DiscoverVehicles>()
+        .add_event::<FleetError>()
+        .add_systems(Startup, spawn_known_vehicles)
+        .add_systems(
+            Update,
+            (discover_vehicles, sync_telemetry, send_commands).chain(),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct Fleet {
+    host: Arc<dyn FleetHost>,
+    notifications: Mutex<Receiver<HostNotification>>,
+    entities: HashMap<VehicleId, Entity>,
+}
+
+impl Fleet {
+    // For anything the components don't cover, e.g. connecting or sending lights.
+    pub fn host(&self) -> &dyn FleetHost {
+        self.host.as_ref()
+    }
+
+    pub fn entity(&self, vehicle: &str) -> Option<Entity> {
+        self.entities.get(vehicle).copied()
+    }
+}
+
+#[derive(Component, Debug, PartialEq, Clone)]
+pub struct Vehicle {
+    pub id: VehicleId,
+    pub name: String,
+    pub connected: bool,
+}
+
+#[derive(Component, Debug, PartialEq, Clone, Default)]
+pub struct VehicleTelemetry {
+    pub version: u16,
+    pub battery_level: u16,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+}
+
+impl VehicleTelemetry {
+    pub fn from_vehicle(data: &AnkiVehicleData) -> VehicleTelemetry {
+        VehicleTelemetry {
+            version: data.version,
+            battery_level: data.battery_level,
+            location_id: data.location_id,
+            road_piece_idx: data.road_piece_idx,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+        }
+    }
+}
+
+// Decoder state behind `VehicleTelemetry`.
+#[derive(Component, Debug, Clone, Default)]
+struct VehicleData(AnkiVehicleData);
+
+#[derive(Component, Debug, PartialEq, Clone, Copy)]
+pub struct TargetSpeed {
+    pub speed_mm_per_sec: i16,
+    pub accel_mm_per_sec2: i16,
+}
+
+#[derive(Component, Debug, PartialEq, Clone, Copy)]
+pub struct TargetOffset {
+    pub offset_from_road_centre_mm: f32,
+    pub horizontal_speed_mm_per_sec: u16,
+    pub horizontal_accel_mm_per_sec2: u16,
+}
+
+// Send to scan for vehicles, new ones are spawned on the same frame.
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct DiscoverVehicles;
+
+#[derive(Event, Debug, PartialEq, Clone)]
+pub struct FleetError(pub HostError);
+
+fn spawn_vehicles(commands: &mut Commands, fleet: &mut Fleet, vehicles: Vec<HostVehicle>) {
+    for vehicle in vehicles {
+        if fleet.entities.contains_key(&vehicle.id) {
+            continue;
+        }
+        let mut data = AnkiVehicleData::default();
+        data.set_name(vehicle.name.clone());
+        let entity = commands
+            .spawn((
+                Vehicle {
+                    id: vehicle.id.clone(),
+                    name: vehicle.name,
+                    connected: vehicle.connected,
+                },
+                VehicleTelemetry::from_vehicle(&data),
+                VehicleData(data),
+            ))
+            .id();
+        fleet.entities.insert(vehicle.id, entity);
+    }
+}
+
+fn spawn_known_vehicles(mut commands: Commands, mut fleet: ResMut<Fleet>) {
+    let vehicles = fleet.host.vehicles();
+    spawn_vehicles(&mut commands, &mut fleet, vehicles);
+}
+
+fn discover_vehicles(
+    mut commands: Commands,
+    mut fleet: ResMut<Fleet>,
+    mut requests: EventReader<DiscoverVehicles>,
+    mut query: Query<&mut Vehicle>,
+    mut errors: EventWriter<FleetError>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    match fleet.host.discover() {
+        Ok(vehicles) => {
+            for found in &vehicles {
+                let entity = fleet.entity(&found.id);
+                if let Some(mut vehicle) = entity.and_then(|e| query.get_mut(e).ok()) {
+                    vehicle.connected = found.connected;
+                }
+            }
+            spawn_vehicles(&mut commands, &mut fleet, vehicles);
+        }
+        Err(e) => {
+            errors.write(FleetError(e));
+        }
+    }
+}
+
+fn sync_telemetry(fleet: Res<Fleet>, mut query: Query<(&mut VehicleData, &mut VehicleTelemetry)>) {
+    let notifications = fleet.notifications.lock().unwrap();
+    for notification in notifications.try_iter() {
+        let Some(entity) = fleet.entity(&notification.vehicle) else {
+            continue;
+        };
+        let Ok((mut data, mut telemetry)) = query.get_mut(entity) else {
+            continue;
+        };
+        if data.0.process_notification(&notification.data).is_ok() {
+            *telemetry = VehicleTelemetry::from_vehicle(&data.0);
+        }
+    }
+}
+
+fn send_commands(
+    fleet: Res<Fleet>,
+    speeds: Query<(&Vehicle, &TargetSpeed), Changed<TargetSpeed>>,
+    offsets: Query<(&Vehicle, &TargetOffset), Changed<TargetOffset>>,
+    mut errors: EventWriter<FleetError>,
+) {
+    for (vehicle, target) in &speeds {
+        let data = AnkiVehicleData::set_speed(target.speed_mm_per_sec, target.accel_mm_per_sec2);
+        if let Err(e) = fleet.host.send(&vehicle.id, data.into()) {
+            errors.write(FleetError(e));
+        }
+    }
+    for (vehicle, target) in &offsets {
+        let data = AnkiVehicleData::change_lane(
+            target.horizontal_speed_mm_per_sec,
+            target.horizontal_accel_mm_per_sec2,
+            target.offset_from_road_centre_mm,
+        );
+        if let Err(e) = fleet.host.send(&vehicle.id, data.into()) {
+            errors.write(FleetError(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::AnkiVehicleMsgType;
+    use bevy_ecs::event::Events;
+    use std::sync::mpsc::{channel, Sender};
+
+    #[derive(Default)]
+    struct TestHost {
+        discovered: Mutex<Vec<HostVehicle>>,
+        sent: Mutex<Vec<(String, Vec<u8>)>>,
+        subscribers: Mutex<Vec<Sender<HostNotification>>>,
+    }
+
+    impl FleetHost for TestHost {
+        fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+            let mut discovered = self.discovered.lock().unwrap();
+            discovered.push(HostVehicle {
+                id: "nuke".into(),
+                name: "Nuke".to_string(),
+                connected: false,
+            });
+            Ok(discovered.clone())
+        }
+
+        fn vehicles(&self) -> Vec<HostVehicle> {
+            vec![HostVehicle {
+                id: "skull".into(),
+                name: "Skull".to_string(),
+                connected: true,
+            }]
+        }
+
+        fn connect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn disconnect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+            if vehicle != "skull" {
+                return Err(HostError::NotConnected(vehicle.to_string()));
+            }
+            self.sent.lock().unwrap().push((vehicle.to_string(), data));
+            Ok(())
+        }
+
+        fn subscribe(&self) -> Receiver<HostNotification> {
+            let (tx, rx) = channel();
+            self.subscribers.lock().unwrap().push(tx);
+            rx
+        }
+    }
+
+    fn app(host: &Arc<TestHost>) -> App {
+        let mut app = App::new();
+        app.add_plugins(AnkiDrivePlugin::new(Arc::clone(host)));
+        app.update();
+        app
+    }
+
+    fn entity(app: &App, vehicle: &str) -> Entity {
+        app.world().resource::<Fleet>().entity(vehicle).unwrap()
+    }
+
+    #[test]
+    fn bevy_telemetry_test() {
+        let host = Arc::new(TestHost::default());
+        let mut app = app(&host);
+        let skull = entity(&app, "skull");
+        assert_eq!(
+            "Skull",
+            app.world().get::<Vehicle>(skull).unwrap().name.as_str()
+        );
+
+        for subscriber in host.subscribers.lock().unwrap().iter() {
+            subscriber
+                .send(HostNotification {
+                    vehicle: "skull".into(),
+                    data: vec![
+                        3,
+                        AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+                        0x10,
+                        0x0E,
+                    ],
+                })
+                .unwrap();
+        }
+        app.update();
+
+        let telemetry = app.world().get::<VehicleTelemetry>(skull).unwrap();
+        assert_eq!(0x0E10, telemetry.battery_level);
+    }
+
+    #[test]
+    fn bevy_commands_test() {
+        let host = Arc::new(TestHost::default());
+        let mut app = app(&host);
+        let skull = entity(&app, "skull");
+
+        let speed = TargetSpeed {
+            speed_mm_per_sec: 500,
+            accel_mm_per_sec2: 1000,
+        };
+        app.world_mut().entity_mut(skull).insert(speed);
+        app.update();
+        app.update();
+        assert_eq!(
+            vec![(
+                "skull".to_string(),
+                AnkiVehicleData::set_speed(500, 1000).to_vec()
+            )],
+            *host.sent.lock().unwrap()
+        );
+
+        app.world_mut().send_event(DiscoverVehicles);
+        app.update();
+        let nuke = entity(&app, "nuke");
+        assert!(!app.world().get::<Vehicle>(nuke).unwrap().connected);
+        app.world_mut().entity_mut(nuke).insert(speed);
+        app.update();
+
+        let errors: Vec<FleetError> = app
+            .world_mut()
+            .resource_mut::<Events<FleetError>>()
+            .drain()
+            .collect();
+        assert_eq!(
+            vec![FleetError(HostError::NotConnected("nuke".to_string()))],
+            errors
+        );
+        assert_eq!(1, host.sent.lock().unwrap().len());
+    }
+}
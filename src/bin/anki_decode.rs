@@ -0,0 +1,377 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::ExitCode;
+
+use anki_drive_sdk::capture::Capture;
+use anki_drive_sdk::error::AnkiError;
+use anki_drive_sdk::firmware::FirmwareVersion;
+use anki_drive_sdk::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse,
+    AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgOffsetFromRoadCentreUpdate,
+    AnkiVehicleMsgType, AnkiVehicleMsgVersionResponse, ParsingFlags, VehicleTurn,
+    VehicleTurnTrigger, WIRE_ENDIAN,
+};
+use anki_drive_sdk::replay::{ReplayDirection, ReplayReader, ReplayRecord, REPLAY_MAGIC};
+use scroll::Pread;
+
+// Decodes vehicle frames and prints every field. Takes hex frames on the command line, `-` for
+// hex frames on stdin, one per line, or files: replay logs, btsnoop and pcap captures, or text
+// files of hex frames.
+
+const USAGE: &str = "\
+usage: anki-decode <frame or file>...
+
+  anki-decode 03197626            decode a frame given in hex, spaces and colons are fine
+  anki-decode session.replay      decode a replay log
+  anki-decode btsnoop_hci.log     decode vehicle traffic in a btsnoop or pcap capture
+  anki-decode frames.txt          decode a text file of hex frames, one per line
+  anki-decode -                   decode hex frames from stdin";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let mut ok = true;
+    for arg in &args {
+        let result = if arg == "-" {
+            decode_lines(io::stdin().lock())
+        } else if Path::new(arg).is_file() {
+            decode_file(Path::new(arg))
+        } else {
+            parse_hex(arg).map(|frame| print_frame(&frame, None))
+        };
+        if let Err(e) = result {
+            eprintln!("{}: {}", arg, e);
+            ok = false;
+        }
+    }
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn decode_file(path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    if data.starts_with(&REPLAY_MAGIC) {
+        let reader = ReplayReader::new(data.as_slice()).map_err(|e| e.to_string())?;
+        for record in reader {
+            print_record(&record.map_err(|e| e.to_string())?);
+        }
+        return Ok(());
+    }
+    match Capture::parse(&data) {
+        Ok(capture) => {
+            capture.records.iter().for_each(print_record);
+            Ok(())
+        }
+        Err(_) => decode_lines(data.as_slice()),
+    }
+}
+
+fn decode_lines<R: BufRead>(reader: R) -> Result<(), String> {
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if !line.is_empty() {
+            print_frame(&parse_hex(line)?, None);
+        }
+    }
+    Ok(())
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':' && *c != ',')
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {:?}", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("not a hex frame or a file: {:?}", text))
+        })
+        .collect()
+}
+
+fn print_record(record: &ReplayRecord) {
+    let arrow = match record.direction {
+        ReplayDirection::Command => "->",
+        ReplayDirection::Notification => "<-",
+    };
+    let heading = format!(
+        "[{:>4}.{:03}s] {} {}",
+        record.timestamp_ms / 1000,
+        record.timestamp_ms % 1000,
+        arrow,
+        record.vehicle
+    );
+    print_frame(&record.frame, Some(&heading));
+}
+
+fn print_frame(frame: &[u8], heading: Option<&str>) {
+    let hex: Vec<String> = frame.iter().map(|byte| format!("{:02x}", byte)).collect();
+    match heading {
+        Some(heading) => println!("{} {}", heading, hex.join(" ")),
+        None => println!("{}", hex.join(" ")),
+    }
+    let msg = match frame.pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN) {
+        Ok(msg) => msg,
+        Err(e) => {
+            println!("  malformed: {}", e);
+            return;
+        }
+    };
+    let msg_id = frame[1];
+    println!(
+        "  {:?} ({:#04x}), {} bytes",
+        msg.msg_id,
+        msg_id,
+        frame.len()
+    );
+    if frame[0] as usize != frame.len() - 1 {
+        println!(
+            "  size byte says {} bytes follow, {} do",
+            frame[0],
+            frame.len() - 1
+        );
+    }
+    if let Err(e) = print_fields(&msg.msg_id, frame) {
+        println!("  malformed {:?}: {}", msg.msg_id, e);
+    }
+}
+
+fn field(name: &str, value: impl std::fmt::Display) {
+    println!("    {:<44} {}", name, value);
+}
+
+fn print_fields(msg_id: &AnkiVehicleMsgType, frame: &[u8]) -> Result<(), AnkiError> {
+    match msg_id {
+        AnkiVehicleMsgType::V2CVersionResponse => {
+            let msg: AnkiVehicleMsgVersionResponse = frame.pread_with(0, WIRE_ENDIAN)?;
+            let firmware = FirmwareVersion::from_packed(msg.version);
+            field(
+                "version",
+                format!(
+                    "{}  build {:#04x} revision {:#04x}",
+                    firmware, firmware.build, firmware.revision
+                ),
+            );
+        }
+        AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+            let msg: AnkiVehicleMsgBatteryLevelResponse = frame.pread_with(0, WIRE_ENDIAN)?;
+            field("battery_level", format!("{} mV", msg.battery_level));
+        }
+        AnkiVehicleMsgType::V2CLocalisationPositionUpdate => {
+            let msg: AnkiVehicleMsgLocalisationPositionUpdate = frame.pread_with(0, WIRE_ENDIAN)?;
+            field("location_id", msg.location_id);
+            field("road_piece_id", msg.road_piece_id);
+            field(
+                "offset_from_road_centre_mm",
+                format!("{:?} mm", msg.offset_from_road_centre_mm),
+            );
+            field("speed_mm_per_sec", format!("{} mm/s", msg.speed_mm_per_sec));
+            field("parsing_flags", parsing_flags(msg.parsing_flags));
+            field(
+                "last_recv_lane_change_cmd_id",
+                msg.last_recv_lane_change_cmd_id,
+            );
+            field(
+                "last_exec_lane_change_cmd_id",
+                msg.last_exec_lane_change_cmd_id,
+            );
+            field(
+                "last_desired_lane_change_speed_mm_per_sec",
+                format!("{} mm/s", msg.last_desired_lane_change_speed_mm_per_sec),
+            );
+            field(
+                "last_desired_speed_mm_per_sec",
+                format!("{} mm/s", msg.last_desired_speed_mm_per_sec),
+            );
+        }
+        AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => {
+            let msg: AnkiVehicleMsgLocalisationTransitionUpdate =
+                frame.pread_with(0, WIRE_ENDIAN)?;
+            field("road_piece_idx", msg.road_piece_idx);
+            field("road_piece_idx_prev", msg.road_piece_idx_prev);
+            field(
+                "offset_from_road_centre_mm",
+                format!("{:?} mm", msg.offset_from_road_centre_mm),
+            );
+            field("last_recv_lane_change_id", msg.last_recv_lane_change_id);
+            field("last_exec_lane_change_id", msg.last_exec_lane_change_id);
+            field(
+                "last_desired_lane_change_speed_mm_per_sec",
+                format!("{} mm/s", msg.last_desired_lane_change_speed_mm_per_sec),
+            );
+            field(
+                "ave_follow_line_drift_pixels",
+                format!("{} px", msg.ave_follow_line_drift_pixels),
+            );
+            field("had_lane_change_activity", msg.had_lane_change_activity);
+            field("uphill_counter", msg.uphill_counter);
+            field("downhill_counter", msg.downhill_counter);
+            field(
+                "left_wheel_dist_cm",
+                format!("{} cm", msg.left_wheel_dist_cm),
+            );
+            field(
+                "right_wheel_dist_cm",
+                format!("{} cm", msg.right_wheel_dist_cm),
+            );
+        }
+        AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => {
+            let msg: AnkiVehicleMsgLocalisationIntersectionUpdate =
+                frame.pread_with(0, WIRE_ENDIAN)?;
+            field("road_piece_idx", msg.road_piece_idx);
+            field(
+                "offset_from_road_centre_mm",
+                format!("{:?} mm", msg.offset_from_road_centre_mm),
+            );
+            field("intersection_code", format!("{:?}", msg.intersection_code));
+            field("is_exiting", msg.is_exiting != 0);
+            field(
+                "mm_since_last_transition_bar",
+                format!("{} mm", msg.mm_since_last_transition_bar),
+            );
+            field(
+                "mm_since_last_intersection_code",
+                format!("{} mm", msg.mm_since_last_intersection_code),
+            );
+        }
+        AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => {
+            let msg: AnkiVehicleMsgOffsetFromRoadCentreUpdate = frame.pread_with(0, WIRE_ENDIAN)?;
+            field(
+                "offset_from_road_centre_mm",
+                format!("{:?} mm", msg.offset_from_road_centre_mm),
+            );
+            field("lane_change_id", msg.lane_change_id);
+        }
+        // Commands only have encoders in the library, their layouts are read here by hand.
+        AnkiVehicleMsgType::C2VSDKMode => {
+            field("on", frame.pread_with::<u8>(2, WIRE_ENDIAN)? != 0);
+            field(
+                "flags",
+                format!("{:#04x}", frame.pread_with::<u8>(3, WIRE_ENDIAN)?),
+            );
+        }
+        AnkiVehicleMsgType::C2VSetSpeed => {
+            field(
+                "speed_mm_per_sec",
+                format!("{} mm/s", frame.pread_with::<i16>(2, WIRE_ENDIAN)?),
+            );
+            field(
+                "accel_mm_per_sec2",
+                format!("{} mm/s²", frame.pread_with::<i16>(4, WIRE_ENDIAN)?),
+            );
+            field(
+                "respect_road_piece_speed_limit",
+                frame.pread_with::<u8>(6, WIRE_ENDIAN)? != 0,
+            );
+        }
+        AnkiVehicleMsgType::C2VChangeLane => {
+            field(
+                "horizontal_speed_mm_per_sec",
+                format!("{} mm/s", frame.pread_with::<u16>(2, WIRE_ENDIAN)?),
+            );
+            field(
+                "horizontal_accel_mm_per_sec2",
+                format!("{} mm/s²", frame.pread_with::<u16>(4, WIRE_ENDIAN)?),
+            );
+            field(
+                "offset_from_road_centre_mm",
+                format!("{:?} mm", frame.pread_with::<f32>(6, WIRE_ENDIAN)?),
+            );
+            field("hop_intent", frame.pread_with::<u8>(10, WIRE_ENDIAN)?);
+            field("tag", frame.pread_with::<u8>(11, WIRE_ENDIAN)?);
+        }
+        AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre => {
+            field(
+                "offset_mm",
+                format!("{:?} mm", frame.pread_with::<f32>(2, WIRE_ENDIAN)?),
+            );
+        }
+        AnkiVehicleMsgType::C2VTurn => {
+            let turn = frame.pread_with::<u8>(2, WIRE_ENDIAN)?;
+            let trigger = frame.pread_with::<u8>(3, WIRE_ENDIAN)?;
+            field(
+                "type",
+                VehicleTurn::try_from(turn)
+                    .map(|turn| format!("{:?}", turn))
+                    .unwrap_or_else(|_| format!("unknown ({})", turn)),
+            );
+            field(
+                "trigger",
+                VehicleTurnTrigger::try_from(trigger)
+                    .map(|trigger| format!("{:?}", trigger))
+                    .unwrap_or_else(|_| format!("unknown ({})", trigger)),
+            );
+        }
+        AnkiVehicleMsgType::C2VSetLights => {
+            field(
+                "light_mask",
+                format!("{:#010b}", frame.pread_with::<u8>(2, WIRE_ENDIAN)?),
+            );
+        }
+        _ => {
+            if frame.len() > 2 {
+                let payload: Vec<String> = frame[2..]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                field("payload", payload.join(" "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parsing_flags(flags: ParsingFlags) -> String {
+    let mut described = format!("{:#04x}  {} bits", flags.bits(), flags.num_code_bits());
+    for (set, name) in [
+        (flags.is_inverted_color(), "inverted colour"),
+        (flags.is_reverse_parsing(), "reverse parsing"),
+        (flags.is_reverse_driving(), "reverse driving"),
+    ] {
+        if set {
+            described.push_str(", ");
+            described.push_str(name);
+        }
+    }
+    described
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_test() {
+        let frame = vec![0x03, 0x19, 0x76, 0x26];
+        assert_eq!(Ok(frame.clone()), parse_hex("03197626"));
+        assert_eq!(Ok(frame.clone()), parse_hex("03 19 76 26"));
+        assert_eq!(Ok(frame), parse_hex("0x03:19:76:26"));
+        assert!(parse_hex("031").is_err());
+        assert!(parse_hex("frames.txt").is_err());
+    }
+
+    #[test]
+    fn parsing_flags_test() {
+        assert_eq!("0x07  7 bits", parsing_flags(ParsingFlags::from_bits(0x07)));
+        assert_eq!(
+            "0xe5  5 bits, inverted colour, reverse parsing, reverse driving",
+            parsing_flags(ParsingFlags::from_bits(0xe5))
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::error::AnkiError;
+use crate::json::JsonMessage;
+use crate::protocol::{LightChannel, LightEffect, ANKI_VEHICLE_MAX_LIGHT_INTENSITY};
+use crate::validation::validate_notification;
+use crate::STOP_ACCEL_MM_PER_SEC2;
+
+// A web dashboard speaks the same JSON as `JsonMessage`, tagged with "msg_type". The bridge only
+// adds the checks a frame from an untrusted client needs on top: commands with unknown fields,
+// notifications sent as commands and values out of range are refused with a `BridgeError`, and
+// notification frames are decoded in strict mode.
+//
+// Speeds go up to BRIDGE_MAX_SPEED_MM_PER_SEC, accelerations up to BRIDGE_MAX_ACCEL_MM_PER_SEC2
+// and offsets from the road centre up to BRIDGE_MAX_OFFSET_MM either way.
+
+pub const BRIDGE_MAX_SPEED_MM_PER_SEC: i16 = 1500;
+pub const BRIDGE_MAX_ACCEL_MM_PER_SEC2: i16 = STOP_ACCEL_MM_PER_SEC2;
+// A little past the outer lanes of both track generations.
+pub const BRIDGE_MAX_OFFSET_MM: f32 = 80.0;
+
+#[derive(Debug, Error)]
+pub enum BridgeError {
+    // Not JSON, or not a message `JsonMessage` knows. Line and column are where the parser gave
+    // up.
+    #[error("Bad JSON command at {line}:{column}: {message}")]
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // A command with a value or field it doesn't allow.
+    #[error("Invalid {field}: {message}")]
+    Invalid {
+        field: &'static str,
+        message: String,
+    },
+    // A frame that isn't a notification a vehicle could have sent.
+    #[error("Bad notification frame: {0}")]
+    Frame(#[from] AnkiError),
+}
+
+impl BridgeError {
+    fn invalid(field: &'static str, message: impl Into<String>) -> BridgeError {
+        BridgeError::Invalid {
+            field,
+            message: message.into(),
+        }
+    }
+
+    // The error as JSON, to send back to the client that sent the command.
+    pub fn to_json(&self) -> String {
+        let value = match self {
+            BridgeError::Parse {
+                line,
+                column,
+                message,
+            } => serde_json::json!({
+                "error": "parse",
+                "line": line,
+                "column": column,
+                "message": message,
+            }),
+            BridgeError::Invalid { field, message } => serde_json::json!({
+                "error": "invalid",
+                "field": field,
+                "message": message,
+            }),
+            BridgeError::Frame(e) => serde_json::json!({
+                "error": "frame",
+                "message": e.to_string(),
+            }),
+        };
+        value.to_string()
+    }
+}
+
+impl From<serde_json::Error> for BridgeError {
+    fn from(e: serde_json::Error) -> Self {
+        BridgeError::Parse {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        }
+    }
+}
+
+fn check_offset(field: &'static str, offset: f32) -> Result<(), BridgeError> {
+    if !(-BRIDGE_MAX_OFFSET_MM..=BRIDGE_MAX_OFFSET_MM).contains(&offset) {
+        return Err(BridgeError::invalid(
+            field,
+            format!(
+                "{} is more than {} mm off centre",
+                offset, BRIDGE_MAX_OFFSET_MM
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_speed(field: &'static str, speed: i64, min: i64) -> Result<(), BridgeError> {
+    if !(min..=BRIDGE_MAX_SPEED_MM_PER_SEC as i64).contains(&speed) {
+        return Err(BridgeError::invalid(
+            field,
+            format!(
+                "{} is outside {}..={}",
+                speed, min, BRIDGE_MAX_SPEED_MM_PER_SEC
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_accel(field: &'static str, accel: i64) -> Result<(), BridgeError> {
+    if !(1..=BRIDGE_MAX_ACCEL_MM_PER_SEC2 as i64).contains(&accel) {
+        return Err(BridgeError::invalid(
+            field,
+            format!("{} is outside 1..={}", accel, BRIDGE_MAX_ACCEL_MM_PER_SEC2),
+        ));
+    }
+    Ok(())
+}
+
+// serde skips fields `JsonMessage` doesn't have, but a typo in a dashboard shouldn't go unnoticed.
+// Everything the client sent has to come back out of the parsed message.
+fn check_fields(sent: &Map<String, Value>, msg: &JsonMessage) -> Result<(), BridgeError> {
+    let Ok(Value::Object(known)) = serde_json::to_value(msg) else {
+        return Ok(());
+    };
+    match sent.keys().find(|field| !known.contains_key(*field)) {
+        Some(field) => Err(BridgeError::invalid(
+            "msg_type",
+            format!("unknown field {}", field),
+        )),
+        None => Ok(()),
+    }
+}
+
+// The range checks on top of what parsing already enforces. Notifications are refused, the
+// dashboard can't send those to a vehicle.
+pub fn validate_command(msg: &JsonMessage) -> Result<(), BridgeError> {
+    match msg {
+        JsonMessage::SetSpeed {
+            speed_mm_per_sec,
+            accel_mm_per_sec2,
+        } => {
+            check_speed("speed_mm_per_sec", *speed_mm_per_sec as i64, 0)?;
+            check_accel("accel_mm_per_sec2", *accel_mm_per_sec2 as i64)
+        }
+        JsonMessage::ChangeLane {
+            horizontal_speed_mm_per_sec,
+            horizontal_accel_mm_per_sec2,
+            offset_from_road_centre_mm,
+        } => {
+            check_offset("offset_from_road_centre_mm", *offset_from_road_centre_mm)?;
+            check_speed(
+                "horizontal_speed_mm_per_sec",
+                *horizontal_speed_mm_per_sec as i64,
+                1,
+            )?;
+            check_accel(
+                "horizontal_accel_mm_per_sec2",
+                *horizontal_accel_mm_per_sec2 as i64,
+            )
+        }
+        JsonMessage::SetOffsetFromRoadCentre { offset_mm } => check_offset("offset_mm", *offset_mm),
+        JsonMessage::LightsPattern {
+            channel,
+            effect,
+            start,
+            end,
+            ..
+        } => {
+            if *channel == LightChannel::Count || *effect == LightEffect::Count {
+                return Err(BridgeError::invalid(
+                    "channel",
+                    "count is not a channel or effect",
+                ));
+            }
+            let intensity = *start.max(end);
+            if intensity > ANKI_VEHICLE_MAX_LIGHT_INTENSITY {
+                return Err(BridgeError::invalid(
+                    "start",
+                    format!(
+                        "intensity {} is over {}",
+                        intensity, ANKI_VEHICLE_MAX_LIGHT_INTENSITY
+                    ),
+                ));
+            }
+            Ok(())
+        }
+        JsonMessage::Disconnect
+        | JsonMessage::PingRequest
+        | JsonMessage::VersionRequest
+        | JsonMessage::BatteryLevelRequest
+        | JsonMessage::SetLights { .. }
+        | JsonMessage::CancelLaneChange
+        | JsonMessage::Turn { .. }
+        | JsonMessage::SetConfigParams { .. }
+        | JsonMessage::SdkMode { .. } => Ok(()),
+        JsonMessage::PingResponse
+        | JsonMessage::VersionResponse { .. }
+        | JsonMessage::BatteryLevelResponse { .. }
+        | JsonMessage::PositionUpdate { .. }
+        | JsonMessage::TransitionUpdate { .. }
+        | JsonMessage::IntersectionUpdate { .. }
+        | JsonMessage::VehicleDelocalized
+        | JsonMessage::OffsetFromRoadCentreUpdate { .. }
+        | JsonMessage::SpeedUpdate { .. }
+        | JsonMessage::ChargerInfo { .. }
+        | JsonMessage::CollisionDetected
+        | JsonMessage::CycleOvertime => Err(BridgeError::invalid(
+            "msg_type",
+            "notifications can't be sent to a vehicle",
+        )),
+    }
+}
+
+// Parses and validates a command from a client.
+pub fn command_from_json(json: &str) -> Result<JsonMessage, BridgeError> {
+    let msg = JsonMessage::from_json(json)?;
+    check_fields(&serde_json::from_str(json)?, &msg)?;
+    validate_command(&msg)?;
+    Ok(msg)
+}
+
+// A JSON command straight to the frame to write.
+pub fn command_to_bytes(json: &str) -> Result<Vec<u8>, BridgeError> {
+    Ok(command_from_json(json)?.to_bytes()?)
+}
+
+// Decodes a notification in strict mode, frames that don't add up are refused rather than
+// passed on to the dashboard.
+pub fn notification_from_bytes(data: &[u8]) -> Result<JsonMessage, BridgeError> {
+    validate_notification(data)?;
+    Ok(JsonMessage::from_bytes(data)?)
+}
+
+// A notification frame straight to JSON.
+pub fn notification_to_json(data: &[u8]) -> Result<String, BridgeError> {
+    notification_from_bytes(data).map(|msg| msg.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{AnkiVehicleMsgType, IntersectionCode};
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn bridge_command_test() {
+        assert_eq!(
+            AnkiVehicleData::set_speed(500, 1000),
+            command_to_bytes(
+                r#"{"msg_type":"set_speed","speed_mm_per_sec":500,"accel_mm_per_sec2":1000}"#
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            vec![3, AnkiVehicleMsgType::C2VTurn as u8, 3, 0],
+            command_to_bytes(r#"{"msg_type":"turn","turn_type":"u_turn","trigger":"immediate"}"#)
+                .unwrap()
+        );
+
+        let e = command_to_bytes(
+            r#"{"msg_type":"set_speed","speed_mm_per_sec":5000,"accel_mm_per_sec2":1000}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            e,
+            BridgeError::Invalid {
+                field: "speed_mm_per_sec",
+                ..
+            }
+        ));
+        assert!(e
+            .to_json()
+            .starts_with(r#"{"error":"invalid","field":"speed_mm_per_sec","#));
+
+        for json in [
+            r#"{"msg_type":"set_speed","speed_mm_per_sec":500,"accel_mm_per_sec2":1000,"turbo":1}"#,
+            r#"{"msg_type":"ping_response"}"#,
+            r#"{"msg_type":"lights_pattern","channel":"red","effect":"steady","start":15,"end":15,"cycles_per_min":0}"#,
+        ] {
+            assert!(
+                matches!(command_to_bytes(json), Err(BridgeError::Invalid { .. })),
+                "{}",
+                json
+            );
+        }
+        for json in [
+            r#"{"msg_type":"warp"}"#,
+            r#"{"speed_mm_per_sec":500}"#,
+            r#"{"msg_type":"set_speed","#,
+        ] {
+            assert!(
+                matches!(command_to_bytes(json), Err(BridgeError::Parse { .. })),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn bridge_notification_test() {
+        let data: &[u8] = &[
+            12,
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate as u8,
+            1,
+            0,
+            0,
+            200,
+            66,
+            IntersectionCode::EntryFirst as u8,
+            1,
+            0x10,
+            0,
+            0x20,
+            0,
+        ];
+        assert_eq!(
+            JsonMessage::from_bytes(data).unwrap().to_json(),
+            notification_to_json(data).unwrap()
+        );
+
+        // Strict mode refuses frames with the wrong size and codes the firmware doesn't send.
+        assert!(matches!(
+            notification_to_json(&[2, AnkiVehicleMsgType::V2CVehicleDelocalized as u8, 0]),
+            Err(BridgeError::Frame(_))
+        ));
+        let mut unknown_code = data.to_vec();
+        unknown_code[7] = 0xff;
+        assert!(matches!(
+            notification_to_json(&unknown_code),
+            Err(BridgeError::Frame(_))
+        ));
+        assert!(notification_to_json(&AnkiVehicleData::set_speed(500, 1000)).is_err());
+    }
+}
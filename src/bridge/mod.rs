@@ -0,0 +1,4 @@
+// Bridges between the wire protocol and formats other programs speak, for clients that shouldn't
+// have to know about frames at all.
+
+pub mod json;
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vehicle_id::VehicleId;
+
+pub const BT_ADDRESS_SIZE: usize = 6;
+
+// A vehicle's Bluetooth address. The bytes are kept in the order the address is written, most
+// significant first, HCI and most controllers put them on the wire the other way round.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BtAddress([u8; BT_ADDRESS_SIZE]);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum BtAddressError {
+    Length(usize),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for BtAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BtAddressError::Length(len) => {
+                write!(f, "Bluetooth address must be 6 bytes, got {}", len)
+            }
+            BtAddressError::InvalidFormat(address) => {
+                write!(f, "Invalid Bluetooth address {:?}", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BtAddressError {}
+
+impl BtAddress {
+    pub const fn new(bytes: [u8; BT_ADDRESS_SIZE]) -> BtAddress {
+        BtAddress(bytes)
+    }
+
+    // The address as HCI events carry it, least significant byte first.
+    pub fn from_le_bytes(data: &[u8]) -> Result<BtAddress, BtAddressError> {
+        let mut bytes = BtAddress::try_from(data)?.0;
+        bytes.reverse();
+        Ok(BtAddress(bytes))
+    }
+
+    pub const fn bytes(&self) -> [u8; BT_ADDRESS_SIZE] {
+        self.0
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; BT_ADDRESS_SIZE] {
+        let mut bytes = self.0;
+        bytes.reverse();
+        bytes
+    }
+}
+
+// Formatted the way BlueZ and most BLE stacks print addresses, e.g. "C2:4A:01:9E:33:0F".
+impl fmt::Display for BtAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+// Either case is accepted, the separators have to be colons.
+impl FromStr for BtAddress {
+    type Err = BtAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BtAddressError::InvalidFormat(s.to_string());
+        let mut bytes = [0u8; BT_ADDRESS_SIZE];
+        let mut parts = s.split(':');
+        for byte in bytes.iter_mut() {
+            let part = parts.next().ok_or_else(invalid)?;
+            // from_str_radix would also take a sign.
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(BtAddress(bytes))
+    }
+}
+
+impl TryFrom<&[u8]> for BtAddress {
+    type Error = BtAddressError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = data
+            .try_into()
+            .map_err(|_| BtAddressError::Length(data.len()))?;
+        Ok(BtAddress(bytes))
+    }
+}
+
+impl From<[u8; BT_ADDRESS_SIZE]> for BtAddress {
+    fn from(bytes: [u8; BT_ADDRESS_SIZE]) -> Self {
+        BtAddress(bytes)
+    }
+}
+
+impl From<BtAddress> for [u8; BT_ADDRESS_SIZE] {
+    fn from(address: BtAddress) -> Self {
+        address.0
+    }
+}
+
+// Some stacks hand addresses around packed into an integer, the top two bytes are unused.
+impl From<BtAddress> for u64 {
+    fn from(address: BtAddress) -> Self {
+        address
+            .0
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | *byte as u64)
+    }
+}
+
+impl TryFrom<u64> for BtAddress {
+    type Error = BtAddressError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        let [0, 0, bytes @ ..] = value.to_be_bytes() else {
+            return Err(BtAddressError::InvalidFormat(format!("{:#x}", value)));
+        };
+        Ok(BtAddress(bytes))
+    }
+}
+
+impl From<BtAddress> for VehicleId {
+    fn from(address: BtAddress) -> Self {
+        VehicleId::new(address.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bt_address_parse_test() {
+        let address: BtAddress = "c2:4A:01:9e:33:0F".parse().unwrap();
+        assert_eq!(
+            BtAddress::new([0xc2, 0x4a, 0x01, 0x9e, 0x33, 0x0f]),
+            address
+        );
+        assert_eq!("C2:4A:01:9E:33:0F", address.to_string());
+        assert_eq!(address, address.to_string().parse().unwrap());
+
+        for invalid in [
+            "",
+            "C2:4A:01:9E:33",
+            "C2:4A:01:9E:33:0F:00",
+            "C2-4A-01-9E-33-0F",
+            "C2:4A:01:9E:33:F",
+            "C2:4A:01:9E:33:0G",
+            "C2:4A:01:9E:+3:0F",
+        ] {
+            assert_eq!(
+                Err(BtAddressError::InvalidFormat(invalid.to_string())),
+                invalid.parse::<BtAddress>()
+            );
+        }
+    }
+
+    #[test]
+    fn bt_address_conversion_test() {
+        let address = BtAddress::from_le_bytes(&[0x0f, 0x33, 0x9e, 0x01, 0x4a, 0xc2]).unwrap();
+        assert_eq!("C2:4A:01:9E:33:0F", address.to_string());
+        assert_eq!([0x0f, 0x33, 0x9e, 0x01, 0x4a, 0xc2], address.to_le_bytes());
+        assert_eq!(
+            Err(BtAddressError::Length(5)),
+            BtAddress::try_from(&[0u8; 5][..])
+        );
+
+        assert_eq!(0xc24a019e330f, u64::from(address));
+        assert_eq!(Ok(address), BtAddress::try_from(0xc24a019e330f));
+        assert!(BtAddress::try_from(1u64 << 48).is_err());
+
+        assert_eq!("C2:4A:01:9E:33:0F", VehicleId::from(address));
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::bt_address::{BtAddress, BT_ADDRESS_SIZE};
+use crate::replay::{ReplayDirection, ReplayError, ReplayRecord, ReplayWriter, ReplayedRecord};
+use crate::vehicle_gatt_profile::{characteristic_role, CharacteristicRole};
+use crate::AnkiVehicleData;
+
+// Pulls vehicle traffic out of Bluetooth sniffer captures: btsnoop files, as written by Android's
+// HCI snoop log and most stack loggers, and pcap files with H4 framed HCI packets, as written by
+// Wireshark and btmon. ATT writes become commands and ATT notifications become notifications, in
+// the same records the replay format uses.
+//
+// The Anki characteristics are picked out from the GATT discovery in the capture. Captures
+// started after discovery don't have it, on those connections any ATT value laid out like a
+// vehicle frame, a size byte followed by exactly that many bytes, is taken.
+
+const BTSNOOP_MAGIC: [u8; 8] = *b"btsnoop\0";
+const BTSNOOP_DATALINK_HCI: u32 = 1001;
+const BTSNOOP_DATALINK_H4: u32 = 1002;
+// Microseconds from year 0 to the unix epoch, btsnoop's time base.
+const BTSNOOP_EPOCH_DELTA_US: u64 = 0x00dc_ddb3_0f2f_8000;
+
+const PCAP_MAGIC_US: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b2_3c4d;
+const PCAP_LINKTYPE_H4: u32 = 187;
+const PCAP_LINKTYPE_H4_WITH_PHDR: u32 = 201;
+
+const H4_ACL: u8 = 0x02;
+const H4_EVENT: u8 = 0x04;
+
+const HCI_EVENT_LE_META: u8 = 0x3e;
+const HCI_LE_CONNECTION_COMPLETE: u8 = 0x01;
+const HCI_LE_ENHANCED_CONNECTION_COMPLETE: u8 = 0x0a;
+
+const L2CAP_CID_ATT: u16 = 0x0004;
+const ATT_READ_BY_TYPE_RSP: u8 = 0x09;
+const ATT_WRITE_REQ: u8 = 0x12;
+const ATT_WRITE_CMD: u8 = 0x52;
+const ATT_HANDLE_VALUE_NTF: u8 = 0x1b;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    UnknownFormat,
+    UnsupportedLinkType(u32),
+    Truncated,
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(e) => write!(f, "Capture I/O error: {}", e),
+            CaptureError::UnknownFormat => {
+                write!(
+                    f,
+                    "Not a btsnoop or pcap capture, pcapng files need saving as pcap"
+                )
+            }
+            CaptureError::UnsupportedLinkType(link_type) => {
+                write!(
+                    f,
+                    "Capture link type {} doesn't carry HCI packets",
+                    link_type
+                )
+            }
+            CaptureError::Truncated => write!(f, "Capture ends in the middle of a packet"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            CaptureError::Truncated
+        } else {
+            CaptureError::Io(e)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CaptureFormat {
+    Btsnoop,
+    Pcap,
+}
+
+// Vehicle traffic found in a capture. Vehicles are named by their BLE address when the capture
+// has the connection being made, otherwise by the connection handle, e.g. "hci:0x0040".
+#[derive(Debug, PartialEq, Clone)]
+pub struct Capture {
+    pub format: CaptureFormat,
+    // Wall clock time of the first packet, record timestamps are relative to it.
+    pub started_unix_ms: u64,
+    pub records: Vec<ReplayRecord>,
+}
+
+impl Capture {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Capture, CaptureError> {
+        Capture::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Capture, CaptureError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Capture::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Capture, CaptureError> {
+        let mut extractor = AttExtractor::default();
+        let format = if data.starts_with(&BTSNOOP_MAGIC) {
+            parse_btsnoop(data, &mut extractor)?;
+            CaptureFormat::Btsnoop
+        } else {
+            parse_pcap(data, &mut extractor)?;
+            CaptureFormat::Pcap
+        };
+        let started_us = extractor.started_us.unwrap_or(0);
+        Ok(Capture {
+            format,
+            started_unix_ms: started_us / 1000,
+            records: extractor.records,
+        })
+    }
+
+    // Writes the records out in the replay format, for the replay player and other tools.
+    pub fn write_replay<W: Write>(&self, writer: W) -> Result<W, ReplayError> {
+        let mut writer = ReplayWriter::new(writer, self.started_unix_ms)?;
+        for record in &self.records {
+            writer.write(record)?;
+        }
+        writer.into_inner()
+    }
+
+    // Runs every notification through the vehicle state for its vehicle, as the replay player
+    // does, without waiting between them.
+    pub fn decode(&self) -> Vec<ReplayedRecord> {
+        let mut vehicles: HashMap<&str, AnkiVehicleData> = HashMap::new();
+        self.records
+            .iter()
+            .map(|record| {
+                let decoded = (record.direction == ReplayDirection::Notification).then(|| {
+                    vehicles
+                        .entry(&record.vehicle)
+                        .or_insert_with(|| {
+                            let mut vehicle = AnkiVehicleData::default();
+                            vehicle.set_name(record.vehicle.to_string());
+                            vehicle
+                        })
+                        .process_notification(&record.frame)
+                });
+                ReplayedRecord {
+                    record: record.clone(),
+                    decoded,
+                }
+            })
+            .collect()
+    }
+}
+
+// A packet header's fields, wherever they came from.
+struct HciPacket<'a> {
+    timestamp_us: u64,
+    packet_type: u8,
+    data: &'a [u8],
+}
+
+fn take<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], CaptureError> {
+    let end = offset.checked_add(len).ok_or(CaptureError::Truncated)?;
+    let bytes = data.get(*offset..end).ok_or(CaptureError::Truncated)?;
+    *offset = end;
+    Ok(bytes)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn le_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn parse_btsnoop(data: &[u8], extractor: &mut AttExtractor) -> Result<(), CaptureError> {
+    let offset = &mut BTSNOOP_MAGIC.len();
+    let _version = be_u32(take(data, offset, 4)?);
+    let datalink = be_u32(take(data, offset, 4)?);
+    if datalink != BTSNOOP_DATALINK_HCI && datalink != BTSNOOP_DATALINK_H4 {
+        return Err(CaptureError::UnsupportedLinkType(datalink));
+    }
+
+    while *offset < data.len() {
+        let header = take(data, offset, 24)?;
+        let included_len = be_u32(&header[4..8]) as usize;
+        let flags = be_u32(&header[8..12]);
+        let timestamp = u64::from_be_bytes(header[16..24].try_into().unwrap());
+        let packet = take(data, offset, included_len)?;
+        let timestamp_us = timestamp.saturating_sub(BTSNOOP_EPOCH_DELTA_US);
+
+        let packet = if datalink == BTSNOOP_DATALINK_H4 {
+            let Some((&packet_type, data)) = packet.split_first() else {
+                continue;
+            };
+            HciPacket {
+                timestamp_us,
+                packet_type,
+                data,
+            }
+        } else {
+            // Bit 1 marks commands and events, and bit 0 which way it went.
+            let packet_type = match flags & 0x3 {
+                0x3 => H4_EVENT,
+                0x0 | 0x1 => H4_ACL,
+                _ => continue,
+            };
+            HciPacket {
+                timestamp_us,
+                packet_type,
+                data: packet,
+            }
+        };
+        extractor.packet(&packet);
+    }
+    Ok(())
+}
+
+fn parse_pcap(data: &[u8], extractor: &mut AttExtractor) -> Result<(), CaptureError> {
+    let offset = &mut 0;
+    let magic = take(data, offset, 4)?;
+    let (read_u32, nanos): (fn(&[u8]) -> u32, bool) = match be_u32(magic) {
+        PCAP_MAGIC_US => (be_u32, false),
+        PCAP_MAGIC_NS => (be_u32, true),
+        magic if magic.swap_bytes() == PCAP_MAGIC_US => (le_u32, false),
+        magic if magic.swap_bytes() == PCAP_MAGIC_NS => (le_u32, true),
+        _ => return Err(CaptureError::UnknownFormat),
+    };
+    let header = take(data, offset, 20)?;
+    let link_type = read_u32(&header[16..20]);
+    if link_type != PCAP_LINKTYPE_H4 && link_type != PCAP_LINKTYPE_H4_WITH_PHDR {
+        return Err(CaptureError::UnsupportedLinkType(link_type));
+    }
+
+    while *offset < data.len() {
+        let header = take(data, offset, 16)?;
+        let secs = read_u32(&header[0..4]) as u64;
+        let fraction = read_u32(&header[4..8]) as u64;
+        let included_len = read_u32(&header[8..12]) as usize;
+        let mut packet = take(data, offset, included_len)?;
+        if link_type == PCAP_LINKTYPE_H4_WITH_PHDR {
+            // Direction, which the ATT opcode tells us anyway.
+            packet = packet.get(4..).unwrap_or_default();
+        }
+        let Some((&packet_type, data)) = packet.split_first() else {
+            continue;
+        };
+        let fraction_us = if nanos { fraction / 1000 } else { fraction };
+        extractor.packet(&HciPacket {
+            timestamp_us: secs * 1_000_000 + fraction_us,
+            packet_type,
+            data,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct AnkiHandles {
+    read: Option<u16>,
+    write: Option<u16>,
+}
+
+impl AnkiHandles {
+    fn discovered(&self) -> bool {
+        self.read.is_some() || self.write.is_some()
+    }
+}
+
+#[derive(Default)]
+struct AttExtractor {
+    started_us: Option<u64>,
+    names: HashMap<u16, String>,
+    handles: HashMap<u16, AnkiHandles>,
+    records: Vec<ReplayRecord>,
+}
+
+impl AttExtractor {
+    fn packet(&mut self, packet: &HciPacket) {
+        let started_us = *self.started_us.get_or_insert(packet.timestamp_us);
+        match packet.packet_type {
+            H4_EVENT => self.event(packet.data),
+            H4_ACL => {
+                let timestamp_ms = packet.timestamp_us.saturating_sub(started_us) / 1000;
+                self.acl(packet.data, timestamp_ms);
+            }
+            _ => {}
+        }
+    }
+
+    // Only connection complete events matter, for the address of the vehicle on each handle.
+    fn event(&mut self, data: &[u8]) {
+        let [HCI_EVENT_LE_META, _len, subevent, status, handle_lo, handle_hi, _role, _addr_type, address @ ..] =
+            data
+        else {
+            return;
+        };
+        if (*subevent != HCI_LE_CONNECTION_COMPLETE
+            && *subevent != HCI_LE_ENHANCED_CONNECTION_COMPLETE)
+            || *status != 0
+            || address.len() < BT_ADDRESS_SIZE
+        {
+            return;
+        }
+        let handle = u16::from_le_bytes([*handle_lo, *handle_hi]) & 0x0fff;
+        let Ok(address) = BtAddress::from_le_bytes(&address[..BT_ADDRESS_SIZE]) else {
+            return;
+        };
+        let name = address.to_string();
+        self.names.insert(handle, name);
+        // A new connection on a reused handle has to be discovered again.
+        self.handles.remove(&handle);
+    }
+
+    // LE ATT PDUs carrying vehicle frames fit in one ACL packet, continuation fragments are
+    // skipped.
+    fn acl(&mut self, data: &[u8], timestamp_ms: u64) {
+        if data.len() < 8 {
+            return;
+        }
+        let handle_flags = le_u16(&data[0..2]);
+        let packet_boundary = (handle_flags >> 12) & 0x3;
+        if packet_boundary == 0x1 {
+            return;
+        }
+        let handle = handle_flags & 0x0fff;
+        let l2cap_len = le_u16(&data[4..6]) as usize;
+        let cid = le_u16(&data[6..8]);
+        if cid != L2CAP_CID_ATT {
+            return;
+        }
+        let Some(att) = data.get(8..8 + l2cap_len) else {
+            return;
+        };
+        self.att(handle, att, timestamp_ms);
+    }
+
+    fn att(&mut self, connection: u16, att: &[u8], timestamp_ms: u64) {
+        let Some((&opcode, pdu)) = att.split_first() else {
+            return;
+        };
+        match opcode {
+            ATT_READ_BY_TYPE_RSP => self.characteristics(connection, pdu),
+            ATT_WRITE_REQ | ATT_WRITE_CMD | ATT_HANDLE_VALUE_NTF if pdu.len() >= 2 => {
+                let attribute = le_u16(&pdu[0..2]);
+                let value = &pdu[2..];
+                let handles = self.handles.entry(connection).or_default();
+                let anki = if handles.discovered() {
+                    if opcode == ATT_HANDLE_VALUE_NTF {
+                        handles.read == Some(attribute)
+                    } else {
+                        handles.write == Some(attribute)
+                    }
+                } else {
+                    looks_like_frame(value)
+                };
+                if !anki {
+                    return;
+                }
+                let vehicle = self
+                    .names
+                    .get(&connection)
+                    .cloned()
+                    .unwrap_or_else(|| format!("hci:{:#06x}", connection));
+                self.records.push(if opcode == ATT_HANDLE_VALUE_NTF {
+                    ReplayRecord::notification(timestamp_ms, &vehicle, value)
+                } else {
+                    ReplayRecord::command(timestamp_ms, &vehicle, value)
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // Characteristic declarations: handle, properties, value handle and UUID, each entry the
+    // length given in the first byte.
+    fn characteristics(&mut self, connection: u16, pdu: &[u8]) {
+        let Some((&len, entries)) = pdu.split_first() else {
+            return;
+        };
+        if len != 21 {
+            return;
+        }
+        for entry in entries.chunks_exact(len as usize) {
+            let value_handle = le_u16(&entry[3..5]);
+            let uuid = u128::from_le_bytes(entry[5..].try_into().unwrap());
+            let handles = self.handles.entry(connection).or_default();
+            match characteristic_role(uuid) {
+                Some(CharacteristicRole::Read) => handles.read = Some(value_handle),
+                Some(CharacteristicRole::Write) => handles.write = Some(value_handle),
+                None => {}
+            }
+        }
+    }
+}
+
+fn looks_like_frame(value: &[u8]) -> bool {
+    value.len() >= 2 && value[0] as usize == value.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::AnkiVehicleMsgType;
+    use crate::replay::ReplayReader;
+
+    const VEHICLE_ADDRESS: [u8; 6] = [0x66, 0x55, 0x44, 0x33, 0x22, 0x11];
+
+    fn acl(connection: u16, att: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&(connection | 0x2000).to_le_bytes());
+        packet.extend_from_slice(&(att.len() as u16 + 4).to_le_bytes());
+        packet.extend_from_slice(&(att.len() as u16).to_le_bytes());
+        packet.extend_from_slice(&L2CAP_CID_ATT.to_le_bytes());
+        packet.extend_from_slice(att);
+        packet
+    }
+
+    fn att_value(opcode: u8, attribute: u16, value: &[u8]) -> Vec<u8> {
+        let mut att = vec![opcode];
+        att.extend_from_slice(&attribute.to_le_bytes());
+        att.extend_from_slice(value);
+        att
+    }
+
+    fn discovery() -> Vec<u8> {
+        let mut att = vec![ATT_READ_BY_TYPE_RSP, 21];
+        for (handle, role) in [
+            (0x000d, CharacteristicRole::Read),
+            (0x0010, CharacteristicRole::Write),
+        ] {
+            att.extend_from_slice(&(handle - 1u16).to_le_bytes());
+            att.push(0x12);
+            att.extend_from_slice(&handle.to_le_bytes());
+            att.extend_from_slice(&role.as_u128().to_le_bytes());
+        }
+        att
+    }
+
+    fn connection_complete(connection: u16) -> Vec<u8> {
+        let mut event = vec![HCI_EVENT_LE_META, 19, HCI_LE_CONNECTION_COMPLETE, 0];
+        event.extend_from_slice(&connection.to_le_bytes());
+        event.extend_from_slice(&[0x00, 0x01]);
+        event.extend_from_slice(&VEHICLE_ADDRESS);
+        event.extend_from_slice(&[0x18, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00]);
+        event
+    }
+
+    fn btsnoop(packets: &[(u8, u64, Vec<u8>)]) -> Vec<u8> {
+        let mut data = BTSNOOP_MAGIC.to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&BTSNOOP_DATALINK_H4.to_be_bytes());
+        for (packet_type, at_ms, packet) in packets {
+            let len = packet.len() as u32 + 1;
+            data.extend_from_slice(&len.to_be_bytes());
+            data.extend_from_slice(&len.to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            let timestamp = BTSNOOP_EPOCH_DELTA_US + 1_700_000_000_000_000 + at_ms * 1000;
+            data.extend_from_slice(&timestamp.to_be_bytes());
+            data.push(*packet_type);
+            data.extend_from_slice(packet);
+        }
+        data
+    }
+
+    #[test]
+    fn btsnoop_capture_test() {
+        let speed = AnkiVehicleData::set_speed(500, 1000);
+        let battery = [0x03, 0x1b, 0xc4, 0x0e];
+        let data = btsnoop(&[
+            (H4_EVENT, 0, connection_complete(0x0040)),
+            (H4_ACL, 5, acl(0x0040, &discovery())),
+            (
+                H4_ACL,
+                10,
+                acl(0x0040, &att_value(ATT_WRITE_CMD, 0x0010, &speed)),
+            ),
+            // The battery service, not the vehicle's.
+            (
+                H4_ACL,
+                12,
+                acl(
+                    0x0040,
+                    &att_value(ATT_HANDLE_VALUE_NTF, 0x0020, &[0x01, 0x64]),
+                ),
+            ),
+            (
+                H4_ACL,
+                20,
+                acl(0x0040, &att_value(ATT_HANDLE_VALUE_NTF, 0x000d, &battery)),
+            ),
+        ]);
+
+        let capture = Capture::parse(&data).unwrap();
+        assert_eq!(CaptureFormat::Btsnoop, capture.format);
+        assert_eq!(1_700_000_000_000, capture.started_unix_ms);
+        assert_eq!(
+            vec![
+                ReplayRecord::command(10, "11:22:33:44:55:66", &speed),
+                ReplayRecord::notification(20, "11:22:33:44:55:66", &battery),
+            ],
+            capture.records
+        );
+
+        let decoded = capture.decode();
+        assert!(decoded[0].decoded.is_none());
+        assert_eq!(
+            AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            *decoded[1].decoded.as_ref().unwrap().as_ref().unwrap()
+        );
+
+        let replay = capture.write_replay(Vec::new()).unwrap();
+        let reader = ReplayReader::new(replay.as_slice()).unwrap();
+        assert_eq!(1_700_000_000_000, reader.header().started_unix_ms);
+        assert_eq!(2, reader.count());
+    }
+
+    #[test]
+    fn pcap_capture_test() {
+        // Little endian, microseconds, H4 with a direction header, no discovery.
+        let mut data = PCAP_MAGIC_US.to_le_bytes().to_vec();
+        data.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0]);
+        data.extend_from_slice(&PCAP_LINKTYPE_H4_WITH_PHDR.to_le_bytes());
+        let version = [0x03, 0x19, 0x76, 0x26];
+        for (at_us, att) in [
+            (
+                1_000_250u32,
+                att_value(ATT_HANDLE_VALUE_NTF, 0x000d, &version),
+            ),
+            (1_002_000, att_value(ATT_HANDLE_VALUE_NTF, 0x0020, &[0x64])),
+        ] {
+            let mut packet = vec![0, 0, 0, 1, H4_ACL];
+            packet.extend_from_slice(&acl(0x0041, &att));
+            data.extend_from_slice(&(at_us / 1_000_000).to_le_bytes());
+            data.extend_from_slice(&(at_us % 1_000_000).to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(&packet);
+        }
+
+        let capture = Capture::parse(&data).unwrap();
+        assert_eq!(CaptureFormat::Pcap, capture.format);
+        assert_eq!(1_000, capture.started_unix_ms);
+        assert_eq!(1, capture.records.len());
+        assert_eq!(ReplayDirection::Notification, capture.records[0].direction);
+        assert_eq!("hci:0x0041", capture.records[0].vehicle);
+        assert_eq!(version.to_vec(), capture.records[0].frame);
+    }
+
+    #[test]
+    fn capture_error_test() {
+        assert!(matches!(
+            Capture::parse(b"not a capture"),
+            Err(CaptureError::UnknownFormat)
+        ));
+        let mut data = btsnoop(&[(H4_ACL, 0, acl(0x0040, &discovery()))]);
+        data.truncate(data.len() - 3);
+        assert!(matches!(
+            Capture::parse(&data),
+            Err(CaptureError::Truncated)
+        ));
+        let mut data = BTSNOOP_MAGIC.to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&1003u32.to_be_bytes());
+        assert!(matches!(
+            Capture::parse(&data),
+            Err(CaptureError::UnsupportedLinkType(1003))
+        ));
+    }
+}
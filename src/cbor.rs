@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use crate::race::RaceEvent;
+use crate::AnkiVehicleData;
+
+// Compact binary telemetry for relays that can't afford JSON, e.g. a serial or LoRa link to a
+// scoreboard. Frames are self-describing CBOR so receivers only need a generic CBOR decoder.
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TelemetrySnapshot {
+    pub vehicle: String,
+    pub timestamp_ms: u64,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub battery_level: u16,
+}
+
+impl TelemetrySnapshot {
+    pub fn from_vehicle(timestamp_ms: u64, vehicle: &AnkiVehicleData) -> TelemetrySnapshot {
+        TelemetrySnapshot {
+            vehicle: vehicle.name.clone(),
+            timestamp_ms,
+            location_id: vehicle.location_id,
+            road_piece_idx: vehicle.road_piece_idx,
+            offset_from_road_centre_mm: vehicle.offset_from_road_centre_mm,
+            speed_mm_per_sec: vehicle.speed_mm_per_sec,
+            battery_level: vehicle.battery_level,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TelemetryEvent {
+    LapCompleted {
+        vehicle: String,
+        lap: u16,
+        lap_ms: u64,
+    },
+    Eliminated {
+        vehicle: String,
+        position: usize,
+    },
+    Parked {
+        vehicle: String,
+    },
+    Winner {
+        vehicle: String,
+    },
+    Incident {
+        road_piece_id: u8,
+        vehicles: Vec<String>,
+    },
+    SafetyCarDeployed,
+    SafetyCarRecalled,
+    Countdown {
+        remaining: u8,
+    },
+    Started,
+    Finished {
+        vehicle: String,
+        position: usize,
+        race_ms: u64,
+    },
+    Overtake {
+        vehicle: String,
+        overtaken: String,
+        position: usize,
+    },
+    DidNotFinish {
+        vehicle: String,
+    },
+}
+
+impl From<&RaceEvent> for TelemetryEvent {
+    fn from(event: &RaceEvent) -> TelemetryEvent {
+        match event {
+            RaceEvent::LapCompleted {
+                vehicle,
+                lap,
+                lap_time,
+            } => TelemetryEvent::LapCompleted {
+                vehicle: vehicle.to_string(),
+                lap: *lap,
+                lap_ms: lap_time.as_millis() as u64,
+            },
+            RaceEvent::Eliminated { vehicle, position } => TelemetryEvent::Eliminated {
+                vehicle: vehicle.to_string(),
+                position: *position,
+            },
+            RaceEvent::Parked { vehicle } => TelemetryEvent::Parked {
+                vehicle: vehicle.to_string(),
+            },
+            RaceEvent::Winner { vehicle } => TelemetryEvent::Winner {
+                vehicle: vehicle.to_string(),
+            },
+            RaceEvent::Incident(report) => TelemetryEvent::Incident {
+                road_piece_id: report.road_piece_id,
+                vehicles: report.vehicles.iter().map(ToString::to_string).collect(),
+            },
+            RaceEvent::SafetyCarDeployed => TelemetryEvent::SafetyCarDeployed,
+            RaceEvent::SafetyCarRecalled => TelemetryEvent::SafetyCarRecalled,
+            RaceEvent::Countdown { remaining } => TelemetryEvent::Countdown {
+                remaining: *remaining,
+            },
+            RaceEvent::Started => TelemetryEvent::Started,
+            RaceEvent::Finished {
+                vehicle,
+                position,
+                race_time,
+            } => TelemetryEvent::Finished {
+                vehicle: vehicle.to_string(),
+                position: *position,
+                race_ms: race_time.as_millis() as u64,
+            },
+            RaceEvent::Overtake {
+                vehicle,
+                overtaken,
+                position,
+            } => TelemetryEvent::Overtake {
+                vehicle: vehicle.to_string(),
+                overtaken: overtaken.to_string(),
+                position: *position,
+            },
+            RaceEvent::DidNotFinish { vehicle } => TelemetryEvent::DidNotFinish {
+                vehicle: vehicle.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TelemetryFrame {
+    Snapshot(TelemetrySnapshot),
+    Event(TelemetryEvent),
+}
+
+impl TelemetryFrame {
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::into_writer(self, &mut data).expect("Failed to serialize TelemetryFrame as CBOR");
+        data
+    }
+
+    pub fn from_cbor(data: &[u8]) -> Result<TelemetryFrame, ciborium::de::Error<io::Error>> {
+        ciborium::from_reader(data)
+    }
+}
+
+impl From<TelemetrySnapshot> for TelemetryFrame {
+    fn from(snapshot: TelemetrySnapshot) -> Self {
+        TelemetryFrame::Snapshot(snapshot)
+    }
+}
+
+impl From<&RaceEvent> for TelemetryFrame {
+    fn from(event: &RaceEvent) -> Self {
+        TelemetryFrame::Event(event.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cbor_snapshot_round_trip_test() {
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_name("Skull".to_string());
+        vehicle.speed_mm_per_sec = 600;
+        vehicle.battery_level = 3900;
+        let frame: TelemetryFrame = TelemetrySnapshot::from_vehicle(1234, &vehicle).into();
+
+        let data = frame.to_cbor();
+        println!("T:{:?} == G:{:?}", TelemetryFrame::from_cbor(&data), frame);
+        assert_eq!(frame, TelemetryFrame::from_cbor(&data).unwrap());
+        assert!(data.len() < 160);
+    }
+
+    #[test]
+    fn cbor_event_round_trip_test() {
+        let frame: TelemetryFrame = (&RaceEvent::LapCompleted {
+            vehicle: "Skull".into(),
+            lap: 2,
+            lap_time: Duration::from_millis(5250),
+        })
+            .into();
+        assert_eq!(frame, TelemetryFrame::from_cbor(&frame.to_cbor()).unwrap());
+        assert!(TelemetryFrame::from_cbor(&[0xff, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Where timestamps come from. Anything that reads the time or waits on it takes a clock, so a
+// test or simulation can run it on virtual time without sleeping and get the same result every
+// time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+// The monotonic system clock, what everything uses unless told otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Only moves when advanced, sleeping advances it by the time slept and returns straight away.
+// Share it behind an `Arc` to drive several components from the same virtual time.
+#[derive(Debug)]
+pub struct VirtualClock {
+    origin: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock {
+            origin: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    // Virtual time passed since the clock was made.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_clock_test() {
+        let clock = VirtualClock::new();
+        let t0 = clock.now();
+        assert_eq!(t0, clock.now());
+
+        clock.advance(Duration::from_millis(250));
+        clock.sleep(Duration::from_secs(3600));
+        assert_eq!(Duration::from_millis(3_600_250), clock.now() - t0);
+        assert_eq!(Duration::from_millis(3_600_250), clock.elapsed());
+    }
+}
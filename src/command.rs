@@ -0,0 +1,441 @@
+use scroll::{ctx, Pwrite};
+use smallvec::SmallVec;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::error::AnkiError;
+use crate::protocol::*;
+
+// Number of commands `AnkiVehicleData::configure` sends to a freshly connected vehicle.
+pub const CONFIGURE_COMMAND_COUNT: usize = 5;
+
+// Commands usually go out one to three at a time, batches up to this size stay inline.
+pub const COMMAND_BATCH_INLINE: usize = 3;
+
+pub type CommandBatch = SmallVec<[VehicleCommand; COMMAND_BATCH_INLINE]>;
+
+// Anything that can be sent to a vehicle as one frame.
+pub trait WireMessage {
+    fn msg_type(&self) -> AnkiVehicleMsgType;
+    fn encode(&self) -> Command;
+}
+
+// One lights pattern channel, the arguments of `anki_vehicle_light_config`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightPattern {
+    pub channel: LightChannel,
+    pub effect: LightEffect,
+    pub start: u8,
+    pub end: u8,
+    pub cycles_per_min: u16,
+}
+
+// A command that hasn't been encoded yet, so it can still be looked at or changed. Every field
+// is a plain value, encoding one can't fail.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum VehicleCommand {
+    Disconnect,
+    PingRequest,
+    VersionRequest,
+    BatteryLevelRequest,
+    SdkMode {
+        on: bool,
+        flags: u8,
+    },
+    SetSpeed {
+        speed_mm_per_sec: i16,
+        accel_mm_per_sec2: i16,
+    },
+    SetOffsetFromRoadCentre {
+        offset_mm: f32,
+    },
+    ChangeLane {
+        horizontal_speed_mm_per_sec: u16,
+        horizontal_accel_mm_per_sec2: u16,
+        offset_from_road_centre_mm: f32,
+    },
+    CancelLaneChange,
+    Turn {
+        turn_type: VehicleTurn,
+        trigger: VehicleTurnTrigger,
+    },
+    SetLights {
+        light_mask: u8,
+    },
+    // Vehicles take up to three channels, any after the third are left out.
+    LightsPattern {
+        channels: SmallVec<[LightPattern; 3]>,
+    },
+    SetConfigParams {
+        super_code_parse_mask: u8,
+        track_material: TrackMaterial,
+    },
+}
+
+impl VehicleCommand {
+    pub fn lights_pattern(pattern: LightPattern) -> VehicleCommand {
+        let mut channels = SmallVec::new();
+        channels.push(pattern);
+        VehicleCommand::LightsPattern { channels }
+    }
+}
+
+impl WireMessage for VehicleCommand {
+    fn msg_type(&self) -> AnkiVehicleMsgType {
+        match self {
+            VehicleCommand::Disconnect => AnkiVehicleMsgType::C2VDisconnect,
+            VehicleCommand::PingRequest => AnkiVehicleMsgType::C2CPingRequest,
+            VehicleCommand::VersionRequest => AnkiVehicleMsgType::C2VVersionRequest,
+            VehicleCommand::BatteryLevelRequest => AnkiVehicleMsgType::C2VBatteryLevelRequest,
+            VehicleCommand::SdkMode { .. } => AnkiVehicleMsgType::C2VSDKMode,
+            VehicleCommand::SetSpeed { .. } => AnkiVehicleMsgType::C2VSetSpeed,
+            VehicleCommand::SetOffsetFromRoadCentre { .. } => {
+                AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre
+            }
+            VehicleCommand::ChangeLane { .. } => AnkiVehicleMsgType::C2VChangeLane,
+            VehicleCommand::CancelLaneChange => AnkiVehicleMsgType::C2VCancelLaneChange,
+            VehicleCommand::Turn { .. } => AnkiVehicleMsgType::C2VTurn,
+            VehicleCommand::SetLights { .. } => AnkiVehicleMsgType::C2VSetLights,
+            VehicleCommand::LightsPattern { .. } => AnkiVehicleMsgType::C2VLightsPattern,
+            VehicleCommand::SetConfigParams { .. } => AnkiVehicleMsgType::C2VSetConfigParams,
+        }
+    }
+
+    fn encode(&self) -> Command {
+        let encoded = match self.clone() {
+            VehicleCommand::Disconnect => Ok(Command::DISCONNECT),
+            VehicleCommand::PingRequest => Ok(Command::PING),
+            VehicleCommand::VersionRequest => Ok(Command::VERSION_REQUEST),
+            VehicleCommand::BatteryLevelRequest => Ok(Command::BATTERY_LEVEL_REQUEST),
+            VehicleCommand::SdkMode { on, flags } => Command::encode(
+                anki_vehicle_msg_set_sdk_mode(on as u8, flags),
+                ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+            ),
+            VehicleCommand::SetSpeed {
+                speed_mm_per_sec,
+                accel_mm_per_sec2,
+            } => Command::encode(
+                anki_vehicle_msg_set_speed(speed_mm_per_sec, accel_mm_per_sec2),
+                ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+            ),
+            VehicleCommand::SetOffsetFromRoadCentre { offset_mm } => Command::encode(
+                anki_vehicle_msg_set_offset_from_road_centre(offset_mm),
+                ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+            ),
+            VehicleCommand::ChangeLane {
+                horizontal_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2,
+                offset_from_road_centre_mm,
+            } => Command::encode(
+                anki_vehicle_msg_change_lane(
+                    horizontal_speed_mm_per_sec,
+                    horizontal_accel_mm_per_sec2,
+                    offset_from_road_centre_mm,
+                ),
+                ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+            ),
+            VehicleCommand::CancelLaneChange => Ok(Command::CANCEL_LANE_CHANGE),
+            VehicleCommand::Turn { turn_type, trigger } => Command::encode(
+                anki_vehicle_msg_turn(turn_type, trigger),
+                ANKI_VEHICLE_MSG_TURN_SIZE,
+            ),
+            VehicleCommand::SetLights { light_mask } => Command::encode(
+                anki_vehicle_msg_set_lights(light_mask),
+                ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+            ),
+            VehicleCommand::LightsPattern { channels } => Command::encode(
+                lights_pattern_msg(channels),
+                ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+            ),
+            VehicleCommand::SetConfigParams {
+                super_code_parse_mask,
+                track_material,
+            } => Command::encode(
+                anki_vehicle_msg_set_config_params(super_code_parse_mask, track_material),
+                ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+            ),
+        };
+        encoded.expect("Failed to write VehicleCommand as bytes")
+    }
+}
+
+fn lights_pattern_msg(channels: SmallVec<[LightPattern; 3]>) -> AnkiVehicleMsgLightsPattern {
+    let mut msg = anki_vehicle_msg_lights_pattern_empty();
+    for p in channels {
+        let config =
+            anki_vehicle_light_config(p.channel, p.effect, p.start, p.end, p.cycles_per_min);
+        if msg.append(config).is_err() {
+            break;
+        }
+    }
+    msg
+}
+
+impl From<VehicleCommand> for Command {
+    fn from(command: VehicleCommand) -> Command {
+        command.encode()
+    }
+}
+
+impl From<VehicleCommand> for Vec<u8> {
+    fn from(command: VehicleCommand) -> Vec<u8> {
+        command.encode().into()
+    }
+}
+
+// One encoded command, held inline so building one never touches the heap. It derefs to the
+// encoded bytes, the unused tail of the buffer is never exposed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Command {
+    data: [u8; ANKI_VEHICLE_MSG_MAX_SIZE],
+    len: usize,
+}
+
+impl Command {
+    pub const DISCONNECT: Command = Command::from_header(ANKI_VEHICLE_MSG_DISCONNECT_BYTES);
+    pub const PING: Command = Command::from_header(ANKI_VEHICLE_MSG_PING_BYTES);
+    pub const VERSION_REQUEST: Command =
+        Command::from_header(ANKI_VEHICLE_MSG_VERSION_REQUEST_BYTES);
+    pub const BATTERY_LEVEL_REQUEST: Command =
+        Command::from_header(ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_BYTES);
+    pub const CANCEL_LANE_CHANGE: Command =
+        Command::from_header(ANKI_VEHICLE_MSG_CANCEL_LANE_CHANGE_BYTES);
+
+    // A message that is nothing but the size and id bytes, built at compile time when used in a
+    // const.
+    pub const fn from_header(header: [u8; ANKI_VEHICLE_MSG_BASE_SIZE]) -> Command {
+        let mut data = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        data[0] = header[0];
+        data[1] = header[1];
+        Command {
+            data,
+            len: ANKI_VEHICLE_MSG_BASE_SIZE,
+        }
+    }
+
+    // `size` is the encoded size of the message, one of the ANKI_VEHICLE_MSG_*_SIZE constants.
+    pub fn encode<T>(msg: T, size: usize) -> Result<Command, AnkiError>
+    where
+        T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+    {
+        let mut data = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        if size > data.len() {
+            return Err(AnkiError::BufferSize {
+                expected: size,
+                got: data.len(),
+            });
+        }
+        let len = data[..size].pwrite_with::<T>(msg, 0, WIRE_ENDIAN)?;
+        Ok(Command { data, len })
+    }
+
+    // Copies the command into `buf`, returning how many bytes were written.
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, AnkiError> {
+        if buf.len() < self.len {
+            return Err(AnkiError::BufferSize {
+                expected: self.len,
+                got: buf.len(),
+            });
+        }
+        buf[..self.len].copy_from_slice(self);
+        Ok(self.len)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Deref for Command {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Command {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Command").field(&self.as_slice()).finish()
+    }
+}
+
+impl PartialEq<[u8]> for Command {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl PartialEq<Vec<u8>> for Command {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+// Hosts own the frames they are given, this is the one place a command is copied to the heap.
+impl From<Command> for Vec<u8> {
+    fn from(command: Command) -> Vec<u8> {
+        command.to_vec()
+    }
+}
+
+// Already encoded, the type is read back from the frame header.
+impl WireMessage for Command {
+    fn msg_type(&self) -> AnkiVehicleMsgType {
+        self.get(1)
+            .and_then(|&id| AnkiVehicleMsgType::try_from(id).ok())
+            .unwrap_or(AnkiVehicleMsgType::Unknown)
+    }
+
+    fn encode(&self) -> Command {
+        *self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_encode_test() {
+        let command = Command::encode(
+            anki_vehicle_msg_set_speed(500, 1000),
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        )
+        .unwrap();
+        assert_eq!(ANKI_VEHICLE_MSG_SET_SPEED_SIZE, command.len());
+        assert_eq!(AnkiVehicleMsgType::C2VSetSpeed as u8, command[1]);
+        assert_eq!(command, Vec::from(command));
+    }
+
+    #[test]
+    fn header_only_command_test() {
+        const PING: AnkiVehicleMsg<'static> = anki_vehicle_msg_ping();
+        static DISCONNECT: [u8; ANKI_VEHICLE_MSG_DISCONNECT_SIZE] =
+            ANKI_VEHICLE_MSG_DISCONNECT_BYTES;
+        assert_eq!(AnkiVehicleMsgType::C2CPingRequest, PING.msg_id);
+        assert_eq!([0x01, 0x0d], DISCONNECT);
+
+        for (constant, msg) in [
+            (Command::DISCONNECT, anki_vehicle_msg_disconnect()),
+            (Command::PING, anki_vehicle_msg_ping()),
+            (Command::VERSION_REQUEST, anki_vehicle_msg_get_version()),
+            (
+                Command::BATTERY_LEVEL_REQUEST,
+                anki_vehicle_msg_get_battery_level(),
+            ),
+            (
+                Command::CANCEL_LANE_CHANGE,
+                anki_vehicle_msg_cancel_lane_change(),
+            ),
+        ] {
+            assert_eq!(
+                Command::encode(msg, ANKI_VEHICLE_MSG_BASE_SIZE).unwrap(),
+                constant
+            );
+        }
+    }
+
+    #[test]
+    fn command_write_to_test() {
+        let command = Command::encode(
+            anki_vehicle_msg_set_speed(500, 1000),
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        )
+        .unwrap();
+        let mut buf = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        assert_eq!(
+            Ok(command.len()),
+            command.write_to(&mut buf).map_err(|_| ())
+        );
+        assert_eq!(command, buf[..command.len()]);
+        assert!(matches!(
+            command.write_to(&mut buf[..2]),
+            Err(AnkiError::BufferSize {
+                expected: 7,
+                got: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn command_batch_test() {
+        let mut batch = CommandBatch::new();
+        for speed in [300, 600, 900] {
+            batch.push(VehicleCommand::SetSpeed {
+                speed_mm_per_sec: speed,
+                accel_mm_per_sec2: 1000,
+            });
+        }
+        assert!(!batch.spilled());
+        batch.push(batch[0].clone());
+        assert!(batch.spilled());
+    }
+
+    #[test]
+    fn vehicle_command_encode_test() {
+        let mut command = VehicleCommand::SetSpeed {
+            speed_mm_per_sec: 400,
+            accel_mm_per_sec2: 1000,
+        };
+        if let VehicleCommand::SetSpeed {
+            speed_mm_per_sec, ..
+        } = &mut command
+        {
+            *speed_mm_per_sec = 500;
+        }
+        let encoded = command.encode();
+        assert_eq!(AnkiVehicleMsgType::C2VSetSpeed, command.msg_type());
+        assert_eq!(command.msg_type(), encoded.msg_type());
+        assert_eq!(
+            Command::encode(
+                anki_vehicle_msg_set_speed(500, 1000),
+                ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+            )
+            .unwrap(),
+            encoded
+        );
+
+        let mut pattern =
+            anki_vehicle_msg_lights_pattern(LightChannel::Red, LightEffect::Flash, 0, 14, 120);
+        pattern
+            .append(anki_vehicle_light_config(
+                LightChannel::Green,
+                LightEffect::Flash,
+                0,
+                14,
+                120,
+            ))
+            .unwrap();
+        let red = LightPattern {
+            channel: LightChannel::Red,
+            effect: LightEffect::Flash,
+            start: 0,
+            end: 14,
+            cycles_per_min: 120,
+        };
+        let green = LightPattern {
+            channel: LightChannel::Green,
+            ..red.clone()
+        };
+        let command = VehicleCommand::LightsPattern {
+            channels: [red, green].into_iter().collect(),
+        };
+        assert_eq!(
+            Command::encode(pattern, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE).unwrap(),
+            Command::from(command)
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::command::{CommandBatch, LightPattern, VehicleCommand};
+use crate::protocol::{LightChannel, LightEffect, ANKI_VEHICLE_MAX_LIGHT_INTENSITY};
+use crate::race::elimination::{EliminationConfig, EliminationInterval};
+use crate::race::incident::IncidentConfig;
+use crate::race::leaderboard::FINISH_LINE_ROAD_PIECE_ID;
+use crate::race::safety_car::{
+    SafetyCar, SAFETY_CAR_ACCEL_MM_PER_SEC2, SAFETY_CAR_SPEED_MM_PER_SEC,
+};
+use crate::race::time_trial::SectorLayout;
+
+// A rig described in TOML, so the vehicles, track and race rules can change without a rebuild:
+//
+//   [[vehicles]]
+//   id = "skull"
+//   name = "Skull"
+//   lane = 0
+//   lights = "team_red"
+//
+//   [lanes]
+//   offsets_mm = [-68.0, -23.0, 23.0, 68.0]
+//
+//   [race.elimination]
+//   every_laps = 2
+//   parking_road_piece_id = 17
+//
+//   [lights.team_red]
+//   channel = "red"
+//   effect = "throb"
+//   end = 14
+//   cycles_per_min = 60
+//
+// Every section is optional, durations are given in milliseconds.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FleetConfig {
+    pub vehicles: Vec<VehicleConfig>,
+    pub lanes: LaneConfig,
+    pub race: RaceRules,
+    pub lights: HashMap<String, LightPreset>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleConfig {
+    // Whatever the host identifies the vehicle by, usually its BLE address.
+    pub id: String,
+    pub name: Option<String>,
+    // Index into `lanes.offsets_mm` to start in.
+    pub lane: Option<usize>,
+    // Name of a preset in `lights`.
+    pub lights: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LaneConfig {
+    // Lane centres measured from the road centre, left to right.
+    pub offsets_mm: Vec<f32>,
+    pub horizontal_speed_mm_per_sec: u16,
+    pub horizontal_accel_mm_per_sec2: u16,
+}
+
+impl Default for LaneConfig {
+    fn default() -> Self {
+        // Same lane change speeds as `AnkiVehicleData::configure`.
+        LaneConfig {
+            offsets_mm: Vec::new(),
+            horizontal_speed_mm_per_sec: 300,
+            horizontal_accel_mm_per_sec2: 2500,
+        }
+    }
+}
+
+impl LaneConfig {
+    // Change lane command for the lane at `lane`, None if there is no such lane.
+    pub fn change_lane(&self, lane: usize) -> Option<VehicleCommand> {
+        Some(VehicleCommand::ChangeLane {
+            horizontal_speed_mm_per_sec: self.horizontal_speed_mm_per_sec,
+            horizontal_accel_mm_per_sec2: self.horizontal_accel_mm_per_sec2,
+            offset_from_road_centre_mm: *self.offsets_mm.get(lane)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaceRules {
+    pub finish_road_piece_id: u8,
+    // Road pieces that start a new time trial sector.
+    pub sector_boundaries: Vec<u8>,
+    pub elimination: Option<EliminationRules>,
+    pub incident: IncidentRules,
+    pub safety_car: SafetyCarRules,
+}
+
+impl Default for RaceRules {
+    fn default() -> Self {
+        RaceRules {
+            finish_road_piece_id: FINISH_LINE_ROAD_PIECE_ID,
+            sector_boundaries: Vec::new(),
+            elimination: None,
+            incident: IncidentRules::default(),
+            safety_car: SafetyCarRules::default(),
+        }
+    }
+}
+
+impl RaceRules {
+    pub fn sectors(&self) -> SectorLayout {
+        SectorLayout {
+            finish_road_piece_id: self.finish_road_piece_id,
+            boundaries: self.sector_boundaries.clone(),
+        }
+    }
+
+    // None unless an elimination race is configured, or if its interval is invalid.
+    pub fn elimination(&self) -> Option<EliminationConfig> {
+        let rules = self.elimination.as_ref()?;
+        let interval = match (rules.every_laps, rules.every_ms) {
+            (Some(laps), None) => EliminationInterval::Laps(laps),
+            (None, Some(ms)) => EliminationInterval::Time(Duration::from_millis(ms)),
+            _ => return None,
+        };
+        Some(EliminationConfig {
+            interval,
+            parking_road_piece_id: rules.parking_road_piece_id,
+            parking_location_id: rules.parking_location_id,
+        })
+    }
+
+    pub fn incident(&self) -> IncidentConfig {
+        IncidentConfig {
+            telemetry_timeout: Duration::from_millis(self.incident.telemetry_timeout_ms),
+            window: Duration::from_millis(self.incident.window_ms),
+            min_vehicles: self.incident.min_vehicles,
+        }
+    }
+
+    pub fn safety_car(&self) -> SafetyCar {
+        SafetyCar::new(
+            self.safety_car.speed_mm_per_sec,
+            self.safety_car.accel_mm_per_sec2,
+        )
+    }
+}
+
+// Set exactly one of `every_laps` and `every_ms`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EliminationRules {
+    pub every_laps: Option<u16>,
+    pub every_ms: Option<u64>,
+    pub parking_road_piece_id: u8,
+    pub parking_location_id: Option<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IncidentRules {
+    pub telemetry_timeout_ms: u64,
+    pub window_ms: u64,
+    pub min_vehicles: usize,
+}
+
+impl Default for IncidentRules {
+    fn default() -> Self {
+        let config = IncidentConfig::default();
+        IncidentRules {
+            telemetry_timeout_ms: config.telemetry_timeout.as_millis() as u64,
+            window_ms: config.window.as_millis() as u64,
+            min_vehicles: config.min_vehicles,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyCarRules {
+    pub speed_mm_per_sec: i16,
+    pub accel_mm_per_sec2: i16,
+}
+
+impl Default for SafetyCarRules {
+    fn default() -> Self {
+        SafetyCarRules {
+            speed_mm_per_sec: SAFETY_CAR_SPEED_MM_PER_SEC,
+            accel_mm_per_sec2: SAFETY_CAR_ACCEL_MM_PER_SEC2,
+        }
+    }
+}
+
+// Same fields as `JsonMessage::LightsPattern`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LightPreset {
+    pub channel: LightChannel,
+    pub effect: LightEffect,
+    #[serde(default)]
+    pub start: u8,
+    #[serde(default)]
+    pub end: u8,
+    #[serde(default)]
+    pub cycles_per_min: u16,
+}
+
+impl LightPreset {
+    pub fn command(&self) -> VehicleCommand {
+        VehicleCommand::lights_pattern(LightPattern {
+            channel: self.channel.clone(),
+            effect: self.effect.clone(),
+            start: self.start,
+            end: self.end,
+            cycles_per_min: self.cycles_per_min,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+fn invalid(message: String) -> Result<(), ConfigError> {
+    Err(ConfigError::Invalid(message))
+}
+
+impl FleetConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FleetConfig, ConfigError> {
+        FleetConfig::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(data: &str) -> Result<FleetConfig, ConfigError> {
+        let config: FleetConfig = toml::from_str(data)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Failed to serialize config as TOML")
+    }
+
+    pub fn vehicle(&self, id: &str) -> Option<&VehicleConfig> {
+        self.vehicles.iter().find(|v| v.id == id)
+    }
+
+    // Commands that put a configured vehicle in its lane with its lights on, in that order.
+    pub fn setup_commands(&self, id: &str) -> CommandBatch {
+        let Some(vehicle) = self.vehicle(id) else {
+            return CommandBatch::new();
+        };
+        let lane = vehicle.lane.and_then(|lane| self.lanes.change_lane(lane));
+        let lights = vehicle
+            .lights
+            .as_ref()
+            .and_then(|name| self.lights.get(name))
+            .map(LightPreset::command);
+        lane.into_iter().chain(lights).collect()
+    }
+
+    // Checks everything the types can't, `from_toml` and `load` call this already.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut ids = HashSet::new();
+        for vehicle in &self.vehicles {
+            if !ids.insert(vehicle.id.as_str()) {
+                return invalid(format!("Vehicle {} is listed more than once", vehicle.id));
+            }
+            if let Some(lane) = vehicle.lane {
+                if lane >= self.lanes.offsets_mm.len() {
+                    return invalid(format!(
+                        "Vehicle {} starts in lane {}, only {} lanes are configured",
+                        vehicle.id,
+                        lane,
+                        self.lanes.offsets_mm.len()
+                    ));
+                }
+            }
+            if let Some(lights) = &vehicle.lights {
+                if !self.lights.contains_key(lights) {
+                    return invalid(format!(
+                        "Vehicle {} uses unknown light preset {}",
+                        vehicle.id, lights
+                    ));
+                }
+            }
+        }
+
+        if self.lanes.offsets_mm.windows(2).any(|w| w[0] >= w[1]) {
+            return invalid("Lane offsets must be in increasing order".to_string());
+        }
+
+        if let Some(elimination) = &self.race.elimination {
+            match (elimination.every_laps, elimination.every_ms) {
+                (Some(0), None) | (None, Some(0)) => {
+                    return invalid("Elimination interval must not be zero".to_string())
+                }
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => {
+                    return invalid(
+                        "Elimination needs exactly one of every_laps and every_ms".to_string(),
+                    )
+                }
+            }
+        }
+        if self.race.incident.min_vehicles == 0 {
+            return invalid("Incidents need at least one vehicle".to_string());
+        }
+
+        for (name, preset) in &self.lights {
+            if preset.start > ANKI_VEHICLE_MAX_LIGHT_INTENSITY
+                || preset.end > ANKI_VEHICLE_MAX_LIGHT_INTENSITY
+            {
+                return invalid(format!(
+                    "Light preset {} exceeds the maximum intensity of {}",
+                    name, ANKI_VEHICLE_MAX_LIGHT_INTENSITY
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [[vehicles]]
+        id = "skull"
+        name = "Skull"
+        lane = 0
+        lights = "team_red"
+
+        [[vehicles]]
+        id = "nuke"
+
+        [lanes]
+        offsets_mm = [-68.0, -23.0, 23.0, 68.0]
+
+        [race]
+        sector_boundaries = [17, 20]
+
+        [race.elimination]
+        every_laps = 2
+        parking_road_piece_id = 17
+
+        [race.safety_car]
+        speed_mm_per_sec = 250
+
+        [lights.team_red]
+        channel = "red"
+        effect = "throb"
+        end = 14
+        cycles_per_min = 60
+    "#;
+
+    #[test]
+    fn config_load_test() {
+        let config = FleetConfig::from_toml(CONFIG).unwrap();
+        assert_eq!(2, config.vehicles.len());
+        assert_eq!(None, config.vehicle("nuke").unwrap().name);
+        assert_eq!(3, config.race.sectors().sector_count());
+        assert_eq!(
+            Some(EliminationConfig {
+                interval: EliminationInterval::Laps(2),
+                parking_road_piece_id: 17,
+                parking_location_id: None,
+            }),
+            config.race.elimination()
+        );
+        assert_eq!(IncidentConfig::default(), config.race.incident());
+        let safety_car = config.race.safety_car();
+        assert_eq!(250, safety_car.speed_mm_per_sec);
+        assert_eq!(SAFETY_CAR_ACCEL_MM_PER_SEC2, safety_car.accel_mm_per_sec2);
+
+        assert_eq!(
+            [
+                VehicleCommand::ChangeLane {
+                    horizontal_speed_mm_per_sec: 300,
+                    horizontal_accel_mm_per_sec2: 2500,
+                    offset_from_road_centre_mm: -68.0,
+                },
+                config.lights["team_red"].command(),
+            ],
+            config.setup_commands("skull").as_slice()
+        );
+        assert!(config.setup_commands("nuke").is_empty());
+
+        assert_eq!(config, FleetConfig::from_toml(&config.to_toml()).unwrap());
+        assert_eq!(FleetConfig::default(), FleetConfig::from_toml("").unwrap());
+    }
+
+    #[test]
+    fn config_validation_test() {
+        let invalid =
+            |data: &str| matches!(FleetConfig::from_toml(data), Err(ConfigError::Invalid(_)));
+        assert!(invalid(
+            "[[vehicles]]\nid = \"skull\"\n[[vehicles]]\nid = \"skull\""
+        ));
+        assert!(invalid("[[vehicles]]\nid = \"skull\"\nlane = 1"));
+        assert!(invalid("[[vehicles]]\nid = \"skull\"\nlights = \"party\""));
+        assert!(invalid("[lanes]\noffsets_mm = [23.0, -23.0]"));
+        assert!(invalid(
+            "[race.elimination]\nevery_laps = 2\nevery_ms = 30000\nparking_road_piece_id = 17"
+        ));
+        assert!(invalid(
+            "[lights.bright]\nchannel = \"blue\"\neffect = \"steady\"\nstart = 15"
+        ));
+        assert!(matches!(
+            FleetConfig::from_toml("[race]\nlaps = 3"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,525 @@
+use scroll::Pread;
+use std::fmt;
+
+use crate::error::AnkiError;
+use crate::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse, AnkiVehicleMsgChargerInfo,
+    AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgOffsetFromRoadCentreUpdate,
+    AnkiVehicleMsgSpeedUpdate, AnkiVehicleMsgType, AnkiVehicleMsgVersionResponse, IntersectionCode,
+    WIRE_ENDIAN,
+};
+use crate::AnkiVehicleData;
+
+// What a frame should decode to, field for field. Only the fields the vehicle fills in are
+// listed, the size and id bytes are covered by the frame itself.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConformanceExpectation {
+    Version {
+        version: u16,
+    },
+    BatteryLevel {
+        battery_level: u16,
+    },
+    PositionUpdate {
+        location_id: u8,
+        road_piece_id: u8,
+        offset_from_road_centre_mm: f32,
+        speed_mm_per_sec: u16,
+        parsing_flags: u8,
+        last_recv_lane_change_cmd_id: u8,
+        last_exec_lane_change_cmd_id: u8,
+        last_desired_lane_change_speed_mm_per_sec: u16,
+        last_desired_speed_mm_per_sec: u16,
+    },
+    TransitionUpdate {
+        road_piece_idx: i8,
+        road_piece_idx_prev: i8,
+        offset_from_road_centre_mm: f32,
+        last_recv_lane_change_id: u8,
+        last_exec_lane_change_id: u8,
+        last_desired_lane_change_speed_mm_per_sec: u16,
+        ave_follow_line_drift_pixels: i8,
+        had_lane_change_activity: u8,
+        uphill_counter: u8,
+        downhill_counter: u8,
+        left_wheel_dist_cm: u8,
+        right_wheel_dist_cm: u8,
+    },
+    IntersectionUpdate {
+        road_piece_idx: i8,
+        offset_from_road_centre_mm: f32,
+        intersection_code: IntersectionCode,
+        is_exiting: u8,
+        mm_since_last_transition_bar: u16,
+        mm_since_last_intersection_code: u16,
+    },
+    OffsetFromRoadCentreUpdate {
+        offset_from_road_centre_mm: f32,
+        lane_change_id: u8,
+    },
+    SpeedUpdate {
+        desired_speed_mm_per_sec: u16,
+        accel_mm_per_sec2: u16,
+        speed_mm_per_sec: u16,
+    },
+    ChargerInfo {
+        on_track: u8,
+        on_charger: u8,
+        battery_low: u8,
+        battery_full: u8,
+    },
+    // Messages without a payload, e.g. ping responses and delocalization.
+    Empty(AnkiVehicleMsgType),
+    // The frame is malformed and every decoder must turn it down.
+    Rejected,
+}
+
+impl ConformanceExpectation {
+    // Runs a frame through the decoder for its message id.
+    pub fn decode(data: &[u8]) -> Result<ConformanceExpectation, AnkiError> {
+        let msg = data.pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)?;
+        Ok(match msg.msg_id {
+            AnkiVehicleMsgType::V2CVersionResponse => {
+                let msg: AnkiVehicleMsgVersionResponse = data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::Version {
+                    version: msg.version,
+                }
+            }
+            AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+                let msg: AnkiVehicleMsgBatteryLevelResponse = data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::BatteryLevel {
+                    battery_level: msg.battery_level,
+                }
+            }
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate => {
+                let msg: AnkiVehicleMsgLocalisationPositionUpdate =
+                    data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::PositionUpdate {
+                    location_id: msg.location_id,
+                    road_piece_id: msg.road_piece_id,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    speed_mm_per_sec: msg.speed_mm_per_sec,
+                    parsing_flags: msg.parsing_flags.bits(),
+                    last_recv_lane_change_cmd_id: msg.last_recv_lane_change_cmd_id,
+                    last_exec_lane_change_cmd_id: msg.last_exec_lane_change_cmd_id,
+                    last_desired_lane_change_speed_mm_per_sec: msg
+                        .last_desired_lane_change_speed_mm_per_sec,
+                    last_desired_speed_mm_per_sec: msg.last_desired_speed_mm_per_sec,
+                }
+            }
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => {
+                let msg: AnkiVehicleMsgLocalisationTransitionUpdate =
+                    data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::TransitionUpdate {
+                    road_piece_idx: msg.road_piece_idx,
+                    road_piece_idx_prev: msg.road_piece_idx_prev,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    last_recv_lane_change_id: msg.last_recv_lane_change_id,
+                    last_exec_lane_change_id: msg.last_exec_lane_change_id,
+                    last_desired_lane_change_speed_mm_per_sec: msg
+                        .last_desired_lane_change_speed_mm_per_sec,
+                    ave_follow_line_drift_pixels: msg.ave_follow_line_drift_pixels,
+                    had_lane_change_activity: msg.had_lane_change_activity,
+                    uphill_counter: msg.uphill_counter,
+                    downhill_counter: msg.downhill_counter,
+                    left_wheel_dist_cm: msg.left_wheel_dist_cm,
+                    right_wheel_dist_cm: msg.right_wheel_dist_cm,
+                }
+            }
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => {
+                let msg: AnkiVehicleMsgLocalisationIntersectionUpdate =
+                    data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::IntersectionUpdate {
+                    road_piece_idx: msg.road_piece_idx,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    intersection_code: msg.intersection_code,
+                    is_exiting: msg.is_exiting,
+                    mm_since_last_transition_bar: msg.mm_since_last_transition_bar,
+                    mm_since_last_intersection_code: msg.mm_since_last_intersection_code,
+                }
+            }
+            AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => {
+                let msg: AnkiVehicleMsgOffsetFromRoadCentreUpdate =
+                    data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::OffsetFromRoadCentreUpdate {
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    lane_change_id: msg.lane_change_id,
+                }
+            }
+            AnkiVehicleMsgType::V2CSpeedUpdate => {
+                let msg: AnkiVehicleMsgSpeedUpdate = data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::SpeedUpdate {
+                    desired_speed_mm_per_sec: msg.desired_speed_mm_per_sec,
+                    accel_mm_per_sec2: msg.accel_mm_per_sec2,
+                    speed_mm_per_sec: msg.speed_mm_per_sec,
+                }
+            }
+            AnkiVehicleMsgType::V2CChargerInfo => {
+                let msg: AnkiVehicleMsgChargerInfo = data.pread_with(0, WIRE_ENDIAN)?;
+                ConformanceExpectation::ChargerInfo {
+                    on_track: msg.on_track,
+                    on_charger: msg.on_charger,
+                    battery_low: msg.battery_low,
+                    battery_full: msg.battery_full,
+                }
+            }
+            msg_id => ConformanceExpectation::Empty(msg_id),
+        })
+    }
+}
+
+// A frame as the vehicle put it on the air, and what it has to decode to.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConformanceCase<'a> {
+    pub name: &'a str,
+    pub frame: &'a [u8],
+    pub expected: ConformanceExpectation,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConformanceMismatch {
+    // Decoded fine, but to something else.
+    Decoded(ConformanceExpectation),
+    // The decoder turned down a frame it should have read.
+    Failed(String),
+    // A malformed frame made it through the dispatcher.
+    Accepted(AnkiVehicleMsgType),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConformanceFailure {
+    pub name: String,
+    pub expected: ConformanceExpectation,
+    pub mismatch: ConformanceMismatch,
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mismatch {
+            ConformanceMismatch::Decoded(actual) => write!(
+                f,
+                "{}: expected {:?}, decoded {:?}",
+                self.name, self.expected, actual
+            ),
+            ConformanceMismatch::Failed(e) => {
+                write!(
+                    f,
+                    "{}: expected {:?}, failed with {}",
+                    self.name, self.expected, e
+                )
+            }
+            ConformanceMismatch::Accepted(msg_id) => {
+                write!(f, "{}: malformed frame accepted as {:?}", self.name, msg_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConformanceFailure {}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ConformanceReport {
+    pub passed: usize,
+    pub failures: Vec<ConformanceFailure>,
+}
+
+impl ConformanceReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Checks a frame against both its own decoder and the notification dispatcher.
+pub fn check(case: &ConformanceCase) -> Result<(), ConformanceFailure> {
+    let failure = |mismatch| ConformanceFailure {
+        name: case.name.to_string(),
+        expected: case.expected.clone(),
+        mismatch,
+    };
+    let dispatched = AnkiVehicleData::default().process_notification(case.frame);
+
+    if case.expected == ConformanceExpectation::Rejected {
+        return match dispatched {
+            Ok(msg_id) => Err(failure(ConformanceMismatch::Accepted(msg_id))),
+            Err(_) => Ok(()),
+        };
+    }
+    if let Err(e) = dispatched {
+        return Err(failure(ConformanceMismatch::Failed(e.to_string())));
+    }
+    match ConformanceExpectation::decode(case.frame) {
+        Ok(decoded) if decoded == case.expected => Ok(()),
+        Ok(decoded) => Err(failure(ConformanceMismatch::Decoded(decoded))),
+        Err(e) => Err(failure(ConformanceMismatch::Failed(e.to_string()))),
+    }
+}
+
+// Runs a set of cases, e.g. the bundled corpus or frames from a capture of your own.
+pub fn run(cases: &[ConformanceCase]) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    for case in cases {
+        match check(case) {
+            Ok(()) => report.passed += 1,
+            Err(failure) => report.failures.push(failure),
+        }
+    }
+    report
+}
+
+pub fn run_corpus() -> ConformanceReport {
+    run(corpus())
+}
+
+// Reference notifications in the firmware's byte layout, at least one per message the
+// dispatcher reads plus malformed frames it has to drop. Frames captured from a vehicle can be
+// checked the same way with `run`.
+pub fn corpus() -> &'static [ConformanceCase<'static>] {
+    &CORPUS
+}
+
+static CORPUS: [ConformanceCase<'static>; 19] = [
+    ConformanceCase {
+        name: "version_2676",
+        frame: &[0x03, 0x19, 0x76, 0x26],
+        expected: ConformanceExpectation::Version { version: 0x2676 },
+    },
+    ConformanceCase {
+        name: "version_2e6a",
+        frame: &[0x03, 0x19, 0x6a, 0x2e],
+        expected: ConformanceExpectation::Version { version: 0x2e6a },
+    },
+    ConformanceCase {
+        name: "battery_level_charged",
+        frame: &[0x03, 0x1b, 0xc4, 0x0e],
+        expected: ConformanceExpectation::BatteryLevel {
+            battery_level: 3780,
+        },
+    },
+    ConformanceCase {
+        name: "position_update_straight",
+        frame: &[
+            0x10, 0x27, 0x16, 0x24, 0x00, 0x00, 0x88, 0xc2, 0xf4, 0x01, 0x47, 0x00, 0x00, 0x00,
+            0x00, 0xf4, 0x01,
+        ],
+        expected: ConformanceExpectation::PositionUpdate {
+            location_id: 22,
+            road_piece_id: 36,
+            offset_from_road_centre_mm: -68.0,
+            speed_mm_per_sec: 500,
+            parsing_flags: 0x47,
+            last_recv_lane_change_cmd_id: 0,
+            last_exec_lane_change_cmd_id: 0,
+            last_desired_lane_change_speed_mm_per_sec: 0,
+            last_desired_speed_mm_per_sec: 500,
+        },
+    },
+    ConformanceCase {
+        name: "position_update_curve_after_lane_change",
+        frame: &[
+            0x10, 0x27, 0x0b, 0x11, 0x00, 0x00, 0xb8, 0x41, 0x84, 0x03, 0x07, 0x02, 0x02, 0x2c,
+            0x01, 0x84, 0x03,
+        ],
+        expected: ConformanceExpectation::PositionUpdate {
+            location_id: 11,
+            road_piece_id: 17,
+            offset_from_road_centre_mm: 23.0,
+            speed_mm_per_sec: 900,
+            parsing_flags: 0x07,
+            last_recv_lane_change_cmd_id: 2,
+            last_exec_lane_change_cmd_id: 2,
+            last_desired_lane_change_speed_mm_per_sec: 300,
+            last_desired_speed_mm_per_sec: 900,
+        },
+    },
+    ConformanceCase {
+        name: "position_update_reverse_driving",
+        frame: &[
+            0x10, 0x27, 0x05, 0x21, 0x00, 0x00, 0xb8, 0xc1, 0x2c, 0x01, 0x67, 0x00, 0x00, 0x00,
+            0x00, 0x2c, 0x01,
+        ],
+        expected: ConformanceExpectation::PositionUpdate {
+            location_id: 5,
+            road_piece_id: 33,
+            offset_from_road_centre_mm: -23.0,
+            speed_mm_per_sec: 300,
+            parsing_flags: 0x67,
+            last_recv_lane_change_cmd_id: 0,
+            last_exec_lane_change_cmd_id: 0,
+            last_desired_lane_change_speed_mm_per_sec: 0,
+            last_desired_speed_mm_per_sec: 300,
+        },
+    },
+    // Firmware doesn't track piece indexes in SDK mode, they come through as zero.
+    ConformanceCase {
+        name: "transition_update",
+        frame: &[
+            0x11, 0x29, 0x00, 0x00, 0x00, 0x00, 0x88, 0xc2, 0x00, 0x00, 0x00, 0x00, 0xfd, 0x00,
+            0x00, 0x00, 0x16, 0x17,
+        ],
+        expected: ConformanceExpectation::TransitionUpdate {
+            road_piece_idx: 0,
+            road_piece_idx_prev: 0,
+            offset_from_road_centre_mm: -68.0,
+            last_recv_lane_change_id: 0,
+            last_exec_lane_change_id: 0,
+            last_desired_lane_change_speed_mm_per_sec: 0,
+            ave_follow_line_drift_pixels: -3,
+            had_lane_change_activity: 0,
+            uphill_counter: 0,
+            downhill_counter: 0,
+            left_wheel_dist_cm: 22,
+            right_wheel_dist_cm: 23,
+        },
+    },
+    ConformanceCase {
+        name: "transition_update_uphill",
+        frame: &[
+            0x11, 0x29, 0x00, 0x00, 0x00, 0x00, 0xb8, 0x41, 0x02, 0x02, 0x2c, 0x01, 0x01, 0x01,
+            0x04, 0x00, 0x2b, 0x2d,
+        ],
+        expected: ConformanceExpectation::TransitionUpdate {
+            road_piece_idx: 0,
+            road_piece_idx_prev: 0,
+            offset_from_road_centre_mm: 23.0,
+            last_recv_lane_change_id: 2,
+            last_exec_lane_change_id: 2,
+            last_desired_lane_change_speed_mm_per_sec: 300,
+            ave_follow_line_drift_pixels: 1,
+            had_lane_change_activity: 1,
+            uphill_counter: 4,
+            downhill_counter: 0,
+            left_wheel_dist_cm: 43,
+            right_wheel_dist_cm: 45,
+        },
+    },
+    ConformanceCase {
+        name: "intersection_update_entry",
+        frame: &[
+            0x0c, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x50, 0x00, 0x00, 0x00,
+        ],
+        expected: ConformanceExpectation::IntersectionUpdate {
+            road_piece_idx: 0,
+            offset_from_road_centre_mm: 0.0,
+            intersection_code: IntersectionCode::EntryFirst,
+            is_exiting: 0,
+            mm_since_last_transition_bar: 80,
+            mm_since_last_intersection_code: 0,
+        },
+    },
+    ConformanceCase {
+        name: "offset_from_road_centre_update",
+        frame: &[0x06, 0x2d, 0x00, 0x00, 0x32, 0x42, 0x01],
+        expected: ConformanceExpectation::OffsetFromRoadCentreUpdate {
+            offset_from_road_centre_mm: 44.5,
+            lane_change_id: 1,
+        },
+    },
+    ConformanceCase {
+        name: "ping_response",
+        frame: &[0x01, 0x17],
+        expected: ConformanceExpectation::Empty(AnkiVehicleMsgType::V2CPingResponse),
+    },
+    ConformanceCase {
+        name: "vehicle_delocalized",
+        frame: &[0x01, 0x2b],
+        expected: ConformanceExpectation::Empty(AnkiVehicleMsgType::V2CVehicleDelocalized),
+    },
+    // Overdrive firmware only, see the notes on the structs in `protocol`.
+    ConformanceCase {
+        name: "speed_update_settling",
+        frame: &[0x07, 0x36, 0xf4, 0x01, 0xe8, 0x03, 0xf0, 0x01],
+        expected: ConformanceExpectation::SpeedUpdate {
+            desired_speed_mm_per_sec: 500,
+            accel_mm_per_sec2: 1000,
+            speed_mm_per_sec: 496,
+        },
+    },
+    ConformanceCase {
+        name: "charger_info_on_charger_full",
+        frame: &[0x05, 0x3f, 0x00, 0x01, 0x00, 0x01],
+        expected: ConformanceExpectation::ChargerInfo {
+            on_track: 0,
+            on_charger: 1,
+            battery_low: 0,
+            battery_full: 1,
+        },
+    },
+    ConformanceCase {
+        name: "collision_detected",
+        frame: &[0x01, 0x4d],
+        expected: ConformanceExpectation::Empty(AnkiVehicleMsgType::V2CCollisionDetected),
+    },
+    ConformanceCase {
+        name: "cycle_overtime",
+        frame: &[0x01, 0x86],
+        expected: ConformanceExpectation::Empty(AnkiVehicleMsgType::V2CCycleOvertime),
+    },
+    ConformanceCase {
+        name: "speed_update_truncated",
+        frame: &[0x07, 0x36, 0xf4, 0x01, 0xe8],
+        expected: ConformanceExpectation::Rejected,
+    },
+    // Cut short by a dropped connection.
+    ConformanceCase {
+        name: "position_update_truncated",
+        frame: &[0x10, 0x27, 0x16, 0x24, 0x00, 0x00, 0x88, 0xc2, 0xf4, 0x01],
+        expected: ConformanceExpectation::Rejected,
+    },
+    ConformanceCase {
+        name: "empty_notification",
+        frame: &[],
+        expected: ConformanceExpectation::Rejected,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conformance_corpus_test() {
+        let report = run_corpus();
+        for failure in &report.failures {
+            println!("{}", failure);
+        }
+        assert!(report.is_ok());
+        assert_eq!(corpus().len(), report.passed);
+    }
+
+    #[test]
+    fn conformance_failure_test() {
+        let cases = [
+            ConformanceCase {
+                name: "wrong_version",
+                frame: &[0x03, 0x19, 0x76, 0x26],
+                expected: ConformanceExpectation::Version { version: 0x2e6a },
+            },
+            ConformanceCase {
+                name: "not_rejected",
+                frame: &[0x01, 0x17],
+                expected: ConformanceExpectation::Rejected,
+            },
+            ConformanceCase {
+                name: "not_accepted",
+                frame: &[0x03, 0x1b, 0xc4],
+                expected: ConformanceExpectation::BatteryLevel {
+                    battery_level: 3780,
+                },
+            },
+        ];
+        let report = run(&cases);
+        assert_eq!(0, report.passed);
+        let mismatches: Vec<&ConformanceMismatch> =
+            report.failures.iter().map(|f| &f.mismatch).collect();
+        assert_eq!(
+            &ConformanceMismatch::Decoded(ConformanceExpectation::Version { version: 0x2676 }),
+            mismatches[0]
+        );
+        assert_eq!(
+            &ConformanceMismatch::Accepted(AnkiVehicleMsgType::V2CPingResponse),
+            mismatches[1]
+        );
+        assert!(matches!(mismatches[2], ConformanceMismatch::Failed(_)));
+    }
+}
@@ -0,0 +1,188 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::AnkiVehicleData;
+
+pub const CSV_DEFAULT_MAX_ROWS: usize = 100_000;
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct CsvTelemetryRow {
+    pub timestamp_ms: u64,
+    pub vehicle: String,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub battery_level: u16,
+}
+
+impl CsvTelemetryRow {
+    pub fn from_vehicle(timestamp_ms: u64, vehicle: &AnkiVehicleData) -> CsvTelemetryRow {
+        CsvTelemetryRow {
+            timestamp_ms,
+            vehicle: vehicle.name.clone(),
+            location_id: vehicle.location_id,
+            road_piece_idx: vehicle.road_piece_idx,
+            offset_from_road_centre_mm: vehicle.offset_from_road_centre_mm,
+            speed_mm_per_sec: vehicle.speed_mm_per_sec,
+            battery_level: vehicle.battery_level,
+        }
+    }
+}
+
+struct VehicleFile {
+    writer: csv::Writer<File>,
+    rows: usize,
+    index: usize,
+}
+
+// Writes one CSV file per vehicle into `dir`, named `<vehicle>-<n>.csv`. Once a file holds
+// `max_rows` rows it is closed and the next one is started, so long sessions stay small enough
+// for a spreadsheet. Every file starts with a header row.
+pub struct CsvTelemetryWriter {
+    dir: PathBuf,
+    max_rows: usize,
+    files: HashMap<String, VehicleFile>,
+}
+
+impl CsvTelemetryWriter {
+    pub fn create<P: AsRef<Path>>(dir: P) -> Result<CsvTelemetryWriter, csv::Error> {
+        CsvTelemetryWriter::with_max_rows(dir, CSV_DEFAULT_MAX_ROWS)
+    }
+
+    pub fn with_max_rows<P: AsRef<Path>>(
+        dir: P,
+        max_rows: usize,
+    ) -> Result<CsvTelemetryWriter, csv::Error> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(CsvTelemetryWriter {
+            dir: dir.as_ref().to_path_buf(),
+            max_rows: max_rows.max(1),
+            files: HashMap::new(),
+        })
+    }
+
+    // Path of the file currently being written for `vehicle`.
+    pub fn path(&self, vehicle: &str) -> Option<PathBuf> {
+        self.files
+            .get(vehicle)
+            .map(|file| self.file_path(vehicle, file.index))
+    }
+
+    pub fn write_vehicle(
+        &mut self,
+        timestamp_ms: u64,
+        vehicle: &AnkiVehicleData,
+    ) -> Result<(), csv::Error> {
+        self.write_row(&CsvTelemetryRow::from_vehicle(timestamp_ms, vehicle))
+    }
+
+    pub fn write_row(&mut self, row: &CsvTelemetryRow) -> Result<(), csv::Error> {
+        let index = match self.files.get(&row.vehicle) {
+            Some(file) if file.rows < self.max_rows => None,
+            Some(file) => Some(file.index + 1),
+            None => Some(0),
+        };
+        if let Some(index) = index {
+            if let Some(mut full) = self.files.remove(&row.vehicle) {
+                full.writer.flush()?;
+            }
+            let writer = csv::Writer::from_path(self.file_path(&row.vehicle, index))?;
+            self.files.insert(
+                row.vehicle.clone(),
+                VehicleFile {
+                    writer,
+                    rows: 0,
+                    index,
+                },
+            );
+        }
+
+        let file = self
+            .files
+            .get_mut(&row.vehicle)
+            .expect("CSV file was just opened");
+        file.writer.serialize(row)?;
+        file.rows += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), csv::Error> {
+        for file in self.files.values_mut() {
+            file.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    // Vehicle names are user set, keep them from escaping the directory.
+    fn file_path(&self, vehicle: &str, index: usize) -> PathBuf {
+        let name: String = vehicle
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}-{}.csv", name, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("anki-csv-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn csv_write_test() {
+        let dir = test_dir("write");
+        let mut writer = CsvTelemetryWriter::create(&dir).unwrap();
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_name("Skull".to_string());
+        vehicle.speed_mm_per_sec = 600;
+        vehicle.battery_level = 3900;
+        writer.write_vehicle(1234, &vehicle).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(Some(dir.join("Skull-0.csv")), writer.path("Skull"));
+        assert_eq!(
+            "timestamp_ms,vehicle,location_id,road_piece_idx,offset_from_road_centre_mm,speed_mm_per_sec,battery_level\n\
+             1234,Skull,0,0,0.0,600,3900\n",
+            fs::read_to_string(dir.join("Skull-0.csv")).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_rotation_test() {
+        let dir = test_dir("rotation");
+        let mut writer = CsvTelemetryWriter::with_max_rows(&dir, 2).unwrap();
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_name("Ground Shock/2".to_string());
+        for timestamp_ms in 0..5 {
+            writer.write_vehicle(timestamp_ms, &vehicle).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(
+            Some(dir.join("Ground_Shock_2-2.csv")),
+            writer.path("Ground Shock/2")
+        );
+        for (index, rows) in [(0, 2), (1, 2), (2, 1)] {
+            let data = fs::read_to_string(dir.join(format!("Ground_Shock_2-{}.csv", index)));
+            assert_eq!(rows + 1, data.unwrap().lines().count());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::zvariant::Type;
+use zbus::{interface, DBusError};
+
+use crate::host::{FleetHost, HostError, HostVehicle};
+use crate::json::JsonMessage;
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Desktop tools and scripts can drive the fleet over D-Bus without linking the crate, e.g.
+//
+//   busctl --user call org.anki.Drive /org/anki/Drive org.anki.Drive.Fleet1 SetSpeed snn skull 500 1000
+//
+// Every method takes the host's vehicle id, errors come back as org.anki.Drive.Error.*.
+pub const DBUS_SERVICE_NAME: &str = "org.anki.Drive";
+pub const DBUS_OBJECT_PATH: &str = "/org/anki/Drive";
+pub const DBUS_INTERFACE_NAME: &str = "org.anki.Drive.Fleet1";
+
+#[derive(Debug, PartialEq, DBusError)]
+#[zbus(prefix = "org.anki.Drive.Error")]
+pub enum DbusError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    UnknownVehicle(String),
+    NotConnected(String),
+    Transport(String),
+    InvalidCommand(String),
+}
+
+impl From<HostError> for DbusError {
+    fn from(e: HostError) -> Self {
+        let message = e.to_string();
+        match e {
+            HostError::UnknownVehicle(_) => DbusError::UnknownVehicle(message),
+            HostError::NotConnected(_) => DbusError::NotConnected(message),
+            HostError::Transport(_) => DbusError::Transport(message),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Type)]
+pub struct DbusVehicle {
+    pub id: String,
+    pub name: String,
+    pub connected: bool,
+}
+
+impl From<HostVehicle> for DbusVehicle {
+    fn from(vehicle: HostVehicle) -> Self {
+        DbusVehicle {
+            id: vehicle.id.into(),
+            name: vehicle.name,
+            connected: vehicle.connected,
+        }
+    }
+}
+
+// D-Bus has no f32, the offset is sent as a double.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Type)]
+pub struct DbusVehicleState {
+    pub version: u16,
+    pub battery_level: u16,
+    pub location_id: u8,
+    pub road_piece_idx: i16,
+    pub offset_from_road_centre_mm: f64,
+    pub speed_mm_per_sec: u16,
+}
+
+impl DbusVehicleState {
+    pub fn from_vehicle(data: &AnkiVehicleData) -> DbusVehicleState {
+        DbusVehicleState {
+            version: data.version,
+            battery_level: data.battery_level,
+            location_id: data.location_id,
+            road_piece_idx: data.road_piece_idx.into(),
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm.into(),
+            speed_mm_per_sec: data.speed_mm_per_sec,
+        }
+    }
+}
+
+// The object served at DBUS_OBJECT_PATH. Like the REST API, vehicle state is rebuilt from the
+// notifications the host forwards.
+pub struct DbusFleet {
+    host: Arc<dyn FleetHost>,
+    states: Arc<Mutex<HashMap<VehicleId, AnkiVehicleData>>>,
+}
+
+impl DbusFleet {
+    pub fn new<H: FleetHost>(host: Arc<H>) -> DbusFleet {
+        let states = Arc::new(Mutex::new(HashMap::new()));
+
+        let notifications = host.subscribe();
+        let tracked_states = Arc::clone(&states);
+        thread::spawn(move || {
+            for notification in notifications {
+                let mut states = tracked_states.lock().unwrap();
+                let state = states
+                    .entry(notification.vehicle)
+                    .or_insert_with(AnkiVehicleData::default);
+                let _ = state.process_notification(&notification.data);
+            }
+        });
+
+        DbusFleet { host, states }
+    }
+
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), DbusError> {
+        Ok(self.host.send(vehicle, data)?)
+    }
+}
+
+#[interface(name = "org.anki.Drive.Fleet1")]
+impl DbusFleet {
+    fn vehicles(&self) -> Vec<DbusVehicle> {
+        self.host.vehicles().into_iter().map(Into::into).collect()
+    }
+
+    fn discover(&self) -> Result<Vec<DbusVehicle>, DbusError> {
+        Ok(self.host.discover()?.into_iter().map(Into::into).collect())
+    }
+
+    fn connect(&self, vehicle: &str) -> Result<(), DbusError> {
+        Ok(self.host.connect(vehicle)?)
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), DbusError> {
+        Ok(self.host.disconnect(vehicle)?)
+    }
+
+    // Vehicles the host knows about but that haven't reported anything yet get a blank state.
+    fn state(&self, vehicle: &str) -> Result<DbusVehicleState, DbusError> {
+        if let Some(data) = self.states.lock().unwrap().get(vehicle) {
+            return Ok(DbusVehicleState::from_vehicle(data));
+        }
+        if self.host.vehicles().iter().any(|v| v.id == vehicle) {
+            return Ok(DbusVehicleState::from_vehicle(&AnkiVehicleData::default()));
+        }
+        Err(HostError::UnknownVehicle(vehicle.to_string()).into())
+    }
+
+    fn set_speed(
+        &self,
+        vehicle: &str,
+        speed_mm_per_sec: i16,
+        accel_mm_per_sec2: i16,
+    ) -> Result<(), DbusError> {
+        self.send(
+            vehicle,
+            AnkiVehicleData::set_speed(speed_mm_per_sec, accel_mm_per_sec2).into(),
+        )
+    }
+
+    fn change_lane(
+        &self,
+        vehicle: &str,
+        horizontal_speed_mm_per_sec: u16,
+        horizontal_accel_mm_per_sec2: u16,
+        offset_from_road_centre_mm: f64,
+    ) -> Result<(), DbusError> {
+        self.send(
+            vehicle,
+            AnkiVehicleData::change_lane(
+                horizontal_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2,
+                offset_from_road_centre_mm as f32,
+            )
+            .into(),
+        )
+    }
+
+    // Any command as JSON, see `JsonMessage`.
+    fn send_command(&self, vehicle: &str, json: &str) -> Result<(), DbusError> {
+        let data = JsonMessage::from_json(json)
+            .map_err(|e| DbusError::InvalidCommand(format!("Invalid JSON message: {}", e)))?
+            .to_bytes()
+            .map_err(|e| DbusError::InvalidCommand(format!("Invalid vehicle message: {}", e)))?;
+        self.send(vehicle, data)
+    }
+}
+
+// Claims DBUS_SERVICE_NAME and serves the fleet until dropped. Method calls are handled on
+// zbus's own thread, the caller only has to keep this alive.
+pub struct DbusService {
+    connection: Connection,
+}
+
+impl DbusService {
+    pub fn session<H: FleetHost>(host: Arc<H>) -> zbus::Result<DbusService> {
+        DbusService::serve(Builder::session()?, host)
+    }
+
+    pub fn system<H: FleetHost>(host: Arc<H>) -> zbus::Result<DbusService> {
+        DbusService::serve(Builder::system()?, host)
+    }
+
+    fn serve<H: FleetHost>(builder: Builder, host: Arc<H>) -> zbus::Result<DbusService> {
+        let connection = builder
+            .name(DBUS_SERVICE_NAME)?
+            .serve_at(DBUS_OBJECT_PATH, DbusFleet::new(host))?
+            .build()?;
+        Ok(DbusService { connection })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostNotification;
+    use crate::protocol::AnkiVehicleMsgType;
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::{Duration, Instant};
+    use zbus::Guid;
+
+    #[derive(Default)]
+    struct TestHost {
+        sent: Mutex<Vec<(String, Vec<u8>)>>,
+        subscribers: Mutex<Vec<Sender<HostNotification>>>,
+    }
+
+    impl FleetHost for TestHost {
+        fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+            Ok(self.vehicles())
+        }
+
+        fn vehicles(&self) -> Vec<HostVehicle> {
+            vec![HostVehicle {
+                id: "skull".into(),
+                name: "Skull".to_string(),
+                connected: true,
+            }]
+        }
+
+        fn connect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn disconnect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+            if vehicle != "skull" {
+                return Err(HostError::UnknownVehicle(vehicle.to_string()));
+            }
+            self.sent.lock().unwrap().push((vehicle.to_string(), data));
+            Ok(())
+        }
+
+        fn subscribe(&self) -> Receiver<HostNotification> {
+            let (tx, rx) = channel();
+            self.subscribers.lock().unwrap().push(tx);
+            rx
+        }
+    }
+
+    #[test]
+    fn dbus_fleet_test() {
+        let host = Arc::new(TestHost::default());
+        let fleet = DbusFleet::new(Arc::clone(&host));
+
+        assert_eq!("Skull", fleet.vehicles()[0].name);
+        fleet.set_speed("skull", 500, 1000).unwrap();
+        fleet
+            .send_command("skull", r#"{"msg_type":"cancel_lane_change"}"#)
+            .unwrap();
+        assert!(matches!(
+            fleet.send_command("skull", r#"{"msg_type":"ping_response"}"#),
+            Err(DbusError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            fleet.set_speed("nuke", 500, 1000),
+            Err(DbusError::UnknownVehicle(_))
+        ));
+        assert_eq!(2, host.sent.lock().unwrap().len());
+        assert_eq!(
+            AnkiVehicleData::set_speed(500, 1000),
+            host.sent.lock().unwrap()[0].1
+        );
+
+        for subscriber in host.subscribers.lock().unwrap().iter() {
+            subscriber
+                .send(HostNotification {
+                    vehicle: "skull".into(),
+                    data: vec![
+                        3,
+                        AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+                        0x10,
+                        0x0E,
+                    ],
+                })
+                .unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while fleet.state("skull").unwrap().battery_level == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(0x0E10, fleet.state("skull").unwrap().battery_level);
+        assert!(fleet.state("nuke").is_err());
+    }
+
+    // A peer to peer connection speaks the same protocol as the bus, without needing a daemon.
+    #[test]
+    fn dbus_peer_test() {
+        let host = Arc::new(TestHost::default());
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let guid = Guid::generate();
+        let server_host = Arc::clone(&host);
+        let server = thread::spawn(move || {
+            Builder::async_io_unix_stream(server_stream)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .serve_at(DBUS_OBJECT_PATH, DbusFleet::new(server_host))
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let _server = server.join().unwrap();
+
+        let reply = client
+            .call_method(
+                None::<&str>,
+                DBUS_OBJECT_PATH,
+                Some(DBUS_INTERFACE_NAME),
+                "SetSpeed",
+                &("skull", 500i16, 1000i16),
+            )
+            .unwrap();
+        assert_eq!((), reply.body().deserialize::<()>().unwrap());
+        assert_eq!(1, host.sent.lock().unwrap().len());
+
+        let reply = client
+            .call_method(
+                None::<&str>,
+                DBUS_OBJECT_PATH,
+                Some(DBUS_INTERFACE_NAME),
+                "Vehicles",
+                &(),
+            )
+            .unwrap();
+        let vehicles: Vec<DbusVehicle> = reply.body().deserialize().unwrap();
+        assert_eq!("skull", vehicles[0].id);
+
+        let Err(zbus::Error::MethodError(name, _, _)) = client.call_method(
+            None::<&str>,
+            DBUS_OBJECT_PATH,
+            Some(DBUS_INTERFACE_NAME),
+            "SetSpeed",
+            &("nuke", 500i16, 1000i16),
+        ) else {
+            panic!("Expected a method error");
+        };
+        assert_eq!("org.anki.Drive.Error.UnknownVehicle", name.as_str());
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::protocol::AnkiVehicleMsgType;
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_id::VehicleId;
+
+// Control loops that re-send the same speed or lights every tick fill the BLE link with
+// commands that change nothing. Put in front of a host, this skips a command identical to the
+// last one of its kind sent to the vehicle, until the window since that send has passed.
+
+pub const DEDUP_DEFAULT_WINDOW: Duration = Duration::from_millis(500);
+
+// Commands that set state outright, sending one twice does no more than sending it once.
+fn is_idempotent(data: &[u8]) -> bool {
+    let speed = u8::from(AnkiVehicleMsgType::C2VSetSpeed);
+    let lights = u8::from(AnkiVehicleMsgType::C2VSetLights);
+    let pattern = u8::from(AnkiVehicleMsgType::C2VLightsPattern);
+    matches!(data.get(1), Some(&msg_id) if msg_id == speed || msg_id == lights || msg_id == pattern)
+}
+
+// The last frame of each kind that made it to each vehicle, and when.
+type SentFrames = HashMap<(VehicleId, u8), (Vec<u8>, Instant)>;
+
+pub struct DedupHost<H> {
+    host: Arc<H>,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    sent: Mutex<SentFrames>,
+    suppressed: AtomicU64,
+}
+
+impl<H: FleetHost> DedupHost<H> {
+    pub fn new(host: Arc<H>) -> DedupHost<H> {
+        DedupHost {
+            host,
+            window: DEDUP_DEFAULT_WINDOW,
+            clock: Arc::new(SystemClock),
+            sent: Mutex::new(HashMap::new()),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    // A repeat is sent anyway once this long has passed, in case the vehicle missed the first.
+    pub fn with_window(mut self, window: Duration) -> DedupHost<H> {
+        self.window = window;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> DedupHost<H> {
+        self.clock = clock;
+        self
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.host
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    // Commands skipped so far.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    // The next command of every kind goes out to the vehicle, whatever was sent before.
+    pub fn forget(&self, vehicle: &str) {
+        self.sent
+            .lock()
+            .unwrap()
+            .retain(|(id, _), _| id.as_str() != vehicle);
+    }
+}
+
+impl<H: FleetHost> FleetHost for DedupHost<H> {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+        self.host.discover()
+    }
+
+    fn vehicles(&self) -> Vec<HostVehicle> {
+        self.host.vehicles()
+    }
+
+    // A vehicle that reconnects has lost whatever it was told before.
+    fn connect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.forget(vehicle);
+        self.host.connect(vehicle)
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.forget(vehicle);
+        self.host.disconnect(vehicle)
+    }
+
+    // Only a send that succeeded counts as the last one, a failed command is never suppressed.
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+        if !is_idempotent(&data) {
+            return self.host.send(vehicle, data);
+        }
+        let key = (VehicleId::from(vehicle), data[1]);
+        let now = self.clock.now();
+        if let Some((last, at)) = self.sent.lock().unwrap().get(&key) {
+            if *last == data && now.saturating_duration_since(*at) < self.window {
+                trace_event!(target: TARGET_TRANSPORT, trace, vehicle, msg_id = data[1], "Suppressed duplicate command");
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        self.host.send(vehicle, data.clone())?;
+        self.sent.lock().unwrap().insert(key, (data, now));
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Receiver<HostNotification> {
+        self.host.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::sim::host::SimulatedHost;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn dedup_host_test() {
+        let clock = Arc::new(VirtualClock::new());
+        let sim =
+            Arc::new(SimulatedHost::new().with_vehicle("skull", "Skull", SimulatedVehicle::new()));
+        let host = DedupHost::new(sim)
+            .with_window(Duration::from_millis(100))
+            .with_clock(clock.clone());
+        let speed: Vec<u8> = AnkiVehicleData::set_speed(500, 1000).into();
+
+        // Not connected, nothing is remembered so the retry isn't suppressed either.
+        assert!(host.send("skull", speed.clone()).is_err());
+        assert!(host.send("skull", speed.clone()).is_err());
+
+        host.connect("skull").unwrap();
+        for _ in 0..5 {
+            host.send("skull", speed.clone()).unwrap();
+            clock.advance(Duration::from_millis(10));
+        }
+        assert_eq!(4, host.suppressed());
+
+        // A different speed, or the same one after the window, goes out.
+        host.send("skull", AnkiVehicleData::set_speed(600, 1000).into())
+            .unwrap();
+        host.send("skull", speed.clone()).unwrap();
+        clock.advance(Duration::from_millis(100));
+        host.send("skull", speed.clone()).unwrap();
+        assert_eq!(4, host.suppressed());
+
+        // Pings aren't state, every one is sent.
+        let ping: Vec<u8> = crate::command::Command::PING.into();
+        host.send("skull", ping.clone()).unwrap();
+        host.send("skull", ping).unwrap();
+        assert_eq!(4, host.suppressed());
+
+        host.forget("skull");
+        host.send("skull", speed).unwrap();
+        assert_eq!(4, host.suppressed());
+    }
+}
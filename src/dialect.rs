@@ -0,0 +1,136 @@
+use crate::command::VehicleCommand;
+use crate::firmware::{min_firmware, FirmwareVersion, FIRMWARE_OVERDRIVE};
+use crate::protocol::{AnkiVehicleMsgType, TrackMaterial, SUPERCODE_NONE};
+use crate::validation::notification_size;
+use crate::AnkiVehicleData;
+
+// The two generations of the vehicle protocol. Overdrive firmware added the config parameters
+// command and the intersection and offset notifications, a vehicle still on Drive firmware
+// ignores the one and never sends the others.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolDialect {
+    Drive,
+    Overdrive,
+}
+
+// Model ids from the advertisement. Ids up to this one are cars first sold with Drive, later
+// ones only ever shipped with Overdrive firmware.
+pub const LAST_DRIVE_MODEL_ID: u8 = 0x07;
+
+impl ProtocolDialect {
+    pub fn from_firmware(firmware: FirmwareVersion) -> ProtocolDialect {
+        if firmware >= FIRMWARE_OVERDRIVE {
+            ProtocolDialect::Overdrive
+        } else {
+            ProtocolDialect::Drive
+        }
+    }
+
+    // Drive cars can be updated to Overdrive firmware, so for those the model only says which
+    // dialect the vehicle started out with. None for an unset model id.
+    pub fn from_model_id(model_id: u8) -> Option<ProtocolDialect> {
+        match model_id {
+            0 => None,
+            1..=LAST_DRIVE_MODEL_ID => Some(ProtocolDialect::Drive),
+            _ => Some(ProtocolDialect::Overdrive),
+        }
+    }
+
+    // The firmware decides when the vehicle has reported it, then the model. With neither the
+    // vehicle is treated as Drive, everything in that dialect works on both generations.
+    pub fn detect(model_id: Option<u8>, firmware: Option<FirmwareVersion>) -> ProtocolDialect {
+        firmware
+            .map(ProtocolDialect::from_firmware)
+            .or_else(|| model_id.and_then(ProtocolDialect::from_model_id))
+            .unwrap_or(ProtocolDialect::Drive)
+    }
+
+    pub fn supports(&self, msg_id: &AnkiVehicleMsgType) -> bool {
+        match self {
+            ProtocolDialect::Drive => min_firmware(msg_id) < FIRMWARE_OVERDRIVE,
+            ProtocolDialect::Overdrive => true,
+        }
+    }
+
+    pub fn supported_commands(&self) -> Vec<AnkiVehicleMsgType> {
+        FIRMWARE_OVERDRIVE
+            .supported_commands()
+            .into_iter()
+            .filter(|msg_id| self.supports(msg_id))
+            .collect()
+    }
+
+    // Size of a notification in this dialect, None for ids the vehicle never sends.
+    pub fn notification_size(&self, msg_id: &AnkiVehicleMsgType) -> Option<usize> {
+        self.supports(msg_id)
+            .then(|| notification_size(msg_id))
+            .flatten()
+    }
+
+    // The config parameters a vehicle gets after connecting, None where the firmware has no
+    // config parameters command.
+    pub fn default_config_params(&self) -> Option<VehicleCommand> {
+        match self {
+            ProtocolDialect::Drive => None,
+            ProtocolDialect::Overdrive => Some(VehicleCommand::SetConfigParams {
+                super_code_parse_mask: SUPERCODE_NONE,
+                track_material: TrackMaterial::Plastic,
+            }),
+        }
+    }
+
+    // `AnkiVehicleData::configure` followed by the dialect's config parameters.
+    pub fn configure(&self, vehicle: &mut AnkiVehicleData) -> Vec<VehicleCommand> {
+        let mut commands = vehicle.configure().to_vec();
+        commands.extend(self.default_config_params());
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_dialect_detect_test() {
+        assert_eq!(
+            ProtocolDialect::Drive,
+            ProtocolDialect::detect(None, Some(FirmwareVersion::from_packed(0x2611)))
+        );
+        // The firmware wins over the model, a Drive car can run Overdrive firmware.
+        assert_eq!(
+            ProtocolDialect::Overdrive,
+            ProtocolDialect::detect(Some(0x01), Some(FirmwareVersion::from_packed(0x2e6a)))
+        );
+        assert_eq!(
+            ProtocolDialect::Overdrive,
+            ProtocolDialect::detect(Some(0x09), None)
+        );
+        assert_eq!(
+            ProtocolDialect::Drive,
+            ProtocolDialect::detect(Some(0x00), None)
+        );
+    }
+
+    #[test]
+    fn protocol_dialect_capabilities_test() {
+        let drive = ProtocolDialect::Drive;
+        let overdrive = ProtocolDialect::Overdrive;
+        assert_eq!(12, drive.supported_commands().len());
+        assert_eq!(13, overdrive.supported_commands().len());
+        assert!(!drive.supports(&AnkiVehicleMsgType::C2VSetConfigParams));
+
+        let intersection = AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate;
+        assert_eq!(None, drive.notification_size(&intersection));
+        assert_eq!(
+            notification_size(&intersection),
+            overdrive.notification_size(&intersection)
+        );
+
+        let mut vehicle = AnkiVehicleData::default();
+        assert_eq!(vehicle.configure().to_vec(), drive.configure(&mut vehicle));
+        let commands = overdrive.configure(&mut vehicle);
+        assert_eq!(overdrive.default_config_params(), commands.last().cloned());
+    }
+}
@@ -0,0 +1,93 @@
+use thiserror::Error;
+
+use crate::protocol::{AnkiVehicleMsgType, ProtocolError};
+
+// Why a vehicle message couldn't be read or written. A frame that doesn't hold the message it
+// should is a `ProtocolError`, the rest are mistakes on this side or whatever scroll reports from
+// underneath, passed through as it is.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AnkiError {
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    // The buffer handed to an encoder isn't the size of the message.
+    #[error("Buffer of {got} bytes can't hold a {expected} byte message")]
+    BufferSize { expected: usize, got: usize },
+    #[error("Unexpected message {0:?}")]
+    UnexpectedMsg(AnkiVehicleMsgType),
+    // A scanned advertisement is missing an AD structure the vehicle always sends.
+    #[error("Advertisement has no {0} AD structure")]
+    MissingAdField(&'static str),
+    #[error("Lights pattern already has all {0} channels")]
+    LightsPatternFull(usize),
+    #[error(transparent)]
+    Scroll(#[from] scroll::Error),
+}
+
+// Decoders take exactly one message, no more and no less.
+pub(crate) fn check_frame_len(data: &[u8], expected: usize) -> Result<(), AnkiError> {
+    let got = data.len();
+    if got < expected {
+        Err(ProtocolError::TooShort { expected, got }.into())
+    } else if got > expected {
+        Err(ProtocolError::TooLong { expected, got }.into())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn check_buffer_len(data: &[u8], expected: usize) -> Result<(), AnkiError> {
+    if data.len() != expected {
+        return Err(AnkiError::BufferSize {
+            expected,
+            got: data.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{AnkiVehicleMsg, AnkiVehicleMsgVersionResponse, WIRE_ENDIAN};
+    use crate::validation::validate_notification;
+    use scroll::Pread;
+
+    #[test]
+    fn anki_error_test() {
+        assert!(matches!(
+            [0x03, 0x19, 0x76].pread_with::<AnkiVehicleMsgVersionResponse>(0, WIRE_ENDIAN),
+            Err(AnkiError::Protocol(ProtocolError::TooShort {
+                expected: 4,
+                got: 3
+            }))
+        ));
+        assert!(matches!(
+            [0x04, 0x19, 0x76, 0x26, 0x00]
+                .pread_with::<AnkiVehicleMsgVersionResponse>(0, WIRE_ENDIAN),
+            Err(AnkiError::Protocol(ProtocolError::TooLong {
+                expected: 4,
+                got: 5
+            }))
+        ));
+        assert!(matches!(
+            [0u8; 21].pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN),
+            Err(AnkiError::Protocol(ProtocolError::TooLong {
+                expected: 20,
+                got: 21
+            }))
+        ));
+        let intersection = [
+            0x0c, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x00, 0x50, 0x00, 0x00, 0x00,
+        ];
+        let e = validate_notification(&intersection).unwrap_err();
+        assert!(matches!(
+            e,
+            AnkiError::Protocol(ProtocolError::InvalidEnumValue {
+                field: "intersection_code",
+                value: 9
+            })
+        ));
+        assert_eq!("No intersection_code with value 9", e.to_string());
+    }
+}
@@ -0,0 +1,496 @@
+// C ABI for apps that can't link against Rust directly. Commands are encoded into a caller
+// owned buffer and the number of bytes written is returned, or -1 if the buffer is too small.
+// Notifications are parsed into the `#[repr(C)]` structs below, returning 0 on success and -1
+// if the frame is malformed, padding after the message is ignored. All pointers must be valid
+// for the given lengths.
+#![allow(clippy::missing_safety_doc)]
+
+use scroll::{ctx, Pread, Pwrite};
+use std::slice;
+
+use crate::error::AnkiError;
+use crate::protocol::{
+    anki_vehicle_msg_cancel_lane_change, anki_vehicle_msg_change_lane, anki_vehicle_msg_disconnect,
+    anki_vehicle_msg_get_battery_level, anki_vehicle_msg_get_version,
+    anki_vehicle_msg_lights_pattern, anki_vehicle_msg_ping, anki_vehicle_msg_set_lights,
+    anki_vehicle_msg_set_offset_from_road_centre, anki_vehicle_msg_set_sdk_mode,
+    anki_vehicle_msg_set_speed, anki_vehicle_msg_turn, strip_padding, AnkiVehicleMsg,
+    AnkiVehicleMsgBatteryLevelResponse, AnkiVehicleMsgLocalisationIntersectionUpdate,
+    AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgLocalisationTransitionUpdate,
+    AnkiVehicleMsgOffsetFromRoadCentreUpdate, AnkiVehicleMsgVersionResponse, LightChannel,
+    LightEffect, VehicleTurn, VehicleTurnTrigger, ANKI_VEHICLE_MSG_BASE_SIZE,
+    ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+    ANKI_VEHICLE_MSG_MAX_SIZE, ANKI_VEHICLE_MSG_SDK_MODE_SIZE, ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+    ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE, ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+    ANKI_VEHICLE_MSG_TURN_SIZE, WIRE_ENDIAN,
+};
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleFfiPositionUpdate {
+    pub location_id: u8,
+    pub road_piece_id: u8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub parsing_flags: u8,
+    pub last_recv_lane_change_cmd_id: u8,
+    pub last_exec_lane_change_cmd_id: u8,
+    pub last_desired_lane_change_speed_mm_per_sec: u16,
+    pub last_desired_speed_mm_per_sec: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleFfiTransitionUpdate {
+    pub road_piece_idx: i8,
+    pub road_piece_idx_prev: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub last_recv_lane_change_id: u8,
+    pub last_exec_lane_change_id: u8,
+    pub last_desired_lane_change_speed_mm_per_sec: u16,
+    pub ave_follow_line_drift_pixels: i8,
+    pub had_lane_change_activity: u8,
+    pub uphill_counter: u8,
+    pub downhill_counter: u8,
+    pub left_wheel_dist_cm: u8,
+    pub right_wheel_dist_cm: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleFfiIntersectionUpdate {
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub intersection_code: u8,
+    pub is_exiting: u8,
+    pub mm_since_last_transition_bar: u16,
+    pub mm_since_last_intersection_code: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleFfiOffsetFromRoadCentreUpdate {
+    pub offset_from_road_centre_mm: f32,
+    pub lane_change_id: u8,
+}
+
+unsafe fn write_frame<T>(msg: T, size: usize, out: *mut u8, out_len: usize) -> i32
+where
+    T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+{
+    let mut data = [0u8; ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE];
+    let Ok(written) = data[..size].pwrite_with::<T>(msg, 0, WIRE_ENDIAN) else {
+        return -1;
+    };
+    if out.is_null() || out_len < written {
+        return -1;
+    }
+    slice::from_raw_parts_mut(out, written).copy_from_slice(&data[..written]);
+    written as i32
+}
+
+unsafe fn read_frame<'a, T>(data: *const u8, len: usize) -> Option<T>
+where
+    T: ctx::TryFromCtx<'a, scroll::Endian, Error = AnkiError>,
+{
+    if data.is_null() || len > ANKI_VEHICLE_MSG_MAX_SIZE {
+        return None;
+    }
+    strip_padding(slice::from_raw_parts(data, len))
+        .pread_with::<T>(0, WIRE_ENDIAN)
+        .ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_set_sdk_mode(
+    on: u8,
+    flags: u8,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_set_sdk_mode(on, flags),
+        ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_set_speed(
+    speed_mm_per_sec: i16,
+    accel_mm_per_sec2: i16,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_set_speed(speed_mm_per_sec, accel_mm_per_sec2),
+        ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_set_offset_from_road_centre(
+    offset_mm: f32,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_set_offset_from_road_centre(offset_mm),
+        ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_change_lane(
+    horizontal_speed_mm_per_sec: u16,
+    horizontal_accel_mm_per_sec2: u16,
+    offset_from_road_centre_mm: f32,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_change_lane(
+            horizontal_speed_mm_per_sec,
+            horizontal_accel_mm_per_sec2,
+            offset_from_road_centre_mm,
+        ),
+        ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_turn(
+    turn_type: u8,
+    trigger: u8,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    let (Ok(turn_type), Ok(trigger)) = (
+        VehicleTurn::try_from(turn_type),
+        VehicleTurnTrigger::try_from(trigger),
+    ) else {
+        return -1;
+    };
+    write_frame(
+        anki_vehicle_msg_turn(turn_type, trigger),
+        ANKI_VEHICLE_MSG_TURN_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_set_lights(
+    mask: u8,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_set_lights(mask),
+        ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+        out,
+        out_len,
+    )
+}
+
+// Single channel pattern, the C SDK never exposed more than one config per call either.
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_lights_pattern(
+    channel: u8,
+    effect: u8,
+    start: u8,
+    end: u8,
+    cycles_per_min: u16,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    let (Ok(channel), Ok(effect)) = (
+        LightChannel::try_from(channel),
+        LightEffect::try_from(effect),
+    ) else {
+        return -1;
+    };
+    let msg = anki_vehicle_msg_lights_pattern(channel, effect, start, end, cycles_per_min);
+    write_frame(msg, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE, out, out_len)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_ping(out: *mut u8, out_len: usize) -> i32 {
+    write_frame(
+        anki_vehicle_msg_ping(),
+        ANKI_VEHICLE_MSG_BASE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_disconnect(out: *mut u8, out_len: usize) -> i32 {
+    write_frame(
+        anki_vehicle_msg_disconnect(),
+        ANKI_VEHICLE_MSG_BASE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_get_version(out: *mut u8, out_len: usize) -> i32 {
+    write_frame(
+        anki_vehicle_msg_get_version(),
+        ANKI_VEHICLE_MSG_BASE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_get_battery_level(
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_get_battery_level(),
+        ANKI_VEHICLE_MSG_BASE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_encode_cancel_lane_change(
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    write_frame(
+        anki_vehicle_msg_cancel_lane_change(),
+        ANKI_VEHICLE_MSG_BASE_SIZE,
+        out,
+        out_len,
+    )
+}
+
+// Returns the message id of a notification so callers know which parse function to use,
+// 0 if the frame can't be read.
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_msg_type(data: *const u8, len: usize) -> u8 {
+    read_frame::<AnkiVehicleMsg>(data, len).map_or(0, |msg| msg.msg_id.into())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_version_response(
+    data: *const u8,
+    len: usize,
+    version: *mut u16,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgVersionResponse>(data, len) {
+        Some(msg) if !version.is_null() => {
+            *version = msg.version;
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_battery_level_response(
+    data: *const u8,
+    len: usize,
+    battery_level: *mut u16,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgBatteryLevelResponse>(data, len) {
+        Some(msg) if !battery_level.is_null() => {
+            *battery_level = msg.battery_level;
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_position_update(
+    data: *const u8,
+    len: usize,
+    out: *mut AnkiVehicleFfiPositionUpdate,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgLocalisationPositionUpdate>(data, len) {
+        Some(msg) if !out.is_null() => {
+            *out = AnkiVehicleFfiPositionUpdate {
+                location_id: msg.location_id,
+                road_piece_id: msg.road_piece_id,
+                offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                speed_mm_per_sec: msg.speed_mm_per_sec,
+                parsing_flags: msg.parsing_flags.bits(),
+                last_recv_lane_change_cmd_id: msg.last_recv_lane_change_cmd_id,
+                last_exec_lane_change_cmd_id: msg.last_exec_lane_change_cmd_id,
+                last_desired_lane_change_speed_mm_per_sec: msg
+                    .last_desired_lane_change_speed_mm_per_sec,
+                last_desired_speed_mm_per_sec: msg.last_desired_speed_mm_per_sec,
+            };
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_transition_update(
+    data: *const u8,
+    len: usize,
+    out: *mut AnkiVehicleFfiTransitionUpdate,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgLocalisationTransitionUpdate>(data, len) {
+        Some(msg) if !out.is_null() => {
+            *out = AnkiVehicleFfiTransitionUpdate {
+                road_piece_idx: msg.road_piece_idx,
+                road_piece_idx_prev: msg.road_piece_idx_prev,
+                offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                last_recv_lane_change_id: msg.last_recv_lane_change_id,
+                last_exec_lane_change_id: msg.last_exec_lane_change_id,
+                last_desired_lane_change_speed_mm_per_sec: msg
+                    .last_desired_lane_change_speed_mm_per_sec,
+                ave_follow_line_drift_pixels: msg.ave_follow_line_drift_pixels,
+                had_lane_change_activity: msg.had_lane_change_activity,
+                uphill_counter: msg.uphill_counter,
+                downhill_counter: msg.downhill_counter,
+                left_wheel_dist_cm: msg.left_wheel_dist_cm,
+                right_wheel_dist_cm: msg.right_wheel_dist_cm,
+            };
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_intersection_update(
+    data: *const u8,
+    len: usize,
+    out: *mut AnkiVehicleFfiIntersectionUpdate,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgLocalisationIntersectionUpdate>(data, len) {
+        Some(msg) if !out.is_null() => {
+            *out = AnkiVehicleFfiIntersectionUpdate {
+                road_piece_idx: msg.road_piece_idx,
+                offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                intersection_code: msg.intersection_code.into(),
+                is_exiting: msg.is_exiting,
+                mm_since_last_transition_bar: msg.mm_since_last_transition_bar,
+                mm_since_last_intersection_code: msg.mm_since_last_intersection_code,
+            };
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn anki_vehicle_parse_offset_from_road_centre_update(
+    data: *const u8,
+    len: usize,
+    out: *mut AnkiVehicleFfiOffsetFromRoadCentreUpdate,
+) -> i32 {
+    match read_frame::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(data, len) {
+        Some(msg) if !out.is_null() => {
+            *out = AnkiVehicleFfiOffsetFromRoadCentreUpdate {
+                offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                lane_change_id: msg.lane_change_id,
+            };
+            0
+        }
+        _ => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{AnkiVehicleMsgType, ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE};
+    use crate::AnkiVehicleData;
+    use std::ptr;
+
+    #[test]
+    fn ffi_encode_test() {
+        let mut out = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        let written =
+            unsafe { anki_vehicle_encode_set_speed(500, 1000, out.as_mut_ptr(), out.len()) };
+        println!(
+            "T:{:?} == G:{:?}",
+            &out[..written as usize],
+            AnkiVehicleData::set_speed(500, 1000)
+        );
+        assert_eq!(
+            AnkiVehicleData::set_speed(500, 1000),
+            out[..written as usize].to_vec()
+        );
+
+        let written = unsafe {
+            anki_vehicle_encode_lights_pattern(0, 2, 0, 14, 60, out.as_mut_ptr(), out.len())
+        };
+        assert_eq!(ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE as i32, written);
+
+        assert_eq!(-1, unsafe {
+            anki_vehicle_encode_set_speed(500, 1000, out.as_mut_ptr(), 3)
+        });
+        assert_eq!(-1, unsafe {
+            anki_vehicle_encode_turn(9, 0, out.as_mut_ptr(), out.len())
+        });
+        assert_eq!(-1, unsafe { anki_vehicle_encode_ping(ptr::null_mut(), 2) });
+    }
+
+    #[test]
+    fn ffi_parse_position_update_test() {
+        let data: &[u8; ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE] = &[
+            16,
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
+            0xA,
+            0xB,
+            0,
+            0,
+            200,
+            66,
+            0xEF,
+            0xCD,
+            1,
+            2,
+            3,
+            0x55,
+            0x44,
+            0x77,
+            0x66,
+        ];
+        assert_eq!(
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
+            unsafe { anki_vehicle_msg_type(data.as_ptr(), data.len()) }
+        );
+
+        let mut update = AnkiVehicleFfiPositionUpdate::default();
+        let result =
+            unsafe { anki_vehicle_parse_position_update(data.as_ptr(), data.len(), &mut update) };
+        println!("T:{:?} == G:{:?}", update, data);
+        assert_eq!(0, result);
+        assert_eq!(0xB, update.road_piece_id);
+        assert_eq!(100.0, update.offset_from_road_centre_mm);
+        assert_eq!(0xCDEF, update.speed_mm_per_sec);
+        assert_eq!(0x6677, update.last_desired_speed_mm_per_sec);
+
+        let mut padded = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        padded[..data.len()].copy_from_slice(data);
+        let mut padded_update = AnkiVehicleFfiPositionUpdate::default();
+        assert_eq!(0, unsafe {
+            anki_vehicle_parse_position_update(padded.as_ptr(), padded.len(), &mut padded_update)
+        });
+        assert_eq!(update, padded_update);
+
+        assert_eq!(-1, unsafe {
+            anki_vehicle_parse_position_update(data.as_ptr(), 4, &mut update)
+        });
+    }
+}
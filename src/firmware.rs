@@ -0,0 +1,198 @@
+use scroll::Pread;
+use std::fmt;
+
+use crate::protocol::{AnkiVehicleMsg, AnkiVehicleMsgType, WIRE_ENDIAN};
+
+// The version response packs the firmware build into the high byte and the revision of that
+// build into the low byte, e.g. 0x2e6a is build 0x2e, revision 0x6a.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FirmwareVersion {
+    pub build: u8,
+    pub revision: u8,
+}
+
+impl FirmwareVersion {
+    pub const fn new(build: u8, revision: u8) -> FirmwareVersion {
+        FirmwareVersion { build, revision }
+    }
+
+    pub const fn from_packed(version: u16) -> FirmwareVersion {
+        FirmwareVersion {
+            build: (version >> 8) as u8,
+            revision: version as u8,
+        }
+    }
+
+    pub const fn packed(&self) -> u16 {
+        (self.build as u16) << 8 | self.revision as u16
+    }
+
+    pub fn supports(&self, msg_id: &AnkiVehicleMsgType) -> bool {
+        *self >= min_firmware(msg_id)
+    }
+
+    // Every command in the capability matrix this firmware will act on.
+    pub fn supported_commands(&self) -> Vec<AnkiVehicleMsgType> {
+        COMMANDS
+            .iter()
+            .filter_map(|&msg_id| {
+                let msg_id = AnkiVehicleMsgType::try_from(msg_id).ok()?;
+                self.supports(&msg_id).then_some(msg_id)
+            })
+            .collect()
+    }
+
+    // Checks an encoded command before it is written. Old firmware silently drops messages it
+    // doesn't know, so the caller gets a chance to warn instead of wondering why nothing happens.
+    pub fn check_command(&self, data: &[u8]) -> Result<(), UnsupportedCommand> {
+        let Ok(msg) = data.pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN) else {
+            return Ok(());
+        };
+        let required = min_firmware(&msg.msg_id);
+        if *self >= required {
+            return Ok(());
+        }
+        Err(UnsupportedCommand {
+            msg_id: msg.msg_id,
+            firmware: *self,
+            required,
+        })
+    }
+}
+
+impl From<u16> for FirmwareVersion {
+    fn from(version: u16) -> Self {
+        FirmwareVersion::from_packed(version)
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06x}", self.packed())
+    }
+}
+
+// Builds that first shipped the newer messages. Anything older than the Overdrive firmware is
+// only known to handle the original Drive command set.
+pub const FIRMWARE_DRIVE_TURNS: FirmwareVersion = FirmwareVersion::new(0x26, 0x00);
+pub const FIRMWARE_OVERDRIVE: FirmwareVersion = FirmwareVersion::new(0x2e, 0x00);
+
+const COMMANDS: [u8; 13] = [
+    AnkiVehicleMsgType::C2VDisconnect as u8,
+    AnkiVehicleMsgType::C2CPingRequest as u8,
+    AnkiVehicleMsgType::C2VVersionRequest as u8,
+    AnkiVehicleMsgType::C2VBatteryLevelRequest as u8,
+    AnkiVehicleMsgType::C2VSetLights as u8,
+    AnkiVehicleMsgType::C2VSetSpeed as u8,
+    AnkiVehicleMsgType::C2VChangeLane as u8,
+    AnkiVehicleMsgType::C2VCancelLaneChange as u8,
+    AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre as u8,
+    AnkiVehicleMsgType::C2VTurn as u8,
+    AnkiVehicleMsgType::C2VLightsPattern as u8,
+    AnkiVehicleMsgType::C2VSetConfigParams as u8,
+    AnkiVehicleMsgType::C2VSDKMode as u8,
+];
+
+// The capability matrix: the oldest firmware that handles each message. Notifications are
+// listed too, a vehicle on older firmware simply never sends them.
+pub fn min_firmware(msg_id: &AnkiVehicleMsgType) -> FirmwareVersion {
+    match msg_id {
+        AnkiVehicleMsgType::C2VTurn | AnkiVehicleMsgType::C2VLightsPattern => FIRMWARE_DRIVE_TURNS,
+        AnkiVehicleMsgType::C2VSetConfigParams
+        | AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate
+        | AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate
+        | AnkiVehicleMsgType::V2CSpeedUpdate
+        | AnkiVehicleMsgType::V2CChargerInfo
+        | AnkiVehicleMsgType::V2CCollisionDetected
+        | AnkiVehicleMsgType::V2CCycleOvertime => FIRMWARE_OVERDRIVE,
+        _ => FirmwareVersion::new(0, 0),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UnsupportedCommand {
+    pub msg_id: AnkiVehicleMsgType,
+    pub firmware: FirmwareVersion,
+    pub required: FirmwareVersion,
+}
+
+impl fmt::Display for UnsupportedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} needs firmware {} or newer, the vehicle runs {} and will ignore it",
+            self.msg_id, self.required, self.firmware
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedCommand {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        anki_vehicle_msg_set_config_params, TrackMaterial, ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+    };
+    use scroll::Pwrite;
+
+    #[test]
+    fn firmware_version_packed_test() {
+        let version = FirmwareVersion::from_packed(0x2e6a);
+        assert_eq!(FirmwareVersion::new(0x2e, 0x6a), version);
+        assert_eq!(0x2e6a, version.packed());
+        assert_eq!("0x2e6a", version.to_string());
+        assert!(FirmwareVersion::from_packed(0x2601) > FirmwareVersion::from_packed(0x25ff));
+    }
+
+    #[test]
+    fn firmware_capabilities_test() {
+        let drive = FirmwareVersion::from_packed(0x2611);
+        assert!(drive.supports(&AnkiVehicleMsgType::C2VTurn));
+        assert!(!drive.supports(&AnkiVehicleMsgType::C2VSetConfigParams));
+        assert_eq!(12, drive.supported_commands().len());
+        assert_eq!(
+            13,
+            FirmwareVersion::from_packed(0x2e6a)
+                .supported_commands()
+                .len()
+        );
+        assert_eq!(
+            10,
+            FirmwareVersion::from_packed(0x2000)
+                .supported_commands()
+                .len()
+        );
+    }
+
+    #[test]
+    fn firmware_check_command_test() {
+        let mut data = [0u8; ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE];
+        data.pwrite_with(
+            anki_vehicle_msg_set_config_params(0, TrackMaterial::Vinyl),
+            0,
+            WIRE_ENDIAN,
+        )
+        .unwrap();
+
+        let drive = FirmwareVersion::from_packed(0x2611);
+        assert_eq!(
+            Err(UnsupportedCommand {
+                msg_id: AnkiVehicleMsgType::C2VSetConfigParams,
+                firmware: drive,
+                required: FIRMWARE_OVERDRIVE,
+            }),
+            drive.check_command(&data)
+        );
+        assert_eq!(
+            Ok(()),
+            FirmwareVersion::from_packed(0x2e6a).check_command(&data)
+        );
+        assert_eq!(
+            Ok(()),
+            drive.check_command(&crate::AnkiVehicleData::set_speed(500, 1000))
+        );
+    }
+}
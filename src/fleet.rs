@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Instant;
+
+use crate::bt_address::BtAddress;
+use crate::command::{Command, WireMessage};
+use crate::error::AnkiError;
+use crate::io::transport::VehicleTransport;
+use crate::race::RaceCommand;
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_state::{StateChange, VehicleState};
+use crate::AnkiVehicleData;
+
+#[derive(Debug)]
+pub enum FleetError {
+    UnknownVehicle(BtAddress),
+    Anki(AnkiError),
+    // The vehicle's transport turned a command down.
+    Transport(String),
+}
+
+impl fmt::Display for FleetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FleetError::UnknownVehicle(address) => write!(f, "Unknown vehicle {}", address),
+            FleetError::Anki(e) => write!(f, "{}", e),
+            FleetError::Transport(e) => write!(f, "Transport error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FleetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FleetError::Anki(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AnkiError> for FleetError {
+    fn from(e: AnkiError) -> Self {
+        FleetError::Anki(e)
+    }
+}
+
+// Counts and extremes across the fleet, for a dashboard's summary line.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FleetSummary {
+    pub vehicles: usize,
+    pub localized: usize,
+    pub on_charger: usize,
+    pub low_battery: usize,
+    // Lowest battery reading among vehicles that have reported one.
+    pub min_battery_level: Option<u16>,
+    pub max_speed_mm_per_sec: u16,
+}
+
+#[derive(Debug, Clone)]
+struct FleetEntry<T> {
+    vehicle: AnkiVehicleData,
+    transport: T,
+}
+
+// The vehicles one controller looks after, by Bluetooth address. Notifications go to the vehicle
+// they came from, commands for the whole fleet come back addressed to each vehicle for the
+// caller to write. Vehicles are kept in address order.
+//
+// A fleet can also hold each vehicle's `VehicleTransport`, then it sends commands and polls
+// notifications itself. Without one, `T` is `()` and frames go in and out through the caller.
+#[derive(Debug, Clone)]
+pub struct Fleet<T = ()> {
+    vehicles: BTreeMap<BtAddress, FleetEntry<T>>,
+}
+
+impl<T> Default for Fleet<T> {
+    fn default() -> Self {
+        Fleet {
+            vehicles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Fleet {
+    // Replaces and returns any vehicle already at this address.
+    pub fn insert(
+        &mut self,
+        address: BtAddress,
+        vehicle: AnkiVehicleData,
+    ) -> Option<AnkiVehicleData> {
+        self.insert_with_transport(address, vehicle, ())
+    }
+}
+
+impl<T> Fleet<T> {
+    pub fn new() -> Fleet<T> {
+        Fleet::default()
+    }
+
+    pub fn insert_with_transport(
+        &mut self,
+        address: BtAddress,
+        vehicle: AnkiVehicleData,
+        transport: T,
+    ) -> Option<AnkiVehicleData> {
+        let entry = FleetEntry {
+            vehicle: vehicle.with_address(address),
+            transport,
+        };
+        self.vehicles
+            .insert(address, entry)
+            .map(|entry| entry.vehicle)
+    }
+
+    pub fn remove(&mut self, address: BtAddress) -> Option<AnkiVehicleData> {
+        self.vehicles.remove(&address).map(|entry| entry.vehicle)
+    }
+
+    pub fn get(&self, address: BtAddress) -> Option<&AnkiVehicleData> {
+        self.vehicles.get(&address).map(|entry| &entry.vehicle)
+    }
+
+    pub fn get_mut(&mut self, address: BtAddress) -> Option<&mut AnkiVehicleData> {
+        self.vehicles
+            .get_mut(&address)
+            .map(|entry| &mut entry.vehicle)
+    }
+
+    pub fn transport(&self, address: BtAddress) -> Option<&T> {
+        self.vehicles.get(&address).map(|entry| &entry.transport)
+    }
+
+    pub fn transport_mut(&mut self, address: BtAddress) -> Option<&mut T> {
+        self.vehicles
+            .get_mut(&address)
+            .map(|entry| &mut entry.transport)
+    }
+
+    pub fn contains(&self, address: BtAddress) -> bool {
+        self.vehicles.contains_key(&address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vehicles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty()
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = BtAddress> + '_ {
+        self.vehicles.keys().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BtAddress, &AnkiVehicleData)> + '_ {
+        self.vehicles
+            .iter()
+            .map(|(&address, entry)| (address, &entry.vehicle))
+    }
+
+    // Hands a raw notification to the vehicle it came from, see
+    // `AnkiVehicleData::process_raw`.
+    pub fn process_notification(
+        &mut self,
+        address: BtAddress,
+        data: &[u8],
+    ) -> Result<StateChange, FleetError> {
+        self.process_notification_at(address, data, Instant::now())
+    }
+
+    pub fn process_notification_at(
+        &mut self,
+        address: BtAddress,
+        data: &[u8],
+        at: Instant,
+    ) -> Result<StateChange, FleetError> {
+        let vehicle = self
+            .get_mut(address)
+            .ok_or(FleetError::UnknownVehicle(address))?;
+        let msg = vehicle.decode_notification(data)?;
+        Ok(vehicle.process_message_at(msg, at))
+    }
+
+    // The same command for every vehicle, encoded once.
+    pub fn broadcast(&self, command: impl WireMessage) -> Vec<(BtAddress, Command)> {
+        let command = command.encode();
+        self.addresses().map(|address| (address, command)).collect()
+    }
+
+    pub fn stop_all(&self) -> Vec<(BtAddress, Command)> {
+        self.broadcast(AnkiVehicleData::stop())
+    }
+
+    pub fn set_speed_all(
+        &self,
+        speed_mm_per_sec: i16,
+        accel_mm_per_sec2: i16,
+    ) -> Vec<(BtAddress, Command)> {
+        self.broadcast(AnkiVehicleData::set_speed(
+            speed_mm_per_sec,
+            accel_mm_per_sec2,
+        ))
+    }
+
+    // Addresses and encodes race commands for a race run on this fleet, see `Race::for_fleet`.
+    // Commands for vehicles that aren't in the fleet are left out.
+    pub fn race_commands<'a, I>(&self, commands: I) -> Vec<(BtAddress, Command)>
+    where
+        I: IntoIterator<Item = &'a RaceCommand>,
+    {
+        commands
+            .into_iter()
+            .filter_map(|race_command| {
+                let address = race_command.vehicle.parse::<BtAddress>().ok()?;
+                self.contains(address)
+                    .then(|| (address, race_command.command.encode()))
+            })
+            .collect()
+    }
+
+    pub fn state(&self, address: BtAddress) -> Option<VehicleState> {
+        self.get(address).map(AnkiVehicleData::state)
+    }
+
+    pub fn states(&self) -> Vec<(BtAddress, VehicleState)> {
+        self.iter()
+            .map(|(address, vehicle)| (address, vehicle.state()))
+            .collect()
+    }
+
+    pub fn summary(&self) -> FleetSummary {
+        let mut summary = FleetSummary {
+            vehicles: self.len(),
+            ..FleetSummary::default()
+        };
+        for (_, vehicle) in self.iter() {
+            summary.localized += vehicle.is_localized() as usize;
+            summary.on_charger += vehicle.is_on_charger() as usize;
+            summary.low_battery += vehicle.advertised_state().low_battery as usize;
+            if vehicle.battery_level() != 0 {
+                summary.min_battery_level = Some(
+                    summary
+                        .min_battery_level
+                        .map_or(vehicle.battery_level(), |min| {
+                            min.min(vehicle.battery_level())
+                        }),
+                );
+            }
+            summary.max_speed_mm_per_sec =
+                summary.max_speed_mm_per_sec.max(vehicle.speed_mm_per_sec());
+        }
+        summary
+    }
+}
+
+impl<T: VehicleTransport> Fleet<T> {
+    pub fn send(
+        &mut self,
+        address: BtAddress,
+        command: impl WireMessage,
+    ) -> Result<(), FleetError> {
+        self.transport_mut(address)
+            .ok_or(FleetError::UnknownVehicle(address))?
+            .send(&command.encode())
+            .map_err(|e| FleetError::Transport(e.to_string()))
+    }
+
+    // Sends to every vehicle even when some fail, the first failure is returned.
+    pub fn send_all(&mut self, command: impl WireMessage) -> Result<(), FleetError> {
+        let command = command.encode();
+        let mut result = Ok(());
+        for entry in self.vehicles.values_mut() {
+            if let Err(e) = entry.transport.send(&command) {
+                result = result.and(Err(FleetError::Transport(e.to_string())));
+            }
+        }
+        result
+    }
+
+    // Takes every notification waiting on every transport to its vehicle and returns what they
+    // changed. Malformed notifications are dropped.
+    pub fn poll_notifications(&mut self, at: Instant) -> Vec<(BtAddress, StateChange)> {
+        let mut changes = Vec::new();
+        for (&address, entry) in self.vehicles.iter_mut() {
+            while let Some(data) = entry.transport.poll_notification() {
+                let change = entry
+                    .vehicle
+                    .decode_notification(&data)
+                    .map(|msg| entry.vehicle.process_message_at(msg, at));
+                match change {
+                    Ok(StateChange::None) => {}
+                    Ok(change) => changes.push((address, change)),
+                    Err(_e) => {
+                        trace_event!(target: TARGET_TRANSPORT, debug, vehicle = %address, error = %_e, "Dropped malformed notification");
+                    }
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::VehicleCommand;
+    use crate::protocol::AnkiVehicleMsgType;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use std::time::Duration;
+
+    #[test]
+    fn fleet_test() {
+        let skull = BtAddress::new([0xd0, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let nuke = BtAddress::new([0xc0, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut fleet = Fleet::new();
+        fleet.insert(skull, AnkiVehicleData::default().with_name("Skull"));
+        fleet.insert(nuke, AnkiVehicleData::default().with_name("Nuke"));
+        assert_eq!(vec![nuke, skull], fleet.addresses().collect::<Vec<_>>());
+        assert_eq!(Some(skull), fleet.get(skull).unwrap().address());
+
+        let battery = [
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse.into(),
+            0x10,
+            0x0e,
+        ];
+        assert_eq!(
+            StateChange::Battery(0x0e10),
+            fleet.process_notification(skull, &battery).unwrap()
+        );
+        assert_eq!(0, fleet.get(nuke).unwrap().battery_level());
+        assert!(matches!(
+            fleet.process_notification(BtAddress::default(), &battery),
+            Err(FleetError::UnknownVehicle(_))
+        ));
+        assert!(matches!(
+            fleet.process_notification(nuke, &battery[..3]),
+            Err(FleetError::Anki(_))
+        ));
+
+        let stop = fleet.stop_all();
+        assert_eq!(2, stop.len());
+        assert!(stop
+            .iter()
+            .all(|(_, command)| *command == AnkiVehicleData::stop()));
+
+        let commands = [
+            RaceCommand {
+                vehicle: skull.into(),
+                command: VehicleCommand::PingRequest,
+            },
+            RaceCommand {
+                vehicle: "Nuke".into(),
+                command: VehicleCommand::PingRequest,
+            },
+        ];
+        assert_eq!(vec![(skull, Command::PING)], fleet.race_commands(&commands));
+
+        assert_eq!("Skull", fleet.state(skull).unwrap().name);
+        assert_eq!(
+            FleetSummary {
+                vehicles: 2,
+                min_battery_level: Some(0x0e10),
+                ..FleetSummary::default()
+            },
+            fleet.summary()
+        );
+    }
+
+    #[test]
+    fn fleet_transport_test() {
+        let t0 = Instant::now();
+        let skull = BtAddress::new([0xd0, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let nuke = BtAddress::new([0xc0, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut fleet = Fleet::new();
+        for (address, battery_level) in [(skull, 3900), (nuke, 4000)] {
+            fleet.insert_with_transport(
+                address,
+                AnkiVehicleData::default(),
+                SimulatedVehicle::new().with_battery_level(battery_level),
+            );
+        }
+
+        fleet.send_all(VehicleCommand::BatteryLevelRequest).unwrap();
+        assert_eq!(
+            vec![
+                (nuke, StateChange::Battery(4000)),
+                (skull, StateChange::Battery(3900))
+            ],
+            fleet.poll_notifications(t0)
+        );
+
+        fleet
+            .send(skull, AnkiVehicleData::set_speed(500, 0))
+            .unwrap();
+        assert!(matches!(
+            fleet.send(BtAddress::default(), Command::PING),
+            Err(FleetError::UnknownVehicle(_))
+        ));
+        for _ in 0..10 {
+            fleet
+                .transport_mut(skull)
+                .unwrap()
+                .advance(Duration::from_millis(100));
+        }
+        let changes = fleet.poll_notifications(t0);
+        assert!(changes.iter().all(|(address, _)| *address == skull));
+        assert_eq!(500, fleet.get(skull).unwrap().speed_mm_per_sec());
+    }
+}
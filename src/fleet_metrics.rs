@@ -0,0 +1,268 @@
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use std::collections::HashSet;
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::AnkiError;
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::protocol::AnkiVehicleMsgType;
+
+// Everything goes through the `metrics` facade, install whichever recorder the fleet is scraped
+// with (e.g. metrics-exporter-prometheus) before connecting vehicles. Without a recorder the
+// calls are no-ops.
+
+pub const MESSAGES_DECODED: &str = "anki_messages_decoded_total";
+pub const DECODE_ERRORS: &str = "anki_decode_errors_total";
+pub const DELOCALIZATIONS: &str = "anki_delocalizations_total";
+pub const WRITE_LATENCY: &str = "anki_write_latency_seconds";
+pub const WRITE_ERRORS: &str = "anki_write_errors_total";
+pub const RECONNECTS: &str = "anki_reconnects_total";
+pub const FRAME_POOL_HITS: &str = "anki_frame_pool_hits_total";
+pub const FRAME_POOL_MISSES: &str = "anki_frame_pool_misses_total";
+
+// Registers units and help text, call once after installing the recorder.
+pub fn describe() {
+    describe_counter!(
+        MESSAGES_DECODED,
+        Unit::Count,
+        "Notifications decoded, by message type"
+    );
+    describe_counter!(
+        DECODE_ERRORS,
+        Unit::Count,
+        "Notifications dropped because they could not be decoded"
+    );
+    describe_counter!(
+        DELOCALIZATIONS,
+        Unit::Count,
+        "Times a vehicle lost its position on the track"
+    );
+    describe_histogram!(
+        WRITE_LATENCY,
+        Unit::Seconds,
+        "Time taken to write a command to a vehicle"
+    );
+    describe_counter!(WRITE_ERRORS, Unit::Count, "Commands that failed to write");
+    describe_counter!(
+        RECONNECTS,
+        Unit::Count,
+        "Connections to a vehicle that had been connected before"
+    );
+    describe_counter!(
+        FRAME_POOL_HITS,
+        Unit::Count,
+        "Frame buffers reused from the pool"
+    );
+    describe_counter!(
+        FRAME_POOL_MISSES,
+        Unit::Count,
+        "Frame buffers the pool had to allocate"
+    );
+}
+
+// Called by `AnkiVehicleData::process_notification` and `process_raw` for every frame given.
+pub(crate) fn record_notification(vehicle: &str, result: Result<&AnkiVehicleMsgType, &AnkiError>) {
+    match result {
+        Ok(msg_id) => {
+            counter!(MESSAGES_DECODED, "msg_type" => format!("{:?}", msg_id)).increment(1);
+            if *msg_id == AnkiVehicleMsgType::V2CVehicleDelocalized {
+                counter!(DELOCALIZATIONS, "vehicle" => vehicle.to_string()).increment(1);
+            }
+        }
+        Err(_) => counter!(DECODE_ERRORS).increment(1),
+    }
+}
+
+// Called by `FramePool::take`, the hit rate is hits over hits plus misses.
+pub(crate) fn record_pool_take(hit: bool) {
+    if hit {
+        counter!(FRAME_POOL_HITS).increment(1);
+    } else {
+        counter!(FRAME_POOL_MISSES).increment(1);
+    }
+}
+
+// Wraps a host to measure command writes and count reconnects, so every front-end built on
+// `FleetHost` reports them without changes to the BLE code.
+pub struct MeteredHost<H> {
+    host: H,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl<H: FleetHost> MeteredHost<H> {
+    pub fn new(host: H) -> MeteredHost<H> {
+        MeteredHost {
+            host,
+            seen: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.host
+    }
+}
+
+impl<H: FleetHost> FleetHost for MeteredHost<H> {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+        self.host.discover()
+    }
+
+    fn vehicles(&self) -> Vec<HostVehicle> {
+        self.host.vehicles()
+    }
+
+    fn connect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.connect(vehicle)?;
+        let first = self.seen.lock().unwrap().insert(vehicle.to_string());
+        if !first {
+            counter!(RECONNECTS, "vehicle" => vehicle.to_string()).increment(1);
+        }
+        Ok(())
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.disconnect(vehicle)
+    }
+
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+        let start = Instant::now();
+        let result = self.host.send(vehicle, data);
+        histogram!(WRITE_LATENCY, "vehicle" => vehicle.to_string()).record(start.elapsed());
+        if result.is_err() {
+            counter!(WRITE_ERRORS, "vehicle" => vehicle.to_string()).increment(1);
+        }
+        result
+    }
+
+    fn subscribe(&self) -> Receiver<HostNotification> {
+        self.host.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnkiVehicleData;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+    use metrics_util::CompositeKey;
+    use std::sync::mpsc::channel;
+
+    type Snapshot = Vec<(
+        CompositeKey,
+        Option<Unit>,
+        Option<metrics::SharedString>,
+        DebugValue,
+    )>;
+
+    // Taking a snapshot drains the histograms, so every test takes exactly one.
+    fn snapshot(snapshotter: &Snapshotter) -> Snapshot {
+        snapshotter.snapshot().into_vec()
+    }
+
+    fn value<'a>(snapshot: &'a Snapshot, name: &str) -> Vec<(Vec<String>, &'a DebugValue)> {
+        snapshot
+            .iter()
+            .filter(|(key, ..)| key.key().name() == name)
+            .map(|(key, _, _, value)| {
+                let labels = key
+                    .key()
+                    .labels()
+                    .map(|l| format!("{}={}", l.key(), l.value()))
+                    .collect();
+                (labels, value)
+            })
+            .collect()
+    }
+
+    struct TestHost;
+
+    impl FleetHost for TestHost {
+        fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+            Ok(Vec::new())
+        }
+
+        fn vehicles(&self) -> Vec<HostVehicle> {
+            Vec::new()
+        }
+
+        fn connect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn disconnect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn send(&self, vehicle: &str, _data: Vec<u8>) -> Result<(), HostError> {
+            match vehicle {
+                "skull" => Ok(()),
+                _ => Err(HostError::NotConnected(vehicle.to_string())),
+            }
+        }
+
+        fn subscribe(&self) -> Receiver<HostNotification> {
+            channel().1
+        }
+    }
+
+    #[test]
+    fn metrics_notification_test() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let mut vehicle = AnkiVehicleData::default();
+            vehicle.set_name("Skull".to_string());
+            let delocalized = [1, AnkiVehicleMsgType::V2CVehicleDelocalized as u8];
+            vehicle.process_notification(&delocalized).unwrap();
+            vehicle.process_notification(&delocalized).unwrap();
+            assert!(vehicle.process_notification(&[]).is_err());
+        });
+        let snapshot = snapshot(&snapshotter);
+
+        assert_eq!(
+            vec![(
+                vec!["msg_type=V2CVehicleDelocalized".to_string()],
+                &DebugValue::Counter(2)
+            )],
+            value(&snapshot, MESSAGES_DECODED)
+        );
+        assert_eq!(
+            vec![(vec!["vehicle=Skull".to_string()], &DebugValue::Counter(2))],
+            value(&snapshot, DELOCALIZATIONS)
+        );
+        assert_eq!(
+            vec![(Vec::new(), &DebugValue::Counter(1))],
+            value(&snapshot, DECODE_ERRORS)
+        );
+    }
+
+    #[test]
+    fn metered_host_test() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let host = MeteredHost::new(TestHost);
+            host.connect("skull").unwrap();
+            host.connect("skull").unwrap();
+            host.connect("nuke").unwrap();
+            host.send("skull", AnkiVehicleData::set_speed(500, 1000).into())
+                .unwrap();
+            assert!(host.send("nuke", Vec::new()).is_err());
+        });
+        let snapshot = snapshot(&snapshotter);
+
+        assert_eq!(
+            vec![(vec!["vehicle=skull".to_string()], &DebugValue::Counter(1))],
+            value(&snapshot, RECONNECTS)
+        );
+        assert_eq!(
+            vec![(vec!["vehicle=nuke".to_string()], &DebugValue::Counter(1))],
+            value(&snapshot, WRITE_ERRORS)
+        );
+        let mut latency = value(&snapshot, WRITE_LATENCY);
+        latency.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(2, latency.len());
+        assert!(matches!(latency[1].1, DebugValue::Histogram(v) if v.len() == 1));
+    }
+}
@@ -0,0 +1,258 @@
+use heapless::{Deque, Vec};
+use scroll::{ctx, Pwrite};
+use std::fmt;
+
+use crate::command::CONFIGURE_COMMAND_COUNT;
+use crate::error::AnkiError;
+use crate::protocol::{
+    anki_vehicle_msg_change_lane, anki_vehicle_msg_get_battery_level, anki_vehicle_msg_get_version,
+    anki_vehicle_msg_set_offset_from_road_centre, anki_vehicle_msg_set_sdk_mode,
+    ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE, ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+    ANKI_VEHICLE_MSG_MAX_SIZE, ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+    ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE, ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE,
+    ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION, WIRE_ENDIAN,
+};
+
+// Fixed capacity counterparts of the `Vec<u8>` frames used elsewhere, for relays running on a
+// microcontroller where every buffer has to be sized up front. Nothing in here allocates.
+
+// One encoded message, a frame can never be longer than the protocol allows.
+pub type Frame = Vec<u8, ANKI_VEHICLE_MSG_MAX_SIZE>;
+
+pub const CONFIGURE_FRAME_COUNT: usize = CONFIGURE_COMMAND_COUNT;
+
+#[derive(Debug)]
+pub enum FrameError {
+    Encode(AnkiError),
+    Full,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Encode(e) => write!(f, "Failed to encode frame: {}", e),
+            FrameError::Full => write!(f, "Frame buffer is full"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+// AnkiError has no defmt support, it is logged through its Debug impl.
+#[cfg(feature = "defmt")]
+impl defmt::Format for FrameError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            FrameError::Encode(e) => {
+                defmt::write!(f, "Failed to encode frame: {}", defmt::Debug2Format(e))
+            }
+            FrameError::Full => defmt::write!(f, "Frame buffer is full"),
+        }
+    }
+}
+
+impl From<AnkiError> for FrameError {
+    fn from(e: AnkiError) -> Self {
+        FrameError::Encode(e)
+    }
+}
+
+// `size` is the encoded size of the message, one of the ANKI_VEHICLE_MSG_*_SIZE constants.
+pub fn encode_frame<T>(msg: T, size: usize) -> Result<Frame, FrameError>
+where
+    T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+{
+    let mut data = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+    if size > data.len() {
+        return Err(FrameError::Full);
+    }
+    let offset = data[..size].pwrite_with::<T>(msg, 0, WIRE_ENDIAN)?;
+    Frame::from_slice(&data[..offset]).map_err(|_| FrameError::Full)
+}
+
+// Frames meant to be written together, in order.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FrameBatch<const N: usize> {
+    frames: Vec<Frame, N>,
+}
+
+impl<const N: usize> FrameBatch<N> {
+    pub fn new() -> FrameBatch<N> {
+        FrameBatch { frames: Vec::new() }
+    }
+
+    pub fn push(&mut self, frame: Frame) -> Result<(), FrameError> {
+        self.frames.push(frame).map_err(|_| FrameError::Full)
+    }
+
+    pub fn push_msg<T>(&mut self, msg: T, size: usize) -> Result<(), FrameError>
+    where
+        T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+    {
+        if self.frames.is_full() {
+            return Err(FrameError::Full);
+        }
+        self.push(encode_frame(msg, size)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl FrameBatch<CONFIGURE_FRAME_COUNT> {
+    // Same sequence as `AnkiVehicleData::configure`.
+    pub fn configure() -> Result<FrameBatch<CONFIGURE_FRAME_COUNT>, FrameError> {
+        let mut batch = FrameBatch::new();
+        batch.push_msg(
+            anki_vehicle_msg_set_sdk_mode(1, ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION),
+            ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+        )?;
+        batch.push_msg(
+            anki_vehicle_msg_get_version(),
+            ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE,
+        )?;
+        batch.push_msg(
+            anki_vehicle_msg_get_battery_level(),
+            ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE,
+        )?;
+        batch.push_msg(
+            anki_vehicle_msg_set_offset_from_road_centre(0.0),
+            ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+        )?;
+        batch.push_msg(
+            anki_vehicle_msg_change_lane(300, 2500, 0.0),
+            ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+        )?;
+        Ok(batch)
+    }
+}
+
+// Frames waiting for the radio, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct FrameQueue<const N: usize> {
+    frames: Deque<Frame, N>,
+}
+
+impl<const N: usize> FrameQueue<N> {
+    pub fn new() -> FrameQueue<N> {
+        FrameQueue {
+            frames: Deque::new(),
+        }
+    }
+
+    pub fn push(&mut self, frame: Frame) -> Result<(), FrameError> {
+        self.frames.push_back(frame).map_err(|_| FrameError::Full)
+    }
+
+    // Either the whole batch is queued or none of it, so a vehicle never sees half a sequence.
+    pub fn push_batch<const M: usize>(&mut self, batch: &FrameBatch<M>) -> Result<(), FrameError> {
+        if self.frames.capacity() - self.frames.len() < batch.len() {
+            return Err(FrameError::Full);
+        }
+        for frame in batch.iter() {
+            self.push(frame.clone())?;
+        }
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Frame> {
+        self.frames.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frames.is_full()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::protocol::{anki_vehicle_msg_set_speed, ANKI_VEHICLE_MSG_SET_SPEED_SIZE};
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn frame_batch_configure_test() {
+        let batch = FrameBatch::configure().unwrap();
+        let expected = AnkiVehicleData::default().configure();
+        assert_eq!(expected.len(), batch.len());
+        for (frame, expected) in batch.iter().zip(expected.iter()) {
+            assert_eq!(expected.encode().as_slice(), frame.as_slice());
+        }
+    }
+
+    #[test]
+    fn frame_batch_full_test() {
+        let mut batch = FrameBatch::<1>::new();
+        batch
+            .push_msg(
+                anki_vehicle_msg_set_speed(500, 1000),
+                ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+            )
+            .unwrap();
+        assert_eq!(
+            AnkiVehicleData::set_speed(500, 1000).as_slice(),
+            batch.iter().next().unwrap().as_slice()
+        );
+        assert!(matches!(
+            batch.push_msg(
+                anki_vehicle_msg_get_version(),
+                ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE
+            ),
+            Err(FrameError::Full)
+        ));
+        assert!(matches!(
+            encode_frame(
+                anki_vehicle_msg_get_version(),
+                ANKI_VEHICLE_MSG_SET_SPEED_SIZE
+            ),
+            Err(FrameError::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn frame_queue_test() {
+        let mut queue = FrameQueue::<6>::new();
+        let speed = encode_frame(
+            anki_vehicle_msg_set_speed(500, 1000),
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        )
+        .unwrap();
+        queue.push(speed.clone()).unwrap();
+
+        let batch = FrameBatch::configure().unwrap();
+        queue.push_batch(&batch).unwrap();
+        assert!(queue.is_full());
+        assert!(matches!(queue.push_batch(&batch), Err(FrameError::Full)));
+
+        assert_eq!(Some(speed), queue.pop());
+        assert!(matches!(queue.push_batch(&batch), Err(FrameError::Full)));
+        assert_eq!(batch.iter().next().cloned(), queue.pop());
+        assert_eq!(4, queue.len());
+    }
+}
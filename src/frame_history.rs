@@ -0,0 +1,268 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::clock::{Clock, SystemClock};
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::replay::{ReplayError, ReplayRecord, ReplayWriter};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_id::VehicleId;
+
+// The last few frames to and from each vehicle, for working out what led up to a problem
+// without logging everything all the time. Frames are kept as replay records so a dump can be
+// written out and played back like any other recording.
+
+pub const FRAME_HISTORY_DEFAULT_CAPACITY: usize = 64;
+
+pub struct FrameHistory {
+    capacity: usize,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    vehicles: HashMap<VehicleId, VecDeque<ReplayRecord>>,
+}
+
+impl Default for FrameHistory {
+    fn default() -> Self {
+        FrameHistory::new(FRAME_HISTORY_DEFAULT_CAPACITY)
+    }
+}
+
+impl FrameHistory {
+    // Keeps up to `capacity` frames per vehicle, at least one.
+    pub fn new(capacity: usize) -> FrameHistory {
+        FrameHistory {
+            capacity: capacity.max(1),
+            clock: Arc::new(SystemClock),
+            started: Instant::now(),
+            vehicles: HashMap::new(),
+        }
+    }
+
+    // Timestamps are taken from this clock, counting from when it is set.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> FrameHistory {
+        self.started = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn record_command(&mut self, vehicle: &str, frame: &[u8]) {
+        let record = ReplayRecord::command(self.elapsed_ms(), vehicle, frame);
+        self.push(record);
+    }
+
+    pub fn record_notification(&mut self, vehicle: &str, frame: &[u8]) {
+        let record = ReplayRecord::notification(self.elapsed_ms(), vehicle, frame);
+        self.push(record);
+    }
+
+    // Oldest first.
+    pub fn frames(&self, vehicle: &str) -> impl Iterator<Item = &ReplayRecord> {
+        self.vehicles.get(vehicle).into_iter().flatten()
+    }
+
+    pub fn dump(&self, vehicle: &str) -> Vec<ReplayRecord> {
+        self.frames(vehicle).cloned().collect()
+    }
+
+    // Every vehicle's frames merged in time order, returning how many were written.
+    pub fn dump_to<W: Write>(&self, writer: &mut ReplayWriter<W>) -> Result<usize, ReplayError> {
+        let mut records: Vec<&ReplayRecord> = self.vehicles.values().flatten().collect();
+        records.sort_by_key(|record| record.timestamp_ms);
+        for record in &records {
+            writer.write(record)?;
+        }
+        Ok(records.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.vehicles.clear();
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.clock.now().duration_since(self.started).as_millis() as u64
+    }
+
+    fn push(&mut self, record: ReplayRecord) {
+        let frames = self.vehicles.entry(record.vehicle.clone()).or_default();
+        if frames.len() == self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(record);
+    }
+}
+
+type ErrorHandler = Box<dyn Fn(&str, &HostError, &[ReplayRecord]) + Send + Sync>;
+
+// Taps a host, keeping the recent frames of every vehicle in a `FrameHistory`. Commands are kept
+// whether or not they made it to the vehicle, and a failed send is reported to the error
+// handler, if there is one, along with the frames leading up to it.
+pub struct HistoryHost<H> {
+    host: Arc<H>,
+    history: Arc<Mutex<FrameHistory>>,
+    subscribers: Arc<Mutex<Vec<Sender<HostNotification>>>>,
+    on_error: Option<ErrorHandler>,
+}
+
+impl<H: FleetHost> HistoryHost<H> {
+    pub fn new(host: Arc<H>, history: FrameHistory) -> HistoryHost<H> {
+        let history = Arc::new(Mutex::new(history));
+        let subscribers: Arc<Mutex<Vec<Sender<HostNotification>>>> = Arc::default();
+
+        let notifications = host.subscribe();
+        let tap = (Arc::clone(&history), Arc::clone(&subscribers));
+        thread::spawn(move || {
+            let (history, subscribers) = tap;
+            for notification in notifications {
+                history
+                    .lock()
+                    .unwrap()
+                    .record_notification(&notification.vehicle, &notification.data);
+                subscribers
+                    .lock()
+                    .unwrap()
+                    .retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+            }
+        });
+
+        HistoryHost {
+            host,
+            history,
+            subscribers,
+            on_error: None,
+        }
+    }
+
+    pub fn with_error_handler<F>(mut self, handler: F) -> HistoryHost<H>
+    where
+        F: Fn(&str, &HostError, &[ReplayRecord]) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Box::new(handler));
+        self
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.host
+    }
+
+    pub fn dump(&self, vehicle: &str) -> Vec<ReplayRecord> {
+        self.history.lock().unwrap().dump(vehicle)
+    }
+
+    pub fn dump_to<W: Write>(&self, writer: &mut ReplayWriter<W>) -> Result<usize, ReplayError> {
+        self.history.lock().unwrap().dump_to(writer)
+    }
+}
+
+impl<H: FleetHost> FleetHost for HistoryHost<H> {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+        self.host.discover()
+    }
+
+    fn vehicles(&self) -> Vec<HostVehicle> {
+        self.host.vehicles()
+    }
+
+    fn connect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.connect(vehicle)
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.disconnect(vehicle)
+    }
+
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+        self.history.lock().unwrap().record_command(vehicle, &data);
+        let result = self.host.send(vehicle, data);
+        if let Err(e) = &result {
+            let frames = self.dump(vehicle);
+            trace_event!(target: TARGET_TRANSPORT, warn, error = %e, vehicle, frames = frames.len(), "Send failed");
+            if let Some(on_error) = &self.on_error {
+                on_error(vehicle, e, &frames);
+            }
+        }
+        result
+    }
+
+    fn subscribe(&self) -> Receiver<HostNotification> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::replay::{ReplayDirection, ReplayReader};
+    use crate::sim::host::SimulatedHost;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_history_test() {
+        let clock = Arc::new(VirtualClock::new());
+        let mut history = FrameHistory::new(3).with_clock(clock.clone());
+        for i in 0..5u8 {
+            clock.advance(Duration::from_millis(10));
+            history.record_command("skull", &[i]);
+        }
+        clock.advance(Duration::from_millis(5));
+        history.record_notification("nuke", &[0xff]);
+
+        let frames = history.dump("skull");
+        assert_eq!(
+            vec![vec![2], vec![3], vec![4]],
+            frames.iter().map(|r| r.frame.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(30, frames[0].timestamp_ms);
+        assert_eq!(0, history.frames("grip").count());
+
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        assert_eq!(4, history.dump_to(&mut writer).unwrap());
+        let reader = ReplayReader::new(Cursor::new(writer.into_inner().unwrap())).unwrap();
+        let records: Vec<ReplayRecord> = reader.map(Result::unwrap).collect();
+        assert_eq!("nuke", records[3].vehicle);
+        assert_eq!(ReplayDirection::Notification, records[3].direction);
+    }
+
+    #[test]
+    fn history_host_test() {
+        let sim =
+            Arc::new(SimulatedHost::new().with_vehicle("skull", "Skull", SimulatedVehicle::new()));
+        let failures: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let reported = Arc::clone(&failures);
+        let host = HistoryHost::new(Arc::clone(&sim), FrameHistory::new(8))
+            .with_error_handler(move |_, _, frames| reported.lock().unwrap().push(frames.len()));
+        let notifications = host.subscribe();
+
+        // Not connected yet, the command is still kept.
+        let speed: Vec<u8> = AnkiVehicleData::set_speed(500, 0).into();
+        assert!(host.send("skull", speed.clone()).is_err());
+        assert_eq!(vec![1], *failures.lock().unwrap());
+
+        host.connect("skull").unwrap();
+        for command in AnkiVehicleData::default().configure() {
+            host.send("skull", command.into()).unwrap();
+        }
+        host.send("skull", speed).unwrap();
+        sim.step(Duration::from_secs(1));
+        while notifications
+            .recv_timeout(Duration::from_millis(100))
+            .is_ok()
+        {}
+
+        let frames = host.dump("skull");
+        assert_eq!(8, frames.len());
+        assert_eq!(ReplayDirection::Notification, frames[7].direction);
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::AnkiVehicleMsgType;
+
+// Parts of the vehicle state that are updated together, by one kind of notification each.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StateGroup {
+    // Speed, offset and road piece, from position, transition and offset updates.
+    Position,
+    Battery,
+    Version,
+    Intersection,
+}
+
+impl StateGroup {
+    // The group a notification updates, None for notifications that carry no state.
+    pub fn of(msg_id: &AnkiVehicleMsgType) -> Option<StateGroup> {
+        match msg_id {
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate
+            | AnkiVehicleMsgType::V2CLocalisationTransitionUpdate
+            | AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => Some(StateGroup::Position),
+            AnkiVehicleMsgType::V2CBatteryLevelResponse | AnkiVehicleMsgType::V2CChargerInfo => {
+                Some(StateGroup::Battery)
+            }
+            AnkiVehicleMsgType::V2CVersionResponse => Some(StateGroup::Version),
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => Some(StateGroup::Intersection),
+            _ => None,
+        }
+    }
+}
+
+// When each state group was last updated. A vehicle keeps its last values through a gap in the
+// notifications, this tells a value that is still current from one that is only the last known.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Freshness {
+    position: Option<Instant>,
+    battery: Option<Instant>,
+    version: Option<Instant>,
+    intersection: Option<Instant>,
+}
+
+impl Freshness {
+    pub fn touch(&mut self, group: StateGroup, at: Instant) {
+        *self.slot(group) = Some(at);
+    }
+
+    // None if the group has never been updated.
+    pub fn updated_at(&self, group: StateGroup) -> Option<Instant> {
+        match group {
+            StateGroup::Position => self.position,
+            StateGroup::Battery => self.battery,
+            StateGroup::Version => self.version,
+            StateGroup::Intersection => self.intersection,
+        }
+    }
+
+    pub fn age(&self, group: StateGroup, now: Instant) -> Option<Duration> {
+        self.updated_at(group)
+            .map(|at| now.saturating_duration_since(at))
+    }
+
+    // Never updated counts as stale.
+    pub fn is_stale(&self, group: StateGroup, now: Instant, max_age: Duration) -> bool {
+        self.age(group, now).is_none_or(|age| age > max_age)
+    }
+
+    fn slot(&mut self, group: StateGroup) -> &mut Option<Instant> {
+        match group {
+            StateGroup::Position => &mut self.position,
+            StateGroup::Battery => &mut self.battery,
+            StateGroup::Version => &mut self.version,
+            StateGroup::Intersection => &mut self.intersection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn freshness_test() {
+        let t0 = Instant::now();
+        let mut vehicle = AnkiVehicleData::default();
+        let mut sim = SimulatedVehicle::new();
+        for command in vehicle.configure() {
+            sim.handle_command(&command.encode()).unwrap();
+        }
+        sim.drain_notifications().for_each(drop);
+        sim.handle_command(&AnkiVehicleData::set_speed(500, 1000))
+            .unwrap();
+        let battery = [
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse.into(),
+            0x10,
+            0x0e,
+        ];
+        vehicle.process_notification_at(&battery, t0).unwrap();
+        for step in 1..=100 {
+            sim.advance(Duration::from_millis(10));
+            let at = t0 + Duration::from_millis(step * 10);
+            for frame in sim.drain_notifications() {
+                vehicle.process_notification_at(&frame, at).unwrap();
+            }
+        }
+
+        // Position updates come every location code, the last one a little before the end.
+        let now = t0 + Duration::from_millis(1500);
+        let freshness = vehicle.freshness();
+        assert_eq!(Some(t0), freshness.updated_at(StateGroup::Battery));
+        assert_eq!(
+            Some(Duration::from_millis(1500)),
+            freshness.age(StateGroup::Battery, now)
+        );
+        assert!(freshness.age(StateGroup::Position, now).unwrap() <= Duration::from_secs(1));
+        assert!(!freshness.is_stale(StateGroup::Position, now, Duration::from_secs(1)));
+        assert!(freshness.is_stale(StateGroup::Battery, now, Duration::from_secs(1)));
+        assert!(freshness.is_stale(StateGroup::Version, now, Duration::from_secs(1)));
+        assert_eq!(None, vehicle.age(StateGroup::Version));
+    }
+}
@@ -0,0 +1,350 @@
+use scroll::Pread;
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgType, WIRE_ENDIAN,
+};
+use crate::AnkiVehicleData;
+
+pub mod proto {
+    tonic::include_proto!("anki_drive");
+}
+
+use proto::telemetry::Update;
+use proto::vehicle_control_server::{VehicleControl, VehicleControlServer};
+
+const TELEMETRY_BUFFER: usize = 64;
+
+impl From<HostError> for Status {
+    fn from(e: HostError) -> Self {
+        match e {
+            HostError::UnknownVehicle(_) => Status::not_found(e.to_string()),
+            HostError::NotConnected(_) => Status::failed_precondition(e.to_string()),
+            HostError::Transport(_) => Status::unavailable(e.to_string()),
+        }
+    }
+}
+
+impl From<HostVehicle> for proto::Vehicle {
+    fn from(vehicle: HostVehicle) -> Self {
+        proto::Vehicle {
+            id: vehicle.id.into(),
+            name: vehicle.name,
+            connected: vehicle.connected,
+        }
+    }
+}
+
+fn vehicle_list(vehicles: Vec<HostVehicle>) -> proto::VehicleList {
+    proto::VehicleList {
+        vehicles: vehicles.into_iter().map(Into::into).collect(),
+    }
+}
+
+// Decodes the notifications clients most likely care about, anything else is only sent raw.
+fn telemetry(notification: HostNotification) -> proto::Telemetry {
+    let data = notification.data.as_slice();
+    let msg_id = data
+        .pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)
+        .map_or(AnkiVehicleMsgType::Unknown, |msg| msg.msg_id);
+    let update = match msg_id {
+        AnkiVehicleMsgType::V2CLocalisationPositionUpdate => data
+            .pread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(0, WIRE_ENDIAN)
+            .ok()
+            .map(|msg| {
+                Update::Position(proto::PositionUpdate {
+                    location_id: msg.location_id.into(),
+                    road_piece_id: msg.road_piece_id.into(),
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    speed_mm_per_sec: msg.speed_mm_per_sec.into(),
+                    parsing_flags: msg.parsing_flags.bits().into(),
+                })
+            }),
+        AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => data
+            .pread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(0, WIRE_ENDIAN)
+            .ok()
+            .map(|msg| {
+                Update::Transition(proto::TransitionUpdate {
+                    road_piece_idx: msg.road_piece_idx.into(),
+                    road_piece_idx_prev: msg.road_piece_idx_prev.into(),
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    left_wheel_dist_cm: msg.left_wheel_dist_cm.into(),
+                    right_wheel_dist_cm: msg.right_wheel_dist_cm.into(),
+                })
+            }),
+        AnkiVehicleMsgType::V2CBatteryLevelResponse => data
+            .pread_with::<AnkiVehicleMsgBatteryLevelResponse>(0, WIRE_ENDIAN)
+            .ok()
+            .map(|msg| Update::BatteryLevel(msg.battery_level.into())),
+        AnkiVehicleMsgType::V2CVehicleDelocalized => Some(Update::Delocalized(true)),
+        _ => None,
+    };
+    proto::Telemetry {
+        vehicle: notification.vehicle.into(),
+        msg_type: u8::from(msg_id).into(),
+        data: notification.data,
+        update,
+    }
+}
+
+pub struct VehicleControlService<H: FleetHost> {
+    host: Arc<H>,
+}
+
+impl<H: FleetHost> VehicleControlService<H> {
+    pub fn new(host: Arc<H>) -> VehicleControlService<H> {
+        VehicleControlService { host }
+    }
+
+    // Ready to be added to a `tonic::transport::Server`.
+    pub fn into_server(self) -> VehicleControlServer<Self> {
+        VehicleControlServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<H: FleetHost> VehicleControl for VehicleControlService<H> {
+    async fn discover(
+        &self,
+        _request: Request<proto::DiscoverRequest>,
+    ) -> Result<Response<proto::VehicleList>, Status> {
+        Ok(Response::new(vehicle_list(self.host.discover()?)))
+    }
+
+    async fn list_vehicles(
+        &self,
+        _request: Request<proto::ListVehiclesRequest>,
+    ) -> Result<Response<proto::VehicleList>, Status> {
+        Ok(Response::new(vehicle_list(self.host.vehicles())))
+    }
+
+    async fn connect_vehicle(
+        &self,
+        request: Request<proto::VehicleRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        self.host.connect(&request.into_inner().vehicle)?;
+        Ok(Response::new(proto::CommandReply {}))
+    }
+
+    async fn disconnect_vehicle(
+        &self,
+        request: Request<proto::VehicleRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        self.host.disconnect(&request.into_inner().vehicle)?;
+        Ok(Response::new(proto::CommandReply {}))
+    }
+
+    async fn set_speed(
+        &self,
+        request: Request<proto::SetSpeedRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        let (Ok(speed), Ok(accel)) = (
+            i16::try_from(request.speed_mm_per_sec),
+            i16::try_from(request.accel_mm_per_sec2),
+        ) else {
+            return Err(Status::invalid_argument(
+                "Speed or acceleration out of range",
+            ));
+        };
+        self.host.send(
+            &request.vehicle,
+            AnkiVehicleData::set_speed(speed, accel).into(),
+        )?;
+        Ok(Response::new(proto::CommandReply {}))
+    }
+
+    async fn change_lane(
+        &self,
+        request: Request<proto::ChangeLaneRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        let (Ok(speed), Ok(accel)) = (
+            u16::try_from(request.horizontal_speed_mm_per_sec),
+            u16::try_from(request.horizontal_accel_mm_per_sec2),
+        ) else {
+            return Err(Status::invalid_argument(
+                "Speed or acceleration out of range",
+            ));
+        };
+        self.host.send(
+            &request.vehicle,
+            AnkiVehicleData::change_lane(speed, accel, request.offset_from_road_centre_mm).into(),
+        )?;
+        Ok(Response::new(proto::CommandReply {}))
+    }
+
+    async fn send_raw(
+        &self,
+        request: Request<proto::RawCommand>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        self.host.send(&request.vehicle, request.data)?;
+        Ok(Response::new(proto::CommandReply {}))
+    }
+
+    type StreamTelemetryStream = ReceiverStream<Result<proto::Telemetry, Status>>;
+
+    async fn stream_telemetry(
+        &self,
+        request: Request<proto::TelemetryRequest>,
+    ) -> Result<Response<Self::StreamTelemetryStream>, Status> {
+        let vehicles = request.into_inner().vehicles;
+        let notifications = self.host.subscribe();
+        let (tx, rx) = mpsc::channel(TELEMETRY_BUFFER);
+
+        // The host hands out a blocking receiver, forward it until the client goes away.
+        thread::spawn(move || {
+            for notification in notifications {
+                if !vehicles.is_empty() && !vehicles.iter().any(|v| notification.vehicle == *v) {
+                    continue;
+                }
+                if tx.blocking_send(Ok(telemetry(notification))).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::Mutex;
+    use tokio_stream::StreamExt;
+
+    #[derive(Default)]
+    struct TestHost {
+        sent: Mutex<Vec<(String, Vec<u8>)>>,
+        subscribers: Mutex<Vec<Sender<HostNotification>>>,
+    }
+
+    impl FleetHost for TestHost {
+        fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+            Ok(self.vehicles())
+        }
+
+        fn vehicles(&self) -> Vec<HostVehicle> {
+            vec![HostVehicle {
+                id: "skull".into(),
+                name: "Skull".to_string(),
+                connected: true,
+            }]
+        }
+
+        fn connect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn disconnect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+            if vehicle != "skull" {
+                return Err(HostError::UnknownVehicle(vehicle.to_string()));
+            }
+            self.sent.lock().unwrap().push((vehicle.to_string(), data));
+            Ok(())
+        }
+
+        fn subscribe(&self) -> Receiver<HostNotification> {
+            let (tx, rx) = channel();
+            self.subscribers.lock().unwrap().push(tx);
+            rx
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn grpc_drive_test() {
+        let host = Arc::new(TestHost::default());
+        let service = VehicleControlService::new(Arc::clone(&host));
+        runtime().block_on(async {
+            service
+                .set_speed(Request::new(proto::SetSpeedRequest {
+                    vehicle: "skull".to_string(),
+                    speed_mm_per_sec: 500,
+                    accel_mm_per_sec2: 1000,
+                }))
+                .await
+                .unwrap();
+            let status = service
+                .set_speed(Request::new(proto::SetSpeedRequest {
+                    vehicle: "nuke".to_string(),
+                    speed_mm_per_sec: 500,
+                    accel_mm_per_sec2: 1000,
+                }))
+                .await
+                .unwrap_err();
+            assert_eq!(tonic::Code::NotFound, status.code());
+            let status = service
+                .set_speed(Request::new(proto::SetSpeedRequest {
+                    vehicle: "skull".to_string(),
+                    speed_mm_per_sec: 50000,
+                    accel_mm_per_sec2: 1000,
+                }))
+                .await
+                .unwrap_err();
+            assert_eq!(tonic::Code::InvalidArgument, status.code());
+        });
+        assert_eq!(
+            vec![(
+                "skull".to_string(),
+                AnkiVehicleData::set_speed(500, 1000).to_vec()
+            )],
+            *host.sent.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn grpc_stream_telemetry_test() {
+        let host = Arc::new(TestHost::default());
+        let service = VehicleControlService::new(Arc::clone(&host));
+        runtime().block_on(async {
+            let mut stream = service
+                .stream_telemetry(Request::new(proto::TelemetryRequest {
+                    vehicles: vec!["skull".to_string()],
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            for subscriber in host.subscribers.lock().unwrap().iter() {
+                for vehicle in ["nuke", "skull"] {
+                    subscriber
+                        .send(HostNotification {
+                            vehicle: vehicle.into(),
+                            data: vec![
+                                3,
+                                AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+                                0x10,
+                                0x0E,
+                            ],
+                        })
+                        .unwrap();
+                }
+            }
+            let telemetry = stream.next().await.unwrap().unwrap();
+            assert_eq!("skull", telemetry.vehicle);
+            assert_eq!(
+                AnkiVehicleMsgType::V2CBatteryLevelResponse as u32,
+                telemetry.msg_type
+            );
+            assert_eq!(Some(Update::BatteryLevel(0x0E10)), telemetry.update);
+        });
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use std::thread;
+
+use crate::telemetry_channel::{bounded_channel, BoundedReceiver, DropPolicy};
+use crate::vehicle_id::VehicleId;
+
+// Implemented by whatever owns the BLE connections, so the remote control front-ends (gRPC,
+// REST, ...) don't need to know how vehicles are found or talked to. Calls are made from the
+// front-end's worker threads and should return quickly.
+pub trait FleetHost: Send + Sync + 'static {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError>;
+
+    fn vehicles(&self) -> Vec<HostVehicle>;
+
+    fn connect(&self, vehicle: &str) -> Result<(), HostError>;
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError>;
+
+    // Writes an encoded command frame to the vehicle.
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError>;
+
+    // Every notification received from any connected vehicle, from now on.
+    fn subscribe(&self) -> Receiver<HostNotification>;
+
+    // Like `subscribe`, but no more than `capacity` notifications are held for a subscriber that
+    // falls behind. Hosts that fan notifications out themselves should override this, the
+    // default forwards from `subscribe` on a thread of its own.
+    fn subscribe_bounded(&self, capacity: usize, policy: DropPolicy) -> BoundedReceiver {
+        let (tx, rx) = bounded_channel(capacity, policy);
+        let notifications = self.subscribe();
+        thread::spawn(move || {
+            for notification in notifications {
+                if tx.send(notification).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HostVehicle {
+    pub id: VehicleId,
+    pub name: String,
+    pub connected: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HostNotification {
+    pub vehicle: VehicleId,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostError {
+    UnknownVehicle(String),
+    NotConnected(String),
+    Transport(String),
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostError::UnknownVehicle(vehicle) => write!(f, "Unknown vehicle {}", vehicle),
+            HostError::NotConnected(vehicle) => write!(f, "Vehicle {} is not connected", vehicle),
+            HostError::Transport(e) => write!(f, "Transport error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HostError {}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::command::{Command, WireMessage};
+use crate::io::transport::VehicleTransport;
+use crate::protocol::AnkiVehicleMsgType;
+
+// Vehicles drop writes that arrive faster than they handle them, this much spacing keeps up
+// with every firmware seen so far.
+pub const COMMAND_QUEUE_DEFAULT_INTERVAL: Duration = Duration::from_millis(50);
+
+// Commands that set a value outright, a newer one makes any still queued pointless.
+fn coalesces(msg_id: AnkiVehicleMsgType) -> bool {
+    matches!(
+        msg_id,
+        AnkiVehicleMsgType::C2VSetSpeed
+            | AnkiVehicleMsgType::C2VChangeLane
+            | AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre
+            | AnkiVehicleMsgType::C2VLightsPattern
+            | AnkiVehicleMsgType::C2VSetConfigParams
+    )
+}
+
+// Holds encoded commands for one vehicle and lets them out no closer together than the minimum
+// interval. A command that sets a value replaces a queued one of the same type where it stands,
+// so a burst of speed changes goes out as the last of them.
+#[derive(Debug, Clone)]
+pub struct CommandQueue {
+    min_interval: Duration,
+    queue: VecDeque<Command>,
+    last_sent_at: Option<Instant>,
+    coalesced: u64,
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandQueue {
+    pub fn new() -> CommandQueue {
+        CommandQueue {
+            min_interval: COMMAND_QUEUE_DEFAULT_INTERVAL,
+            queue: VecDeque::new(),
+            last_sent_at: None,
+            coalesced: 0,
+        }
+    }
+
+    pub fn with_min_interval(mut self, min_interval: Duration) -> CommandQueue {
+        self.min_interval = min_interval;
+        self
+    }
+
+    pub fn push(&mut self, command: impl WireMessage) {
+        let msg_id = command.msg_type();
+        let command = command.encode();
+        if coalesces(msg_id.clone()) {
+            if let Some(queued) = self
+                .queue
+                .iter_mut()
+                .find(|queued| queued.msg_type() == msg_id)
+            {
+                *queued = command;
+                self.coalesced += 1;
+                return;
+            }
+        }
+        self.queue.push_back(command);
+    }
+
+    // When the next command may go, None with nothing queued.
+    pub fn next_due_at(&self, now: Instant) -> Option<Instant> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        Some(
+            self.last_sent_at
+                .map_or(now, |sent_at| (sent_at + self.min_interval).max(now)),
+        )
+    }
+
+    // The next command if the interval since the last one has passed. The caller is taken to
+    // send it at `now`.
+    pub fn pop_due(&mut self, now: Instant) -> Option<Command> {
+        if self.next_due_at(now)? > now {
+            return None;
+        }
+        self.last_sent_at = Some(now);
+        self.queue.pop_front()
+    }
+
+    // Sends the next command if it's due, returns whether one went out. A command the transport
+    // turned down stays at the front of the queue for the next try.
+    pub fn send_due<T: VehicleTransport>(
+        &mut self,
+        now: Instant,
+        transport: &mut T,
+    ) -> Result<bool, T::Error> {
+        let Some(command) = self.pop_due(now) else {
+            return Ok(false);
+        };
+        if let Err(e) = transport.send(&command) {
+            self.queue.push_front(command);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    // Waits on the clock until the next command is due, None straight away with nothing queued.
+    pub fn pop_blocking(&mut self, clock: &dyn Clock) -> Option<Command> {
+        let now = clock.now();
+        let due_at = self.next_due_at(now)?;
+        if due_at > now {
+            clock.sleep(due_at - now);
+        }
+        self.pop_due(due_at.max(clock.now()))
+    }
+
+    // Same as `pop_blocking` for async code, `sleep` is the runtime's sleep, such as
+    // `tokio::time::sleep`.
+    pub async fn pop_async<F, Fut>(&mut self, sleep: F) -> Option<Command>
+    where
+        F: FnOnce(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let now = Instant::now();
+        let due_at = self.next_due_at(now)?;
+        if due_at > now {
+            sleep(due_at - now).await;
+        }
+        self.pop_due(due_at.max(Instant::now()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Commands replaced by a newer one of the same type before they went out.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    // Drops everything queued, the interval still counts from the last command sent.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::command::VehicleCommand;
+    use crate::io::transport::MockTransport;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn command_queue_test() {
+        let t0 = Instant::now();
+        let mut queue = CommandQueue::new().with_min_interval(Duration::from_millis(40));
+        queue.push(AnkiVehicleData::set_speed(300, 1000));
+        queue.push(VehicleCommand::VersionRequest);
+        queue.push(AnkiVehicleData::set_speed(600, 1000));
+        assert_eq!(2, queue.len());
+        assert_eq!(1, queue.coalesced());
+
+        assert_eq!(
+            Some(AnkiVehicleData::set_speed(600, 1000)),
+            queue.pop_due(t0)
+        );
+        assert_eq!(None, queue.pop_due(t0 + Duration::from_millis(39)));
+        assert_eq!(
+            Some(t0 + Duration::from_millis(40)),
+            queue.next_due_at(t0 + Duration::from_millis(10))
+        );
+        assert_eq!(
+            Some(Command::VERSION_REQUEST),
+            queue.pop_due(t0 + Duration::from_millis(40))
+        );
+        assert_eq!(None, queue.next_due_at(t0 + Duration::from_millis(40)));
+    }
+
+    #[test]
+    fn command_queue_blocking_test() {
+        let clock = VirtualClock::new();
+        let mut queue = CommandQueue::new();
+        queue.push(Command::PING);
+        queue.push(Command::PING);
+        let start = clock.now();
+        assert_eq!(Some(Command::PING), queue.pop_blocking(&clock));
+        assert_eq!(Some(Command::PING), queue.pop_blocking(&clock));
+        assert_eq!(COMMAND_QUEUE_DEFAULT_INTERVAL, clock.now() - start);
+        assert_eq!(None, queue.pop_blocking(&clock));
+    }
+
+    #[test]
+    fn command_queue_send_due_test() {
+        let t0 = Instant::now();
+        let mut queue = CommandQueue::new();
+        let mut transport = MockTransport::new();
+        queue.push(Command::PING);
+        queue.push(Command::BATTERY_LEVEL_REQUEST);
+        assert!(queue.send_due(t0, &mut transport).unwrap());
+        assert!(!queue.send_due(t0, &mut transport).unwrap());
+
+        transport.disconnect();
+        let t1 = t0 + COMMAND_QUEUE_DEFAULT_INTERVAL;
+        assert!(queue.send_due(t1, &mut transport).is_err());
+        assert_eq!(1, queue.len());
+        transport.reconnect();
+        assert!(queue
+            .send_due(t1 + COMMAND_QUEUE_DEFAULT_INTERVAL, &mut transport)
+            .unwrap());
+        assert_eq!(
+            vec![
+                Command::PING.to_vec(),
+                Command::BATTERY_LEVEL_REQUEST.to_vec()
+            ],
+            transport.sent()
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::command::{VehicleCommand, CONFIGURE_COMMAND_COUNT};
+use crate::protocol::VehicleMessage;
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_state::StateChange;
+use crate::AnkiVehicleData;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectionState {
+    // Not connected, waiting for the next attempt.
+    Disconnected,
+    Connected,
+    // Connected, but the vehicle has lost the track.
+    Delocalized,
+    // Out of reconnect attempts, nothing more will be tried.
+    GaveUp,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConnectionEvent {
+    // Connected and sent the configure commands, on the first connection and every reconnect.
+    Connected,
+    Disconnected,
+    // 1-based attempt since the connection was lost.
+    ReconnectScheduled { attempt: u32, delay: Duration },
+    GaveUp { attempts: u32 },
+    Delocalized,
+    // Back on the track after being delocalized.
+    Relocalized,
+}
+
+// Reconnect attempts wait `initial_delay`, then twice as long after every failure up to
+// `max_delay`. Without `max_attempts` it never gives up.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    // How long to wait before the 1-based `attempt`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(31);
+        self.initial_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay)
+    }
+}
+
+// What a connection to one vehicle goes through, without the transport. The driver reports
+// connecting, failing and losing the connection, and passes the vehicle's messages through.
+// In return it gets the configure commands to send on every (re)connect, when to try again, and
+// the events to hand on, queued up for `poll_event`.
+#[derive(Debug, Clone)]
+pub struct ConnectionLifecycle {
+    vehicle: AnkiVehicleData,
+    policy: ReconnectPolicy,
+    state: ConnectionState,
+    failed_attempts: u32,
+    reconnect_at: Option<Instant>,
+    events: VecDeque<ConnectionEvent>,
+}
+
+impl ConnectionLifecycle {
+    pub fn new(vehicle: AnkiVehicleData) -> ConnectionLifecycle {
+        ConnectionLifecycle {
+            vehicle,
+            policy: ReconnectPolicy::default(),
+            state: ConnectionState::Disconnected,
+            failed_attempts: 0,
+            reconnect_at: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> ConnectionLifecycle {
+        self.policy = policy;
+        self
+    }
+
+    pub fn vehicle(&self) -> &AnkiVehicleData {
+        &self.vehicle
+    }
+
+    pub fn vehicle_mut(&mut self) -> &mut AnkiVehicleData {
+        &mut self.vehicle
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self.state,
+            ConnectionState::Connected | ConnectionState::Delocalized
+        )
+    }
+
+    // The connection is up, returns the commands to send before anything else. A vehicle that
+    // lost its connection has left SDK mode and forgotten its lane, so this is the whole
+    // configure sequence every time.
+    pub fn connected(&mut self) -> [VehicleCommand; CONFIGURE_COMMAND_COUNT] {
+        trace_event!(target: TARGET_TRANSPORT, info, vehicle = self.vehicle.name(), "Vehicle connected");
+        self.state = ConnectionState::Connected;
+        self.failed_attempts = 0;
+        self.reconnect_at = None;
+        self.events.push_back(ConnectionEvent::Connected);
+        self.vehicle.configure()
+    }
+
+    pub fn disconnected(&mut self, at: Instant) {
+        if !self.is_connected() {
+            return;
+        }
+        trace_event!(target: TARGET_TRANSPORT, info, vehicle = self.vehicle.name(), "Vehicle disconnected");
+        self.events.push_back(ConnectionEvent::Disconnected);
+        self.schedule_reconnect(at);
+    }
+
+    // A reconnect attempt didn't get through.
+    pub fn connect_failed(&mut self, at: Instant) {
+        if self.is_connected() {
+            return;
+        }
+        self.failed_attempts += 1;
+        self.schedule_reconnect(at);
+    }
+
+    fn schedule_reconnect(&mut self, at: Instant) {
+        if self
+            .policy
+            .max_attempts
+            .is_some_and(|max_attempts| self.failed_attempts >= max_attempts)
+        {
+            trace_event!(target: TARGET_TRANSPORT, warn, vehicle = self.vehicle.name(), attempts = self.failed_attempts, "Giving up reconnecting");
+            self.state = ConnectionState::GaveUp;
+            self.reconnect_at = None;
+            self.events.push_back(ConnectionEvent::GaveUp {
+                attempts: self.failed_attempts,
+            });
+            return;
+        }
+        let attempt = self.failed_attempts + 1;
+        let delay = self.policy.delay(attempt);
+        self.state = ConnectionState::Disconnected;
+        self.reconnect_at = Some(at + delay);
+        self.events
+            .push_back(ConnectionEvent::ReconnectScheduled { attempt, delay });
+    }
+
+    // When to try connecting again, None while connected or after giving up.
+    pub fn reconnect_at(&self) -> Option<Instant> {
+        self.reconnect_at
+    }
+
+    pub fn reconnect_due(&self, now: Instant) -> bool {
+        self.reconnect_at.is_some_and(|at| at <= now)
+    }
+
+    // Processes the message on the vehicle, see `AnkiVehicleData::process_message_at`, and
+    // notes the vehicle leaving and finding the track.
+    pub fn process_message_at(&mut self, msg: VehicleMessage, at: Instant) -> StateChange {
+        let change = self.vehicle.process_message_at(msg, at);
+        match (&change, self.state) {
+            (StateChange::Delocalized, ConnectionState::Connected) => {
+                self.state = ConnectionState::Delocalized;
+                self.events.push_back(ConnectionEvent::Delocalized);
+            }
+            (_, ConnectionState::Delocalized) if self.vehicle.is_localized() => {
+                self.state = ConnectionState::Connected;
+                self.events.push_back(ConnectionEvent::Relocalized);
+            }
+            _ => {}
+        }
+        change
+    }
+
+    pub fn poll_event(&mut self) -> Option<ConnectionEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn drain_events(&mut self) -> impl Iterator<Item = ConnectionEvent> + '_ {
+        self.events.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::AnkiVehicleMsgType;
+    use crate::race::test_util::position_update;
+
+    #[test]
+    fn reconnect_policy_test() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(Duration::from_millis(500), policy.delay(1));
+        assert_eq!(Duration::from_secs(2), policy.delay(3));
+        assert_eq!(Duration::from_secs(10), policy.delay(10));
+        assert_eq!(Duration::from_secs(10), policy.delay(u32::MAX));
+    }
+
+    #[test]
+    fn connection_lifecycle_test() {
+        let t0 = Instant::now();
+        let mut lifecycle = ConnectionLifecycle::new(AnkiVehicleData::default())
+            .with_reconnect_policy(ReconnectPolicy {
+                max_attempts: Some(2),
+                ..ReconnectPolicy::default()
+            });
+        let commands = lifecycle.connected();
+        assert!(matches!(
+            commands[0],
+            VehicleCommand::SdkMode { on: true, .. }
+        ));
+        assert_eq!(Some(ConnectionEvent::Connected), lifecycle.poll_event());
+
+        let delocalized = [1, AnkiVehicleMsgType::V2CVehicleDelocalized.into()];
+        lifecycle.process_message_at(VehicleMessage::parse(&delocalized).unwrap(), t0);
+        assert_eq!(ConnectionState::Delocalized, lifecycle.state());
+        lifecycle.process_message_at(VehicleMessage::PositionUpdate(position_update(3, 17)), t0);
+        assert_eq!(
+            vec![ConnectionEvent::Delocalized, ConnectionEvent::Relocalized],
+            lifecycle.drain_events().collect::<Vec<_>>()
+        );
+
+        lifecycle.disconnected(t0);
+        assert!(!lifecycle.reconnect_due(t0));
+        assert!(lifecycle.reconnect_due(t0 + Duration::from_millis(500)));
+        lifecycle.connect_failed(t0 + Duration::from_millis(500));
+        assert_eq!(
+            Some(t0 + Duration::from_millis(1500)),
+            lifecycle.reconnect_at()
+        );
+        lifecycle.connect_failed(t0 + Duration::from_millis(1500));
+        assert_eq!(ConnectionState::GaveUp, lifecycle.state());
+        assert_eq!(
+            vec![
+                ConnectionEvent::Disconnected,
+                ConnectionEvent::ReconnectScheduled {
+                    attempt: 1,
+                    delay: Duration::from_millis(500)
+                },
+                ConnectionEvent::ReconnectScheduled {
+                    attempt: 2,
+                    delay: Duration::from_secs(1)
+                },
+                ConnectionEvent::GaveUp { attempts: 2 },
+            ],
+            lifecycle.drain_events().collect::<Vec<_>>()
+        );
+        assert_eq!(None, lifecycle.reconnect_at());
+    }
+}
@@ -0,0 +1,7 @@
+// Getting commands to a vehicle in a way it copes with, whichever transport carries them.
+
+pub mod command_queue;
+pub mod lifecycle;
+#[cfg(feature = "btleplug")]
+pub mod supervisor;
+pub mod transport;
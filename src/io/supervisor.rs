@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+
+use crate::bt_address::BtAddress;
+use crate::command::{Command, WireMessage};
+use crate::io::lifecycle::{
+    ConnectionEvent, ConnectionLifecycle, ConnectionState, ReconnectPolicy,
+};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::transport::{
+    connect, MessageStream, NotificationStream, TransportError, VehicleConnection,
+};
+use crate::vehicle_state::StateChange;
+use crate::AnkiVehicleData;
+
+// Not every platform ends the notification stream when the link drops, with no notification
+// for this long the connection is checked.
+pub const SUPERVISOR_CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SupervisorEvent {
+    Connection(ConnectionEvent),
+    // What a notification from the vehicle changed.
+    State(StateChange),
+}
+
+// Keeps a BLE connection to one vehicle going. Lost connections are retried following the
+// reconnect policy, and every new connection is configured the way the first one was. The
+// lifecycle itself is `ConnectionLifecycle`, this drives it over `VehicleConnection`.
+pub struct ConnectionSupervisor {
+    address: BtAddress,
+    lifecycle: ConnectionLifecycle,
+    connection: Option<VehicleConnection>,
+    messages: Option<MessageStream<NotificationStream>>,
+}
+
+impl ConnectionSupervisor {
+    // Connects and configures the vehicle, a vehicle that can't be reached the first time is an
+    // error rather than retried.
+    pub async fn connect(
+        address: BtAddress,
+        vehicle: AnkiVehicleData,
+        policy: ReconnectPolicy,
+    ) -> Result<ConnectionSupervisor, TransportError> {
+        let mut supervisor = ConnectionSupervisor {
+            address,
+            lifecycle: ConnectionLifecycle::new(vehicle).with_reconnect_policy(policy),
+            connection: None,
+            messages: None,
+        };
+        supervisor.open().await?;
+        Ok(supervisor)
+    }
+
+    async fn open(&mut self) -> Result<(), TransportError> {
+        let connection = connect(self.address).await?;
+        let messages = connection.messages().await?;
+        for command in self.lifecycle.connected() {
+            connection.write(&Command::from(command)).await?;
+        }
+        self.connection = Some(connection);
+        self.messages = Some(messages);
+        Ok(())
+    }
+
+    fn drop_connection(&mut self) {
+        self.connection = None;
+        self.messages = None;
+        self.lifecycle.disconnected(Instant::now());
+    }
+
+    pub fn address(&self) -> BtAddress {
+        self.address
+    }
+
+    pub fn vehicle(&self) -> &AnkiVehicleData {
+        self.lifecycle.vehicle()
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.lifecycle.state()
+    }
+
+    // Sends a command while connected, commands aren't held on to across a reconnect.
+    pub async fn send(&self, command: impl WireMessage) -> Result<(), TransportError> {
+        self.connection
+            .as_ref()
+            .ok_or(TransportError::NotConnected(self.address))?
+            .send(command)
+            .await
+    }
+
+    // Waits for the next thing to happen to the vehicle, reconnecting along the way when the
+    // connection is lost. None once the reconnect policy has given up.
+    pub async fn next_event(&mut self) -> Option<SupervisorEvent> {
+        loop {
+            if let Some(event) = self.lifecycle.poll_event() {
+                return Some(SupervisorEvent::Connection(event));
+            }
+            if self.lifecycle.state() == ConnectionState::GaveUp {
+                return None;
+            }
+
+            let Some(messages) = self.messages.as_mut() else {
+                let now = Instant::now();
+                let reconnect_at = self.lifecycle.reconnect_at()?;
+                tokio::time::sleep(reconnect_at.saturating_duration_since(now)).await;
+                if let Err(_e) = self.open().await {
+                    trace_event!(target: TARGET_TRANSPORT, debug, vehicle = %self.address, error = %_e, "Reconnect failed");
+                    self.connection = None;
+                    self.messages = None;
+                    // Configuring can fail after the connection came up.
+                    if self.lifecycle.is_connected() {
+                        self.lifecycle.disconnected(Instant::now());
+                    } else {
+                        self.lifecycle.connect_failed(Instant::now());
+                    }
+                }
+                continue;
+            };
+
+            match tokio::time::timeout(SUPERVISOR_CONNECTION_CHECK_INTERVAL, messages.next()).await
+            {
+                Ok(Some(Ok(msg))) => {
+                    let change = self.lifecycle.process_message_at(msg, Instant::now());
+                    if change != StateChange::None {
+                        return Some(SupervisorEvent::State(change));
+                    }
+                }
+                Ok(Some(Err(_e))) => {
+                    trace_event!(target: TARGET_TRANSPORT, debug, vehicle = %self.address, error = %_e, "Dropped malformed notification");
+                }
+                Ok(None) => self.drop_connection(),
+                Err(_) => {
+                    let connected = match self.connection.as_ref() {
+                        Some(connection) => connection.is_connected().await.unwrap_or(false),
+                        None => false,
+                    };
+                    if !connected {
+                        self.drop_connection();
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn disconnect(mut self) -> Result<(), TransportError> {
+        self.messages = None;
+        match self.connection.take() {
+            Some(connection) => connection.disconnect().await,
+            None => Ok(()),
+        }
+    }
+}
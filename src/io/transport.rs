@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::command::WireMessage;
+
+// Carries frames to and from one vehicle. Neither call waits, `send` hands the frame over to be
+// written and `poll_notification` takes a notification that has already arrived. That is all
+// the command queue and the fleet need, so they run the same over BLE, against the simulator or
+// against `MockTransport` in tests.
+pub trait VehicleTransport {
+    type Error: fmt::Display;
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    // None when nothing is waiting.
+    fn poll_notification(&mut self) -> Option<Vec<u8>>;
+
+    fn send_command(&mut self, command: impl WireMessage) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        self.send(&command.encode())
+    }
+}
+
+impl<T: VehicleTransport> VehicleTransport for &mut T {
+    type Error = T::Error;
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        (**self).send(data)
+    }
+
+    fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        (**self).poll_notification()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MockTransportError {
+    Disconnected,
+}
+
+impl fmt::Display for MockTransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockTransportError::Disconnected => write!(f, "Mock transport is disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for MockTransportError {}
+
+// An in-memory transport for tests. Frames sent are kept for the test to look at, notifications
+// are whatever the test queued up with `push_notification`.
+#[derive(Debug, Clone)]
+pub struct MockTransport {
+    connected: bool,
+    sent: Vec<Vec<u8>>,
+    notifications: VecDeque<Vec<u8>>,
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport {
+            connected: true,
+            sent: Vec::new(),
+            notifications: VecDeque::new(),
+        }
+    }
+
+    pub fn push_notification(&mut self, data: impl Into<Vec<u8>>) {
+        self.notifications.push_back(data.into());
+    }
+
+    pub fn sent(&self) -> &[Vec<u8>] {
+        &self.sent
+    }
+
+    pub fn take_sent(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.sent)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // Sends fail from now on, notifications already queued can still be polled.
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+    }
+
+    pub fn reconnect(&mut self) {
+        self.connected = true;
+    }
+}
+
+impl VehicleTransport for MockTransport {
+    type Error = MockTransportError;
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        if !self.connected {
+            return Err(MockTransportError::Disconnected);
+        }
+        self.sent.push(data.to_vec());
+        Ok(())
+    }
+
+    fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        self.notifications.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+
+    fn ping<T: VehicleTransport>(mut transport: T) -> Option<Vec<u8>> {
+        transport.send_command(Command::PING).ok()?;
+        transport.poll_notification()
+    }
+
+    #[test]
+    fn mock_transport_test() {
+        let mut mock = MockTransport::new();
+        assert_eq!(None, ping(&mut mock));
+        assert_eq!(vec![Command::PING.to_vec()], mock.sent());
+
+        mock.push_notification([1, 0x17]);
+        mock.disconnect();
+        assert_eq!(
+            Err(MockTransportError::Disconnected),
+            mock.send_command(AnkiVehicleData::stop())
+        );
+        assert_eq!(Some(vec![1, 0x17]), mock.poll_notification());
+        assert_eq!(1, mock.take_sent().len());
+
+        // The simulator answers the ping itself.
+        let mut sim = SimulatedVehicle::new();
+        assert_eq!(Some(vec![1, 0x17]), ping(&mut sim));
+    }
+}
@@ -0,0 +1,433 @@
+use scroll::{ctx, Pread, Pwrite};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AnkiError;
+use crate::protocol::{
+    anki_vehicle_msg_cancel_lane_change, anki_vehicle_msg_change_lane, anki_vehicle_msg_disconnect,
+    anki_vehicle_msg_get_battery_level, anki_vehicle_msg_get_version,
+    anki_vehicle_msg_lights_pattern, anki_vehicle_msg_ping, anki_vehicle_msg_set_config_params,
+    anki_vehicle_msg_set_lights, anki_vehicle_msg_set_offset_from_road_centre,
+    anki_vehicle_msg_set_sdk_mode, anki_vehicle_msg_set_speed, anki_vehicle_msg_turn,
+    AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse, AnkiVehicleMsgChargerInfo,
+    AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgOffsetFromRoadCentreUpdate,
+    AnkiVehicleMsgSpeedUpdate, AnkiVehicleMsgType, AnkiVehicleMsgVersionResponse, IntersectionCode,
+    LightChannel, LightEffect, TrackMaterial, VehicleTurn, VehicleTurnTrigger,
+    ANKI_VEHICLE_MSG_BASE_SIZE, ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+    ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE, ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+    ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE, ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+    ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE, ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+    ANKI_VEHICLE_MSG_TURN_SIZE, WIRE_ENDIAN,
+};
+
+// JSON form of every message on the wire, tagged with the message type so bridges in other
+// languages never have to deal with the binary framing. Commands can be encoded to frames and
+// vehicle notifications can be decoded from them.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[serde(tag = "msg_type", rename_all = "snake_case")]
+pub enum JsonMessage {
+    // Commands
+    Disconnect,
+    PingRequest,
+    VersionRequest,
+    BatteryLevelRequest,
+    SetLights {
+        mask: u8,
+    },
+    SetSpeed {
+        speed_mm_per_sec: i16,
+        accel_mm_per_sec2: i16,
+    },
+    ChangeLane {
+        horizontal_speed_mm_per_sec: u16,
+        horizontal_accel_mm_per_sec2: u16,
+        offset_from_road_centre_mm: f32,
+    },
+    CancelLaneChange,
+    SetOffsetFromRoadCentre {
+        offset_mm: f32,
+    },
+    Turn {
+        turn_type: VehicleTurn,
+        trigger: VehicleTurnTrigger,
+    },
+    LightsPattern {
+        channel: LightChannel,
+        effect: LightEffect,
+        start: u8,
+        end: u8,
+        cycles_per_min: u16,
+    },
+    SetConfigParams {
+        super_code_parse_mask: u8,
+        track_material: TrackMaterial,
+    },
+    SdkMode {
+        on: bool,
+        flags: u8,
+    },
+
+    // Notifications
+    PingResponse,
+    VersionResponse {
+        version: u16,
+    },
+    BatteryLevelResponse {
+        battery_level: u16,
+    },
+    PositionUpdate {
+        location_id: u8,
+        road_piece_id: u8,
+        offset_from_road_centre_mm: f32,
+        speed_mm_per_sec: u16,
+        parsing_flags: u8,
+        last_recv_lane_change_cmd_id: u8,
+        last_exec_lane_change_cmd_id: u8,
+        last_desired_lane_change_speed_mm_per_sec: u16,
+        last_desired_speed_mm_per_sec: u16,
+    },
+    TransitionUpdate {
+        road_piece_idx: i8,
+        road_piece_idx_prev: i8,
+        offset_from_road_centre_mm: f32,
+        last_recv_lane_change_id: u8,
+        last_exec_lane_change_id: u8,
+        last_desired_lane_change_speed_mm_per_sec: u16,
+        ave_follow_line_drift_pixels: i8,
+        had_lane_change_activity: u8,
+        uphill_counter: u8,
+        downhill_counter: u8,
+        left_wheel_dist_cm: u8,
+        right_wheel_dist_cm: u8,
+    },
+    IntersectionUpdate {
+        road_piece_idx: i8,
+        offset_from_road_centre_mm: f32,
+        intersection_code: IntersectionCode,
+        is_exiting: u8,
+        mm_since_last_transition_bar: u16,
+        mm_since_last_intersection_code: u16,
+    },
+    VehicleDelocalized,
+    OffsetFromRoadCentreUpdate {
+        offset_from_road_centre_mm: f32,
+        lane_change_id: u8,
+    },
+    SpeedUpdate {
+        desired_speed_mm_per_sec: u16,
+        accel_mm_per_sec2: u16,
+        speed_mm_per_sec: u16,
+    },
+    ChargerInfo {
+        on_track: u8,
+        on_charger: u8,
+        battery_low: u8,
+        battery_full: u8,
+    },
+    CollisionDetected,
+    CycleOvertime,
+}
+
+fn encode<T>(msg: T, size: usize) -> Result<Vec<u8>, AnkiError>
+where
+    T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+{
+    let mut data = [0u8; ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE];
+    let offset = data[..size].pwrite_with::<T>(msg, 0, WIRE_ENDIAN)?;
+    Ok(data[..offset].to_vec())
+}
+
+fn decode<'a, T>(data: &'a [u8]) -> Result<T, AnkiError>
+where
+    T: ctx::TryFromCtx<'a, scroll::Endian, Error = AnkiError>,
+{
+    data.pread_with::<T>(0, WIRE_ENDIAN)
+}
+
+impl JsonMessage {
+    pub fn from_json(json: &str) -> Result<JsonMessage, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize JsonMessage as JSON")
+    }
+
+    // Encodes a command into the frame written to the vehicle.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AnkiError> {
+        match self.clone() {
+            JsonMessage::Disconnect => {
+                encode(anki_vehicle_msg_disconnect(), ANKI_VEHICLE_MSG_BASE_SIZE)
+            }
+            JsonMessage::PingRequest => encode(anki_vehicle_msg_ping(), ANKI_VEHICLE_MSG_BASE_SIZE),
+            JsonMessage::VersionRequest => {
+                encode(anki_vehicle_msg_get_version(), ANKI_VEHICLE_MSG_BASE_SIZE)
+            }
+            JsonMessage::BatteryLevelRequest => encode(
+                anki_vehicle_msg_get_battery_level(),
+                ANKI_VEHICLE_MSG_BASE_SIZE,
+            ),
+            JsonMessage::SetLights { mask } => encode(
+                anki_vehicle_msg_set_lights(mask),
+                ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+            ),
+            JsonMessage::SetSpeed {
+                speed_mm_per_sec,
+                accel_mm_per_sec2,
+            } => encode(
+                anki_vehicle_msg_set_speed(speed_mm_per_sec, accel_mm_per_sec2),
+                ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+            ),
+            JsonMessage::ChangeLane {
+                horizontal_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2,
+                offset_from_road_centre_mm,
+            } => encode(
+                anki_vehicle_msg_change_lane(
+                    horizontal_speed_mm_per_sec,
+                    horizontal_accel_mm_per_sec2,
+                    offset_from_road_centre_mm,
+                ),
+                ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+            ),
+            JsonMessage::CancelLaneChange => encode(
+                anki_vehicle_msg_cancel_lane_change(),
+                ANKI_VEHICLE_MSG_BASE_SIZE,
+            ),
+            JsonMessage::SetOffsetFromRoadCentre { offset_mm } => encode(
+                anki_vehicle_msg_set_offset_from_road_centre(offset_mm),
+                ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+            ),
+            JsonMessage::Turn { turn_type, trigger } => encode(
+                anki_vehicle_msg_turn(turn_type, trigger),
+                ANKI_VEHICLE_MSG_TURN_SIZE,
+            ),
+            JsonMessage::LightsPattern {
+                channel,
+                effect,
+                start,
+                end,
+                cycles_per_min,
+            } => encode(
+                anki_vehicle_msg_lights_pattern(channel, effect, start, end, cycles_per_min),
+                ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+            ),
+            JsonMessage::SetConfigParams {
+                super_code_parse_mask,
+                track_material,
+            } => encode(
+                anki_vehicle_msg_set_config_params(super_code_parse_mask, track_material),
+                ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+            ),
+            JsonMessage::SdkMode { on, flags } => encode(
+                anki_vehicle_msg_set_sdk_mode(on.into(), flags),
+                ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+            ),
+            notification => Err(AnkiError::UnexpectedMsg(notification.notification_type())),
+        }
+    }
+
+    // Only called for notifications, which can't be encoded as bytes.
+    fn notification_type(&self) -> AnkiVehicleMsgType {
+        match self {
+            JsonMessage::PingResponse => AnkiVehicleMsgType::V2CPingResponse,
+            JsonMessage::VersionResponse { .. } => AnkiVehicleMsgType::V2CVersionResponse,
+            JsonMessage::BatteryLevelResponse { .. } => AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            JsonMessage::PositionUpdate { .. } => AnkiVehicleMsgType::V2CLocalisationPositionUpdate,
+            JsonMessage::TransitionUpdate { .. } => {
+                AnkiVehicleMsgType::V2CLocalisationTransitionUpdate
+            }
+            JsonMessage::IntersectionUpdate { .. } => {
+                AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate
+            }
+            JsonMessage::VehicleDelocalized => AnkiVehicleMsgType::V2CVehicleDelocalized,
+            JsonMessage::OffsetFromRoadCentreUpdate { .. } => {
+                AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate
+            }
+            JsonMessage::SpeedUpdate { .. } => AnkiVehicleMsgType::V2CSpeedUpdate,
+            JsonMessage::ChargerInfo { .. } => AnkiVehicleMsgType::V2CChargerInfo,
+            JsonMessage::CollisionDetected => AnkiVehicleMsgType::V2CCollisionDetected,
+            JsonMessage::CycleOvertime => AnkiVehicleMsgType::V2CCycleOvertime,
+            _ => AnkiVehicleMsgType::Unknown,
+        }
+    }
+
+    // Decodes a notification received from the vehicle.
+    pub fn from_bytes(data: &[u8]) -> Result<JsonMessage, AnkiError> {
+        let msg = decode::<AnkiVehicleMsg>(data)?;
+        match msg.msg_id {
+            AnkiVehicleMsgType::V2CPingResponse => Ok(JsonMessage::PingResponse),
+            AnkiVehicleMsgType::V2CVersionResponse => {
+                let msg = decode::<AnkiVehicleMsgVersionResponse>(data)?;
+                Ok(JsonMessage::VersionResponse {
+                    version: msg.version,
+                })
+            }
+            AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+                let msg = decode::<AnkiVehicleMsgBatteryLevelResponse>(data)?;
+                Ok(JsonMessage::BatteryLevelResponse {
+                    battery_level: msg.battery_level,
+                })
+            }
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate => {
+                let msg = decode::<AnkiVehicleMsgLocalisationPositionUpdate>(data)?;
+                Ok(JsonMessage::PositionUpdate {
+                    location_id: msg.location_id,
+                    road_piece_id: msg.road_piece_id,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    speed_mm_per_sec: msg.speed_mm_per_sec,
+                    parsing_flags: msg.parsing_flags.bits(),
+                    last_recv_lane_change_cmd_id: msg.last_recv_lane_change_cmd_id,
+                    last_exec_lane_change_cmd_id: msg.last_exec_lane_change_cmd_id,
+                    last_desired_lane_change_speed_mm_per_sec: msg
+                        .last_desired_lane_change_speed_mm_per_sec,
+                    last_desired_speed_mm_per_sec: msg.last_desired_speed_mm_per_sec,
+                })
+            }
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => {
+                let msg = decode::<AnkiVehicleMsgLocalisationTransitionUpdate>(data)?;
+                Ok(JsonMessage::TransitionUpdate {
+                    road_piece_idx: msg.road_piece_idx,
+                    road_piece_idx_prev: msg.road_piece_idx_prev,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    last_recv_lane_change_id: msg.last_recv_lane_change_id,
+                    last_exec_lane_change_id: msg.last_exec_lane_change_id,
+                    last_desired_lane_change_speed_mm_per_sec: msg
+                        .last_desired_lane_change_speed_mm_per_sec,
+                    ave_follow_line_drift_pixels: msg.ave_follow_line_drift_pixels,
+                    had_lane_change_activity: msg.had_lane_change_activity,
+                    uphill_counter: msg.uphill_counter,
+                    downhill_counter: msg.downhill_counter,
+                    left_wheel_dist_cm: msg.left_wheel_dist_cm,
+                    right_wheel_dist_cm: msg.right_wheel_dist_cm,
+                })
+            }
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => {
+                let msg = decode::<AnkiVehicleMsgLocalisationIntersectionUpdate>(data)?;
+                Ok(JsonMessage::IntersectionUpdate {
+                    road_piece_idx: msg.road_piece_idx,
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    intersection_code: msg.intersection_code,
+                    is_exiting: msg.is_exiting,
+                    mm_since_last_transition_bar: msg.mm_since_last_transition_bar,
+                    mm_since_last_intersection_code: msg.mm_since_last_intersection_code,
+                })
+            }
+            AnkiVehicleMsgType::V2CVehicleDelocalized => Ok(JsonMessage::VehicleDelocalized),
+            AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => {
+                let msg = decode::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(data)?;
+                Ok(JsonMessage::OffsetFromRoadCentreUpdate {
+                    offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+                    lane_change_id: msg.lane_change_id,
+                })
+            }
+            AnkiVehicleMsgType::V2CSpeedUpdate => {
+                let msg = decode::<AnkiVehicleMsgSpeedUpdate>(data)?;
+                Ok(JsonMessage::SpeedUpdate {
+                    desired_speed_mm_per_sec: msg.desired_speed_mm_per_sec,
+                    accel_mm_per_sec2: msg.accel_mm_per_sec2,
+                    speed_mm_per_sec: msg.speed_mm_per_sec,
+                })
+            }
+            AnkiVehicleMsgType::V2CChargerInfo => {
+                let msg = decode::<AnkiVehicleMsgChargerInfo>(data)?;
+                Ok(JsonMessage::ChargerInfo {
+                    on_track: msg.on_track,
+                    on_charger: msg.on_charger,
+                    battery_low: msg.battery_low,
+                    battery_full: msg.battery_full,
+                })
+            }
+            AnkiVehicleMsgType::V2CCollisionDetected => Ok(JsonMessage::CollisionDetected),
+            AnkiVehicleMsgType::V2CCycleOvertime => Ok(JsonMessage::CycleOvertime),
+            msg_id => Err(AnkiError::UnexpectedMsg(msg_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn json_command_to_bytes_test() {
+        let msg = JsonMessage::from_json(
+            r#"{"msg_type":"set_speed","speed_mm_per_sec":500,"accel_mm_per_sec2":1000}"#,
+        )
+        .unwrap();
+        println!(
+            "T:{:?} == G:{:?}",
+            msg.to_bytes(),
+            AnkiVehicleData::set_speed(500, 1000)
+        );
+        assert_eq!(
+            AnkiVehicleData::set_speed(500, 1000),
+            msg.to_bytes().unwrap()
+        );
+
+        let msg = JsonMessage::from_json(
+            r#"{"msg_type":"turn","turn_type":"u_turn","trigger":"immediate"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![3, AnkiVehicleMsgType::C2VTurn as u8, 3, 0],
+            msg.to_bytes().unwrap()
+        );
+
+        assert!(JsonMessage::PingResponse.to_bytes().is_err());
+    }
+
+    #[test]
+    fn json_notification_from_bytes_test() {
+        let data: &[u8] = &[
+            12,
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate as u8,
+            1,
+            0,
+            0,
+            200,
+            66,
+            IntersectionCode::EntryFirst as u8,
+            0,
+            0x10,
+            0,
+            0x20,
+            0,
+        ];
+        let msg = JsonMessage::from_bytes(data).unwrap();
+        println!("T:{:?} == G:{:?}", msg, data);
+        assert_eq!(
+            r#"{"msg_type":"intersection_update","road_piece_idx":1,"offset_from_road_centre_mm":100.0,"intersection_code":"entry_first","is_exiting":0,"mm_since_last_transition_bar":16,"mm_since_last_intersection_code":32}"#,
+            msg.to_json()
+        );
+        assert_eq!(msg, JsonMessage::from_json(&msg.to_json()).unwrap());
+
+        let data: &[u8] = &[1, AnkiVehicleMsgType::V2CVehicleDelocalized as u8];
+        assert_eq!(
+            JsonMessage::VehicleDelocalized,
+            JsonMessage::from_bytes(data).unwrap()
+        );
+
+        let data: &[u8] = &[
+            7,
+            AnkiVehicleMsgType::V2CSpeedUpdate as u8,
+            0xf4,
+            0x01,
+            0xe8,
+            0x03,
+            0xf0,
+            0x01,
+        ];
+        assert_eq!(
+            r#"{"msg_type":"speed_update","desired_speed_mm_per_sec":500,"accel_mm_per_sec2":1000,"speed_mm_per_sec":496}"#,
+            JsonMessage::from_bytes(data).unwrap().to_json()
+        );
+        let data: &[u8] = &[1, AnkiVehicleMsgType::V2CCollisionDetected as u8];
+        assert_eq!(
+            JsonMessage::CollisionDetected,
+            JsonMessage::from_bytes(data).unwrap()
+        );
+    }
+}
@@ -0,0 +1,93 @@
+// Lanes numbered from 1 on the left to `lane_count` on the right, the way drivers count them.
+// Every lane has the same width and the lanes sit evenly either side of the road centre.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LaneLayout {
+    lane_count: u8,
+    lane_width_mm: f32,
+}
+
+impl LaneLayout {
+    // The four lanes of a Drive track, centred 22.5 mm and 67.5 mm either side of the road centre.
+    pub const DRIVE: LaneLayout = LaneLayout::new(4, 45.0);
+    // The sixteen positions Overdrive localisation codes are printed at, across the same width
+    // as the Drive lanes.
+    pub const OVERDRIVE: LaneLayout = LaneLayout::new(16, 9.0);
+
+    // A layout needs at least one lane, a count of 0 is taken as 1.
+    pub const fn new(lane_count: u8, lane_width_mm: f32) -> LaneLayout {
+        LaneLayout {
+            lane_count: if lane_count == 0 { 1 } else { lane_count },
+            lane_width_mm,
+        }
+    }
+
+    pub fn lane_count(&self) -> u8 {
+        self.lane_count
+    }
+
+    pub fn lane_width_mm(&self) -> f32 {
+        self.lane_width_mm
+    }
+
+    // Offset from the road centre of the middle of `lane`, None for a lane the layout doesn't
+    // have.
+    pub fn offset_for_lane(&self, lane: u8) -> Option<f32> {
+        if lane == 0 || lane > self.lane_count {
+            return None;
+        }
+        Some((lane as f32 - self.centre_lane()) * self.lane_width_mm)
+    }
+
+    // The lane nearest `offset_mm`, halfway between two lanes goes to the right one and an offset
+    // beyond the outermost lane belongs to that lane.
+    pub fn lane_for_offset(&self, offset_mm: f32) -> u8 {
+        let lane = (offset_mm / self.lane_width_mm + self.centre_lane()).round();
+        lane.clamp(1.0, self.lane_count as f32) as u8
+    }
+
+    // Lane offsets from left to right.
+    pub fn offsets_mm(&self) -> impl Iterator<Item = f32> + '_ {
+        (1..=self.lane_count).filter_map(|lane| self.offset_for_lane(lane))
+    }
+
+    // The lane number at the road centre, halfway between two lanes for an even count.
+    fn centre_lane(&self) -> f32 {
+        (self.lane_count as f32 + 1.0) / 2.0
+    }
+}
+
+impl Default for LaneLayout {
+    fn default() -> Self {
+        LaneLayout::DRIVE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lane_layout_test() {
+        let drive = LaneLayout::DRIVE;
+        assert_eq!(
+            vec![-67.5, -22.5, 22.5, 67.5],
+            drive.offsets_mm().collect::<Vec<_>>()
+        );
+        assert_eq!(None, drive.offset_for_lane(0));
+        assert_eq!(None, drive.offset_for_lane(5));
+        assert_eq!(1, drive.lane_for_offset(-68.0));
+        assert_eq!(3, drive.lane_for_offset(10.0));
+        assert_eq!(3, drive.lane_for_offset(0.0));
+        assert_eq!(4, drive.lane_for_offset(200.0));
+
+        let overdrive = LaneLayout::OVERDRIVE;
+        assert_eq!(Some(-67.5), overdrive.offset_for_lane(1));
+        assert_eq!(Some(67.5), overdrive.offset_for_lane(16));
+        for lane in 1..=overdrive.lane_count() {
+            let offset = overdrive.offset_for_lane(lane).unwrap();
+            assert_eq!(lane, overdrive.lane_for_offset(offset));
+        }
+        assert_eq!(1, LaneLayout::new(0, 45.0).lane_count());
+    }
+}
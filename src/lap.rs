@@ -0,0 +1,239 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::{AnkiVehicleMsgLocalisationPositionUpdate, VehicleMessage};
+use crate::race::leaderboard::FINISH_LINE_ROAD_PIECE_ID;
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::track::TrackMap;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LapCompleted {
+    // 1-based, the lap just finished.
+    pub lap: u16,
+    pub lap_time: Duration,
+    pub personal_best: bool,
+}
+
+// Counts the laps of one vehicle from its position updates. The first finish line crossing only
+// starts the clock, after that every crossing is a lap. Position updates keep coming while the
+// vehicle is on the finish line piece, only the first of them counts.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LapCounter {
+    finish_road_piece_id: u8,
+    road_piece_id: Option<u8>,
+    lap_started_at: Option<Instant>,
+    laps: u16,
+    last_lap_time: Option<Duration>,
+    best_lap_time: Option<Duration>,
+}
+
+impl Default for LapCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LapCounter {
+    pub fn new() -> LapCounter {
+        LapCounter {
+            finish_road_piece_id: FINISH_LINE_ROAD_PIECE_ID,
+            road_piece_id: None,
+            lap_started_at: None,
+            laps: 0,
+            last_lap_time: None,
+            best_lap_time: None,
+        }
+    }
+
+    pub fn with_finish_road_piece_id(mut self, road_piece_id: u8) -> LapCounter {
+        self.finish_road_piece_id = road_piece_id;
+        self
+    }
+
+    // Counts laps from the first piece of a mapped track, which is where the mapper started.
+    pub fn for_track(map: &TrackMap) -> LapCounter {
+        match map.pieces().first() {
+            Some(piece) => LapCounter::new().with_finish_road_piece_id(piece.road_piece_id),
+            None => LapCounter::new(),
+        }
+    }
+
+    pub fn process_position_update(
+        &mut self,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) -> Option<LapCompleted> {
+        if self.road_piece_id.replace(data.road_piece_id) == Some(data.road_piece_id)
+            || data.road_piece_id != self.finish_road_piece_id
+        {
+            return None;
+        }
+
+        self.lap_started_at.replace(at).map(|started_at| {
+            let lap_time = at.saturating_duration_since(started_at);
+            let personal_best = self.best_lap_time.is_none_or(|best| lap_time < best);
+            self.laps = self.laps.saturating_add(1);
+            self.last_lap_time = Some(lap_time);
+            if personal_best {
+                self.best_lap_time = Some(lap_time);
+            }
+            trace_event!(target: TARGET_CONTROLLER, debug, lap = self.laps, ?lap_time, "Lap completed");
+            LapCompleted {
+                lap: self.laps,
+                lap_time,
+                personal_best,
+            }
+        })
+    }
+
+    // The vehicle has lost the track and may come back on anywhere, the lap in progress is
+    // abandoned and the clock starts again at the next finish line crossing.
+    pub fn process_delocalized(&mut self) {
+        self.road_piece_id = None;
+        self.lap_started_at = None;
+    }
+
+    // Feeds any notification to the counter, those that aren't position updates or
+    // delocalizations are ignored.
+    pub fn process_message(&mut self, msg: &VehicleMessage, at: Instant) -> Option<LapCompleted> {
+        match msg {
+            VehicleMessage::PositionUpdate(data) => self.process_position_update(data, at),
+            VehicleMessage::Delocalized(_) => {
+                self.process_delocalized();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    pub fn finish_road_piece_id(&self) -> u8 {
+        self.finish_road_piece_id
+    }
+
+    // Road piece of the last position update, None before the first one or after losing the
+    // track.
+    pub fn road_piece_id(&self) -> Option<u8> {
+        self.road_piece_id
+    }
+
+    // When the finish line was last crossed, None before the first crossing.
+    pub fn lap_started_at(&self) -> Option<Instant> {
+        self.lap_started_at
+    }
+
+    // Laps completed so far.
+    pub fn laps(&self) -> u16 {
+        self.laps
+    }
+
+    pub fn last_lap_time(&self) -> Option<Duration> {
+        self.last_lap_time
+    }
+
+    pub fn best_lap_time(&self) -> Option<Duration> {
+        self.best_lap_time
+    }
+
+    // How long the lap in progress has run, None before the first finish line crossing.
+    pub fn current_lap_time(&self, at: Instant) -> Option<Duration> {
+        self.lap_started_at
+            .map(|started_at| at.saturating_duration_since(started_at))
+    }
+
+    // Starts counting from zero again, keeping the finish line piece.
+    pub fn reset(&mut self) {
+        *self = LapCounter::new().with_finish_road_piece_id(self.finish_road_piece_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+    use crate::vehicle_state::StateChange;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn lap_counter_test() {
+        let t0 = Instant::now();
+        let mut laps = LapCounter::new();
+
+        assert_eq!(
+            None,
+            laps.process_position_update(&position_update(0, 34), t0)
+        );
+        assert_eq!(
+            None,
+            laps.process_position_update(&position_update(1, 34), t0)
+        );
+        laps.process_position_update(&position_update(0, 17), t0);
+        assert_eq!(
+            Some(LapCompleted {
+                lap: 1,
+                lap_time: Duration::from_secs(6),
+                personal_best: true,
+            }),
+            laps.process_position_update(&position_update(0, 34), t0 + Duration::from_secs(6))
+        );
+        laps.process_position_update(&position_update(0, 17), t0);
+        assert_eq!(
+            Some(LapCompleted {
+                lap: 2,
+                lap_time: Duration::from_secs(7),
+                personal_best: false,
+            }),
+            laps.process_position_update(&position_update(0, 34), t0 + Duration::from_secs(13))
+        );
+        assert_eq!(2, laps.laps());
+        assert_eq!(Some(Duration::from_secs(6)), laps.best_lap_time());
+        assert_eq!(Some(Duration::from_secs(7)), laps.last_lap_time());
+
+        // A lap broken by a delocalization isn't counted.
+        laps.process_delocalized();
+        laps.process_position_update(&position_update(0, 17), t0);
+        assert_eq!(
+            None,
+            laps.process_position_update(&position_update(0, 34), t0 + Duration::from_secs(20))
+        );
+        assert_eq!(2, laps.laps());
+
+        laps.reset();
+        assert_eq!(0, laps.laps());
+        assert_eq!(None, laps.best_lap_time());
+    }
+
+    #[test]
+    fn vehicle_lap_counter_test() {
+        let t0 = Instant::now();
+        let mut vehicle = AnkiVehicleData::default()
+            .with_lap_counter(LapCounter::new().with_finish_road_piece_id(33));
+        for (road_piece_id, at) in [(33, 0), (17, 2), (34, 3)] {
+            vehicle.process_message_at(
+                VehicleMessage::PositionUpdate(position_update(0, road_piece_id)),
+                t0 + Duration::from_secs(at),
+            );
+        }
+        let mut finish = position_update(4, 33);
+        finish.speed_mm_per_sec = 600;
+        finish.offset_from_road_centre_mm = -23.0;
+        assert_eq!(
+            StateChange::LapCompleted {
+                lap: LapCompleted {
+                    lap: 1,
+                    lap_time: Duration::from_secs(5),
+                    personal_best: true,
+                },
+                location_id: 4,
+                speed_mm_per_sec: 600,
+                offset_from_road_centre_mm: -23.0,
+            },
+            vehicle.process_message_at(
+                VehicleMessage::PositionUpdate(finish),
+                t0 + Duration::from_secs(5),
+            )
+        );
+        assert_eq!(4, vehicle.location_id);
+        assert_eq!(600, vehicle.speed_mm_per_sec);
+        assert_eq!(1, vehicle.laps().laps());
+    }
+}
@@ -1,29 +1,127 @@
 extern crate core;
 
 use crate::advertisement::AnkiVehicleState;
-use scroll::Pwrite;
+use crate::battery::BatteryFlags;
+use crate::bt_address::BtAddress;
+use crate::command::{Command, VehicleCommand, CONFIGURE_COMMAND_COUNT};
+use crate::dialect::ProtocolDialect;
+use crate::error::AnkiError;
+use crate::firmware::FirmwareVersion;
+use crate::freshness::{Freshness, StateGroup};
+use crate::lanes::LaneLayout;
+use crate::lap::LapCounter;
+use crate::ping::PingTracker;
+use crate::trace::{trace_event, TARGET_PROTOCOL};
+use crate::validation::{validate_notification, ValidationMode};
+use crate::vehicle_model::VehicleModel;
+use crate::vehicle_state::{StateChange, VehicleState};
+use std::time::{Duration, Instant};
 
 use crate::protocol::{
-    anki_vehicle_msg_change_lane, anki_vehicle_msg_get_battery_level, anki_vehicle_msg_get_version,
-    anki_vehicle_msg_set_offset_from_road_centre, anki_vehicle_msg_set_sdk_mode,
-    anki_vehicle_msg_set_speed, AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse,
-    AnkiVehicleMsgChangeLane, AnkiVehicleMsgLocalisationIntersectionUpdate,
-    AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgLocalisationTransitionUpdate,
-    AnkiVehicleMsgOffsetFromRoadCentreUpdate, AnkiVehicleMsgSdkMode,
-    AnkiVehicleMsgSetOffsetFromRoadCentre, AnkiVehicleMsgSetSpeed, AnkiVehicleMsgVersionResponse,
-    IntersectionCode, ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE,
-    ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE, ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
-    ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE, ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
-    ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE, ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION,
+    anki_vehicle_msg_change_lane, anki_vehicle_msg_set_speed, anki_vehicle_msg_turn,
+    AnkiVehicleMsgBatteryLevelResponse, AnkiVehicleMsgChargerInfo,
+    AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgLocalisationPositionUpdate,
+    AnkiVehicleMsgLocalisationTransitionUpdate, AnkiVehicleMsgOffsetFromRoadCentreUpdate,
+    AnkiVehicleMsgType, AnkiVehicleMsgVehicleDelocalized, AnkiVehicleMsgVersionResponse,
+    IntersectionCode, ParsingFlags, VehicleMessage, VehicleTurn, VehicleTurnTrigger,
+    ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE, ANKI_VEHICLE_MSG_SET_SPEED_SIZE, ANKI_VEHICLE_MSG_TURN_SIZE,
+    ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION,
 };
 
 pub mod advertisement;
+pub mod battery;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+#[cfg(feature = "json")]
+pub mod bridge;
+pub mod bt_address;
+pub mod capture;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod clock;
+pub mod command;
+#[cfg(feature = "toml")]
+pub mod config;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "csv")]
+pub mod csv_export;
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+pub mod dbus;
+pub mod dedup;
+pub mod dialect;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod firmware;
+pub mod fleet;
+#[cfg(feature = "metrics")]
+pub mod fleet_metrics;
+#[cfg(feature = "heapless")]
+pub mod frame;
+pub mod frame_history;
+pub mod freshness;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod host;
+pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod lanes;
+pub mod lap;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "opentelemetry")]
+pub mod otel;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+#[cfg(feature = "parquet")]
+pub mod parquet_export;
+pub mod ping;
+pub mod pool;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
 pub mod protocol;
+pub mod proximity;
+pub mod race;
+pub mod recorder;
+#[cfg(feature = "bincode")]
+pub mod relay;
+pub mod replay;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod road_piece;
+#[cfg(feature = "ros2")]
+pub mod ros2;
+pub mod schema;
+pub mod script;
+pub mod sim;
+pub mod snapshot;
+pub mod soak;
+#[cfg(feature = "spectator")]
+pub mod spectator;
+pub mod telemetry;
+pub mod telemetry_channel;
+pub mod telemetry_queue;
+mod trace;
+pub mod track;
+#[cfg(feature = "btleplug")]
+pub mod transport;
+pub mod validation;
 pub mod vehicle_gatt_profile;
+pub mod vehicle_id;
+pub mod vehicle_model;
+pub mod vehicle_state;
+#[cfg(all(feature = "web-bluetooth", target_arch = "wasm32"))]
+pub mod web_bluetooth;
 
 #[derive(Debug, Clone)]
 pub struct AnkiVehicleData {
     name: String,
+    address: Option<BtAddress>,
+    model_id: Option<u8>,
     state: AnkiVehicleState,
     version: u16,
     battery_level: u16,
@@ -32,8 +130,9 @@ pub struct AnkiVehicleData {
     speed_mm_per_sec: u16,
     offset_from_road_centre_mm: f32,
     location_id: u8,
+    localized: bool,
     // Driving State Info
-    parsing_flags: u8,
+    parsing_flags: ParsingFlags,
 
     // Additional Speed Info
     last_desired_speed_mm_per_sec: u16,
@@ -53,12 +152,30 @@ pub struct AnkiVehicleData {
     mm_since_last_transition_bar: u16,
     mm_since_last_intersection_code: u16,
     //TODO: Lighting
+    validation_mode: ValidationMode,
+    freshness: Freshness,
+    pings: PingTracker,
+    laps: LapCounter,
+
+    // Sent by `configure`
+    sdk_flags: u8,
+    lane_change_speed_mm_per_sec: u16,
+    lane_change_accel_mm_per_sec2: u16,
+    lane_layout: LaneLayout,
 }
 
-impl AnkiVehicleData {
-    pub fn new() -> AnkiVehicleData {
+pub const DEFAULT_LANE_CHANGE_SPEED_MM_PER_SEC: u16 = 300;
+pub const DEFAULT_LANE_CHANGE_ACCEL_MM_PER_SEC2: u16 = 2500;
+// Brakes hard enough to stop within a road piece from full speed.
+pub const STOP_ACCEL_MM_PER_SEC2: i16 = 12500;
+
+// A vehicle not known by name or address yet, as when replaying or simulating.
+impl Default for AnkiVehicleData {
+    fn default() -> Self {
         AnkiVehicleData {
             name: "Anki Vehicle".to_string(),
+            address: None,
+            model_id: None,
             state: AnkiVehicleState {
                 low_battery: false,
                 full_battery: false,
@@ -69,7 +186,8 @@ impl AnkiVehicleData {
             speed_mm_per_sec: 0,
             offset_from_road_centre_mm: 0.0,
             location_id: 0,
-            parsing_flags: 0,
+            localized: false,
+            parsing_flags: ParsingFlags::default(),
             last_desired_speed_mm_per_sec: 0,
             last_desired_lane_change_speed_mm_per_sec: 0,
             road_piece_idx_prev: 0,
@@ -82,13 +200,208 @@ impl AnkiVehicleData {
             is_exiting_intersection: 0,
             mm_since_last_transition_bar: 0,
             mm_since_last_intersection_code: 0,
+            validation_mode: ValidationMode::Lenient,
+            freshness: Freshness::default(),
+            pings: PingTracker::new(),
+            laps: LapCounter::new(),
+            sdk_flags: ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION,
+            lane_change_speed_mm_per_sec: DEFAULT_LANE_CHANGE_SPEED_MM_PER_SEC,
+            lane_change_accel_mm_per_sec2: DEFAULT_LANE_CHANGE_ACCEL_MM_PER_SEC2,
+            lane_layout: LaneLayout::default(),
+        }
+    }
+}
+
+impl AnkiVehicleData {
+    // The vehicle with this name and address, the with_* setters fill in the rest.
+    pub fn new(name: impl Into<String>, bt_address: BtAddress) -> AnkiVehicleData {
+        AnkiVehicleData {
+            name: name.into(),
+            address: Some(bt_address),
+            ..AnkiVehicleData::default()
         }
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> AnkiVehicleData {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_address(mut self, address: BtAddress) -> AnkiVehicleData {
+        self.address = Some(address);
+        self
+    }
+
+    // The model id from the vehicle's advertisement.
+    pub fn with_model_id(mut self, model_id: u8) -> AnkiVehicleData {
+        self.model_id = Some(model_id);
+        self
+    }
+
+    // Flags of the SDK mode command sent by `configure`.
+    pub fn with_sdk_flags(mut self, flags: u8) -> AnkiVehicleData {
+        self.sdk_flags = flags;
+        self
+    }
+
+    // How fast `configure` has the vehicle move between lanes.
+    pub fn with_lane_change(
+        mut self,
+        horizontal_speed_mm_per_sec: u16,
+        horizontal_accel_mm_per_sec2: u16,
+    ) -> AnkiVehicleData {
+        self.lane_change_speed_mm_per_sec = horizontal_speed_mm_per_sec;
+        self.lane_change_accel_mm_per_sec2 = horizontal_accel_mm_per_sec2;
+        self
+    }
+
+    // The lanes of the track the vehicle drives on, for `lane` and `change_to_lane`.
+    pub fn with_lane_layout(mut self, layout: LaneLayout) -> AnkiVehicleData {
+        self.lane_layout = layout;
+        self
+    }
+
+    // Counts laps from the position updates, set up for the vehicle's track.
+    pub fn with_lap_counter(mut self, laps: LapCounter) -> AnkiVehicleData {
+        self.laps = laps;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> Option<BtAddress> {
+        self.address
+    }
+
+    pub fn model_id(&self) -> Option<u8> {
+        self.model_id
+    }
+
+    // None without a model id, or for one the crate doesn't know.
+    pub fn model(&self) -> Option<VehicleModel> {
+        self.model_id.and_then(|id| VehicleModel::try_from(id).ok())
+    }
+
+    // A copy of everything below, see `VehicleState`.
+    pub fn state(&self) -> VehicleState {
+        VehicleState::from(self)
+    }
+
+    // The flags from the advertisement, as last set by `set_state`.
+    pub fn advertised_state(&self) -> &AnkiVehicleState {
+        &self.state
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn battery_level(&self) -> u16 {
+        self.battery_level
+    }
+
+    pub fn battery_millivolts(&self) -> u16 {
+        self.battery_level
+    }
+
+    // None until the vehicle has answered a battery level request.
+    pub fn battery_flags(&self) -> Option<BatteryFlags> {
+        (self.battery_level != 0).then(|| BatteryFlags::from_millivolts(self.battery_level))
+    }
+
+    pub fn is_on_charger(&self) -> bool {
+        self.state.is_on_charger()
+    }
+
+    // Charged going by either the advertisement or the last battery level read.
+    pub fn is_charged(&self) -> bool {
+        self.state.is_charged() || self.battery_flags().is_some_and(|flags| flags.is_charged())
+    }
+
+    pub fn speed_mm_per_sec(&self) -> u16 {
+        self.speed_mm_per_sec
+    }
+
+    pub fn offset_from_road_centre_mm(&self) -> f32 {
+        self.offset_from_road_centre_mm
+    }
+
+    pub fn location_id(&self) -> u8 {
+        self.location_id
+    }
+
+    // False until the first position or transition update, and again after a delocalized
+    // notification until the vehicle reads a location code.
+    pub fn is_localized(&self) -> bool {
+        self.localized
+    }
+
+    pub fn parsing_flags(&self) -> ParsingFlags {
+        self.parsing_flags
+    }
+
+    pub fn last_desired_speed_mm_per_sec(&self) -> u16 {
+        self.last_desired_speed_mm_per_sec
+    }
+
+    pub fn last_desired_lane_change_speed_mm_per_sec(&self) -> u16 {
+        self.last_desired_lane_change_speed_mm_per_sec
+    }
+
+    pub fn road_piece_idx(&self) -> i8 {
+        self.road_piece_idx
+    }
+
+    pub fn road_piece_idx_prev(&self) -> i8 {
+        self.road_piece_idx_prev
+    }
+
+    pub fn uphill_counter(&self) -> u8 {
+        self.uphill_counter
+    }
+
+    pub fn downhill_counter(&self) -> u8 {
+        self.downhill_counter
+    }
+
+    pub fn left_wheel_dist_cm(&self) -> u8 {
+        self.left_wheel_dist_cm
+    }
+
+    pub fn right_wheel_dist_cm(&self) -> u8 {
+        self.right_wheel_dist_cm
+    }
+
+    pub fn intersection_code(&self) -> IntersectionCode {
+        self.intersection_code.clone()
+    }
+
+    pub fn is_exiting_intersection(&self) -> bool {
+        self.is_exiting_intersection != 0
+    }
+
+    pub fn mm_since_last_transition_bar(&self) -> u16 {
+        self.mm_since_last_transition_bar
+    }
+
+    pub fn mm_since_last_intersection_code(&self) -> u16 {
+        self.mm_since_last_intersection_code
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.name = name;
     }
 
+    pub fn set_validation_mode(&mut self, mode: ValidationMode) {
+        self.validation_mode = mode;
+    }
+
+    pub fn validation_mode(&self) -> ValidationMode {
+        self.validation_mode
+    }
+
     pub fn set_state(&mut self, state: AnkiVehicleState) {
         self.state = state;
     }
@@ -97,52 +410,59 @@ impl AnkiVehicleData {
         self.version = version;
     }
 
-    pub fn configure(&mut self) -> Vec<Vec<u8>> {
-        let mut commands: Vec<Vec<u8>> = Vec::new();
-
-        let msg: AnkiVehicleMsgSdkMode =
-            anki_vehicle_msg_set_sdk_mode(1, ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION);
-        let mut data = [0u8; ANKI_VEHICLE_MSG_SDK_MODE_SIZE];
-        let offset = data
-            .pwrite_with::<AnkiVehicleMsgSdkMode>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsgSdkMode as bytes");
-
-        commands.push(data[..offset].to_vec());
-
-        let msg: AnkiVehicleMsg = anki_vehicle_msg_get_version();
-        let mut data = [0u8; ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE];
-        let offset = data
-            .pwrite_with::<AnkiVehicleMsg>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsg as bytes");
+    pub fn freshness(&self) -> &Freshness {
+        &self.freshness
+    }
 
-        commands.push(data[..offset].to_vec());
+    // How long ago a notification last updated the group, None if none ever has.
+    pub fn age(&self, group: StateGroup) -> Option<Duration> {
+        self.freshness.age(group, Instant::now())
+    }
 
-        let msg: AnkiVehicleMsg = anki_vehicle_msg_get_battery_level();
-        let mut data = [0u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE];
-        let offset = data
-            .pwrite_with::<AnkiVehicleMsg>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsg as bytes");
+    pub fn pings(&self) -> &PingTracker {
+        &self.pings
+    }
 
-        commands.push(data[..offset].to_vec());
+    pub fn laps(&self) -> &LapCounter {
+        &self.laps
+    }
 
-        let msg: AnkiVehicleMsgSetOffsetFromRoadCentre =
-            anki_vehicle_msg_set_offset_from_road_centre(0.0);
-        let mut data = [0u8; ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE];
-        let offset = data
-            .pwrite_with::<AnkiVehicleMsgSetOffsetFromRoadCentre>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsgSetOffsetFromRoadCentre as bytes");
+    // A ping request to send, the latency is taken when the response comes back.
+    pub fn ping(&mut self) -> Command {
+        self.ping_at(Instant::now())
+    }
 
-        commands.push(data[..offset].to_vec());
+    pub fn ping_at(&mut self, at: Instant) -> Command {
+        self.pings.sent_at(at);
+        Command::PING
+    }
 
-        let msg: AnkiVehicleMsgChangeLane = anki_vehicle_msg_change_lane(300, 2500, 0.0);
-        let mut data = [0u8; ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE];
-        let offset = data
-            .pwrite_with::<AnkiVehicleMsgChangeLane>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsgChangeLane as bytes");
+    // None until the vehicle has answered a version request.
+    pub fn firmware(&self) -> Option<FirmwareVersion> {
+        (self.version != 0).then(|| FirmwareVersion::from_packed(self.version))
+    }
 
-        commands.push(data[..offset].to_vec());
+    // The dialect the vehicle speaks, going by the firmware version and then the model.
+    pub fn dialect(&self) -> ProtocolDialect {
+        ProtocolDialect::detect(self.model_id, self.firmware())
+    }
 
-        commands
+    // The commands to send, in order, to a vehicle that has just connected.
+    pub fn configure(&mut self) -> [VehicleCommand; CONFIGURE_COMMAND_COUNT] {
+        [
+            VehicleCommand::SdkMode {
+                on: true,
+                flags: self.sdk_flags,
+            },
+            VehicleCommand::VersionRequest,
+            VehicleCommand::BatteryLevelRequest,
+            VehicleCommand::SetOffsetFromRoadCentre { offset_mm: 0.0 },
+            VehicleCommand::ChangeLane {
+                horizontal_speed_mm_per_sec: self.lane_change_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2: self.lane_change_accel_mm_per_sec2,
+                offset_from_road_centre_mm: 0.0,
+            },
+        ]
     }
 
     pub fn process_battery_level_response(&mut self, data: AnkiVehicleMsgBatteryLevelResponse) {
@@ -153,7 +473,16 @@ impl AnkiVehicleData {
         self.version = data.version;
     }
 
+    pub fn process_charger_info(&mut self, data: AnkiVehicleMsgChargerInfo) {
+        self.state = AnkiVehicleState {
+            low_battery: data.battery_low != 0,
+            full_battery: data.battery_full != 0,
+            on_charger: data.on_charger != 0,
+        };
+    }
+
     pub fn process_position_update(&mut self, data: AnkiVehicleMsgLocalisationPositionUpdate) {
+        self.localized = true;
         self.location_id = data.location_id;
         self.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
         self.speed_mm_per_sec = data.speed_mm_per_sec;
@@ -164,6 +493,7 @@ impl AnkiVehicleData {
     }
 
     pub fn process_transition_update(&mut self, data: AnkiVehicleMsgLocalisationTransitionUpdate) {
+        self.localized = true;
         self.road_piece_idx = data.road_piece_idx;
         self.road_piece_idx_prev = data.road_piece_idx_prev;
         self.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
@@ -186,6 +516,17 @@ impl AnkiVehicleData {
         self.mm_since_last_intersection_code = data.mm_since_last_intersection_code;
     }
 
+    // The last position no longer holds, so it is cleared rather than left looking current. The
+    // speed stays, the vehicle keeps driving.
+    pub fn process_delocalized(&mut self, _data: AnkiVehicleMsgVehicleDelocalized) {
+        self.localized = false;
+        self.laps.process_delocalized();
+        self.location_id = 0;
+        self.offset_from_road_centre_mm = 0.0;
+        self.road_piece_idx = 0;
+        self.road_piece_idx_prev = 0;
+    }
+
     pub fn process_offset_from_road_centre_update(
         &mut self,
         data: AnkiVehicleMsgOffsetFromRoadCentreUpdate,
@@ -193,40 +534,212 @@ impl AnkiVehicleData {
         self.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
     }
 
-    pub fn set_speed(speed_mm_per_sec: i16, accel_mm_per_sec2: i16) -> Vec<u8> {
-        let msg: AnkiVehicleMsgSetSpeed =
-            anki_vehicle_msg_set_speed(speed_mm_per_sec, accel_mm_per_sec2);
-        let mut set_speed = [0u8; ANKI_VEHICLE_MSG_SET_SPEED_SIZE];
-        let offset = set_speed
-            .pwrite_with::<AnkiVehicleMsgSetSpeed>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsgSetSpeed as bytes");
+    // Hands a parsed message to the matching process_* function.
+    pub fn process_message(&mut self, msg: VehicleMessage) -> StateChange {
+        self.process_message_at(msg, Instant::now())
+    }
 
-        set_speed[..offset].to_vec()
+    // Same as `process_message`, with the time the message arrived given rather than taken from
+    // the system clock. It is what the freshness of the state is measured from.
+    pub fn process_message_at(&mut self, msg: VehicleMessage, at: Instant) -> StateChange {
+        if let Some(group) = StateGroup::of(&msg.msg_type()) {
+            self.freshness.touch(group, at);
+        }
+        match msg {
+            VehicleMessage::VersionResponse(data) => {
+                self.process_version_response(data);
+                StateChange::Version(self.version)
+            }
+            VehicleMessage::BatteryLevelResponse(data) => {
+                self.process_battery_level_response(data);
+                StateChange::Battery(self.battery_level)
+            }
+            VehicleMessage::PositionUpdate(data) => {
+                let lap = self.laps.process_position_update(&data, at);
+                self.process_position_update(data);
+                match lap {
+                    Some(lap) => StateChange::LapCompleted {
+                        lap,
+                        location_id: self.location_id,
+                        speed_mm_per_sec: self.speed_mm_per_sec,
+                        offset_from_road_centre_mm: self.offset_from_road_centre_mm,
+                    },
+                    None => StateChange::Position {
+                        location_id: self.location_id,
+                        speed_mm_per_sec: self.speed_mm_per_sec,
+                        offset_from_road_centre_mm: self.offset_from_road_centre_mm,
+                    },
+                }
+            }
+            VehicleMessage::TransitionUpdate(data) => {
+                self.process_transition_update(data);
+                StateChange::Transition {
+                    road_piece_idx: self.road_piece_idx,
+                    road_piece_idx_prev: self.road_piece_idx_prev,
+                }
+            }
+            VehicleMessage::IntersectionUpdate(data) => {
+                self.process_intersection_update(data);
+                StateChange::Intersection {
+                    intersection_code: self.intersection_code.clone(),
+                    is_exiting: self.is_exiting_intersection(),
+                }
+            }
+            VehicleMessage::OffsetFromRoadCentreUpdate(data) => {
+                self.process_offset_from_road_centre_update(data);
+                StateChange::Offset(self.offset_from_road_centre_mm)
+            }
+            VehicleMessage::Delocalized(data) => {
+                self.process_delocalized(data);
+                StateChange::Delocalized
+            }
+            VehicleMessage::PingResponse(_) => self
+                .pings
+                .response_at(at)
+                .map_or(StateChange::None, StateChange::Latency),
+            VehicleMessage::ChargerInfo(data) => {
+                self.process_charger_info(data);
+                StateChange::Charger(self.state.clone())
+            }
+            VehicleMessage::SpeedUpdate(_)
+            | VehicleMessage::CollisionDetected(_)
+            | VehicleMessage::CycleOvertime(_)
+            | VehicleMessage::Unknown { .. } => StateChange::None,
+        }
+    }
+
+    // Parses a raw notification and processes it, see `process_message`.
+    pub fn process_raw(&mut self, data: &[u8]) -> Result<StateChange, AnkiError> {
+        self.process_frame_at(data, Instant::now())
+            .map(|(_, change)| change)
+    }
+
+    // Reads a raw notification and hands it to the matching process_* function. Messages that
+    // don't carry vehicle state are read but otherwise ignored.
+    pub fn process_notification(&mut self, data: &[u8]) -> Result<AnkiVehicleMsgType, AnkiError> {
+        self.process_notification_at(data, Instant::now())
+    }
+
+    // Same as `process_notification`, with the time the notification arrived given rather than
+    // taken from the system clock.
+    pub fn process_notification_at(
+        &mut self,
+        data: &[u8],
+        at: Instant,
+    ) -> Result<AnkiVehicleMsgType, AnkiError> {
+        self.process_frame_at(data, at).map(|(msg_id, _)| msg_id)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(vehicle = %self.name, len = data.len()))
+    )]
+    fn process_frame_at(
+        &mut self,
+        data: &[u8],
+        at: Instant,
+    ) -> Result<(AnkiVehicleMsgType, StateChange), AnkiError> {
+        let result = self.decode_notification(data).map(|msg| {
+            let msg_id = msg.msg_type();
+            trace_event!(target: TARGET_PROTOCOL, trace, msg_id = ?msg_id, "Decoded notification");
+            (msg_id, self.process_message_at(msg, at))
+        });
+        let result = result.inspect_err(|_e| {
+            trace_event!(target: TARGET_PROTOCOL, debug, error = %_e, "Dropped malformed notification");
+        });
+        #[cfg(feature = "metrics")]
+        fleet_metrics::record_notification(&self.name, result.as_ref().map(|(msg_id, _)| msg_id));
+        result
+    }
+
+    pub(crate) fn decode_notification<'a>(
+        &self,
+        data: &'a [u8],
+    ) -> Result<VehicleMessage<'a>, AnkiError> {
+        match self.validation_mode {
+            ValidationMode::Lenient => VehicleMessage::parse_padded(data),
+            ValidationMode::Strict => {
+                validate_notification(data)?;
+                VehicleMessage::parse(data)
+            }
+        }
+    }
+
+    pub fn set_speed(speed_mm_per_sec: i16, accel_mm_per_sec2: i16) -> Command {
+        Command::encode(
+            anki_vehicle_msg_set_speed(speed_mm_per_sec, accel_mm_per_sec2),
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        )
+        .expect("Failed to write AnkiVehicleMsgSetSpeed as bytes")
     }
 
     pub fn change_lane(
         horizontal_speed_mm_per_sec: u16,
         horizontal_accel_mm_per_sec2: u16,
         offset_from_road_centre: f32,
-    ) -> Vec<u8> {
-        let msg: AnkiVehicleMsgChangeLane = anki_vehicle_msg_change_lane(
-            horizontal_speed_mm_per_sec,
-            horizontal_accel_mm_per_sec2,
-            offset_from_road_centre,
-        );
-        let mut change_lane = [0u8; ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE];
-        let offset = change_lane
-            .pwrite_with::<AnkiVehicleMsgChangeLane>(msg, 0, scroll::LE)
-            .expect("Failed to write AnkiVehicleMsgChangeLane as bytes");
+    ) -> Command {
+        Command::encode(
+            anki_vehicle_msg_change_lane(
+                horizontal_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2,
+                offset_from_road_centre,
+            ),
+            ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+        )
+        .expect("Failed to write AnkiVehicleMsgChangeLane as bytes")
+    }
+
+    // A lane change to `offset_mm` from the road centre, at the lane change speed and acceleration
+    // the vehicle was set up with.
+    pub fn change_lane_to(&self, offset_mm: f32) -> Command {
+        AnkiVehicleData::change_lane(
+            self.lane_change_speed_mm_per_sec,
+            self.lane_change_accel_mm_per_sec2,
+            offset_mm,
+        )
+    }
+
+    pub fn lane_layout(&self) -> &LaneLayout {
+        &self.lane_layout
+    }
+
+    // The lane the vehicle is nearest to going by its last reported offset.
+    pub fn lane(&self) -> u8 {
+        self.lane_layout
+            .lane_for_offset(self.offset_from_road_centre_mm)
+    }
+
+    // A lane change to the middle of `lane`, None for a lane the layout doesn't have.
+    pub fn change_to_lane(&self, lane: u8) -> Option<Command> {
+        Some(self.change_lane_to(self.lane_layout.offset_for_lane(lane)?))
+    }
+
+    pub fn stop() -> Command {
+        AnkiVehicleData::set_speed(0, STOP_ACCEL_MM_PER_SEC2)
+    }
+
+    // Turns around on the spot, without waiting for an intersection.
+    pub fn u_turn() -> Command {
+        Command::encode(
+            anki_vehicle_msg_turn(VehicleTurn::UTurn, VehicleTurnTrigger::Immediate),
+            ANKI_VEHICLE_MSG_TURN_SIZE,
+        )
+        .expect("Failed to write AnkiVehicleMsgTurn as bytes")
+    }
 
-        change_lane[..offset].to_vec()
+    pub fn request_battery() -> Command {
+        Command::BATTERY_LEVEL_REQUEST
+    }
+
+    pub fn request_version() -> Command {
+        Command::VERSION_REQUEST
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::advertisement::AnkiVehicleState;
-    use scroll::{Pread, Pwrite, BE};
+    use scroll::{Pread, Pwrite};
 
     use crate::protocol::{
         AnkiVehicleMsgType, LightChannel, LightEffect, VehicleTurn, VehicleTurnTrigger,
@@ -242,7 +755,7 @@ mod tests {
         ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE, ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
         ANKI_VEHICLE_MSG_SET_SPEED_SIZE, ANKI_VEHICLE_MSG_TURN_SIZE,
         ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE, ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE,
-        SUPERCODE_BOOST_JUMP,
+        SUPERCODE_BOOST_JUMP, WIRE_ENDIAN,
     };
 
     #[test]
@@ -252,52 +765,56 @@ mod tests {
         let msg: AnkiVehicleMsgSetSpeed = anki_vehicle_msg_set_speed(2, 25);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SET_SPEED_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSetSpeed>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSetSpeed>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSetSpeed as bytes");
         println!("AnkiVehicleMsgSetSpeed T:{:?}", test_data);
     }
 
     #[test]
-    fn anki_vehicle_msg_struct_read<'a>() {
+    fn anki_vehicle_msg_struct_read() {
         use crate::protocol::{anki_vehicle_msg_ping, AnkiVehicleMsg};
 
-        let data: &'a [u8; ANKI_VEHICLE_MSG_PING_SIZE] = &[0x1, 0x16];
+        let data: &[u8; ANKI_VEHICLE_MSG_PING_SIZE] = &[0x1, 0x16];
         let msg: AnkiVehicleMsg = anki_vehicle_msg_ping();
-        let test_msg = data.gread_with::<AnkiVehicleMsg<'a>>(&mut 0, BE).unwrap();
+        let test_msg = data
+            .gread_with::<AnkiVehicleMsg<'_>>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
     }
 
     #[test]
-    fn anki_vehicle_msg_struct_write<'a>() {
+    fn anki_vehicle_msg_struct_write() {
         use crate::protocol::{anki_vehicle_msg_ping, AnkiVehicleMsg};
 
         let data: &[u8; ANKI_VEHICLE_MSG_PING_SIZE] =
             &[0x1, AnkiVehicleMsgType::C2CPingRequest as u8];
-        let msg: AnkiVehicleMsg<'a> = anki_vehicle_msg_ping();
+        let msg: AnkiVehicleMsg<'_> = anki_vehicle_msg_ping();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_PING_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg<'a>>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg<'_>>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSdkMode as bytes");
         println!("AnkiVehicleMsgSdkMode T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
     }
 
     #[test]
-    fn anki_vehicle_msg_check_and_read<'a>() {
+    fn anki_vehicle_msg_check_and_read() {
         use crate::protocol::{AnkiVehicleMsg, AnkiVehicleMsgBatteryLevelResponse};
 
-        let data: &'a [u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE] = &[
+        let data: &[u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE] = &[
             0x3,
             AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
-            0xAB,
             0xCD,
+            0xAB,
         ];
 
-        let msg = data.gread_with::<AnkiVehicleMsg>(&mut 0, BE).unwrap();
+        let msg = data
+            .gread_with::<AnkiVehicleMsg>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
         if msg.msg_id == AnkiVehicleMsgType::V2CBatteryLevelResponse {
             let test_msg = data
-                .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, BE)
+                .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, WIRE_ENDIAN)
                 .unwrap();
             println!("T:{:?} == G:{:?}", test_msg, data);
             assert_eq!(0xABCD, test_msg.battery_level)
@@ -313,11 +830,11 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE] = &[
             0x3,
             AnkiVehicleMsgType::V2CVersionResponse as u8,
-            0xAB,
             0xCD,
+            0xAB,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgVersionResponse>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgVersionResponse>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(0xABCD, test_msg.version)
@@ -330,11 +847,11 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE] = &[
             0x3,
             AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
-            0xAB,
             0xCD,
+            0xAB,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(0xABCD, test_msg.battery_level)
@@ -349,7 +866,7 @@ mod tests {
         let msg: AnkiVehicleMsgSdkMode = anki_vehicle_msg_set_sdk_mode(1, 0);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SDK_MODE_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSdkMode>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSdkMode>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSdkMode as bytes");
         println!("AnkiVehicleMsgSdkMode T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -362,16 +879,16 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_SET_SPEED_SIZE] = &[
             0x6,
             AnkiVehicleMsgType::C2VSetSpeed as u8,
-            0x7B,
             0xCD,
             0x7B,
             0xCD,
+            0x7B,
             0x0,
         ];
         let msg: AnkiVehicleMsgSetSpeed = anki_vehicle_msg_set_speed(0x7BCD, 0x7BCD);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SET_SPEED_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSetSpeed>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSetSpeed>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSetSpeed as bytes");
         println!("AnkiVehicleMsgSetSpeed T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -387,7 +904,7 @@ mod tests {
             anki_vehicle_msg_turn(VehicleTurn::Left, VehicleTurnTrigger::Intersection);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_TURN_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgTurn>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgTurn>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgTurn as bytes");
         println!("AnkiVehicleMsgTurn T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -402,16 +919,16 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE] = &[
             5,
             AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre as u8,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
         ];
         let msg: AnkiVehicleMsgSetOffsetFromRoadCentre =
             anki_vehicle_msg_set_offset_from_road_centre(100.0);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSetOffsetFromRoadCentre>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSetOffsetFromRoadCentre>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSetOffsetFromRoadCentre as bytes");
         println!(
             "AnkiVehicleMsgSetOffsetFromRoadCentre T:{:?} == G:{:?}",
@@ -427,26 +944,56 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE] = &[
             11,
             AnkiVehicleMsgType::C2VChangeLane as u8,
-            0,
             10,
             0,
             100,
-            65,
-            160,
             0,
             0,
             0,
+            160,
+            65,
+            0,
             0,
         ];
         let msg: AnkiVehicleMsgChangeLane = anki_vehicle_msg_change_lane(10, 100, 20.0);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgChangeLane>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgChangeLane>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgChangeLane as bytes");
         println!("AnkiVehicleMsgChangeLane T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
     }
 
+    #[test]
+    fn vehicle_command_helpers_test() {
+        use crate::command::WireMessage;
+        use crate::{AnkiVehicleData, STOP_ACCEL_MM_PER_SEC2};
+
+        let vehicle = AnkiVehicleData::default().with_lane_change(400, 3000);
+        assert_eq!(
+            AnkiVehicleData::change_lane(400, 3000, -23.0),
+            vehicle.change_lane_to(-23.0)
+        );
+        assert_eq!(
+            AnkiVehicleData::set_speed(0, STOP_ACCEL_MM_PER_SEC2),
+            AnkiVehicleData::stop()
+        );
+        assert_eq!(
+            Some(vehicle.change_lane_to(67.5)),
+            vehicle.change_to_lane(4)
+        );
+        assert_eq!(None, vehicle.change_to_lane(5));
+        assert_eq!(3, vehicle.lane());
+
+        let u_turn = AnkiVehicleData::u_turn();
+        assert_eq!(AnkiVehicleMsgType::C2VTurn, u_turn.msg_type());
+        assert_eq!([VehicleTurn::UTurn as u8, 0], u_turn[2..]);
+        assert_eq!(
+            AnkiVehicleMsgType::C2VBatteryLevelRequest,
+            AnkiVehicleData::request_battery().msg_type()
+        );
+    }
+
     #[test]
     fn anki_vehicle_msg_localisation_position_update_struct_test() {
         use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
@@ -456,29 +1003,29 @@ mod tests {
             AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
             0xA,
             0xB,
-            66,
-            200,
             0,
             0,
-            0xCD,
+            200,
+            66,
             0xEF,
+            0xCD,
             1,
             2,
             3,
-            0x44,
             0x55,
-            0x66,
+            0x44,
             0x77,
+            0x66,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(0xA, test_msg.location_id);
         assert_eq!(0xB, test_msg.road_piece_id);
         assert_eq!(100.0, test_msg.offset_from_road_centre_mm);
         assert_eq!(0xCDEF, test_msg.speed_mm_per_sec);
-        assert_eq!(0x1, test_msg.parsing_flags);
+        assert_eq!(0x1, test_msg.parsing_flags.bits());
         assert_eq!(0x2, test_msg.last_recv_lane_change_cmd_id);
         assert_eq!(0x3, test_msg.last_exec_lane_change_cmd_id);
         assert_eq!(0x4455, test_msg.last_desired_lane_change_speed_mm_per_sec);
@@ -494,14 +1041,14 @@ mod tests {
             AnkiVehicleMsgType::V2CLocalisationTransitionUpdate as u8,
             0xA,
             0xB,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             0xC,
             0xD,
-            0x7E,
             0xF0,
+            0x7E,
             1,
             0x1,
             0x2,
@@ -510,7 +1057,7 @@ mod tests {
             0x5,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(0xA, test_msg.road_piece_idx);
@@ -535,19 +1082,19 @@ mod tests {
             12,
             AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate as u8,
             1,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             IntersectionCode::EntryFirst as u8,
             0xB,
-            0xCD,
             0xEF,
-            0x12,
+            0xCD,
             0x34,
+            0x12,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(1, test_msg.road_piece_idx);
@@ -565,14 +1112,14 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE] = &[
             6,
             AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate as u8,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             0xAB,
         ];
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, data);
         assert_eq!(100.0, test_msg.offset_from_road_centre_mm);
@@ -588,7 +1135,7 @@ mod tests {
         let msg: AnkiVehicleMsgSetLights = anki_vehicle_msg_set_lights(0xAB);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSetLights>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSetLights>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSetLights as bytes");
         println!("AnkiVehicleMsgSetLights T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -609,7 +1156,7 @@ mod tests {
             &anki_vehicle_light_config(LightChannel::Tail, LightEffect::Flash, 0xA, 0xB, 600);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_LIGHT_CONFIG_SIZE];
         test_data
-            .gwrite_with::<&AnkiVehicleLightConfig>(config, &mut 0, BE)
+            .gwrite_with::<&AnkiVehicleLightConfig>(config, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleLightConfig as bytes");
         println!("AnkiVehicleLightConfig T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -646,10 +1193,11 @@ mod tests {
             anki_vehicle_msg_lights_pattern(LightChannel::FrontL, LightEffect::Fade, 0xA, 0xB, 600);
         let config2: AnkiVehicleLightConfig =
             anki_vehicle_light_config(LightChannel::Tail, LightEffect::Flash, 0xC, 0xD, 600);
-        config.append(config2);
+        assert_eq!(2, config.append(config2).unwrap());
+        assert_eq!(1, config.remaining());
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgLightsPattern>(config, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgLightsPattern>(config, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgLightsPattern as bytes");
         println!(
             "AnkiVehicleMsgLightsPattern T:{:?} == G:{:?}",
@@ -658,6 +1206,34 @@ mod tests {
         assert_eq!(data, test_data)
     }
 
+    #[test]
+    fn anki_vehicle_msg_lights_pattern_full_test() {
+        use crate::protocol::{
+            anki_vehicle_light_config, anki_vehicle_msg_lights_pattern_empty,
+            AnkiVehicleMsgLightsPattern,
+        };
+
+        let mut pattern = anki_vehicle_msg_lights_pattern_empty();
+        assert_eq!(3, AnkiVehicleMsgLightsPattern::capacity());
+        for channel in [LightChannel::Red, LightChannel::Green, LightChannel::Blue] {
+            let config = anki_vehicle_light_config(channel, LightEffect::Steady, 0, 14, 0);
+            pattern.append(config).unwrap();
+        }
+        assert!(pattern.is_full());
+        assert!(matches!(
+            pattern.append(anki_vehicle_light_config(
+                LightChannel::Tail,
+                LightEffect::Steady,
+                0,
+                14,
+                0
+            )),
+            Err(crate::error::AnkiError::LightsPatternFull(3))
+        ));
+        assert_eq!(3, pattern.channel_count());
+        assert_eq!(3, pattern.channels().count());
+    }
+
     #[test]
     fn anki_vehicle_msg_ping_request_test() {
         use crate::protocol::{anki_vehicle_msg_ping, AnkiVehicleMsg};
@@ -667,7 +1243,7 @@ mod tests {
         let msg: AnkiVehicleMsg = anki_vehicle_msg_ping();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_PING_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsg as bytes");
         println!("AnkiVehicleMsg (Ping) T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -682,7 +1258,7 @@ mod tests {
         let msg: AnkiVehicleMsg = anki_vehicle_msg_disconnect();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_DISCONNECT_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsg as bytes");
         println!(
             "AnkiVehicleMsg (Disconnect) T:{:?} == G:{:?}",
@@ -700,7 +1276,7 @@ mod tests {
         let msg: AnkiVehicleMsg = anki_vehicle_msg_get_version();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsg as bytes");
         println!("AnkiVehicleMsg (Version) T:{:?} == G:{:?}", test_data, data);
         assert_eq!(data, test_data)
@@ -715,7 +1291,7 @@ mod tests {
         let msg: AnkiVehicleMsg = anki_vehicle_msg_get_battery_level();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsg as bytes");
         println!(
             "AnkiVehicleMsg (Battery Level) T:{:?} == G:{:?}",
@@ -733,7 +1309,7 @@ mod tests {
         let msg: AnkiVehicleMsg = anki_vehicle_msg_cancel_lane_change();
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_CANCEL_LANE_CHANGE_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsg>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsg as bytes");
         println!(
             "AnkiVehicleMsg (Cancel Lane Change) T:{:?} == G:{:?}",
@@ -758,7 +1334,7 @@ mod tests {
             anki_vehicle_msg_set_config_params(SUPERCODE_BOOST_JUMP, TrackMaterial::Plastic);
         let test_data: &mut [u8] = &mut [0u8; ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE];
         test_data
-            .gwrite_with::<AnkiVehicleMsgSetConfigParams>(msg, &mut 0, BE)
+            .gwrite_with::<AnkiVehicleMsgSetConfigParams>(msg, &mut 0, WIRE_ENDIAN)
             .expect("Failed to write AnkiVehicleMsgSetConfigParams as bytes");
         println!(
             "AnkiVehicleMsgSetConfigParams T:{:?} == G:{:?}",
@@ -767,18 +1343,64 @@ mod tests {
         assert_eq!(data, test_data)
     }
 
+    #[test]
+    fn anki_vehicle_process_notification_test() {
+        use crate::AnkiVehicleData;
+
+        let data: &[u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE] = &[
+            0x3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0xCD,
+            0xAB,
+        ];
+        let mut vehicle = AnkiVehicleData::default();
+        let msg_id = vehicle.process_notification(data).unwrap();
+        println!("T:{:?} == G:{:?}", vehicle, data);
+        assert_eq!(AnkiVehicleMsgType::V2CBatteryLevelResponse, msg_id);
+        assert_eq!(0xABCD, vehicle.battery_level);
+        assert!(vehicle.process_notification(&data[..3]).is_err());
+    }
+
+    #[test]
+    fn anki_vehicle_builder_test() {
+        use crate::bt_address::BtAddress;
+        use crate::command::VehicleCommand;
+        use crate::dialect::ProtocolDialect;
+        use crate::AnkiVehicleData;
+
+        let address: BtAddress = "C2:4A:01:9E:33:0F".parse().unwrap();
+        let mut vehicle = AnkiVehicleData::new("Skull", address)
+            .with_model_id(0x09)
+            .with_sdk_flags(0)
+            .with_lane_change(150, 1000);
+        assert_eq!("Skull", vehicle.name());
+        assert_eq!(Some(address), vehicle.address());
+        assert_eq!(ProtocolDialect::Overdrive, vehicle.dialect());
+
+        let commands = vehicle.configure();
+        assert_eq!(VehicleCommand::SdkMode { on: true, flags: 0 }, commands[0]);
+        assert!(matches!(
+            commands[4],
+            VehicleCommand::ChangeLane {
+                horizontal_speed_mm_per_sec: 150,
+                horizontal_accel_mm_per_sec2: 1000,
+                ..
+            }
+        ));
+        assert_eq!(None, AnkiVehicleData::default().address());
+    }
+
     #[test]
     fn anki_vehicle_adv_local_name_struct_test() {
         use crate::advertisement::{AnkiVehicleAdvLocalName, ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE};
 
         let data: &[u8; ANKI_VEHICLE_ADV_LOCAL_NAME_SIZE] = &[
-            0x0, 0xCD, 0xEF, 0x1, 0x2, 0x3, 0x4, 0x5, 'l' as u8, 'o' as u8, 'c' as u8, 'a' as u8,
-            'l' as u8, 'n' as u8, 'a' as u8, 'm' as u8, 'e' as u8, 't' as u8, 'e' as u8, 's' as u8,
-            't' as u8,
+            0x0, 0xEF, 0xCD, 0x1, 0x2, 0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a',
+            b'm', b'e', b't', b'e', b's', b't',
         ];
 
         let test_local_name = data
-            .gread_with::<AnkiVehicleAdvLocalName>(&mut 0, BE)
+            .gread_with::<AnkiVehicleAdvLocalName>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_local_name, data);
         assert_eq!(
@@ -798,10 +1420,10 @@ mod tests {
         use crate::advertisement::{AnkiVehicleAdvMfgData, ANKI_VEHICLE_ADV_MFG_DATA_SIZE};
 
         let data: &[u8; ANKI_VEHICLE_ADV_MFG_DATA_SIZE] =
-            &[0x89, 0xAB, 0xCD, 0xEF, 0xAB, 0x12, 0xCD, 0xEF];
+            &[0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x12, 0xEF, 0xCD];
 
         let test_mfg_data = data
-            .gread_with::<AnkiVehicleAdvMfgData>(&mut 0, BE)
+            .gread_with::<AnkiVehicleAdvMfgData>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_mfg_data, data);
         assert_eq!(0x89ABCDEF, test_mfg_data.identifier);
@@ -810,20 +1432,21 @@ mod tests {
     }
 
     #[test]
-    fn anki_vehicle_adv_struct_test<'a>() {
+    fn anki_vehicle_adv_struct_test() {
         use crate::advertisement::{AnkiVehicleAdv, ANKI_VEHICLE_ADV_SIZE};
 
         let data: &[u8; ANKI_VEHICLE_ADV_SIZE] = &[
-            0x12, 0x34, 0x89, 0xAB, 0xCD, 0xEF, 0xAB, 0x56, 0xCD, 0xEF, 0x0, 0xCD, 0xEF, 0x1, 0x2,
-            0x3, 0x4, 0x5, 'l' as u8, 'o' as u8, 'c' as u8, 'a' as u8, 'l' as u8, 'n' as u8,
-            'a' as u8, 'm' as u8, 'e' as u8, 't' as u8, 'e' as u8, 's' as u8, 't' as u8, 0x0, 0x1,
-            0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
+            0x12, 0x34, 0xEF, 0xCD, 0xAB, 0x89, 0xAB, 0x56, 0xEF, 0xCD, 0x0, 0xEF, 0xCD, 0x1, 0x2,
+            0x3, 0x4, 0x5, b'l', b'o', b'c', b'a', b'l', b'n', b'a', b'm', b'e', b't', b'e', b's',
+            b't', 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
         ];
 
-        let test_adv = data.gread_with::<AnkiVehicleAdv>(&mut 0, BE).unwrap();
+        let test_adv = data
+            .gread_with::<AnkiVehicleAdv>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
         println!("T:{:?} == G:{:?}", test_adv, data);
 
-        let service_id: &'a [u8] = &[
+        let service_id: &[u8] = &[
             0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
         ];
 
@@ -0,0 +1,243 @@
+use rumqttc::{Client, ClientError, Connection, ConnectionError, Event, MqttOptions, Packet, QoS};
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::AnkiError;
+use crate::json::JsonMessage;
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+
+pub const MQTT_DEFAULT_TOPIC_PREFIX: &str = "anki";
+
+#[derive(Debug)]
+pub enum MqttBridgeError {
+    Client(ClientError),
+    Connection(Box<ConnectionError>),
+    Json(serde_json::Error),
+    Message(AnkiError),
+}
+
+impl fmt::Display for MqttBridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MqttBridgeError::Client(e) => write!(f, "MQTT client error: {}", e),
+            MqttBridgeError::Connection(e) => write!(f, "MQTT connection error: {}", e),
+            MqttBridgeError::Json(e) => write!(f, "Invalid JSON message: {}", e),
+            MqttBridgeError::Message(e) => write!(f, "Invalid vehicle message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MqttBridgeError {}
+
+impl From<ClientError> for MqttBridgeError {
+    fn from(e: ClientError) -> Self {
+        MqttBridgeError::Client(e)
+    }
+}
+
+impl From<ConnectionError> for MqttBridgeError {
+    fn from(e: ConnectionError) -> Self {
+        MqttBridgeError::Connection(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for MqttBridgeError {
+    fn from(e: serde_json::Error) -> Self {
+        MqttBridgeError::Json(e)
+    }
+}
+
+impl From<AnkiError> for MqttBridgeError {
+    fn from(e: AnkiError) -> Self {
+        MqttBridgeError::Message(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MqttCommand {
+    pub vehicle: String,
+    pub data: Vec<u8>,
+}
+
+// Topic layout, with the default prefix:
+//   anki/<vehicle>/telemetry/<msg_type>  JSON notifications published by the bridge
+//   anki/<vehicle>/command               JSON commands, see `JsonMessage`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MqttTopics {
+    pub prefix: String,
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        MqttTopics {
+            prefix: MQTT_DEFAULT_TOPIC_PREFIX.to_string(),
+        }
+    }
+}
+
+impl MqttTopics {
+    pub fn telemetry(&self, vehicle: &str, msg_type: &str) -> String {
+        format!("{}/{}/telemetry/{}", self.prefix, vehicle, msg_type)
+    }
+
+    pub fn command(&self, vehicle: &str) -> String {
+        format!("{}/{}/command", self.prefix, vehicle)
+    }
+
+    pub fn command_filter(&self) -> String {
+        format!("{}/+/command", self.prefix)
+    }
+
+    // Returns the vehicle a command topic is addressed to.
+    pub fn command_vehicle<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix('/')?
+            .strip_suffix("/command")
+            .filter(|vehicle| !vehicle.is_empty() && !vehicle.contains('/'))
+    }
+
+    pub fn parse_command(
+        &self,
+        topic: &str,
+        payload: &[u8],
+    ) -> Result<Option<MqttCommand>, MqttBridgeError> {
+        let Some(vehicle) = self.command_vehicle(topic) else {
+            return Ok(None);
+        };
+        let msg: JsonMessage = serde_json::from_slice(payload)?;
+        Ok(Some(MqttCommand {
+            vehicle: vehicle.to_string(),
+            data: msg.to_bytes()?,
+        }))
+    }
+}
+
+fn msg_type(msg: &JsonMessage) -> String {
+    serde_json::to_value(msg)
+        .ok()
+        .and_then(|value| value["msg_type"].as_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Publishes vehicle notifications as JSON telemetry and turns JSON commands received over MQTT
+// into frames ready to be written to the vehicles. `poll` must be called regularly, it drives
+// the MQTT connection.
+pub struct MqttBridge {
+    topics: MqttTopics,
+    client: Client,
+    connection: Connection,
+}
+
+impl MqttBridge {
+    pub fn connect(
+        options: MqttOptions,
+        topics: MqttTopics,
+    ) -> Result<MqttBridge, MqttBridgeError> {
+        trace_event!(target: TARGET_TRANSPORT, info, broker = ?options.broker_address(), "Connecting MQTT bridge");
+        let (client, connection) = Client::new(options, 64);
+        client.subscribe(topics.command_filter(), QoS::AtLeastOnce)?;
+        Ok(MqttBridge {
+            topics,
+            client,
+            connection,
+        })
+    }
+
+    pub fn topics(&self) -> &MqttTopics {
+        &self.topics
+    }
+
+    pub fn publish_message(&self, vehicle: &str, msg: &JsonMessage) -> Result<(), MqttBridgeError> {
+        let topic = self.topics.telemetry(vehicle, &msg_type(msg));
+        trace_event!(target: TARGET_TRANSPORT, trace, %topic, "Publishing telemetry");
+        self.client
+            .publish(topic, QoS::AtMostOnce, false, msg.to_json())?;
+        Ok(())
+    }
+
+    // Decodes a raw notification received from `vehicle` and publishes it.
+    pub fn publish_notification(&self, vehicle: &str, data: &[u8]) -> Result<(), MqttBridgeError> {
+        self.publish_message(vehicle, &JsonMessage::from_bytes(data)?)
+    }
+
+    // Waits up to `timeout` for network activity and returns the commands received. Commands
+    // that can't be parsed are dropped so a bad publisher can't stall the bridge.
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<MqttCommand>, MqttBridgeError> {
+        let mut commands = Vec::new();
+        let mut event = self.connection.recv_timeout(timeout).ok();
+        while let Some(result) = event {
+            if let Event::Incoming(Packet::Publish(publish)) = result? {
+                match self.topics.parse_command(&publish.topic, &publish.payload) {
+                    Ok(Some(command)) => commands.push(command),
+                    Ok(None) => {}
+                    Err(_e) => {
+                        trace_event!(target: TARGET_TRANSPORT, warn, topic = %publish.topic, error = %_e, "Dropped MQTT command");
+                    }
+                }
+            }
+            event = self.connection.try_recv().ok();
+        }
+        Ok(commands)
+    }
+
+    pub fn disconnect(&self) -> Result<(), MqttBridgeError> {
+        self.client.disconnect()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnkiVehicleData;
+
+    #[test]
+    fn mqtt_topics_test() {
+        let topics = MqttTopics::default();
+        assert_eq!(
+            "anki/Skull/telemetry/position_update",
+            topics.telemetry("Skull", "position_update")
+        );
+        assert_eq!(Some("Skull"), topics.command_vehicle("anki/Skull/command"));
+        assert_eq!(
+            None,
+            topics.command_vehicle("anki/Skull/telemetry/ping_response")
+        );
+        assert_eq!(None, topics.command_vehicle("other/Skull/command"));
+        assert_eq!(None, topics.command_vehicle("anki//command"));
+    }
+
+    #[test]
+    fn mqtt_parse_command_test() {
+        let topics = MqttTopics::default();
+        let command = topics
+            .parse_command(
+                "anki/Skull/command",
+                br#"{"msg_type":"set_speed","speed_mm_per_sec":500,"accel_mm_per_sec2":1000}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            Some(MqttCommand {
+                vehicle: "Skull".to_string(),
+                data: AnkiVehicleData::set_speed(500, 1000).to_vec(),
+            }),
+            command
+        );
+        assert!(topics
+            .parse_command("anki/Skull/command", br#"{"msg_type":"ping_response"}"#)
+            .is_err());
+        assert_eq!(
+            None,
+            topics.parse_command("anki/Skull/state", b"{}").unwrap()
+        );
+    }
+
+    #[test]
+    fn mqtt_msg_type_test() {
+        assert_eq!(
+            "vehicle_delocalized",
+            msg_type(&JsonMessage::VehicleDelocalized)
+        );
+    }
+}
@@ -0,0 +1,734 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use scroll::ctx::StrCtx;
+use scroll::{self, ctx, Pread, Pwrite, LE};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use thiserror::Error;
+
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::AnkiVehicleData;
+
+// Coordination frames are always little-endian, independent of the vehicle wire format.
+pub const NET_MAGIC: u16 = 0xA4D1;
+pub const NET_PROTOCOL_VERSION: u8 = 1;
+pub const NET_HEADER_SIZE: usize = 6;
+pub const NET_MSG_MAX_SIZE: usize = 512;
+pub const NET_NAME_MAX_SIZE: usize = 64;
+// Bytes a coordination link keeps for a peer that has stopped reading before the link gives up.
+pub const NET_LINK_MAX_PENDING: usize = 64 * 1024;
+// A vehicle state this far behind the last one taken is a late datagram and dropped. Any further
+// back and the owning host is taken to have restarted its count.
+pub const NET_SEQUENCE_REORDER_WINDOW: u32 = 64;
+
+// Why a coordination frame couldn't be read or written.
+#[derive(Debug, Error)]
+pub enum NetError {
+    #[error("Coordination frame of {0} bytes, expected {NET_HEADER_SIZE} to {NET_MSG_MAX_SIZE}")]
+    FrameSize(usize),
+    // The magic or protocol version doesn't match, the frame is from something else or a host
+    // running another version.
+    #[error(
+        "Not a coordination message of a supported version: magic {magic:#06x}, version {version}"
+    )]
+    UnsupportedHeader { magic: u16, version: u8 },
+    #[error("Unknown coordination message type {0}")]
+    UnknownMsgType(u8),
+    #[error("Name of {0} bytes too long for coordination message, at most {NET_NAME_MAX_SIZE}")]
+    NameTooLong(usize),
+    #[error(transparent)]
+    Scroll(#[from] scroll::Error),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, TryFromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum NetMsgType {
+    Unknown = 0,
+    Hello = 1,
+    VehicleState = 2,
+    RaceEvent = 3,
+    Goodbye = 4,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NetVehicleState {
+    pub vehicle: String,
+    // Incremented by the owning host, stale UDP datagrams are dropped.
+    pub sequence: u32,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub battery_level: u16,
+}
+
+impl NetVehicleState {
+    pub fn from_vehicle(sequence: u32, vehicle: &AnkiVehicleData) -> NetVehicleState {
+        NetVehicleState {
+            vehicle: vehicle.name.clone(),
+            sequence,
+            location_id: vehicle.location_id,
+            road_piece_idx: vehicle.road_piece_idx,
+            offset_from_road_centre_mm: vehicle.offset_from_road_centre_mm,
+            speed_mm_per_sec: vehicle.speed_mm_per_sec,
+            battery_level: vehicle.battery_level,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, TryFromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum NetRaceEventKind {
+    Unknown = 0,
+    CountdownStarted = 1,
+    Started = 2,
+    LapCompleted = 3,
+    Finished = 4,
+    Stopped = 5,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NetRaceEvent {
+    pub kind: NetRaceEventKind,
+    pub vehicle: String,
+    pub lap: u16,
+    pub time_ms: u32,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum NetMessageBody {
+    Hello { host_name: String },
+    VehicleState(NetVehicleState),
+    RaceEvent(NetRaceEvent),
+    Goodbye,
+}
+
+impl NetMessageBody {
+    pub fn msg_type(&self) -> NetMsgType {
+        match self {
+            NetMessageBody::Hello { .. } => NetMsgType::Hello,
+            NetMessageBody::VehicleState(_) => NetMsgType::VehicleState,
+            NetMessageBody::RaceEvent(_) => NetMsgType::RaceEvent,
+            NetMessageBody::Goodbye => NetMsgType::Goodbye,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NetMessage {
+    pub host_id: u16,
+    pub body: NetMessageBody,
+}
+
+fn read_name<'a>(data: &'a [u8], offset: &mut usize) -> Result<&'a str, NetError> {
+    let len = data.gread_with::<u8>(offset, LE)? as usize;
+    if len > NET_NAME_MAX_SIZE {
+        return Err(NetError::NameTooLong(len));
+    }
+    Ok(data.gread_with::<&'a str>(offset, StrCtx::Length(len))?)
+}
+
+fn write_name(data: &mut [u8], name: &str, offset: &mut usize) -> Result<(), NetError> {
+    if name.len() > NET_NAME_MAX_SIZE {
+        return Err(NetError::NameTooLong(name.len()));
+    }
+    data.gwrite_with::<u8>(name.len() as u8, offset, LE)?;
+    data.gwrite::<&str>(name, offset)?;
+    Ok(())
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for NetVehicleState {
+    type Error = NetError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let vehicle = read_name(data, offset)?.to_string();
+        let sequence: u32 = data.gread_with::<u32>(offset, ctx)?;
+        let location_id: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let road_piece_idx: i8 = data.gread_with::<i8>(offset, ctx)?;
+        let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
+        let speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let battery_level: u16 = data.gread_with::<u16>(offset, ctx)?;
+
+        Ok((
+            NetVehicleState {
+                vehicle,
+                sequence,
+                location_id,
+                road_piece_idx,
+                offset_from_road_centre_mm,
+                speed_mm_per_sec,
+                battery_level,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for &NetVehicleState {
+    type Error = NetError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+        write_name(data, &self.vehicle, offset)?;
+        data.gwrite_with::<u32>(self.sequence, offset, ctx)?;
+        data.gwrite_with::<u8>(self.location_id, offset, ctx)?;
+        data.gwrite_with::<i8>(self.road_piece_idx, offset, ctx)?;
+        data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
+        data.gwrite_with::<u16>(self.speed_mm_per_sec, offset, ctx)?;
+        data.gwrite_with::<u16>(self.battery_level, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for NetRaceEvent {
+    type Error = NetError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let kind: NetRaceEventKind = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(NetRaceEventKind::Unknown);
+        let vehicle = read_name(data, offset)?.to_string();
+        let lap: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let time_ms: u32 = data.gread_with::<u32>(offset, ctx)?;
+
+        Ok((
+            NetRaceEvent {
+                kind,
+                vehicle,
+                lap,
+                time_ms,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for &NetRaceEvent {
+    type Error = NetError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.kind.into(), offset, ctx)?;
+        write_name(data, &self.vehicle, offset)?;
+        data.gwrite_with::<u16>(self.lap, offset, ctx)?;
+        data.gwrite_with::<u32>(self.time_ms, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for NetMessage {
+    type Error = NetError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        if data.len() < NET_HEADER_SIZE || data.len() > NET_MSG_MAX_SIZE {
+            return Err(NetError::FrameSize(data.len()));
+        }
+
+        let offset = &mut 0;
+        let magic: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let version: u8 = data.gread_with::<u8>(offset, ctx)?;
+        if magic != NET_MAGIC || version != NET_PROTOCOL_VERSION {
+            return Err(NetError::UnsupportedHeader { magic, version });
+        }
+        let msg_id: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_type =
+            NetMsgType::try_from(msg_id).map_err(|_| NetError::UnknownMsgType(msg_id))?;
+        let host_id: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let body = match msg_type {
+            NetMsgType::Hello => NetMessageBody::Hello {
+                host_name: read_name(data, offset)?.to_string(),
+            },
+            NetMsgType::VehicleState => {
+                NetMessageBody::VehicleState(data.gread_with::<NetVehicleState>(offset, ctx)?)
+            }
+            NetMsgType::RaceEvent => {
+                NetMessageBody::RaceEvent(data.gread_with::<NetRaceEvent>(offset, ctx)?)
+            }
+            NetMsgType::Goodbye => NetMessageBody::Goodbye,
+            NetMsgType::Unknown => return Err(NetError::UnknownMsgType(msg_id)),
+        };
+
+        Ok((NetMessage { host_id, body }, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for &NetMessage {
+    type Error = NetError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        let offset = &mut 0;
+        data.gwrite_with::<u16>(NET_MAGIC, offset, ctx)?;
+        data.gwrite_with::<u8>(NET_PROTOCOL_VERSION, offset, ctx)?;
+        data.gwrite_with::<u8>(self.body.msg_type().into(), offset, ctx)?;
+        data.gwrite_with::<u16>(self.host_id, offset, ctx)?;
+        match &self.body {
+            NetMessageBody::Hello { host_name } => write_name(data, host_name, offset)?,
+            NetMessageBody::VehicleState(state) => {
+                data.gwrite_with::<&NetVehicleState>(state, offset, ctx)?;
+            }
+            NetMessageBody::RaceEvent(event) => {
+                data.gwrite_with::<&NetRaceEvent>(event, offset, ctx)?;
+            }
+            NetMessageBody::Goodbye => {}
+        }
+
+        Ok(*offset)
+    }
+}
+
+impl NetMessage {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, NetError> {
+        let mut data = [0u8; NET_MSG_MAX_SIZE];
+        let len = data.pwrite_with::<&NetMessage>(self, 0, LE)?;
+        Ok(data[..len].to_vec())
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<NetMessage, NetError> {
+        data.pread_with::<NetMessage>(0, LE)
+    }
+}
+
+fn invalid_data(err: NetError) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+// Reliable event link between two hosts. Frames are prefixed with a u16 LE length. The socket
+// never blocks, whatever it won't take yet is kept and written by the next `send` or `poll`.
+#[derive(Debug)]
+pub struct CoordinationLink {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+}
+
+impl CoordinationLink {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<CoordinationLink> {
+        CoordinationLink::from_stream(TcpStream::connect(addr)?)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<CoordinationLink> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        trace_event!(target: TARGET_TRANSPORT, info, peer = ?stream.peer_addr().ok(), "Coordination link up");
+        Ok(CoordinationLink {
+            stream,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+        })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(msg_type = ?msg.body.msg_type()))
+    )]
+    pub fn send(&mut self, msg: &NetMessage) -> io::Result<()> {
+        let bytes = msg.to_bytes().map_err(invalid_data)?;
+        trace_event!(target: TARGET_TRANSPORT, trace, len = bytes.len(), "Writing frame");
+        // Whole frames only, so a peer never sees half of one followed by the next.
+        if self.write_buf.len() + bytes.len() + 2 > NET_LINK_MAX_PENDING {
+            return Err(io::Error::other(format!(
+                "Coordination peer isn't reading, {} bytes still unsent",
+                self.write_buf.len()
+            )));
+        }
+        self.write_buf
+            .extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        self.write_buf.extend_from_slice(&bytes);
+        self.flush()
+    }
+
+    // Writes as much of what is waiting as the socket takes without blocking.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.write_buf.len() {
+                break Ok(());
+            }
+            match self.stream.write(&self.write_buf[written..]) {
+                Ok(0) => break Err(ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.write_buf.drain(..written);
+        result
+    }
+
+    // Bytes sent but not written to the socket yet.
+    pub fn pending(&self) -> usize {
+        self.write_buf.len()
+    }
+
+    // Returns every complete message that has arrived without blocking, after writing what
+    // earlier sends left behind.
+    pub fn poll(&mut self) -> io::Result<Vec<NetMessage>> {
+        self.flush()?;
+        let mut buf = [0u8; NET_MSG_MAX_SIZE];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    trace_event!(target: TARGET_TRANSPORT, info, peer = ?self.stream.peer_addr().ok(), "Coordination link closed");
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                Ok(n) => self.read_buf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut messages = Vec::new();
+        while self.read_buf.len() >= 2 {
+            let len = u16::from_le_bytes([self.read_buf[0], self.read_buf[1]]) as usize;
+            if self.read_buf.len() < len + 2 {
+                break;
+            }
+            let frame: Vec<u8> = self.read_buf.drain(..len + 2).skip(2).collect();
+            let msg = NetMessage::from_bytes(&frame).map_err(|e| {
+                trace_event!(target: TARGET_TRANSPORT, warn, error = %e, "Malformed frame on coordination link");
+                invalid_data(e)
+            })?;
+            messages.push(msg);
+        }
+        Ok(messages)
+    }
+}
+
+// What a poll brought in. Links that failed are taken out of the coordinator and handed back
+// with their error, the messages from every other source are returned all the same.
+#[derive(Debug, Default)]
+pub struct NetPoll {
+    pub messages: Vec<NetMessage>,
+    pub failed_links: Vec<(CoordinationLink, io::Error)>,
+}
+
+// Synchronises vehicle state over UDP (lossy, latest wins) and race events over TCP links
+// between hosts that each drive part of the fleet.
+#[derive(Debug)]
+pub struct RaceCoordinator {
+    host_id: u16,
+    socket: UdpSocket,
+    peers: Vec<SocketAddr>,
+    links: Vec<CoordinationLink>,
+    remote_vehicles: HashMap<String, (u16, NetVehicleState)>,
+}
+
+impl RaceCoordinator {
+    pub fn bind<A: ToSocketAddrs>(host_id: u16, addr: A) -> io::Result<RaceCoordinator> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        trace_event!(target: TARGET_TRANSPORT, info, host_id, addr = ?socket.local_addr().ok(), "Race coordinator bound");
+        Ok(RaceCoordinator {
+            host_id,
+            socket,
+            peers: Vec::new(),
+            links: Vec::new(),
+            remote_vehicles: HashMap::new(),
+        })
+    }
+
+    pub fn host_id(&self) -> u16 {
+        self.host_id
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) {
+        if !self.peers.contains(&addr) {
+            self.peers.push(addr);
+        }
+    }
+
+    pub fn attach_link(&mut self, link: CoordinationLink) {
+        self.links.push(link);
+    }
+
+    pub fn publish_state(&self, state: &NetVehicleState) -> io::Result<()> {
+        let bytes = NetMessage {
+            host_id: self.host_id,
+            body: NetMessageBody::VehicleState(state.clone()),
+        }
+        .to_bytes()
+        .map_err(invalid_data)?;
+        for peer in &self.peers {
+            self.socket.send_to(&bytes, peer)?;
+        }
+        Ok(())
+    }
+
+    pub fn publish_event(&mut self, event: &NetRaceEvent) -> io::Result<()> {
+        let msg = NetMessage {
+            host_id: self.host_id,
+            body: NetMessageBody::RaceEvent(event.clone()),
+        };
+        for link in self.links.iter_mut() {
+            link.send(&msg)?;
+        }
+        Ok(())
+    }
+
+    // Drains both the UDP socket and every TCP link. Vehicle state updates are folded into
+    // `remote_vehicle` and also returned so callers can react to them. Only an error on the UDP
+    // socket fails the poll.
+    pub fn poll(&mut self) -> io::Result<NetPoll> {
+        let mut messages = Vec::new();
+        let mut buf = [0u8; NET_MSG_MAX_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                // Malformed datagrams from the network are ignored rather than fatal.
+                Ok((n, _from)) => match NetMessage::from_bytes(&buf[..n]) {
+                    Ok(msg) => messages.push(msg),
+                    Err(_e) => {
+                        trace_event!(target: TARGET_TRANSPORT, debug, from = %_from, error = %_e, "Ignored malformed datagram");
+                    }
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // Some platforms report a peer that went away on the next receive.
+                Err(_e) if _e.kind() == ErrorKind::ConnectionReset => {
+                    trace_event!(target: TARGET_TRANSPORT, debug, error = %_e, "Peer unreachable");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut failed_links = Vec::new();
+        for mut link in std::mem::take(&mut self.links) {
+            match link.poll() {
+                Ok(received) => {
+                    messages.extend(received);
+                    self.links.push(link);
+                }
+                Err(e) => {
+                    trace_event!(target: TARGET_TRANSPORT, info, peer = ?link.peer_addr().ok(), error = %e, "Dropped coordination link");
+                    failed_links.push((link, e));
+                }
+            }
+        }
+
+        messages.retain(|msg| msg.host_id != self.host_id && self.accept(msg));
+        Ok(NetPoll {
+            messages,
+            failed_links,
+        })
+    }
+
+    fn accept(&mut self, msg: &NetMessage) -> bool {
+        let state = match &msg.body {
+            NetMessageBody::VehicleState(state) => state,
+            // A host saying hello or goodbye starts its sequence numbers over.
+            NetMessageBody::Hello { .. } | NetMessageBody::Goodbye => {
+                self.remote_vehicles
+                    .retain(|_, (host_id, _)| *host_id != msg.host_id);
+                return true;
+            }
+            NetMessageBody::RaceEvent(_) => return true,
+        };
+        match self.remote_vehicles.get(&state.vehicle) {
+            Some((_, known))
+                if known.sequence.wrapping_sub(state.sequence) <= NET_SEQUENCE_REORDER_WINDOW =>
+            {
+                false
+            }
+            _ => {
+                self.remote_vehicles
+                    .insert(state.vehicle.clone(), (msg.host_id, state.clone()));
+                true
+            }
+        }
+    }
+
+    pub fn remote_vehicle(&self, vehicle: &str) -> Option<&NetVehicleState> {
+        self.remote_vehicles.get(vehicle).map(|(_, state)| state)
+    }
+
+    pub fn remote_vehicles(&self) -> impl Iterator<Item = (u16, &NetVehicleState)> {
+        self.remote_vehicles
+            .values()
+            .map(|(host_id, state)| (*host_id, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    fn test_state(sequence: u32) -> NetVehicleState {
+        NetVehicleState {
+            vehicle: "Skull".to_string(),
+            sequence,
+            location_id: 12,
+            road_piece_idx: -3,
+            offset_from_road_centre_mm: 23.0,
+            speed_mm_per_sec: 600,
+            battery_level: 3900,
+        }
+    }
+
+    fn poll_until<F: FnMut() -> Vec<NetMessage>>(mut f: F) -> Vec<NetMessage> {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            let messages = f();
+            if !messages.is_empty() || Instant::now() > deadline {
+                return messages;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn net_message_round_trip_test() {
+        let messages = [
+            NetMessage {
+                host_id: 1,
+                body: NetMessageBody::Hello {
+                    host_name: "pit-lane".to_string(),
+                },
+            },
+            NetMessage {
+                host_id: 2,
+                body: NetMessageBody::VehicleState(test_state(7)),
+            },
+            NetMessage {
+                host_id: 3,
+                body: NetMessageBody::RaceEvent(NetRaceEvent {
+                    kind: NetRaceEventKind::LapCompleted,
+                    vehicle: "Skull".to_string(),
+                    lap: 4,
+                    time_ms: 8123,
+                }),
+            },
+            NetMessage {
+                host_id: 4,
+                body: NetMessageBody::Goodbye,
+            },
+        ];
+        for msg in messages {
+            let bytes = msg.to_bytes().unwrap();
+            let test_msg = NetMessage::from_bytes(&bytes).unwrap();
+            println!("T:{:?} == G:{:?}", test_msg, msg);
+            assert_eq!(msg, test_msg)
+        }
+    }
+
+    #[test]
+    fn net_message_rejects_bad_magic_test() {
+        let mut bytes = NetMessage {
+            host_id: 1,
+            body: NetMessageBody::Goodbye,
+        }
+        .to_bytes()
+        .unwrap();
+        bytes[0] = 0;
+        assert!(matches!(
+            NetMessage::from_bytes(&bytes),
+            Err(NetError::UnsupportedHeader { magic: 0xA400, .. })
+        ));
+        bytes[0] = 0xD1;
+        bytes[3] = 0x7f;
+        assert!(matches!(
+            NetMessage::from_bytes(&bytes),
+            Err(NetError::UnknownMsgType(0x7f))
+        ));
+        assert!(matches!(
+            NetMessage::from_bytes(&bytes[..3]),
+            Err(NetError::FrameSize(3))
+        ));
+
+        let mut bytes = NetMessage {
+            host_id: 1,
+            body: NetMessageBody::Hello {
+                host_name: "x".repeat(NET_NAME_MAX_SIZE),
+            },
+        }
+        .to_bytes()
+        .unwrap();
+        bytes[NET_HEADER_SIZE] += 1;
+        bytes.push(b'x');
+        assert!(matches!(
+            NetMessage::from_bytes(&bytes),
+            Err(NetError::NameTooLong(65))
+        ));
+    }
+
+    #[test]
+    fn race_coordinator_udp_state_sync_test() {
+        let mut a = RaceCoordinator::bind(1, "127.0.0.1:0").unwrap();
+        let mut b = RaceCoordinator::bind(2, "127.0.0.1:0").unwrap();
+        a.add_peer(b.local_addr().unwrap());
+        b.add_peer(a.local_addr().unwrap());
+
+        a.publish_state(&test_state(2)).unwrap();
+        let messages = poll_until(|| b.poll().unwrap().messages);
+        assert_eq!(1, messages.len());
+        assert_eq!(Some(&test_state(2)), b.remote_vehicle("Skull"));
+
+        // Older sequence numbers are dropped.
+        a.publish_state(&test_state(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(b.poll().unwrap().messages.is_empty());
+        assert_eq!(2, b.remote_vehicle("Skull").unwrap().sequence);
+
+        // Far behind is a host that restarted.
+        a.publish_state(&test_state(1000)).unwrap();
+        poll_until(|| b.poll().unwrap().messages);
+        a.publish_state(&test_state(0)).unwrap();
+        poll_until(|| b.poll().unwrap().messages);
+        assert_eq!(0, b.remote_vehicle("Skull").unwrap().sequence);
+        assert_eq!(
+            vec![1],
+            b.remote_vehicles().map(|(h, _)| h).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn race_coordinator_tcp_event_link_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut a = RaceCoordinator::bind(1, "127.0.0.1:0").unwrap();
+        let mut b = RaceCoordinator::bind(2, "127.0.0.1:0").unwrap();
+        a.attach_link(CoordinationLink::connect(listener.local_addr().unwrap()).unwrap());
+        let (stream, _) = listener.accept().unwrap();
+        b.attach_link(CoordinationLink::from_stream(stream).unwrap());
+
+        let event = NetRaceEvent {
+            kind: NetRaceEventKind::Started,
+            vehicle: String::new(),
+            lap: 0,
+            time_ms: 0,
+        };
+        a.publish_event(&event).unwrap();
+        let messages = poll_until(|| b.poll().unwrap().messages);
+        assert_eq!(
+            vec![NetMessage {
+                host_id: 1,
+                body: NetMessageBody::RaceEvent(event)
+            }],
+            messages
+        );
+
+        // A closed link is handed back, datagrams still come through.
+        drop(a);
+        let mut c = RaceCoordinator::bind(3, "127.0.0.1:0").unwrap();
+        c.add_peer(b.local_addr().unwrap());
+        c.publish_state(&test_state(5)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut messages = Vec::new();
+        let mut failed = 0;
+        while (messages.is_empty() || failed == 0) && Instant::now() < deadline {
+            let poll = b.poll().unwrap();
+            messages.extend(poll.messages);
+            failed += poll.failed_links.len();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(1, messages.len());
+        assert_eq!(1, failed);
+        assert!(b.poll().unwrap().failed_links.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use opentelemetry::trace::{Span, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue, StringValue, Value};
+use std::time::SystemTime;
+
+use crate::race::{RaceEvent, RaceUpdate};
+
+// Records a race session as a trace for post-race analysis in existing OpenTelemetry tooling.
+// The session is the root span and every completed lap becomes a child span covering the lap,
+// everything else (incidents, eliminations, safety car) is added to the session as an event.
+// Pass a tracer from whichever SDK and exporter the team already runs, e.g. OTLP.
+pub struct RaceSessionExporter<T: Tracer> {
+    tracer: T,
+    session: Context,
+}
+
+impl<T: Tracer> RaceSessionExporter<T>
+where
+    T::Span: Send + Sync + 'static,
+{
+    pub fn start(tracer: T, name: &str, at: SystemTime) -> RaceSessionExporter<T> {
+        let span = tracer
+            .span_builder(format!("race {}", name))
+            .with_start_time(at)
+            .with_attributes(vec![KeyValue::new("race.name", name.to_string())])
+            .start(&tracer);
+        RaceSessionExporter {
+            tracer,
+            session: Context::new().with_span(span),
+        }
+    }
+
+    pub fn record_update(&mut self, update: &RaceUpdate, at: SystemTime) {
+        for event in &update.events {
+            self.record(event, at);
+        }
+    }
+
+    // `at` is when the controller reported the event, the end of the lap for a lap span.
+    pub fn record(&mut self, event: &RaceEvent, at: SystemTime) {
+        let session = self.session.span();
+        match event {
+            RaceEvent::LapCompleted {
+                vehicle,
+                lap,
+                lap_time,
+            } => {
+                let start = at.checked_sub(*lap_time).unwrap_or(at);
+                let mut span = self
+                    .tracer
+                    .span_builder(format!("lap {}", lap))
+                    .with_start_time(start)
+                    .with_attributes(vec![
+                        KeyValue::new("race.vehicle", vehicle.to_string()),
+                        KeyValue::new("race.lap", i64::from(*lap)),
+                        KeyValue::new("race.lap_ms", lap_time.as_millis() as i64),
+                    ])
+                    .start_with_context(&self.tracer, &self.session);
+                span.end_with_timestamp(at);
+            }
+            RaceEvent::Eliminated { vehicle, position } => session.add_event_with_timestamp(
+                "eliminated",
+                at,
+                vec![
+                    KeyValue::new("race.vehicle", vehicle.to_string()),
+                    KeyValue::new("race.position", *position as i64),
+                ],
+            ),
+            RaceEvent::Parked { vehicle } => session.add_event_with_timestamp(
+                "parked",
+                at,
+                vec![KeyValue::new("race.vehicle", vehicle.to_string())],
+            ),
+            RaceEvent::Winner { vehicle } => {
+                session.set_attribute(KeyValue::new("race.winner", vehicle.to_string()));
+                session.add_event_with_timestamp(
+                    "winner",
+                    at,
+                    vec![KeyValue::new("race.vehicle", vehicle.to_string())],
+                );
+            }
+            RaceEvent::Incident(report) => {
+                let vehicles: Vec<StringValue> = report
+                    .vehicles
+                    .iter()
+                    .map(|v| v.to_string().into())
+                    .collect();
+                session.add_event_with_timestamp(
+                    "incident",
+                    at,
+                    vec![
+                        KeyValue::new("race.road_piece_id", i64::from(report.road_piece_id)),
+                        KeyValue::new("race.vehicles", Value::Array(vehicles.into())),
+                    ],
+                );
+            }
+            RaceEvent::SafetyCarDeployed => {
+                session.add_event_with_timestamp("safety car deployed", at, Vec::new())
+            }
+            RaceEvent::SafetyCarRecalled => {
+                session.add_event_with_timestamp("safety car recalled", at, Vec::new())
+            }
+            RaceEvent::Countdown { remaining } => session.add_event_with_timestamp(
+                "countdown",
+                at,
+                vec![KeyValue::new("race.countdown", i64::from(*remaining))],
+            ),
+            RaceEvent::Started => session.add_event_with_timestamp("started", at, Vec::new()),
+            RaceEvent::Finished {
+                vehicle,
+                position,
+                race_time,
+            } => session.add_event_with_timestamp(
+                "finished",
+                at,
+                vec![
+                    KeyValue::new("race.vehicle", vehicle.to_string()),
+                    KeyValue::new("race.position", *position as i64),
+                    KeyValue::new("race.race_ms", race_time.as_millis() as i64),
+                ],
+            ),
+            RaceEvent::Overtake {
+                vehicle,
+                overtaken,
+                position,
+            } => session.add_event_with_timestamp(
+                "overtake",
+                at,
+                vec![
+                    KeyValue::new("race.vehicle", vehicle.to_string()),
+                    KeyValue::new("race.overtaken", overtaken.to_string()),
+                    KeyValue::new("race.position", *position as i64),
+                ],
+            ),
+            RaceEvent::DidNotFinish { vehicle } => session.add_event_with_timestamp(
+                "did not finish",
+                at,
+                vec![KeyValue::new("race.vehicle", vehicle.to_string())],
+            ),
+        }
+    }
+
+    pub fn finish(self, at: SystemTime) {
+        self.session.span().end_with_timestamp(at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::incident::IncidentReport;
+    use crate::race::RaceCommands;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn otel_race_session_test() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let mut session = RaceSessionExporter::start(provider.tracer("race"), "final", t0);
+        session.record(
+            &RaceEvent::LapCompleted {
+                vehicle: "Skull".into(),
+                lap: 1,
+                lap_time: Duration::from_millis(5250),
+            },
+            t0 + Duration::from_secs(7),
+        );
+        session.record_update(
+            &RaceUpdate {
+                events: vec![
+                    RaceEvent::Incident(IncidentReport {
+                        at: Instant::now(),
+                        road_piece_id: 17,
+                        vehicles: vec!["Skull".into(), "Nuke".into()],
+                        positions: Vec::new(),
+                    }),
+                    RaceEvent::SafetyCarDeployed,
+                ],
+                commands: RaceCommands::new(),
+            },
+            t0 + Duration::from_secs(8),
+        );
+        session.finish(t0 + Duration::from_secs(10));
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(2, spans.len());
+        let (lap, race) = (&spans[0], &spans[1]);
+        assert_eq!("lap 1", lap.name);
+        assert_eq!("race final", race.name);
+        assert_eq!(race.span_context.span_id(), lap.parent_span_id);
+        assert_eq!(race.span_context.trace_id(), lap.span_context.trace_id());
+        assert_eq!(t0 + Duration::from_millis(1750), lap.start_time);
+        assert_eq!(t0 + Duration::from_secs(7), lap.end_time);
+        assert!(lap
+            .attributes
+            .contains(&KeyValue::new("race.vehicle", "Skull")));
+
+        let events: Vec<&str> = race.events.iter().map(|e| e.name.as_ref()).collect();
+        assert_eq!(vec!["incident", "safety car deployed"], events);
+        assert_eq!(t0 + Duration::from_secs(10), race.end_time);
+    }
+}
@@ -0,0 +1,142 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use crate::replay::{ReplayDirection, ReplayError, ReplayReader, ReplayRecord, ReplayedRecord};
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Decodes recordings on every core. A vehicle's state only ever depends on its own frames, so the
+// records are split up per vehicle and each vehicle's share is decoded on one thread, in the
+// order it was recorded. Vehicles come back sorted by id, the same on every run.
+
+// One vehicle's share of the records, with the state it ended up in.
+#[derive(Debug)]
+pub struct VehicleDecode {
+    pub vehicle: VehicleId,
+    pub records: Vec<ReplayedRecord>,
+    pub state: AnkiVehicleData,
+}
+
+impl VehicleDecode {
+    // Notifications that could not be decoded.
+    pub fn errors(&self) -> usize {
+        self.records
+            .iter()
+            .filter(|record| matches!(record.decoded, Some(Err(_))))
+            .count()
+    }
+}
+
+// Same handling as `ReplayPlayer`, without the waiting.
+fn decode_vehicle(vehicle: VehicleId, records: Vec<ReplayRecord>) -> VehicleDecode {
+    let mut state = AnkiVehicleData::default();
+    state.set_name(vehicle.to_string());
+    let records = records
+        .into_iter()
+        .map(|record| {
+            let decoded = match record.direction {
+                ReplayDirection::Command => None,
+                ReplayDirection::Notification => Some(state.process_notification(&record.frame)),
+            };
+            ReplayedRecord { record, decoded }
+        })
+        .collect();
+    VehicleDecode {
+        vehicle,
+        records,
+        state,
+    }
+}
+
+// Works for live streams too, as long as each vehicle's frames are in the order they arrived.
+pub fn decode_records<I: IntoIterator<Item = ReplayRecord>>(records: I) -> Vec<VehicleDecode> {
+    let mut partitions: BTreeMap<VehicleId, Vec<ReplayRecord>> = BTreeMap::new();
+    for record in records {
+        partitions
+            .entry(record.vehicle.clone())
+            .or_default()
+            .push(record);
+    }
+    partitions
+        .into_par_iter()
+        .map(|(vehicle, records)| decode_vehicle(vehicle, records))
+        .collect()
+}
+
+// Reading the file stays on the calling thread, only the decoding is spread out.
+pub fn decode_replay<R: Read>(reader: ReplayReader<R>) -> Result<Vec<VehicleDecode>, ReplayError> {
+    let records = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok(decode_records(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::replay::{ReplayPlayer, ReplayWriter};
+    use crate::script::VehicleSnapshot;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    // Three vehicles driving laps, their notifications interleaved as they would be on the air.
+    fn recording() -> Vec<ReplayRecord> {
+        let mut vehicles: Vec<(String, SimulatedVehicle)> = ["skull", "nuke", "grip"]
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| {
+                let mut sim = SimulatedVehicle::new().with_seed(idx as u64);
+                for command in AnkiVehicleData::default().configure() {
+                    sim.handle_command(&command.encode()).unwrap();
+                }
+                let speed = 400 + 100 * idx as i16;
+                sim.handle_command(&AnkiVehicleData::set_speed(speed, 1000))
+                    .unwrap();
+                (id.to_string(), sim)
+            })
+            .collect();
+        let mut records = Vec::new();
+        for tick in 0..2_000u64 {
+            for (id, sim) in vehicles.iter_mut() {
+                sim.advance(Duration::from_millis(10));
+                for frame in sim.drain_notifications() {
+                    records.push(ReplayRecord::notification(tick * 10, id, &frame));
+                }
+            }
+        }
+        records.push(ReplayRecord::notification(20_000, "nuke", &[0xff]));
+        records
+    }
+
+    #[test]
+    fn decode_records_test() {
+        let records = recording();
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+        let mut player = ReplayPlayer::new(ReplayReader::new(Cursor::new(data.clone())).unwrap())
+            .with_speed(0.0);
+        player.play_to_end().unwrap();
+
+        let decoded = decode_replay(ReplayReader::new(Cursor::new(data)).unwrap()).unwrap();
+        let ids: Vec<&str> = decoded.iter().map(|v| v.vehicle.as_str()).collect();
+        assert_eq!(vec!["grip", "nuke", "skull"], ids);
+        for vehicle in &decoded {
+            assert_eq!(
+                VehicleSnapshot::from_vehicle(player.vehicle(&vehicle.vehicle).unwrap()),
+                VehicleSnapshot::from_vehicle(&vehicle.state)
+            );
+            let expected: Vec<&ReplayRecord> = records
+                .iter()
+                .filter(|record| record.vehicle == vehicle.vehicle)
+                .collect();
+            let got: Vec<&ReplayRecord> = vehicle.records.iter().map(|r| &r.record).collect();
+            assert_eq!(expected, got);
+        }
+        assert_eq!(1, decoded[1].errors());
+        assert_eq!(0, decoded[2].errors());
+    }
+}
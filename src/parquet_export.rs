@@ -0,0 +1,213 @@
+use arrow_array::builder::{
+    ArrayBuilder, Float32Builder, Int8Builder, StringBuilder, UInt16Builder, UInt64Builder,
+    UInt8Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::AnkiVehicleData;
+
+// Rows buffered before a record batch is handed to the Parquet writer. Each batch becomes (part
+// of) a row group, large enough for good compression and fast scans from pandas or arrow in R.
+pub const PARQUET_DEFAULT_BATCH_ROWS: usize = 8192;
+
+// Same columns as the CSV export, so notebooks can read either.
+pub fn telemetry_schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+    SCHEMA
+        .get_or_init(|| {
+            Arc::new(Schema::new(vec![
+                Field::new("timestamp_ms", DataType::UInt64, false),
+                Field::new("vehicle", DataType::Utf8, false),
+                Field::new("location_id", DataType::UInt8, false),
+                Field::new("road_piece_idx", DataType::Int8, false),
+                Field::new("offset_from_road_centre_mm", DataType::Float32, false),
+                Field::new("speed_mm_per_sec", DataType::UInt16, false),
+                Field::new("battery_level", DataType::UInt16, false),
+            ]))
+        })
+        .clone()
+}
+
+// Collects telemetry column by column into Arrow record batches, for callers that keep the data
+// in memory or ship it over Arrow IPC / Flight instead of writing files.
+#[derive(Debug, Default)]
+pub struct TelemetryBatchBuilder {
+    timestamp_ms: UInt64Builder,
+    vehicle: StringBuilder,
+    location_id: UInt8Builder,
+    road_piece_idx: Int8Builder,
+    offset_from_road_centre_mm: Float32Builder,
+    speed_mm_per_sec: UInt16Builder,
+    battery_level: UInt16Builder,
+}
+
+impl TelemetryBatchBuilder {
+    pub fn new() -> TelemetryBatchBuilder {
+        TelemetryBatchBuilder::default()
+    }
+
+    pub fn push_vehicle(&mut self, timestamp_ms: u64, vehicle: &AnkiVehicleData) {
+        self.timestamp_ms.append_value(timestamp_ms);
+        self.vehicle.append_value(&vehicle.name);
+        self.location_id.append_value(vehicle.location_id);
+        self.road_piece_idx.append_value(vehicle.road_piece_idx);
+        self.offset_from_road_centre_mm
+            .append_value(vehicle.offset_from_road_centre_mm);
+        self.speed_mm_per_sec.append_value(vehicle.speed_mm_per_sec);
+        self.battery_level.append_value(vehicle.battery_level);
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamp_ms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Takes the buffered rows, leaving the builder empty.
+    pub fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.timestamp_ms.finish()),
+            Arc::new(self.vehicle.finish()),
+            Arc::new(self.location_id.finish()),
+            Arc::new(self.road_piece_idx.finish()),
+            Arc::new(self.offset_from_road_centre_mm.finish()),
+            Arc::new(self.speed_mm_per_sec.finish()),
+            Arc::new(self.battery_level.finish()),
+        ];
+        RecordBatch::try_new(telemetry_schema(), columns)
+    }
+}
+
+// Records a whole session, every vehicle, into one Snappy compressed Parquet file. The file is
+// only valid once `close` has written the footer.
+pub struct ParquetTelemetryWriter {
+    writer: ArrowWriter<File>,
+    builder: TelemetryBatchBuilder,
+    batch_rows: usize,
+    rows: usize,
+}
+
+impl ParquetTelemetryWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<ParquetTelemetryWriter, ParquetError> {
+        ParquetTelemetryWriter::with_batch_rows(path, PARQUET_DEFAULT_BATCH_ROWS)
+    }
+
+    pub fn with_batch_rows<P: AsRef<Path>>(
+        path: P,
+        batch_rows: usize,
+    ) -> Result<ParquetTelemetryWriter, ParquetError> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            ArrowWriter::try_new(File::create(path)?, telemetry_schema(), Some(properties))?;
+        Ok(ParquetTelemetryWriter {
+            writer,
+            builder: TelemetryBatchBuilder::new(),
+            batch_rows: batch_rows.max(1),
+            rows: 0,
+        })
+    }
+
+    pub fn write_vehicle(
+        &mut self,
+        timestamp_ms: u64,
+        vehicle: &AnkiVehicleData,
+    ) -> Result<(), ParquetError> {
+        self.builder.push_vehicle(timestamp_ms, vehicle);
+        self.rows += 1;
+        if self.builder.len() >= self.batch_rows {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    // Writes any buffered rows and closes the current row group.
+    pub fn flush(&mut self) -> Result<(), ParquetError> {
+        self.write_batch()?;
+        self.writer.flush()
+    }
+
+    // Rows written so far, including those still buffered.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn close(mut self) -> Result<(), ParquetError> {
+        self.write_batch()?;
+        self.writer.close()?;
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<(), ParquetError> {
+        if self.builder.is_empty() {
+            return Ok(());
+        }
+        self.writer.write(&self.builder.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{UInt16Type, UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn arrow_batch_test() {
+        let mut builder = TelemetryBatchBuilder::new();
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_name("Skull".to_string());
+        vehicle.road_piece_idx = -3;
+        builder.push_vehicle(1234, &vehicle);
+        builder.push_vehicle(1250, &vehicle);
+
+        let batch = builder.finish().unwrap();
+        assert!(builder.is_empty());
+        assert_eq!(2, batch.num_rows());
+        assert_eq!(telemetry_schema(), batch.schema());
+        assert_eq!("Skull", batch.column(1).as_string::<i32>().value(1));
+    }
+
+    #[test]
+    fn parquet_write_test() {
+        let path = env::temp_dir().join(format!("anki-parquet-{}.parquet", process::id()));
+        let mut writer = ParquetTelemetryWriter::with_batch_rows(&path, 4).unwrap();
+        let mut skull = AnkiVehicleData::default();
+        skull.set_name("Skull".to_string());
+        let mut nuke = AnkiVehicleData::default();
+        nuke.set_name("Nuke".to_string());
+        for timestamp_ms in 0..5 {
+            skull.speed_mm_per_sec = 500 + timestamp_ms as u16;
+            writer.write_vehicle(timestamp_ms, &skull).unwrap();
+            writer.write_vehicle(timestamp_ms, &nuke).unwrap();
+        }
+        assert_eq!(10, writer.rows());
+        writer.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(10, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        let batch = &batches[0];
+        assert_eq!(0, batch.column(0).as_primitive::<UInt64Type>().value(0));
+        assert_eq!(501, batch.column(5).as_primitive::<UInt16Type>().value(2));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Round-trip latency from ping requests and their responses. Responses carry nothing to tell
+// which ping they answer, so they are matched to the oldest ping still waiting, which holds as
+// long as the vehicle answers in order.
+
+// Pings kept waiting for a response. A ping the vehicle never answered stays in the queue and
+// puts the matching off by one until it is pushed out here.
+pub const PING_MAX_OUTSTANDING: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct PingTracker {
+    outstanding: VecDeque<Instant>,
+    last_latency: Option<Duration>,
+    responses: u64,
+}
+
+impl PingTracker {
+    pub fn new() -> PingTracker {
+        PingTracker::default()
+    }
+
+    pub fn sent_at(&mut self, at: Instant) {
+        if self.outstanding.len() == PING_MAX_OUTSTANDING {
+            self.outstanding.pop_front();
+        }
+        self.outstanding.push_back(at);
+    }
+
+    // The latency of the ping this response answers, None for a response to no known ping.
+    pub fn response_at(&mut self, at: Instant) -> Option<Duration> {
+        let sent = self.outstanding.pop_front()?;
+        let latency = at.saturating_duration_since(sent);
+        self.last_latency = Some(latency);
+        self.responses += 1;
+        Some(latency)
+    }
+
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.last_latency
+    }
+
+    // Pings sent that haven't been answered yet.
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    // Responses matched to a ping so far.
+    pub fn responses(&self) -> u64 {
+        self.responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_tracker_test() {
+        let t0 = Instant::now();
+        let mut pings = PingTracker::new();
+        assert_eq!(None, pings.response_at(t0));
+
+        pings.sent_at(t0);
+        pings.sent_at(t0 + Duration::from_millis(10));
+        assert_eq!(2, pings.outstanding());
+        assert_eq!(
+            Some(Duration::from_millis(30)),
+            pings.response_at(t0 + Duration::from_millis(30))
+        );
+        assert_eq!(
+            Some(Duration::from_millis(25)),
+            pings.response_at(t0 + Duration::from_millis(35))
+        );
+        assert_eq!(Some(Duration::from_millis(25)), pings.last_latency());
+        assert_eq!(0, pings.outstanding());
+        assert_eq!(2, pings.responses());
+
+        for _ in 0..PING_MAX_OUTSTANDING + 2 {
+            pings.sent_at(t0);
+        }
+        assert_eq!(PING_MAX_OUTSTANDING, pings.outstanding());
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::protocol::ANKI_VEHICLE_MSG_MAX_SIZE;
+
+// Frame buffers kept for reuse, enough for a busy fleet to recycle everything it decodes in one
+// pass without going back to the allocator.
+pub const FRAME_POOL_DEFAULT_CAPACITY: usize = 256;
+
+// Recycles the owned buffers notifications arrive in. Whatever produces frames takes a buffer,
+// whoever decodes them hands it back once done, and the next frame reuses it. Shared between
+// threads behind an `Arc`, a buffer that isn't handed back is simply dropped.
+#[derive(Debug)]
+pub struct FramePool {
+    free: Mutex<Vec<Vec<u8>>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    recycled: AtomicU64,
+    discarded: AtomicU64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct PoolStats {
+    // Takes served from a recycled buffer.
+    pub hits: u64,
+    // Takes that had to allocate.
+    pub misses: u64,
+    pub recycled: u64,
+    // Buffers handed back while the pool was already full.
+    pub discarded: u64,
+    pub available: usize,
+}
+
+impl PoolStats {
+    // Share of takes that didn't allocate, 0 before anything has been taken.
+    pub fn hit_rate(&self) -> f64 {
+        let takes = self.hits + self.misses;
+        if takes == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / takes as f64
+    }
+}
+
+impl Default for FramePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FramePool {
+    pub fn new() -> FramePool {
+        FramePool::with_capacity(FRAME_POOL_DEFAULT_CAPACITY)
+    }
+
+    // Holds on to at most `capacity` free buffers.
+    pub fn with_capacity(capacity: usize) -> FramePool {
+        FramePool {
+            free: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            recycled: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+
+    // An empty buffer with room for the largest frame.
+    pub fn take(&self) -> Vec<u8> {
+        let recycled = self.free.lock().unwrap().pop();
+        #[cfg(feature = "metrics")]
+        crate::fleet_metrics::record_pool_take(recycled.is_some());
+        match recycled {
+            Some(buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(ANKI_VEHICLE_MSG_MAX_SIZE)
+            }
+        }
+    }
+
+    // For hosts that are handed borrowed notification data and need an owned copy.
+    pub fn copy_from(&self, data: &[u8]) -> Vec<u8> {
+        let mut buf = self.take();
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    pub fn recycle(&self, mut buf: Vec<u8>) {
+        let mut free = self.free.lock().unwrap();
+        if free.len() >= self.capacity {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        buf.clear();
+        free.push(buf);
+        self.recycled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            recycled: self.recycled.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+            available: self.free.lock().unwrap().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_pool_test() {
+        let pool = FramePool::with_capacity(1);
+        let first = pool.copy_from(&[0x01, 0x17]);
+        assert_eq!(vec![0x01, 0x17], first);
+        let ptr = first.as_ptr();
+        pool.recycle(first);
+
+        let second = pool.take();
+        assert!(second.is_empty());
+        assert_eq!(ptr, second.as_ptr());
+        pool.recycle(second);
+        pool.recycle(vec![0x01]);
+
+        assert_eq!(
+            PoolStats {
+                hits: 1,
+                misses: 1,
+                recycled: 2,
+                discarded: 1,
+                available: 1,
+            },
+            pool.stats()
+        );
+        assert_eq!(0.5, pool.stats().hit_rate());
+        assert_eq!(0.0, FramePool::new().stats().hit_rate());
+    }
+}
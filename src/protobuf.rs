@@ -0,0 +1,172 @@
+use prost::Message;
+
+use crate::race::RaceEvent;
+use crate::AnkiVehicleData;
+
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/anki_drive.telemetry.rs"));
+}
+
+use proto::race_event::Event;
+use proto::telemetry_message::Message as TelemetryKind;
+
+// Typed race telemetry for analytics backends, the schema lives in proto/telemetry.proto so
+// consumers in other languages can generate their own bindings from it.
+
+impl proto::VehicleState {
+    pub fn from_vehicle(timestamp_ms: u64, vehicle: &AnkiVehicleData) -> proto::VehicleState {
+        proto::VehicleState {
+            vehicle: vehicle.name.clone(),
+            timestamp_ms,
+            location_id: vehicle.location_id.into(),
+            road_piece_idx: vehicle.road_piece_idx.into(),
+            offset_from_road_centre_mm: vehicle.offset_from_road_centre_mm,
+            speed_mm_per_sec: vehicle.speed_mm_per_sec.into(),
+            battery_level: vehicle.battery_level.into(),
+            version: vehicle.version.into(),
+        }
+    }
+}
+
+impl From<&RaceEvent> for proto::RaceEvent {
+    fn from(event: &RaceEvent) -> Self {
+        let event = match event {
+            RaceEvent::LapCompleted {
+                vehicle,
+                lap,
+                lap_time,
+            } => Event::LapCompleted(proto::LapCompleted {
+                vehicle: vehicle.to_string(),
+                lap: (*lap).into(),
+                lap_ms: lap_time.as_millis() as u64,
+            }),
+            RaceEvent::Eliminated { vehicle, position } => Event::Eliminated(proto::Eliminated {
+                vehicle: vehicle.to_string(),
+                position: *position as u64,
+            }),
+            RaceEvent::Parked { vehicle } => Event::Parked(proto::Parked {
+                vehicle: vehicle.to_string(),
+            }),
+            RaceEvent::Winner { vehicle } => Event::Winner(proto::Winner {
+                vehicle: vehicle.to_string(),
+            }),
+            RaceEvent::Incident(report) => Event::Incident(proto::Incident {
+                road_piece_id: report.road_piece_id.into(),
+                vehicles: report.vehicles.iter().map(ToString::to_string).collect(),
+            }),
+            RaceEvent::SafetyCarDeployed => Event::SafetyCar(proto::SafetyCar { deployed: true }),
+            RaceEvent::SafetyCarRecalled => Event::SafetyCar(proto::SafetyCar { deployed: false }),
+            RaceEvent::Countdown { remaining } => Event::Countdown(proto::Countdown {
+                remaining: (*remaining).into(),
+            }),
+            RaceEvent::Started => Event::Started(proto::Started {}),
+            RaceEvent::Finished {
+                vehicle,
+                position,
+                race_time,
+            } => Event::Finished(proto::Finished {
+                vehicle: vehicle.to_string(),
+                position: *position as u64,
+                race_ms: race_time.as_millis() as u64,
+            }),
+            RaceEvent::Overtake {
+                vehicle,
+                overtaken,
+                position,
+            } => Event::Overtake(proto::Overtake {
+                vehicle: vehicle.to_string(),
+                overtaken: overtaken.to_string(),
+                position: *position as u64,
+            }),
+            RaceEvent::DidNotFinish { vehicle } => Event::DidNotFinish(proto::DidNotFinish {
+                vehicle: vehicle.to_string(),
+            }),
+        };
+        proto::RaceEvent { event: Some(event) }
+    }
+}
+
+impl From<proto::VehicleState> for proto::TelemetryMessage {
+    fn from(state: proto::VehicleState) -> Self {
+        proto::TelemetryMessage {
+            message: Some(TelemetryKind::State(state)),
+        }
+    }
+}
+
+impl From<&RaceEvent> for proto::TelemetryMessage {
+    fn from(event: &RaceEvent) -> Self {
+        proto::TelemetryMessage {
+            message: Some(TelemetryKind::Event(event.into())),
+        }
+    }
+}
+
+impl proto::TelemetryMessage {
+    // Frames for a byte stream, each prefixed with its varint encoded length.
+    pub fn to_length_delimited(&self) -> Vec<u8> {
+        self.encode_length_delimited_to_vec()
+    }
+
+    pub fn from_length_delimited(
+        data: &[u8],
+    ) -> Result<proto::TelemetryMessage, prost::DecodeError> {
+        proto::TelemetryMessage::decode_length_delimited(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::incident::IncidentReport;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn protobuf_state_round_trip_test() {
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_name("Skull".to_string());
+        vehicle.road_piece_idx = -3;
+        vehicle.speed_mm_per_sec = 600;
+        let msg: proto::TelemetryMessage = proto::VehicleState::from_vehicle(1234, &vehicle).into();
+
+        let data = msg.to_length_delimited();
+        assert_eq!(data.len() - 1, data[0] as usize);
+        let decoded = proto::TelemetryMessage::from_length_delimited(&data).unwrap();
+        let Some(TelemetryKind::State(state)) = &decoded.message else {
+            panic!("Expected a vehicle state, got {:?}", decoded);
+        };
+        assert_eq!(-3, state.road_piece_idx);
+        assert_eq!(msg, decoded);
+    }
+
+    #[test]
+    fn protobuf_event_test() {
+        let lap: proto::RaceEvent = (&RaceEvent::LapCompleted {
+            vehicle: "Skull".into(),
+            lap: 2,
+            lap_time: Duration::from_millis(5250),
+        })
+            .into();
+        assert_eq!(
+            Some(Event::LapCompleted(proto::LapCompleted {
+                vehicle: "Skull".to_string(),
+                lap: 2,
+                lap_ms: 5250,
+            })),
+            lap.event
+        );
+
+        let msg: proto::TelemetryMessage = (&RaceEvent::Incident(IncidentReport {
+            at: Instant::now(),
+            road_piece_id: 17,
+            vehicles: vec!["Skull".into(), "Nuke".into()],
+            positions: Vec::new(),
+        }))
+            .into();
+        assert_eq!(
+            msg,
+            proto::TelemetryMessage::from_length_delimited(&msg.to_length_delimited()).unwrap()
+        );
+        assert!(proto::TelemetryMessage::from_length_delimited(&[0x05, 0x0a]).is_err());
+    }
+}
@@ -1,12 +1,70 @@
+use crate::command::Command;
+use crate::error::{check_buffer_len, check_frame_len, AnkiError};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use scroll::{self, ctx, Pread, Pwrite};
-use std::ops::Add;
+use std::borrow::Cow;
+use thiserror::Error;
+
+#[cfg(feature = "c-compat")]
+pub mod compat;
+
+// Why a frame doesn't hold the message it should. The codecs fail with an `AnkiError`, these
+// come wrapped in `AnkiError::Protocol`.
+#[derive(Debug, PartialEq, Eq, Clone, Error)]
+pub enum ProtocolError {
+    #[error("Frame too short, expected {expected} bytes, got {got}")]
+    TooShort { expected: usize, got: usize },
+    #[error("Frame too long, expected {expected} bytes, got {got}")]
+    TooLong { expected: usize, got: usize },
+    #[error("Unknown message type {0:#04x}")]
+    UnknownMsgType(u8),
+    // The size byte at the start of the frame disagrees with the frame's length.
+    #[error("Frame declares {declared} bytes, got {got}")]
+    SizeMismatch { declared: usize, got: usize },
+    // A byte that isn't one of the values of the field's enum.
+    #[error("No {field} with value {value}")]
+    InvalidEnumValue { field: &'static str, value: u32 },
+    // A length or flag outside what the field allows.
+    #[error("Field {field} out of range: {value}")]
+    FieldOutOfRange { field: &'static str, value: u32 },
+}
 
 pub const ANKI_VEHICLE_MSG_MAX_SIZE: usize = 20;
 pub const ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE: usize = 18;
 pub const ANKI_VEHICLE_MSG_BASE_SIZE: usize = 2;
 
-#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
+// Vehicles send and expect every multi-byte field little endian. Everything that reads or writes
+// a frame uses this rather than picking a byte order itself.
+pub const WIRE_ENDIAN: scroll::Endian = scroll::LE;
+
+// A message with a fixed size, read and written at `WIRE_ENDIAN`.
+pub trait WireCodec:
+    Sized
+    + Clone
+    + for<'a> ctx::TryFromCtx<'a, scroll::Endian, Error = AnkiError>
+    + ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>
+{
+    // The encoded size, one of the ANKI_VEHICLE_MSG_*_SIZE constants.
+    const SIZE: usize;
+
+    fn from_bytes(data: &[u8]) -> Result<Self, AnkiError> {
+        data.pread_with(0, WIRE_ENDIAN)
+    }
+
+    // Writing a message into a buffer of its own size can't fail.
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_command().to_vec()
+    }
+
+    // The same frame held inline, building it never touches the heap.
+    fn to_command(&self) -> Command {
+        Command::encode(self.clone(), Self::SIZE).expect("Failed to write message as bytes")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 #[repr(u8)]
 pub enum AnkiVehicleMsgType {
@@ -48,14 +106,25 @@ pub enum AnkiVehicleMsgType {
     // Light Patterns
     C2VLightsPattern = 0x33,
 
+    // Overdrive status notifications
+    V2CSpeedUpdate = 0x36,
+    V2CChargerInfo = 0x3f,
+
     // Vehicle Configuration Parameters
     C2VSetConfigParams = 0x45,
 
+    // Overdrive event notifications
+    V2CCollisionDetected = 0x4d,
+    V2CCycleOvertime = 0x86,
+
     // SDK Mode
     C2VSDKMode = 0x90,
 }
 
+// Only serializes, deserialize into `AnkiVehicleMsgOwned`.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct AnkiVehicleMsg<'a> {
     size: u8,
     pub msg_id: AnkiVehicleMsgType,
@@ -63,10 +132,21 @@ pub struct AnkiVehicleMsg<'a> {
 }
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsg<'a> {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        if data.len() < ANKI_VEHICLE_MSG_BASE_SIZE {
+            return Err(ProtocolError::TooShort {
+                expected: ANKI_VEHICLE_MSG_BASE_SIZE,
+                got: data.len(),
+            }
+            .into());
+        }
         if data.len() > ANKI_VEHICLE_MSG_MAX_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
+            return Err(ProtocolError::TooLong {
+                expected: ANKI_VEHICLE_MSG_MAX_SIZE,
+                got: data.len(),
+            }
+            .into());
         }
 
         let offset = &mut 0;
@@ -74,13 +154,12 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsg<'a> {
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
-        let payload: &'a [u8];
-        if data.len() > ANKI_VEHICLE_MSG_BASE_SIZE {
-            payload = data.gread_with::<&'a [u8]>(offset, data.len() - 2)?;
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let payload: &'a [u8] = if data.len() > ANKI_VEHICLE_MSG_BASE_SIZE {
+            data.gread_with::<&'a [u8]>(offset, data.len() - 2)?
         } else {
-            payload = &[]
-        }
+            &[]
+        };
 
         Ok((
             AnkiVehicleMsg {
@@ -94,25 +173,14 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsg<'a> {
 }
 
 impl<'a> ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsg<'a> {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_BASE_SIZE + self.payload.len() {
-            return Err((scroll::Error::Custom(
-                "Incorrect size of byte array for anki vehicle message".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_BASE_SIZE + self.payload.len())?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
-        if self.payload.len() > 0 {
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        if !self.payload.is_empty() {
             data.gwrite::<&'a [u8]>(self.payload, offset)?;
         }
 
@@ -120,7 +188,95 @@ impl<'a> ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsg<'a> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> AnkiVehicleMsg<'a> {
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    // Copies the payload out of the frame, for keeping the message past the buffer.
+    pub fn to_owned(&self) -> AnkiVehicleMsgOwned {
+        AnkiVehicleMsgOwned {
+            size: self.size,
+            msg_id: self.msg_id.clone(),
+            payload: self.payload.to_vec(),
+        }
+    }
+}
+
+// An `AnkiVehicleMsg` holding its own payload.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgOwned {
+    size: u8,
+    pub msg_id: AnkiVehicleMsgType,
+    payload: Vec<u8>,
+}
+
+impl AnkiVehicleMsgOwned {
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    // Borrows the message back, to encode it.
+    pub fn as_msg(&self) -> AnkiVehicleMsg<'_> {
+        AnkiVehicleMsg {
+            size: self.size,
+            msg_id: self.msg_id.clone(),
+            payload: &self.payload,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsg<'_>> for AnkiVehicleMsgOwned {
+    fn from(msg: AnkiVehicleMsg<'_>) -> Self {
+        msg.to_owned()
+    }
+}
+
+// The answer to a ping request, nothing but the header.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgPingResponse {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+}
+
+pub const ANKI_VEHICLE_MSG_PING_RESPONSE_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgPingResponse {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_PING_RESPONSE_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+
+        Ok((AnkiVehicleMsgPingResponse { size, msg_id }, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgPingResponse {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_PING_RESPONSE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgVersionResponse {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -130,18 +286,16 @@ pub struct AnkiVehicleMsgVersionResponse {
 pub const ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE: usize = 4;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgVersionResponse {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let version: u16 = data.gread_with::<u16>(offset, ctx)?;
 
         Ok((
@@ -155,7 +309,23 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgVersionResponse {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgVersionResponse {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u16>(self.version, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgBatteryLevelResponse {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -165,18 +335,16 @@ pub struct AnkiVehicleMsgBatteryLevelResponse {
 pub const ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE: usize = 4;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgBatteryLevelResponse {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let battery_level: u16 = data.gread_with::<u16>(offset, ctx)?;
 
         Ok((
@@ -190,9 +358,25 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgBatteryLevelRespo
     }
 }
 
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgBatteryLevelResponse {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u16>(self.battery_level, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 pub const ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION: u8 = 0x1;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgSdkMode {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -203,24 +387,13 @@ pub struct AnkiVehicleMsgSdkMode {
 pub const ANKI_VEHICLE_MSG_SDK_MODE_SIZE: usize = 4;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSdkMode {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SDK_MODE_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<u8>(self.on, offset, ctx)?;
         data.gwrite_with::<u8>(self.flags, offset, ctx)?;
 
@@ -228,7 +401,35 @@ impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSdkMode {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSdkMode {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SDK_MODE_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let on: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let flags: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgSdkMode {
+                size,
+                msg_id,
+                on,
+                flags,
+            },
+            *offset,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgSetSpeed {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -240,24 +441,13 @@ pub struct AnkiVehicleMsgSetSpeed {
 pub const ANKI_VEHICLE_MSG_SET_SPEED_SIZE: usize = 7;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSetSpeed {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_SET_SPEED_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SET_SPEED_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<i16>(self.speed_mm_per_sec, offset, ctx)?;
         data.gwrite_with::<i16>(self.accel_mm_per_sec2, offset, ctx)?;
         data.gwrite_with::<u8>(self.respect_road_piece_speed_limit, offset, ctx)?;
@@ -266,7 +456,42 @@ impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSetSpeed {
     }
 }
 
-#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSetSpeed {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SET_SPEED_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let speed_mm_per_sec: i16 = data.gread_with::<i16>(offset, ctx)?;
+        let accel_mm_per_sec2: i16 = data.gread_with::<i16>(offset, ctx)?;
+        let respect_road_piece_speed_limit: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgSetSpeed {
+                size,
+                msg_id,
+                speed_mm_per_sec,
+                accel_mm_per_sec2,
+                respect_road_piece_speed_limit,
+            },
+            *offset,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum VehicleTurn {
     None = 0,
@@ -276,7 +501,14 @@ pub enum VehicleTurn {
     UTurnJump = 4,
 }
 
-#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
+#[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum VehicleTurnTrigger {
     // Run immediately
@@ -285,7 +517,9 @@ pub enum VehicleTurnTrigger {
     Intersection = 1,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgTurn {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -296,44 +530,63 @@ pub struct AnkiVehicleMsgTurn {
 pub const ANKI_VEHICLE_MSG_TURN_SIZE: usize = 4;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgTurn {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_TURN_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_TURN_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
-        data.gwrite_with::<u8>(
-            self.turn_type
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.turn_type.into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.trigger.into(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgTurn {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_TURN_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let turn_type = data.gread_with::<u8>(offset, ctx)?;
+        let turn_type: VehicleTurn =
+            turn_type
                 .try_into()
-                .unwrap_or_else(|_| VehicleTurn::None.into()),
-            offset,
-            ctx,
-        )?;
-        data.gwrite_with::<u8>(
-            self.trigger
+                .map_err(|_| ProtocolError::InvalidEnumValue {
+                    field: "turn_type",
+                    value: turn_type as u32,
+                })?;
+        let trigger = data.gread_with::<u8>(offset, ctx)?;
+        let trigger: VehicleTurnTrigger =
+            trigger
                 .try_into()
-                .unwrap_or_else(|_| VehicleTurnTrigger::Immediate.into()),
-            offset,
-            ctx,
-        )?;
+                .map_err(|_| ProtocolError::InvalidEnumValue {
+                    field: "trigger",
+                    value: trigger as u32,
+                })?;
 
-        Ok(*offset)
+        Ok((
+            AnkiVehicleMsgTurn {
+                size,
+                msg_id,
+                turn_type,
+                trigger,
+            },
+            *offset,
+        ))
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgSetOffsetFromRoadCentre {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -343,31 +596,46 @@ pub struct AnkiVehicleMsgSetOffsetFromRoadCentre {
 pub const ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE: usize = 6;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSetOffsetFromRoadCentre {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<f32>(self.offset_mm, offset, ctx)?;
 
         Ok(*offset)
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSetOffsetFromRoadCentre {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let offset_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgSetOffsetFromRoadCentre {
+                size,
+                msg_id,
+                offset_mm,
+            },
+            *offset,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgChangeLane {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -381,24 +649,13 @@ pub struct AnkiVehicleMsgChangeLane {
 pub const ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE: usize = 12;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgChangeLane {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<u16>(self.horizontal_speed_mm_per_sec, offset, ctx)?;
         data.gwrite_with::<u16>(self.horizontal_accel_mm_per_sec2, offset, ctx)?;
         data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
@@ -409,12 +666,95 @@ impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgChangeLane {
     }
 }
 
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgChangeLane {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let horizontal_speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let horizontal_accel_mm_per_sec2: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
+        let hop_intent: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let tag: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgChangeLane {
+                size,
+                msg_id,
+                horizontal_speed_mm_per_sec,
+                horizontal_accel_mm_per_sec2,
+                offset_from_road_centre_mm,
+                hop_intent,
+                tag,
+            },
+            *offset,
+        ))
+    }
+}
+
 pub const PARSE_FLAGS_MASK_NUM_BITS: u8 = 0x0f;
 pub const PARSE_FLAGS_MASK_INVERTED_COLOR: u8 = 0x80;
 pub const PARSE_FLAGS_MASK_REVERSE_PARSING: u8 = 0x40;
 pub const PARSE_FLAGS_MASK_REVERSE_DRIVING: u8 = 0x20;
 
-#[derive(Debug, PartialEq)]
+// The parsing flags of a position update, how the vehicle read the last location code.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ParsingFlags(u8);
+
+impl ParsingFlags {
+    pub const fn from_bits(bits: u8) -> ParsingFlags {
+        ParsingFlags(bits)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    // How many bits the location code had.
+    pub const fn num_code_bits(&self) -> u8 {
+        self.0 & PARSE_FLAGS_MASK_NUM_BITS
+    }
+
+    pub const fn is_inverted_color(&self) -> bool {
+        self.0 & PARSE_FLAGS_MASK_INVERTED_COLOR != 0
+    }
+
+    pub const fn is_reverse_parsing(&self) -> bool {
+        self.0 & PARSE_FLAGS_MASK_REVERSE_PARSING != 0
+    }
+
+    pub const fn is_reverse_driving(&self) -> bool {
+        self.0 & PARSE_FLAGS_MASK_REVERSE_DRIVING != 0
+    }
+}
+
+impl From<u8> for ParsingFlags {
+    fn from(bits: u8) -> Self {
+        ParsingFlags(bits)
+    }
+}
+
+impl From<ParsingFlags> for u8 {
+    fn from(flags: ParsingFlags) -> Self {
+        flags.0
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgLocalisationPositionUpdate {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -422,7 +762,7 @@ pub struct AnkiVehicleMsgLocalisationPositionUpdate {
     pub road_piece_id: u8,
     pub offset_from_road_centre_mm: f32,
     pub speed_mm_per_sec: u16,
-    pub parsing_flags: u8,
+    pub parsing_flags: ParsingFlags,
 
     /* ACK commands received */
     pub last_recv_lane_change_cmd_id: u8,
@@ -434,23 +774,21 @@ pub struct AnkiVehicleMsgLocalisationPositionUpdate {
 pub const ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE: usize = 17;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationPositionUpdate {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let location_id: u8 = data.gread_with::<u8>(offset, ctx)?;
         let road_piece_id: u8 = data.gread_with::<u8>(offset, ctx)?;
         let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
         let speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
-        let parsing_flags: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let parsing_flags = ParsingFlags::from_bits(data.gread_with::<u8>(offset, ctx)?);
         let last_recv_lane_change_cmd_id: u8 = data.gread_with::<u8>(offset, ctx)?;
         let last_exec_lane_change_cmd_id: u8 = data.gread_with::<u8>(offset, ctx)?;
         let last_desired_lane_change_speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
@@ -475,6 +813,28 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationPosit
     }
 }
 
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgLocalisationPositionUpdate {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.location_id, offset, ctx)?;
+        data.gwrite_with::<u8>(self.road_piece_id, offset, ctx)?;
+        data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
+        data.gwrite_with::<u16>(self.speed_mm_per_sec, offset, ctx)?;
+        data.gwrite_with::<u8>(self.parsing_flags.bits(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.last_recv_lane_change_cmd_id, offset, ctx)?;
+        data.gwrite_with::<u8>(self.last_exec_lane_change_cmd_id, offset, ctx)?;
+        data.gwrite_with::<u16>(self.last_desired_lane_change_speed_mm_per_sec, offset, ctx)?;
+        data.gwrite_with::<u16>(self.last_desired_speed_mm_per_sec, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 #[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u8)]
 #[allow(unused)]
@@ -483,7 +843,9 @@ enum AnkiVehicleDrivingDirection {
     Reverse = 1,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgLocalisationTransitionUpdate {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -510,18 +872,16 @@ pub struct AnkiVehicleMsgLocalisationTransitionUpdate {
 pub const ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE: usize = 18;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationTransitionUpdate {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let road_piece_idx: i8 = data.gread_with::<i8>(offset, ctx)?;
         let road_piece_idx_prev: i8 = data.gread_with::<i8>(offset, ctx)?;
         let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
@@ -557,7 +917,39 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationTrans
     }
 }
 
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgLocalisationTransitionUpdate {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<i8>(self.road_piece_idx, offset, ctx)?;
+        data.gwrite_with::<i8>(self.road_piece_idx_prev, offset, ctx)?;
+        data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
+        data.gwrite_with::<u8>(self.last_recv_lane_change_id, offset, ctx)?;
+        data.gwrite_with::<u8>(self.last_exec_lane_change_id, offset, ctx)?;
+        data.gwrite_with::<u16>(self.last_desired_lane_change_speed_mm_per_sec, offset, ctx)?;
+        data.gwrite_with::<i8>(self.ave_follow_line_drift_pixels, offset, ctx)?;
+        data.gwrite_with::<u8>(self.had_lane_change_activity, offset, ctx)?;
+        data.gwrite_with::<u8>(self.uphill_counter, offset, ctx)?;
+        data.gwrite_with::<u8>(self.downhill_counter, offset, ctx)?;
+        data.gwrite_with::<u8>(self.left_wheel_dist_cm, offset, ctx)?;
+        data.gwrite_with::<u8>(self.right_wheel_dist_cm, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum IntersectionCode {
     None = 0,
@@ -567,7 +959,9 @@ pub enum IntersectionCode {
     ExitSecond = 4,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgLocalisationIntersectionUpdate {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -583,24 +977,23 @@ pub struct AnkiVehicleMsgLocalisationIntersectionUpdate {
 pub const ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE: usize = 13;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationIntersectionUpdate {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let road_piece_idx: i8 = data.gread_with::<i8>(offset, ctx)?;
         let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
+        // Strict mode refuses codes out of range, see `validation::validate_notification`.
         let intersection_code: IntersectionCode = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| IntersectionCode::None);
+            .unwrap_or(IntersectionCode::None);
         let is_exiting: u8 = data.gread_with::<u8>(offset, ctx)?;
         let mm_since_last_transition_bar: u16 = data.gread_with::<u16>(offset, ctx)?;
         let mm_since_last_intersection_code: u16 = data.gread_with::<u16>(offset, ctx)?;
@@ -621,29 +1014,89 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLocalisationInter
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct AnkiVehicleMsgOffsetFromRoadCentreUpdate {
-    size: u8,
-    msg_id: AnkiVehicleMsgType,
-    pub offset_from_road_centre_mm: f32,
-    pub lane_change_id: u8,
-}
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgLocalisationIntersectionUpdate {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE)?;
 
-pub const ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE: usize = 7;
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<i8>(self.road_piece_idx, offset, ctx)?;
+        data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
+        data.gwrite_with::<u8>(self.intersection_code.into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.is_exiting, offset, ctx)?;
+        data.gwrite_with::<u16>(self.mm_since_last_transition_bar, offset, ctx)?;
+        data.gwrite_with::<u16>(self.mm_since_last_intersection_code, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Sent when the vehicle loses the track, nothing but the header. Position updates stop until it
+// finds a location code again.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgVehicleDelocalized {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+}
+
+pub const ANKI_VEHICLE_MSG_VEHICLE_DELOCALIZED_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgVehicleDelocalized {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_VEHICLE_DELOCALIZED_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+
+        Ok((AnkiVehicleMsgVehicleDelocalized { size, msg_id }, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgVehicleDelocalized {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_VEHICLE_DELOCALIZED_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+    pub offset_from_road_centre_mm: f32,
+    pub lane_change_id: u8,
+}
+
+pub const ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE: usize = 7;
 
 impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgOffsetFromRoadCentreUpdate {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE {
-            return Err((scroll::Error::Custom("Incorrect num of bytes".to_string())).into());
-        }
+        check_frame_len(data, ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE)?;
 
         let offset = &mut 0;
         let size: u8 = data.gread_with::<u8>(offset, ctx)?;
         let msg_id: AnkiVehicleMsgType = data
             .gread_with::<u8>(offset, ctx)?
             .try_into()
-            .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown);
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
         let offset_from_road_centre_mm: f32 = data.gread_with::<f32>(offset, ctx)?;
         let lane_change_id: u8 = data.gread_with::<u8>(offset, ctx)?;
 
@@ -659,6 +1112,372 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgOffsetFromRoadCen
     }
 }
 
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<f32>(self.offset_from_road_centre_mm, offset, ctx)?;
+        data.gwrite_with::<u8>(self.lane_change_id, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// The notifications below only come from Overdrive firmware. The drive-sdk never documented
+// them, their layouts are as captured from Overdrive vehicles.
+
+// Sent as the vehicle's speed settles after a set speed command.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgSpeedUpdate {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+    pub desired_speed_mm_per_sec: u16,
+    pub accel_mm_per_sec2: u16,
+    pub speed_mm_per_sec: u16,
+}
+
+pub const ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE: usize = 8;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSpeedUpdate {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let desired_speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let accel_mm_per_sec2: u16 = data.gread_with::<u16>(offset, ctx)?;
+        let speed_mm_per_sec: u16 = data.gread_with::<u16>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgSpeedUpdate {
+                size,
+                msg_id,
+                desired_speed_mm_per_sec,
+                accel_mm_per_sec2,
+                speed_mm_per_sec,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSpeedUpdate {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u16>(self.desired_speed_mm_per_sec, offset, ctx)?;
+        data.gwrite_with::<u16>(self.accel_mm_per_sec2, offset, ctx)?;
+        data.gwrite_with::<u16>(self.speed_mm_per_sec, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Sent when the vehicle is put on or taken off the track or the charger. Each flag is 0 or 1.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgChargerInfo {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+    pub on_track: u8,
+    pub on_charger: u8,
+    pub battery_low: u8,
+    pub battery_full: u8,
+}
+
+pub const ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE: usize = 6;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgChargerInfo {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let on_track: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let on_charger: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let battery_low: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let battery_full: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgChargerInfo {
+                size,
+                msg_id,
+                on_track,
+                on_charger,
+                battery_low,
+                battery_full,
+            },
+            *offset,
+        ))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgChargerInfo {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.on_track, offset, ctx)?;
+        data.gwrite_with::<u8>(self.on_charger, offset, ctx)?;
+        data.gwrite_with::<u8>(self.battery_low, offset, ctx)?;
+        data.gwrite_with::<u8>(self.battery_full, offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Sent when the vehicle detects it has hit something.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgCollisionDetected {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+}
+
+pub const ANKI_VEHICLE_MSG_COLLISION_DETECTED_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgCollisionDetected {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_COLLISION_DETECTED_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+
+        Ok((AnkiVehicleMsgCollisionDetected { size, msg_id }, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgCollisionDetected {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_COLLISION_DETECTED_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Sent when the vehicle's control loop has run over its time budget.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnkiVehicleMsgCycleOvertime {
+    size: u8,
+    msg_id: AnkiVehicleMsgType,
+}
+
+pub const ANKI_VEHICLE_MSG_CYCLE_OVERTIME_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgCycleOvertime {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_CYCLE_OVERTIME_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+
+        Ok((AnkiVehicleMsgCycleOvertime { size, msg_id }, *offset))
+    }
+}
+
+impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgCycleOvertime {
+    type Error = AnkiError;
+    fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
+        check_buffer_len(data, ANKI_VEHICLE_MSG_CYCLE_OVERTIME_SIZE)?;
+
+        let offset = &mut 0;
+        data.gwrite_with::<u8>(self.size, offset, ctx)?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
+
+        Ok(*offset)
+    }
+}
+
+// Any notification a vehicle sends, read in one go instead of reading the header and then the
+// buffer again as whichever struct the id calls for. Ids that aren't notifications, including
+// ones nobody knows, come back raw.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum VehicleMessage<'a> {
+    PingResponse(AnkiVehicleMsgPingResponse),
+    VersionResponse(AnkiVehicleMsgVersionResponse),
+    BatteryLevelResponse(AnkiVehicleMsgBatteryLevelResponse),
+    PositionUpdate(AnkiVehicleMsgLocalisationPositionUpdate),
+    TransitionUpdate(AnkiVehicleMsgLocalisationTransitionUpdate),
+    IntersectionUpdate(AnkiVehicleMsgLocalisationIntersectionUpdate),
+    Delocalized(AnkiVehicleMsgVehicleDelocalized),
+    OffsetFromRoadCentreUpdate(AnkiVehicleMsgOffsetFromRoadCentreUpdate),
+    SpeedUpdate(AnkiVehicleMsgSpeedUpdate),
+    ChargerInfo(AnkiVehicleMsgChargerInfo),
+    CollisionDetected(AnkiVehicleMsgCollisionDetected),
+    CycleOvertime(AnkiVehicleMsgCycleOvertime),
+    // Borrows the payload from the frame until made owned.
+    Unknown { msg_id: u8, payload: Cow<'a, [u8]> },
+}
+
+impl<'a> VehicleMessage<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<VehicleMessage<'a>, AnkiError> {
+        data.pread_with(0, WIRE_ENDIAN)
+    }
+
+    // Same as `parse`, for a frame that may have padding after the message.
+    pub fn parse_padded(data: &'a [u8]) -> Result<VehicleMessage<'a>, AnkiError> {
+        VehicleMessage::parse(strip_padding(data))
+    }
+
+    // Detaches the message from the frame it was read from, for keeping it past the buffer.
+    pub fn into_owned(self) -> VehicleMessage<'static> {
+        match self {
+            VehicleMessage::PingResponse(msg) => VehicleMessage::PingResponse(msg),
+            VehicleMessage::VersionResponse(msg) => VehicleMessage::VersionResponse(msg),
+            VehicleMessage::BatteryLevelResponse(msg) => VehicleMessage::BatteryLevelResponse(msg),
+            VehicleMessage::PositionUpdate(msg) => VehicleMessage::PositionUpdate(msg),
+            VehicleMessage::TransitionUpdate(msg) => VehicleMessage::TransitionUpdate(msg),
+            VehicleMessage::IntersectionUpdate(msg) => VehicleMessage::IntersectionUpdate(msg),
+            VehicleMessage::Delocalized(msg) => VehicleMessage::Delocalized(msg),
+            VehicleMessage::OffsetFromRoadCentreUpdate(msg) => {
+                VehicleMessage::OffsetFromRoadCentreUpdate(msg)
+            }
+            VehicleMessage::SpeedUpdate(msg) => VehicleMessage::SpeedUpdate(msg),
+            VehicleMessage::ChargerInfo(msg) => VehicleMessage::ChargerInfo(msg),
+            VehicleMessage::CollisionDetected(msg) => VehicleMessage::CollisionDetected(msg),
+            VehicleMessage::CycleOvertime(msg) => VehicleMessage::CycleOvertime(msg),
+            VehicleMessage::Unknown { msg_id, payload } => VehicleMessage::Unknown {
+                msg_id,
+                payload: Cow::Owned(payload.into_owned()),
+            },
+        }
+    }
+
+    pub fn msg_type(&self) -> AnkiVehicleMsgType {
+        match self {
+            VehicleMessage::PingResponse(_) => AnkiVehicleMsgType::V2CPingResponse,
+            VehicleMessage::VersionResponse(_) => AnkiVehicleMsgType::V2CVersionResponse,
+            VehicleMessage::BatteryLevelResponse(_) => AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            VehicleMessage::PositionUpdate(_) => AnkiVehicleMsgType::V2CLocalisationPositionUpdate,
+            VehicleMessage::TransitionUpdate(_) => {
+                AnkiVehicleMsgType::V2CLocalisationTransitionUpdate
+            }
+            VehicleMessage::IntersectionUpdate(_) => {
+                AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate
+            }
+            VehicleMessage::Delocalized(_) => AnkiVehicleMsgType::V2CVehicleDelocalized,
+            VehicleMessage::OffsetFromRoadCentreUpdate(_) => {
+                AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate
+            }
+            VehicleMessage::SpeedUpdate(_) => AnkiVehicleMsgType::V2CSpeedUpdate,
+            VehicleMessage::ChargerInfo(_) => AnkiVehicleMsgType::V2CChargerInfo,
+            VehicleMessage::CollisionDetected(_) => AnkiVehicleMsgType::V2CCollisionDetected,
+            VehicleMessage::CycleOvertime(_) => AnkiVehicleMsgType::V2CCycleOvertime,
+            VehicleMessage::Unknown { msg_id, .. } => {
+                AnkiVehicleMsgType::try_from(*msg_id).unwrap_or(AnkiVehicleMsgType::Unknown)
+            }
+        }
+    }
+}
+
+// Some BLE stacks pad notifications out to the full 20 bytes. The size byte says where the message
+// ends, anything after it is cut off. A frame no longer than it declares comes back unchanged, so a
+// short one still fails to parse.
+pub fn strip_padding(data: &[u8]) -> &[u8] {
+    match data.first() {
+        Some(&size) if size as usize + 1 < data.len() => &data[..size as usize + 1],
+        _ => data,
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for VehicleMessage<'a> {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        let (msg, len) = AnkiVehicleMsg::try_from_ctx(data, ctx)?;
+        let message = match msg.msg_id {
+            AnkiVehicleMsgType::V2CPingResponse => {
+                VehicleMessage::PingResponse(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CVersionResponse => {
+                VehicleMessage::VersionResponse(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+                VehicleMessage::BatteryLevelResponse(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate => {
+                VehicleMessage::PositionUpdate(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => {
+                VehicleMessage::TransitionUpdate(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => {
+                VehicleMessage::IntersectionUpdate(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CVehicleDelocalized => {
+                VehicleMessage::Delocalized(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => {
+                VehicleMessage::OffsetFromRoadCentreUpdate(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CSpeedUpdate => {
+                VehicleMessage::SpeedUpdate(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CChargerInfo => {
+                VehicleMessage::ChargerInfo(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CCollisionDetected => {
+                VehicleMessage::CollisionDetected(data.pread_with(0, ctx)?)
+            }
+            AnkiVehicleMsgType::V2CCycleOvertime => {
+                VehicleMessage::CycleOvertime(data.pread_with(0, ctx)?)
+            }
+            _ => VehicleMessage::Unknown {
+                msg_id: data[1],
+                payload: Cow::Borrowed(msg.payload),
+            },
+        };
+        Ok((message, len))
+    }
+}
+
 // TODO: Work out what this is used for. Think it is for the helper macros below.
 #[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u8)]
@@ -672,7 +1491,9 @@ enum Light {
 
 // TODO: Helper macros for parsing lights bits
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgSetLights {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -682,35 +1503,55 @@ pub struct AnkiVehicleMsgSetLights {
 pub const ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE: usize = 3;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSetLights {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<u8>(self.light_mask, offset, ctx)?;
 
         Ok(*offset)
     }
 }
 
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSetLights {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let light_mask: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleMsgSetLights {
+                size,
+                msg_id,
+                light_mask,
+            },
+            *offset,
+        ))
+    }
+}
+
 // TODO: Check type requirements of these below
 pub const ANKI_VEHICLE_MAX_LIGHT_INTENSITY: u8 = 14;
 pub const ANKI_VEHICLE_MAX_LIGHT_TIME: u8 = 11;
 
 #[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum LightChannel {
     Red = 0,
@@ -723,6 +1564,13 @@ pub enum LightChannel {
 }
 
 #[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum LightEffect {
     // Simply set the light intensity to 'start' value
@@ -738,7 +1586,9 @@ pub enum LightEffect {
     Count = 5,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleLightConfig {
     channel: LightChannel,
     effect: LightEffect,
@@ -751,35 +1601,18 @@ const LIGHT_CHANNEL_COUNT_MAX: usize = 3;
 pub const ANKI_VEHICLE_LIGHT_CONFIG_SIZE: usize = 5;
 
 impl ctx::TryIntoCtx<scroll::Endian> for &AnkiVehicleLightConfig {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        // TODO: This might break if a bigger size data is inputted.
         if data.len() < ANKI_VEHICLE_LIGHT_CONFIG_SIZE || data.len() > ANKI_VEHICLE_MSG_MAX_SIZE {
-            return Err((scroll::Error::Custom(
-                "Invalid space requirements in byte array. data_len:"
-                    .to_string()
-                    .add(&*(data.len().to_string())),
-            ))
-            .into());
+            return Err(AnkiError::BufferSize {
+                expected: ANKI_VEHICLE_LIGHT_CONFIG_SIZE,
+                got: data.len(),
+            });
         }
 
         let offset = &mut 0;
-        data.gwrite_with::<u8>(
-            self.channel
-                .clone()
-                .try_into()
-                .unwrap_or_else(|_| LightChannel::Tail.into()),
-            offset,
-            ctx,
-        )?;
-        data.gwrite_with::<u8>(
-            self.effect
-                .clone()
-                .try_into()
-                .unwrap_or_else(|_| LightEffect::Steady.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.channel.clone().into(), offset, ctx)?;
+        data.gwrite_with::<u8>(self.effect.clone().into(), offset, ctx)?;
         data.gwrite_with::<u8>(self.start, offset, ctx)?;
         data.gwrite_with::<u8>(self.end, offset, ctx)?;
         data.gwrite_with::<u8>(self.cycles_per_10_sec, offset, ctx)?;
@@ -788,7 +1621,54 @@ impl ctx::TryIntoCtx<scroll::Endian> for &AnkiVehicleLightConfig {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleLightConfig {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        if data.len() < ANKI_VEHICLE_LIGHT_CONFIG_SIZE {
+            return Err(ProtocolError::TooShort {
+                expected: ANKI_VEHICLE_LIGHT_CONFIG_SIZE,
+                got: data.len(),
+            }
+            .into());
+        }
+
+        let offset = &mut 0;
+        let channel = data.gread_with::<u8>(offset, ctx)?;
+        let channel: LightChannel =
+            channel
+                .try_into()
+                .map_err(|_| ProtocolError::InvalidEnumValue {
+                    field: "channel",
+                    value: channel as u32,
+                })?;
+        let effect = data.gread_with::<u8>(offset, ctx)?;
+        let effect: LightEffect =
+            effect
+                .try_into()
+                .map_err(|_| ProtocolError::InvalidEnumValue {
+                    field: "effect",
+                    value: effect as u32,
+                })?;
+        let start: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let end: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let cycles_per_10_sec: u8 = data.gread_with::<u8>(offset, ctx)?;
+
+        Ok((
+            AnkiVehicleLightConfig {
+                channel,
+                effect,
+                start,
+                end,
+                cycles_per_10_sec,
+            },
+            *offset,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgLightsPattern {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -800,37 +1680,24 @@ pub const ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE: usize =
     (LIGHT_CHANNEL_COUNT_MAX * ANKI_VEHICLE_LIGHT_CONFIG_SIZE) + 3;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgLightsPattern {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx<'a>(
         self,
         data: &'a mut [u8],
         ctx: scroll::Endian,
     ) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<u8>(self.channel_count, offset, ctx)?;
 
-        for i in 0..LIGHT_CHANNEL_COUNT_MAX {
-            // TODO: This could panic if wrong arguments entered.
-            let config = self.channel_config.get(i).unwrap().as_ref();
+        for config in &self.channel_config {
             match config {
                 None => {
                     data.gwrite_with::<&'a [u8]>(
-                        &[0u8; ANKI_VEHICLE_LIGHT_CONFIG_SIZE as usize],
+                        &[0u8; ANKI_VEHICLE_LIGHT_CONFIG_SIZE],
                         offset,
                         (),
                     )?;
@@ -845,7 +1712,55 @@ impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgLightsPattern {
     }
 }
 
-#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
+// Channels past the channel count are padding and read as None, whatever bytes they hold.
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgLightsPattern {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let channel_count: u8 = data.gread_with::<u8>(offset, ctx)?;
+        if channel_count as usize > LIGHT_CHANNEL_COUNT_MAX {
+            return Err(ProtocolError::FieldOutOfRange {
+                field: "channel_count",
+                value: channel_count as u32,
+            }
+            .into());
+        }
+        let mut channel_config = [None, None, None];
+        for (i, config) in channel_config.iter_mut().enumerate() {
+            if i < channel_count as usize {
+                *config = Some(data.gread_with::<AnkiVehicleLightConfig>(offset, ctx)?);
+            } else {
+                *offset += ANKI_VEHICLE_LIGHT_CONFIG_SIZE;
+            }
+        }
+
+        Ok((
+            AnkiVehicleMsgLightsPattern {
+                size,
+                msg_id,
+                channel_count,
+                channel_config,
+            },
+            *offset,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[repr(u8)]
 pub enum TrackMaterial {
     Plastic = 0,
@@ -856,7 +1771,9 @@ pub const SUPERCODE_NONE: u8 = 0;
 pub const SUPERCODE_BOOST_JUMP: u8 = 1;
 pub const SUPERCODE_ALL: u8 = SUPERCODE_BOOST_JUMP;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnkiVehicleMsgSetConfigParams {
     size: u8,
     msg_id: AnkiVehicleMsgType,
@@ -867,37 +1784,53 @@ pub struct AnkiVehicleMsgSetConfigParams {
 pub const ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE: usize = 4;
 
 impl ctx::TryIntoCtx<scroll::Endian> for AnkiVehicleMsgSetConfigParams {
-    type Error = scroll::Error;
+    type Error = AnkiError;
     fn try_into_ctx(self, data: &mut [u8], ctx: scroll::Endian) -> Result<usize, Self::Error> {
-        if data.len() != ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE {
-            return Err((scroll::Error::Custom(
-                "Not enough space available in byte array".to_string(),
-            ))
-            .into());
-        }
+        check_buffer_len(data, ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE)?;
 
         let offset = &mut 0;
         data.gwrite_with::<u8>(self.size, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.msg_id
-                .try_into()
-                .unwrap_or_else(|_| AnkiVehicleMsgType::Unknown.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.msg_id.into(), offset, ctx)?;
         data.gwrite_with::<u8>(self.super_code_parse_mask, offset, ctx)?;
-        data.gwrite_with::<u8>(
-            self.track_material
-                .try_into()
-                .unwrap_or_else(|_| TrackMaterial::Plastic.into()),
-            offset,
-            ctx,
-        )?;
+        data.gwrite_with::<u8>(self.track_material.into(), offset, ctx)?;
 
         Ok(*offset)
     }
 }
 
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for AnkiVehicleMsgSetConfigParams {
+    type Error = AnkiError;
+    fn try_from_ctx(data: &'a [u8], ctx: scroll::Endian) -> Result<(Self, usize), Self::Error> {
+        check_frame_len(data, ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE)?;
+
+        let offset = &mut 0;
+        let size: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let msg_id: AnkiVehicleMsgType = data
+            .gread_with::<u8>(offset, ctx)?
+            .try_into()
+            .unwrap_or(AnkiVehicleMsgType::Unknown);
+        let super_code_parse_mask: u8 = data.gread_with::<u8>(offset, ctx)?;
+        let track_material = data.gread_with::<u8>(offset, ctx)?;
+        let track_material: TrackMaterial =
+            track_material
+                .try_into()
+                .map_err(|_| ProtocolError::InvalidEnumValue {
+                    field: "track_material",
+                    value: track_material as u32,
+                })?;
+
+        Ok((
+            AnkiVehicleMsgSetConfigParams {
+                size,
+                msg_id,
+                super_code_parse_mask,
+                track_material,
+            },
+            *offset,
+        ))
+    }
+}
+
 pub fn anki_vehicle_msg_set_sdk_mode(on: u8, flags: u8) -> AnkiVehicleMsgSdkMode {
     AnkiVehicleMsgSdkMode {
         size: ANKI_VEHICLE_MSG_SDK_MODE_SIZE as u8 - 1,
@@ -995,20 +1928,62 @@ pub fn anki_vehicle_msg_lights_pattern(
     }
 }
 
+// A pattern with no channels yet, filled in with `append`.
+pub fn anki_vehicle_msg_lights_pattern_empty() -> AnkiVehicleMsgLightsPattern {
+    AnkiVehicleMsgLightsPattern {
+        size: ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE as u8 - 1,
+        msg_id: AnkiVehicleMsgType::C2VLightsPattern,
+        channel_count: 0,
+        channel_config: [None, None, None],
+    }
+}
+
 impl AnkiVehicleMsgLightsPattern {
-    pub fn append(&mut self, config: AnkiVehicleLightConfig) -> u8 {
-        if self.channel_count >= 3 {
-            return 0;
-        }
-        self.channel_config[self.channel_count as usize] = Some(config);
+    // Adds a channel, returning the number of channels now in the pattern.
+    pub fn append(&mut self, config: AnkiVehicleLightConfig) -> Result<u8, AnkiError> {
+        let slot = self
+            .channel_config
+            .get_mut(self.channel_count as usize)
+            .ok_or(AnkiError::LightsPatternFull(LIGHT_CHANNEL_COUNT_MAX))?;
+        *slot = Some(config);
         self.channel_count += 1;
-        self.channel_count
+        Ok(self.channel_count)
+    }
+
+    // The most channels a pattern can hold.
+    pub const fn capacity() -> usize {
+        LIGHT_CHANNEL_COUNT_MAX
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count as usize
+    }
+
+    pub fn remaining(&self) -> usize {
+        LIGHT_CHANNEL_COUNT_MAX.saturating_sub(self.channel_count())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &AnkiVehicleLightConfig> {
+        self.channel_config.iter().flatten()
     }
 }
 
+// The commands below carry no payload, so they are also available already encoded for callers
+// that can't spare the time or the buffer to encode them.
+const fn header_only_msg_bytes(msg_id: AnkiVehicleMsgType) -> [u8; ANKI_VEHICLE_MSG_BASE_SIZE] {
+    [ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1, msg_id as u8]
+}
+
 pub const ANKI_VEHICLE_MSG_PING_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
 
-pub fn anki_vehicle_msg_ping<'a>() -> AnkiVehicleMsg<'a> {
+pub const ANKI_VEHICLE_MSG_PING_BYTES: [u8; ANKI_VEHICLE_MSG_PING_SIZE] =
+    header_only_msg_bytes(AnkiVehicleMsgType::C2CPingRequest);
+
+pub const fn anki_vehicle_msg_ping<'a>() -> AnkiVehicleMsg<'a> {
     AnkiVehicleMsg {
         size: ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1,
         msg_id: AnkiVehicleMsgType::C2CPingRequest,
@@ -1018,7 +1993,10 @@ pub fn anki_vehicle_msg_ping<'a>() -> AnkiVehicleMsg<'a> {
 
 pub const ANKI_VEHICLE_MSG_DISCONNECT_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
 
-pub fn anki_vehicle_msg_disconnect() -> AnkiVehicleMsg<'static> {
+pub const ANKI_VEHICLE_MSG_DISCONNECT_BYTES: [u8; ANKI_VEHICLE_MSG_DISCONNECT_SIZE] =
+    header_only_msg_bytes(AnkiVehicleMsgType::C2VDisconnect);
+
+pub const fn anki_vehicle_msg_disconnect() -> AnkiVehicleMsg<'static> {
     AnkiVehicleMsg {
         size: ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1,
         msg_id: AnkiVehicleMsgType::C2VDisconnect,
@@ -1028,7 +2006,10 @@ pub fn anki_vehicle_msg_disconnect() -> AnkiVehicleMsg<'static> {
 
 pub const ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
 
-pub fn anki_vehicle_msg_get_version() -> AnkiVehicleMsg<'static> {
+pub const ANKI_VEHICLE_MSG_VERSION_REQUEST_BYTES: [u8; ANKI_VEHICLE_MSG_VERSION_REQUEST_SIZE] =
+    header_only_msg_bytes(AnkiVehicleMsgType::C2VVersionRequest);
+
+pub const fn anki_vehicle_msg_get_version() -> AnkiVehicleMsg<'static> {
     AnkiVehicleMsg {
         size: ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1,
         msg_id: AnkiVehicleMsgType::C2VVersionRequest,
@@ -1038,7 +2019,11 @@ pub fn anki_vehicle_msg_get_version() -> AnkiVehicleMsg<'static> {
 
 pub const ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
 
-pub fn anki_vehicle_msg_get_battery_level() -> AnkiVehicleMsg<'static> {
+pub const ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_BYTES: [u8;
+    ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE] =
+    header_only_msg_bytes(AnkiVehicleMsgType::C2VBatteryLevelRequest);
+
+pub const fn anki_vehicle_msg_get_battery_level() -> AnkiVehicleMsg<'static> {
     AnkiVehicleMsg {
         size: ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1,
         msg_id: AnkiVehicleMsgType::C2VBatteryLevelRequest,
@@ -1048,7 +2033,11 @@ pub fn anki_vehicle_msg_get_battery_level() -> AnkiVehicleMsg<'static> {
 
 pub const ANKI_VEHICLE_MSG_CANCEL_LANE_CHANGE_SIZE: usize = ANKI_VEHICLE_MSG_BASE_SIZE;
 
-pub fn anki_vehicle_msg_cancel_lane_change() -> AnkiVehicleMsg<'static> {
+pub const ANKI_VEHICLE_MSG_CANCEL_LANE_CHANGE_BYTES: [u8;
+    ANKI_VEHICLE_MSG_CANCEL_LANE_CHANGE_SIZE] =
+    header_only_msg_bytes(AnkiVehicleMsgType::C2VCancelLaneChange);
+
+pub const fn anki_vehicle_msg_cancel_lane_change() -> AnkiVehicleMsg<'static> {
     AnkiVehicleMsg {
         size: ANKI_VEHICLE_MSG_BASE_SIZE as u8 - 1,
         msg_id: AnkiVehicleMsgType::C2VCancelLaneChange,
@@ -1089,9 +2078,116 @@ pub fn anki_vehicle_msg_set_config_params(
     }
 }
 
+impl WireCodec for AnkiVehicleMsgPingResponse {
+    const SIZE: usize = ANKI_VEHICLE_MSG_PING_RESPONSE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgVersionResponse {
+    const SIZE: usize = ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgBatteryLevelResponse {
+    const SIZE: usize = ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSdkMode {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SDK_MODE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSetSpeed {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SET_SPEED_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgTurn {
+    const SIZE: usize = ANKI_VEHICLE_MSG_TURN_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSetOffsetFromRoadCentre {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgChangeLane {
+    const SIZE: usize = ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgLocalisationPositionUpdate {
+    const SIZE: usize = ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgLocalisationTransitionUpdate {
+    const SIZE: usize = ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgLocalisationIntersectionUpdate {
+    const SIZE: usize = ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgVehicleDelocalized {
+    const SIZE: usize = ANKI_VEHICLE_MSG_VEHICLE_DELOCALIZED_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+    const SIZE: usize = ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSpeedUpdate {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgChargerInfo {
+    const SIZE: usize = ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgCollisionDetected {
+    const SIZE: usize = ANKI_VEHICLE_MSG_COLLISION_DETECTED_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgCycleOvertime {
+    const SIZE: usize = ANKI_VEHICLE_MSG_CYCLE_OVERTIME_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSetLights {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgLightsPattern {
+    const SIZE: usize = ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE;
+}
+
+impl WireCodec for AnkiVehicleMsgSetConfigParams {
+    const SIZE: usize = ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE;
+}
+
+// Commands also encode to an array of exactly their size, for frames kept in a static or handed
+// to a host that wants a fixed length.
+macro_rules! command_to_array {
+    ($($msg:ty => $size:ident),* $(,)?) => {
+        $(
+            impl $msg {
+                pub fn to_array(&self) -> [u8; $size] {
+                    let mut data = [0u8; $size];
+                    data.copy_from_slice(&self.to_command());
+                    data
+                }
+            }
+        )*
+    };
+}
+
+command_to_array! {
+    AnkiVehicleMsgSdkMode => ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+    AnkiVehicleMsgSetSpeed => ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+    AnkiVehicleMsgTurn => ANKI_VEHICLE_MSG_TURN_SIZE,
+    AnkiVehicleMsgSetOffsetFromRoadCentre => ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+    AnkiVehicleMsgChangeLane => ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+    AnkiVehicleMsgSetLights => ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+    AnkiVehicleMsgLightsPattern => ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+    AnkiVehicleMsgSetConfigParams => ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+}
+
 #[cfg(test)]
 mod tests {
-    use scroll::{Pread, BE};
+    use scroll::Pread;
 
     use super::*;
 
@@ -1100,8 +2196,8 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE] = &[
             0x3,
             AnkiVehicleMsgType::V2CVersionResponse as u8,
-            0xAB,
             0xCD,
+            0xAB,
         ];
         let msg: AnkiVehicleMsgVersionResponse = AnkiVehicleMsgVersionResponse {
             size: 3,
@@ -1109,7 +2205,7 @@ mod tests {
             version: 0xABCD,
         };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgVersionResponse>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgVersionResponse>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
@@ -1120,8 +2216,8 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE] = &[
             0x3,
             AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
-            0xAB,
             0xCD,
+            0xAB,
         ];
         let msg: AnkiVehicleMsgBatteryLevelResponse = AnkiVehicleMsgBatteryLevelResponse {
             size: 3,
@@ -1129,12 +2225,72 @@ mod tests {
             battery_level: 0xABCD,
         };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgBatteryLevelResponse>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
     }
 
+    #[test]
+    fn vehicle_message_parse_test() {
+        let battery = [
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0x10,
+            0x0e,
+        ];
+        let msg = VehicleMessage::parse(&battery).unwrap();
+        assert_eq!(AnkiVehicleMsgType::V2CBatteryLevelResponse, msg.msg_type());
+        let VehicleMessage::BatteryLevelResponse(response) = msg else {
+            panic!("Expected a battery level response, got {:?}", msg);
+        };
+        assert_eq!(0x0e10, response.battery_level);
+
+        assert!(matches!(
+            VehicleMessage::parse(&[1, AnkiVehicleMsgType::V2CVehicleDelocalized as u8]),
+            Ok(VehicleMessage::Delocalized(_))
+        ));
+        // Commands and unknown ids come back raw.
+        assert_eq!(
+            VehicleMessage::Unknown {
+                msg_id: 0xee,
+                payload: Cow::Borrowed(&[1, 2]),
+            },
+            VehicleMessage::parse(&[3, 0xee, 1, 2]).unwrap()
+        );
+        let ping = VehicleMessage::parse(&[1, AnkiVehicleMsgType::C2CPingRequest as u8]).unwrap();
+        assert_eq!(AnkiVehicleMsgType::C2CPingRequest, ping.msg_type());
+
+        assert!(matches!(
+            VehicleMessage::parse(&battery[..3]),
+            Err(AnkiError::Protocol(ProtocolError::TooShort {
+                expected: ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE,
+                got: 3,
+            }))
+        ));
+    }
+
+    #[test]
+    fn vehicle_message_parse_padded_test() {
+        let mut padded = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        padded[..4].copy_from_slice(&[
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0x10,
+            0x0e,
+        ]);
+        assert_eq!(&padded[..4], strip_padding(&padded));
+        assert!(VehicleMessage::parse(&padded).is_err());
+        assert!(matches!(
+            VehicleMessage::parse_padded(&padded),
+            Ok(VehicleMessage::BatteryLevelResponse(_))
+        ));
+        assert!(matches!(
+            VehicleMessage::parse_padded(&padded[..3]),
+            Err(AnkiError::Protocol(ProtocolError::TooShort { .. }))
+        ));
+    }
+
     #[test]
     fn anki_vehicle_msg_localisation_position_update_struct_test() {
         let data: &[u8; ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE] = &[
@@ -1142,19 +2298,19 @@ mod tests {
             AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
             0xA,
             0xB,
-            66,
-            200,
             0,
             0,
-            0xCD,
+            200,
+            66,
             0xEF,
+            0xCD,
             1,
             2,
             3,
-            0x44,
             0x55,
-            0x66,
+            0x44,
             0x77,
+            0x66,
         ];
         let msg: AnkiVehicleMsgLocalisationPositionUpdate =
             AnkiVehicleMsgLocalisationPositionUpdate {
@@ -1164,14 +2320,14 @@ mod tests {
                 road_piece_id: 0xB,
                 offset_from_road_centre_mm: 100.0,
                 speed_mm_per_sec: 0xCDEF,
-                parsing_flags: 1,
+                parsing_flags: ParsingFlags::from_bits(1),
                 last_recv_lane_change_cmd_id: 2,
                 last_exec_lane_change_cmd_id: 3,
                 last_desired_lane_change_speed_mm_per_sec: 0x4455,
                 last_desired_speed_mm_per_sec: 0x6677,
             };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
@@ -1184,14 +2340,14 @@ mod tests {
             AnkiVehicleMsgType::V2CLocalisationTransitionUpdate as u8,
             0xA,
             0xB,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             0xC,
             0xD,
-            0x7E,
             0xF0,
+            0x7E,
             1,
             0x1,
             0x2,
@@ -1217,7 +2373,7 @@ mod tests {
                 right_wheel_dist_cm: 0x5,
             };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
@@ -1229,16 +2385,16 @@ mod tests {
             12,
             AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate as u8,
             1,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             IntersectionCode::EntryFirst as u8,
             0xB,
-            0xCD,
             0xEF,
-            0x12,
+            0xCD,
             0x34,
+            0x12,
         ];
         let msg: AnkiVehicleMsgLocalisationIntersectionUpdate =
             AnkiVehicleMsgLocalisationIntersectionUpdate {
@@ -1252,7 +2408,7 @@ mod tests {
                 mm_since_last_intersection_code: 0x1234,
             };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
@@ -1263,10 +2419,10 @@ mod tests {
         let data: &[u8; ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE] = &[
             6,
             AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate as u8,
-            66,
-            200,
             0,
             0,
+            200,
+            66,
             0xAB,
         ];
         let msg: AnkiVehicleMsgOffsetFromRoadCentreUpdate =
@@ -1277,9 +2433,288 @@ mod tests {
                 lane_change_id: 0xAB,
             };
         let test_msg = data
-            .gread_with::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(&mut 0, BE)
+            .gread_with::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>(&mut 0, WIRE_ENDIAN)
             .unwrap();
         println!("T:{:?} == G:{:?}", test_msg, msg);
         assert_eq!(msg, test_msg)
     }
+
+    fn encode<T: ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>>(
+        msg: T,
+        size: usize,
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; size];
+        data.pwrite_with(msg, 0, WIRE_ENDIAN).unwrap();
+        data
+    }
+
+    // Decodes a frame and encodes it again, the bytes have to come back unchanged.
+    fn round_trip<T>(data: &[u8]) -> T
+    where
+        T: for<'a> ctx::TryFromCtx<'a, scroll::Endian, Error = AnkiError>
+            + ctx::TryIntoCtx<scroll::Endian, Error = AnkiError>,
+    {
+        let msg = data.pread_with::<T>(0, WIRE_ENDIAN).unwrap();
+        assert_eq!(data, encode(msg, data.len()));
+        data.pread_with::<T>(0, WIRE_ENDIAN).unwrap()
+    }
+
+    #[test]
+    fn anki_vehicle_msg_owned_test() {
+        let msg = {
+            let frame = [0x03, 0x19, 0x76, 0x26];
+            frame
+                .pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(AnkiVehicleMsgType::V2CVersionResponse, msg.msg_id);
+        assert_eq!([0x76, 0x26], msg.payload());
+        assert_eq!(vec![0x03, 0x19, 0x76, 0x26], encode(msg.as_msg(), 4));
+    }
+
+    #[test]
+    fn anki_vehicle_msg_command_round_trip_test() {
+        let data = encode(
+            anki_vehicle_msg_set_sdk_mode(1, ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION),
+            ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+        );
+        let msg: AnkiVehicleMsgSdkMode = round_trip(&data);
+        assert_eq!(
+            anki_vehicle_msg_set_sdk_mode(1, ANKI_VEHICLE_SDK_OPTION_OVERRIDE_LOCALIZATION),
+            msg
+        );
+
+        let data = encode(
+            anki_vehicle_msg_set_speed(-500, 1000),
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        );
+        let msg: AnkiVehicleMsgSetSpeed = round_trip(&data);
+        assert_eq!(anki_vehicle_msg_set_speed(-500, 1000), msg);
+
+        let data = encode(
+            anki_vehicle_msg_turn(VehicleTurn::UTurn, VehicleTurnTrigger::Intersection),
+            ANKI_VEHICLE_MSG_TURN_SIZE,
+        );
+        let msg: AnkiVehicleMsgTurn = round_trip(&data);
+        assert_eq!(
+            anki_vehicle_msg_turn(VehicleTurn::UTurn, VehicleTurnTrigger::Intersection),
+            msg
+        );
+
+        let data = encode(
+            anki_vehicle_msg_set_offset_from_road_centre(-23.5),
+            ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+        );
+        let msg: AnkiVehicleMsgSetOffsetFromRoadCentre = round_trip(&data);
+        assert_eq!(anki_vehicle_msg_set_offset_from_road_centre(-23.5), msg);
+
+        let data = encode(
+            anki_vehicle_msg_change_lane(300, 2500, 44.5),
+            ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+        );
+        let msg: AnkiVehicleMsgChangeLane = round_trip(&data);
+        assert_eq!(anki_vehicle_msg_change_lane(300, 2500, 44.5), msg);
+
+        let data = encode(
+            anki_vehicle_msg_set_lights(0x44),
+            ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+        );
+        let msg: AnkiVehicleMsgSetLights = round_trip(&data);
+        assert_eq!(anki_vehicle_msg_set_lights(0x44), msg);
+
+        let data = encode(
+            anki_vehicle_msg_set_config_params(SUPERCODE_BOOST_JUMP, TrackMaterial::Vinyl),
+            ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+        );
+        let msg: AnkiVehicleMsgSetConfigParams = round_trip(&data);
+        assert_eq!(
+            anki_vehicle_msg_set_config_params(SUPERCODE_BOOST_JUMP, TrackMaterial::Vinyl),
+            msg
+        );
+
+        let pattern = || {
+            let mut pattern =
+                anki_vehicle_msg_lights_pattern(LightChannel::Red, LightEffect::Throb, 0, 14, 60);
+            pattern
+                .append(anki_vehicle_light_config(
+                    LightChannel::FrontL,
+                    LightEffect::Flash,
+                    1,
+                    2,
+                    120,
+                ))
+                .unwrap();
+            pattern
+        };
+        let data = encode(pattern(), ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE);
+        let msg: AnkiVehicleMsgLightsPattern = round_trip(&data);
+        assert_eq!(pattern(), msg);
+
+        let mut data = data;
+        data[2] = 4;
+        assert!(matches!(
+            data.pread_with::<AnkiVehicleMsgLightsPattern>(0, WIRE_ENDIAN),
+            Err(AnkiError::Protocol(ProtocolError::FieldOutOfRange {
+                field: "channel_count",
+                value: 4,
+            }))
+        ));
+        let mut data = encode(
+            anki_vehicle_msg_turn(VehicleTurn::Left, VehicleTurnTrigger::Immediate),
+            ANKI_VEHICLE_MSG_TURN_SIZE,
+        );
+        data[2] = 9;
+        assert!(matches!(
+            data.pread_with::<AnkiVehicleMsgTurn>(0, WIRE_ENDIAN),
+            Err(AnkiError::Protocol(ProtocolError::InvalidEnumValue {
+                field: "turn_type",
+                value: 9,
+            }))
+        ));
+    }
+
+    #[test]
+    fn anki_vehicle_msg_notification_round_trip_test() {
+        let ping: AnkiVehicleMsgPingResponse =
+            round_trip(&[1, AnkiVehicleMsgType::V2CPingResponse as u8]);
+        assert_eq!(AnkiVehicleMsgType::V2CPingResponse, ping.msg_id);
+        let delocalized: AnkiVehicleMsgVehicleDelocalized =
+            round_trip(&[1, AnkiVehicleMsgType::V2CVehicleDelocalized as u8]);
+        assert_eq!(
+            AnkiVehicleMsgType::V2CVehicleDelocalized,
+            delocalized.msg_id
+        );
+        let speed: AnkiVehicleMsgSpeedUpdate = round_trip(&[
+            7,
+            AnkiVehicleMsgType::V2CSpeedUpdate as u8,
+            0xf4,
+            0x01,
+            0xe8,
+            0x03,
+            0xf0,
+            0x01,
+        ]);
+        assert_eq!(500, speed.desired_speed_mm_per_sec);
+        assert_eq!(496, speed.speed_mm_per_sec);
+        let charger: AnkiVehicleMsgChargerInfo =
+            round_trip(&[5, AnkiVehicleMsgType::V2CChargerInfo as u8, 0, 1, 0, 1]);
+        assert_eq!(1, charger.on_charger);
+        assert_eq!(1, charger.battery_full);
+        let _: AnkiVehicleMsgCollisionDetected =
+            round_trip(&[1, AnkiVehicleMsgType::V2CCollisionDetected as u8]);
+        let _: AnkiVehicleMsgCycleOvertime =
+            round_trip(&[1, AnkiVehicleMsgType::V2CCycleOvertime as u8]);
+        let version: AnkiVehicleMsgVersionResponse =
+            round_trip(&[3, AnkiVehicleMsgType::V2CVersionResponse as u8, 0x6a, 0x2e]);
+        assert_eq!(0x2e6a, version.version);
+        let battery: AnkiVehicleMsgBatteryLevelResponse = round_trip(&[
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0x10,
+            0x0e,
+        ]);
+        assert_eq!(0x0e10, battery.battery_level);
+
+        let position: AnkiVehicleMsgLocalisationPositionUpdate = round_trip(&[
+            16,
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
+            0xA,
+            0xB,
+            0,
+            0,
+            200,
+            66,
+            0xEF,
+            0xCD,
+            1,
+            2,
+            3,
+            0x55,
+            0x44,
+            0x77,
+            0x66,
+        ]);
+        assert_eq!(100.0, position.offset_from_road_centre_mm);
+        assert_eq!(0x6677, position.last_desired_speed_mm_per_sec);
+
+        let transition: AnkiVehicleMsgLocalisationTransitionUpdate = round_trip(&[
+            17,
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate as u8,
+            0xA,
+            0xFF,
+            0,
+            0,
+            200,
+            194,
+            0xC,
+            0xD,
+            0xF0,
+            0x7E,
+            0xFE,
+            1,
+            2,
+            3,
+            4,
+            5,
+        ]);
+        assert_eq!(-1, transition.road_piece_idx_prev);
+        assert_eq!(-100.0, transition.offset_from_road_centre_mm);
+        assert_eq!(-2, transition.ave_follow_line_drift_pixels);
+
+        let intersection: AnkiVehicleMsgLocalisationIntersectionUpdate = round_trip(&[
+            12,
+            AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate as u8,
+            1,
+            0,
+            0,
+            200,
+            66,
+            IntersectionCode::ExitSecond as u8,
+            1,
+            0xEF,
+            0xCD,
+            0x34,
+            0x12,
+        ]);
+        assert_eq!(IntersectionCode::ExitSecond, intersection.intersection_code);
+
+        let offset: AnkiVehicleMsgOffsetFromRoadCentreUpdate = round_trip(&[
+            6,
+            AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate as u8,
+            0,
+            0,
+            200,
+            66,
+            0xAB,
+        ]);
+        assert_eq!(0xAB, offset.lane_change_id);
+    }
+
+    #[test]
+    fn parsing_flags_test() {
+        let flags = ParsingFlags::from_bits(0x67);
+        assert_eq!(7, flags.num_code_bits());
+        assert!(flags.is_reverse_driving());
+        assert!(flags.is_reverse_parsing());
+        assert!(!flags.is_inverted_color());
+        assert_eq!(0x67, u8::from(flags));
+    }
+
+    #[test]
+    fn wire_codec_test() {
+        let data = [3, AnkiVehicleMsgType::V2CVersionResponse as u8, 0x19, 0x6a];
+        let version = AnkiVehicleMsgVersionResponse::from_bytes(&data).unwrap();
+        assert_eq!(0x6a19, version.version);
+        assert_eq!(data.to_vec(), version.to_bytes());
+
+        let speed = anki_vehicle_msg_set_speed(0x0190, 0x03e8);
+        assert_eq!(ANKI_VEHICLE_MSG_SET_SPEED_SIZE, speed.to_bytes().len());
+        assert_eq!([0x90, 0x01, 0xe8, 0x03], speed.to_array()[2..6]);
+        assert_eq!(speed.to_command(), speed.to_bytes());
+        assert!(matches!(
+            AnkiVehicleMsgVersionResponse::from_bytes(&data[..3]),
+            Err(AnkiError::Protocol(ProtocolError::TooShort { .. }))
+        ));
+    }
 }
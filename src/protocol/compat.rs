@@ -0,0 +1,833 @@
+// `#[repr(C, packed)]` mirrors of the message structs, field for field identical to the
+// `anki_vehicle_msg_*_t` typedefs in the original drive-sdk `protocol.h`. A C codebase can hand
+// its structs over as-is (or memcpy them) and convert to the Rust types at the boundary, one
+// call site at a time. Like the C SDK, multi-byte fields are in host byte order.
+//
+// Enum fields are plain `u8`s as they are in C. Converting unknown values into the Rust types
+// falls back the same way parsing a frame does, e.g. to `AnkiVehicleMsgType::Unknown`.
+
+use crate::protocol::{
+    self, IntersectionCode, LightChannel, LightEffect, TrackMaterial, VehicleTurn,
+    VehicleTurnTrigger, ANKI_VEHICLE_MSG_BASE_SIZE, ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE,
+    LIGHT_CHANNEL_COUNT_MAX,
+};
+
+fn msg_type(msg_id: u8) -> protocol::AnkiVehicleMsgType {
+    msg_id
+        .try_into()
+        .unwrap_or(protocol::AnkiVehicleMsgType::Unknown)
+}
+
+// anki_vehicle_msg_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsg {
+    pub size: u8,
+    pub msg_id: u8,
+    pub payload: [u8; ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE],
+}
+
+impl From<protocol::AnkiVehicleMsg<'_>> for AnkiVehicleMsg {
+    fn from(msg: protocol::AnkiVehicleMsg<'_>) -> Self {
+        let mut payload = [0u8; ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE];
+        let len = msg.payload.len().min(ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE);
+        payload[..len].copy_from_slice(&msg.payload[..len]);
+        AnkiVehicleMsg {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            payload,
+        }
+    }
+}
+
+// The payload borrows from the C struct, its length is taken from `size`.
+impl<'a> From<&'a AnkiVehicleMsg> for protocol::AnkiVehicleMsg<'a> {
+    fn from(msg: &'a AnkiVehicleMsg) -> Self {
+        let len = (msg.size as usize)
+            .saturating_sub(ANKI_VEHICLE_MSG_BASE_SIZE - 1)
+            .min(ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE);
+        protocol::AnkiVehicleMsg {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            payload: &msg.payload[..len],
+        }
+    }
+}
+
+// anki_vehicle_msg_version_response_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgVersionResponse {
+    pub size: u8,
+    pub msg_id: u8,
+    pub version: u16,
+}
+
+impl From<protocol::AnkiVehicleMsgVersionResponse> for AnkiVehicleMsgVersionResponse {
+    fn from(msg: protocol::AnkiVehicleMsgVersionResponse) -> Self {
+        AnkiVehicleMsgVersionResponse {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            version: msg.version,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgVersionResponse> for protocol::AnkiVehicleMsgVersionResponse {
+    fn from(msg: AnkiVehicleMsgVersionResponse) -> Self {
+        protocol::AnkiVehicleMsgVersionResponse {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            version: msg.version,
+        }
+    }
+}
+
+// anki_vehicle_msg_battery_level_response_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgBatteryLevelResponse {
+    pub size: u8,
+    pub msg_id: u8,
+    pub battery_level: u16,
+}
+
+impl From<protocol::AnkiVehicleMsgBatteryLevelResponse> for AnkiVehicleMsgBatteryLevelResponse {
+    fn from(msg: protocol::AnkiVehicleMsgBatteryLevelResponse) -> Self {
+        AnkiVehicleMsgBatteryLevelResponse {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            battery_level: msg.battery_level,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgBatteryLevelResponse> for protocol::AnkiVehicleMsgBatteryLevelResponse {
+    fn from(msg: AnkiVehicleMsgBatteryLevelResponse) -> Self {
+        protocol::AnkiVehicleMsgBatteryLevelResponse {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            battery_level: msg.battery_level,
+        }
+    }
+}
+
+// anki_vehicle_msg_sdk_mode_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgSdkMode {
+    pub size: u8,
+    pub msg_id: u8,
+    pub on: u8,
+    pub flags: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgSdkMode> for AnkiVehicleMsgSdkMode {
+    fn from(msg: protocol::AnkiVehicleMsgSdkMode) -> Self {
+        AnkiVehicleMsgSdkMode {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            on: msg.on,
+            flags: msg.flags,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgSdkMode> for protocol::AnkiVehicleMsgSdkMode {
+    fn from(msg: AnkiVehicleMsgSdkMode) -> Self {
+        protocol::AnkiVehicleMsgSdkMode {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            on: msg.on,
+            flags: msg.flags,
+        }
+    }
+}
+
+// anki_vehicle_msg_set_speed_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgSetSpeed {
+    pub size: u8,
+    pub msg_id: u8,
+    pub speed_mm_per_sec: i16,
+    pub accel_mm_per_sec2: i16,
+    pub respect_road_piece_speed_limit: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgSetSpeed> for AnkiVehicleMsgSetSpeed {
+    fn from(msg: protocol::AnkiVehicleMsgSetSpeed) -> Self {
+        AnkiVehicleMsgSetSpeed {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            speed_mm_per_sec: msg.speed_mm_per_sec,
+            accel_mm_per_sec2: msg.accel_mm_per_sec2,
+            respect_road_piece_speed_limit: msg.respect_road_piece_speed_limit,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgSetSpeed> for protocol::AnkiVehicleMsgSetSpeed {
+    fn from(msg: AnkiVehicleMsgSetSpeed) -> Self {
+        protocol::AnkiVehicleMsgSetSpeed {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            speed_mm_per_sec: msg.speed_mm_per_sec,
+            accel_mm_per_sec2: msg.accel_mm_per_sec2,
+            respect_road_piece_speed_limit: msg.respect_road_piece_speed_limit,
+        }
+    }
+}
+
+// anki_vehicle_msg_turn_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgTurn {
+    pub size: u8,
+    pub msg_id: u8,
+    pub turn_type: u8,
+    pub trigger: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgTurn> for AnkiVehicleMsgTurn {
+    fn from(msg: protocol::AnkiVehicleMsgTurn) -> Self {
+        AnkiVehicleMsgTurn {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            turn_type: msg.turn_type.into(),
+            trigger: msg.trigger.into(),
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgTurn> for protocol::AnkiVehicleMsgTurn {
+    fn from(msg: AnkiVehicleMsgTurn) -> Self {
+        protocol::AnkiVehicleMsgTurn {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            turn_type: msg.turn_type.try_into().unwrap_or(VehicleTurn::None),
+            trigger: msg
+                .trigger
+                .try_into()
+                .unwrap_or(VehicleTurnTrigger::Immediate),
+        }
+    }
+}
+
+// anki_vehicle_msg_set_offset_from_road_center_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgSetOffsetFromRoadCentre {
+    pub size: u8,
+    pub msg_id: u8,
+    pub offset_mm: f32,
+}
+
+impl From<protocol::AnkiVehicleMsgSetOffsetFromRoadCentre>
+    for AnkiVehicleMsgSetOffsetFromRoadCentre
+{
+    fn from(msg: protocol::AnkiVehicleMsgSetOffsetFromRoadCentre) -> Self {
+        AnkiVehicleMsgSetOffsetFromRoadCentre {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            offset_mm: msg.offset_mm,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgSetOffsetFromRoadCentre>
+    for protocol::AnkiVehicleMsgSetOffsetFromRoadCentre
+{
+    fn from(msg: AnkiVehicleMsgSetOffsetFromRoadCentre) -> Self {
+        protocol::AnkiVehicleMsgSetOffsetFromRoadCentre {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            offset_mm: msg.offset_mm,
+        }
+    }
+}
+
+// anki_vehicle_msg_change_lane_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgChangeLane {
+    pub size: u8,
+    pub msg_id: u8,
+    pub horizontal_speed_mm_per_sec: u16,
+    pub horizontal_accel_mm_per_sec2: u16,
+    pub offset_from_road_centre_mm: f32,
+    pub hop_intent: u8,
+    pub tag: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgChangeLane> for AnkiVehicleMsgChangeLane {
+    fn from(msg: protocol::AnkiVehicleMsgChangeLane) -> Self {
+        AnkiVehicleMsgChangeLane {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            horizontal_speed_mm_per_sec: msg.horizontal_speed_mm_per_sec,
+            horizontal_accel_mm_per_sec2: msg.horizontal_accel_mm_per_sec2,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            hop_intent: msg.hop_intent,
+            tag: msg.tag,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgChangeLane> for protocol::AnkiVehicleMsgChangeLane {
+    fn from(msg: AnkiVehicleMsgChangeLane) -> Self {
+        protocol::AnkiVehicleMsgChangeLane {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            horizontal_speed_mm_per_sec: msg.horizontal_speed_mm_per_sec,
+            horizontal_accel_mm_per_sec2: msg.horizontal_accel_mm_per_sec2,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            hop_intent: msg.hop_intent,
+            tag: msg.tag,
+        }
+    }
+}
+
+// anki_vehicle_msg_localization_position_update_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgLocalisationPositionUpdate {
+    pub size: u8,
+    pub msg_id: u8,
+    pub location_id: u8,
+    pub road_piece_id: u8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub parsing_flags: u8,
+    pub last_recv_lane_change_cmd_id: u8,
+    pub last_exec_lane_change_cmd_id: u8,
+    pub last_desired_lane_change_speed_mm_per_sec: u16,
+    pub last_desired_speed_mm_per_sec: u16,
+}
+
+impl From<protocol::AnkiVehicleMsgLocalisationPositionUpdate>
+    for AnkiVehicleMsgLocalisationPositionUpdate
+{
+    fn from(msg: protocol::AnkiVehicleMsgLocalisationPositionUpdate) -> Self {
+        AnkiVehicleMsgLocalisationPositionUpdate {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            location_id: msg.location_id,
+            road_piece_id: msg.road_piece_id,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            speed_mm_per_sec: msg.speed_mm_per_sec,
+            parsing_flags: msg.parsing_flags.bits(),
+            last_recv_lane_change_cmd_id: msg.last_recv_lane_change_cmd_id,
+            last_exec_lane_change_cmd_id: msg.last_exec_lane_change_cmd_id,
+            last_desired_lane_change_speed_mm_per_sec: msg
+                .last_desired_lane_change_speed_mm_per_sec,
+            last_desired_speed_mm_per_sec: msg.last_desired_speed_mm_per_sec,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgLocalisationPositionUpdate>
+    for protocol::AnkiVehicleMsgLocalisationPositionUpdate
+{
+    fn from(msg: AnkiVehicleMsgLocalisationPositionUpdate) -> Self {
+        protocol::AnkiVehicleMsgLocalisationPositionUpdate {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            location_id: msg.location_id,
+            road_piece_id: msg.road_piece_id,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            speed_mm_per_sec: msg.speed_mm_per_sec,
+            parsing_flags: protocol::ParsingFlags::from_bits(msg.parsing_flags),
+            last_recv_lane_change_cmd_id: msg.last_recv_lane_change_cmd_id,
+            last_exec_lane_change_cmd_id: msg.last_exec_lane_change_cmd_id,
+            last_desired_lane_change_speed_mm_per_sec: msg
+                .last_desired_lane_change_speed_mm_per_sec,
+            last_desired_speed_mm_per_sec: msg.last_desired_speed_mm_per_sec,
+        }
+    }
+}
+
+// anki_vehicle_msg_localization_transition_update_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgLocalisationTransitionUpdate {
+    pub size: u8,
+    pub msg_id: u8,
+    pub road_piece_idx: i8,
+    pub road_piece_idx_prev: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub last_recv_lane_change_id: u8,
+    pub last_exec_lane_change_id: u8,
+    pub last_desired_lane_change_speed_mm_per_sec: u16,
+    pub ave_follow_line_drift_pixels: i8,
+    pub had_lane_change_activity: u8,
+    pub uphill_counter: u8,
+    pub downhill_counter: u8,
+    pub left_wheel_dist_cm: u8,
+    pub right_wheel_dist_cm: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgLocalisationTransitionUpdate>
+    for AnkiVehicleMsgLocalisationTransitionUpdate
+{
+    fn from(msg: protocol::AnkiVehicleMsgLocalisationTransitionUpdate) -> Self {
+        AnkiVehicleMsgLocalisationTransitionUpdate {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            road_piece_idx: msg.road_piece_idx,
+            road_piece_idx_prev: msg.road_piece_idx_prev,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            last_recv_lane_change_id: msg.last_recv_lane_change_id,
+            last_exec_lane_change_id: msg.last_exec_lane_change_id,
+            last_desired_lane_change_speed_mm_per_sec: msg
+                .last_desired_lane_change_speed_mm_per_sec,
+            ave_follow_line_drift_pixels: msg.ave_follow_line_drift_pixels,
+            had_lane_change_activity: msg.had_lane_change_activity,
+            uphill_counter: msg.uphill_counter,
+            downhill_counter: msg.downhill_counter,
+            left_wheel_dist_cm: msg.left_wheel_dist_cm,
+            right_wheel_dist_cm: msg.right_wheel_dist_cm,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgLocalisationTransitionUpdate>
+    for protocol::AnkiVehicleMsgLocalisationTransitionUpdate
+{
+    fn from(msg: AnkiVehicleMsgLocalisationTransitionUpdate) -> Self {
+        protocol::AnkiVehicleMsgLocalisationTransitionUpdate {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            road_piece_idx: msg.road_piece_idx,
+            road_piece_idx_prev: msg.road_piece_idx_prev,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            last_recv_lane_change_id: msg.last_recv_lane_change_id,
+            last_exec_lane_change_id: msg.last_exec_lane_change_id,
+            last_desired_lane_change_speed_mm_per_sec: msg
+                .last_desired_lane_change_speed_mm_per_sec,
+            ave_follow_line_drift_pixels: msg.ave_follow_line_drift_pixels,
+            had_lane_change_activity: msg.had_lane_change_activity,
+            uphill_counter: msg.uphill_counter,
+            downhill_counter: msg.downhill_counter,
+            left_wheel_dist_cm: msg.left_wheel_dist_cm,
+            right_wheel_dist_cm: msg.right_wheel_dist_cm,
+        }
+    }
+}
+
+// anki_vehicle_msg_localization_intersection_update_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgLocalisationIntersectionUpdate {
+    pub size: u8,
+    pub msg_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub intersection_code: u8,
+    pub is_exiting: u8,
+    pub mm_since_last_transition_bar: u16,
+    pub mm_since_last_intersection_code: u16,
+}
+
+impl From<protocol::AnkiVehicleMsgLocalisationIntersectionUpdate>
+    for AnkiVehicleMsgLocalisationIntersectionUpdate
+{
+    fn from(msg: protocol::AnkiVehicleMsgLocalisationIntersectionUpdate) -> Self {
+        AnkiVehicleMsgLocalisationIntersectionUpdate {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            road_piece_idx: msg.road_piece_idx,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            intersection_code: msg.intersection_code.into(),
+            is_exiting: msg.is_exiting,
+            mm_since_last_transition_bar: msg.mm_since_last_transition_bar,
+            mm_since_last_intersection_code: msg.mm_since_last_intersection_code,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgLocalisationIntersectionUpdate>
+    for protocol::AnkiVehicleMsgLocalisationIntersectionUpdate
+{
+    fn from(msg: AnkiVehicleMsgLocalisationIntersectionUpdate) -> Self {
+        protocol::AnkiVehicleMsgLocalisationIntersectionUpdate {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            road_piece_idx: msg.road_piece_idx,
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            intersection_code: msg
+                .intersection_code
+                .try_into()
+                .unwrap_or(IntersectionCode::None),
+            is_exiting: msg.is_exiting,
+            mm_since_last_transition_bar: msg.mm_since_last_transition_bar,
+            mm_since_last_intersection_code: msg.mm_since_last_intersection_code,
+        }
+    }
+}
+
+// anki_vehicle_msg_offset_from_road_center_update_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+    pub size: u8,
+    pub msg_id: u8,
+    pub offset_from_road_centre_mm: f32,
+    pub lane_change_id: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgOffsetFromRoadCentreUpdate>
+    for AnkiVehicleMsgOffsetFromRoadCentreUpdate
+{
+    fn from(msg: protocol::AnkiVehicleMsgOffsetFromRoadCentreUpdate) -> Self {
+        AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            lane_change_id: msg.lane_change_id,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgOffsetFromRoadCentreUpdate>
+    for protocol::AnkiVehicleMsgOffsetFromRoadCentreUpdate
+{
+    fn from(msg: AnkiVehicleMsgOffsetFromRoadCentreUpdate) -> Self {
+        protocol::AnkiVehicleMsgOffsetFromRoadCentreUpdate {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            offset_from_road_centre_mm: msg.offset_from_road_centre_mm,
+            lane_change_id: msg.lane_change_id,
+        }
+    }
+}
+
+// anki_vehicle_msg_set_lights_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgSetLights {
+    pub size: u8,
+    pub msg_id: u8,
+    pub light_mask: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgSetLights> for AnkiVehicleMsgSetLights {
+    fn from(msg: protocol::AnkiVehicleMsgSetLights) -> Self {
+        AnkiVehicleMsgSetLights {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            light_mask: msg.light_mask,
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgSetLights> for protocol::AnkiVehicleMsgSetLights {
+    fn from(msg: AnkiVehicleMsgSetLights) -> Self {
+        protocol::AnkiVehicleMsgSetLights {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            light_mask: msg.light_mask,
+        }
+    }
+}
+
+// anki_vehicle_light_config_t
+#[repr(C, packed)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleLightConfig {
+    pub channel: u8,
+    pub effect: u8,
+    pub start: u8,
+    pub end: u8,
+    pub cycles_per_10_sec: u8,
+}
+
+impl From<protocol::AnkiVehicleLightConfig> for AnkiVehicleLightConfig {
+    fn from(config: protocol::AnkiVehicleLightConfig) -> Self {
+        AnkiVehicleLightConfig {
+            channel: config.channel.into(),
+            effect: config.effect.into(),
+            start: config.start,
+            end: config.end,
+            cycles_per_10_sec: config.cycles_per_10_sec,
+        }
+    }
+}
+
+impl From<AnkiVehicleLightConfig> for protocol::AnkiVehicleLightConfig {
+    fn from(config: AnkiVehicleLightConfig) -> Self {
+        protocol::AnkiVehicleLightConfig {
+            channel: config.channel.try_into().unwrap_or(LightChannel::Count),
+            effect: config.effect.try_into().unwrap_or(LightEffect::Count),
+            start: config.start,
+            end: config.end,
+            cycles_per_10_sec: config.cycles_per_10_sec,
+        }
+    }
+}
+
+// anki_vehicle_msg_lights_pattern_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgLightsPattern {
+    pub size: u8,
+    pub msg_id: u8,
+    pub channel_count: u8,
+    pub channel_config: [AnkiVehicleLightConfig; LIGHT_CHANNEL_COUNT_MAX],
+}
+
+impl From<protocol::AnkiVehicleMsgLightsPattern> for AnkiVehicleMsgLightsPattern {
+    fn from(msg: protocol::AnkiVehicleMsgLightsPattern) -> Self {
+        AnkiVehicleMsgLightsPattern {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            channel_count: msg.channel_count,
+            channel_config: msg
+                .channel_config
+                .map(|config| config.map(Into::into).unwrap_or_default()),
+        }
+    }
+}
+
+// Only the first `channel_count` configs are used, the same as the vehicle does.
+impl From<AnkiVehicleMsgLightsPattern> for protocol::AnkiVehicleMsgLightsPattern {
+    fn from(msg: AnkiVehicleMsgLightsPattern) -> Self {
+        let channel_count = msg.channel_count.min(LIGHT_CHANNEL_COUNT_MAX as u8);
+        let mut i = 0;
+        protocol::AnkiVehicleMsgLightsPattern {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            channel_count,
+            channel_config: msg.channel_config.map(|config| {
+                i += 1;
+                (i <= channel_count).then(|| config.into())
+            }),
+        }
+    }
+}
+
+// anki_vehicle_msg_set_config_params_t
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnkiVehicleMsgSetConfigParams {
+    pub size: u8,
+    pub msg_id: u8,
+    pub super_code_parse_mask: u8,
+    pub track_material: u8,
+}
+
+impl From<protocol::AnkiVehicleMsgSetConfigParams> for AnkiVehicleMsgSetConfigParams {
+    fn from(msg: protocol::AnkiVehicleMsgSetConfigParams) -> Self {
+        AnkiVehicleMsgSetConfigParams {
+            size: msg.size,
+            msg_id: msg.msg_id.into(),
+            super_code_parse_mask: msg.super_code_parse_mask,
+            track_material: msg.track_material.into(),
+        }
+    }
+}
+
+impl From<AnkiVehicleMsgSetConfigParams> for protocol::AnkiVehicleMsgSetConfigParams {
+    fn from(msg: AnkiVehicleMsgSetConfigParams) -> Self {
+        protocol::AnkiVehicleMsgSetConfigParams {
+            size: msg.size,
+            msg_id: msg_type(msg.msg_id),
+            super_code_parse_mask: msg.super_code_parse_mask,
+            track_material: msg
+                .track_material
+                .try_into()
+                .unwrap_or(TrackMaterial::Plastic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        anki_vehicle_light_config, anki_vehicle_msg_get_version, anki_vehicle_msg_lights_pattern,
+        anki_vehicle_msg_set_speed, AnkiVehicleMsgType, ANKI_VEHICLE_LIGHT_CONFIG_SIZE,
+        ANKI_VEHICLE_MAX_LIGHT_INTENSITY, ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE,
+        ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE, ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+        ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE,
+        ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE,
+        ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE, ANKI_VEHICLE_MSG_MAX_SIZE,
+        ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE, ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+        ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE, ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+        ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE, ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        ANKI_VEHICLE_MSG_TURN_SIZE, ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE, WIRE_ENDIAN,
+    };
+    use scroll::Pread;
+    use std::mem::size_of;
+
+    #[test]
+    fn compat_struct_size_test() {
+        assert_eq!(ANKI_VEHICLE_MSG_MAX_SIZE, size_of::<AnkiVehicleMsg>());
+        assert_eq!(
+            ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE,
+            size_of::<AnkiVehicleMsgVersionResponse>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE,
+            size_of::<AnkiVehicleMsgBatteryLevelResponse>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+            size_of::<AnkiVehicleMsgSdkMode>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+            size_of::<AnkiVehicleMsgSetSpeed>()
+        );
+        assert_eq!(ANKI_VEHICLE_MSG_TURN_SIZE, size_of::<AnkiVehicleMsgTurn>());
+        assert_eq!(
+            ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+            size_of::<AnkiVehicleMsgSetOffsetFromRoadCentre>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+            size_of::<AnkiVehicleMsgChangeLane>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE,
+            size_of::<AnkiVehicleMsgLocalisationPositionUpdate>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE,
+            size_of::<AnkiVehicleMsgLocalisationTransitionUpdate>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE,
+            size_of::<AnkiVehicleMsgLocalisationIntersectionUpdate>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE,
+            size_of::<AnkiVehicleMsgOffsetFromRoadCentreUpdate>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+            size_of::<AnkiVehicleMsgSetLights>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_LIGHT_CONFIG_SIZE,
+            size_of::<AnkiVehicleLightConfig>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+            size_of::<AnkiVehicleMsgLightsPattern>()
+        );
+        assert_eq!(
+            ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+            size_of::<AnkiVehicleMsgSetConfigParams>()
+        );
+    }
+
+    #[test]
+    fn compat_set_speed_test() {
+        let msg: AnkiVehicleMsgSetSpeed = anki_vehicle_msg_set_speed(500, -1000).into();
+        assert_eq!(6, msg.size);
+        assert_eq!(AnkiVehicleMsgType::C2VSetSpeed as u8, msg.msg_id);
+        assert_eq!(500, { msg.speed_mm_per_sec });
+        assert_eq!(-1000, { msg.accel_mm_per_sec2 });
+
+        let data = protocol::AnkiVehicleMsgSetSpeed::from(msg).to_array();
+        assert_eq!(crate::AnkiVehicleData::set_speed(500, -1000), data.to_vec());
+    }
+
+    #[test]
+    fn compat_position_update_test() {
+        let data: &[u8; ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE] = &[
+            16,
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate as u8,
+            0xA,
+            0xB,
+            0,
+            0,
+            200,
+            66,
+            0xEF,
+            0xCD,
+            1,
+            2,
+            3,
+            0x55,
+            0x44,
+            0x77,
+            0x66,
+        ];
+        let msg: AnkiVehicleMsgLocalisationPositionUpdate = data
+            .pread_with::<protocol::AnkiVehicleMsgLocalisationPositionUpdate>(0, WIRE_ENDIAN)
+            .unwrap()
+            .into();
+        assert_eq!(0xB, msg.road_piece_id);
+        assert_eq!(100.0, { msg.offset_from_road_centre_mm });
+        assert_eq!(0xCDEF, { msg.speed_mm_per_sec });
+        assert_eq!(0x6677, { msg.last_desired_speed_mm_per_sec });
+
+        let back: protocol::AnkiVehicleMsgLocalisationPositionUpdate = msg.into();
+        assert_eq!(
+            data.pread_with::<protocol::AnkiVehicleMsgLocalisationPositionUpdate>(0, WIRE_ENDIAN)
+                .unwrap(),
+            back
+        );
+    }
+
+    #[test]
+    fn compat_lights_pattern_test() {
+        let mut pattern = anki_vehicle_msg_lights_pattern(
+            LightChannel::Red,
+            LightEffect::Throb,
+            0,
+            ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+            60,
+        );
+        pattern
+            .append(anki_vehicle_light_config(
+                LightChannel::Blue,
+                LightEffect::Steady,
+                5,
+                5,
+                0,
+            ))
+            .unwrap();
+        let expected = anki_vehicle_msg_lights_pattern(
+            LightChannel::Red,
+            LightEffect::Throb,
+            0,
+            ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+            60,
+        )
+        .to_array();
+
+        let msg: AnkiVehicleMsgLightsPattern = pattern.into();
+        assert_eq!(2, msg.channel_count);
+        assert_eq!(LightChannel::Blue as u8, msg.channel_config[1].channel);
+        assert_eq!(AnkiVehicleLightConfig::default(), msg.channel_config[2]);
+
+        let single = AnkiVehicleMsgLightsPattern {
+            channel_count: 1,
+            ..msg
+        };
+        assert_eq!(
+            expected,
+            protocol::AnkiVehicleMsgLightsPattern::from(single).to_array()
+        );
+    }
+
+    #[test]
+    fn compat_msg_test() {
+        let msg: AnkiVehicleMsg = anki_vehicle_msg_get_version().into();
+        assert_eq!(1, msg.size);
+        assert_eq!(AnkiVehicleMsgType::C2VVersionRequest as u8, msg.msg_id);
+        assert_eq!(anki_vehicle_msg_get_version(), (&msg).into());
+
+        let msg = AnkiVehicleMsg {
+            size: 3,
+            msg_id: 0xff,
+            payload: [7; ANKI_VEHICLE_MSG_PAYLOAD_MAX_SIZE],
+        };
+        let back: protocol::AnkiVehicleMsg = (&msg).into();
+        assert_eq!(AnkiVehicleMsgType::Unknown, back.msg_id);
+        assert_eq!(&[7, 7], back.payload);
+    }
+}
@@ -0,0 +1,334 @@
+use std::time::{Duration, Instant};
+
+use crate::lanes::LaneLayout;
+use crate::protocol::{AnkiVehicleMsgLocalisationPositionUpdate, VehicleMessage};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::track::TrackMap;
+use crate::vehicle_id::VehicleId;
+
+pub const PROXIMITY_DEFAULT_WARNING_DISTANCE_MM: f32 = 150.0;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ProximityConfig {
+    // Two cars in the same lane closer than this are warned about.
+    pub warning_distance_mm: f32,
+    pub lanes: LaneLayout,
+    // A vehicle that hasn't reported a position for this long is left out.
+    pub telemetry_timeout: Duration,
+}
+
+impl Default for ProximityConfig {
+    fn default() -> Self {
+        ProximityConfig {
+            warning_distance_mm: PROXIMITY_DEFAULT_WARNING_DISTANCE_MM,
+            lanes: LaneLayout::default(),
+            telemetry_timeout: Duration::from_millis(750),
+        }
+    }
+}
+
+// Distance from a vehicle to the nearest car ahead of it.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gap {
+    pub vehicle: VehicleId,
+    pub ahead: VehicleId,
+    pub gap_mm: f32,
+    // Positive while the gap is closing.
+    pub closing_speed_mm_per_sec: i32,
+    pub same_lane: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProximityWarning {
+    // The car behind.
+    pub vehicle: VehicleId,
+    pub ahead: VehicleId,
+    pub lane: u8,
+    pub gap_mm: f32,
+    pub closing_speed_mm_per_sec: i32,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    vehicle: VehicleId,
+    // Index into the track map of the piece the vehicle is on.
+    piece_idx: usize,
+    road_piece_id: u8,
+    entered_at: Instant,
+    last_seen: Instant,
+    speed_mm_per_sec: u16,
+    offset_from_road_centre_mm: f32,
+}
+
+// Estimates where every car is on a mapped track and how close they are to each other.
+// Position updates only say which piece a car is on, the distance into the piece is dead
+// reckoned from its speed since it entered and piece lengths are nominal, so gaps are good to
+// a few centimetres at best. Pieces that appear more than once on the track are told apart by
+// following the map in driving order.
+#[derive(Debug, Clone)]
+pub struct ProximityMonitor {
+    config: ProximityConfig,
+    // Distance along the lap to the start of every piece, and the lap length last.
+    piece_starts_mm: Vec<f32>,
+    map: TrackMap,
+    vehicles: Vec<Tracked>,
+    // Pairs warned about that are still too close, (behind, ahead).
+    warned: Vec<(VehicleId, VehicleId)>,
+}
+
+impl ProximityMonitor {
+    pub fn new(map: TrackMap, config: ProximityConfig) -> ProximityMonitor {
+        let mut piece_starts_mm = Vec::with_capacity(map.len() + 1);
+        let mut distance_mm = 0.0;
+        piece_starts_mm.push(distance_mm);
+        for piece in map.pieces() {
+            distance_mm += piece.kind.nominal_length_mm();
+            piece_starts_mm.push(distance_mm);
+        }
+        ProximityMonitor {
+            config,
+            piece_starts_mm,
+            map,
+            vehicles: Vec::new(),
+            warned: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ProximityConfig {
+        &self.config
+    }
+
+    pub fn lap_length_mm(&self) -> f32 {
+        self.piece_starts_mm.last().copied().unwrap_or(0.0)
+    }
+
+    // The next piece with this id after `from`, wrapping round the lap.
+    fn next_piece_idx(&self, from: Option<usize>, road_piece_id: u8) -> Option<usize> {
+        let pieces = self.map.pieces();
+        let Some(from) = from else {
+            return self.map.position(road_piece_id);
+        };
+        (1..=pieces.len())
+            .map(|step| (from + step) % pieces.len())
+            .find(|&idx| pieces[idx].road_piece_id == road_piece_id)
+    }
+
+    // Updates for pieces that aren't on the map are ignored.
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) {
+        let existing = self.vehicles.iter().position(|t| t.vehicle == vehicle);
+        if let Some(tracked) = existing.map(|i| &mut self.vehicles[i]) {
+            tracked.last_seen = at;
+            tracked.speed_mm_per_sec = data.speed_mm_per_sec;
+            tracked.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
+            if tracked.road_piece_id == data.road_piece_id {
+                return;
+            }
+        }
+        let from = existing.map(|i| self.vehicles[i].piece_idx);
+        let Some(piece_idx) = self.next_piece_idx(from, data.road_piece_id) else {
+            return;
+        };
+        let tracked = Tracked {
+            vehicle: vehicle.into(),
+            piece_idx,
+            road_piece_id: data.road_piece_id,
+            entered_at: at,
+            last_seen: at,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+        };
+        match existing {
+            Some(i) => self.vehicles[i] = tracked,
+            None => self.vehicles.push(tracked),
+        }
+    }
+
+    // A car off the track is nowhere on it, it's picked up again on its next position update.
+    pub fn process_delocalized(&mut self, vehicle: &str) {
+        self.vehicles.retain(|t| t.vehicle != vehicle);
+        self.warned
+            .retain(|(behind, ahead)| behind != vehicle && ahead != vehicle);
+    }
+
+    pub fn process_message(&mut self, vehicle: &str, msg: &VehicleMessage, at: Instant) {
+        match msg {
+            VehicleMessage::PositionUpdate(data) => self.process_position_update(vehicle, data, at),
+            VehicleMessage::Delocalized(_) => self.process_delocalized(vehicle),
+            _ => {}
+        }
+    }
+
+    fn distance_mm(&self, tracked: &Tracked, at: Instant) -> f32 {
+        let start = self.piece_starts_mm[tracked.piece_idx];
+        let length = self.piece_starts_mm[tracked.piece_idx + 1] - start;
+        let travelled = tracked.speed_mm_per_sec as f32
+            * at.saturating_duration_since(tracked.entered_at)
+                .as_secs_f32();
+        start + travelled.min(length)
+    }
+
+    // Estimated distance of the vehicle from the start of the finish line piece.
+    pub fn distance_along_lap_mm(&self, vehicle: &str, at: Instant) -> Option<f32> {
+        self.vehicles
+            .iter()
+            .find(|t| t.vehicle == vehicle)
+            .map(|tracked| self.distance_mm(tracked, at))
+    }
+
+    fn lane(&self, tracked: &Tracked) -> u8 {
+        self.config
+            .lanes
+            .lane_for_offset(tracked.offset_from_road_centre_mm)
+    }
+
+    // For every car, the gap to the nearest car ahead of it. With `same_lane` only cars in the
+    // same lane count.
+    fn gaps_at(&self, at: Instant, same_lane: bool) -> Vec<Gap> {
+        let lap_length = self.lap_length_mm();
+        let live: Vec<(&Tracked, f32)> = self
+            .vehicles
+            .iter()
+            .filter(|t| at.saturating_duration_since(t.last_seen) <= self.config.telemetry_timeout)
+            .map(|t| (t, self.distance_mm(t, at)))
+            .collect();
+        let mut gaps = Vec::new();
+        for &(behind, behind_mm) in &live {
+            let nearest = live
+                .iter()
+                .filter(|(ahead, _)| ahead.vehicle != behind.vehicle)
+                .filter(|(ahead, _)| !same_lane || self.lane(ahead) == self.lane(behind))
+                .map(|&(ahead, ahead_mm)| (ahead, (ahead_mm - behind_mm).rem_euclid(lap_length)))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((ahead, gap_mm)) = nearest {
+                gaps.push(Gap {
+                    vehicle: behind.vehicle.clone(),
+                    ahead: ahead.vehicle.clone(),
+                    gap_mm,
+                    closing_speed_mm_per_sec: behind.speed_mm_per_sec as i32
+                        - ahead.speed_mm_per_sec as i32,
+                    same_lane: self.lane(ahead) == self.lane(behind),
+                });
+            }
+        }
+        gaps
+    }
+
+    pub fn gaps(&self, at: Instant) -> Vec<Gap> {
+        self.gaps_at(at, false)
+    }
+
+    // Warns once when a car closes to within the warning distance of the car ahead in its lane,
+    // again only after the pair has been further apart.
+    pub fn check(&mut self, at: Instant) -> Vec<ProximityWarning> {
+        let close: Vec<Gap> = self
+            .gaps_at(at, true)
+            .into_iter()
+            .filter(|gap| gap.gap_mm < self.config.warning_distance_mm)
+            .collect();
+        self.warned.retain(|(behind, ahead)| {
+            close
+                .iter()
+                .any(|gap| gap.vehicle == *behind && gap.ahead == *ahead)
+        });
+
+        let mut warnings = Vec::new();
+        for gap in close {
+            let pair = (gap.vehicle.clone(), gap.ahead.clone());
+            if self.warned.contains(&pair) {
+                continue;
+            }
+            let lane = self
+                .vehicles
+                .iter()
+                .find(|t| t.vehicle == gap.vehicle)
+                .map_or(0, |t| self.lane(t));
+            trace_event!(target: TARGET_CONTROLLER, info, vehicle = %gap.vehicle, ahead = %gap.ahead, gap_mm = gap.gap_mm, "Vehicles too close");
+            warnings.push(ProximityWarning {
+                vehicle: gap.vehicle,
+                ahead: gap.ahead,
+                lane,
+                gap_mm: gap.gap_mm,
+                closing_speed_mm_per_sec: gap.closing_speed_mm_per_sec,
+            });
+            self.warned.push(pair);
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+    use crate::track::TrackMapper;
+
+    fn oval_map() -> TrackMap {
+        let mut mapper = TrackMapper::new();
+        for road_piece_id in [34, 33, 17, 20, 36, 18, 23, 39, 34] {
+            mapper.process_position_update(&position_update(0, road_piece_id));
+        }
+        mapper.map().unwrap().clone()
+    }
+
+    fn update(
+        road_piece_id: u8,
+        speed_mm_per_sec: u16,
+    ) -> AnkiVehicleMsgLocalisationPositionUpdate {
+        let mut data = position_update(0, road_piece_id);
+        data.speed_mm_per_sec = speed_mm_per_sec;
+        data
+    }
+
+    #[test]
+    fn proximity_test() {
+        let t0 = Instant::now();
+        let mut monitor = ProximityMonitor::new(oval_map(), ProximityConfig::default());
+        assert_eq!(3440.0, monitor.lap_length_mm());
+
+        monitor.process_position_update("a", &update(17, 500), t0);
+        monitor.process_position_update("b", &update(20, 200), t0);
+        assert_eq!(Some(560.0), monitor.distance_along_lap_mm("a", t0));
+        assert!(monitor.check(t0).is_empty());
+
+        // a is 440 mm behind b and closing at 300 mm/s.
+        let gaps = monitor.gaps(t0);
+        assert_eq!(2, gaps.len());
+        assert_eq!("b", gaps[0].ahead);
+        assert_eq!(440.0, gaps[0].gap_mm);
+        assert_eq!(300, gaps[0].closing_speed_mm_per_sec);
+        assert_eq!(3000.0, gaps[1].gap_mm);
+
+        // b has gone 100 mm into the piece a has just entered.
+        let t1 = t0 + Duration::from_millis(500);
+        monitor.process_position_update("b", &update(20, 200), t1);
+        monitor.process_position_update("a", &update(20, 500), t1);
+        assert_eq!(
+            vec![ProximityWarning {
+                vehicle: "a".into(),
+                ahead: "b".into(),
+                lane: 3,
+                gap_mm: 100.0,
+                closing_speed_mm_per_sec: 300,
+            }],
+            monitor.check(t1)
+        );
+        assert!(monitor.check(t1).is_empty());
+
+        // In another lane they pass without a warning.
+        let mut other_lane = update(20, 500);
+        other_lane.offset_from_road_centre_mm = 67.5;
+        monitor.process_position_update("a", &other_lane, t1);
+        assert!(monitor.check(t1).is_empty());
+        assert!(!monitor.gaps(t1)[0].same_lane);
+
+        monitor.process_delocalized("b");
+        assert!(monitor.gaps(t1).is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+use std::time::{Duration, Instant};
+
+use crate::command::{LightPattern, VehicleCommand};
+use crate::protocol::{
+    AnkiVehicleMsgLocalisationPositionUpdate, LightChannel, LightEffect,
+    ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+};
+use crate::race::leaderboard::Leaderboard;
+use crate::race::stop::StopAtLocation;
+use crate::race::{RaceCommand, RaceEvent, RaceUpdate};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EliminationInterval {
+    // Eliminate each time the leader completes this many laps.
+    Laps(u16),
+    // Eliminate on a fixed timer from the first position update.
+    Time(Duration),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EliminationConfig {
+    pub interval: EliminationInterval,
+    pub parking_road_piece_id: u8,
+    pub parking_location_id: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EliminationRace {
+    config: EliminationConfig,
+    leaderboard: Leaderboard,
+    active: Vec<VehicleId>,
+    parking: Vec<(VehicleId, StopAtLocation)>,
+    started_at: Option<Instant>,
+    eliminations: u16,
+    winner: Option<VehicleId>,
+}
+
+fn eliminated_lights() -> VehicleCommand {
+    VehicleCommand::lights_pattern(LightPattern {
+        channel: LightChannel::Red,
+        effect: LightEffect::Throb,
+        start: 0,
+        end: ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+        cycles_per_min: 60,
+    })
+}
+
+impl EliminationRace {
+    pub fn new<I>(vehicles: I, config: EliminationConfig) -> EliminationRace
+    where
+        I: IntoIterator,
+        I::Item: Into<VehicleId>,
+    {
+        let active: Vec<VehicleId> = vehicles.into_iter().map(Into::into).collect();
+        EliminationRace {
+            config,
+            leaderboard: Leaderboard::new(active.clone()),
+            active,
+            parking: Vec::new(),
+            started_at: None,
+            eliminations: 0,
+            winner: None,
+        }
+    }
+
+    pub fn leaderboard(&self) -> &Leaderboard {
+        &self.leaderboard
+    }
+
+    pub fn active(&self) -> &[VehicleId] {
+        &self.active
+    }
+
+    pub fn winner(&self) -> Option<&str> {
+        self.winner.as_deref()
+    }
+
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        self.started_at.get_or_insert(at);
+
+        if let Some((_, stop)) = self.parking.iter_mut().find(|(v, _)| v == vehicle) {
+            if let Some(cmd) = stop.process_position_update(data) {
+                trace_event!(target: TARGET_CONTROLLER, debug, vehicle, "Eliminated vehicle parked");
+                update.commands.push(RaceCommand {
+                    vehicle: vehicle.into(),
+                    command: cmd,
+                });
+                update.events.push(RaceEvent::Parked {
+                    vehicle: vehicle.into(),
+                });
+            }
+            return update;
+        }
+
+        if let Some(event) = self.leaderboard.process_position_update(vehicle, data, at) {
+            update.events.push(event);
+        }
+        if self.winner.is_none() && self.interval_elapsed(at) {
+            self.eliminate_last(&mut update);
+        }
+        update
+    }
+
+    fn interval_elapsed(&self, at: Instant) -> bool {
+        let next = self.eliminations as u32 + 1;
+        match &self.config.interval {
+            EliminationInterval::Laps(laps) => self
+                .leaderboard
+                .leader()
+                .is_some_and(|leader| leader.laps as u32 >= next * *laps as u32),
+            EliminationInterval::Time(interval) => self
+                .started_at
+                .is_some_and(|started_at| at >= started_at + *interval * next),
+        }
+    }
+
+    fn eliminate_last(&mut self, update: &mut RaceUpdate) {
+        // Nobody left to race against, the last car standing has won.
+        if self.active.len() <= 1 {
+            self.declare_winner(update);
+            return;
+        }
+        self.eliminations += 1;
+        let active = &self.active;
+        let Some(last) = self
+            .leaderboard
+            .last(|s| active.contains(&s.vehicle))
+            .map(|s| s.vehicle.clone())
+        else {
+            return;
+        };
+        let position = self.active.len();
+        self.active.retain(|v| *v != last);
+        trace_event!(target: TARGET_CONTROLLER, info, vehicle = %last, position, "Eliminating last placed vehicle");
+
+        let stop = StopAtLocation::new(
+            self.config.parking_road_piece_id,
+            self.config.parking_location_id,
+        );
+        update.commands.push(RaceCommand {
+            vehicle: last.clone(),
+            command: eliminated_lights(),
+        });
+        update.commands.push(RaceCommand {
+            vehicle: last.clone(),
+            command: stop.arm(),
+        });
+        update.events.push(RaceEvent::Eliminated {
+            vehicle: last.clone(),
+            position,
+        });
+        self.parking.push((last, stop));
+        self.declare_winner(update);
+    }
+
+    fn declare_winner(&mut self, update: &mut RaceUpdate) {
+        if let [winner] = self.active.as_slice() {
+            trace_event!(target: TARGET_CONTROLLER, info, vehicle = %winner, "Elimination race won");
+            self.winner = Some(winner.clone());
+            update.events.push(RaceEvent::Winner {
+                vehicle: winner.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+    use crate::STOP_ACCEL_MM_PER_SEC2;
+
+    fn lap(race: &mut EliminationRace, vehicle: &str, at: Instant) -> RaceUpdate {
+        race.process_position_update(vehicle, &position_update(0, 17), at);
+        race.process_position_update(vehicle, &position_update(0, 34), at)
+    }
+
+    #[test]
+    fn elimination_by_laps_test() {
+        let t0 = Instant::now();
+        let mut race = EliminationRace::new(
+            ["a".to_string(), "b".to_string(), "c".to_string()],
+            EliminationConfig {
+                interval: EliminationInterval::Laps(1),
+                parking_road_piece_id: 18,
+                parking_location_id: None,
+            },
+        );
+        for v in ["a", "b", "c"] {
+            lap(&mut race, v, t0);
+        }
+        race.process_position_update("a", &position_update(0, 17), t0);
+        race.process_position_update("a", &position_update(0, 20), t0);
+        race.process_position_update("c", &position_update(0, 17), t0);
+
+        // The leader finishing lap 1 eliminates the slowest car.
+        let update = lap(&mut race, "b", t0 + Duration::from_secs(4));
+        assert_eq!(
+            vec![
+                RaceEvent::LapCompleted {
+                    vehicle: "b".into(),
+                    lap: 1,
+                    lap_time: Duration::from_secs(4),
+                },
+                RaceEvent::Eliminated {
+                    vehicle: "c".into(),
+                    position: 3,
+                }
+            ],
+            update.events
+        );
+        assert_eq!(vec!["a", "b"], race.active());
+
+        let update = race.process_position_update("c", &position_update(0, 18), t0);
+        assert_eq!(
+            vec![RaceEvent::Parked {
+                vehicle: "c".into()
+            }],
+            update.events
+        );
+        assert_eq!(
+            VehicleCommand::SetSpeed {
+                speed_mm_per_sec: 0,
+                accel_mm_per_sec2: STOP_ACCEL_MM_PER_SEC2,
+            },
+            update.commands[0].command
+        );
+
+        let update = lap(&mut race, "a", t0 + Duration::from_secs(9));
+        assert_eq!(1, update.events.len());
+        let update = lap(&mut race, "b", t0 + Duration::from_secs(10));
+        assert!(update.events.contains(&RaceEvent::Eliminated {
+            vehicle: "a".into(),
+            position: 2,
+        }));
+        assert!(update.events.contains(&RaceEvent::Winner {
+            vehicle: "b".into()
+        }));
+        assert_eq!(Some("b"), race.winner());
+    }
+
+    #[test]
+    fn elimination_single_vehicle_test() {
+        let t0 = Instant::now();
+        let config = EliminationConfig {
+            interval: EliminationInterval::Laps(1),
+            parking_road_piece_id: 18,
+            parking_location_id: None,
+        };
+        let mut race = EliminationRace::new(["a".to_string()], config.clone());
+        lap(&mut race, "a", t0);
+        let update = lap(&mut race, "a", t0 + Duration::from_secs(4));
+        assert_eq!(
+            Some(&RaceEvent::Winner {
+                vehicle: "a".into()
+            }),
+            update.events.last()
+        );
+        assert!(update.commands.is_empty());
+        assert_eq!(vec!["a"], race.active());
+        assert_eq!(Some("a"), race.winner());
+
+        let mut race = EliminationRace::new(Vec::<String>::new(), config);
+        let update = lap(&mut race, "a", t0);
+        assert!(update.commands.is_empty());
+        assert_eq!(None, race.winner());
+    }
+
+    #[test]
+    fn elimination_by_time_test() {
+        let t0 = Instant::now();
+        let mut race = EliminationRace::new(
+            ["a".to_string(), "b".to_string()],
+            EliminationConfig {
+                interval: EliminationInterval::Time(Duration::from_secs(30)),
+                parking_road_piece_id: 18,
+                parking_location_id: None,
+            },
+        );
+        race.process_position_update("a", &position_update(0, 17), t0);
+        race.process_position_update("a", &position_update(0, 20), t0);
+        race.process_position_update("b", &position_update(0, 17), t0);
+        let update = race.process_position_update(
+            "b",
+            &position_update(0, 20),
+            t0 + Duration::from_secs(29),
+        );
+        assert!(update.events.is_empty());
+
+        let update = race.process_position_update(
+            "a",
+            &position_update(0, 21),
+            t0 + Duration::from_secs(30),
+        );
+        assert_eq!(
+            vec![
+                RaceEvent::Eliminated {
+                    vehicle: "b".into(),
+                    position: 2,
+                },
+                RaceEvent::Winner {
+                    vehicle: "a".into()
+                }
+            ],
+            update.events
+        );
+        assert_eq!(2, update.commands.len());
+    }
+}
@@ -0,0 +1,470 @@
+use std::time::{Duration, Instant};
+
+use crate::command::{LightPattern, VehicleCommand};
+use crate::fleet::Fleet;
+use crate::protocol::{
+    AnkiVehicleMsgLocalisationPositionUpdate, LightChannel, LightEffect, VehicleMessage,
+    ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+};
+use crate::race::leaderboard::Leaderboard;
+use crate::race::{RaceCommand, RaceEvent, RaceUpdate};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+use crate::STOP_ACCEL_MM_PER_SEC2;
+
+pub const RACE_DEFAULT_LAPS: u16 = 5;
+pub const RACE_DEFAULT_SPEED_MM_PER_SEC: i16 = 600;
+pub const RACE_DEFAULT_ACCEL_MM_PER_SEC2: i16 = 1000;
+pub const RACE_DEFAULT_COUNTDOWN_STEPS: u8 = 3;
+pub const RACE_DEFAULT_COUNTDOWN_STEP: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceConfig {
+    pub laps: u16,
+    // Every car is sent off at this speed when the countdown ends.
+    pub speed_mm_per_sec: i16,
+    pub accel_mm_per_sec2: i16,
+    pub countdown_steps: u8,
+    pub countdown_step: Duration,
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        RaceConfig {
+            laps: RACE_DEFAULT_LAPS,
+            speed_mm_per_sec: RACE_DEFAULT_SPEED_MM_PER_SEC,
+            accel_mm_per_sec2: RACE_DEFAULT_ACCEL_MM_PER_SEC2,
+            countdown_steps: RACE_DEFAULT_COUNTDOWN_STEPS,
+            countdown_step: RACE_DEFAULT_COUNTDOWN_STEP,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RacePhase {
+    Grid,
+    Countdown,
+    Running,
+    // Every car has finished or dropped out.
+    Finished,
+}
+
+// One red fade per step, like the lights on a start gantry going out.
+fn countdown_lights(step: Duration) -> VehicleCommand {
+    VehicleCommand::lights_pattern(LightPattern {
+        channel: LightChannel::Red,
+        effect: LightEffect::Fade,
+        start: ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+        end: 0,
+        cycles_per_min: (60_000 / step.as_millis().max(1)).min(u16::MAX as u128) as u16,
+    })
+}
+
+fn go_lights() -> VehicleCommand {
+    let red = LightPattern {
+        channel: LightChannel::Red,
+        effect: LightEffect::Steady,
+        start: 0,
+        end: 0,
+        cycles_per_min: 0,
+    };
+    let green = LightPattern {
+        channel: LightChannel::Green,
+        start: ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+        end: ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+        ..red.clone()
+    };
+    VehicleCommand::LightsPattern {
+        channels: [red, green].into_iter().collect(),
+    }
+}
+
+fn stop_command() -> VehicleCommand {
+    VehicleCommand::SetSpeed {
+        speed_mm_per_sec: 0,
+        accel_mm_per_sec2: STOP_ACCEL_MM_PER_SEC2,
+    }
+}
+
+// A race over a set number of laps. `start` begins the countdown and `tick` runs it, the cars
+// are sent off together when it ends. Laps and positions come from the leaderboard, a car is
+// flagged once it completes the last lap and stopped, one that loses the track is out of the
+// race. The race is over when no car is left running.
+#[derive(Debug, Clone)]
+pub struct Race {
+    config: RaceConfig,
+    participants: Vec<VehicleId>,
+    leaderboard: Leaderboard,
+    phase: RacePhase,
+    countdown_started_at: Option<Instant>,
+    countdown_remaining: u8,
+    started_at: Option<Instant>,
+    // Cars still racing, in running order.
+    running: Vec<VehicleId>,
+    finishers: Vec<VehicleId>,
+    did_not_finish: Vec<VehicleId>,
+}
+
+impl Race {
+    pub fn new<I>(vehicles: I, config: RaceConfig) -> Race
+    where
+        I: IntoIterator,
+        I::Item: Into<VehicleId>,
+    {
+        let participants: Vec<VehicleId> = vehicles.into_iter().map(Into::into).collect();
+        Race {
+            config,
+            leaderboard: Leaderboard::new(participants.clone()),
+            running: participants.clone(),
+            participants,
+            phase: RacePhase::Grid,
+            countdown_started_at: None,
+            countdown_remaining: 0,
+            started_at: None,
+            finishers: Vec::new(),
+            did_not_finish: Vec::new(),
+        }
+    }
+
+    // Every vehicle in the fleet, known by its address, see `Fleet::race_commands`.
+    pub fn for_fleet<T>(fleet: &Fleet<T>, config: RaceConfig) -> Race {
+        Race::new(fleet.addresses(), config)
+    }
+
+    pub fn with_finish_road_piece_id(mut self, road_piece_id: u8) -> Race {
+        self.leaderboard = self.leaderboard.with_finish_road_piece_id(road_piece_id);
+        self
+    }
+
+    pub fn config(&self) -> &RaceConfig {
+        &self.config
+    }
+
+    pub fn participants(&self) -> &[VehicleId] {
+        &self.participants
+    }
+
+    pub fn phase(&self) -> RacePhase {
+        self.phase
+    }
+
+    pub fn leaderboard(&self) -> &Leaderboard {
+        &self.leaderboard
+    }
+
+    // In finishing order.
+    pub fn finishers(&self) -> &[VehicleId] {
+        &self.finishers
+    }
+
+    pub fn did_not_finish(&self) -> &[VehicleId] {
+        &self.did_not_finish
+    }
+
+    pub fn winner(&self) -> Option<&VehicleId> {
+        self.finishers.first()
+    }
+
+    // Time since the cars were sent off.
+    pub fn race_time(&self, at: Instant) -> Option<Duration> {
+        self.started_at
+            .map(|started_at| at.saturating_duration_since(started_at))
+    }
+
+    // Starts the countdown, the cars are expected to be on the grid with SDK mode on.
+    pub fn start(&mut self, at: Instant) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        if self.phase != RacePhase::Grid {
+            return update;
+        }
+        trace_event!(target: TARGET_CONTROLLER, info, vehicles = self.participants.len(), laps = self.config.laps, "Race countdown started");
+        self.phase = RacePhase::Countdown;
+        self.countdown_started_at = Some(at);
+        self.countdown_remaining = self.config.countdown_steps;
+        if self.countdown_remaining == 0 {
+            self.go(at, &mut update);
+        } else {
+            self.countdown_step(&mut update);
+        }
+        update
+    }
+
+    // Runs the countdown, call it at least once per countdown step until the race is running.
+    pub fn tick(&mut self, at: Instant) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        let Some(countdown_started_at) = self.countdown_started_at else {
+            return update;
+        };
+        if self.phase != RacePhase::Countdown {
+            return update;
+        }
+        let elapsed = at.saturating_duration_since(countdown_started_at);
+        let steps = (elapsed.as_millis() / self.config.countdown_step.as_millis().max(1))
+            .min(self.config.countdown_steps as u128) as u8;
+        let remaining = self.config.countdown_steps - steps;
+        if remaining == self.countdown_remaining {
+            return update;
+        }
+        self.countdown_remaining = remaining;
+        if remaining == 0 {
+            self.go(at, &mut update);
+        } else {
+            self.countdown_step(&mut update);
+        }
+        update
+    }
+
+    fn countdown_step(&self, update: &mut RaceUpdate) {
+        let lights = countdown_lights(self.config.countdown_step);
+        for vehicle in &self.participants {
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: lights.clone(),
+            });
+        }
+        update.events.push(RaceEvent::Countdown {
+            remaining: self.countdown_remaining,
+        });
+    }
+
+    fn go(&mut self, at: Instant, update: &mut RaceUpdate) {
+        trace_event!(target: TARGET_CONTROLLER, info, "Race started");
+        self.phase = RacePhase::Running;
+        self.started_at = Some(at);
+        for vehicle in &self.participants {
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: go_lights(),
+            });
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: self.config.speed_mm_per_sec,
+                    accel_mm_per_sec2: self.config.accel_mm_per_sec2,
+                },
+            });
+        }
+        update.events.push(RaceEvent::Started);
+    }
+
+    pub fn process_message(
+        &mut self,
+        vehicle: &str,
+        msg: &VehicleMessage,
+        at: Instant,
+    ) -> RaceUpdate {
+        match msg {
+            VehicleMessage::PositionUpdate(data) => self.process_position_update(vehicle, data, at),
+            VehicleMessage::Delocalized(_) => self.process_delocalized(vehicle),
+            _ => RaceUpdate::default(),
+        }
+    }
+
+    // Updates from before the start, or from a car no longer racing, are ignored.
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        if self.phase != RacePhase::Running {
+            return update;
+        }
+        let Some(before) = self.running.iter().position(|v| v == vehicle) else {
+            return update;
+        };
+
+        let lap = self.leaderboard.process_position_update(vehicle, data, at);
+        let lap_completed = match &lap {
+            Some(RaceEvent::LapCompleted { lap, .. }) => Some(*lap),
+            _ => None,
+        };
+        update.events.extend(lap);
+
+        self.update_running_order(vehicle, before, &mut update);
+        if lap_completed.is_some_and(|lap| lap >= self.config.laps) {
+            self.finish(vehicle, at, &mut update);
+        }
+        update
+    }
+
+    fn update_running_order(&mut self, vehicle: &str, before: usize, update: &mut RaceUpdate) {
+        let previous = std::mem::take(&mut self.running);
+        self.running = self
+            .leaderboard
+            .standings()
+            .iter()
+            .map(|s| &s.vehicle)
+            .filter(|v| previous.contains(v))
+            .cloned()
+            .collect();
+        let Some(after) = self.running.iter().position(|v| v == vehicle) else {
+            return;
+        };
+        if after >= before {
+            return;
+        }
+        let position = self.leaderboard.position(vehicle).unwrap_or(after + 1);
+        for overtaken in &previous[after..before] {
+            trace_event!(target: TARGET_CONTROLLER, debug, vehicle, overtaken = %overtaken, position, "Overtake");
+            update.events.push(RaceEvent::Overtake {
+                vehicle: vehicle.into(),
+                overtaken: overtaken.clone(),
+                position,
+            });
+        }
+    }
+
+    fn finish(&mut self, vehicle: &str, at: Instant, update: &mut RaceUpdate) {
+        self.running.retain(|v| v != vehicle);
+        self.finishers.push(vehicle.into());
+        let position = self.finishers.len();
+        let race_time = self.race_time(at).unwrap_or_default();
+        trace_event!(target: TARGET_CONTROLLER, info, vehicle, position, ?race_time, "Vehicle finished");
+        update.commands.push(RaceCommand {
+            vehicle: vehicle.into(),
+            command: stop_command(),
+        });
+        update.events.push(RaceEvent::Finished {
+            vehicle: vehicle.into(),
+            position,
+            race_time,
+        });
+        if position == 1 {
+            update.events.push(RaceEvent::Winner {
+                vehicle: vehicle.into(),
+            });
+        }
+        self.check_over();
+    }
+
+    // A car that loses the track during the race is out, it's stopped where it is.
+    pub fn process_delocalized(&mut self, vehicle: &str) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        if self.phase != RacePhase::Running || !self.running.iter().any(|v| v == vehicle) {
+            return update;
+        }
+        trace_event!(target: TARGET_CONTROLLER, info, vehicle, "Vehicle did not finish");
+        self.running.retain(|v| v != vehicle);
+        self.did_not_finish.push(vehicle.into());
+        update.commands.push(RaceCommand {
+            vehicle: vehicle.into(),
+            command: stop_command(),
+        });
+        update.events.push(RaceEvent::DidNotFinish {
+            vehicle: vehicle.into(),
+        });
+        self.check_over();
+        update
+    }
+
+    fn check_over(&mut self) {
+        if self.running.is_empty() {
+            trace_event!(target: TARGET_CONTROLLER, info, finishers = self.finishers.len(), "Race over");
+            self.phase = RacePhase::Finished;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+
+    #[test]
+    fn race_test() {
+        let t0 = Instant::now();
+        let mut race = Race::new(
+            ["a", "b", "c"],
+            RaceConfig {
+                laps: 1,
+                ..RaceConfig::default()
+            },
+        );
+        assert_eq!(
+            RaceUpdate::default(),
+            race.process_position_update("a", &position_update(0, 34), t0)
+        );
+
+        let update = race.start(t0);
+        assert_eq!(vec![RaceEvent::Countdown { remaining: 3 }], update.events);
+        assert_eq!(3, update.commands.len());
+        assert!(race.tick(t0 + Duration::from_millis(500)).events.is_empty());
+        assert_eq!(
+            vec![RaceEvent::Countdown { remaining: 1 }],
+            race.tick(t0 + Duration::from_secs(2)).events
+        );
+        let update = race.tick(t0 + Duration::from_secs(3));
+        assert_eq!(vec![RaceEvent::Started], update.events);
+        assert_eq!(
+            VehicleCommand::SetSpeed {
+                speed_mm_per_sec: RACE_DEFAULT_SPEED_MM_PER_SEC,
+                accel_mm_per_sec2: RACE_DEFAULT_ACCEL_MM_PER_SEC2,
+            },
+            update.commands[1].command
+        );
+        assert_eq!(RacePhase::Running, race.phase());
+
+        let t1 = t0 + Duration::from_secs(3);
+        for v in ["a", "b", "c"] {
+            race.process_position_update(v, &position_update(0, 34), t1);
+        }
+        race.process_position_update("a", &position_update(0, 17), t1);
+        let update = race.process_position_update("b", &position_update(0, 17), t1);
+        assert!(update.events.is_empty());
+        let update = race.process_position_update("b", &position_update(0, 20), t1);
+        assert_eq!(
+            vec![RaceEvent::Overtake {
+                vehicle: "b".into(),
+                overtaken: "a".into(),
+                position: 1,
+            }],
+            update.events
+        );
+
+        let update = race.process_message(
+            "c",
+            &VehicleMessage::parse(&[
+                1,
+                crate::protocol::AnkiVehicleMsgType::V2CVehicleDelocalized.into(),
+            ])
+            .unwrap(),
+            t1,
+        );
+        assert_eq!(
+            vec![RaceEvent::DidNotFinish {
+                vehicle: "c".into()
+            }],
+            update.events
+        );
+        assert_eq!(stop_command(), update.commands[0].command);
+
+        let update =
+            race.process_position_update("b", &position_update(0, 34), t1 + Duration::from_secs(6));
+        assert_eq!(
+            vec![
+                RaceEvent::LapCompleted {
+                    vehicle: "b".into(),
+                    lap: 1,
+                    lap_time: Duration::from_secs(6),
+                },
+                RaceEvent::Finished {
+                    vehicle: "b".into(),
+                    position: 1,
+                    race_time: Duration::from_secs(6),
+                },
+                RaceEvent::Winner {
+                    vehicle: "b".into()
+                },
+            ],
+            update.events
+        );
+        race.process_position_update("a", &position_update(0, 20), t1);
+        race.process_position_update("a", &position_update(0, 34), t1 + Duration::from_secs(7));
+        assert_eq!(RacePhase::Finished, race.phase());
+        assert_eq!(vec!["b", "a"], race.finishers());
+        assert_eq!(vec!["c"], race.did_not_finish());
+    }
+}
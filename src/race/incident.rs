@@ -0,0 +1,279 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::race::leaderboard::Leaderboard;
+use crate::race::safety_car::SafetyCar;
+use crate::race::{RaceEvent, RaceUpdate};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentConfig {
+    // A vehicle that hasn't reported a position for this long is considered stopped.
+    pub telemetry_timeout: Duration,
+    // Delocalizations older than this aren't linked to a new incident.
+    pub window: Duration,
+    pub min_vehicles: usize,
+}
+
+impl Default for IncidentConfig {
+    fn default() -> Self {
+        IncidentConfig {
+            telemetry_timeout: Duration::from_millis(750),
+            window: Duration::from_secs(3),
+            min_vehicles: 2,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentPosition {
+    pub vehicle: VehicleId,
+    pub race_position: Option<usize>,
+    pub road_piece_id: u8,
+    pub location_id: u8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+    pub involved: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncidentReport {
+    pub at: Instant,
+    pub road_piece_id: u8,
+    pub vehicles: Vec<VehicleId>,
+    // Last known position of every tracked vehicle when the incident was detected.
+    pub positions: Vec<IncidentPosition>,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    vehicle: VehicleId,
+    last_seen: Instant,
+    road_piece_id: u8,
+    location_id: u8,
+    offset_from_road_centre_mm: f32,
+    speed_mm_per_sec: u16,
+    delocalized_at: Option<Instant>,
+    in_incident: bool,
+}
+
+// Looks for several vehicles going quiet or delocalizing on the same road piece, which is
+// what a crash on the track looks like from telemetry alone.
+#[derive(Debug, Clone)]
+pub struct IncidentDetector {
+    config: IncidentConfig,
+    vehicles: Vec<Tracked>,
+}
+
+impl IncidentDetector {
+    pub fn new(config: IncidentConfig) -> IncidentDetector {
+        IncidentDetector {
+            config,
+            vehicles: Vec::new(),
+        }
+    }
+
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) {
+        let tracked = match self.vehicles.iter_mut().find(|t| t.vehicle == vehicle) {
+            Some(tracked) => tracked,
+            None => {
+                self.vehicles.push(Tracked {
+                    vehicle: vehicle.into(),
+                    last_seen: at,
+                    road_piece_id: 0,
+                    location_id: 0,
+                    offset_from_road_centre_mm: 0.0,
+                    speed_mm_per_sec: 0,
+                    delocalized_at: None,
+                    in_incident: false,
+                });
+                self.vehicles.last_mut().unwrap()
+            }
+        };
+        tracked.last_seen = at;
+        tracked.road_piece_id = data.road_piece_id;
+        tracked.location_id = data.location_id;
+        tracked.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
+        tracked.speed_mm_per_sec = data.speed_mm_per_sec;
+        tracked.delocalized_at = None;
+        tracked.in_incident = false;
+    }
+
+    pub fn process_delocalized(&mut self, vehicle: &str, at: Instant) {
+        if let Some(tracked) = self.vehicles.iter_mut().find(|t| t.vehicle == vehicle) {
+            tracked.delocalized_at = Some(at);
+        }
+    }
+
+    fn is_delocalized(&self, tracked: &Tracked, at: Instant) -> bool {
+        tracked
+            .delocalized_at
+            .is_some_and(|d| at.saturating_duration_since(d) <= self.config.window)
+    }
+
+    fn is_suspect(&self, tracked: &Tracked, at: Instant) -> bool {
+        !tracked.in_incident
+            && (self.is_delocalized(tracked, at)
+                || at.saturating_duration_since(tracked.last_seen) > self.config.telemetry_timeout)
+    }
+
+    // Vehicles are reported at most once per incident, they rejoin on their next position update.
+    pub fn check(
+        &mut self,
+        at: Instant,
+        leaderboard: Option<&Leaderboard>,
+    ) -> Option<IncidentReport> {
+        let suspects: Vec<&Tracked> = self
+            .vehicles
+            .iter()
+            .filter(|t| self.is_suspect(t, at))
+            .collect();
+        let (road_piece_id, involved) = suspects.iter().find_map(|candidate| {
+            let zone: Vec<VehicleId> = suspects
+                .iter()
+                .filter(|t| t.road_piece_id == candidate.road_piece_id)
+                .map(|t| t.vehicle.clone())
+                .collect();
+            let any_delocalized = suspects
+                .iter()
+                .any(|t| t.road_piece_id == candidate.road_piece_id && self.is_delocalized(t, at));
+            (zone.len() >= self.config.min_vehicles && any_delocalized)
+                .then_some((candidate.road_piece_id, zone))
+        })?;
+
+        let positions = self
+            .vehicles
+            .iter()
+            .map(|t| IncidentPosition {
+                vehicle: t.vehicle.clone(),
+                race_position: leaderboard.and_then(|l| l.position(&t.vehicle)),
+                road_piece_id: t.road_piece_id,
+                location_id: t.location_id,
+                offset_from_road_centre_mm: t.offset_from_road_centre_mm,
+                speed_mm_per_sec: t.speed_mm_per_sec,
+                involved: involved.contains(&t.vehicle),
+            })
+            .collect();
+        trace_event!(target: TARGET_CONTROLLER, warn, road_piece_id, vehicles = ?involved, "Incident detected");
+        for tracked in self.vehicles.iter_mut() {
+            if involved.contains(&tracked.vehicle) {
+                tracked.in_incident = true;
+            }
+        }
+        Some(IncidentReport {
+            at,
+            road_piece_id,
+            vehicles: involved,
+            positions,
+        })
+    }
+
+    pub fn vehicles(&self) -> impl Iterator<Item = &VehicleId> {
+        self.vehicles.iter().map(|t| &t.vehicle)
+    }
+}
+
+// Deploys the safety car for the whole field as soon as an incident is detected.
+#[derive(Debug, Clone)]
+pub struct IncidentControl {
+    pub detector: IncidentDetector,
+    pub safety_car: SafetyCar,
+}
+
+impl IncidentControl {
+    pub fn new(config: IncidentConfig, safety_car: SafetyCar) -> IncidentControl {
+        IncidentControl {
+            detector: IncidentDetector::new(config),
+            safety_car,
+        }
+    }
+
+    pub fn check(&mut self, at: Instant, leaderboard: Option<&Leaderboard>) -> RaceUpdate {
+        let Some(report) = self.detector.check(at, leaderboard) else {
+            return RaceUpdate::default();
+        };
+        let vehicles: Vec<VehicleId> = self.detector.vehicles().cloned().collect();
+        let mut update = self.safety_car.deploy(&vehicles);
+        update.events.insert(0, RaceEvent::Incident(report));
+        update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+
+    #[test]
+    fn incident_detected_for_multiple_cars_in_zone_test() {
+        let t0 = Instant::now();
+        let mut detector = IncidentDetector::new(IncidentConfig::default());
+        detector.process_position_update("a", &position_update(3, 20), t0);
+        detector.process_position_update("b", &position_update(1, 20), t0);
+        detector.process_position_update("c", &position_update(1, 17), t0);
+
+        detector.process_delocalized("a", t0 + Duration::from_millis(100));
+        // Only one car is in trouble, the other is still reporting.
+        detector.process_position_update(
+            "b",
+            &position_update(2, 20),
+            t0 + Duration::from_millis(900),
+        );
+        detector.process_position_update(
+            "c",
+            &position_update(2, 17),
+            t0 + Duration::from_millis(900),
+        );
+        assert_eq!(None, detector.check(t0 + Duration::from_millis(1000), None));
+
+        let at = t0 + Duration::from_millis(1800);
+        detector.process_position_update("c", &position_update(3, 17), at);
+        let report = detector.check(at, None).unwrap();
+        assert_eq!(20, report.road_piece_id);
+        assert_eq!(vec!["a".to_string(), "b".to_string()], report.vehicles);
+        assert_eq!(3, report.positions.len());
+        assert!(!report.positions[2].involved);
+
+        // Already reported.
+        assert_eq!(None, detector.check(at, None));
+    }
+
+    #[test]
+    fn stopped_cars_without_delocalization_are_not_an_incident_test() {
+        let t0 = Instant::now();
+        let mut detector = IncidentDetector::new(IncidentConfig::default());
+        detector.process_position_update("a", &position_update(3, 20), t0);
+        detector.process_position_update("b", &position_update(1, 20), t0);
+        assert_eq!(None, detector.check(t0 + Duration::from_secs(2), None));
+    }
+
+    #[test]
+    fn incident_control_deploys_safety_car_test() {
+        let t0 = Instant::now();
+        let mut control = IncidentControl::new(IncidentConfig::default(), SafetyCar::default());
+        let leaderboard = Leaderboard::new(["b".to_string(), "a".to_string()]);
+        control
+            .detector
+            .process_position_update("a", &position_update(3, 20), t0);
+        control
+            .detector
+            .process_position_update("b", &position_update(1, 20), t0);
+        control.detector.process_delocalized("a", t0);
+        control.detector.process_delocalized("b", t0);
+
+        let update = control.check(t0, Some(&leaderboard));
+        let RaceEvent::Incident(report) = &update.events[0] else {
+            panic!["Expected an incident report"]
+        };
+        assert_eq!(Some(2), report.positions[0].race_position);
+        assert_eq!(RaceEvent::SafetyCarDeployed, update.events[1]);
+        assert_eq!(4, update.commands.len());
+        assert!(control.safety_car.is_deployed());
+    }
+}
@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::lap::LapCounter;
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::race::RaceEvent;
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+
+// Road piece the vehicles report while crossing the finish line on a standard kit.
+pub const FINISH_LINE_ROAD_PIECE_ID: u8 = 34;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Standing {
+    pub vehicle: VehicleId,
+    pub laps: u16,
+    // Road pieces entered since the last lap was completed.
+    pub pieces: u16,
+    pub last_lap_time: Option<Duration>,
+    pub best_lap_time: Option<Duration>,
+    lap_counter: LapCounter,
+    // When the vehicle entered the piece it is on now.
+    piece_entered_at: Option<Instant>,
+}
+
+impl Standing {
+    fn new(vehicle: VehicleId) -> Standing {
+        Standing {
+            vehicle,
+            laps: 0,
+            pieces: 0,
+            last_lap_time: None,
+            best_lap_time: None,
+            lap_counter: LapCounter::new(),
+            piece_entered_at: None,
+        }
+    }
+
+    // Further along the track first, ties broken by who got there first.
+    fn race_order(&self, other: &Standing) -> Ordering {
+        other
+            .laps
+            .cmp(&self.laps)
+            .then(other.pieces.cmp(&self.pieces))
+            .then(match (self.piece_entered_at, other.piece_entered_at) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Leaderboard {
+    standings: Vec<Standing>,
+}
+
+impl Leaderboard {
+    pub fn new<I>(vehicles: I) -> Leaderboard
+    where
+        I: IntoIterator,
+        I::Item: Into<VehicleId>,
+    {
+        Leaderboard {
+            standings: vehicles
+                .into_iter()
+                .map(|vehicle| Standing::new(vehicle.into()))
+                .collect(),
+        }
+    }
+
+    pub fn with_finish_road_piece_id(mut self, road_piece_id: u8) -> Leaderboard {
+        for standing in &mut self.standings {
+            standing.lap_counter = LapCounter::new().with_finish_road_piece_id(road_piece_id);
+        }
+        self
+    }
+
+    // Laps are counted by each vehicle's `LapCounter`, the first finish line crossing only starts
+    // the clock.
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) -> Option<RaceEvent> {
+        let standing = self.standings.iter_mut().find(|s| s.vehicle == vehicle)?;
+        if standing.lap_counter.road_piece_id() == Some(data.road_piece_id) {
+            return None;
+        }
+        let lap = standing.lap_counter.process_position_update(data, at);
+        standing.piece_entered_at = Some(at);
+
+        if data.road_piece_id != standing.lap_counter.finish_road_piece_id() {
+            standing.pieces = standing.pieces.saturating_add(1);
+            self.standings.sort_by(Standing::race_order);
+            return None;
+        }
+
+        let event = lap.map(|lap| {
+            standing.laps = lap.lap;
+            standing.last_lap_time = Some(lap.lap_time);
+            standing.best_lap_time = standing.lap_counter.best_lap_time();
+            trace_event!(target: TARGET_CONTROLLER, debug, vehicle = %standing.vehicle, lap = lap.lap, lap_time = ?lap.lap_time, "Lap completed");
+            RaceEvent::LapCompleted {
+                vehicle: standing.vehicle.clone(),
+                lap: lap.lap,
+                lap_time: lap.lap_time,
+            }
+        });
+        standing.pieces = 0;
+        self.standings.sort_by(Standing::race_order);
+        event
+    }
+
+    pub fn standings(&self) -> &[Standing] {
+        &self.standings
+    }
+
+    pub fn standing(&self, vehicle: &str) -> Option<&Standing> {
+        self.standings.iter().find(|s| s.vehicle == vehicle)
+    }
+
+    // 1-based race position.
+    pub fn position(&self, vehicle: &str) -> Option<usize> {
+        self.standings
+            .iter()
+            .position(|s| s.vehicle == vehicle)
+            .map(|p| p + 1)
+    }
+
+    pub fn leader(&self) -> Option<&Standing> {
+        self.standings.first()
+    }
+
+    // Last placed vehicle among those accepted by `filter`.
+    pub fn last<F: Fn(&Standing) -> bool>(&self, filter: F) -> Option<&Standing> {
+        self.standings.iter().rev().find(|s| filter(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+
+    #[test]
+    fn leaderboard_lap_test() {
+        let t0 = Instant::now();
+        let mut board = Leaderboard::new(["a".to_string(), "b".to_string()]);
+
+        assert_eq!(
+            None,
+            board.process_position_update("a", &position_update(0, 34), t0)
+        );
+        board.process_position_update("a", &position_update(0, 17), t0);
+        let event = board.process_position_update(
+            "a",
+            &position_update(0, 34),
+            t0 + Duration::from_secs(5),
+        );
+        assert_eq!(
+            Some(RaceEvent::LapCompleted {
+                vehicle: "a".into(),
+                lap: 1,
+                lap_time: Duration::from_secs(5),
+            }),
+            event
+        );
+        assert_eq!(Some(1), board.position("a"));
+        assert_eq!(Some(2), board.position("b"));
+        assert_eq!("b", board.last(|_| true).unwrap().vehicle);
+    }
+
+    #[test]
+    fn leaderboard_orders_by_pieces_within_lap_test() {
+        let t0 = Instant::now();
+        let mut board = Leaderboard::new(["a".to_string(), "b".to_string()]);
+        board.process_position_update("b", &position_update(0, 17), t0);
+        board.process_position_update("b", &position_update(0, 20), t0);
+        board.process_position_update("a", &position_update(0, 17), t0);
+        board.process_position_update("a", &position_update(0, 34), t0);
+        board.process_position_update("b", &position_update(0, 34), t0 + Duration::from_secs(1));
+        assert_eq!("a", board.leader().unwrap().vehicle);
+        board.process_position_update("b", &position_update(0, 18), t0);
+
+        assert_eq!("b", board.leader().unwrap().vehicle);
+        // Repeated updates on the same piece are not counted again.
+        board.process_position_update("b", &position_update(1, 18), t0);
+        assert_eq!(1, board.standing("b").unwrap().pieces);
+    }
+
+    #[test]
+    fn leaderboard_tie_break_test() {
+        let t0 = Instant::now();
+        let mut board = Leaderboard::new(["a".to_string(), "b".to_string()]);
+        // b starts its lap first, but a gets to the piece they are both on first.
+        board.process_position_update("b", &position_update(0, 34), t0);
+        board.process_position_update("a", &position_update(0, 34), t0 + Duration::from_secs(1));
+        board.process_position_update("b", &position_update(0, 17), t0 + Duration::from_secs(2));
+        board.process_position_update("a", &position_update(0, 17), t0 + Duration::from_secs(3));
+        board.process_position_update("a", &position_update(0, 18), t0 + Duration::from_secs(5));
+        board.process_position_update("b", &position_update(0, 18), t0 + Duration::from_secs(6));
+
+        assert_eq!(2, board.standing("a").unwrap().pieces);
+        assert_eq!(2, board.standing("b").unwrap().pieces);
+        assert_eq!(Some(1), board.position("a"));
+        assert_eq!(Some(2), board.position("b"));
+    }
+}
@@ -0,0 +1,95 @@
+use smallvec::SmallVec;
+use std::time::Duration;
+
+use crate::command::{VehicleCommand, COMMAND_BATCH_INLINE};
+use crate::race::incident::IncidentReport;
+use crate::vehicle_id::VehicleId;
+
+pub mod elimination;
+pub mod engine;
+pub mod incident;
+pub mod leaderboard;
+pub mod safety_car;
+pub mod stop;
+pub mod time_trial;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RaceEvent {
+    LapCompleted {
+        vehicle: VehicleId,
+        lap: u16,
+        lap_time: Duration,
+    },
+    Eliminated {
+        vehicle: VehicleId,
+        position: usize,
+    },
+    Parked {
+        vehicle: VehicleId,
+    },
+    Winner {
+        vehicle: VehicleId,
+    },
+    Incident(IncidentReport),
+    SafetyCarDeployed,
+    SafetyCarRecalled,
+    // Steps left before the start, counting down to 1.
+    Countdown {
+        remaining: u8,
+    },
+    Started,
+    Finished {
+        vehicle: VehicleId,
+        position: usize,
+        race_time: Duration,
+    },
+    // Passed `overtaken` and is now in `position`.
+    Overtake {
+        vehicle: VehicleId,
+        overtaken: VehicleId,
+        position: usize,
+    },
+    // Lost the track during the race.
+    DidNotFinish {
+        vehicle: VehicleId,
+    },
+}
+
+// Commands produced by race controllers, addressed to a vehicle. They are encoded on the way out,
+// so a caller can still drop or adjust them first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceCommand {
+    pub vehicle: VehicleId,
+    pub command: VehicleCommand,
+}
+
+pub type RaceCommands = SmallVec<[RaceCommand; COMMAND_BATCH_INLINE]>;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RaceUpdate {
+    pub events: Vec<RaceEvent>,
+    pub commands: RaceCommands,
+}
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use scroll::Pread;
+
+    use crate::protocol::{
+        AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgType,
+        ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE, WIRE_ENDIAN,
+    };
+
+    pub fn position_update(
+        location_id: u8,
+        road_piece_id: u8,
+    ) -> AnkiVehicleMsgLocalisationPositionUpdate {
+        let mut data = [0u8; ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE];
+        data[0] = ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE as u8 - 1;
+        data[1] = AnkiVehicleMsgType::V2CLocalisationPositionUpdate.into();
+        data[2] = location_id;
+        data[3] = road_piece_id;
+        data.pread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(0, WIRE_ENDIAN)
+            .unwrap()
+    }
+}
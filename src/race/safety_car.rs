@@ -0,0 +1,161 @@
+use crate::command::{LightPattern, VehicleCommand};
+use crate::protocol::{LightChannel, LightEffect, ANKI_VEHICLE_MAX_LIGHT_INTENSITY};
+use crate::race::{RaceCommand, RaceEvent, RaceUpdate};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+
+pub const SAFETY_CAR_SPEED_MM_PER_SEC: i16 = 300;
+pub const SAFETY_CAR_ACCEL_MM_PER_SEC2: i16 = 1500;
+
+// Red and green flashing together reads as amber on the vehicle LEDs.
+fn caution_lights() -> VehicleCommand {
+    let red = LightPattern {
+        channel: LightChannel::Red,
+        effect: LightEffect::Flash,
+        start: 0,
+        end: ANKI_VEHICLE_MAX_LIGHT_INTENSITY,
+        cycles_per_min: 120,
+    };
+    let green = LightPattern {
+        channel: LightChannel::Green,
+        ..red.clone()
+    };
+    VehicleCommand::LightsPattern {
+        channels: [red, green].into_iter().collect(),
+    }
+}
+
+fn clear_lights() -> VehicleCommand {
+    VehicleCommand::lights_pattern(LightPattern {
+        channel: LightChannel::Red,
+        effect: LightEffect::Steady,
+        start: 0,
+        end: 0,
+        cycles_per_min: 0,
+    })
+}
+
+// Neutralises the race: every car is slowed to the same speed with caution lights until the
+// safety car is recalled. Controllers should pass their speeds through `limit_speed`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SafetyCar {
+    pub speed_mm_per_sec: i16,
+    pub accel_mm_per_sec2: i16,
+    deployed: bool,
+}
+
+impl Default for SafetyCar {
+    fn default() -> Self {
+        SafetyCar::new(SAFETY_CAR_SPEED_MM_PER_SEC, SAFETY_CAR_ACCEL_MM_PER_SEC2)
+    }
+}
+
+impl SafetyCar {
+    pub fn new(speed_mm_per_sec: i16, accel_mm_per_sec2: i16) -> SafetyCar {
+        SafetyCar {
+            speed_mm_per_sec,
+            accel_mm_per_sec2,
+            deployed: false,
+        }
+    }
+
+    pub fn is_deployed(&self) -> bool {
+        self.deployed
+    }
+
+    pub fn limit_speed(&self, speed_mm_per_sec: i16) -> i16 {
+        if self.deployed {
+            speed_mm_per_sec.min(self.speed_mm_per_sec)
+        } else {
+            speed_mm_per_sec
+        }
+    }
+
+    pub fn deploy<'a, I: IntoIterator<Item = &'a VehicleId>>(&mut self, vehicles: I) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        if self.deployed {
+            return update;
+        }
+        self.deployed = true;
+        trace_event!(target: TARGET_CONTROLLER, info, speed = self.speed_mm_per_sec, "Safety car deployed");
+        for vehicle in vehicles {
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: caution_lights(),
+            });
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: self.speed_mm_per_sec,
+                    accel_mm_per_sec2: self.accel_mm_per_sec2,
+                },
+            });
+        }
+        update.events.push(RaceEvent::SafetyCarDeployed);
+        update
+    }
+
+    pub fn recall<'a, I: IntoIterator<Item = &'a VehicleId>>(
+        &mut self,
+        vehicles: I,
+        resume_speed_mm_per_sec: i16,
+    ) -> RaceUpdate {
+        let mut update = RaceUpdate::default();
+        if !self.deployed {
+            return update;
+        }
+        self.deployed = false;
+        trace_event!(target: TARGET_CONTROLLER, info, resume_speed_mm_per_sec, "Safety car recalled");
+        for vehicle in vehicles {
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: clear_lights(),
+            });
+            update.commands.push(RaceCommand {
+                vehicle: vehicle.clone(),
+                command: VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: resume_speed_mm_per_sec,
+                    accel_mm_per_sec2: self.accel_mm_per_sec2,
+                },
+            });
+        }
+        update.events.push(RaceEvent::SafetyCarRecalled);
+        update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safety_car_deploy_and_recall_test() {
+        let vehicles: Vec<VehicleId> = vec!["a".into(), "b".into()];
+        let mut safety_car = SafetyCar::default();
+        assert_eq!(800, safety_car.limit_speed(800));
+
+        let update = safety_car.deploy(&vehicles);
+        assert_eq!(vec![RaceEvent::SafetyCarDeployed], update.events);
+        assert_eq!(4, update.commands.len());
+        assert_eq!(
+            VehicleCommand::SetSpeed {
+                speed_mm_per_sec: SAFETY_CAR_SPEED_MM_PER_SEC,
+                accel_mm_per_sec2: SAFETY_CAR_ACCEL_MM_PER_SEC2,
+            },
+            update.commands[1].command
+        );
+        assert_eq!(SAFETY_CAR_SPEED_MM_PER_SEC, safety_car.limit_speed(800));
+        assert_eq!(RaceUpdate::default(), safety_car.deploy(&vehicles));
+
+        let update = safety_car.recall(&vehicles, 600);
+        assert_eq!(vec![RaceEvent::SafetyCarRecalled], update.events);
+        assert_eq!(
+            VehicleCommand::SetSpeed {
+                speed_mm_per_sec: 600,
+                accel_mm_per_sec2: SAFETY_CAR_ACCEL_MM_PER_SEC2,
+            },
+            update.commands[3].command
+        );
+        assert!(!safety_car.is_deployed());
+    }
+}
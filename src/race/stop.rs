@@ -0,0 +1,75 @@
+use crate::command::VehicleCommand;
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::STOP_ACCEL_MM_PER_SEC2;
+
+pub const STOP_APPROACH_SPEED_MM_PER_SEC: i16 = 300;
+
+// Brings a vehicle to a halt once it reports the target location. When armed the vehicle is
+// slowed to an approach speed so it stops close to the requested spot.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StopAtLocation {
+    pub road_piece_id: u8,
+    pub location_id: Option<u8>,
+    stopped: bool,
+}
+
+impl StopAtLocation {
+    pub fn new(road_piece_id: u8, location_id: Option<u8>) -> StopAtLocation {
+        StopAtLocation {
+            road_piece_id,
+            location_id,
+            stopped: false,
+        }
+    }
+
+    pub fn arm(&self) -> VehicleCommand {
+        VehicleCommand::SetSpeed {
+            speed_mm_per_sec: STOP_APPROACH_SPEED_MM_PER_SEC,
+            accel_mm_per_sec2: STOP_ACCEL_MM_PER_SEC2,
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    // Returns the stop command the first time the target location is reached.
+    pub fn process_position_update(
+        &mut self,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+    ) -> Option<VehicleCommand> {
+        if self.stopped || data.road_piece_id != self.road_piece_id {
+            return None;
+        }
+        if self.location_id.is_some_and(|id| id > data.location_id) {
+            return None;
+        }
+        self.stopped = true;
+        Some(VehicleCommand::SetSpeed {
+            speed_mm_per_sec: 0,
+            accel_mm_per_sec2: STOP_ACCEL_MM_PER_SEC2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+
+    #[test]
+    fn stop_at_location_test() {
+        let mut stop = StopAtLocation::new(17, Some(4));
+        assert_eq!(None, stop.process_position_update(&position_update(10, 18)));
+        assert_eq!(None, stop.process_position_update(&position_update(2, 17)));
+        assert_eq!(
+            Some(VehicleCommand::SetSpeed {
+                speed_mm_per_sec: 0,
+                accel_mm_per_sec2: STOP_ACCEL_MM_PER_SEC2,
+            }),
+            stop.process_position_update(&position_update(5, 17))
+        );
+        assert!(stop.is_stopped());
+        assert_eq!(None, stop.process_position_update(&position_update(6, 17)));
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::lap::LapCounter;
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::race::leaderboard::FINISH_LINE_ROAD_PIECE_ID;
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::vehicle_id::VehicleId;
+
+// Sector 1 starts at the finish line, every boundary starts the next sector in track order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SectorLayout {
+    pub finish_road_piece_id: u8,
+    pub boundaries: Vec<u8>,
+}
+
+impl SectorLayout {
+    pub fn new(boundaries: Vec<u8>) -> SectorLayout {
+        SectorLayout {
+            finish_road_piece_id: FINISH_LINE_ROAD_PIECE_ID,
+            boundaries,
+        }
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.boundaries.len() + 1
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TimeTrialEvent {
+    SectorCompleted {
+        vehicle: VehicleId,
+        lap: u16,
+        // 0-based sector index.
+        sector: usize,
+        time: Duration,
+        // Cumulative lap time so far compared to the best lap at the same point.
+        delta_to_best_ms: Option<i64>,
+    },
+    LapCompleted {
+        vehicle: VehicleId,
+        lap: u16,
+        lap_time: Duration,
+        splits: Vec<Duration>,
+        delta_to_best_ms: Option<i64>,
+        personal_best: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct VehicleTimes {
+    lap_counter: LapCounter,
+    // Laps timed through every sector, unlike the counter this leaves out discarded laps.
+    laps: u16,
+    sector_started_at: Option<Instant>,
+    splits: Vec<Duration>,
+    best_lap: Option<(Duration, Vec<Duration>)>,
+    best_sectors: Vec<Option<Duration>>,
+}
+
+fn delta_ms(time: Duration, best: Duration) -> i64 {
+    time.as_millis() as i64 - best.as_millis() as i64
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeTrial {
+    layout: SectorLayout,
+    vehicles: HashMap<VehicleId, VehicleTimes>,
+}
+
+impl TimeTrial {
+    pub fn new(layout: SectorLayout) -> TimeTrial {
+        TimeTrial {
+            layout,
+            vehicles: HashMap::new(),
+        }
+    }
+
+    pub fn layout(&self) -> &SectorLayout {
+        &self.layout
+    }
+
+    pub fn process_position_update(
+        &mut self,
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        at: Instant,
+    ) -> Vec<TimeTrialEvent> {
+        let layout = &self.layout;
+        let times = self
+            .vehicles
+            .entry(vehicle.into())
+            .or_insert_with(|| VehicleTimes {
+                lap_counter: LapCounter::new()
+                    .with_finish_road_piece_id(layout.finish_road_piece_id),
+                laps: 0,
+                sector_started_at: None,
+                splits: Vec::new(),
+                best_lap: None,
+                best_sectors: vec![None; layout.sector_count()],
+            });
+        if times.lap_counter.road_piece_id() == Some(data.road_piece_id) {
+            return Vec::new();
+        }
+        let lap = times.lap_counter.process_position_update(data, at);
+
+        let mut events = Vec::new();
+        if data.road_piece_id == layout.finish_road_piece_id {
+            // Laps that skipped a boundary can't be split reliably and are discarded.
+            if let Some(lap) = lap.filter(|_| times.splits.len() + 1 == layout.sector_count()) {
+                events.push(Self::complete_sector(vehicle, times, at));
+                events.push(Self::complete_lap(vehicle, times, lap.lap_time));
+            }
+            times.sector_started_at = Some(at);
+            times.splits.clear();
+        } else if times.lap_counter.lap_started_at().is_some()
+            && layout.boundaries.get(times.splits.len()) == Some(&data.road_piece_id)
+        {
+            events.push(Self::complete_sector(vehicle, times, at));
+        }
+        events
+    }
+
+    fn complete_sector(vehicle: &str, times: &mut VehicleTimes, at: Instant) -> TimeTrialEvent {
+        let sector = times.splits.len();
+        let time = at.saturating_duration_since(times.sector_started_at.unwrap_or(at));
+        times.splits.push(time);
+        times.sector_started_at = Some(at);
+        if times.best_sectors[sector].is_none_or(|best| time < best) {
+            times.best_sectors[sector] = Some(time);
+        }
+
+        let delta_to_best_ms = times.best_lap.as_ref().map(|(_, best_splits)| {
+            let so_far: Duration = times.splits.iter().sum();
+            let best_so_far: Duration = best_splits[..=sector].iter().sum();
+            delta_ms(so_far, best_so_far)
+        });
+        TimeTrialEvent::SectorCompleted {
+            vehicle: vehicle.into(),
+            lap: times.laps + 1,
+            sector,
+            time,
+            delta_to_best_ms,
+        }
+    }
+
+    fn complete_lap(vehicle: &str, times: &mut VehicleTimes, lap_time: Duration) -> TimeTrialEvent {
+        times.laps += 1;
+        let delta_to_best_ms = times
+            .best_lap
+            .as_ref()
+            .map(|(best, _)| delta_ms(lap_time, *best));
+        let personal_best = delta_to_best_ms.is_none_or(|delta| delta < 0);
+        if personal_best {
+            times.best_lap = Some((lap_time, times.splits.clone()));
+        }
+        trace_event!(
+            target: TARGET_CONTROLLER,
+            debug,
+            vehicle,
+            lap = times.laps,
+            ?lap_time,
+            personal_best,
+            "Lap completed"
+        );
+        TimeTrialEvent::LapCompleted {
+            vehicle: vehicle.into(),
+            lap: times.laps,
+            lap_time,
+            splits: times.splits.clone(),
+            delta_to_best_ms,
+            personal_best,
+        }
+    }
+
+    pub fn laps(&self, vehicle: &str) -> u16 {
+        self.vehicles.get(vehicle).map_or(0, |t| t.laps)
+    }
+
+    pub fn best_lap(&self, vehicle: &str) -> Option<Duration> {
+        self.vehicles
+            .get(vehicle)?
+            .best_lap
+            .as_ref()
+            .map(|(t, _)| *t)
+    }
+
+    pub fn best_sectors(&self, vehicle: &str) -> Option<&[Option<Duration>]> {
+        self.vehicles
+            .get(vehicle)
+            .map(|t| t.best_sectors.as_slice())
+    }
+
+    // Sum of the best time in every sector, only available once each sector has been timed.
+    pub fn theoretical_best(&self, vehicle: &str) -> Option<Duration> {
+        self.best_sectors(vehicle)?.iter().copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+
+    fn drive(trial: &mut TimeTrial, t0: Instant, pieces: &[(u8, u64)]) -> Vec<TimeTrialEvent> {
+        pieces
+            .iter()
+            .flat_map(|(piece, ms)| {
+                trial.process_position_update(
+                    "a",
+                    &position_update(0, *piece),
+                    t0 + Duration::from_millis(*ms),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn time_trial_sector_splits_test() {
+        let t0 = Instant::now();
+        let mut trial = TimeTrial::new(SectorLayout::new(vec![20]));
+        let events = drive(
+            &mut trial,
+            t0,
+            &[(34, 0), (17, 500), (20, 2000), (18, 3000)],
+        );
+        assert_eq!(
+            vec![TimeTrialEvent::SectorCompleted {
+                vehicle: "a".into(),
+                lap: 1,
+                sector: 0,
+                time: Duration::from_millis(2000),
+                delta_to_best_ms: None,
+            }],
+            events
+        );
+
+        let events = drive(&mut trial, t0, &[(34, 5000)]);
+        assert_eq!(
+            TimeTrialEvent::LapCompleted {
+                vehicle: "a".into(),
+                lap: 1,
+                lap_time: Duration::from_millis(5000),
+                splits: vec![Duration::from_millis(2000), Duration::from_millis(3000)],
+                delta_to_best_ms: None,
+                personal_best: true,
+            },
+            events[1]
+        );
+
+        // Faster first sector, slower second: slower lap but a better theoretical best.
+        let events = drive(&mut trial, t0, &[(20, 6500), (34, 10500)]);
+        assert_eq!(
+            TimeTrialEvent::SectorCompleted {
+                vehicle: "a".into(),
+                lap: 2,
+                sector: 0,
+                time: Duration::from_millis(1500),
+                delta_to_best_ms: Some(-500),
+            },
+            events[0]
+        );
+        assert_eq!(
+            TimeTrialEvent::LapCompleted {
+                vehicle: "a".into(),
+                lap: 2,
+                lap_time: Duration::from_millis(5500),
+                splits: vec![Duration::from_millis(1500), Duration::from_millis(4000)],
+                delta_to_best_ms: Some(500),
+                personal_best: false,
+            },
+            events[2]
+        );
+        assert_eq!(Some(Duration::from_millis(5000)), trial.best_lap("a"));
+        assert_eq!(
+            Some(Duration::from_millis(4500)),
+            trial.theoretical_best("a")
+        );
+        assert_eq!(2, trial.laps("a"));
+    }
+
+    #[test]
+    fn time_trial_discards_incomplete_lap_test() {
+        let t0 = Instant::now();
+        let mut trial = TimeTrial::new(SectorLayout::new(vec![20, 23]));
+        let events = drive(
+            &mut trial,
+            t0,
+            &[(34, 0), (20, 1000), (18, 2000), (34, 3000)],
+        );
+        assert_eq!(1, events.len());
+        assert_eq!(0, trial.laps("a"));
+        assert_eq!(None, trial.theoretical_best("a"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::clock::{Clock, SystemClock};
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::replay::{ReplayError, ReplayRecord, ReplayWriter};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+
+// Wall clock time for the replay header.
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+struct Recording<W: Write> {
+    writer: ReplayWriter<W>,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+}
+
+impl<W: Write> Recording<W> {
+    fn write(&mut self, record: ReplayRecord) {
+        if let Err(_e) = self.writer.write(&record) {
+            trace_event!(target: TARGET_TRANSPORT, warn, error = %_e, vehicle = %record.vehicle, "Failed to record frame");
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.clock.now().duration_since(self.started).as_millis() as u64
+    }
+}
+
+// Taps a host, logging every command written and every notification received to the replay
+// format. Notifications are recorded before subscribers of the recording host see them, so
+// anything a subscriber has seen is in the log.
+pub struct RecordingHost<H, W: Write> {
+    host: Arc<H>,
+    recording: Arc<Mutex<Recording<W>>>,
+    subscribers: Arc<Mutex<Vec<Sender<HostNotification>>>>,
+}
+
+impl<H: FleetHost, W: Write + Send + 'static> RecordingHost<H, W> {
+    pub fn new(host: Arc<H>, writer: ReplayWriter<W>) -> RecordingHost<H, W> {
+        let recording = Arc::new(Mutex::new(Recording {
+            writer,
+            clock: Arc::new(SystemClock),
+            started: Instant::now(),
+        }));
+        let subscribers: Arc<Mutex<Vec<Sender<HostNotification>>>> = Arc::default();
+
+        let notifications = host.subscribe();
+        let tap = (Arc::clone(&recording), Arc::clone(&subscribers));
+        thread::spawn(move || {
+            let (recording, subscribers) = tap;
+            for notification in notifications {
+                {
+                    let mut recording = recording.lock().unwrap();
+                    let timestamp_ms = recording.elapsed_ms();
+                    recording.write(ReplayRecord::notification(
+                        timestamp_ms,
+                        &notification.vehicle,
+                        &notification.data,
+                    ));
+                }
+                subscribers
+                    .lock()
+                    .unwrap()
+                    .retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+            }
+        });
+
+        RecordingHost {
+            host,
+            recording,
+            subscribers,
+        }
+    }
+
+    // Timestamps are taken from this clock from now on, counting from zero again.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> RecordingHost<H, W> {
+        {
+            let mut recording = self.recording.lock().unwrap();
+            recording.started = clock.now();
+            recording.clock = clock;
+        }
+        self
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.host
+    }
+
+    pub fn flush(&self) -> Result<(), ReplayError> {
+        self.recording.lock().unwrap().writer.flush()
+    }
+}
+
+impl<H: FleetHost, W: Write + Send + 'static> FleetHost for RecordingHost<H, W> {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+        self.host.discover()
+    }
+
+    fn vehicles(&self) -> Vec<HostVehicle> {
+        self.host.vehicles()
+    }
+
+    fn connect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.connect(vehicle)
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.host.disconnect(vehicle)
+    }
+
+    // Only frames that made it to the vehicle are recorded.
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+        let record = {
+            let recording = self.recording.lock().unwrap();
+            ReplayRecord::command(recording.elapsed_ms(), vehicle, &data)
+        };
+        self.host.send(vehicle, data)?;
+        self.recording.lock().unwrap().write(record);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Receiver<HostNotification> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{ReplayDirection, ReplayPlayer, ReplayReader};
+    use crate::sim::host::SimulatedHost;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+
+    #[test]
+    fn recording_host_test() {
+        let path = env::temp_dir().join(format!("anki-recording-{}.replay", process::id()));
+        let sim =
+            Arc::new(SimulatedHost::new().with_vehicle("skull", "Skull", SimulatedVehicle::new()));
+        let writer = ReplayWriter::create(&path, unix_time_ms()).unwrap();
+        let host = RecordingHost::new(Arc::clone(&sim), writer);
+        let notifications = host.subscribe();
+
+        host.connect("skull").unwrap();
+        let mut vehicle = AnkiVehicleData::default();
+        for command in vehicle.configure() {
+            host.send("skull", command.into()).unwrap();
+        }
+        assert!(host
+            .send("nuke", AnkiVehicleData::set_speed(500, 0).into())
+            .is_err());
+        host.send("skull", AnkiVehicleData::set_speed(500, 0).into())
+            .unwrap();
+        sim.step(Duration::from_secs(1));
+
+        // Version and battery responses, then the drive.
+        let mut received = 0;
+        while let Ok(notification) = notifications.recv_timeout(Duration::from_millis(200)) {
+            vehicle.process_notification(&notification.data).unwrap();
+            received += 1;
+        }
+        host.flush().unwrap();
+
+        let reader = ReplayReader::open(&path).unwrap();
+        let records: Vec<ReplayRecord> = reader.map(Result::unwrap).collect();
+        let commands = records
+            .iter()
+            .filter(|record| record.direction == ReplayDirection::Command)
+            .count();
+        assert_eq!(6, commands);
+        assert_eq!(received, records.len() - commands);
+
+        let mut player = ReplayPlayer::new(ReplayReader::open(&path).unwrap()).with_speed(0.0);
+        player.play_to_end().unwrap();
+        let replayed = player.vehicle("skull").unwrap();
+        assert_eq!(vehicle.speed_mm_per_sec, replayed.speed_mm_per_sec);
+        assert_eq!(vehicle.battery_level, replayed.battery_level);
+        assert_eq!(vehicle.road_piece_idx, replayed.road_piece_idx);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use std::io::{Read, Write};
+
+use crate::error::AnkiError;
+use crate::json::JsonMessage;
+
+// Envelope for forwarding vehicle traffic between hosts, e.g. from the machine holding the BLE
+// connections to a race controller or a recorder. Frames can travel raw, exactly as they were on
+// the wire, or decoded so the receiver doesn't need the protocol layer. Bincode keeps a position
+// update at around 30 bytes and envelopes can be written back to back on a stream.
+
+// Decoding refuses anything claiming to be larger, a bad length can't exhaust memory.
+pub const RELAY_MAX_SIZE: usize = 64 * 1024;
+
+const CONFIG: bincode::config::Configuration<
+    bincode::config::LittleEndian,
+    bincode::config::Varint,
+    bincode::config::Limit<RELAY_MAX_SIZE>,
+> = bincode::config::standard().with_limit::<RELAY_MAX_SIZE>();
+
+#[derive(Debug, PartialEq, Clone, Copy, Encode, Decode)]
+pub enum RelayDirection {
+    // Written to the vehicle.
+    Command,
+    // Received from the vehicle.
+    Notification,
+}
+
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+pub enum RelayPayload {
+    Raw(Vec<u8>),
+    Decoded(JsonMessage),
+}
+
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+pub struct RelayEnvelope {
+    pub vehicle: String,
+    pub timestamp_ms: u64,
+    pub direction: RelayDirection,
+    pub payload: RelayPayload,
+}
+
+impl RelayEnvelope {
+    pub fn raw(
+        vehicle: &str,
+        timestamp_ms: u64,
+        direction: RelayDirection,
+        data: &[u8],
+    ) -> RelayEnvelope {
+        RelayEnvelope {
+            vehicle: vehicle.to_string(),
+            timestamp_ms,
+            direction,
+            payload: RelayPayload::Raw(data.to_vec()),
+        }
+    }
+
+    // Decodes a raw notification up front, so the receiver gets the message itself.
+    pub fn notification(
+        vehicle: &str,
+        timestamp_ms: u64,
+        data: &[u8],
+    ) -> Result<RelayEnvelope, AnkiError> {
+        Ok(RelayEnvelope {
+            vehicle: vehicle.to_string(),
+            timestamp_ms,
+            direction: RelayDirection::Notification,
+            payload: RelayPayload::Decoded(JsonMessage::from_bytes(data)?),
+        })
+    }
+
+    pub fn command(vehicle: &str, timestamp_ms: u64, msg: JsonMessage) -> RelayEnvelope {
+        RelayEnvelope {
+            vehicle: vehicle.to_string(),
+            timestamp_ms,
+            direction: RelayDirection::Command,
+            payload: RelayPayload::Decoded(msg),
+        }
+    }
+
+    // The frame as it is on the wire, encoding a decoded command if needed. Decoded
+    // notifications can't be turned back into frames.
+    pub fn frame(&self) -> Result<Vec<u8>, AnkiError> {
+        match &self.payload {
+            RelayPayload::Raw(data) => Ok(data.clone()),
+            RelayPayload::Decoded(msg) => msg.to_bytes(),
+        }
+    }
+
+    // The decoded message, decoding a raw notification if needed.
+    pub fn message(&self) -> Result<JsonMessage, AnkiError> {
+        match &self.payload {
+            RelayPayload::Raw(data) => JsonMessage::from_bytes(data),
+            RelayPayload::Decoded(msg) => Ok(msg.clone()),
+        }
+    }
+
+    pub fn to_bincode(&self) -> Result<Vec<u8>, EncodeError> {
+        bincode::encode_to_vec(self, CONFIG)
+    }
+
+    pub fn from_bincode(data: &[u8]) -> Result<RelayEnvelope, DecodeError> {
+        let (envelope, _) = bincode::decode_from_slice(data, CONFIG)?;
+        Ok(envelope)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, EncodeError> {
+        bincode::encode_into_std_write(self, writer, CONFIG)
+    }
+
+    // Reads the next envelope from a stream of back to back envelopes.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<RelayEnvelope, DecodeError> {
+        bincode::decode_from_std_read(reader, CONFIG)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{AnkiVehicleMsgType, LightChannel, LightEffect};
+    use crate::AnkiVehicleData;
+    use std::io::Cursor;
+
+    #[test]
+    fn relay_round_trip_test() {
+        let battery = [
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0x10,
+            0x0E,
+        ];
+        let envelopes = [
+            RelayEnvelope::raw(
+                "skull",
+                1234,
+                RelayDirection::Command,
+                &AnkiVehicleData::set_speed(500, 1000),
+            ),
+            RelayEnvelope::notification("skull", 1250, &battery).unwrap(),
+            RelayEnvelope::command(
+                "nuke",
+                1300,
+                JsonMessage::LightsPattern {
+                    channel: LightChannel::Red,
+                    effect: LightEffect::Throb,
+                    start: 0,
+                    end: 14,
+                    cycles_per_min: 60,
+                },
+            ),
+        ];
+
+        let mut stream = Vec::new();
+        for envelope in &envelopes {
+            envelope.write_to(&mut stream).unwrap();
+        }
+        let mut reader = Cursor::new(stream);
+        for envelope in &envelopes {
+            assert_eq!(*envelope, RelayEnvelope::read_from(&mut reader).unwrap());
+        }
+        assert!(RelayEnvelope::read_from(&mut reader).is_err());
+
+        let data = envelopes[1].to_bincode().unwrap();
+        assert!(data.len() < 16);
+        assert_eq!(envelopes[1], RelayEnvelope::from_bincode(&data).unwrap());
+    }
+
+    #[test]
+    fn relay_payload_test() {
+        let speed = AnkiVehicleData::set_speed(500, 1000);
+        let raw = RelayEnvelope::raw("skull", 0, RelayDirection::Command, &speed);
+        let decoded = RelayEnvelope::command(
+            "skull",
+            0,
+            JsonMessage::SetSpeed {
+                speed_mm_per_sec: 500,
+                accel_mm_per_sec2: 1000,
+            },
+        );
+        assert_eq!(speed, raw.frame().unwrap());
+        assert_eq!(speed, decoded.frame().unwrap());
+
+        let battery = [
+            3,
+            AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+            0x10,
+            0x0E,
+        ];
+        let raw = RelayEnvelope::raw("skull", 0, RelayDirection::Notification, &battery);
+        assert_eq!(
+            JsonMessage::BatteryLevelResponse {
+                battery_level: 0x0E10
+            },
+            raw.message().unwrap()
+        );
+        assert!(RelayEnvelope::notification("skull", 0, &[]).is_err());
+
+        // A length prefix far beyond the limit is rejected before anything is allocated.
+        assert!(RelayEnvelope::from_bincode(&[0xfc, 0xff, 0xff, 0xff, 0x7f]).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::AnkiError;
+use crate::protocol::AnkiVehicleMsgType;
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Binary log of raw vehicle traffic, the common format for recording sessions, replaying them
+// and sharing them between tools. All integers are little endian.
+//
+// Header, 20 bytes:
+//   0  magic            b"ANKIRPLY"
+//   8  version          u16, REPLAY_VERSION
+//  10  flags            u16, reserved and written as zero
+//  12  started_unix_ms  u64, wall clock time the recording started
+//
+// Followed by records back to back until the end of the file:
+//   0  timestamp_ms     u64, milliseconds since started_unix_ms
+//   8  direction        u8, 0 command written to the vehicle, 1 notification received from it
+//   9  vehicle_len      u8
+//  10  vehicle          vehicle_len bytes of UTF-8, usually the BLE address
+//   .  frame_len        u8
+//   .  frame            frame_len bytes, the frame exactly as it was on the wire
+//
+// Frames are stored as they were, even malformed ones, so a log can reproduce parser bugs. A
+// reader refuses versions newer than its own, new fields mean a new version.
+
+pub const REPLAY_MAGIC: [u8; 8] = *b"ANKIRPLY";
+pub const REPLAY_VERSION: u16 = 1;
+pub const REPLAY_HEADER_SIZE: usize = 20;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "Replay I/O error: {}", e),
+            ReplayError::BadMagic => write!(f, "Not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported replay version {}", version)
+            }
+            ReplayError::Truncated => write!(f, "Replay ends in the middle of a record"),
+            ReplayError::Invalid(e) => write!(f, "Invalid replay record: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            ReplayError::Truncated
+        } else {
+            ReplayError::Io(e)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ReplayDirection {
+    Command = 0,
+    Notification = 1,
+}
+
+impl TryFrom<u8> for ReplayDirection {
+    type Error = ReplayError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ReplayDirection::Command),
+            1 => Ok(ReplayDirection::Notification),
+            _ => Err(ReplayError::Invalid(format!("unknown direction {}", value))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReplayHeader {
+    pub version: u16,
+    pub started_unix_ms: u64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplayRecord {
+    pub timestamp_ms: u64,
+    pub direction: ReplayDirection,
+    pub vehicle: VehicleId,
+    pub frame: Vec<u8>,
+}
+
+impl ReplayRecord {
+    pub fn command(timestamp_ms: u64, vehicle: &str, frame: &[u8]) -> ReplayRecord {
+        ReplayRecord {
+            timestamp_ms,
+            direction: ReplayDirection::Command,
+            vehicle: vehicle.into(),
+            frame: frame.to_vec(),
+        }
+    }
+
+    pub fn notification(timestamp_ms: u64, vehicle: &str, frame: &[u8]) -> ReplayRecord {
+        ReplayRecord {
+            timestamp_ms,
+            direction: ReplayDirection::Notification,
+            vehicle: vehicle.into(),
+            frame: frame.to_vec(),
+        }
+    }
+}
+
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+    records: usize,
+}
+
+impl ReplayWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        started_unix_ms: u64,
+    ) -> Result<ReplayWriter<BufWriter<File>>, ReplayError> {
+        ReplayWriter::new(BufWriter::new(File::create(path)?), started_unix_ms)
+    }
+}
+
+impl<W: Write> ReplayWriter<W> {
+    // Writes the header straight away, an empty recording is still a valid file.
+    pub fn new(mut writer: W, started_unix_ms: u64) -> Result<ReplayWriter<W>, ReplayError> {
+        writer.write_all(&REPLAY_MAGIC)?;
+        writer.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        writer.write_all(&0u16.to_le_bytes())?;
+        writer.write_all(&started_unix_ms.to_le_bytes())?;
+        Ok(ReplayWriter { writer, records: 0 })
+    }
+
+    pub fn write(&mut self, record: &ReplayRecord) -> Result<(), ReplayError> {
+        let vehicle = record.vehicle.as_bytes();
+        if vehicle.len() > u8::MAX as usize {
+            return Err(ReplayError::Invalid(format!(
+                "vehicle id is {} bytes long",
+                vehicle.len()
+            )));
+        }
+        if record.frame.len() > u8::MAX as usize {
+            return Err(ReplayError::Invalid(format!(
+                "frame is {} bytes long",
+                record.frame.len()
+            )));
+        }
+        // Built in one buffer so a failed write can't leave half a record behind in the writer.
+        let mut data = Vec::with_capacity(11 + vehicle.len() + record.frame.len());
+        data.extend_from_slice(&record.timestamp_ms.to_le_bytes());
+        data.push(record.direction as u8);
+        data.push(vehicle.len() as u8);
+        data.extend_from_slice(vehicle);
+        data.push(record.frame.len() as u8);
+        data.extend_from_slice(&record.frame);
+        self.writer.write_all(&data)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    pub fn flush(&mut self) -> Result<(), ReplayError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> Result<W, ReplayError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub struct ReplayReader<R: Read> {
+    reader: R,
+    header: ReplayHeader,
+}
+
+impl ReplayReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ReplayReader<BufReader<File>>, ReplayError> {
+        ReplayReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> ReplayReader<R> {
+    pub fn new(mut reader: R) -> Result<ReplayReader<R>, ReplayError> {
+        let mut header = [0u8; REPLAY_HEADER_SIZE];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => ReplayError::BadMagic,
+            _ => ReplayError::Io(e),
+        })?;
+        if header[..8] != REPLAY_MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version == 0 || version > REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let mut started = [0u8; 8];
+        started.copy_from_slice(&header[12..20]);
+        Ok(ReplayReader {
+            reader,
+            header: ReplayHeader {
+                version,
+                started_unix_ms: u64::from_le_bytes(started),
+            },
+        })
+    }
+
+    pub fn header(&self) -> ReplayHeader {
+        self.header
+    }
+
+    // None at the end of the file, a record cut short by a crashed recorder is an error.
+    pub fn read_record(&mut self) -> Result<Option<ReplayRecord>, ReplayError> {
+        let mut timestamp = [0u8; 8];
+        let mut read = 0;
+        while read < timestamp.len() {
+            match self.reader.read(&mut timestamp[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(ReplayError::Truncated),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let direction = ReplayDirection::try_from(self.read_u8()?)?;
+        let vehicle = self.read_bytes()?;
+        let vehicle = String::from_utf8(vehicle)
+            .map_err(|_| ReplayError::Invalid("vehicle id is not UTF-8".to_string()))?;
+        let frame = self.read_bytes()?;
+        Ok(Some(ReplayRecord {
+            timestamp_ms: u64::from_le_bytes(timestamp),
+            direction,
+            vehicle: vehicle.into(),
+            frame,
+        }))
+    }
+
+    fn read_u8(&mut self) -> Result<u8, ReplayError> {
+        let mut value = [0u8; 1];
+        self.reader.read_exact(&mut value)?;
+        Ok(value[0])
+    }
+
+    fn read_bytes(&mut self) -> Result<Vec<u8>, ReplayError> {
+        let mut data = vec![0u8; self.read_u8()? as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl<R: Read> Iterator for ReplayReader<R> {
+    type Item = Result<ReplayRecord, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// A record played back, with what became of it. Notifications are decoded into the vehicle's
+// state, commands are only passed along.
+#[derive(Debug)]
+pub struct ReplayedRecord {
+    pub record: ReplayRecord,
+    pub decoded: Option<Result<AnkiVehicleMsgType, AnkiError>>,
+}
+
+// Plays a recording back through the same state handling live notifications go through, keeping
+// the gaps between records. A speed of 2.0 plays twice as fast, 0.0 or infinity as fast as the
+// records can be read.
+pub struct ReplayPlayer<R: Read> {
+    reader: ReplayReader<R>,
+    speed: f64,
+    clock: Arc<dyn Clock>,
+    started: Option<(Instant, u64)>,
+    vehicles: HashMap<VehicleId, AnkiVehicleData>,
+}
+
+impl<R: Read> ReplayPlayer<R> {
+    pub fn new(reader: ReplayReader<R>) -> ReplayPlayer<R> {
+        ReplayPlayer {
+            reader,
+            speed: 1.0,
+            clock: Arc::new(SystemClock),
+            started: None,
+            vehicles: HashMap::new(),
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> ReplayPlayer<R> {
+        self.speed = speed;
+        self
+    }
+
+    // The gaps between records are waited out on this clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> ReplayPlayer<R> {
+        self.clock = clock;
+        self
+    }
+
+    pub fn header(&self) -> ReplayHeader {
+        self.reader.header()
+    }
+
+    pub fn vehicle(&self, vehicle: &str) -> Option<&AnkiVehicleData> {
+        self.vehicles.get(vehicle)
+    }
+
+    pub fn vehicles(&self) -> &HashMap<VehicleId, AnkiVehicleData> {
+        &self.vehicles
+    }
+
+    // Waits until the next record is due, then applies it. None at the end of the recording.
+    pub fn next_record(&mut self) -> Result<Option<ReplayedRecord>, ReplayError> {
+        let Some(record) = self.reader.read_record()? else {
+            return Ok(None);
+        };
+        self.wait_for(record.timestamp_ms)?;
+
+        let decoded = match record.direction {
+            ReplayDirection::Command => None,
+            ReplayDirection::Notification => {
+                let vehicle = self
+                    .vehicles
+                    .entry(record.vehicle.clone())
+                    .or_insert_with(|| {
+                        let mut vehicle = AnkiVehicleData::default();
+                        vehicle.set_name(record.vehicle.to_string());
+                        vehicle
+                    });
+                Some(vehicle.process_notification(&record.frame))
+            }
+        };
+        Ok(Some(ReplayedRecord { record, decoded }))
+    }
+
+    // Plays the rest of the recording, returning the number of records played.
+    pub fn play_to_end(&mut self) -> Result<usize, ReplayError> {
+        let mut played = 0;
+        while self.next_record()?.is_some() {
+            played += 1;
+        }
+        Ok(played)
+    }
+
+    fn wait_for(&mut self, timestamp_ms: u64) -> Result<(), ReplayError> {
+        let (started, first_ms) = *self
+            .started
+            .get_or_insert_with(|| (self.clock.now(), timestamp_ms));
+        let due = replay_due(started, first_ms, timestamp_ms, self.speed)?;
+        let now = self.clock.now();
+        if due > now {
+            self.clock.sleep(due - now);
+        }
+        Ok(())
+    }
+}
+
+// When a record is due in a replay at `speed` whose first record, stamped `first_ms`, played at
+// `started`. A speed of 0.0 or infinity makes everything due at once. Timestamps too far out to
+// wait for, from a corrupt log or a tiny speed, are an error rather than a panic.
+pub(crate) fn replay_due(
+    started: Instant,
+    first_ms: u64,
+    timestamp_ms: u64,
+    speed: f64,
+) -> Result<Instant, ReplayError> {
+    if speed <= 0.0 || !speed.is_finite() {
+        return Ok(started);
+    }
+    let offset_ms = timestamp_ms.saturating_sub(first_ms) as f64 / speed;
+    Duration::try_from_secs_f64(offset_ms / 1000.0)
+        .ok()
+        .and_then(|offset| started.checked_add(offset))
+        .ok_or_else(|| {
+            ReplayError::Invalid(format!(
+                "timestamp {} ms is out of reach at speed {}",
+                timestamp_ms, speed
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::protocol::AnkiVehicleMsgType;
+    use crate::AnkiVehicleData;
+    use std::io::Cursor;
+
+    fn records() -> Vec<ReplayRecord> {
+        vec![
+            ReplayRecord::command(0, "skull", &AnkiVehicleData::set_speed(500, 1000)),
+            ReplayRecord::notification(
+                16,
+                "skull",
+                &[
+                    3,
+                    AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+                    0x10,
+                    0x0E,
+                ],
+            ),
+            ReplayRecord::notification(20, "nuke", &[]),
+        ]
+    }
+
+    #[test]
+    fn replay_round_trip_test() {
+        let mut writer = ReplayWriter::new(Vec::new(), 1_700_000_000_000).unwrap();
+        for record in &records() {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(3, writer.records());
+        let data = writer.into_inner().unwrap();
+        assert_eq!(b"ANKIRPLY\x01\x00\x00\x00", &data[..12]);
+
+        let reader = ReplayReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(
+            ReplayHeader {
+                version: REPLAY_VERSION,
+                started_unix_ms: 1_700_000_000_000
+            },
+            reader.header()
+        );
+        let read: Vec<ReplayRecord> = reader.map(Result::unwrap).collect();
+        assert_eq!(records(), read);
+    }
+
+    #[test]
+    fn replay_invalid_test() {
+        assert!(matches!(
+            ReplayReader::new(Cursor::new(b"ANKI".to_vec())),
+            Err(ReplayError::BadMagic)
+        ));
+
+        let mut data = ReplayWriter::new(Vec::new(), 0)
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        data[8] = 2;
+        assert!(matches!(
+            ReplayReader::new(Cursor::new(data)),
+            Err(ReplayError::UnsupportedVersion(2))
+        ));
+
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        writer.write(&records()[0]).unwrap();
+        let mut data = writer.into_inner().unwrap();
+        data.pop();
+        let mut reader = ReplayReader::new(Cursor::new(data)).unwrap();
+        assert!(matches!(reader.read_record(), Err(ReplayError::Truncated)));
+
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        let long = ReplayRecord::command(0, &"x".repeat(256), &[]);
+        assert!(matches!(writer.write(&long), Err(ReplayError::Invalid(_))));
+        assert_eq!(0, writer.records());
+    }
+
+    #[test]
+    fn replay_player_test() {
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        for record in &records() {
+            writer.write(record).unwrap();
+        }
+        writer
+            .write(&ReplayRecord::notification(500, "nuke", &[0xff]))
+            .unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let reader = ReplayReader::new(Cursor::new(data.clone())).unwrap();
+        let mut player = ReplayPlayer::new(reader).with_speed(f64::INFINITY);
+        let command = player.next_record().unwrap().unwrap();
+        assert!(command.decoded.is_none());
+        assert!(player.vehicles().is_empty());
+        let battery = player.next_record().unwrap().unwrap();
+        assert_eq!(
+            AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            battery.decoded.unwrap().unwrap()
+        );
+        assert_eq!(0x0E10, player.vehicle("skull").unwrap().battery_level);
+        assert_eq!(2, player.play_to_end().unwrap());
+        assert_eq!("nuke", player.vehicle("nuke").unwrap().name);
+
+        // 500 ms of recording at 50 times the speed.
+        let reader = ReplayReader::new(Cursor::new(data.clone())).unwrap();
+        let mut player = ReplayPlayer::new(reader).with_speed(50.0);
+        let start = Instant::now();
+        assert_eq!(4, player.play_to_end().unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        let clock = Arc::new(VirtualClock::new());
+        let reader = ReplayReader::new(Cursor::new(data.clone())).unwrap();
+        let mut player = ReplayPlayer::new(reader).with_clock(clock.clone());
+        assert_eq!(4, player.play_to_end().unwrap());
+        assert_eq!(Duration::from_millis(500), clock.elapsed());
+
+        // At this speed the gaps are longer than any clock can wait.
+        let reader = ReplayReader::new(Cursor::new(data)).unwrap();
+        let mut player = ReplayPlayer::new(reader)
+            .with_speed(1e-300)
+            .with_clock(clock);
+        player.next_record().unwrap();
+        assert!(matches!(player.next_record(), Err(ReplayError::Invalid(_))));
+    }
+}
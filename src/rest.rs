@@ -0,0 +1,571 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::host::{FleetHost, HostError, HostVehicle};
+use crate::json::JsonMessage;
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Largest request body the server reads, bigger ones are answered with 413.
+pub const REST_MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum RestError {
+    Bind(String),
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestError::Bind(e) => write!(f, "Failed to start REST server: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RestError {}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RestVehicle {
+    pub id: String,
+    pub name: String,
+    pub connected: bool,
+}
+
+impl From<HostVehicle> for RestVehicle {
+    fn from(vehicle: HostVehicle) -> Self {
+        RestVehicle {
+            id: vehicle.id.into(),
+            name: vehicle.name,
+            connected: vehicle.connected,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RestVehicleState {
+    pub vehicle: String,
+    pub version: u16,
+    pub battery_level: u16,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub offset_from_road_centre_mm: f32,
+    pub speed_mm_per_sec: u16,
+}
+
+impl RestVehicleState {
+    pub fn from_vehicle(vehicle: &str, data: &AnkiVehicleData) -> RestVehicleState {
+        RestVehicleState {
+            vehicle: vehicle.to_string(),
+            version: data.version,
+            battery_level: data.battery_level,
+            location_id: data.location_id,
+            road_piece_idx: data.road_piece_idx,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RestResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl RestResponse {
+    fn json<T: Serialize>(value: &T) -> RestResponse {
+        RestResponse {
+            status: 200,
+            body: serde_json::to_string(value).expect("Failed to serialize REST response as JSON"),
+        }
+    }
+
+    fn ok() -> RestResponse {
+        RestResponse {
+            status: 200,
+            body: "{}".to_string(),
+        }
+    }
+
+    fn error(status: u16, message: impl fmt::Display) -> RestResponse {
+        RestResponse {
+            status,
+            body: serde_json::json!({ "error": message.to_string() }).to_string(),
+        }
+    }
+}
+
+impl From<HostError> for RestResponse {
+    fn from(e: HostError) -> Self {
+        match e {
+            HostError::UnknownVehicle(_) => RestResponse::error(404, e),
+            HostError::NotConnected(_) => RestResponse::error(409, e),
+            HostError::Transport(_) => RestResponse::error(502, e),
+        }
+    }
+}
+
+// Routes, all bodies are JSON:
+//   GET  /vehicles                   every vehicle known to the host
+//   POST /vehicles/discover          scan for vehicles, returns the updated list
+//   GET  /vehicles/state             latest state of every vehicle that has reported in
+//   GET  /vehicles/<id>/state        latest state of one vehicle
+//   POST /vehicles/<id>/connect
+//   POST /vehicles/<id>/disconnect
+//   POST /vehicles/<id>/command      a command, see `JsonMessage`
+//
+// Vehicle state is rebuilt from the notifications the host forwards, so it starts out empty
+// until the vehicles begin reporting.
+pub struct RestApi<H: FleetHost> {
+    host: Arc<H>,
+    states: Arc<Mutex<HashMap<VehicleId, AnkiVehicleData>>>,
+}
+
+impl<H: FleetHost> RestApi<H> {
+    pub fn new(host: Arc<H>) -> RestApi<H> {
+        let states = Arc::new(Mutex::new(HashMap::new()));
+
+        let notifications = host.subscribe();
+        let tracked_states = Arc::clone(&states);
+        thread::spawn(move || {
+            for notification in notifications {
+                let mut states = tracked_states.lock().unwrap();
+                let state = states
+                    .entry(notification.vehicle)
+                    .or_insert_with(AnkiVehicleData::default);
+                // A malformed notification shouldn't take down the whole API.
+                let _ = state.process_notification(&notification.data);
+            }
+        });
+
+        RestApi { host, states }
+    }
+
+    pub fn state(&self, vehicle: &str) -> Option<RestVehicleState> {
+        self.states
+            .lock()
+            .unwrap()
+            .get(vehicle)
+            .map(|data| RestVehicleState::from_vehicle(vehicle, data))
+    }
+
+    pub fn states(&self) -> Vec<RestVehicleState> {
+        let mut states: Vec<RestVehicleState> = self
+            .states
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(vehicle, data)| RestVehicleState::from_vehicle(vehicle, data))
+            .collect();
+        states.sort_by(|a, b| a.vehicle.cmp(&b.vehicle));
+        states
+    }
+
+    // `content_type` is the request's Content-Type header, commands are only taken as JSON.
+    pub fn handle(
+        &self,
+        method: &Method,
+        url: &str,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> RestResponse {
+        let path = url.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let result = match (method, segments.as_slice()) {
+            (Method::Get, ["vehicles"]) => Ok(RestResponse::json(&self.vehicles())),
+            (Method::Post, ["vehicles", "discover"]) => self
+                .host
+                .discover()
+                .map(|vehicles| RestResponse::json(&rest_vehicles(vehicles))),
+            (Method::Get, ["vehicles", "state"]) => Ok(RestResponse::json(&self.states())),
+            (Method::Get, ["vehicles", vehicle, "state"]) => self.vehicle_state(vehicle),
+            (Method::Post, ["vehicles", vehicle, "connect"]) => {
+                self.host.connect(vehicle).map(|_| RestResponse::ok())
+            }
+            (Method::Post, ["vehicles", vehicle, "disconnect"]) => {
+                self.host.disconnect(vehicle).map(|_| RestResponse::ok())
+            }
+            (Method::Post, ["vehicles", vehicle, "command"]) => {
+                return self.command(vehicle, content_type, body);
+            }
+            (_, ["vehicles"])
+            | (_, ["vehicles", "discover" | "state"])
+            | (_, ["vehicles", _, "state" | "connect" | "disconnect" | "command"]) => {
+                return RestResponse::error(405, "Method not allowed");
+            }
+            _ => return RestResponse::error(404, "Not found"),
+        };
+        result.unwrap_or_else(RestResponse::from)
+    }
+
+    fn vehicles(&self) -> Vec<RestVehicle> {
+        rest_vehicles(self.host.vehicles())
+    }
+
+    // Vehicles the host knows about but that haven't reported anything yet get a blank state.
+    fn vehicle_state(&self, vehicle: &str) -> Result<RestResponse, HostError> {
+        if let Some(state) = self.state(vehicle) {
+            return Ok(RestResponse::json(&state));
+        }
+        if self.host.vehicles().iter().any(|v| v.id == vehicle) {
+            return Ok(RestResponse::json(&RestVehicleState::from_vehicle(
+                vehicle,
+                &AnkiVehicleData::default(),
+            )));
+        }
+        Err(HostError::UnknownVehicle(vehicle.to_string()))
+    }
+
+    fn command(&self, vehicle: &str, content_type: Option<&str>, body: &[u8]) -> RestResponse {
+        if !content_type.is_some_and(is_json) {
+            return RestResponse::error(415, "Commands must be sent as application/json");
+        }
+        let msg: JsonMessage = match serde_json::from_slice(body) {
+            Ok(msg) => msg,
+            Err(e) => return RestResponse::error(400, format!("Invalid JSON message: {}", e)),
+        };
+        let data = match msg.to_bytes() {
+            Ok(data) => data,
+            Err(e) => return RestResponse::error(400, format!("Invalid vehicle message: {}", e)),
+        };
+        match self.host.send(vehicle, data) {
+            Ok(()) => RestResponse::ok(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+fn rest_vehicles(vehicles: Vec<HostVehicle>) -> Vec<RestVehicle> {
+    vehicles.into_iter().map(Into::into).collect()
+}
+
+// Parameters such as `charset` don't matter, only the media type itself.
+fn is_json(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"))
+}
+
+// Small blocking HTTP server in front of a `RestApi`, meant for a web UI on the local network.
+// Requests are served one at a time on the thread calling `run`. Only same-origin pages may call
+// it unless other origins are allowed with `with_allowed_origins`.
+pub struct RestServer<H: FleetHost> {
+    server: Server,
+    api: RestApi<H>,
+    allowed_origins: Vec<String>,
+}
+
+impl<H: FleetHost> RestServer<H> {
+    pub fn bind<A: ToSocketAddrs>(addr: A, host: Arc<H>) -> Result<RestServer<H>, RestError> {
+        let server = Server::http(addr).map_err(|e| RestError::Bind(e.to_string()))?;
+        Ok(RestServer {
+            server,
+            api: RestApi::new(host),
+            allowed_origins: Vec::new(),
+        })
+    }
+
+    // Origins such as `http://dashboard.local:8080` whose pages may call the API across origins,
+    // `*` allows any of them.
+    pub fn with_allowed_origins<I, S>(mut self, origins: I) -> RestServer<H>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_origins = origins.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn api(&self) -> &RestApi<H> {
+        &self.api
+    }
+
+    // Serves requests until the server is dropped.
+    pub fn run(&self) {
+        for mut request in self.server.incoming_requests() {
+            let origin = header_value(&request, "Origin").and_then(|o| self.allowed_origin(&o));
+            let response = if *request.method() == Method::Options {
+                // CORS preflight, the browser asks before sending a JSON command across origins.
+                RestResponse {
+                    status: 204,
+                    body: String::new(),
+                }
+            } else {
+                let content_type = header_value(&request, "Content-Type");
+                let mut body = Vec::new();
+                match request
+                    .as_reader()
+                    .take(REST_MAX_BODY_SIZE as u64 + 1)
+                    .read_to_end(&mut body)
+                {
+                    Ok(_) if body.len() > REST_MAX_BODY_SIZE => {
+                        RestResponse::error(413, "Request body too large")
+                    }
+                    Ok(_) => self.api.handle(
+                        request.method(),
+                        request.url(),
+                        content_type.as_deref(),
+                        &body,
+                    ),
+                    Err(e) => RestResponse::error(400, e),
+                }
+            };
+            let mut response = Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(header("Content-Type", "application/json"))
+                .with_header(header("Vary", "Origin"));
+            if let Some(origin) = origin {
+                response = response
+                    .with_header(header("Access-Control-Allow-Origin", origin))
+                    .with_header(header("Access-Control-Allow-Methods", "GET, POST"))
+                    .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+            }
+            // The client may already be gone, nothing left to do for it.
+            let _ = request.respond(response);
+        }
+    }
+
+    fn allowed_origin(&self, origin: &str) -> Option<&str> {
+        self.allowed_origins
+            .iter()
+            .find(|allowed| *allowed == "*" || *allowed == origin)
+            .map(String::as_str)
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("Invalid HTTP header")
+}
+
+fn header_value(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostNotification;
+    use crate::protocol::AnkiVehicleMsgType;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct TestHost {
+        sent: Mutex<Vec<(String, Vec<u8>)>>,
+        subscribers: Mutex<Vec<Sender<HostNotification>>>,
+    }
+
+    impl FleetHost for TestHost {
+        fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+            Ok(self.vehicles())
+        }
+
+        fn vehicles(&self) -> Vec<HostVehicle> {
+            vec![HostVehicle {
+                id: "skull".into(),
+                name: "Skull".to_string(),
+                connected: true,
+            }]
+        }
+
+        fn connect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn disconnect(&self, _vehicle: &str) -> Result<(), HostError> {
+            Ok(())
+        }
+
+        fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+            if vehicle != "skull" {
+                return Err(HostError::UnknownVehicle(vehicle.to_string()));
+            }
+            self.sent.lock().unwrap().push((vehicle.to_string(), data));
+            Ok(())
+        }
+
+        fn subscribe(&self) -> Receiver<HostNotification> {
+            let (tx, rx) = channel();
+            self.subscribers.lock().unwrap().push(tx);
+            rx
+        }
+    }
+
+    #[test]
+    fn rest_command_test() {
+        let host = Arc::new(TestHost::default());
+        let api = RestApi::new(Arc::clone(&host));
+        let speed = br#"{"msg_type":"set_speed","speed_mm_per_sec":500,"accel_mm_per_sec2":1000}"#;
+        let json = Some("application/json; charset=utf-8");
+
+        assert_eq!(
+            RestResponse::ok(),
+            api.handle(&Method::Post, "/vehicles/skull/command", json, speed)
+        );
+        assert_eq!(
+            404,
+            api.handle(&Method::Post, "/vehicles/nuke/command", json, speed)
+                .status
+        );
+        assert_eq!(
+            400,
+            api.handle(&Method::Post, "/vehicles/skull/command", json, b"speed")
+                .status
+        );
+        assert_eq!(
+            400,
+            api.handle(
+                &Method::Post,
+                "/vehicles/skull/command",
+                json,
+                br#"{"msg_type":"ping_response"}"#
+            )
+            .status
+        );
+        assert_eq!(
+            405,
+            api.handle(&Method::Get, "/vehicles/skull/command", None, b"")
+                .status
+        );
+        assert_eq!(
+            415,
+            api.handle(&Method::Post, "/vehicles/skull/command", None, speed)
+                .status
+        );
+        assert_eq!(
+            415,
+            api.handle(
+                &Method::Post,
+                "/vehicles/skull/command",
+                Some("text/plain"),
+                speed
+            )
+            .status
+        );
+        assert_eq!(404, api.handle(&Method::Get, "/tracks", None, b"").status);
+        assert_eq!(
+            vec![(
+                "skull".to_string(),
+                AnkiVehicleData::set_speed(500, 1000).to_vec()
+            )],
+            *host.sent.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rest_vehicle_state_test() {
+        let host = Arc::new(TestHost::default());
+        let api = RestApi::new(Arc::clone(&host));
+        assert_eq!(
+            r#"{"vehicle":"skull","version":0,"battery_level":0,"location_id":0,"road_piece_idx":0,"offset_from_road_centre_mm":0.0,"speed_mm_per_sec":0}"#,
+            api.handle(&Method::Get, "/vehicles/skull/state", None, b"")
+                .body
+        );
+        assert_eq!(
+            404,
+            api.handle(&Method::Get, "/vehicles/nuke/state", None, b"")
+                .status
+        );
+
+        for subscriber in host.subscribers.lock().unwrap().iter() {
+            subscriber
+                .send(HostNotification {
+                    vehicle: "skull".into(),
+                    data: vec![
+                        3,
+                        AnkiVehicleMsgType::V2CBatteryLevelResponse as u8,
+                        0x10,
+                        0x0E,
+                    ],
+                })
+                .unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while api.state("skull").is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(0x0E10, api.state("skull").unwrap().battery_level);
+        assert_eq!(1, api.states().len());
+    }
+
+    fn http(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        // The server may answer before reading all of an oversized body and reset the rest.
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    #[test]
+    fn rest_server_test() {
+        let server = RestServer::bind("127.0.0.1:0", Arc::new(TestHost::default()))
+            .unwrap()
+            .with_allowed_origins(["http://dashboard.local"]);
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let response = http(
+            addr,
+            &format!(
+                "GET /vehicles HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                addr
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+        assert!(response.ends_with(r#"[{"id":"skull","name":"Skull","connected":true}]"#));
+
+        let response = http(
+            addr,
+            &format!(
+                "GET /vehicles HTTP/1.1\r\nHost: {}\r\nOrigin: http://evil.example\r\n\
+                 Connection: close\r\n\r\n",
+                addr
+            ),
+        );
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+
+        let response = http(
+            addr,
+            &format!(
+                "OPTIONS /vehicles/skull/command HTTP/1.1\r\nHost: {}\r\n\
+                 Origin: http://dashboard.local\r\nConnection: close\r\n\r\n",
+                addr
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 204"));
+        assert!(response.contains("Access-Control-Allow-Origin: http://dashboard.local"));
+
+        let body = "x".repeat(REST_MAX_BODY_SIZE + 1);
+        let response = http(
+            addr,
+            &format!(
+                "POST /vehicles/skull/command HTTP/1.1\r\nHost: {}\r\n\
+                 Content-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                addr,
+                body.len(),
+                body
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 413"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::race::leaderboard::FINISH_LINE_ROAD_PIECE_ID;
+
+// What a road piece is. Every piece of a type carries the same id, shapes that came in more than
+// one print run have an id for each.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoadPieceType {
+    Straight,
+    Curve,
+    Start,
+    Finish,
+    Intersection,
+    // The jump ramp and its landing piece.
+    Jump,
+    // Ids of pieces this doesn't know, or that the vehicle misread.
+    Unknown,
+}
+
+impl RoadPieceType {
+    // Length along the road centre, curves are shorter on the inside lanes and longer on the
+    // outside. Pieces this doesn't know are taken as a straight.
+    pub fn nominal_length_mm(&self) -> f32 {
+        match self {
+            RoadPieceType::Curve => 440.0,
+            RoadPieceType::Start => 220.0,
+            RoadPieceType::Finish => 340.0,
+            RoadPieceType::Straight
+            | RoadPieceType::Intersection
+            | RoadPieceType::Jump
+            | RoadPieceType::Unknown => 560.0,
+        }
+    }
+}
+
+// The pieces of the Drive and Overdrive kits and expansions.
+pub fn road_piece_type(road_piece_id: u8) -> RoadPieceType {
+    match road_piece_id {
+        36 | 39 | 40 | 48 | 51 => RoadPieceType::Straight,
+        17 | 18 | 20 | 23 | 24 | 27 => RoadPieceType::Curve,
+        33 => RoadPieceType::Start,
+        FINISH_LINE_ROAD_PIECE_ID => RoadPieceType::Finish,
+        10 => RoadPieceType::Intersection,
+        46 | 58 => RoadPieceType::Jump,
+        _ => RoadPieceType::Unknown,
+    }
+}
+
+impl AnkiVehicleMsgLocalisationPositionUpdate {
+    pub fn road_piece_type(&self) -> RoadPieceType {
+        road_piece_type(self.road_piece_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::track::TrackLayout;
+
+    #[test]
+    fn road_piece_type_test() {
+        let oval: Vec<RoadPieceType> = TrackLayout::oval()
+            .pieces()
+            .iter()
+            .map(|piece| road_piece_type(piece.road_piece_id))
+            .collect();
+        assert_eq!(RoadPieceType::Finish, oval[0]);
+        assert_eq!(RoadPieceType::Start, oval[1]);
+        assert_eq!(
+            4,
+            oval.iter()
+                .filter(|&&kind| kind == RoadPieceType::Curve)
+                .count()
+        );
+        assert_eq!(RoadPieceType::Jump, road_piece_type(58));
+        assert_eq!(RoadPieceType::Unknown, road_piece_type(0xff));
+    }
+}
@@ -0,0 +1,298 @@
+use std::iter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::command::VehicleCommand;
+use crate::protocol::{
+    AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgLocalisationTransitionUpdate,
+};
+
+// Mirrors of the std_msgs/geometry_msgs/nav_msgs types with the same field names, so they can
+// be copied into rclrs/r2r messages or serialized for rosbridge without depending on ROS here.
+//
+// Vehicles don't know where they are in the room, only how far they've driven and how far they
+// are from the road centre. Poses are therefore reported in a track-following frame: x is the
+// distance driven along the track, y the offset from the road centre (positive left, REP-103)
+// and the orientation always points along the track.
+
+pub const ROS2_DEFAULT_FRAME_ID: &str = "anki_track";
+pub const ROS2_DEFAULT_ACCEL_MM_PER_SEC2: i16 = 1000;
+pub const ROS2_LANE_CHANGE_ACCEL_MM_PER_SEC2: u16 = 1000;
+// Distance between neighbouring lanes on the standard track pieces.
+pub const ROS2_LANE_WIDTH_MM: f32 = 45.0;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+impl Time {
+    pub fn from_duration(duration: Duration) -> Time {
+        Time {
+            sec: duration.as_secs() as i32,
+            nanosec: duration.subsec_nanos(),
+        }
+    }
+
+    pub fn now() -> Time {
+        Time::from_duration(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pose {
+    pub position: Point,
+    pub orientation: Quaternion,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseStamped {
+    pub header: Header,
+    pub pose: Pose,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Twist {
+    pub linear: Vector3,
+    pub angular: Vector3,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Odometry {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub pose: Pose,
+    pub twist: Twist,
+}
+
+// Tracks one vehicle and converts its telemetry into odometry, and Twist commands into frames.
+#[derive(Debug, Clone)]
+pub struct Ros2Bridge {
+    frame_id: String,
+    child_frame_id: String,
+    distance_mm: f64,
+    offset_from_road_centre_mm: f32,
+    speed_mm_per_sec: u16,
+    lateral_mm_per_sec: f64,
+}
+
+impl Ros2Bridge {
+    pub fn new(child_frame_id: &str) -> Ros2Bridge {
+        Ros2Bridge {
+            frame_id: ROS2_DEFAULT_FRAME_ID.to_string(),
+            child_frame_id: child_frame_id.to_string(),
+            distance_mm: 0.0,
+            offset_from_road_centre_mm: 0.0,
+            speed_mm_per_sec: 0,
+            lateral_mm_per_sec: 0.0,
+        }
+    }
+
+    pub fn with_frame_id(mut self, frame_id: &str) -> Ros2Bridge {
+        self.frame_id = frame_id.to_string();
+        self
+    }
+
+    pub fn process_position_update(
+        &mut self,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+        stamp: Time,
+    ) -> Odometry {
+        self.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
+        self.speed_mm_per_sec = data.speed_mm_per_sec;
+        self.odometry(stamp)
+    }
+
+    // Transition bars report the wheel distance driven since the previous bar.
+    pub fn process_transition_update(
+        &mut self,
+        data: &AnkiVehicleMsgLocalisationTransitionUpdate,
+        stamp: Time,
+    ) -> Odometry {
+        let driven_cm = (data.left_wheel_dist_cm as f64 + data.right_wheel_dist_cm as f64) / 2.0;
+        self.distance_mm += driven_cm * 10.0;
+        self.offset_from_road_centre_mm = data.offset_from_road_centre_mm;
+        self.odometry(stamp)
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose {
+            position: Point {
+                x: self.distance_mm / 1000.0,
+                // Anki offsets grow to the right, ROS y grows to the left.
+                y: -self.offset_from_road_centre_mm as f64 / 1000.0,
+                z: 0.0,
+            },
+            orientation: Quaternion::default(),
+        }
+    }
+
+    pub fn pose_stamped(&self, stamp: Time) -> PoseStamped {
+        PoseStamped {
+            header: Header {
+                stamp,
+                frame_id: self.frame_id.clone(),
+            },
+            pose: self.pose(),
+        }
+    }
+
+    pub fn odometry(&self, stamp: Time) -> Odometry {
+        Odometry {
+            header: Header {
+                stamp,
+                frame_id: self.frame_id.clone(),
+            },
+            child_frame_id: self.child_frame_id.clone(),
+            pose: self.pose(),
+            twist: Twist {
+                linear: Vector3 {
+                    x: self.speed_mm_per_sec as f64 / 1000.0,
+                    y: self.lateral_mm_per_sec / 1000.0,
+                    z: 0.0,
+                },
+                angular: Vector3::default(),
+            },
+        }
+    }
+
+    // linear.x sets the forward speed, a non-zero linear.y moves one lane to that side at the
+    // requested lateral speed. Angular velocity can't be commanded on a track and is ignored.
+    pub fn twist_commands(&mut self, twist: &Twist) -> impl Iterator<Item = VehicleCommand> {
+        let speed_mm_per_sec = (twist.linear.x * 1000.0).clamp(0.0, i16::MAX as f64) as i16;
+        let speed = VehicleCommand::SetSpeed {
+            speed_mm_per_sec,
+            accel_mm_per_sec2: ROS2_DEFAULT_ACCEL_MM_PER_SEC2,
+        };
+
+        self.lateral_mm_per_sec = twist.linear.y * 1000.0;
+        let lane = (twist.linear.y != 0.0).then(|| VehicleCommand::ChangeLane {
+            horizontal_speed_mm_per_sec: self.lateral_mm_per_sec.abs().min(u16::MAX as f64) as u16,
+            horizontal_accel_mm_per_sec2: ROS2_LANE_CHANGE_ACCEL_MM_PER_SEC2,
+            offset_from_road_centre_mm: self.offset_from_road_centre_mm
+                - ROS2_LANE_WIDTH_MM * twist.linear.y.signum() as f32,
+        });
+        iter::once(speed).chain(lane)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{AnkiVehicleMsgType, WIRE_ENDIAN};
+    use scroll::Pread;
+
+    #[test]
+    fn ros2_odometry_test() {
+        let mut bridge = Ros2Bridge::new("skull");
+        let data: &[u8] = &[
+            17,
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate as u8,
+            1,
+            0,
+            0,
+            0,
+            72,
+            66,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            20,
+            22,
+        ];
+        let update = data
+            .pread_with::<AnkiVehicleMsgLocalisationTransitionUpdate>(0, WIRE_ENDIAN)
+            .unwrap();
+        let odometry = bridge.process_transition_update(&update, Time::default());
+        println!("T:{:?} == G:{:?}", odometry, data);
+        assert_eq!("anki_track", odometry.header.frame_id);
+        assert_eq!("skull", odometry.child_frame_id);
+        assert_eq!(0.21, odometry.pose.position.x);
+        assert_eq!(-0.05, odometry.pose.position.y);
+    }
+
+    #[test]
+    fn ros2_twist_commands_test() {
+        let mut bridge = Ros2Bridge::new("skull");
+        let commands = bridge.twist_commands(&Twist {
+            linear: Vector3 {
+                x: 0.5,
+                y: 0.1,
+                z: 0.0,
+            },
+            angular: Vector3::default(),
+        });
+        assert_eq!(
+            vec![
+                VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: 500,
+                    accel_mm_per_sec2: ROS2_DEFAULT_ACCEL_MM_PER_SEC2,
+                },
+                VehicleCommand::ChangeLane {
+                    horizontal_speed_mm_per_sec: 100,
+                    horizontal_accel_mm_per_sec2: ROS2_LANE_CHANGE_ACCEL_MM_PER_SEC2,
+                    offset_from_road_centre_mm: -45.0,
+                },
+            ],
+            commands.collect::<Vec<_>>()
+        );
+        assert_eq!(1, bridge.twist_commands(&Twist::default()).count());
+    }
+}
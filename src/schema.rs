@@ -0,0 +1,604 @@
+use crate::protocol::*;
+use crate::replay::ReplayDirection;
+
+// The wire layout of every message the crate knows, as data. Generic decoders, documentation and
+// dissector plugins can be generated from it instead of copying the codecs by hand. Message names
+// are the `msg_type` tags `JsonMessage` uses. All multi-byte fields are little endian.
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnumSchema {
+    pub name: &'static str,
+    pub values: &'static [(&'static str, u8)],
+}
+
+impl EnumSchema {
+    pub fn value_name(&self, value: u8) -> Option<&'static str> {
+        self.values
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| *name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    F32,
+    // One byte holding a value of the enum.
+    Enum(&'static EnumSchema),
+}
+
+impl FieldType {
+    pub const fn size(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Enum(_) => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::F32 => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::U8 => "u8",
+            FieldType::I8 => "i8",
+            FieldType::U16 => "u16",
+            FieldType::I16 => "i16",
+            FieldType::F32 => "f32",
+            FieldType::Enum(schema) => schema.name,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    pub offset: usize,
+}
+
+impl FieldSchema {
+    pub fn size(&self) -> usize {
+        self.field_type.size()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MessageSchema {
+    pub name: &'static str,
+    pub msg_type: AnkiVehicleMsgType,
+    pub direction: ReplayDirection,
+    pub size: usize,
+    // Including the size and msg_id header every message starts with.
+    pub fields: &'static [FieldSchema],
+}
+
+impl MessageSchema {
+    pub fn msg_id(&self) -> u8 {
+        self.msg_type.clone().into()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&'static FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+pub static VEHICLE_TURN: EnumSchema = EnumSchema {
+    name: "vehicle_turn",
+    values: &[
+        ("none", 0),
+        ("left", 1),
+        ("right", 2),
+        ("u_turn", 3),
+        ("u_turn_jump", 4),
+    ],
+};
+
+pub static VEHICLE_TURN_TRIGGER: EnumSchema = EnumSchema {
+    name: "vehicle_turn_trigger",
+    values: &[("immediate", 0), ("intersection", 1)],
+};
+
+pub static INTERSECTION_CODE: EnumSchema = EnumSchema {
+    name: "intersection_code",
+    values: &[
+        ("none", 0),
+        ("entry_first", 1),
+        ("exit_first", 2),
+        ("entry_second", 3),
+        ("exit_second", 4),
+    ],
+};
+
+pub static LIGHT_CHANNEL: EnumSchema = EnumSchema {
+    name: "light_channel",
+    values: &[
+        ("red", 0),
+        ("tail", 1),
+        ("blue", 2),
+        ("green", 3),
+        ("front_l", 4),
+        ("front_r", 5),
+    ],
+};
+
+pub static LIGHT_EFFECT: EnumSchema = EnumSchema {
+    name: "light_effect",
+    values: &[
+        ("steady", 0),
+        ("fade", 1),
+        ("throb", 2),
+        ("flash", 3),
+        ("random", 4),
+    ],
+};
+
+pub static TRACK_MATERIAL: EnumSchema = EnumSchema {
+    name: "track_material",
+    values: &[("plastic", 0), ("vinyl", 1)],
+};
+
+const fn field(name: &'static str, field_type: FieldType, offset: usize) -> FieldSchema {
+    FieldSchema {
+        name,
+        field_type,
+        offset,
+    }
+}
+
+const HEADER: [FieldSchema; 2] = [
+    field("size", FieldType::U8, 0),
+    field("msg_id", FieldType::U8, 1),
+];
+
+const fn header_only(
+    name: &'static str,
+    msg_type: AnkiVehicleMsgType,
+    direction: ReplayDirection,
+) -> MessageSchema {
+    MessageSchema {
+        name,
+        msg_type,
+        direction,
+        size: ANKI_VEHICLE_MSG_BASE_SIZE,
+        fields: &HEADER,
+    }
+}
+
+// Each of the three channel configs, named with its index so every field name is unique.
+const fn light_config(names: [&'static str; 5], n: usize) -> [FieldSchema; 5] {
+    let offset = 3 + n * ANKI_VEHICLE_LIGHT_CONFIG_SIZE;
+    [
+        field(names[0], FieldType::Enum(&LIGHT_CHANNEL), offset),
+        field(names[1], FieldType::Enum(&LIGHT_EFFECT), offset + 1),
+        field(names[2], FieldType::U8, offset + 2),
+        field(names[3], FieldType::U8, offset + 3),
+        field(names[4], FieldType::U8, offset + 4),
+    ]
+}
+
+const LIGHT_CONFIGS: [[FieldSchema; 5]; 3] = [
+    light_config(
+        [
+            "channel_0",
+            "effect_0",
+            "start_0",
+            "end_0",
+            "cycles_per_10_sec_0",
+        ],
+        0,
+    ),
+    light_config(
+        [
+            "channel_1",
+            "effect_1",
+            "start_1",
+            "end_1",
+            "cycles_per_10_sec_1",
+        ],
+        1,
+    ),
+    light_config(
+        [
+            "channel_2",
+            "effect_2",
+            "start_2",
+            "end_2",
+            "cycles_per_10_sec_2",
+        ],
+        2,
+    ),
+];
+
+const LIGHTS_PATTERN_FIELDS: [FieldSchema; 18] = [
+    HEADER[0],
+    HEADER[1],
+    field("channel_count", FieldType::U8, 2),
+    LIGHT_CONFIGS[0][0],
+    LIGHT_CONFIGS[0][1],
+    LIGHT_CONFIGS[0][2],
+    LIGHT_CONFIGS[0][3],
+    LIGHT_CONFIGS[0][4],
+    LIGHT_CONFIGS[1][0],
+    LIGHT_CONFIGS[1][1],
+    LIGHT_CONFIGS[1][2],
+    LIGHT_CONFIGS[1][3],
+    LIGHT_CONFIGS[1][4],
+    LIGHT_CONFIGS[2][0],
+    LIGHT_CONFIGS[2][1],
+    LIGHT_CONFIGS[2][2],
+    LIGHT_CONFIGS[2][3],
+    LIGHT_CONFIGS[2][4],
+];
+
+static MESSAGES: [MessageSchema; 25] = [
+    // Commands
+    header_only(
+        "disconnect",
+        AnkiVehicleMsgType::C2VDisconnect,
+        ReplayDirection::Command,
+    ),
+    header_only(
+        "ping_request",
+        AnkiVehicleMsgType::C2CPingRequest,
+        ReplayDirection::Command,
+    ),
+    header_only(
+        "version_request",
+        AnkiVehicleMsgType::C2VVersionRequest,
+        ReplayDirection::Command,
+    ),
+    header_only(
+        "battery_level_request",
+        AnkiVehicleMsgType::C2VBatteryLevelRequest,
+        ReplayDirection::Command,
+    ),
+    MessageSchema {
+        name: "set_lights",
+        msg_type: AnkiVehicleMsgType::C2VSetLights,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_SET_LIGHTS_SIZE,
+        fields: &[HEADER[0], HEADER[1], field("mask", FieldType::U8, 2)],
+    },
+    MessageSchema {
+        name: "set_speed",
+        msg_type: AnkiVehicleMsgType::C2VSetSpeed,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_SET_SPEED_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("speed_mm_per_sec", FieldType::I16, 2),
+            field("accel_mm_per_sec2", FieldType::I16, 4),
+            field("respect_road_piece_speed_limit", FieldType::U8, 6),
+        ],
+    },
+    MessageSchema {
+        name: "change_lane",
+        msg_type: AnkiVehicleMsgType::C2VChangeLane,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_CHANGE_LANE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("horizontal_speed_mm_per_sec", FieldType::U16, 2),
+            field("horizontal_accel_mm_per_sec2", FieldType::U16, 4),
+            field("offset_from_road_centre_mm", FieldType::F32, 6),
+            field("hop_intent", FieldType::U8, 10),
+            field("tag", FieldType::U8, 11),
+        ],
+    },
+    header_only(
+        "cancel_lane_change",
+        AnkiVehicleMsgType::C2VCancelLaneChange,
+        ReplayDirection::Command,
+    ),
+    MessageSchema {
+        name: "set_offset_from_road_centre",
+        msg_type: AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_SET_OFFSET_FROM_ROAD_CENTRE_SIZE,
+        fields: &[HEADER[0], HEADER[1], field("offset_mm", FieldType::F32, 2)],
+    },
+    MessageSchema {
+        name: "turn",
+        msg_type: AnkiVehicleMsgType::C2VTurn,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_TURN_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("turn_type", FieldType::Enum(&VEHICLE_TURN), 2),
+            field("trigger", FieldType::Enum(&VEHICLE_TURN_TRIGGER), 3),
+        ],
+    },
+    MessageSchema {
+        name: "lights_pattern",
+        msg_type: AnkiVehicleMsgType::C2VLightsPattern,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_LIGHTS_PATTERN_SIZE,
+        fields: &LIGHTS_PATTERN_FIELDS,
+    },
+    MessageSchema {
+        name: "set_config_params",
+        msg_type: AnkiVehicleMsgType::C2VSetConfigParams,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_SET_CONFIG_PARAMS_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("super_code_parse_mask", FieldType::U8, 2),
+            field("track_material", FieldType::Enum(&TRACK_MATERIAL), 3),
+        ],
+    },
+    MessageSchema {
+        name: "sdk_mode",
+        msg_type: AnkiVehicleMsgType::C2VSDKMode,
+        direction: ReplayDirection::Command,
+        size: ANKI_VEHICLE_MSG_SDK_MODE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("on", FieldType::U8, 2),
+            field("flags", FieldType::U8, 3),
+        ],
+    },
+    // Notifications
+    header_only(
+        "ping_response",
+        AnkiVehicleMsgType::V2CPingResponse,
+        ReplayDirection::Notification,
+    ),
+    MessageSchema {
+        name: "version_response",
+        msg_type: AnkiVehicleMsgType::V2CVersionResponse,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE,
+        fields: &[HEADER[0], HEADER[1], field("version", FieldType::U16, 2)],
+    },
+    MessageSchema {
+        name: "battery_level_response",
+        msg_type: AnkiVehicleMsgType::V2CBatteryLevelResponse,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("battery_level", FieldType::U16, 2),
+        ],
+    },
+    MessageSchema {
+        name: "position_update",
+        msg_type: AnkiVehicleMsgType::V2CLocalisationPositionUpdate,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("location_id", FieldType::U8, 2),
+            field("road_piece_id", FieldType::U8, 3),
+            field("offset_from_road_centre_mm", FieldType::F32, 4),
+            field("speed_mm_per_sec", FieldType::U16, 8),
+            field("parsing_flags", FieldType::U8, 10),
+            field("last_recv_lane_change_cmd_id", FieldType::U8, 11),
+            field("last_exec_lane_change_cmd_id", FieldType::U8, 12),
+            field(
+                "last_desired_lane_change_speed_mm_per_sec",
+                FieldType::U16,
+                13,
+            ),
+            field("last_desired_speed_mm_per_sec", FieldType::U16, 15),
+        ],
+    },
+    MessageSchema {
+        name: "transition_update",
+        msg_type: AnkiVehicleMsgType::V2CLocalisationTransitionUpdate,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("road_piece_idx", FieldType::I8, 2),
+            field("road_piece_idx_prev", FieldType::I8, 3),
+            field("offset_from_road_centre_mm", FieldType::F32, 4),
+            field("last_recv_lane_change_id", FieldType::U8, 8),
+            field("last_exec_lane_change_id", FieldType::U8, 9),
+            field(
+                "last_desired_lane_change_speed_mm_per_sec",
+                FieldType::U16,
+                10,
+            ),
+            field("ave_follow_line_drift_pixels", FieldType::I8, 12),
+            field("had_lane_change_activity", FieldType::U8, 13),
+            field("uphill_counter", FieldType::U8, 14),
+            field("downhill_counter", FieldType::U8, 15),
+            field("left_wheel_dist_cm", FieldType::U8, 16),
+            field("right_wheel_dist_cm", FieldType::U8, 17),
+        ],
+    },
+    MessageSchema {
+        name: "intersection_update",
+        msg_type: AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("road_piece_idx", FieldType::I8, 2),
+            field("offset_from_road_centre_mm", FieldType::F32, 3),
+            field("intersection_code", FieldType::Enum(&INTERSECTION_CODE), 7),
+            field("is_exiting", FieldType::U8, 8),
+            field("mm_since_last_transition_bar", FieldType::U16, 9),
+            field("mm_since_last_intersection_code", FieldType::U16, 11),
+        ],
+    },
+    header_only(
+        "vehicle_delocalized",
+        AnkiVehicleMsgType::V2CVehicleDelocalized,
+        ReplayDirection::Notification,
+    ),
+    MessageSchema {
+        name: "offset_from_road_centre_update",
+        msg_type: AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("offset_from_road_centre_mm", FieldType::F32, 2),
+            field("lane_change_id", FieldType::U8, 6),
+        ],
+    },
+    MessageSchema {
+        name: "speed_update",
+        msg_type: AnkiVehicleMsgType::V2CSpeedUpdate,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("desired_speed_mm_per_sec", FieldType::U16, 2),
+            field("accel_mm_per_sec2", FieldType::U16, 4),
+            field("speed_mm_per_sec", FieldType::U16, 6),
+        ],
+    },
+    MessageSchema {
+        name: "charger_info",
+        msg_type: AnkiVehicleMsgType::V2CChargerInfo,
+        direction: ReplayDirection::Notification,
+        size: ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE,
+        fields: &[
+            HEADER[0],
+            HEADER[1],
+            field("on_track", FieldType::U8, 2),
+            field("on_charger", FieldType::U8, 3),
+            field("battery_low", FieldType::U8, 4),
+            field("battery_full", FieldType::U8, 5),
+        ],
+    },
+    header_only(
+        "collision_detected",
+        AnkiVehicleMsgType::V2CCollisionDetected,
+        ReplayDirection::Notification,
+    ),
+    header_only(
+        "cycle_overtime",
+        AnkiVehicleMsgType::V2CCycleOvertime,
+        ReplayDirection::Notification,
+    ),
+];
+
+pub fn message_schemas() -> &'static [MessageSchema] {
+    &MESSAGES
+}
+
+pub fn message_schema(msg_id: u8) -> Option<&'static MessageSchema> {
+    MESSAGES.iter().find(|schema| schema.msg_id() == msg_id)
+}
+
+pub fn message_schema_by_name(name: &str) -> Option<&'static MessageSchema> {
+    MESSAGES.iter().find(|schema| schema.name == name)
+}
+
+// The whole table as one JSON document, for tools outside Rust.
+#[cfg(feature = "json")]
+pub fn schema_json() -> serde_json::Value {
+    use serde_json::json;
+
+    let messages: Vec<serde_json::Value> = MESSAGES
+        .iter()
+        .map(|schema| {
+            let fields: Vec<serde_json::Value> = schema
+                .fields
+                .iter()
+                .map(|field| {
+                    let mut value = json!({
+                        "name": field.name,
+                        "type": field.field_type.name(),
+                        "offset": field.offset,
+                        "size": field.size(),
+                    });
+                    if let FieldType::Enum(schema) = field.field_type {
+                        value["values"] = schema
+                            .values
+                            .iter()
+                            .map(|(name, v)| (name.to_string(), json!(v)))
+                            .collect::<serde_json::Map<_, _>>()
+                            .into();
+                    }
+                    value
+                })
+                .collect();
+            json!({
+                "name": schema.name,
+                "msg_id": schema.msg_id(),
+                "direction": match schema.direction {
+                    ReplayDirection::Command => "command",
+                    ReplayDirection::Notification => "notification",
+                },
+                "size": schema.size,
+                "fields": fields,
+            })
+        })
+        .collect();
+    json!({ "byte_order": "little_endian", "messages": messages })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{VehicleCommand, WireMessage};
+    use crate::validation::notification_size;
+
+    #[test]
+    fn message_schema_layout_test() {
+        for schema in message_schemas() {
+            let mut offset = 0;
+            for field in schema.fields {
+                assert_eq!(offset, field.offset, "{}.{}", schema.name, field.name);
+                offset += field.size();
+            }
+            assert_eq!(schema.size, offset, "{}", schema.name);
+            for (idx, field) in schema.fields.iter().enumerate() {
+                assert_eq!(Some(field), schema.field(field.name), "{}", idx);
+            }
+            if schema.direction == ReplayDirection::Notification {
+                assert_eq!(Some(schema.size), notification_size(&schema.msg_type));
+            }
+        }
+
+        let command = VehicleCommand::SetSpeed {
+            speed_mm_per_sec: -300,
+            accel_mm_per_sec2: 1000,
+        }
+        .encode();
+        let schema = message_schema(command[1]).unwrap();
+        assert_eq!("set_speed", schema.name);
+        assert_eq!(command.len(), schema.size);
+        let speed = schema.field("speed_mm_per_sec").unwrap();
+        assert_eq!(
+            -300i16,
+            i16::from_le_bytes([command[speed.offset], command[speed.offset + 1]])
+        );
+        assert_eq!(
+            Some("flash"),
+            LIGHT_EFFECT.value_name(LightEffect::Flash.into())
+        );
+        assert!(message_schema_by_name("turn").is_some());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn schema_json_test() {
+        let schema = schema_json();
+        let messages = schema["messages"].as_array().unwrap();
+        assert_eq!(message_schemas().len(), messages.len());
+        let turn = messages.iter().find(|m| m["name"] == "turn").unwrap();
+        assert_eq!(0x32, turn["msg_id"]);
+        assert_eq!("command", turn["direction"]);
+        assert_eq!("vehicle_turn", turn["fields"][2]["type"]);
+        assert_eq!(3, turn["fields"][2]["values"]["u_turn"]);
+    }
+}
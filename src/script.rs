@@ -0,0 +1,392 @@
+use scroll::{Pread, Pwrite};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::protocol::{
+    AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgType, IntersectionCode, WIRE_ENDIAN,
+};
+use crate::race::{RaceCommand, RaceEvent, RaceUpdate};
+use crate::replay::{ReplayDirection, ReplayError, ReplayReader};
+use crate::sim::vehicle::encode_notification;
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Deterministic tests for code sitting on top of the vehicle state: a script of timestamped
+// notifications is fed through `AnkiVehicleData` and any race controllers hooked up to the
+// runner, and the resulting state, events and commands can then be asserted on. No threads, no
+// clocks, the same script always gives the same result.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScriptedMessage {
+    // Milliseconds since the start of the script.
+    pub at_ms: u64,
+    pub vehicle: VehicleId,
+    pub frame: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MessageScript {
+    messages: Vec<ScriptedMessage>,
+}
+
+impl MessageScript {
+    pub fn new() -> MessageScript {
+        MessageScript::default()
+    }
+
+    // Any frame, malformed ones included. Messages are kept in time order, messages with the
+    // same timestamp in the order they were added.
+    pub fn frame(mut self, at_ms: u64, vehicle: &str, frame: &[u8]) -> MessageScript {
+        let idx = self.messages.partition_point(|msg| msg.at_ms <= at_ms);
+        self.messages.insert(
+            idx,
+            ScriptedMessage {
+                at_ms,
+                vehicle: vehicle.into(),
+                frame: frame.to_vec(),
+            },
+        );
+        self
+    }
+
+    pub fn position_update(
+        self,
+        at_ms: u64,
+        vehicle: &str,
+        location_id: u8,
+        road_piece_id: u8,
+        speed_mm_per_sec: u16,
+    ) -> MessageScript {
+        let frame = encode_notification(
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate,
+            |data, offset| {
+                data.gwrite_with::<u8>(location_id, offset, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(road_piece_id, offset, WIRE_ENDIAN)?;
+                data.gwrite_with::<f32>(0.0, offset, WIRE_ENDIAN)?;
+                data.gwrite_with::<u16>(speed_mm_per_sec, offset, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    pub fn transition_update(
+        self,
+        at_ms: u64,
+        vehicle: &str,
+        road_piece_idx: i8,
+        road_piece_idx_prev: i8,
+        offset_from_road_centre_mm: f32,
+    ) -> MessageScript {
+        let frame = encode_notification(
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate,
+            |data, offset| {
+                data.gwrite_with::<i8>(road_piece_idx, offset, WIRE_ENDIAN)?;
+                data.gwrite_with::<i8>(road_piece_idx_prev, offset, WIRE_ENDIAN)?;
+                data.gwrite_with::<f32>(offset_from_road_centre_mm, offset, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    pub fn offset_update(
+        self,
+        at_ms: u64,
+        vehicle: &str,
+        offset_from_road_centre_mm: f32,
+    ) -> MessageScript {
+        let frame = encode_notification(
+            AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate,
+            |data, offset| {
+                data.gwrite_with::<f32>(offset_from_road_centre_mm, offset, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    pub fn battery_level(self, at_ms: u64, vehicle: &str, battery_level: u16) -> MessageScript {
+        let frame = encode_notification(
+            AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            |data, offset| {
+                data.gwrite_with::<u16>(battery_level, offset, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    pub fn version(self, at_ms: u64, vehicle: &str, version: u16) -> MessageScript {
+        let frame = encode_notification(AnkiVehicleMsgType::V2CVersionResponse, |data, offset| {
+            data.gwrite_with::<u16>(version, offset, WIRE_ENDIAN)?;
+            Ok(())
+        });
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    pub fn delocalized(self, at_ms: u64, vehicle: &str) -> MessageScript {
+        let frame = encode_notification(AnkiVehicleMsgType::V2CVehicleDelocalized, |_, _| Ok(()));
+        self.frame(at_ms, vehicle, &frame)
+    }
+
+    // The notifications of a recording, to turn a bug seen on the track into a test.
+    pub fn from_replay<R: Read>(reader: ReplayReader<R>) -> Result<MessageScript, ReplayError> {
+        let mut script = MessageScript::new();
+        for record in reader {
+            let record = record?;
+            if record.direction == ReplayDirection::Notification {
+                script = script.frame(record.timestamp_ms, &record.vehicle, &record.frame);
+            }
+        }
+        Ok(script)
+    }
+
+    pub fn messages(&self) -> &[ScriptedMessage] {
+        &self.messages
+    }
+}
+
+// The parts of a vehicle's state a test is likely to check.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VehicleSnapshot {
+    pub name: String,
+    pub version: u16,
+    pub battery_level: u16,
+    pub speed_mm_per_sec: u16,
+    pub offset_from_road_centre_mm: f32,
+    pub location_id: u8,
+    pub road_piece_idx: i8,
+    pub road_piece_idx_prev: i8,
+    pub intersection_code: IntersectionCode,
+}
+
+impl VehicleSnapshot {
+    pub fn from_vehicle(data: &AnkiVehicleData) -> VehicleSnapshot {
+        VehicleSnapshot {
+            name: data.name.clone(),
+            version: data.version,
+            battery_level: data.battery_level,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+            location_id: data.location_id,
+            road_piece_idx: data.road_piece_idx,
+            road_piece_idx_prev: data.road_piece_idx_prev,
+            intersection_code: data.intersection_code.clone(),
+        }
+    }
+}
+
+type Handler<'a> = Box<dyn FnMut(&str, &[u8], Instant) -> RaceUpdate + 'a>;
+
+// Runs scripts. Handlers see every message after it has been applied to the vehicle's state,
+// with `at_ms` turned into an `Instant` relative to when the runner was made.
+pub struct ScriptRunner<'a> {
+    started: Instant,
+    vehicles: BTreeMap<VehicleId, AnkiVehicleData>,
+    handlers: Vec<Handler<'a>>,
+    events: Vec<RaceEvent>,
+    commands: Vec<RaceCommand>,
+    errors: Vec<ScriptedMessage>,
+}
+
+impl<'a> Default for ScriptRunner<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ScriptRunner<'a> {
+    pub fn new() -> ScriptRunner<'a> {
+        ScriptRunner {
+            started: Instant::now(),
+            vehicles: BTreeMap::new(),
+            handlers: Vec::new(),
+            events: Vec::new(),
+            commands: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    // Messages are timed from this clock's current time rather than the system clock's.
+    pub fn with_clock(mut self, clock: &dyn Clock) -> ScriptRunner<'a> {
+        self.started = clock.now();
+        self
+    }
+
+    pub fn with_handler<F>(mut self, handler: F) -> ScriptRunner<'a>
+    where
+        F: FnMut(&str, &[u8], Instant) -> RaceUpdate + 'a,
+    {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    // Only called with position updates, the message race controllers work from.
+    pub fn with_position_handler<F>(self, mut handler: F) -> ScriptRunner<'a>
+    where
+        F: FnMut(&str, &AnkiVehicleMsgLocalisationPositionUpdate, Instant) -> RaceUpdate + 'a,
+    {
+        self.with_handler(move |vehicle, frame, at| match frame
+            .pread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(
+            0,
+            WIRE_ENDIAN,
+        ) {
+            Ok(data) => handler(vehicle, &data, at),
+            Err(_) => RaceUpdate::default(),
+        })
+    }
+
+    pub fn run(&mut self, script: &MessageScript) -> &mut ScriptRunner<'a> {
+        for msg in script.messages() {
+            let at = self.started + Duration::from_millis(msg.at_ms);
+            let vehicle = self.vehicles.entry(msg.vehicle.clone()).or_insert_with(|| {
+                let mut vehicle = AnkiVehicleData::default();
+                vehicle.set_name(msg.vehicle.to_string());
+                vehicle
+            });
+            if vehicle.process_notification(&msg.frame).is_err() {
+                self.errors.push(msg.clone());
+                continue;
+            }
+            for handler in self.handlers.iter_mut() {
+                let update = handler(&msg.vehicle, &msg.frame, at);
+                self.events.extend(update.events);
+                self.commands.extend(update.commands);
+            }
+        }
+        self
+    }
+
+    pub fn vehicle(&self, vehicle: &str) -> Option<VehicleSnapshot> {
+        self.vehicles
+            .get(vehicle)
+            .map(VehicleSnapshot::from_vehicle)
+    }
+
+    // Every vehicle the script has sent a message from, in id order.
+    pub fn vehicle_ids(&self) -> impl Iterator<Item = &str> + '_ {
+        self.vehicles.keys().map(VehicleId::as_str)
+    }
+
+    pub fn events(&self) -> &[RaceEvent] {
+        &self.events
+    }
+
+    pub fn commands(&self) -> &[RaceCommand] {
+        &self.commands
+    }
+
+    // Messages the vehicle state refused, they are not passed on to the handlers.
+    pub fn errors(&self) -> &[ScriptedMessage] {
+        &self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::leaderboard::{Leaderboard, FINISH_LINE_ROAD_PIECE_ID};
+    use crate::race::RaceCommands;
+    use crate::replay::{ReplayRecord, ReplayWriter};
+    use std::io::Cursor;
+
+    #[test]
+    fn script_state_test() {
+        let script = MessageScript::new()
+            .transition_update(200, "skull", 3, 2, 23.0)
+            .battery_level(100, "skull", 3900)
+            .version(0, "skull", 0x2676)
+            .position_update(300, "skull", 12, 17, 600)
+            .frame(400, "skull", &[0xff])
+            .offset_update(500, "nuke", -68.0);
+        assert_eq!(
+            vec![0, 100, 200, 300, 400, 500],
+            script
+                .messages()
+                .iter()
+                .map(|msg| msg.at_ms)
+                .collect::<Vec<u64>>()
+        );
+
+        let mut runner = ScriptRunner::new();
+        runner.run(&script);
+        let skull = runner.vehicle("skull").unwrap();
+        assert_eq!(0x2676, skull.version);
+        assert_eq!(3900, skull.battery_level);
+        assert_eq!(600, skull.speed_mm_per_sec);
+        assert_eq!(12, skull.location_id);
+        assert_eq!(3, skull.road_piece_idx);
+        assert_eq!(2, skull.road_piece_idx_prev);
+        assert_eq!(
+            -68.0,
+            runner.vehicle("nuke").unwrap().offset_from_road_centre_mm
+        );
+        assert_eq!(&script.messages()[4..5], runner.errors());
+    }
+
+    #[test]
+    fn script_race_test() {
+        let mut leaderboard = Leaderboard::new(["skull".to_string(), "nuke".to_string()]);
+        let mut script = MessageScript::new();
+        for lap in 0..3 {
+            let at_ms = lap * 10_000;
+            script = script
+                .position_update(at_ms, "skull", 0, FINISH_LINE_ROAD_PIECE_ID, 800)
+                .position_update(at_ms + 5_000, "skull", 0, 17, 800)
+                .delocalized(at_ms + 6_000, "skull");
+        }
+        {
+            let mut runner =
+                ScriptRunner::new().with_position_handler(|vehicle, data, at| RaceUpdate {
+                    events: leaderboard
+                        .process_position_update(vehicle, data, at)
+                        .into_iter()
+                        .collect(),
+                    commands: RaceCommands::new(),
+                });
+            runner.run(&script);
+            assert_eq!(
+                vec![
+                    RaceEvent::LapCompleted {
+                        vehicle: "skull".into(),
+                        lap: 1,
+                        lap_time: Duration::from_secs(10),
+                    },
+                    RaceEvent::LapCompleted {
+                        vehicle: "skull".into(),
+                        lap: 2,
+                        lap_time: Duration::from_secs(10),
+                    },
+                ],
+                runner.events()
+            );
+            assert!(runner.errors().is_empty());
+        }
+        assert_eq!(Some(1), leaderboard.position("skull"));
+    }
+
+    #[test]
+    fn script_from_replay_test() {
+        let mut writer = ReplayWriter::new(Vec::new(), 0).unwrap();
+        let script = MessageScript::new().battery_level(20, "skull", 3900);
+        writer
+            .write(&ReplayRecord::command(
+                10,
+                "skull",
+                &AnkiVehicleData::set_speed(500, 0),
+            ))
+            .unwrap();
+        writer
+            .write(&ReplayRecord::notification(
+                20,
+                "skull",
+                &script.messages()[0].frame,
+            ))
+            .unwrap();
+        let reader = ReplayReader::new(Cursor::new(writer.into_inner().unwrap())).unwrap();
+        assert_eq!(script, MessageScript::from_replay(reader).unwrap());
+    }
+}
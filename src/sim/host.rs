@@ -0,0 +1,530 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::host::{FleetHost, HostError, HostNotification, HostVehicle};
+use crate::pool::FramePool;
+use crate::protocol::AnkiVehicleMsgType;
+use crate::sim::link::{InFlight, LinkConditions, LinkStats, SimulatedLink};
+use crate::sim::vehicle::SimulatedVehicle;
+use crate::telemetry_channel::{bounded_channel, BoundedReceiver, BoundedSender, DropPolicy};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+
+struct SimulatedEntry {
+    info: HostVehicle,
+    vehicle: SimulatedVehicle,
+    commands: VecDeque<InFlight>,
+    notifications: VecDeque<InFlight>,
+}
+
+impl SimulatedEntry {
+    fn deliver(&mut self, data: &[u8]) -> Result<(), HostError> {
+        let msg_id = self
+            .vehicle
+            .handle_command(data)
+            .map_err(|e| HostError::Transport(e.to_string()))?;
+        if msg_id == AnkiVehicleMsgType::C2VDisconnect {
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    // Anything still on the air is lost with the connection.
+    fn disconnect(&mut self) {
+        self.info.connected = false;
+        self.commands.clear();
+        self.notifications.clear();
+    }
+
+    fn queue_notifications(&mut self, link: &mut SimulatedLink) {
+        let sent: Vec<Vec<u8>> = self.vehicle.drain_notifications().collect();
+        for data in sent {
+            for data in link.transmit_notification(data) {
+                let due = link.notification_due(self.notifications.back());
+                self.notifications.push_back(InFlight { due, data });
+            }
+        }
+    }
+
+    fn take_due(&mut self, link: &SimulatedLink, due: &mut Vec<(Duration, HostNotification)>) {
+        while self
+            .notifications
+            .front()
+            .is_some_and(|frame| frame.due <= link.now)
+        {
+            let frame = self.notifications.pop_front().unwrap();
+            due.push((
+                frame.due,
+                HostNotification {
+                    vehicle: self.info.id.clone(),
+                    data: frame.data,
+                },
+            ));
+        }
+    }
+}
+
+enum Subscriber {
+    Unbounded(Sender<HostNotification>),
+    Bounded(BoundedSender),
+}
+
+impl Subscriber {
+    // False once the subscriber has gone away.
+    fn send(&self, notification: HostNotification) -> bool {
+        match self {
+            Subscriber::Unbounded(tx) => tx.send(notification).is_ok(),
+            Subscriber::Bounded(tx) => tx.send(notification).is_ok(),
+        }
+    }
+}
+
+// A fleet of simulated vehicles behind the same interface as real BLE hosts, so front-ends and
+// race controllers can be run without hardware. Time only moves on when `step` is called, or
+// from a `spawn_clock` thread for real time use. Frames are delivered instantly unless link
+// conditions say otherwise.
+#[derive(Default)]
+pub struct SimulatedHost {
+    vehicles: Mutex<Vec<SimulatedEntry>>,
+    // Always locked after `vehicles`.
+    link: Mutex<SimulatedLink>,
+    subscribers: Mutex<Vec<Subscriber>>,
+    frame_pool: Option<Arc<FramePool>>,
+}
+
+impl SimulatedHost {
+    pub fn new() -> SimulatedHost {
+        SimulatedHost::default()
+    }
+
+    pub fn with_vehicle(self, id: &str, name: &str, vehicle: SimulatedVehicle) -> SimulatedHost {
+        self.add_vehicle(id, name, vehicle);
+        self
+    }
+
+    pub fn add_vehicle(&self, id: &str, name: &str, mut vehicle: SimulatedVehicle) {
+        if let Some(pool) = &self.frame_pool {
+            vehicle.set_frame_pool(pool.clone());
+        }
+        self.vehicles.lock().unwrap().push(SimulatedEntry {
+            info: HostVehicle {
+                id: id.into(),
+                name: name.to_string(),
+                connected: false,
+            },
+            vehicle,
+            commands: VecDeque::new(),
+            notifications: VecDeque::new(),
+        });
+    }
+
+    // Every vehicle, including ones added later, writes its notifications into buffers from
+    // `pool`. Subscribers hand `HostNotification::data` back to the pool once decoded.
+    pub fn with_frame_pool(mut self, pool: Arc<FramePool>) -> SimulatedHost {
+        for entry in self.vehicles.get_mut().unwrap().iter_mut() {
+            entry.vehicle.set_frame_pool(pool.clone());
+        }
+        self.frame_pool = Some(pool);
+        self
+    }
+
+    pub fn with_link(self, conditions: LinkConditions) -> SimulatedHost {
+        self.set_link(conditions);
+        self
+    }
+
+    // Reseeds the delay sampling, so a run with jitter can be repeated exactly.
+    pub fn with_link_seed(self, seed: u64) -> SimulatedHost {
+        self.link.lock().unwrap().reseed(seed);
+        self
+    }
+
+    // Applies to frames sent from now on, frames already on the air keep their delay.
+    pub fn set_link(&self, conditions: LinkConditions) {
+        self.link.lock().unwrap().conditions = conditions;
+    }
+
+    // What the link has done to the frames sent over it so far.
+    pub fn link_stats(&self) -> LinkStats {
+        self.link.lock().unwrap().stats
+    }
+
+    // Simulated time since the host was created.
+    pub fn elapsed(&self) -> Duration {
+        self.link.lock().unwrap().now
+    }
+
+    // Frames on the air in either direction, across every vehicle.
+    pub fn in_flight(&self) -> usize {
+        self.vehicles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.commands.len() + entry.notifications.len())
+            .sum()
+    }
+
+    // A copy of the simulated vehicle, for checking on it from tests.
+    pub fn vehicle(&self, id: &str) -> Option<SimulatedVehicle> {
+        self.vehicles
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|entry| entry.info.id == id)
+            .map(|entry| entry.vehicle.clone())
+    }
+
+    // Advances every connected vehicle and publishes what they sent, once it has made it across
+    // the link. Delays resolve to the step size: commands due during a step reach the vehicle at
+    // the end of it.
+    pub fn step(&self, elapsed: Duration) {
+        let mut due = Vec::new();
+        {
+            let mut vehicles = self.vehicles.lock().unwrap();
+            let mut link = self.link.lock().unwrap();
+            link.now += elapsed;
+            for entry in vehicles.iter_mut() {
+                if !entry.info.connected {
+                    continue;
+                }
+                entry.vehicle.advance(elapsed);
+                while entry
+                    .commands
+                    .front()
+                    .is_some_and(|frame| frame.due <= link.now)
+                {
+                    let frame = entry.commands.pop_front().unwrap();
+                    // Nobody is waiting on the result of a delayed write any more.
+                    if let Err(_e) = entry.deliver(&frame.data) {
+                        trace_event!(target: TARGET_TRANSPORT, debug, error = %_e, vehicle = %entry.info.id, "Simulated vehicle dropped command");
+                    }
+                }
+                entry.queue_notifications(&mut link);
+                entry.take_due(&link, &mut due);
+            }
+        }
+        // Interleaved across vehicles in the order they arrived.
+        due.sort_by_key(|(at, _)| *at);
+        self.publish(
+            due.into_iter()
+                .map(|(_, notification)| notification)
+                .collect(),
+        );
+    }
+
+    // Steps the simulation every `tick` until the host is dropped.
+    pub fn spawn_clock(host: &Arc<SimulatedHost>, tick: Duration) -> JoinHandle<()> {
+        let host = Arc::downgrade(host);
+        thread::spawn(move || loop {
+            thread::sleep(tick);
+            match host.upgrade() {
+                Some(host) => host.step(tick),
+                None => break,
+            }
+        })
+    }
+
+    fn publish(&self, notifications: Vec<HostNotification>) {
+        if notifications.is_empty() {
+            return;
+        }
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            notifications
+                .iter()
+                .all(|notification| subscriber.send(notification.clone()))
+        });
+    }
+
+    fn with_entry<T, F>(&self, vehicle: &str, f: F) -> Result<T, HostError>
+    where
+        F: FnOnce(&mut SimulatedEntry) -> Result<T, HostError>,
+    {
+        let mut vehicles = self.vehicles.lock().unwrap();
+        let entry = vehicles
+            .iter_mut()
+            .find(|entry| entry.info.id == vehicle)
+            .ok_or_else(|| HostError::UnknownVehicle(vehicle.to_string()))?;
+        f(entry)
+    }
+}
+
+impl FleetHost for SimulatedHost {
+    fn discover(&self) -> Result<Vec<HostVehicle>, HostError> {
+        Ok(self.vehicles())
+    }
+
+    fn vehicles(&self) -> Vec<HostVehicle> {
+        self.vehicles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    fn connect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.with_entry(vehicle, |entry| {
+            entry.info.connected = true;
+            Ok(())
+        })
+    }
+
+    fn disconnect(&self, vehicle: &str) -> Result<(), HostError> {
+        self.with_entry(vehicle, |entry| {
+            entry.disconnect();
+            Ok(())
+        })
+    }
+
+    // Without a command delay the vehicle handles the frame straight away, so it can be turned
+    // down here, and responses are published without waiting for the next step. Delayed
+    // frames are handled on the step they arrive in. Frames lost or corrupted by link faults
+    // fail silently, as far as the sender can tell the write went through.
+    fn send(&self, vehicle: &str, data: Vec<u8>) -> Result<(), HostError> {
+        let mut due = Vec::new();
+        self.with_entry(vehicle, |entry| {
+            if !entry.info.connected {
+                return Err(HostError::NotConnected(vehicle.to_string()));
+            }
+            let mut link = self.link.lock().unwrap();
+            for received in link.transmit_command(data.clone()) {
+                let arrives = link.command_due(entry.commands.back());
+                if arrives > link.now {
+                    entry.commands.push_back(InFlight {
+                        due: arrives,
+                        data: received,
+                    });
+                    continue;
+                }
+                match entry.deliver(&received) {
+                    Err(e) if received == data => return Err(e),
+                    Err(_e) => {
+                        trace_event!(target: TARGET_TRANSPORT, debug, error = %_e, vehicle = %entry.info.id, "Simulated vehicle dropped corrupted command");
+                    }
+                    Ok(()) => {}
+                }
+                // A duplicated disconnect lands on a vehicle that's already gone.
+                if !entry.info.connected {
+                    break;
+                }
+            }
+            entry.queue_notifications(&mut link);
+            entry.take_due(&link, &mut due);
+            Ok(())
+        })?;
+        self.publish(
+            due.into_iter()
+                .map(|(_, notification)| notification)
+                .collect(),
+        );
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Receiver<HostNotification> {
+        let (tx, rx) = channel();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber::Unbounded(tx));
+        rx
+    }
+
+    fn subscribe_bounded(&self, capacity: usize, policy: DropPolicy) -> BoundedReceiver {
+        let (tx, rx) = bounded_channel(capacity, policy);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber::Bounded(tx));
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::VehicleCommand;
+    use crate::protocol::{AnkiVehicleMsg, WIRE_ENDIAN};
+    use crate::sim::link::{DelayDistribution, LinkFaults};
+    use crate::AnkiVehicleData;
+    use scroll::Pread;
+
+    #[test]
+    fn sim_host_test() {
+        let host = SimulatedHost::new()
+            .with_vehicle("skull", "Skull", SimulatedVehicle::new())
+            .with_vehicle("nuke", "Nuke", SimulatedVehicle::new());
+        let notifications = host.subscribe();
+        let speed = AnkiVehicleData::set_speed(500, 0);
+
+        assert_eq!(
+            Err(HostError::NotConnected("skull".to_string())),
+            host.send("skull", speed.into())
+        );
+        assert_eq!(
+            Err(HostError::UnknownVehicle("grip".to_string())),
+            host.connect("grip")
+        );
+        host.connect("skull").unwrap();
+        assert!(host.vehicles()[0].connected);
+
+        let mut vehicle = AnkiVehicleData::default();
+        for command in vehicle.configure() {
+            host.send("skull", command.into()).unwrap();
+        }
+        host.send("skull", speed.into()).unwrap();
+        host.step(Duration::from_secs(1));
+
+        let received: Vec<HostNotification> = notifications.try_iter().collect();
+        assert!(received.iter().all(|n| n.vehicle == "skull"));
+        for notification in &received {
+            vehicle.process_notification(&notification.data).unwrap();
+        }
+        assert_eq!(500, vehicle.speed_mm_per_sec);
+        assert_ne!(0, vehicle.battery_level);
+        assert_eq!(0, host.vehicle("nuke").unwrap().speed_mm_per_sec());
+    }
+
+    #[test]
+    fn sim_host_bounded_subscriber_test() {
+        let host = SimulatedHost::new().with_vehicle("skull", "Skull", SimulatedVehicle::new());
+        host.connect("skull").unwrap();
+        host.send("skull", AnkiVehicleData::set_speed(1000, 0).into())
+            .unwrap();
+        let notifications = host.subscribe_bounded(4, DropPolicy::CoalescePositions);
+        host.step(Duration::from_secs(5));
+
+        // Only the latest position survives, next to whatever else was still queued.
+        assert!(notifications.len() <= 4);
+        assert!(notifications.coalesced() > 0);
+        let positions = std::iter::from_fn(|| notifications.try_recv().ok())
+            .filter(|n| n.data[1] == u8::from(AnkiVehicleMsgType::V2CLocalisationPositionUpdate))
+            .count();
+        assert_eq!(1, positions);
+    }
+
+    #[test]
+    fn sim_host_clock_test() {
+        let host =
+            Arc::new(SimulatedHost::new().with_vehicle("skull", "Skull", SimulatedVehicle::new()));
+        host.connect("skull").unwrap();
+        host.send("skull", AnkiVehicleData::set_speed(1000, 0).into())
+            .unwrap();
+        let notifications = host.subscribe();
+        let clock = SimulatedHost::spawn_clock(&host, Duration::from_millis(5));
+
+        assert!(notifications.recv_timeout(Duration::from_secs(5)).is_ok());
+        drop(host);
+        clock.join().unwrap();
+    }
+
+    #[test]
+    fn sim_host_latency_test() {
+        let host = SimulatedHost::new()
+            .with_vehicle("skull", "Skull", SimulatedVehicle::new())
+            .with_link(
+                LinkConditions::new()
+                    .with_command_delay(DelayDistribution::Fixed(Duration::from_millis(50)))
+                    .with_notification_delay(DelayDistribution::Fixed(Duration::from_millis(20))),
+            );
+        let notifications = host.subscribe();
+        host.connect("skull").unwrap();
+        let mut vehicle = AnkiVehicleData::default();
+        host.send("skull", VehicleCommand::VersionRequest.into())
+            .unwrap();
+
+        host.step(Duration::from_millis(40));
+        assert!(notifications.try_recv().is_err());
+        host.step(Duration::from_millis(20));
+        assert!(notifications.try_recv().is_err());
+        host.step(Duration::from_millis(20));
+        let notification = notifications.try_recv().unwrap();
+        assert_eq!(
+            AnkiVehicleMsgType::V2CVersionResponse,
+            vehicle.process_notification(&notification.data).unwrap()
+        );
+        assert_eq!(Duration::from_millis(80), host.elapsed());
+    }
+
+    #[test]
+    fn sim_host_jitter_order_test() {
+        let jitter = DelayDistribution::Uniform {
+            min: Duration::from_millis(5),
+            max: Duration::from_millis(100),
+        };
+        let host = SimulatedHost::new()
+            .with_vehicle("skull", "Skull", SimulatedVehicle::new())
+            .with_link(
+                LinkConditions::new()
+                    .with_command_delay(jitter.clone())
+                    .with_notification_delay(jitter),
+            )
+            .with_link_seed(11);
+        let notifications = host.subscribe();
+        host.connect("skull").unwrap();
+        for _ in 0..20 {
+            host.send("skull", VehicleCommand::VersionRequest.into())
+                .unwrap();
+            host.send("skull", VehicleCommand::BatteryLevelRequest.into())
+                .unwrap();
+        }
+        for _ in 0..100 {
+            host.step(Duration::from_millis(10));
+        }
+
+        let received: Vec<AnkiVehicleMsgType> = notifications
+            .try_iter()
+            .map(|n| {
+                n.data
+                    .pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)
+                    .unwrap()
+                    .msg_id
+            })
+            .collect();
+        assert_eq!(40, received.len());
+        for pair in received.chunks(2) {
+            assert_eq!(AnkiVehicleMsgType::V2CVersionResponse, pair[0]);
+            assert_eq!(AnkiVehicleMsgType::V2CBatteryLevelResponse, pair[1]);
+        }
+    }
+
+    #[test]
+    fn sim_host_faults_test() {
+        let faults = LinkFaults::new()
+            .with_drop_rate(0.2)
+            .with_duplicate_rate(0.2)
+            .with_corrupt_rate(0.2);
+        let host = SimulatedHost::new()
+            .with_vehicle("skull", "Skull", SimulatedVehicle::new())
+            .with_link(LinkConditions::ble().with_faults(faults))
+            .with_link_seed(7);
+        let notifications = host.subscribe();
+        host.connect("skull").unwrap();
+
+        let mut vehicle = AnkiVehicleData::default();
+        for command in vehicle.configure() {
+            host.send("skull", command.into()).unwrap();
+        }
+        // Faulty links get the same command repeated until it sticks.
+        for _ in 0..50 {
+            host.send("skull", AnkiVehicleData::set_speed(500, 0).into())
+                .unwrap();
+            host.step(Duration::from_millis(100));
+        }
+
+        let (mut decoded, mut rejected) = (0, 0);
+        for notification in notifications.try_iter() {
+            match vehicle.process_notification(&notification.data) {
+                Ok(_) => decoded += 1,
+                Err(_) => rejected += 1,
+            }
+        }
+        let stats = host.link_stats();
+        assert!(stats.dropped > 0 && stats.duplicated > 0 && stats.corrupted > 0);
+        // Most corruption lands in a payload and still decodes, just to the wrong values.
+        assert!(decoded > rejected);
+        assert!(host.vehicles()[0].connected);
+        assert_ne!(0, host.vehicle("skull").unwrap().speed_mm_per_sec());
+    }
+}
@@ -0,0 +1,315 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::sim::track::SimRng;
+
+pub const SIM_DEFAULT_LINK_SEED: u64 = 0x626c65;
+
+// How long a frame spends between being written and arriving. Delays are in simulated time.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DelayDistribution {
+    Fixed(Duration),
+    Uniform { min: Duration, max: Duration },
+    // Samples below zero arrive straight away.
+    Normal { mean: Duration, std_dev: Duration },
+}
+
+impl Default for DelayDistribution {
+    fn default() -> Self {
+        DelayDistribution::Fixed(Duration::ZERO)
+    }
+}
+
+impl DelayDistribution {
+    pub fn is_zero(&self) -> bool {
+        match self {
+            DelayDistribution::Fixed(delay) => delay.is_zero(),
+            DelayDistribution::Uniform { max, .. } => max.is_zero(),
+            DelayDistribution::Normal { mean, std_dev } => mean.is_zero() && std_dev.is_zero(),
+        }
+    }
+
+    pub(crate) fn sample(&self, rng: &mut SimRng) -> Duration {
+        match self {
+            DelayDistribution::Fixed(delay) => *delay,
+            DelayDistribution::Uniform { min, max } => {
+                let (min, max) = (min.min(max), min.max(max));
+                *min + (*max - *min).mul_f32(rng.next_f32())
+            }
+            DelayDistribution::Normal { mean, std_dev } => {
+                // Box-Muller, u1 is kept off zero for the log.
+                let u1 = 1.0 - rng.next_f32();
+                let u2 = rng.next_f32();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+                let secs = mean.as_secs_f32() + z * std_dev.as_secs_f32();
+                Duration::from_secs_f32(secs.max(0.0))
+            }
+        }
+    }
+}
+
+// Chances, per frame, of the link losing it, delivering it twice, or changing one of its bytes.
+// Lost or duplicated frames happen on real connections, corruption mostly doesn't get past the
+// link layer checks but is a good test of the decoders.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct LinkFaults {
+    pub drop_rate: f32,
+    pub duplicate_rate: f32,
+    pub corrupt_rate: f32,
+}
+
+impl LinkFaults {
+    pub fn new() -> LinkFaults {
+        LinkFaults::default()
+    }
+
+    pub fn with_drop_rate(mut self, drop_rate: f32) -> LinkFaults {
+        self.drop_rate = drop_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_duplicate_rate(mut self, duplicate_rate: f32) -> LinkFaults {
+        self.duplicate_rate = duplicate_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_corrupt_rate(mut self, corrupt_rate: f32) -> LinkFaults {
+        self.corrupt_rate = corrupt_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.drop_rate <= 0.0 && self.duplicate_rate <= 0.0 && self.corrupt_rate <= 0.0
+    }
+
+    // What comes out the other end of the link for one frame sent.
+    pub(crate) fn apply(
+        &self,
+        mut data: Vec<u8>,
+        rng: &mut SimRng,
+        stats: &mut LinkStats,
+    ) -> Vec<Vec<u8>> {
+        stats.sent += 1;
+        if self.is_none() {
+            return vec![data];
+        }
+        if rng.next_f32() < self.drop_rate {
+            stats.dropped += 1;
+            return Vec::new();
+        }
+        if !data.is_empty() && rng.next_f32() < self.corrupt_rate {
+            let idx = ((rng.next_f32() * data.len() as f32) as usize).min(data.len() - 1);
+            // Never xor with zero, a corrupted frame is always different.
+            data[idx] ^= 1 + (rng.next_f32() * 255.0) as u8;
+            stats.corrupted += 1;
+        }
+        if rng.next_f32() < self.duplicate_rate {
+            stats.duplicated += 1;
+            return vec![data.clone(), data];
+        }
+        vec![data]
+    }
+}
+
+// Frames the link has handled so far, across both directions.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct LinkStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub corrupted: u64,
+}
+
+// Timing and reliability of the radio link between a simulated host and its vehicles. Frames to
+// and from a vehicle stay in order however much they are jittered, as they would over a BLE
+// connection.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct LinkConditions {
+    pub command_delay: DelayDistribution,
+    pub notification_delay: DelayDistribution,
+    pub command_faults: LinkFaults,
+    pub notification_faults: LinkFaults,
+}
+
+impl LinkConditions {
+    // Frames arrive as soon as they are sent.
+    pub fn new() -> LinkConditions {
+        LinkConditions::default()
+    }
+
+    // A connection with a 30ms interval. Most frames go out on the next connection event, a few
+    // miss it and wait for the one after.
+    pub fn ble() -> LinkConditions {
+        let delay = DelayDistribution::Normal {
+            mean: Duration::from_millis(30),
+            std_dev: Duration::from_millis(10),
+        };
+        LinkConditions {
+            command_delay: delay.clone(),
+            notification_delay: delay,
+            ..LinkConditions::default()
+        }
+    }
+
+    pub fn with_command_delay(mut self, command_delay: DelayDistribution) -> LinkConditions {
+        self.command_delay = command_delay;
+        self
+    }
+
+    pub fn with_notification_delay(
+        mut self,
+        notification_delay: DelayDistribution,
+    ) -> LinkConditions {
+        self.notification_delay = notification_delay;
+        self
+    }
+
+    // The same faults in both directions.
+    pub fn with_faults(self, faults: LinkFaults) -> LinkConditions {
+        self.with_command_faults(faults.clone())
+            .with_notification_faults(faults)
+    }
+
+    pub fn with_command_faults(mut self, command_faults: LinkFaults) -> LinkConditions {
+        self.command_faults = command_faults;
+        self
+    }
+
+    pub fn with_notification_faults(mut self, notification_faults: LinkFaults) -> LinkConditions {
+        self.notification_faults = notification_faults;
+        self
+    }
+}
+
+// A frame on its way, due once the simulation clock reaches `due`.
+#[derive(Debug, Clone)]
+pub(crate) struct InFlight {
+    pub(crate) due: Duration,
+    pub(crate) data: Vec<u8>,
+}
+
+pub(crate) struct SimulatedLink {
+    pub(crate) conditions: LinkConditions,
+    rng: SimRng,
+    pub(crate) now: Duration,
+    pub(crate) stats: LinkStats,
+}
+
+impl Default for SimulatedLink {
+    fn default() -> Self {
+        SimulatedLink {
+            conditions: LinkConditions::default(),
+            rng: SimRng::new(SIM_DEFAULT_LINK_SEED),
+            now: Duration::ZERO,
+            stats: LinkStats::default(),
+        }
+    }
+}
+
+impl SimulatedLink {
+    pub(crate) fn reseed(&mut self, seed: u64) {
+        self.rng = SimRng::new(seed);
+    }
+
+    pub(crate) fn transmit_command(&mut self, data: Vec<u8>) -> Vec<Vec<u8>> {
+        self.conditions
+            .command_faults
+            .apply(data, &mut self.rng, &mut self.stats)
+    }
+
+    pub(crate) fn transmit_notification(&mut self, data: Vec<u8>) -> Vec<Vec<u8>> {
+        self.conditions
+            .notification_faults
+            .apply(data, &mut self.rng, &mut self.stats)
+    }
+
+    // When a frame sent now behind `queued` arrives, never overtaking the frames ahead of it.
+    pub(crate) fn command_due(&mut self, queued: Option<&InFlight>) -> Duration {
+        let delay = self.conditions.command_delay.sample(&mut self.rng);
+        (self.now + delay).max(queued.map_or(Duration::ZERO, |frame| frame.due))
+    }
+
+    pub(crate) fn notification_due(&mut self, queued: Option<&InFlight>) -> Duration {
+        let delay = self.conditions.notification_delay.sample(&mut self.rng);
+        (self.now + delay).max(queued.map_or(Duration::ZERO, |frame| frame.due))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_distribution_test() {
+        let mut rng = SimRng::new(3);
+        let uniform = DelayDistribution::Uniform {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+        };
+        let normal = LinkConditions::ble().command_delay;
+        let mut total = Duration::ZERO;
+        for _ in 0..1000 {
+            let delay = uniform.sample(&mut rng);
+            assert!(Duration::from_millis(10) <= delay && delay <= Duration::from_millis(20));
+            total += normal.sample(&mut rng);
+        }
+        let mean = total / 1000;
+        assert!(Duration::from_millis(27) < mean && mean < Duration::from_millis(33));
+        assert!(DelayDistribution::default().is_zero());
+        assert!(!normal.is_zero());
+    }
+
+    #[test]
+    fn link_ordering_test() {
+        let mut link = SimulatedLink {
+            conditions: LinkConditions::new().with_command_delay(DelayDistribution::Uniform {
+                min: Duration::ZERO,
+                max: Duration::from_millis(100),
+            }),
+            ..SimulatedLink::default()
+        };
+        let mut queued: Option<InFlight> = None;
+        for _ in 0..100 {
+            let due = link.command_due(queued.as_ref());
+            if let Some(ahead) = &queued {
+                assert!(ahead.due <= due);
+            }
+            queued = Some(InFlight {
+                due,
+                data: Vec::new(),
+            });
+        }
+    }
+
+    #[test]
+    fn link_faults_test() {
+        let mut rng = SimRng::new(5);
+        let mut stats = LinkStats::default();
+        let frame = vec![0x03, 0x19, 0x76, 0x26];
+        assert_eq!(
+            vec![frame.clone()],
+            LinkFaults::new().apply(frame.clone(), &mut rng, &mut stats)
+        );
+
+        let lossy = LinkFaults::new().with_drop_rate(1.0);
+        assert!(lossy.apply(frame.clone(), &mut rng, &mut stats).is_empty());
+        let echo = LinkFaults::new().with_duplicate_rate(1.0);
+        assert_eq!(2, echo.apply(frame.clone(), &mut rng, &mut stats).len());
+        let noisy = LinkFaults::new().with_corrupt_rate(1.0);
+        for _ in 0..100 {
+            let received = noisy.apply(frame.clone(), &mut rng, &mut stats);
+            assert_eq!(1, received.len());
+            assert_eq!(frame.len(), received[0].len());
+            assert_ne!(frame, received[0]);
+        }
+        assert_eq!(
+            LinkStats {
+                sent: 103,
+                dropped: 1,
+                duplicated: 1,
+                corrupted: 100,
+            },
+            stats
+        );
+    }
+}
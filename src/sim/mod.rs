@@ -0,0 +1,8 @@
+// Protocol level simulation, vehicles that take the same command frames and send the same
+// notifications as the real thing. Everything above the transport can be run against it.
+
+pub mod host;
+pub mod link;
+pub mod race;
+pub mod track;
+pub mod vehicle;
@@ -0,0 +1,498 @@
+use scroll::Pread;
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::host::{FleetHost, HostNotification};
+use crate::pool::{FramePool, PoolStats};
+use crate::protocol::{AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgType, WIRE_ENDIAN};
+use crate::race::leaderboard::Leaderboard;
+use crate::race::RaceEvent;
+use crate::sim::host::SimulatedHost;
+use crate::sim::link::LinkConditions;
+use crate::sim::track::TrackLayout;
+use crate::sim::vehicle::SimulatedVehicle;
+use crate::snapshot::render_event;
+use crate::AnkiVehicleData;
+
+// Whole races on the simulator, without a window or a clock to wait on. Each driver is a simple
+// rule set: one speed for straights, another for curves, a lane to hold and a marshal who puts it
+// back on the track when it comes off. Races are deterministic for a given seed, so strategies can
+// be compared by sweeping their parameters over a batch of races.
+
+pub const SIM_DEFAULT_RACE_SEED: u64 = 0x72616365;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AiDriver {
+    pub name: String,
+    pub straight_speed_mm_per_sec: u16,
+    // Used on curves and on the straight before one. Above the curve's speed limit the vehicle
+    // risks coming off.
+    pub curve_speed_mm_per_sec: u16,
+    pub accel_mm_per_sec2: u16,
+    pub lane_offset_mm: f32,
+}
+
+impl AiDriver {
+    pub fn new(name: &str) -> AiDriver {
+        AiDriver {
+            name: name.to_string(),
+            straight_speed_mm_per_sec: 1000,
+            curve_speed_mm_per_sec: 800,
+            accel_mm_per_sec2: 1500,
+            lane_offset_mm: 0.0,
+        }
+    }
+
+    pub fn with_straight_speed(mut self, straight_speed_mm_per_sec: u16) -> AiDriver {
+        self.straight_speed_mm_per_sec = straight_speed_mm_per_sec;
+        self
+    }
+
+    pub fn with_curve_speed(mut self, curve_speed_mm_per_sec: u16) -> AiDriver {
+        self.curve_speed_mm_per_sec = curve_speed_mm_per_sec;
+        self
+    }
+
+    pub fn with_accel(mut self, accel_mm_per_sec2: u16) -> AiDriver {
+        self.accel_mm_per_sec2 = accel_mm_per_sec2;
+        self
+    }
+
+    pub fn with_lane_offset(mut self, lane_offset_mm: f32) -> AiDriver {
+        self.lane_offset_mm = lane_offset_mm;
+        self
+    }
+
+    fn target_speed(&self, track: &TrackLayout, road_piece_idx: usize) -> u16 {
+        let next = (road_piece_idx + 1) % track.len();
+        let curve = [road_piece_idx, next]
+            .iter()
+            .any(|&idx| track.piece(idx).speed_limit_mm_per_sec.is_some());
+        if curve {
+            self.curve_speed_mm_per_sec
+        } else {
+            self.straight_speed_mm_per_sec
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceSimConfig {
+    pub drivers: Vec<AiDriver>,
+    pub laps: u16,
+    pub track: TrackLayout,
+    pub link: LinkConditions,
+    pub seed: u64,
+    pub tick: Duration,
+    // Races still going after this are stopped, whoever hasn't finished is classified as they
+    // stand.
+    pub max_duration: Duration,
+    // How long the marshal takes to put a vehicle back on the track.
+    pub recovery_time: Duration,
+}
+
+impl RaceSimConfig {
+    pub fn new(laps: u16) -> RaceSimConfig {
+        RaceSimConfig {
+            drivers: Vec::new(),
+            laps,
+            track: TrackLayout::oval(),
+            link: LinkConditions::new(),
+            seed: SIM_DEFAULT_RACE_SEED,
+            tick: Duration::from_millis(10),
+            max_duration: Duration::from_secs(30 * 60),
+            recovery_time: Duration::from_secs(3),
+        }
+    }
+
+    pub fn with_driver(mut self, driver: AiDriver) -> RaceSimConfig {
+        self.drivers.push(driver);
+        self
+    }
+
+    pub fn with_track(mut self, track: TrackLayout) -> RaceSimConfig {
+        self.track = track;
+        self
+    }
+
+    pub fn with_link(mut self, link: LinkConditions) -> RaceSimConfig {
+        self.link = link;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> RaceSimConfig {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> RaceSimConfig {
+        self.max_duration = max_duration;
+        self
+    }
+}
+
+// A race event and the race time it happened at.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceLogEntry {
+    pub at: Duration,
+    pub event: RaceEvent,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceClassification {
+    pub vehicle: String,
+    pub position: usize,
+    pub laps: u16,
+    // Race time when the last lap was completed, None for vehicles that didn't finish.
+    pub finish_time: Option<Duration>,
+    pub best_lap_time: Option<Duration>,
+    pub delocalizations: u32,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RaceResult {
+    pub elapsed: Duration,
+    pub events: Vec<RaceLogEntry>,
+    pub classification: Vec<RaceClassification>,
+    // How well telemetry buffers were reused over the race.
+    pub frame_pool: PoolStats,
+}
+
+impl RaceResult {
+    pub fn winner(&self) -> Option<&str> {
+        self.events.iter().find_map(|entry| match &entry.event {
+            RaceEvent::Winner { vehicle } => Some(vehicle.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn vehicle(&self, vehicle: &str) -> Option<&RaceClassification> {
+        self.classification
+            .iter()
+            .find(|classified| classified.vehicle == vehicle)
+    }
+}
+
+// The event log, one line per event, in the same form as fleet snapshots.
+impl fmt::Display for RaceResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.events {
+            writeln!(
+                f,
+                "{:>5}.{:03}s {}",
+                entry.at.as_secs(),
+                entry.at.subsec_millis(),
+                render_event(&entry.event)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Racer {
+    driver: AiDriver,
+    data: AnkiVehicleData,
+    commanded_speed: Option<u16>,
+    delocalized_at: Option<Duration>,
+    delocalizations: u32,
+    finish_time: Option<Duration>,
+}
+
+pub fn run_race(config: &RaceSimConfig) -> RaceResult {
+    let pool = Arc::new(FramePool::new());
+    let host = SimulatedHost::new()
+        .with_link(config.link.clone())
+        .with_link_seed(config.seed)
+        .with_frame_pool(pool.clone());
+    for (idx, driver) in config.drivers.iter().enumerate() {
+        let vehicle = SimulatedVehicle::new()
+            .with_track(config.track.clone())
+            .with_seed(config.seed.wrapping_add(idx as u64 + 1));
+        host.add_vehicle(&driver.name, &driver.name, vehicle);
+    }
+    RaceSim::new(config, host, pool).run()
+}
+
+// Runs every race, spread over the available cores. Results come back in the order of `configs`.
+pub fn run_batch(configs: &[RaceSimConfig]) -> Vec<RaceResult> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(configs.len().max(1));
+    let chunk = configs.len().div_ceil(workers).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = configs
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(run_race).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Race simulation panicked"))
+            .collect()
+    })
+}
+
+struct RaceSim<'a> {
+    config: &'a RaceSimConfig,
+    host: SimulatedHost,
+    notifications: Receiver<HostNotification>,
+    pool: Arc<FramePool>,
+    racers: Vec<Racer>,
+    leaderboard: Leaderboard,
+    // Race time is laid over a real instant for the race controllers that want one.
+    start: Instant,
+    now: Duration,
+    events: Vec<RaceLogEntry>,
+    finished: usize,
+}
+
+impl<'a> RaceSim<'a> {
+    fn new(config: &'a RaceSimConfig, host: SimulatedHost, pool: Arc<FramePool>) -> RaceSim<'a> {
+        let notifications = host.subscribe();
+        RaceSim {
+            config,
+            host,
+            notifications,
+            pool,
+            racers: config
+                .drivers
+                .iter()
+                .map(|driver| Racer {
+                    driver: driver.clone(),
+                    data: AnkiVehicleData::default(),
+                    commanded_speed: None,
+                    delocalized_at: None,
+                    delocalizations: 0,
+                    finish_time: None,
+                })
+                .collect(),
+            leaderboard: Leaderboard::new(config.drivers.iter().map(|driver| driver.name.as_str())),
+            start: Instant::now(),
+            now: Duration::ZERO,
+            events: Vec::new(),
+            finished: 0,
+        }
+    }
+
+    fn run(mut self) -> RaceResult {
+        for racer in &mut self.racers {
+            let name = &racer.driver.name;
+            // The simulated host only fails for vehicles it doesn't know, and it knows them all.
+            let _ = self.host.connect(name);
+            for command in racer.data.configure() {
+                let _ = self.host.send(name, command.into());
+            }
+            let lane = AnkiVehicleData::change_lane(300, 2500, racer.driver.lane_offset_mm);
+            let _ = self.host.send(name, lane.into());
+        }
+        for idx in 0..self.racers.len() {
+            self.drive(idx);
+        }
+
+        while self.finished < self.racers.len() && self.now < self.config.max_duration {
+            self.host.step(self.config.tick);
+            self.now += self.config.tick;
+            while let Ok(notification) = self.notifications.try_recv() {
+                self.receive(&notification);
+                self.pool.recycle(notification.data);
+            }
+            for idx in 0..self.racers.len() {
+                let recovered = self.racers[idx]
+                    .delocalized_at
+                    .is_some_and(|at| self.now >= at + self.config.recovery_time);
+                if recovered {
+                    self.racers[idx].delocalized_at = None;
+                    self.racers[idx].commanded_speed = None;
+                    self.drive(idx);
+                }
+            }
+        }
+        for racer in &self.racers {
+            let _ = self
+                .host
+                .send(&racer.driver.name, AnkiVehicleData::set_speed(0, 0).into());
+        }
+        self.result()
+    }
+
+    fn receive(&mut self, notification: &HostNotification) {
+        let Some(idx) = self
+            .racers
+            .iter()
+            .position(|racer| racer.driver.name == notification.vehicle)
+        else {
+            return;
+        };
+        match self.racers[idx]
+            .data
+            .process_notification(&notification.data)
+        {
+            Ok(AnkiVehicleMsgType::V2CLocalisationPositionUpdate) => {
+                let Ok(update) = notification
+                    .data
+                    .pread_with::<AnkiVehicleMsgLocalisationPositionUpdate>(0, WIRE_ENDIAN)
+                else {
+                    return;
+                };
+                let event = self.leaderboard.process_position_update(
+                    &notification.vehicle,
+                    &update,
+                    self.start + self.now,
+                );
+                if let Some(event) = event {
+                    self.lap_completed(idx, event);
+                }
+            }
+            Ok(AnkiVehicleMsgType::V2CLocalisationTransitionUpdate) => self.drive(idx),
+            Ok(AnkiVehicleMsgType::V2CVehicleDelocalized) => {
+                let racer = &mut self.racers[idx];
+                racer.delocalized_at = Some(self.now);
+                racer.delocalizations += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn lap_completed(&mut self, idx: usize, event: RaceEvent) {
+        let finished =
+            matches!(event, RaceEvent::LapCompleted { lap, .. } if lap >= self.config.laps);
+        self.log(event);
+        if !finished || self.racers[idx].finish_time.is_some() {
+            return;
+        }
+        let name = self.racers[idx].driver.name.clone();
+        if self.finished == 0 {
+            self.log(RaceEvent::Winner {
+                vehicle: name.as_str().into(),
+            });
+        }
+        self.finished += 1;
+        self.racers[idx].finish_time = Some(self.now);
+        let _ = self
+            .host
+            .send(&name, AnkiVehicleData::set_speed(0, 1000).into());
+        self.log(RaceEvent::Parked {
+            vehicle: name.into(),
+        });
+    }
+
+    // Sets the speed for where the vehicle is on the track, unless it is off it or done.
+    fn drive(&mut self, idx: usize) {
+        let track = &self.config.track;
+        let racer = &mut self.racers[idx];
+        if racer.delocalized_at.is_some() || racer.finish_time.is_some() {
+            return;
+        }
+        let road_piece_idx = (racer.data.road_piece_idx.max(0) as usize) % track.len();
+        let speed = racer.driver.target_speed(track, road_piece_idx);
+        if racer.commanded_speed == Some(speed) {
+            return;
+        }
+        racer.commanded_speed = Some(speed);
+        let data = AnkiVehicleData::set_speed(speed as i16, racer.driver.accel_mm_per_sec2 as i16);
+        let _ = self.host.send(&racer.driver.name, data.into());
+    }
+
+    fn log(&mut self, event: RaceEvent) {
+        self.events.push(RaceLogEntry {
+            at: self.now,
+            event,
+        });
+    }
+
+    // Finishers in the order they finished, then everyone else as the leaderboard has them.
+    fn result(self) -> RaceResult {
+        let mut order: Vec<&Racer> = self.racers.iter().collect();
+        order.sort_by_key(|racer| {
+            (
+                racer.finish_time.unwrap_or(Duration::MAX),
+                self.leaderboard.position(&racer.driver.name),
+            )
+        });
+        let classification = order
+            .iter()
+            .enumerate()
+            .map(|(idx, racer)| {
+                let standing = self.leaderboard.standing(&racer.driver.name);
+                RaceClassification {
+                    vehicle: racer.driver.name.as_str().into(),
+                    position: idx + 1,
+                    laps: standing.map_or(0, |standing| standing.laps),
+                    finish_time: racer.finish_time,
+                    best_lap_time: standing.and_then(|standing| standing.best_lap_time),
+                    delocalizations: racer.delocalizations,
+                }
+            })
+            .collect();
+        RaceResult {
+            elapsed: self.now,
+            events: self.events,
+            classification,
+            frame_pool: self.pool.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn race() -> RaceSimConfig {
+        RaceSimConfig::new(3)
+            .with_driver(AiDriver::new("skull").with_lane_offset(-68.0))
+            .with_driver(
+                AiDriver::new("nuke")
+                    .with_straight_speed(700)
+                    .with_curve_speed(600)
+                    .with_lane_offset(68.0),
+            )
+    }
+
+    #[test]
+    fn run_race_test() {
+        let result = run_race(&race());
+        assert_eq!(Some("skull"), result.winner());
+        let skull = result.vehicle("skull").unwrap();
+        let nuke = result.vehicle("nuke").unwrap();
+        assert_eq!((1, 3), (skull.position, skull.laps));
+        assert_eq!((2, 3), (nuke.position, nuke.laps));
+        assert!(skull.finish_time.unwrap() < nuke.finish_time.unwrap());
+        assert!(nuke.finish_time.unwrap() <= result.elapsed);
+        // Three laps each, the win and both vehicles parking.
+        assert_eq!(9, result.events.len());
+        // Only the frames on the air at any one time ever need a buffer of their own.
+        assert!(
+            result.frame_pool.hit_rate() > 0.99,
+            "{:?}",
+            result.frame_pool
+        );
+
+        let log = result.to_string();
+        assert!(log.contains("s lap_completed skull lap=1 lap_time_ms="));
+        assert!(log.contains("s winner skull\n"));
+        assert_eq!(run_race(&race()).to_string(), log);
+    }
+
+    #[test]
+    fn run_batch_test() {
+        // Too fast for the curves, the reckless driver spends the race being put back on.
+        let configs: Vec<RaceSimConfig> = [800, 1400]
+            .into_iter()
+            .map(|curve_speed| {
+                RaceSimConfig::new(5)
+                    .with_driver(AiDriver::new("skull").with_curve_speed(curve_speed))
+                    .with_max_duration(Duration::from_secs(5 * 60))
+            })
+            .collect();
+        let results = run_batch(&configs);
+        assert_eq!(2, results.len());
+        let careful = results[0].vehicle("skull").unwrap();
+        let reckless = results[1].vehicle("skull").unwrap();
+        assert_eq!(0, careful.delocalizations);
+        assert!(reckless.delocalizations > 0);
+        assert!(careful.finish_time.unwrap() < reckless.finish_time.unwrap_or(Duration::MAX));
+        assert_eq!(results[0], run_race(&configs[0]));
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+// Delocalizations per second when a vehicle is going twice a piece's speed limit. The chance
+// scales with how far over the limit it is.
+pub const SIM_DEFAULT_DELOCALIZATION_RATE: f32 = 2.0;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrackPiece {
+    pub road_piece_id: u8,
+    // Along the centre line.
+    pub length_mm: f32,
+    // Fastest a vehicle can take the piece without risking coming off, None on straights.
+    pub speed_limit_mm_per_sec: Option<u16>,
+}
+
+impl TrackPiece {
+    pub fn straight(road_piece_id: u8, length_mm: f32) -> TrackPiece {
+        TrackPiece {
+            road_piece_id,
+            length_mm,
+            speed_limit_mm_per_sec: None,
+        }
+    }
+
+    pub fn curve(road_piece_id: u8, length_mm: f32, speed_limit_mm_per_sec: u16) -> TrackPiece {
+        TrackPiece {
+            road_piece_id,
+            length_mm,
+            speed_limit_mm_per_sec: Some(speed_limit_mm_per_sec),
+        }
+    }
+}
+
+// The pieces a simulated vehicle drives over, in driving order, and how unforgiving they are.
+// The last piece joins back onto the first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrackLayout {
+    pieces: Vec<TrackPiece>,
+    delocalization_rate: f32,
+}
+
+impl Default for TrackLayout {
+    fn default() -> Self {
+        Self::oval()
+    }
+}
+
+impl TrackLayout {
+    // None for a track without any pieces, or with a piece of no length.
+    pub fn new(pieces: Vec<TrackPiece>) -> Option<TrackLayout> {
+        if pieces.is_empty() || pieces.iter().any(|piece| piece.length_mm <= 0.0) {
+            return None;
+        }
+        Some(TrackLayout {
+            pieces,
+            delocalization_rate: SIM_DEFAULT_DELOCALIZATION_RATE,
+        })
+    }
+
+    // The starter kit oval, starting with the finish line.
+    pub fn oval() -> TrackLayout {
+        TrackLayout {
+            pieces: vec![
+                TrackPiece::straight(34, 340.0),
+                TrackPiece::straight(33, 220.0),
+                TrackPiece::curve(17, 440.0, 900),
+                TrackPiece::curve(20, 440.0, 900),
+                TrackPiece::straight(36, 560.0),
+                TrackPiece::curve(18, 440.0, 900),
+                TrackPiece::curve(23, 440.0, 900),
+                TrackPiece::straight(39, 560.0),
+            ],
+            delocalization_rate: SIM_DEFAULT_DELOCALIZATION_RATE,
+        }
+    }
+
+    // Zero turns delocalizations off.
+    pub fn with_delocalization_rate(mut self, delocalization_rate: f32) -> TrackLayout {
+        self.delocalization_rate = delocalization_rate.max(0.0);
+        self
+    }
+
+    pub fn pieces(&self) -> &[TrackPiece] {
+        &self.pieces
+    }
+
+    pub fn piece(&self, road_piece_idx: usize) -> &TrackPiece {
+        &self.pieces[road_piece_idx % self.pieces.len()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    pub fn lap_length_mm(&self) -> f32 {
+        self.pieces.iter().map(|piece| piece.length_mm).sum()
+    }
+
+    // Chance of coming off the piece while driving it at `speed_mm_per_sec` for `elapsed`.
+    pub fn delocalization_probability(
+        &self,
+        road_piece_idx: usize,
+        speed_mm_per_sec: f32,
+        elapsed: Duration,
+    ) -> f32 {
+        let Some(limit) = self.piece(road_piece_idx).speed_limit_mm_per_sec else {
+            return 0.0;
+        };
+        let limit = (limit as f32).max(1.0);
+        if speed_mm_per_sec <= limit {
+            return 0.0;
+        }
+        let excess = (speed_mm_per_sec - limit) / limit;
+        1.0 - (-self.delocalization_rate * excess * elapsed.as_secs_f32()).exp()
+    }
+}
+
+// Small seeded generator, so a simulation run can be repeated exactly.
+#[derive(Debug, Clone)]
+pub(crate) struct SimRng(u64);
+
+impl SimRng {
+    pub(crate) fn new(seed: u64) -> SimRng {
+        // xorshift gets stuck on zero.
+        SimRng(seed.max(1))
+    }
+
+    // Uniform in [0, 1).
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_layout_test() {
+        let oval = TrackLayout::oval();
+        assert_eq!(8, oval.len());
+        assert_eq!(3440.0, oval.lap_length_mm());
+        assert_eq!(34, oval.piece(8).road_piece_id);
+        assert_eq!(None, TrackLayout::new(Vec::new()));
+        assert_eq!(None, TrackLayout::new(vec![TrackPiece::straight(36, 0.0)]));
+
+        let elapsed = Duration::from_millis(100);
+        assert_eq!(0.0, oval.delocalization_probability(0, 2000.0, elapsed));
+        assert_eq!(0.0, oval.delocalization_probability(2, 900.0, elapsed));
+        let over = oval.delocalization_probability(2, 1000.0, elapsed);
+        let way_over = oval.delocalization_probability(2, 1800.0, elapsed);
+        assert!(0.0 < over && over < way_over && way_over < 1.0);
+        let off = oval.with_delocalization_rate(0.0);
+        assert_eq!(0.0, off.delocalization_probability(2, 1800.0, elapsed));
+    }
+
+    #[test]
+    fn sim_rng_test() {
+        let mut a = SimRng::new(7);
+        let mut b = SimRng::new(7);
+        for _ in 0..1000 {
+            let x = a.next_f32();
+            assert_eq!(x, b.next_f32());
+            assert!((0.0..1.0).contains(&x));
+        }
+        let mut zero = SimRng::new(0);
+        assert!((0..10).any(|_| zero.next_f32() > 0.0));
+    }
+}
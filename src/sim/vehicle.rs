@@ -0,0 +1,699 @@
+use scroll::{Pread, Pwrite};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::AnkiError;
+use crate::io::transport::VehicleTransport;
+use crate::pool::FramePool;
+use crate::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgType, ANKI_VEHICLE_MSG_BASE_SIZE, WIRE_ENDIAN,
+};
+use crate::sim::track::{SimRng, TrackLayout};
+use crate::validation::notification_size;
+
+// Battery readings are in mV, a charged car reports a little over 4 V.
+pub const SIM_BATTERY_FULL: u16 = 4200;
+pub const SIM_BATTERY_EMPTY: u16 = 3300;
+// Below this the vehicle reports a low battery.
+pub const SIM_BATTERY_LOW: u16 = 3500;
+pub const SIM_FIRMWARE_VERSION: u16 = 0x2676;
+pub const SIM_DEFAULT_SEED: u64 = 0x616e6b69;
+
+// Distance between the location codes printed on the track.
+const LOCATION_SPACING_MM: f32 = 40.0;
+
+// mV per second, idle and per m/s of speed. A full battery lasts around half an hour of racing.
+const BATTERY_IDLE_DRAIN: f32 = 0.05;
+const BATTERY_DRIVE_DRAIN: f32 = 0.5;
+// mV per second on the charger, flat to full in about eight minutes.
+const BATTERY_CHARGE_RATE: f32 = 2.0;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct ChargerState {
+    on_charger: bool,
+    battery_low: bool,
+    battery_full: bool,
+}
+
+// A vehicle on a track, without the radio. C2V frames go in through `handle_command`, time moves
+// on through `advance` and the V2C frames a real vehicle would have sent in the meantime are
+// queued up for `poll_notification`.
+#[derive(Debug, Clone)]
+pub struct SimulatedVehicle {
+    sdk_mode: bool,
+    version: u16,
+    battery_level: f32,
+    battery_drain: f32,
+    on_charger: bool,
+    // What the last charger info said, a new one is sent when this changes.
+    charger_state: ChargerState,
+
+    speed_mm_per_sec: f32,
+    target_speed_mm_per_sec: f32,
+    accel_mm_per_sec2: f32,
+    respect_road_piece_speed_limit: bool,
+
+    offset_from_road_centre_mm: f32,
+    target_offset_from_road_centre_mm: f32,
+    horizontal_speed_mm_per_sec: f32,
+    last_recv_lane_change_id: u8,
+    last_exec_lane_change_id: u8,
+    had_lane_change_activity: bool,
+
+    track: TrackLayout,
+    rng: SimRng,
+    delocalized: bool,
+    road_piece_idx: usize,
+    road_piece_idx_prev: usize,
+    distance_mm: f32,
+    location_id: u8,
+
+    notifications: VecDeque<Vec<u8>>,
+    frame_pool: Option<Arc<FramePool>>,
+}
+
+impl Default for SimulatedVehicle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedVehicle {
+    pub fn new() -> SimulatedVehicle {
+        SimulatedVehicle {
+            sdk_mode: false,
+            version: SIM_FIRMWARE_VERSION,
+            battery_level: SIM_BATTERY_FULL as f32,
+            battery_drain: 1.0,
+            on_charger: false,
+            charger_state: ChargerState {
+                on_charger: false,
+                battery_low: false,
+                battery_full: false,
+            },
+            speed_mm_per_sec: 0.0,
+            target_speed_mm_per_sec: 0.0,
+            accel_mm_per_sec2: 0.0,
+            respect_road_piece_speed_limit: false,
+            offset_from_road_centre_mm: 0.0,
+            target_offset_from_road_centre_mm: 0.0,
+            horizontal_speed_mm_per_sec: 0.0,
+            last_recv_lane_change_id: 0,
+            last_exec_lane_change_id: 0,
+            had_lane_change_activity: false,
+            track: TrackLayout::oval(),
+            rng: SimRng::new(SIM_DEFAULT_SEED),
+            delocalized: false,
+            road_piece_idx: 0,
+            road_piece_idx_prev: 0,
+            distance_mm: 0.0,
+            location_id: 0,
+            notifications: VecDeque::new(),
+            frame_pool: None,
+        }
+    }
+
+    pub fn with_battery_level(mut self, battery_level: u16) -> SimulatedVehicle {
+        self.battery_level = battery_level as f32;
+        self.charger_state = self.current_charger_state();
+        self
+    }
+
+    // Scales how fast the battery runs down, zero keeps it charged for long runs.
+    pub fn with_battery_drain(mut self, battery_drain: f32) -> SimulatedVehicle {
+        self.battery_drain = battery_drain.max(0.0);
+        self
+    }
+
+    pub fn with_version(mut self, version: u16) -> SimulatedVehicle {
+        self.version = version;
+        self
+    }
+
+    // Starts the vehicle on the first piece of `track`.
+    pub fn with_track(mut self, track: TrackLayout) -> SimulatedVehicle {
+        self.track = track;
+        self.road_piece_idx = 0;
+        self.road_piece_idx_prev = 0;
+        self.distance_mm = 0.0;
+        self
+    }
+
+    // Vehicles with the same seed, track and commands come off the track at the same moments.
+    pub fn with_seed(mut self, seed: u64) -> SimulatedVehicle {
+        self.rng = SimRng::new(seed);
+        self
+    }
+
+    // Notifications are written into buffers taken from `pool`, hand them back once decoded.
+    pub fn with_frame_pool(mut self, pool: Arc<FramePool>) -> SimulatedVehicle {
+        self.set_frame_pool(pool);
+        self
+    }
+
+    pub(crate) fn set_frame_pool(&mut self, pool: Arc<FramePool>) {
+        self.frame_pool = Some(pool);
+    }
+
+    pub fn track(&self) -> &TrackLayout {
+        &self.track
+    }
+
+    pub fn delocalized(&self) -> bool {
+        self.delocalized
+    }
+
+    pub fn sdk_mode(&self) -> bool {
+        self.sdk_mode
+    }
+
+    pub fn on_charger(&self) -> bool {
+        self.on_charger
+    }
+
+    // Stops the vehicle where it is and starts charging it, as if it had been lifted onto the
+    // charger. It won't drive until it's taken off again.
+    pub fn place_on_charger(&mut self) {
+        self.on_charger = true;
+        self.speed_mm_per_sec = 0.0;
+        self.target_speed_mm_per_sec = 0.0;
+        self.queue_charger_info_if_changed();
+    }
+
+    // Back on the track at the start of the piece it was taken from.
+    pub fn remove_from_charger(&mut self) {
+        self.on_charger = false;
+        self.delocalized = false;
+        self.distance_mm = 0.0;
+        self.location_id = 0;
+        self.queue_charger_info_if_changed();
+    }
+
+    pub fn battery_level(&self) -> u16 {
+        self.battery_level as u16
+    }
+
+    pub fn speed_mm_per_sec(&self) -> u16 {
+        self.speed_mm_per_sec as u16
+    }
+
+    pub fn offset_from_road_centre_mm(&self) -> f32 {
+        self.offset_from_road_centre_mm
+    }
+
+    pub fn road_piece_id(&self) -> u8 {
+        self.track.piece(self.road_piece_idx).road_piece_id
+    }
+
+    pub fn road_piece_idx(&self) -> i8 {
+        self.road_piece_idx as i8
+    }
+
+    // Applies a command frame the way the firmware would. Commands that don't change anything the
+    // simulation models, like lights, are accepted and ignored.
+    pub fn handle_command(&mut self, data: &[u8]) -> Result<AnkiVehicleMsgType, AnkiError> {
+        let msg = data.pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)?;
+        match msg.msg_id {
+            AnkiVehicleMsgType::C2VSDKMode => {
+                self.sdk_mode = data.pread_with::<u8>(2, WIRE_ENDIAN)? != 0;
+            }
+            AnkiVehicleMsgType::C2CPingRequest => {
+                self.queue(AnkiVehicleMsgType::V2CPingResponse, |_, _| Ok(()));
+            }
+            AnkiVehicleMsgType::C2VVersionRequest => {
+                let version = self.version;
+                self.queue(AnkiVehicleMsgType::V2CVersionResponse, |data, offset| {
+                    data.gwrite_with::<u16>(version, offset, WIRE_ENDIAN)?;
+                    Ok(())
+                });
+            }
+            AnkiVehicleMsgType::C2VBatteryLevelRequest => {
+                let battery_level = self.battery_level();
+                self.queue(
+                    AnkiVehicleMsgType::V2CBatteryLevelResponse,
+                    |data, offset| {
+                        data.gwrite_with::<u16>(battery_level, offset, WIRE_ENDIAN)?;
+                        Ok(())
+                    },
+                );
+            }
+            AnkiVehicleMsgType::C2VSetSpeed => {
+                let speed = data.pread_with::<i16>(2, WIRE_ENDIAN)?;
+                let accel = data.pread_with::<i16>(4, WIRE_ENDIAN)?;
+                self.target_speed_mm_per_sec = speed.max(0) as f32;
+                self.accel_mm_per_sec2 = accel.max(0) as f32;
+                self.respect_road_piece_speed_limit = data.pread_with::<u8>(6, WIRE_ENDIAN)? != 0;
+                // Someone has put it back on the track, it sets off from the start of the piece.
+                if self.delocalized {
+                    self.delocalized = false;
+                    self.distance_mm = 0.0;
+                    self.location_id = 0;
+                }
+            }
+            AnkiVehicleMsgType::C2VChangeLane => {
+                let horizontal_speed = data.pread_with::<u16>(2, WIRE_ENDIAN)?;
+                let offset = data.pread_with::<f32>(6, WIRE_ENDIAN)?;
+                self.horizontal_speed_mm_per_sec = horizontal_speed as f32;
+                self.target_offset_from_road_centre_mm = offset;
+                self.last_recv_lane_change_id = self.last_recv_lane_change_id.wrapping_add(1);
+                self.had_lane_change_activity = true;
+            }
+            AnkiVehicleMsgType::C2VCancelLaneChange => {
+                self.target_offset_from_road_centre_mm = self.offset_from_road_centre_mm;
+            }
+            // Tells the vehicle where it is, it doesn't move.
+            AnkiVehicleMsgType::C2VSetOffsetFromRoadCentre => {
+                let offset = data.pread_with::<f32>(2, WIRE_ENDIAN)?;
+                self.offset_from_road_centre_mm = offset;
+                self.target_offset_from_road_centre_mm = offset;
+            }
+            _ => {}
+        }
+        Ok(msg.msg_id)
+    }
+
+    // Moves the simulation on by `elapsed`, queueing a transition update for every road piece
+    // entered and a position update for every location code read. Going over a piece's speed
+    // limit risks coming off the track, a delocalized vehicle stays put until the next set speed.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f32();
+
+        if self.on_charger {
+            self.battery_level =
+                (self.battery_level + secs * BATTERY_CHARGE_RATE).min(SIM_BATTERY_FULL as f32);
+            self.queue_charger_info_if_changed();
+            return;
+        }
+        self.battery_level = (self.battery_level
+            - secs
+                * self.battery_drain
+                * (BATTERY_IDLE_DRAIN + BATTERY_DRIVE_DRAIN * self.speed_mm_per_sec / 1000.0))
+            .max(0.0);
+        if self.battery_level <= SIM_BATTERY_EMPTY as f32 {
+            self.target_speed_mm_per_sec = 0.0;
+            self.accel_mm_per_sec2 = 0.0;
+        }
+        self.queue_charger_info_if_changed();
+        if self.delocalized {
+            return;
+        }
+
+        let piece = self.track.piece(self.road_piece_idx);
+        let target_speed = match piece.speed_limit_mm_per_sec {
+            Some(limit) if self.respect_road_piece_speed_limit => {
+                self.target_speed_mm_per_sec.min(limit as f32)
+            }
+            _ => self.target_speed_mm_per_sec,
+        };
+        // No acceleration means the new speed applies straight away.
+        let previous_speed = self.speed_mm_per_sec;
+        let step = if self.accel_mm_per_sec2 > 0.0 {
+            self.accel_mm_per_sec2 * secs
+        } else {
+            f32::INFINITY
+        };
+        self.speed_mm_per_sec = approach(self.speed_mm_per_sec, target_speed, step);
+
+        let probability = self.track.delocalization_probability(
+            self.road_piece_idx,
+            self.speed_mm_per_sec,
+            elapsed,
+        );
+        if probability > 0.0 && self.rng.next_f32() < probability {
+            self.delocalized = true;
+            self.speed_mm_per_sec = 0.0;
+            self.target_speed_mm_per_sec = 0.0;
+            self.queue(AnkiVehicleMsgType::V2CVehicleDelocalized, |_, _| Ok(()));
+            return;
+        }
+        self.advance_lane_change(secs);
+
+        let mut travelled = (previous_speed + self.speed_mm_per_sec) / 2.0 * secs;
+        while travelled > 0.0 {
+            let length = self.track.piece(self.road_piece_idx).length_mm;
+            let step = travelled.min(length - self.distance_mm);
+            self.distance_mm += step;
+            travelled -= step;
+            if self.distance_mm >= length {
+                self.distance_mm = 0.0;
+                self.road_piece_idx_prev = self.road_piece_idx;
+                self.road_piece_idx = (self.road_piece_idx + 1) % self.track.len();
+                self.queue_transition_update();
+                self.location_id = 0;
+                self.queue_position_update();
+                continue;
+            }
+            let location_id = (self.distance_mm / LOCATION_SPACING_MM) as u8;
+            if location_id != self.location_id {
+                self.location_id = location_id;
+                self.queue_position_update();
+            }
+        }
+    }
+
+    pub fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        self.notifications.pop_front()
+    }
+
+    pub fn drain_notifications(&mut self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.notifications.drain(..)
+    }
+
+    fn advance_lane_change(&mut self, secs: f32) {
+        if self.offset_from_road_centre_mm == self.target_offset_from_road_centre_mm {
+            return;
+        }
+        let step = if self.horizontal_speed_mm_per_sec > 0.0 {
+            self.horizontal_speed_mm_per_sec * secs
+        } else {
+            f32::INFINITY
+        };
+        self.offset_from_road_centre_mm = approach(
+            self.offset_from_road_centre_mm,
+            self.target_offset_from_road_centre_mm,
+            step,
+        );
+        if self.offset_from_road_centre_mm == self.target_offset_from_road_centre_mm {
+            self.last_exec_lane_change_id = self.last_recv_lane_change_id;
+            let offset = self.offset_from_road_centre_mm;
+            let lane_change_id = self.last_exec_lane_change_id;
+            self.queue(
+                AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate,
+                |data, offset_| {
+                    data.gwrite_with::<f32>(offset, offset_, WIRE_ENDIAN)?;
+                    data.gwrite_with::<u8>(lane_change_id, offset_, WIRE_ENDIAN)?;
+                    Ok(())
+                },
+            );
+        }
+    }
+
+    // Full is only reported once charging has finished.
+    fn current_charger_state(&self) -> ChargerState {
+        ChargerState {
+            on_charger: self.on_charger,
+            battery_low: self.battery_level < SIM_BATTERY_LOW as f32,
+            battery_full: self.on_charger && self.battery_level >= SIM_BATTERY_FULL as f32,
+        }
+    }
+
+    fn queue_charger_info_if_changed(&mut self) {
+        let state = self.current_charger_state();
+        if state == self.charger_state {
+            return;
+        }
+        self.charger_state = state;
+        self.queue(AnkiVehicleMsgType::V2CChargerInfo, |data, offset| {
+            data.gwrite_with::<u8>(!state.on_charger as u8, offset, WIRE_ENDIAN)?;
+            data.gwrite_with::<u8>(state.on_charger as u8, offset, WIRE_ENDIAN)?;
+            data.gwrite_with::<u8>(state.battery_low as u8, offset, WIRE_ENDIAN)?;
+            data.gwrite_with::<u8>(state.battery_full as u8, offset, WIRE_ENDIAN)?;
+            Ok(())
+        });
+    }
+
+    fn queue_position_update(&mut self) {
+        let location_id = self.location_id;
+        let road_piece_id = self.road_piece_id();
+        let offset = self.offset_from_road_centre_mm;
+        let speed = self.speed_mm_per_sec();
+        let recv = self.last_recv_lane_change_id;
+        let exec = self.last_exec_lane_change_id;
+        let lane_change_speed = self.horizontal_speed_mm_per_sec as u16;
+        let desired_speed = self.target_speed_mm_per_sec as u16;
+        self.queue(
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate,
+            |data, offset_| {
+                data.gwrite_with::<u8>(location_id, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(road_piece_id, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<f32>(offset, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u16>(speed, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(0, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(recv, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(exec, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u16>(lane_change_speed, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u16>(desired_speed, offset_, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+    }
+
+    fn queue_transition_update(&mut self) {
+        let road_piece_idx = self.road_piece_idx();
+        let road_piece_idx_prev = self.road_piece_idx_prev as i8;
+        let offset = self.offset_from_road_centre_mm;
+        let recv = self.last_recv_lane_change_id;
+        let exec = self.last_exec_lane_change_id;
+        let lane_change_speed = self.horizontal_speed_mm_per_sec as u16;
+        let lane_change_activity = self.had_lane_change_activity as u8;
+        let wheel_dist_cm = (self.track.piece(self.road_piece_idx_prev).length_mm / 10.0) as u8;
+        self.had_lane_change_activity = false;
+        self.queue(
+            AnkiVehicleMsgType::V2CLocalisationTransitionUpdate,
+            |data, offset_| {
+                data.gwrite_with::<i8>(road_piece_idx, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<i8>(road_piece_idx_prev, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<f32>(offset, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(recv, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(exec, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u16>(lane_change_speed, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<i8>(0, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(lane_change_activity, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(0, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(0, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(wheel_dist_cm, offset_, WIRE_ENDIAN)?;
+                data.gwrite_with::<u8>(wheel_dist_cm, offset_, WIRE_ENDIAN)?;
+                Ok(())
+            },
+        );
+    }
+
+    fn queue<F>(&mut self, msg_id: AnkiVehicleMsgType, payload: F)
+    where
+        F: FnOnce(&mut [u8], &mut usize) -> Result<(), scroll::Error>,
+    {
+        let data = match &self.frame_pool {
+            Some(pool) => pool.take(),
+            None => Vec::new(),
+        };
+        self.notifications
+            .push_back(write_notification(data, msg_id, payload));
+    }
+}
+
+// Commands are handled as they're sent, notifications come out as `advance` produces them.
+impl VehicleTransport for SimulatedVehicle {
+    type Error = AnkiError;
+
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.handle_command(data).map(|_| ())
+    }
+
+    fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        SimulatedVehicle::poll_notification(self)
+    }
+}
+
+// Writes the header for `msg_id`, then lets `payload` fill in the rest of the frame.
+pub(crate) fn encode_notification<F>(msg_id: AnkiVehicleMsgType, payload: F) -> Vec<u8>
+where
+    F: FnOnce(&mut [u8], &mut usize) -> Result<(), scroll::Error>,
+{
+    write_notification(Vec::new(), msg_id, payload)
+}
+
+// Same as `encode_notification`, reusing the allocation behind `data`.
+fn write_notification<F>(mut data: Vec<u8>, msg_id: AnkiVehicleMsgType, payload: F) -> Vec<u8>
+where
+    F: FnOnce(&mut [u8], &mut usize) -> Result<(), scroll::Error>,
+{
+    let size = notification_size(&msg_id).unwrap_or(ANKI_VEHICLE_MSG_BASE_SIZE);
+    data.clear();
+    data.resize(size, 0);
+    let offset = &mut 0;
+    data.gwrite_with::<u8>(size as u8 - 1, offset, WIRE_ENDIAN)
+        .and_then(|_| data.gwrite_with::<u8>(msg_id.into(), offset, WIRE_ENDIAN))
+        .and_then(|_| payload(&mut data, offset))
+        .expect("Failed to write simulated notification as bytes");
+    data
+}
+
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if (target - current).abs() <= step {
+        target
+    } else if target > current {
+        current + step
+    } else {
+        current - step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::protocol::{
+        anki_vehicle_msg_get_battery_level, ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE,
+    };
+    use crate::sim::track::TrackPiece;
+    use crate::AnkiVehicleData;
+
+    fn drive(
+        sim: &mut SimulatedVehicle,
+        vehicle: &mut AnkiVehicleData,
+        millis: u64,
+    ) -> Vec<AnkiVehicleMsgType> {
+        let mut received = Vec::new();
+        for _ in 0..millis / 10 {
+            sim.advance(Duration::from_millis(10));
+            for data in sim.drain_notifications() {
+                received.push(vehicle.process_notification(&data).unwrap());
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn sim_configure_test() {
+        let mut sim = SimulatedVehicle::new().with_battery_level(3900);
+        let mut vehicle = AnkiVehicleData::default();
+        for command in vehicle.configure() {
+            sim.handle_command(&command.encode()).unwrap();
+        }
+        assert!(sim.sdk_mode());
+        while let Some(data) = sim.poll_notification() {
+            vehicle.process_notification(&data).unwrap();
+        }
+        assert_eq!(SIM_FIRMWARE_VERSION, vehicle.version);
+        assert_eq!(3900, vehicle.battery_level);
+        assert!(sim.handle_command(&[]).is_err());
+    }
+
+    #[test]
+    fn sim_drive_test() {
+        let track = TrackLayout::oval().with_delocalization_rate(0.0);
+        let mut sim = SimulatedVehicle::new().with_track(track);
+        let mut vehicle = AnkiVehicleData::default();
+        sim.handle_command(&AnkiVehicleData::set_speed(1000, 2000))
+            .unwrap();
+        // Half a second to get up to speed, then a second at full speed, 1250 mm in total.
+        let received = drive(&mut sim, &mut vehicle, 1500);
+        assert_eq!(1000, sim.speed_mm_per_sec());
+        assert_eq!(1000, vehicle.speed_mm_per_sec);
+        assert_eq!(3, sim.road_piece_idx());
+        assert_eq!(3, vehicle.road_piece_idx);
+        assert_eq!(
+            3,
+            received
+                .iter()
+                .filter(|msg| **msg == AnkiVehicleMsgType::V2CLocalisationTransitionUpdate)
+                .count()
+        );
+        assert!(sim.battery_level() < SIM_BATTERY_FULL);
+
+        sim.handle_command(&AnkiVehicleData::change_lane(100, 1000, 50.0))
+            .unwrap();
+        let received = drive(&mut sim, &mut vehicle, 600);
+        assert_eq!(50.0, sim.offset_from_road_centre_mm());
+        assert_eq!(50.0, vehicle.offset_from_road_centre_mm);
+        assert!(received.contains(&AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate));
+    }
+
+    #[test]
+    fn sim_battery_test() {
+        let mut sim = SimulatedVehicle::new().with_battery_level(SIM_BATTERY_EMPTY + 1);
+        sim.handle_command(&AnkiVehicleData::set_speed(1000, 0))
+            .unwrap();
+        sim.advance(Duration::from_secs(1));
+        assert_eq!(1000, sim.speed_mm_per_sec());
+        sim.advance(Duration::from_secs(10));
+        assert_eq!(0, sim.speed_mm_per_sec());
+
+        let mut data = [0u8; ANKI_VEHICLE_MSG_BATTERY_LEVEL_REQUEST_SIZE];
+        data.pwrite_with(anki_vehicle_msg_get_battery_level(), 0, WIRE_ENDIAN)
+            .unwrap();
+        sim.handle_command(&data).unwrap();
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle
+            .process_notification(&sim.poll_notification().unwrap())
+            .unwrap();
+        assert!(vehicle.battery_level <= SIM_BATTERY_EMPTY);
+    }
+
+    #[test]
+    fn sim_charger_test() {
+        let mut sim = SimulatedVehicle::new()
+            .with_track(TrackLayout::oval().with_delocalization_rate(0.0))
+            .with_battery_level(SIM_BATTERY_LOW + 1);
+        let mut vehicle = AnkiVehicleData::default();
+        sim.handle_command(&AnkiVehicleData::set_speed(1000, 0))
+            .unwrap();
+        let received = drive(&mut sim, &mut vehicle, 3000);
+        assert_eq!(
+            1,
+            received
+                .iter()
+                .filter(|msg| **msg == AnkiVehicleMsgType::V2CChargerInfo)
+                .count()
+        );
+        assert!(vehicle.advertised_state().low_battery);
+
+        sim.place_on_charger();
+        assert_eq!(0, sim.speed_mm_per_sec());
+        let received = drive(&mut sim, &mut vehicle, 600_000);
+        assert!(!received.contains(&AnkiVehicleMsgType::V2CLocalisationPositionUpdate));
+        assert_eq!(SIM_BATTERY_FULL, sim.battery_level());
+        assert!(vehicle.is_on_charger());
+        assert!(vehicle.advertised_state().full_battery);
+        assert!(!vehicle.advertised_state().low_battery);
+
+        sim.remove_from_charger();
+        vehicle
+            .process_notification(&sim.poll_notification().unwrap())
+            .unwrap();
+        assert!(!vehicle.is_on_charger());
+        assert!(!sim.on_charger());
+    }
+
+    #[test]
+    fn sim_delocalization_test() {
+        let track = TrackLayout::new(vec![
+            TrackPiece::straight(36, 560.0),
+            TrackPiece::curve(17, 440.0, 500),
+        ])
+        .unwrap();
+        let mut sim = SimulatedVehicle::new().with_track(track.clone());
+        let mut vehicle = AnkiVehicleData::default();
+        sim.handle_command(&AnkiVehicleData::set_speed(1500, 0))
+            .unwrap();
+        let received = drive(&mut sim, &mut vehicle, 10_000);
+        assert!(sim.delocalized());
+        assert_eq!(0, sim.speed_mm_per_sec());
+        assert_eq!(17, sim.road_piece_id());
+        assert_eq!(
+            Some(&AnkiVehicleMsgType::V2CVehicleDelocalized),
+            received.last()
+        );
+        assert!(drive(&mut sim, &mut vehicle, 1000).is_empty());
+
+        // Back on the track, and this time keeping to the limit in the curve.
+        let mut speed = AnkiVehicleData::set_speed(1500, 0).to_vec();
+        speed[6] = 1;
+        sim.handle_command(&speed).unwrap();
+        assert!(!sim.delocalized());
+        let received = drive(&mut sim, &mut vehicle, 10_000);
+        assert!(!received.contains(&AnkiVehicleMsgType::V2CVehicleDelocalized));
+
+        // The same seed comes off at the same moment.
+        let run = |seed| {
+            let mut sim = SimulatedVehicle::new()
+                .with_track(track.clone())
+                .with_seed(seed);
+            sim.handle_command(&AnkiVehicleData::set_speed(1500, 0))
+                .unwrap();
+            drive(&mut sim, &mut AnkiVehicleData::default(), 10_000).len()
+        };
+        assert_eq!(run(3), run(3));
+    }
+}
@@ -0,0 +1,392 @@
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::command::WireMessage;
+use crate::race::{RaceCommand, RaceEvent};
+use crate::script::{ScriptRunner, VehicleSnapshot};
+use crate::vehicle_id::VehicleId;
+use crate::AnkiVehicleData;
+
+// Snapshot testing for behaviour built on the vehicle state. The fleet's state after a run is
+// rendered as plain text, one field per line in a fixed order, so a change in behaviour shows up
+// as a readable diff. Times are relative and floats use their shortest exact form, the same run
+// always renders the same.
+//
+// The text can be handed straight to `insta::assert_snapshot!`, or checked against a file with
+// `assert_snapshot`, which reads and writes insta's `.snap` layout so `cargo insta review` works
+// on the results.
+
+// Set to overwrite snapshot files instead of failing on a mismatch.
+pub const SNAPSHOT_UPDATE_ENV: &str = "ANKI_UPDATE_SNAPSHOTS";
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct FleetSnapshot {
+    vehicles: Vec<(VehicleId, VehicleSnapshot)>,
+    events: Vec<RaceEvent>,
+    commands: Vec<RaceCommand>,
+    errors: usize,
+}
+
+impl FleetSnapshot {
+    pub fn new() -> FleetSnapshot {
+        FleetSnapshot::default()
+    }
+
+    // Vehicles are rendered in id order, whatever order they are added in.
+    pub fn with_vehicle(mut self, id: &str, data: &AnkiVehicleData) -> FleetSnapshot {
+        let idx = self
+            .vehicles
+            .partition_point(|(existing, _)| existing.as_str() < id);
+        self.vehicles
+            .insert(idx, (id.into(), VehicleSnapshot::from_vehicle(data)));
+        self
+    }
+
+    pub fn with_events(mut self, events: &[RaceEvent]) -> FleetSnapshot {
+        self.events.extend_from_slice(events);
+        self
+    }
+
+    pub fn with_commands(mut self, commands: &[RaceCommand]) -> FleetSnapshot {
+        self.commands.extend_from_slice(commands);
+        self
+    }
+
+    pub fn with_errors(mut self, errors: usize) -> FleetSnapshot {
+        self.errors = errors;
+        self
+    }
+
+    // Everything a script run ended up with.
+    pub fn from_runner(runner: &ScriptRunner) -> FleetSnapshot {
+        let mut snapshot = FleetSnapshot::new()
+            .with_events(runner.events())
+            .with_commands(runner.commands())
+            .with_errors(runner.errors().len());
+        snapshot.vehicles = runner
+            .vehicle_ids()
+            .filter_map(|id| Some((id.into(), runner.vehicle(id)?)))
+            .collect();
+        snapshot
+    }
+}
+
+impl fmt::Display for FleetSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "vehicles:")?;
+        for (id, vehicle) in &self.vehicles {
+            writeln!(f, "  {}:", id)?;
+            writeln!(f, "    name: {}", vehicle.name)?;
+            writeln!(f, "    version: {:#06x}", vehicle.version)?;
+            writeln!(f, "    battery_level: {}", vehicle.battery_level)?;
+            writeln!(f, "    speed_mm_per_sec: {}", vehicle.speed_mm_per_sec)?;
+            writeln!(
+                f,
+                "    offset_from_road_centre_mm: {:?}",
+                vehicle.offset_from_road_centre_mm
+            )?;
+            writeln!(f, "    location_id: {}", vehicle.location_id)?;
+            writeln!(f, "    road_piece_idx: {}", vehicle.road_piece_idx)?;
+            writeln!(
+                f,
+                "    road_piece_idx_prev: {}",
+                vehicle.road_piece_idx_prev
+            )?;
+            writeln!(f, "    intersection_code: {:?}", vehicle.intersection_code)?;
+        }
+        writeln!(f, "events:")?;
+        for event in &self.events {
+            writeln!(f, "  - {}", render_event(event))?;
+        }
+        writeln!(f, "commands:")?;
+        for command in &self.commands {
+            writeln!(
+                f,
+                "  - {}: {}",
+                command.vehicle,
+                hex(&command.command.encode())
+            )?;
+        }
+        writeln!(f, "errors: {}", self.errors)
+    }
+}
+
+// Incident times are instants, only where and who make it into the snapshot.
+pub(crate) fn render_event(event: &RaceEvent) -> String {
+    match event {
+        RaceEvent::LapCompleted {
+            vehicle,
+            lap,
+            lap_time,
+        } => format!(
+            "lap_completed {} lap={} lap_time_ms={}",
+            vehicle,
+            lap,
+            lap_time.as_millis()
+        ),
+        RaceEvent::Eliminated { vehicle, position } => {
+            format!("eliminated {} position={}", vehicle, position)
+        }
+        RaceEvent::Parked { vehicle } => format!("parked {}", vehicle),
+        RaceEvent::Winner { vehicle } => format!("winner {}", vehicle),
+        RaceEvent::Incident(report) => format!(
+            "incident road_piece_id={} vehicles=[{}]",
+            report.road_piece_id,
+            report.vehicles.join(", ")
+        ),
+        RaceEvent::SafetyCarDeployed => "safety_car_deployed".to_string(),
+        RaceEvent::SafetyCarRecalled => "safety_car_recalled".to_string(),
+        RaceEvent::Countdown { remaining } => format!("countdown remaining={}", remaining),
+        RaceEvent::Started => "started".to_string(),
+        RaceEvent::Finished {
+            vehicle,
+            position,
+            race_time,
+        } => format!(
+            "finished {} position={} race_time_ms={}",
+            vehicle,
+            position,
+            race_time.as_millis()
+        ),
+        RaceEvent::Overtake {
+            vehicle,
+            overtaken,
+            position,
+        } => format!(
+            "overtake {} overtaken={} position={}",
+            vehicle, overtaken, position
+        ),
+        RaceEvent::DidNotFinish { vehicle } => format!("did_not_finish {}", vehicle),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 3);
+    for (i, byte) in data.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // No snapshot yet, the new one has been written next to where it belongs.
+    Missing {
+        pending: PathBuf,
+    },
+    Mismatch {
+        path: PathBuf,
+        pending: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Snapshot IO error: {}", e),
+            SnapshotError::Missing { pending } => {
+                write!(f, "No snapshot yet, review {}", pending.display())
+            }
+            SnapshotError::Mismatch {
+                path,
+                pending,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Snapshot {} changed, review {}\n--- expected\n{}\n+++ actual\n{}",
+                path.display(),
+                pending.display(),
+                expected,
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+// Compares `actual` with the snapshot at `path`. A new or changed snapshot is written alongside
+// as `<path>.new` for review, or straight over `path` when `ANKI_UPDATE_SNAPSHOTS` is set.
+pub fn assert_snapshot<P: AsRef<Path>>(
+    path: P,
+    source: &str,
+    actual: &str,
+) -> Result<(), SnapshotError> {
+    let path = path.as_ref();
+    let actual = actual.trim_end();
+    let expected = match fs::read_to_string(path) {
+        Ok(contents) => Some(snapshot_body(&contents).to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if expected.as_deref() == Some(actual) {
+        return Ok(());
+    }
+
+    let contents = format!(
+        "---\nsource: {}\nexpression: snapshot\n---\n{}\n",
+        source, actual
+    );
+    if env::var_os(SNAPSHOT_UPDATE_ENV).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)?;
+        return Ok(());
+    }
+    let mut pending = path.as_os_str().to_owned();
+    pending.push(".new");
+    let pending = PathBuf::from(pending);
+    if let Some(dir) = pending.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&pending, contents)?;
+    Err(match expected {
+        None => SnapshotError::Missing { pending },
+        Some(expected) => SnapshotError::Mismatch {
+            path: path.to_path_buf(),
+            pending,
+            expected,
+            actual: actual.to_string(),
+        },
+    })
+}
+
+// Drops insta's metadata header, if there is one.
+fn snapshot_body(contents: &str) -> &str {
+    let body = contents
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .map_or(contents, |(_, body)| body);
+    body.trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::VehicleCommand;
+    use crate::race::leaderboard::{Leaderboard, FINISH_LINE_ROAD_PIECE_ID};
+    use crate::race::{RaceCommands, RaceUpdate};
+    use crate::script::MessageScript;
+    use std::process;
+    use std::time::Duration;
+
+    #[test]
+    fn fleet_snapshot_test() {
+        let script = MessageScript::new()
+            .version(0, "skull", 0x2676)
+            .battery_level(10, "skull", 3780)
+            .position_update(20, "skull", 0, FINISH_LINE_ROAD_PIECE_ID, 600)
+            .position_update(2000, "skull", 7, 17, 600)
+            .position_update(4270, "skull", 0, FINISH_LINE_ROAD_PIECE_ID, 600)
+            .transition_update(30, "nuke", 3, 2, -68.0)
+            .frame(40, "nuke", &[0xff]);
+        let mut leaderboard = Leaderboard::new(["skull".to_string(), "nuke".to_string()]);
+        let mut runner =
+            ScriptRunner::new().with_position_handler(|vehicle, data, at| RaceUpdate {
+                events: leaderboard
+                    .process_position_update(vehicle, data, at)
+                    .into_iter()
+                    .collect(),
+                commands: RaceCommands::new(),
+            });
+        runner.run(&script);
+
+        let expected = "\
+vehicles:
+  nuke:
+    name: nuke
+    version: 0x0000
+    battery_level: 0
+    speed_mm_per_sec: 0
+    offset_from_road_centre_mm: -68.0
+    location_id: 0
+    road_piece_idx: 3
+    road_piece_idx_prev: 2
+    intersection_code: None
+  skull:
+    name: skull
+    version: 0x2676
+    battery_level: 3780
+    speed_mm_per_sec: 600
+    offset_from_road_centre_mm: 0.0
+    location_id: 0
+    road_piece_idx: 0
+    road_piece_idx_prev: 0
+    intersection_code: None
+events:
+  - lap_completed skull lap=1 lap_time_ms=4250
+commands:
+errors: 1
+";
+        let snapshot = FleetSnapshot::from_runner(&runner);
+        assert_eq!(expected, snapshot.to_string());
+
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_version(0x2676);
+        let built = FleetSnapshot::new()
+            .with_vehicle("skull", &vehicle)
+            .with_vehicle("nuke", &vehicle)
+            .with_events(&[RaceEvent::LapCompleted {
+                vehicle: "skull".into(),
+                lap: 2,
+                lap_time: Duration::from_millis(4250),
+            }])
+            .with_commands(&[RaceCommand {
+                vehicle: "skull".into(),
+                command: VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: 0,
+                    accel_mm_per_sec2: 800,
+                },
+            }])
+            .to_string();
+        assert!(built.find("  nuke:").unwrap() < built.find("  skull:").unwrap());
+        assert!(built.contains("  - lap_completed skull lap=2 lap_time_ms=4250\n"));
+        assert!(built.contains("  - skull: 06 24 00 00 20 03 00\n"));
+    }
+
+    #[test]
+    fn assert_snapshot_test() {
+        let dir = env::temp_dir().join(format!("anki-snapshots-{}", process::id()));
+        let path = dir.join("fleet.snap");
+        let pending = dir.join("fleet.snap.new");
+
+        assert!(matches!(
+            assert_snapshot(&path, "src/snapshot.rs", "errors: 0\n"),
+            Err(SnapshotError::Missing { .. })
+        ));
+        fs::rename(&pending, &path).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("---\nsource: src/snapshot.rs\n"));
+        assert_snapshot(&path, "src/snapshot.rs", "errors: 0").unwrap();
+
+        match assert_snapshot(&path, "src/snapshot.rs", "errors: 1") {
+            Err(SnapshotError::Mismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!("errors: 0", expected);
+                assert_eq!("errors: 1", actual);
+            }
+            other => panic!("Expected a mismatch, got {:?}", other),
+        }
+        assert!(pending.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
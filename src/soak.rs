@@ -0,0 +1,522 @@
+use std::fmt;
+use std::fs;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+
+use crate::command::{VehicleCommand, WireMessage};
+use crate::host::{FleetHost, HostError, HostNotification};
+use crate::protocol::AnkiVehicleMsgType;
+use crate::sim::host::SimulatedHost;
+use crate::sim::track::SimRng;
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+use crate::AnkiVehicleData;
+
+// Long running stability checks, for installs that have to drive all day. A vehicle is sent a
+// steady stream of random speed, lane and light commands, and after each one the harness checks
+// the vehicle ends up where it was told to be. Along the way it watches for telemetry going quiet,
+// frames piling up and the process growing. Simulated vehicles run as fast as the machine allows,
+// real ones in real time.
+
+pub const SOAK_DEFAULT_SEED: u64 = 0x736f616b;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SoakConfig {
+    pub duration: Duration,
+    pub tick: Duration,
+    pub command_interval: Duration,
+    // How long a vehicle gets to reach a new speed or lane before it is checked.
+    pub settle_time: Duration,
+    pub checkpoint_interval: Duration,
+    pub min_speed_mm_per_sec: u16,
+    pub max_speed_mm_per_sec: u16,
+    pub accel_mm_per_sec2: u16,
+    pub lane_offsets_mm: Vec<f32>,
+    pub speed_tolerance_mm_per_sec: u16,
+    pub offset_tolerance_mm: f32,
+    // Longest a moving vehicle may go without a position update.
+    pub max_silence: Duration,
+    // Most frames allowed to be waiting, on the link or in the notification channel.
+    pub max_queue_depth: usize,
+    // Resident memory growth allowed between the first and last checkpoints.
+    pub max_memory_growth_bytes: u64,
+    // Below this the run ends, a real vehicle would be going back to its charger.
+    pub low_battery_mv: u16,
+    pub seed: u64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        SoakConfig {
+            duration: Duration::from_secs(60 * 60),
+            tick: Duration::from_millis(20),
+            command_interval: Duration::from_secs(5),
+            settle_time: Duration::from_secs(3),
+            checkpoint_interval: Duration::from_secs(60),
+            // Under the curve speed limit, so vehicles stay on the track.
+            min_speed_mm_per_sec: 300,
+            max_speed_mm_per_sec: 800,
+            accel_mm_per_sec2: 1000,
+            lane_offsets_mm: vec![-68.0, -23.0, 23.0, 68.0],
+            speed_tolerance_mm_per_sec: 50,
+            offset_tolerance_mm: 5.0,
+            max_silence: Duration::from_secs(2),
+            max_queue_depth: 256,
+            max_memory_growth_bytes: 16 * 1024 * 1024,
+            low_battery_mv: 3500,
+            seed: SOAK_DEFAULT_SEED,
+        }
+    }
+}
+
+impl SoakConfig {
+    pub fn new() -> SoakConfig {
+        SoakConfig::default()
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> SoakConfig {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_tick(mut self, tick: Duration) -> SoakConfig {
+        self.tick = tick.max(Duration::from_millis(1));
+        self
+    }
+
+    pub fn with_command_interval(mut self, command_interval: Duration) -> SoakConfig {
+        self.command_interval = command_interval;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> SoakConfig {
+        self.seed = seed;
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SoakViolation {
+    Transport {
+        at: Duration,
+        error: HostError,
+    },
+    SpeedDrift {
+        at: Duration,
+        expected: u16,
+        actual: u16,
+    },
+    OffsetDrift {
+        at: Duration,
+        expected: f32,
+        actual: f32,
+    },
+    Silent {
+        at: Duration,
+        since: Duration,
+    },
+    Backlog {
+        at: Duration,
+        depth: usize,
+    },
+    MemoryGrowth {
+        first: u64,
+        last: u64,
+    },
+}
+
+impl fmt::Display for SoakViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoakViolation::Transport { at, error } => write!(f, "{:?}: {}", at, error),
+            SoakViolation::SpeedDrift {
+                at,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?}: Speed {} mm/s, expected {} mm/s",
+                at, actual, expected
+            ),
+            SoakViolation::OffsetDrift {
+                at,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?}: Offset {:.1} mm, expected {:.1} mm",
+                at, actual, expected
+            ),
+            SoakViolation::Silent { at, since } => {
+                write!(f, "{:?}: No position update since {:?}", at, since)
+            }
+            SoakViolation::Backlog { at, depth } => {
+                write!(f, "{:?}: {} frames waiting", at, depth)
+            }
+            SoakViolation::MemoryGrowth { first, last } => {
+                write!(f, "Resident memory grew from {} to {} bytes", first, last)
+            }
+        }
+    }
+}
+
+// Where things stood at the end of each checkpoint interval.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SoakCheckpoint {
+    pub at: Duration,
+    pub commands_sent: u64,
+    pub notifications: u64,
+    pub queue_depth: usize,
+    pub resident_bytes: Option<u64>,
+    pub battery_level: u16,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SoakReport {
+    pub elapsed: Duration,
+    pub commands_sent: u64,
+    pub notifications: u64,
+    pub checks: u64,
+    pub checkpoints: Vec<SoakCheckpoint>,
+    pub violations: Vec<SoakViolation>,
+    // Set when the run ended early on a flat battery.
+    pub low_battery_at: Option<Duration>,
+}
+
+impl SoakReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+// Soaks a simulated vehicle, stepping the host's clock rather than waiting on it.
+pub fn soak_simulated(host: &SimulatedHost, vehicle: &str, config: &SoakConfig) -> SoakReport {
+    soak(
+        host,
+        vehicle,
+        config,
+        |tick| host.step(tick),
+        || host.in_flight(),
+    )
+}
+
+// Soaks a real vehicle in real time. The link isn't visible, so only the notification backlog
+// counts towards the queue depth.
+pub fn soak_realtime<H: FleetHost>(host: &H, vehicle: &str, config: &SoakConfig) -> SoakReport {
+    soak(host, vehicle, config, thread::sleep, || 0)
+}
+
+// `advance` moves time on by one tick, `queue_depth` counts frames the host is holding on to.
+pub fn soak<H, A, Q>(
+    host: &H,
+    vehicle: &str,
+    config: &SoakConfig,
+    advance: A,
+    queue_depth: Q,
+) -> SoakReport
+where
+    H: FleetHost,
+    A: FnMut(Duration),
+    Q: Fn() -> usize,
+{
+    let notifications = host.subscribe();
+    let mut run = SoakRun {
+        host,
+        vehicle,
+        config,
+        notifications,
+        rng: SimRng::new(config.seed),
+        data: AnkiVehicleData::default(),
+        report: SoakReport::default(),
+        now: Duration::ZERO,
+        target_speed: 0,
+        target_offset: 0.0,
+        headlights: false,
+        last_position: Duration::ZERO,
+        silent: false,
+    };
+    run.run(advance, queue_depth);
+    run.report
+}
+
+struct SoakRun<'a, H: FleetHost> {
+    host: &'a H,
+    vehicle: &'a str,
+    config: &'a SoakConfig,
+    notifications: Receiver<HostNotification>,
+    rng: SimRng,
+    data: AnkiVehicleData,
+    report: SoakReport,
+    now: Duration,
+    target_speed: u16,
+    target_offset: f32,
+    headlights: bool,
+    last_position: Duration,
+    // Only the first tick of each quiet spell is reported.
+    silent: bool,
+}
+
+impl<H: FleetHost> SoakRun<'_, H> {
+    fn run<A: FnMut(Duration), Q: Fn() -> usize>(&mut self, mut advance: A, queue_depth: Q) {
+        if let Err(error) = self.host.connect(self.vehicle) {
+            self.violation(SoakViolation::Transport {
+                at: self.now,
+                error,
+            });
+            return;
+        }
+        for command in self.data.configure() {
+            self.send(command);
+        }
+        let config = self.config;
+        let mut next_command = Duration::ZERO;
+        let mut next_check = None;
+        let mut next_checkpoint = config.checkpoint_interval;
+        while self.now < config.duration {
+            if self.now >= next_command {
+                self.command();
+                next_command = self.now + config.command_interval;
+                next_check = Some(self.now + config.settle_time);
+            }
+
+            advance(config.tick);
+            self.now += config.tick;
+
+            let backlog = self.receive();
+            let depth = backlog + queue_depth();
+            if depth > config.max_queue_depth {
+                self.violation(SoakViolation::Backlog {
+                    at: self.now,
+                    depth,
+                });
+            }
+            if next_check.is_some_and(|at| self.now >= at) {
+                next_check = None;
+                self.check();
+            }
+            self.check_silence();
+            if self.now >= next_checkpoint {
+                next_checkpoint += config.checkpoint_interval;
+                self.checkpoint(depth);
+                if self.data.battery_level != 0 && self.data.battery_level < config.low_battery_mv {
+                    self.report.low_battery_at = Some(self.now);
+                    break;
+                }
+            }
+        }
+
+        self.send(AnkiVehicleData::set_speed(
+            0,
+            config.accel_mm_per_sec2 as i16,
+        ));
+        self.report.elapsed = self.now;
+        let resident: Vec<u64> = self
+            .report
+            .checkpoints
+            .iter()
+            .filter_map(|checkpoint| checkpoint.resident_bytes)
+            .collect();
+        if let (Some(&first), Some(&last)) = (resident.first(), resident.last()) {
+            if last > first + config.max_memory_growth_bytes {
+                self.violation(SoakViolation::MemoryGrowth { first, last });
+            }
+        }
+    }
+
+    // One random speed, lane or light change.
+    fn command(&mut self) {
+        let config = self.config;
+        let choice = (self.rng.next_f32() * 3.0) as u8;
+        let command = match choice {
+            1 if !config.lane_offsets_mm.is_empty() => {
+                let idx = (self.rng.next_f32() * config.lane_offsets_mm.len() as f32) as usize;
+                self.target_offset =
+                    config.lane_offsets_mm[idx.min(config.lane_offsets_mm.len() - 1)];
+                VehicleCommand::ChangeLane {
+                    horizontal_speed_mm_per_sec: 300,
+                    horizontal_accel_mm_per_sec2: 2500,
+                    offset_from_road_centre_mm: self.target_offset,
+                }
+            }
+            2 => {
+                self.headlights = !self.headlights;
+                VehicleCommand::SetLights {
+                    light_mask: 1 | (self.headlights as u8) << 4,
+                }
+            }
+            _ => {
+                let range = config
+                    .max_speed_mm_per_sec
+                    .saturating_sub(config.min_speed_mm_per_sec);
+                self.target_speed =
+                    config.min_speed_mm_per_sec + (self.rng.next_f32() * range as f32) as u16;
+                // Give a vehicle that has only just set off its full allowance of silence.
+                if self.data.speed_mm_per_sec == 0 {
+                    self.last_position = self.now;
+                }
+                VehicleCommand::SetSpeed {
+                    speed_mm_per_sec: self.target_speed as i16,
+                    accel_mm_per_sec2: config.accel_mm_per_sec2 as i16,
+                }
+            }
+        };
+        self.send(command);
+    }
+
+    fn send<C: WireMessage>(&mut self, command: C) {
+        self.report.commands_sent += 1;
+        if let Err(error) = self.host.send(self.vehicle, command.encode().into()) {
+            self.violation(SoakViolation::Transport {
+                at: self.now,
+                error,
+            });
+        }
+    }
+
+    // Drains the notifications, returns how many were waiting.
+    fn receive(&mut self) -> usize {
+        let mut received = 0;
+        while let Ok(notification) = self.notifications.try_recv() {
+            received += 1;
+            if notification.vehicle != self.vehicle {
+                continue;
+            }
+            self.report.notifications += 1;
+            if let Ok(AnkiVehicleMsgType::V2CLocalisationPositionUpdate) =
+                self.data.process_notification(&notification.data)
+            {
+                self.last_position = self.now;
+                self.silent = false;
+            }
+        }
+        received
+    }
+
+    fn check(&mut self) {
+        self.report.checks += 1;
+        let actual = self.data.speed_mm_per_sec;
+        if actual.abs_diff(self.target_speed) > self.config.speed_tolerance_mm_per_sec {
+            self.violation(SoakViolation::SpeedDrift {
+                at: self.now,
+                expected: self.target_speed,
+                actual,
+            });
+        }
+        let actual = self.data.offset_from_road_centre_mm;
+        if (actual - self.target_offset).abs() > self.config.offset_tolerance_mm {
+            self.violation(SoakViolation::OffsetDrift {
+                at: self.now,
+                expected: self.target_offset,
+                actual,
+            });
+        }
+    }
+
+    fn check_silence(&mut self) {
+        if self.target_speed == 0 || self.silent {
+            return;
+        }
+        if self.now - self.last_position > self.config.max_silence {
+            self.silent = true;
+            self.violation(SoakViolation::Silent {
+                at: self.now,
+                since: self.last_position,
+            });
+        }
+    }
+
+    fn checkpoint(&mut self, queue_depth: usize) {
+        self.send(VehicleCommand::BatteryLevelRequest);
+        let checkpoint = SoakCheckpoint {
+            at: self.now,
+            commands_sent: self.report.commands_sent,
+            notifications: self.report.notifications,
+            queue_depth,
+            resident_bytes: resident_bytes(),
+            battery_level: self.data.battery_level,
+        };
+        trace_event!(
+            target: TARGET_CONTROLLER,
+            info,
+            vehicle = %self.vehicle,
+            at = ?checkpoint.at,
+            notifications = checkpoint.notifications,
+            violations = self.report.violations.len(),
+            "Soak checkpoint"
+        );
+        self.report.checkpoints.push(checkpoint);
+    }
+
+    fn violation(&mut self, violation: SoakViolation) {
+        trace_event!(target: TARGET_CONTROLLER, warn, vehicle = %self.vehicle, violation = %violation, "Soak violation");
+        self.report.violations.push(violation);
+    }
+}
+
+// The process's resident set size, where the OS makes it easy to find.
+pub fn resident_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::link::{LinkConditions, LinkFaults};
+    use crate::sim::vehicle::{SimulatedVehicle, SIM_BATTERY_FULL};
+
+    fn host() -> SimulatedHost {
+        SimulatedHost::new()
+            .with_link(LinkConditions::ble())
+            .with_vehicle(
+                "skull",
+                "Skull",
+                SimulatedVehicle::new().with_battery_drain(0.0),
+            )
+    }
+
+    #[test]
+    fn soak_simulated_test() {
+        let config = SoakConfig::new().with_duration(Duration::from_secs(10 * 60));
+        let report = soak_simulated(&host(), "skull", &config);
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert_eq!(Duration::from_secs(10 * 60), report.elapsed);
+        assert_eq!(120, report.checks);
+        assert_eq!(10, report.checkpoints.len());
+        assert!(report.notifications > 1000);
+        assert_eq!(SIM_BATTERY_FULL, report.checkpoints[9].battery_level);
+    }
+
+    #[test]
+    fn soak_violations_test() {
+        let host = host().with_link(
+            LinkConditions::new().with_notification_faults(LinkFaults::new().with_drop_rate(1.0)),
+        );
+        let config = SoakConfig::new().with_duration(Duration::from_secs(60));
+        let report = soak_simulated(&host, "skull", &config);
+        assert!(!report.is_ok());
+        assert!(matches!(report.violations[0], SoakViolation::Silent { .. }));
+        assert!(report
+            .violations
+            .iter()
+            .any(|violation| matches!(violation, SoakViolation::SpeedDrift { .. })));
+
+        let report = soak_simulated(&SimulatedHost::new(), "skull", &config);
+        assert!(matches!(
+            report.violations[..],
+            [SoakViolation::Transport { .. }]
+        ));
+    }
+
+    // A working day on the simulator, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn soak_hours_test() {
+        let config = SoakConfig::new().with_duration(Duration::from_secs(8 * 60 * 60));
+        let report = soak_simulated(&host(), "skull", &config);
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert_eq!(None, report.low_battery_at);
+    }
+}
@@ -0,0 +1,450 @@
+use serde::Serialize;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+use crate::protocol::AnkiVehicleMsgLocalisationPositionUpdate;
+use crate::race::leaderboard::Leaderboard;
+use crate::race::RaceEvent;
+
+// A client that connects but never finishes the WebSocket handshake is dropped after this long.
+// It's also the write timeout for spectators, a write that takes longer drops the spectator.
+pub const SPECTATOR_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections past this many, handshakes included, are closed straight away.
+pub const SPECTATOR_MAX_CLIENTS: usize = 64;
+// Messages queued for one spectator. A spectator that falls this far behind is dropped rather
+// than holding up the broadcast.
+pub const SPECTATOR_QUEUE_CAPACITY: usize = 64;
+
+// How often a spectator's thread looks for frames from the client when there's nothing to send.
+const SPECTATOR_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct SpectatorStanding {
+    pub position: usize,
+    pub vehicle: String,
+    pub laps: u16,
+    pub last_lap_ms: Option<u64>,
+    pub best_lap_ms: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SpectatorEvent {
+    LapCompleted {
+        vehicle: String,
+        lap: u16,
+        lap_ms: u64,
+    },
+    Eliminated {
+        vehicle: String,
+        position: usize,
+    },
+    Parked {
+        vehicle: String,
+    },
+    Winner {
+        vehicle: String,
+    },
+    Incident {
+        road_piece_id: u8,
+        vehicles: Vec<String>,
+    },
+    SafetyCarDeployed,
+    SafetyCarRecalled,
+    Countdown {
+        remaining: u8,
+    },
+    Started,
+    Finished {
+        vehicle: String,
+        position: usize,
+        race_ms: u64,
+    },
+    Overtake {
+        vehicle: String,
+        overtaken: String,
+        position: usize,
+    },
+    DidNotFinish {
+        vehicle: String,
+    },
+}
+
+impl From<&RaceEvent> for SpectatorEvent {
+    fn from(event: &RaceEvent) -> SpectatorEvent {
+        match event {
+            RaceEvent::LapCompleted {
+                vehicle,
+                lap,
+                lap_time,
+            } => SpectatorEvent::LapCompleted {
+                vehicle: vehicle.to_string(),
+                lap: *lap,
+                lap_ms: lap_time.as_millis() as u64,
+            },
+            RaceEvent::Eliminated { vehicle, position } => SpectatorEvent::Eliminated {
+                vehicle: vehicle.to_string(),
+                position: *position,
+            },
+            RaceEvent::Parked { vehicle } => SpectatorEvent::Parked {
+                vehicle: vehicle.to_string(),
+            },
+            RaceEvent::Winner { vehicle } => SpectatorEvent::Winner {
+                vehicle: vehicle.to_string(),
+            },
+            RaceEvent::Incident(report) => SpectatorEvent::Incident {
+                road_piece_id: report.road_piece_id,
+                vehicles: report.vehicles.iter().map(ToString::to_string).collect(),
+            },
+            RaceEvent::SafetyCarDeployed => SpectatorEvent::SafetyCarDeployed,
+            RaceEvent::SafetyCarRecalled => SpectatorEvent::SafetyCarRecalled,
+            RaceEvent::Countdown { remaining } => SpectatorEvent::Countdown {
+                remaining: *remaining,
+            },
+            RaceEvent::Started => SpectatorEvent::Started,
+            RaceEvent::Finished {
+                vehicle,
+                position,
+                race_time,
+            } => SpectatorEvent::Finished {
+                vehicle: vehicle.to_string(),
+                position: *position,
+                race_ms: race_time.as_millis() as u64,
+            },
+            RaceEvent::Overtake {
+                vehicle,
+                overtaken,
+                position,
+            } => SpectatorEvent::Overtake {
+                vehicle: vehicle.to_string(),
+                overtaken: overtaken.to_string(),
+                position: *position,
+            },
+            RaceEvent::DidNotFinish { vehicle } => SpectatorEvent::DidNotFinish {
+                vehicle: vehicle.to_string(),
+            },
+        }
+    }
+}
+
+// Every frame sent to spectators is one of these, tagged with "type" for easy dispatch in JS.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpectatorMessage {
+    Standings {
+        standings: Vec<SpectatorStanding>,
+    },
+    Position {
+        vehicle: String,
+        road_piece_id: u8,
+        location_id: u8,
+        offset_from_road_centre_mm: f32,
+        speed_mm_per_sec: u16,
+    },
+    Event(SpectatorEvent),
+}
+
+impl SpectatorMessage {
+    pub fn standings(leaderboard: &Leaderboard) -> SpectatorMessage {
+        SpectatorMessage::Standings {
+            standings: leaderboard
+                .standings()
+                .iter()
+                .enumerate()
+                .map(|(i, s)| SpectatorStanding {
+                    position: i + 1,
+                    vehicle: s.vehicle.to_string(),
+                    laps: s.laps,
+                    last_lap_ms: s.last_lap_time.map(|t| t.as_millis() as u64),
+                    best_lap_ms: s.best_lap_time.map(|t| t.as_millis() as u64),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn position(
+        vehicle: &str,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+    ) -> SpectatorMessage {
+        SpectatorMessage::Position {
+            vehicle: vehicle.to_string(),
+            road_piece_id: data.road_piece_id,
+            location_id: data.location_id,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+        }
+    }
+
+    pub fn event(event: &RaceEvent) -> SpectatorMessage {
+        SpectatorMessage::Event(event.into())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize SpectatorMessage as JSON")
+    }
+}
+
+#[derive(Debug, Default)]
+struct Clients {
+    queues: Vec<SyncSender<String>>,
+    // Sent to late joiners so overlays don't start blank.
+    last_standings: Option<String>,
+}
+
+impl Clients {
+    // Never blocks, a spectator whose queue is full or whose thread has finished is dropped.
+    fn send(&mut self, text: &str) {
+        self.queues
+            .retain(|queue| queue.try_send(text.to_string()).is_ok());
+    }
+}
+
+// Broadcast-only WebSocket server. Connections are accepted on a background thread and each
+// spectator gets a thread of its own, which does the handshake, writes what is queued for it
+// and answers pings and close frames. Every publish call queues the JSON text frame for all
+// connected spectators. Dropping the server stops accepting and disconnects everyone.
+#[derive(Debug)]
+pub struct SpectatorServer {
+    local_addr: SocketAddr,
+    clients: Arc<Mutex<Clients>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl SpectatorServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<SpectatorServer> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Clients::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let accept_clients = Arc::clone(&clients);
+        let accept_shutdown = Arc::clone(&shutdown);
+        let connections = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_shutdown.load(Ordering::Acquire) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                if connections.fetch_add(1, Ordering::AcqRel) >= SPECTATOR_MAX_CLIENTS {
+                    connections.fetch_sub(1, Ordering::AcqRel);
+                    continue;
+                }
+                let clients = Arc::clone(&accept_clients);
+                let shutdown = Arc::clone(&accept_shutdown);
+                let connections = Arc::clone(&connections);
+                thread::spawn(move || {
+                    serve_spectator(stream, &clients, &shutdown);
+                    connections.fetch_sub(1, Ordering::AcqRel);
+                });
+            }
+        });
+
+        Ok(SpectatorServer {
+            local_addr,
+            clients,
+            shutdown,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().queues.len()
+    }
+
+    // Queues the message for every spectator without waiting on any of them. Spectators that
+    // have fallen `SPECTATOR_QUEUE_CAPACITY` messages behind or have gone away are disconnected.
+    pub fn broadcast(&self, msg: &SpectatorMessage) {
+        let text = msg.to_json();
+        let mut clients = self.clients.lock().unwrap();
+        clients.send(&text);
+        if let SpectatorMessage::Standings { .. } = msg {
+            clients.last_standings = Some(text);
+        }
+    }
+
+    pub fn publish_standings(&self, leaderboard: &Leaderboard) {
+        self.broadcast(&SpectatorMessage::standings(leaderboard));
+    }
+
+    pub fn publish_position(&self, vehicle: &str, data: &AnkiVehicleMsgLocalisationPositionUpdate) {
+        self.broadcast(&SpectatorMessage::position(vehicle, data));
+    }
+
+    pub fn publish_event(&self, event: &RaceEvent) {
+        self.broadcast(&SpectatorMessage::event(event));
+    }
+}
+
+impl Drop for SpectatorServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        // Spectator threads close their connection once their queue is gone.
+        self.clients.lock().unwrap().queues.clear();
+        // The accept loop only sees the flag once `accept` returns, so hand it one last connection.
+        let mut wake = self.local_addr;
+        if wake.ip().is_unspecified() {
+            wake.set_ip(match wake.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let _ = TcpStream::connect_timeout(&wake, SPECTATOR_HANDSHAKE_TIMEOUT);
+    }
+}
+
+fn serve_spectator(stream: TcpStream, clients: &Mutex<Clients>, shutdown: &AtomicBool) {
+    if stream
+        .set_read_timeout(Some(SPECTATOR_HANDSHAKE_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(SPECTATOR_HANDSHAKE_TIMEOUT)))
+        .is_err()
+    {
+        return;
+    }
+    let Ok(mut socket) = tungstenite::accept(stream) else {
+        return;
+    };
+    // From here on reads only check for pings and close frames between writes.
+    if socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .is_err()
+    {
+        return;
+    }
+
+    let (queue, messages) = mpsc::sync_channel(SPECTATOR_QUEUE_CAPACITY);
+    {
+        let mut clients = clients.lock().unwrap();
+        if shutdown.load(Ordering::Acquire) {
+            return;
+        }
+        if let Some(standings) = &clients.last_standings {
+            let _ = queue.try_send(standings.clone());
+        }
+        clients.queues.push(queue);
+    }
+    run_spectator(&mut socket, &messages);
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+// Writes queued messages until the server drops the queue or the spectator goes away. Reading
+// lets tungstenite answer pings and close frames, anything else the client sends is ignored.
+fn run_spectator(socket: &mut WebSocket<TcpStream>, messages: &Receiver<String>) {
+    loop {
+        match messages.recv_timeout(SPECTATOR_POLL_INTERVAL) {
+            Ok(text) => {
+                if socket.send(Message::text(text)).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        match socket.read() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::test_util::position_update;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn spectator_message_json_test() {
+        let msg = SpectatorMessage::event(&RaceEvent::LapCompleted {
+            vehicle: "Skull".into(),
+            lap: 3,
+            lap_time: Duration::from_millis(4250),
+        });
+        assert_eq!(
+            r#"{"type":"event","event":"lap_completed","vehicle":"Skull","lap":3,"lap_ms":4250}"#,
+            msg.to_json()
+        );
+
+        let msg = SpectatorMessage::position("Skull", &position_update(7, 17));
+        assert_eq!(
+            r#"{"type":"position","vehicle":"Skull","road_piece_id":17,"location_id":7,"offset_from_road_centre_mm":0.0,"speed_mm_per_sec":0}"#,
+            msg.to_json()
+        );
+    }
+
+    #[test]
+    fn spectator_slow_client_test() {
+        let mut clients = Clients::default();
+        let (slow, _slow_messages) = mpsc::sync_channel(1);
+        let (fast, fast_messages) = mpsc::sync_channel(1);
+        clients.queues.extend([slow, fast]);
+
+        clients.send("first");
+        assert_eq!("first", fast_messages.recv().unwrap());
+        clients.send("second");
+        assert_eq!(1, clients.queues.len());
+        assert_eq!("second", fast_messages.recv().unwrap());
+    }
+
+    #[test]
+    fn spectator_server_broadcast_test() {
+        let server = SpectatorServer::bind("127.0.0.1:0").unwrap();
+        let leaderboard = Leaderboard::new(["Skull".to_string()]);
+        server.publish_standings(&leaderboard);
+
+        let url = format!("ws://{}", server.local_addr());
+        let (mut client, _) = tungstenite::connect(url).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while server.client_count() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // A client stuck before its handshake doesn't hold up anyone else.
+        let _stalled = TcpStream::connect(server.local_addr()).unwrap();
+        let (_late, _) = tungstenite::connect(format!("ws://{}", server.local_addr())).unwrap();
+
+        let standings = client.read().unwrap();
+        assert_eq!(
+            r#"{"type":"standings","standings":[{"position":1,"vehicle":"Skull","laps":0,"last_lap_ms":null,"best_lap_ms":null}]}"#,
+            standings.to_text().unwrap()
+        );
+
+        server.publish_event(&RaceEvent::Winner {
+            vehicle: "Skull".into(),
+        });
+        let event = client.read().unwrap();
+        assert_eq!(
+            r#"{"type":"event","event":"winner","vehicle":"Skull"}"#,
+            event.to_text().unwrap()
+        );
+
+        client.send(Message::Ping("overlay".into())).unwrap();
+        assert_eq!(Message::Pong("overlay".into()), client.read().unwrap());
+
+        let addr = server.local_addr();
+        drop(server);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while TcpStream::connect(addr).is_ok() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
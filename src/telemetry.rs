@@ -0,0 +1,584 @@
+use std::fs::File;
+#[cfg(feature = "json")]
+use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::bt_address::BtAddress;
+use crate::clock::{Clock, SystemClock};
+use crate::fleet::Fleet;
+use crate::io::transport::VehicleTransport;
+use crate::recorder::unix_time_ms;
+use crate::replay::{replay_due, ReplayDirection, ReplayError, ReplayRecord, ReplayWriter};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_id::VehicleId;
+use crate::vehicle_state::StateChange;
+
+// How a recorder lays out its log.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TelemetryFormat {
+    // The replay format from src/replay.rs, compact and readable by `ReplayReader`.
+    Binary,
+    // One `JsonlRecord` per line, for tools that would rather not parse the binary format.
+    #[cfg(feature = "json")]
+    Jsonl,
+}
+
+// A line of a JSONL log. The frame is hex without separators, e.g. "0117" for a ping response.
+#[cfg(feature = "json")]
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct JsonlRecord {
+    pub timestamp_ms: u64,
+    pub direction: ReplayDirection,
+    pub vehicle: VehicleId,
+    pub frame: String,
+}
+
+#[cfg(feature = "json")]
+impl From<&ReplayRecord> for JsonlRecord {
+    fn from(record: &ReplayRecord) -> Self {
+        JsonlRecord {
+            timestamp_ms: record.timestamp_ms,
+            direction: record.direction,
+            vehicle: record.vehicle.clone(),
+            frame: record.frame.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl TryFrom<JsonlRecord> for ReplayRecord {
+    type Error = ReplayError;
+
+    fn try_from(record: JsonlRecord) -> Result<Self, Self::Error> {
+        let hex = record.frame.as_bytes();
+        if !hex.len().is_multiple_of(2) {
+            return Err(ReplayError::Invalid(format!(
+                "odd length frame {:?}",
+                record.frame
+            )));
+        }
+        let frame = hex
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| ReplayError::Invalid(format!("bad hex {:?}", record.frame)))
+            })
+            .collect::<Result<Vec<u8>, ReplayError>>()?;
+        Ok(ReplayRecord {
+            timestamp_ms: record.timestamp_ms,
+            direction: record.direction,
+            vehicle: record.vehicle,
+            frame,
+        })
+    }
+}
+
+// Reads a JSONL log back, blank lines are skipped.
+#[cfg(feature = "json")]
+pub struct JsonlReader<R: BufRead> {
+    reader: R,
+    line: String,
+}
+
+#[cfg(feature = "json")]
+impl JsonlReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JsonlReader<BufReader<File>>, ReplayError> {
+        Ok(JsonlReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<R: BufRead> JsonlReader<R> {
+    pub fn new(reader: R) -> JsonlReader<R> {
+        JsonlReader {
+            reader,
+            line: String::new(),
+        }
+    }
+
+    // None at the end of the log.
+    pub fn read_record(&mut self) -> Result<Option<ReplayRecord>, ReplayError> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let line = self.line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record: JsonlRecord =
+                serde_json::from_str(line).map_err(|e| ReplayError::Invalid(e.to_string()))?;
+            return ReplayRecord::try_from(record).map(Some);
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<R: BufRead> Iterator for JsonlReader<R> {
+    type Item = Result<ReplayRecord, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+enum TelemetryLog<W: Write> {
+    Binary(ReplayWriter<W>),
+    #[cfg(feature = "json")]
+    Jsonl {
+        writer: W,
+        records: usize,
+    },
+}
+
+// Logs every frame written to and received from the vehicles, stamped with the milliseconds since
+// the recording started on a monotonic clock. Frames can be handed over directly or captured by
+// wrapping transports in a `RecordingTransport`.
+pub struct Recorder<W: Write> {
+    log: TelemetryLog<W>,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: TelemetryFormat,
+    ) -> Result<Recorder<BufWriter<File>>, ReplayError> {
+        Recorder::new(BufWriter::new(File::create(path)?), format)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W, format: TelemetryFormat) -> Result<Recorder<W>, ReplayError> {
+        let log = match format {
+            TelemetryFormat::Binary => {
+                TelemetryLog::Binary(ReplayWriter::new(writer, unix_time_ms())?)
+            }
+            #[cfg(feature = "json")]
+            TelemetryFormat::Jsonl => TelemetryLog::Jsonl { writer, records: 0 },
+        };
+        Ok(Recorder {
+            log,
+            clock: Arc::new(SystemClock),
+            started: Instant::now(),
+        })
+    }
+
+    // Timestamps are taken from this clock from now on, counting from zero again.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Recorder<W> {
+        self.started = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    pub fn format(&self) -> TelemetryFormat {
+        match self.log {
+            TelemetryLog::Binary(_) => TelemetryFormat::Binary,
+            #[cfg(feature = "json")]
+            TelemetryLog::Jsonl { .. } => TelemetryFormat::Jsonl,
+        }
+    }
+
+    pub fn records(&self) -> usize {
+        match &self.log {
+            TelemetryLog::Binary(writer) => writer.records(),
+            #[cfg(feature = "json")]
+            TelemetryLog::Jsonl { records, .. } => *records,
+        }
+    }
+
+    pub fn record_command(&mut self, vehicle: &str, frame: &[u8]) -> Result<(), ReplayError> {
+        let at = self.clock.now();
+        self.record_at(ReplayDirection::Command, vehicle, frame, at)
+    }
+
+    pub fn record_notification(&mut self, vehicle: &str, frame: &[u8]) -> Result<(), ReplayError> {
+        let at = self.clock.now();
+        self.record_at(ReplayDirection::Notification, vehicle, frame, at)
+    }
+
+    // Frames from before the recording started are stamped zero.
+    pub fn record_at(
+        &mut self,
+        direction: ReplayDirection,
+        vehicle: &str,
+        frame: &[u8],
+        at: Instant,
+    ) -> Result<(), ReplayError> {
+        let record = ReplayRecord {
+            timestamp_ms: at.saturating_duration_since(self.started).as_millis() as u64,
+            direction,
+            vehicle: vehicle.into(),
+            frame: frame.to_vec(),
+        };
+        match &mut self.log {
+            TelemetryLog::Binary(writer) => writer.write(&record),
+            #[cfg(feature = "json")]
+            TelemetryLog::Jsonl { writer, records } => {
+                let mut line = serde_json::to_vec(&JsonlRecord::from(&record))
+                    .map_err(|e| ReplayError::Invalid(e.to_string()))?;
+                line.push(b'\n');
+                writer.write_all(&line)?;
+                *records += 1;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), ReplayError> {
+        match &mut self.log {
+            TelemetryLog::Binary(writer) => writer.flush(),
+            #[cfg(feature = "json")]
+            TelemetryLog::Jsonl { writer, .. } => Ok(writer.flush()?),
+        }
+    }
+
+    pub fn into_inner(self) -> Result<W, ReplayError> {
+        match self.log {
+            TelemetryLog::Binary(writer) => writer.into_inner(),
+            #[cfg(feature = "json")]
+            TelemetryLog::Jsonl { mut writer, .. } => {
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+// Records the traffic of one vehicle's transport. Several transports can share a recorder, so a
+// whole fleet ends up in one log. A frame that fails to record is traced and the transport carries
+// on, losing a log line is better than losing the command.
+pub struct RecordingTransport<T, W: Write> {
+    transport: T,
+    vehicle: VehicleId,
+    recorder: Arc<Mutex<Recorder<W>>>,
+}
+
+impl<T: VehicleTransport, W: Write> RecordingTransport<T, W> {
+    pub fn new(
+        transport: T,
+        vehicle: impl Into<VehicleId>,
+        recorder: Arc<Mutex<Recorder<W>>>,
+    ) -> RecordingTransport<T, W> {
+        RecordingTransport {
+            transport,
+            vehicle: vehicle.into(),
+            recorder,
+        }
+    }
+
+    pub fn vehicle(&self) -> &VehicleId {
+        &self.vehicle
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    fn record(&self, direction: ReplayDirection, frame: &[u8]) {
+        let mut recorder = self.recorder.lock().unwrap();
+        let at = recorder.clock.now();
+        if let Err(_e) = recorder.record_at(direction, &self.vehicle, frame, at) {
+            trace_event!(target: TARGET_TRANSPORT, warn, error = %_e, vehicle = %self.vehicle, "Failed to record frame");
+        }
+    }
+}
+
+impl<T: VehicleTransport, W: Write> VehicleTransport for RecordingTransport<T, W> {
+    type Error = T::Error;
+
+    // Only frames the transport accepted are recorded.
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.transport.send(data)?;
+        self.record(ReplayDirection::Command, data);
+        Ok(())
+    }
+
+    fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        let notification = self.transport.poll_notification()?;
+        self.record(ReplayDirection::Notification, &notification);
+        Some(notification)
+    }
+}
+
+// Plays the notifications of a recorded log back on the caller's time, keeping the gaps between
+// them. Commands in the log are skipped, the code under test is the one giving commands now. A
+// speed of 2.0 plays twice as fast, 0.0 or infinity makes every notification due at once. Reads
+// either log format, `ReplayReader` or `JsonlReader`.
+pub struct Replayer<I> {
+    records: I,
+    speed: f64,
+    started: Option<(Instant, u64)>,
+    pending: Option<ReplayRecord>,
+    finished: bool,
+}
+
+impl<I: Iterator<Item = Result<ReplayRecord, ReplayError>>> Replayer<I> {
+    pub fn new(records: I) -> Replayer<I> {
+        Replayer {
+            records,
+            speed: 1.0,
+            started: None,
+            pending: None,
+            finished: false,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Replayer<I> {
+        self.speed = speed;
+        self
+    }
+
+    // The first notification is due at `at`. Without a start the first poll starts the replay.
+    pub fn start(&mut self, at: Instant) -> Result<(), ReplayError> {
+        self.fill()?;
+        if let Some(record) = &self.pending {
+            self.started = Some((at, record.timestamp_ms));
+        }
+        Ok(())
+    }
+
+    // Every notification has been played.
+    pub fn is_finished(&self) -> bool {
+        self.finished && self.pending.is_none()
+    }
+
+    // When the next notification is due, None once the log is played or before the start.
+    pub fn next_due(&mut self) -> Result<Option<Instant>, ReplayError> {
+        self.fill()?;
+        self.pending_due()
+    }
+
+    // The next notification due by `at`, None when nothing is due yet.
+    pub fn poll_notification_at(
+        &mut self,
+        at: Instant,
+    ) -> Result<Option<ReplayRecord>, ReplayError> {
+        self.fill()?;
+        let Some(record) = &self.pending else {
+            return Ok(None);
+        };
+        if self.started.is_none() {
+            self.started = Some((at, record.timestamp_ms));
+        }
+        match self.pending_due()? {
+            Some(due) if due <= at => Ok(self.pending.take()),
+            _ => Ok(None),
+        }
+    }
+
+    // Feeds the notifications due by `at` to the fleet, the way `Fleet::poll_notifications`
+    // does with live ones, each processed at the time it was due. Notifications for vehicles
+    // that aren't in the fleet, or that don't decode, are dropped.
+    pub fn process_fleet_at<T>(
+        &mut self,
+        fleet: &mut Fleet<T>,
+        at: Instant,
+    ) -> Result<Vec<(BtAddress, StateChange)>, ReplayError> {
+        let mut changes = Vec::new();
+        while let Some(record) = self.poll_notification_at(at)? {
+            let due = self.due(record.timestamp_ms)?.unwrap_or(at).min(at);
+            let Ok(address) = record.vehicle.parse::<BtAddress>() else {
+                trace_event!(target: TARGET_TRANSPORT, debug, vehicle = %record.vehicle, "Replayed notification isn't from a fleet vehicle");
+                continue;
+            };
+            match fleet.process_notification_at(address, &record.frame, due) {
+                Ok(StateChange::None) => {}
+                Ok(change) => changes.push((address, change)),
+                Err(_e) => {
+                    trace_event!(target: TARGET_TRANSPORT, debug, vehicle = %address, error = %_e, "Dropped replayed notification");
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    fn fill(&mut self) -> Result<(), ReplayError> {
+        while self.pending.is_none() && !self.finished {
+            match self.records.next().transpose()? {
+                Some(record) if record.direction == ReplayDirection::Notification => {
+                    self.pending = Some(record)
+                }
+                Some(_) => {}
+                None => self.finished = true,
+            }
+        }
+        Ok(())
+    }
+
+    // A notification that can't be scheduled is dropped, so the replay carries on past it.
+    fn pending_due(&mut self) -> Result<Option<Instant>, ReplayError> {
+        let Some(record) = &self.pending else {
+            return Ok(None);
+        };
+        self.due(record.timestamp_ms)
+            .inspect_err(|_| self.pending = None)
+    }
+
+    fn due(&self, timestamp_ms: u64) -> Result<Option<Instant>, ReplayError> {
+        self.started
+            .map(|(started, first_ms)| replay_due(started, first_ms, timestamp_ms, self.speed))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::command::Command;
+    use crate::io::transport::MockTransport;
+    use crate::protocol::AnkiVehicleMsgType;
+    use crate::replay::ReplayReader;
+    use crate::AnkiVehicleData;
+    use std::time::Duration;
+
+    #[test]
+    fn recording_transport_test() {
+        let clock = Arc::new(VirtualClock::new());
+        let recorder = Recorder::new(Vec::new(), TelemetryFormat::Binary)
+            .unwrap()
+            .with_clock(clock.clone());
+        let recorder = Arc::new(Mutex::new(recorder));
+        let mut transport =
+            RecordingTransport::new(MockTransport::new(), "skull", recorder.clone());
+
+        transport.send_command(Command::PING).unwrap();
+        clock.advance(Duration::from_millis(25));
+        transport.inner_mut().push_notification([1, 0x17]);
+        assert_eq!(Some(vec![1, 0x17]), transport.poll_notification());
+        assert_eq!(None, transport.poll_notification());
+
+        // Refused sends aren't recorded.
+        transport.inner_mut().disconnect();
+        assert!(transport.send_command(AnkiVehicleData::stop()).is_err());
+        drop(transport);
+
+        let recorder = Arc::try_unwrap(recorder)
+            .ok()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        assert_eq!(2, recorder.records());
+        let log = recorder.into_inner().unwrap();
+        let records: Vec<ReplayRecord> = ReplayReader::new(&log[..])
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            vec![
+                ReplayRecord::command(0, "skull", &Command::PING),
+                ReplayRecord::notification(25, "skull", &[1, 0x17]),
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn replayer_fleet_test() {
+        let address: BtAddress = "AA:BB:CC:DD:EE:01".parse().unwrap();
+        let vehicle = address.to_string();
+        let battery = |level: u16| {
+            let [lo, hi] = level.to_le_bytes();
+            [3, AnkiVehicleMsgType::V2CBatteryLevelResponse as u8, lo, hi]
+        };
+        let records = vec![
+            Ok(ReplayRecord::notification(200, &vehicle, &battery(3900))),
+            Ok(ReplayRecord::command(700, &vehicle, &Command::PING)),
+            Ok(ReplayRecord::notification(1200, &vehicle, &battery(3800))),
+            Ok(ReplayRecord::notification(1300, "nuke", &battery(3700))),
+        ];
+        let mut fleet = Fleet::new();
+        fleet.insert(address, AnkiVehicleData::default());
+
+        // Twice as fast, the second reading is due half a second after the first.
+        let start = Instant::now();
+        let mut replayer = Replayer::new(records.into_iter()).with_speed(2.0);
+        replayer.start(start).unwrap();
+        assert_eq!(
+            vec![(address, StateChange::Battery(3900))],
+            replayer.process_fleet_at(&mut fleet, start).unwrap()
+        );
+        let second = start + Duration::from_millis(500);
+        assert_eq!(Some(second), replayer.next_due().unwrap());
+        assert!(replayer
+            .process_fleet_at(&mut fleet, second - Duration::from_millis(1))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![(address, StateChange::Battery(3800))],
+            replayer.process_fleet_at(&mut fleet, second).unwrap()
+        );
+        assert_eq!(3800, fleet.get(address).unwrap().battery_level);
+
+        // Nuke isn't in the fleet.
+        assert!(replayer
+            .process_fleet_at(&mut fleet, second + Duration::from_secs(1))
+            .unwrap()
+            .is_empty());
+        assert!(replayer.is_finished());
+
+        // A corrupt timestamp can't be waited for, it's skipped instead of panicking.
+        let records = vec![
+            Ok(ReplayRecord::notification(0, &vehicle, &battery(3900))),
+            Ok(ReplayRecord::notification(
+                u64::MAX,
+                &vehicle,
+                &battery(3800),
+            )),
+            Ok(ReplayRecord::notification(100, &vehicle, &battery(3700))),
+        ];
+        let mut replayer = Replayer::new(records.into_iter()).with_speed(1e-300);
+        replayer.start(start).unwrap();
+        assert!(replayer.poll_notification_at(start).unwrap().is_some());
+        assert!(matches!(
+            replayer.poll_notification_at(start),
+            Err(ReplayError::Invalid(_))
+        ));
+        assert!(replayer.next_due().is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn jsonl_recorder_test() {
+        let clock = Arc::new(VirtualClock::new());
+        let mut recorder = Recorder::new(Vec::new(), TelemetryFormat::Jsonl)
+            .unwrap()
+            .with_clock(clock.clone());
+        recorder.record_command("skull", &Command::PING).unwrap();
+        clock.advance(Duration::from_millis(1500));
+        recorder.record_notification("skull", &[1, 0x17]).unwrap();
+
+        let log = String::from_utf8(recorder.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            r#"{"timestamp_ms":1500,"direction":"notification","vehicle":"skull","frame":"0117"}"#,
+            lines[1]
+        );
+        let records: Vec<ReplayRecord> = JsonlReader::new(log.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            vec![
+                ReplayRecord::command(0, "skull", &Command::PING),
+                ReplayRecord::notification(1500, "skull", &[1, 0x17]),
+            ],
+            records
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::host::HostNotification;
+use crate::protocol::AnkiVehicleMsgType;
+
+// Bounded counterpart of the channels `FleetHost::subscribe` hands out. A subscriber that can't
+// keep up loses notifications according to its policy instead of letting them pile up, and the
+// receiver counts what it lost.
+
+pub const TELEMETRY_CHANNEL_DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DropPolicy {
+    // Make room by discarding the oldest queued notification.
+    #[default]
+    DropOldest,
+    // Keep what is queued and discard the new notification.
+    DropNewest,
+    // A position update replaces any still queued for the same vehicle, only the latest position
+    // is worth acting on. Anything else falls back to dropping the oldest.
+    CoalescePositions,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    queue: VecDeque<HostNotification>,
+    dropped: u64,
+    coalesced: u64,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    capacity: usize,
+    policy: DropPolicy,
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+pub struct BoundedSender {
+    shared: Arc<Shared>,
+}
+
+pub struct BoundedReceiver {
+    shared: Arc<Shared>,
+}
+
+// Room for `capacity` notifications, at least one.
+pub fn bounded_channel(capacity: usize, policy: DropPolicy) -> (BoundedSender, BoundedReceiver) {
+    let shared = Arc::new(Shared {
+        capacity: capacity.max(1),
+        policy,
+        state: Mutex::new(State {
+            sender_alive: true,
+            receiver_alive: true,
+            ..State::default()
+        }),
+        available: Condvar::new(),
+    });
+    (
+        BoundedSender {
+            shared: shared.clone(),
+        },
+        BoundedReceiver { shared },
+    )
+}
+
+fn is_position_update(notification: &HostNotification) -> bool {
+    notification.data.get(1).copied()
+        == Some(u8::from(AnkiVehicleMsgType::V2CLocalisationPositionUpdate))
+}
+
+impl BoundedSender {
+    // Never blocks. Hands the notification back once the receiver is gone, a full queue isn't
+    // an error.
+    pub fn send(&self, notification: HostNotification) -> Result<(), HostNotification> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if !state.receiver_alive {
+            return Err(notification);
+        }
+        if shared.policy == DropPolicy::CoalescePositions && is_position_update(&notification) {
+            let before = state.queue.len();
+            state.queue.retain(|queued| {
+                queued.vehicle != notification.vehicle || !is_position_update(queued)
+            });
+            state.coalesced += (before - state.queue.len()) as u64;
+        }
+        if state.queue.len() >= shared.capacity {
+            state.dropped += 1;
+            if shared.policy == DropPolicy::DropNewest {
+                return Ok(());
+            }
+            state.queue.pop_front();
+        }
+        state.queue.push_back(notification);
+        shared.available.notify_one();
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.state.lock().unwrap().receiver_alive
+    }
+}
+
+impl Drop for BoundedSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().sender_alive = false;
+        self.shared.available.notify_all();
+    }
+}
+
+impl BoundedReceiver {
+    // Blocks until a notification arrives, fails once the queue is empty and the sender is gone.
+    pub fn recv(&self) -> Result<HostNotification, RecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(notification) = state.queue.pop_front() {
+                return Ok(notification);
+            }
+            if !state.sender_alive {
+                return Err(RecvError);
+            }
+            state = self.shared.available.wait(state).unwrap();
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<HostNotification, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(notification) = state.queue.pop_front() {
+                return Ok(notification);
+            }
+            if !state.sender_alive {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<HostNotification, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(notification) => Ok(notification),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = HostNotification> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    pub fn policy(&self) -> DropPolicy {
+        self.shared.policy
+    }
+
+    // Notifications lost to a full queue.
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+
+    // Position updates replaced by a newer one for the same vehicle.
+    pub fn coalesced(&self) -> u64 {
+        self.shared.state.lock().unwrap().coalesced
+    }
+}
+
+impl Drop for BoundedReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver_alive = false;
+        state.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(vehicle: &str, msg_type: AnkiVehicleMsgType, seq: u8) -> HostNotification {
+        HostNotification {
+            vehicle: vehicle.into(),
+            data: vec![2, msg_type.into(), seq],
+        }
+    }
+
+    // The sequence numbers of everything queued.
+    fn drain(rx: &BoundedReceiver) -> Vec<u8> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|notification| notification.data[2])
+            .collect()
+    }
+
+    #[test]
+    fn bounded_channel_drop_policy_test() {
+        let battery = AnkiVehicleMsgType::V2CBatteryLevelResponse;
+        for (policy, kept) in [
+            (DropPolicy::DropOldest, [2, 3, 4]),
+            (DropPolicy::DropNewest, [0, 1, 2]),
+        ] {
+            let (tx, rx) = bounded_channel(3, policy);
+            for seq in 0..5 {
+                tx.send(notification("skull", battery.clone(), seq))
+                    .unwrap();
+            }
+            assert_eq!(2, rx.dropped());
+            assert_eq!(kept.to_vec(), drain(&rx));
+        }
+
+        let position = AnkiVehicleMsgType::V2CLocalisationPositionUpdate;
+        let (tx, rx) = bounded_channel(3, DropPolicy::CoalescePositions);
+        tx.send(notification("skull", position.clone(), 0)).unwrap();
+        tx.send(notification("nuke", position.clone(), 1)).unwrap();
+        tx.send(notification("skull", battery.clone(), 2)).unwrap();
+        tx.send(notification("skull", position.clone(), 3)).unwrap();
+        tx.send(notification("skull", position.clone(), 4)).unwrap();
+        assert_eq!(2, rx.coalesced());
+        assert_eq!(0, rx.dropped());
+        assert_eq!(vec![1, 2, 4], drain(&rx));
+
+        drop(tx);
+        assert_eq!(Err(TryRecvError::Disconnected), rx.try_recv());
+        assert_eq!(Err(RecvError), rx.recv());
+    }
+
+    #[test]
+    fn bounded_channel_disconnect_test() {
+        let (tx, rx) = bounded_channel(1, DropPolicy::default());
+        assert_eq!(
+            Err(RecvTimeoutError::Timeout),
+            rx.recv_timeout(Duration::from_millis(1))
+        );
+        drop(rx);
+        assert!(!tx.is_connected());
+        assert!(tx
+            .send(notification(
+                "skull",
+                AnkiVehicleMsgType::V2CPingResponse,
+                0
+            ))
+            .is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Hands decoded messages from the BLE notification callback to the thread that uses them. There
+// is exactly one sender and one receiver, so neither side ever takes a lock or waits on the
+// other. The queue is bounded: when the receiver falls behind, new messages are turned away and
+// counted rather than piling up.
+
+pub const TELEMETRY_QUEUE_DEFAULT_CAPACITY: usize = 1024;
+
+// Keeps the two indices on separate cache lines so the threads don't keep stealing each other's.
+#[repr(align(64))]
+struct Padded(AtomicUsize);
+
+struct Shared<T> {
+    // A power of two long, so wrapping indices keep mapping to consecutive slots when they
+    // overflow. `capacity` is how many of them are used.
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    capacity: usize,
+    // Next slot to read, only written by the receiver.
+    head: Padded,
+    // Next slot to write, only written by the sender.
+    tail: Padded,
+    rejected: AtomicU64,
+}
+
+// Slots between head and tail belong to the receiver, the rest to the sender, and each index is
+// only published once the slot it covers has been handed over.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, pos: usize) -> *mut MaybeUninit<T> {
+        self.slots[pos & (self.slots.len() - 1)].get()
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        while head != tail {
+            unsafe { (*self.slot(head)).assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+pub struct TelemetrySender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct TelemetryReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Both ends can be moved to another thread, neither can be cloned.
+unsafe impl<T: Send> Send for TelemetrySender<T> {}
+unsafe impl<T: Send> Send for TelemetryReceiver<T> {}
+
+// Room for `capacity` messages, at least one.
+pub fn telemetry_queue<T>(capacity: usize) -> (TelemetrySender<T>, TelemetryReceiver<T>) {
+    telemetry_queue_from(capacity, 0)
+}
+
+// Starts the indices at `start`, so tests can cross the point where they wrap.
+fn telemetry_queue_from<T>(
+    capacity: usize,
+    start: usize,
+) -> (TelemetrySender<T>, TelemetryReceiver<T>) {
+    let capacity = capacity.max(1);
+    let slots = (0..capacity.next_power_of_two())
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        capacity,
+        head: Padded(AtomicUsize::new(start)),
+        tail: Padded(AtomicUsize::new(start)),
+        rejected: AtomicU64::new(0),
+    });
+    (
+        TelemetrySender {
+            shared: shared.clone(),
+        },
+        TelemetryReceiver { shared },
+    )
+}
+
+impl<T> TelemetrySender<T> {
+    // Hands `msg` back if the queue is full.
+    pub fn push(&mut self, msg: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let tail = shared.tail.0.load(Ordering::Relaxed);
+        let head = shared.head.0.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == shared.capacity {
+            shared.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(msg);
+        }
+        unsafe { (*shared.slot(tail)).write(msg) };
+        shared.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn is_full(&self) -> bool {
+        len(&self.shared) == self.shared.capacity
+    }
+
+    // False once the receiver has been dropped, nothing pushed after that will be read.
+    pub fn is_connected(&self) -> bool {
+        Arc::strong_count(&self.shared) > 1
+    }
+}
+
+impl<T> TelemetryReceiver<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        let head = shared.head.0.load(Ordering::Relaxed);
+        let tail = shared.tail.0.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let msg = unsafe { (*shared.slot(head)).assume_init_read() };
+        shared.head.0.store(head.wrapping_add(1), Ordering::Release);
+        Some(msg)
+    }
+
+    // Everything queued right now, without waiting for more.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.pop())
+    }
+
+    pub fn len(&self) -> usize {
+        len(&self.shared)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    // Messages turned away because the queue was full.
+    pub fn rejected(&self) -> u64 {
+        self.shared.rejected.load(Ordering::Relaxed)
+    }
+
+    // False once the sender has been dropped and everything it sent has been read.
+    pub fn is_connected(&self) -> bool {
+        Arc::strong_count(&self.shared) > 1 || !self.is_empty()
+    }
+}
+
+fn len<T>(shared: &Shared<T>) -> usize {
+    let tail = shared.tail.0.load(Ordering::Acquire);
+    let head = shared.head.0.load(Ordering::Acquire);
+    tail.wrapping_sub(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::AnkiVehicleMsgType;
+    use std::thread;
+
+    #[test]
+    fn telemetry_queue_test() {
+        let (mut tx, mut rx) = telemetry_queue(2);
+        assert_eq!(Ok(()), tx.push(AnkiVehicleMsgType::V2CPingResponse));
+        assert_eq!(Ok(()), tx.push(AnkiVehicleMsgType::V2CVehicleDelocalized));
+        assert!(tx.is_full());
+        assert_eq!(
+            Err(AnkiVehicleMsgType::V2CBatteryLevelResponse),
+            tx.push(AnkiVehicleMsgType::V2CBatteryLevelResponse)
+        );
+        assert_eq!(1, rx.rejected());
+
+        assert_eq!(Some(AnkiVehicleMsgType::V2CPingResponse), rx.pop());
+        assert_eq!(Ok(()), tx.push(AnkiVehicleMsgType::V2CVersionResponse));
+        assert_eq!(
+            vec![
+                AnkiVehicleMsgType::V2CVehicleDelocalized,
+                AnkiVehicleMsgType::V2CVersionResponse
+            ],
+            rx.drain().collect::<Vec<_>>()
+        );
+        assert!(rx.is_empty());
+
+        drop(tx);
+        assert!(!rx.is_connected());
+    }
+
+    #[test]
+    fn telemetry_queue_wrap_test() {
+        let live = Arc::new(());
+        let (mut tx, mut rx) = telemetry_queue_from(3, usize::MAX - 4);
+        assert_eq!(3, rx.capacity());
+        for i in 0..20u32 {
+            assert!(tx.push((i, live.clone())).is_ok());
+            if i % 3 == 2 {
+                assert!(tx.is_full());
+                assert!(tx.push((99, live.clone())).is_err());
+                let popped: Vec<u32> = rx.drain().map(|(i, _)| i).collect();
+                assert_eq!(vec![i - 2, i - 1, i], popped);
+            }
+        }
+        assert_eq!(2, rx.len());
+        assert_eq!(3, Arc::strong_count(&live));
+        drop((tx, rx));
+        assert_eq!(1, Arc::strong_count(&live));
+    }
+
+    #[test]
+    fn telemetry_queue_threads_test() {
+        let (mut tx, mut rx) = telemetry_queue::<Vec<u8>>(16);
+        let producer = thread::spawn(move || {
+            for i in 0..10_000u32 {
+                let mut msg = i.to_le_bytes().to_vec();
+                while let Err(back) = tx.push(msg) {
+                    msg = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0u32;
+        while rx.is_connected() {
+            match rx.pop() {
+                Some(msg) => {
+                    assert_eq!(expected.to_le_bytes().to_vec(), msg);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(10_000, expected);
+    }
+}
@@ -0,0 +1,35 @@
+// Lets the transport, protocol and race code emit `tracing` events without a #[cfg] on every
+// call. Without the `tracing` or `log` feature the macro expands to nothing and its arguments are
+// never evaluated. Spans are added with `cfg_attr(feature = "tracing", tracing::instrument(..))`.
+//
+// The `log` feature turns on tracing's own `log` output, so every event also reaches the `log`
+// facade as long as no tracing subscriber is installed. Events carry one of the targets below
+// so loggers can be told how much to show of each part of the SDK.
+
+// Host, BLE and network links: connections, frames written and dropped.
+pub(crate) const TARGET_TRANSPORT: &str = "anki_drive_sdk::transport";
+// Decoding vehicle messages.
+pub(crate) const TARGET_PROTOCOL: &str = "anki_drive_sdk::protocol";
+// Race controllers and anything else deciding what vehicles should do.
+pub(crate) const TARGET_CONTROLLER: &str = "anki_drive_sdk::controller";
+
+#[cfg(any(feature = "tracing", feature = "log"))]
+macro_rules! trace_event {
+    (target: $target:expr, $level:ident, $($arg:tt)+) => {
+        tracing::$level!(target: $target, $($arg)+)
+    };
+    ($level:ident, $($arg:tt)+) => {
+        tracing::$level!($($arg)+)
+    };
+}
+
+#[cfg(not(any(feature = "tracing", feature = "log")))]
+macro_rules! trace_event {
+    // Only the target is looked at, to keep it from going unused.
+    (target: $target:expr, $level:ident, $($arg:tt)+) => {{
+        let _ = $target;
+    }};
+    ($level:ident, $($arg:tt)+) => {};
+}
+
+pub(crate) use trace_event;
@@ -0,0 +1,218 @@
+use crate::protocol::{
+    AnkiVehicleMsgLocalisationPositionUpdate, AnkiVehicleMsgLocalisationTransitionUpdate,
+    VehicleMessage,
+};
+use crate::race::leaderboard::FINISH_LINE_ROAD_PIECE_ID;
+use crate::road_piece::{road_piece_type, RoadPieceType};
+use crate::trace::{trace_event, TARGET_CONTROLLER};
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MappedPiece {
+    pub road_piece_id: u8,
+    pub kind: RoadPieceType,
+}
+
+impl MappedPiece {
+    pub fn new(road_piece_id: u8) -> MappedPiece {
+        MappedPiece {
+            road_piece_id,
+            kind: road_piece_type(road_piece_id),
+        }
+    }
+}
+
+// One lap of the track in driving order, starting with the finish line piece. The last piece
+// joins back onto the first.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackMap {
+    pieces: Vec<MappedPiece>,
+}
+
+impl TrackMap {
+    pub fn pieces(&self) -> &[MappedPiece] {
+        &self.pieces
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    pub fn count(&self, kind: RoadPieceType) -> usize {
+        self.pieces
+            .iter()
+            .filter(|piece| piece.kind == kind)
+            .count()
+    }
+
+    // Index of the first piece with `road_piece_id`, pieces of the same type share an id so it
+    // may be on the track more than once.
+    pub fn position(&self, road_piece_id: u8) -> Option<usize> {
+        self.pieces
+            .iter()
+            .position(|piece| piece.road_piece_id == road_piece_id)
+    }
+}
+
+// Works out the track a vehicle drives on from its localisation updates. A transition update
+// says the vehicle has moved onto the next piece, the position update after it says which piece
+// that is. Mapping starts at the first finish line crossing and a map is ready on the next one.
+// Every lap after that replaces the map, so a track changed between laps is picked up.
+#[derive(Debug, Clone)]
+pub struct TrackMapper {
+    finish_road_piece_id: u8,
+    lap: Option<Vec<MappedPiece>>,
+    entered_piece: bool,
+    map: Option<TrackMap>,
+}
+
+impl Default for TrackMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrackMapper {
+    pub fn new() -> TrackMapper {
+        TrackMapper {
+            finish_road_piece_id: FINISH_LINE_ROAD_PIECE_ID,
+            lap: None,
+            entered_piece: false,
+            map: None,
+        }
+    }
+
+    pub fn with_finish_road_piece_id(mut self, road_piece_id: u8) -> TrackMapper {
+        self.finish_road_piece_id = road_piece_id;
+        self
+    }
+
+    pub fn process_transition_update(
+        &mut self,
+        _data: &AnkiVehicleMsgLocalisationTransitionUpdate,
+    ) {
+        self.entered_piece = true;
+    }
+
+    // Returns the map when this update completed a lap.
+    pub fn process_position_update(
+        &mut self,
+        data: &AnkiVehicleMsgLocalisationPositionUpdate,
+    ) -> Option<&TrackMap> {
+        let road_piece_id = data.road_piece_id;
+        let entered_piece = std::mem::take(&mut self.entered_piece);
+        let Some(lap) = self.lap.as_mut() else {
+            if road_piece_id == self.finish_road_piece_id {
+                self.lap = Some(vec![MappedPiece::new(road_piece_id)]);
+            }
+            return None;
+        };
+        // Position updates on the piece already mapped say nothing new. A missed transition
+        // update still shows up as the piece id changing.
+        let same_piece = lap.last().map(|piece| piece.road_piece_id) == Some(road_piece_id);
+        if same_piece && !entered_piece {
+            return None;
+        }
+        if road_piece_id != self.finish_road_piece_id {
+            lap.push(MappedPiece::new(road_piece_id));
+            return None;
+        }
+
+        let pieces = std::mem::replace(lap, vec![MappedPiece::new(road_piece_id)]);
+        trace_event!(target: TARGET_CONTROLLER, debug, pieces = pieces.len(), "Track mapped");
+        self.map = Some(TrackMap { pieces });
+        self.map.as_ref()
+    }
+
+    // The vehicle has lost the track, pieces it passed while off it weren't seen so the lap
+    // being mapped is thrown away. The last complete map is kept.
+    pub fn process_delocalized(&mut self) {
+        self.lap = None;
+        self.entered_piece = false;
+    }
+
+    // Feeds any notification to the mapper, those that aren't localisation updates are ignored.
+    pub fn process_message(&mut self, msg: &VehicleMessage) -> Option<&TrackMap> {
+        match msg {
+            VehicleMessage::TransitionUpdate(data) => {
+                self.process_transition_update(data);
+                None
+            }
+            VehicleMessage::PositionUpdate(data) => self.process_position_update(data),
+            VehicleMessage::Delocalized(_) => {
+                self.process_delocalized();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // None until a full lap has been driven.
+    pub fn map(&self) -> Option<&TrackMap> {
+        self.map.as_ref()
+    }
+
+    // Pieces mapped so far on the lap in progress.
+    pub fn pieces_mapped(&self) -> usize {
+        self.lap.as_ref().map_or(0, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::sim::track::TrackLayout;
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+    use std::time::Duration;
+
+    #[test]
+    fn track_mapper_test() {
+        let mut vehicle = AnkiVehicleData::default();
+        let mut sim = SimulatedVehicle::new().with_track(TrackLayout::oval());
+        for command in vehicle.configure() {
+            sim.handle_command(&command.encode()).unwrap();
+        }
+        sim.handle_command(&AnkiVehicleData::set_speed(800, 2000))
+            .unwrap();
+
+        let mut mapper = TrackMapper::new();
+        let mut laps = 0;
+        for _ in 0..2000 {
+            sim.advance(Duration::from_millis(10));
+            for frame in sim.drain_notifications() {
+                let msg = VehicleMessage::parse(&frame).unwrap();
+                if mapper.process_message(&msg).is_some() {
+                    laps += 1;
+                }
+            }
+        }
+        assert!(laps >= 1);
+
+        let map = mapper.map().unwrap();
+        let oval = TrackLayout::oval();
+        assert_eq!(
+            oval.pieces()
+                .iter()
+                .map(|piece| piece.road_piece_id)
+                .collect::<Vec<_>>(),
+            map.pieces()
+                .iter()
+                .map(|piece| piece.road_piece_id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(RoadPieceType::Finish, map.pieces()[0].kind);
+        assert_eq!(4, map.count(RoadPieceType::Curve));
+        assert_eq!(Some(4), map.position(36));
+
+        mapper.process_delocalized();
+        assert_eq!(0, mapper.pieces_mapped());
+        assert!(mapper.map().is_some());
+    }
+}
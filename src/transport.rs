@@ -0,0 +1,415 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use btleplug::api::{
+    Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::bt_address::BtAddress;
+use crate::command::{Command, WireMessage};
+use crate::error::AnkiError;
+use crate::host::HostNotification;
+use crate::io::transport::VehicleTransport;
+use crate::protocol::VehicleMessage;
+use crate::telemetry_channel::{
+    bounded_channel, BoundedReceiver, DropPolicy, TELEMETRY_CHANNEL_DEFAULT_CAPACITY,
+};
+use crate::trace::{trace_event, TARGET_TRANSPORT};
+use crate::vehicle_gatt_profile::{ANKI_CHR_READ_UUID, ANKI_CHR_WRITE_UUID, ANKI_SERVICE_UUID};
+use crate::AnkiVehicleData;
+
+// Native BLE transport on top of btleplug, for everywhere the browser transport in
+// `web_bluetooth` doesn't reach. Runs on whatever async runtime btleplug does, tokio on most
+// platforms.
+
+// How long `connect` scans for the vehicle before giving up.
+pub const SCAN_DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Commands a `BleTransport` queues up before `send` starts refusing them.
+pub const BLE_TRANSPORT_WRITE_CAPACITY: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("BLE error: {0}")]
+    Ble(#[from] btleplug::Error),
+    #[error("No Bluetooth adapter found")]
+    NoAdapter,
+    #[error("Vehicle {0} not found")]
+    NotFound(BtAddress),
+    // A command for a vehicle that has lost its connection and not got it back yet.
+    #[error("Vehicle {0} is not connected")]
+    NotConnected(BtAddress),
+    // Too many commands are waiting to be written to the vehicle.
+    #[error("Write queue for vehicle {0} is full")]
+    QueueFull(BtAddress),
+    // The vehicle doesn't have the read or write characteristic of the Anki service.
+    #[error("Vehicle has no characteristic {0}")]
+    MissingCharacteristic(uuid::Uuid),
+}
+
+// A vehicle seen while scanning, not connected yet.
+#[derive(Debug, Clone)]
+pub struct DiscoveredVehicle {
+    pub address: BtAddress,
+    pub name: Option<String>,
+    pub rssi: Option<i16>,
+    peripheral: Peripheral,
+}
+
+impl DiscoveredVehicle {
+    pub async fn connect(&self) -> Result<VehicleConnection, TransportError> {
+        VehicleConnection::open(self.peripheral.clone()).await
+    }
+}
+
+async fn adapter() -> Result<Adapter, TransportError> {
+    Manager::new()
+        .await?
+        .adapters()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(TransportError::NoAdapter)
+}
+
+// Scans the first adapter for `timeout`, returning every vehicle advertising the Anki service.
+pub async fn scan_for_vehicles(
+    timeout: Duration,
+) -> Result<Vec<DiscoveredVehicle>, TransportError> {
+    let central = adapter().await?;
+    central
+        .start_scan(ScanFilter {
+            services: vec![ANKI_SERVICE_UUID],
+        })
+        .await?;
+    tokio::time::sleep(timeout).await;
+    central.stop_scan().await?;
+
+    let mut vehicles = Vec::new();
+    for peripheral in central.peripherals().await? {
+        // Some backends ignore the filter, check the advertised services again.
+        let Some(properties) = peripheral.properties().await? else {
+            continue;
+        };
+        if !properties.services.contains(&ANKI_SERVICE_UUID) {
+            continue;
+        }
+        vehicles.push(DiscoveredVehicle {
+            address: BtAddress::new(peripheral.address().into_inner()),
+            name: properties.local_name,
+            rssi: properties.rssi,
+            peripheral,
+        });
+    }
+    trace_event!(target: TARGET_TRANSPORT, debug, vehicles = vehicles.len(), "Scan finished");
+    Ok(vehicles)
+}
+
+// Scans for up to `SCAN_DEFAULT_TIMEOUT` and connects to the vehicle with this address.
+pub async fn connect(bt_address: BtAddress) -> Result<VehicleConnection, TransportError> {
+    scan_for_vehicles(SCAN_DEFAULT_TIMEOUT)
+        .await?
+        .into_iter()
+        .find(|vehicle| vehicle.address == bt_address)
+        .ok_or(TransportError::NotFound(bt_address))?
+        .connect()
+        .await
+}
+
+// A notification as it came off the read characteristic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub data: Vec<u8>,
+}
+
+impl Notification {
+    // Some adapters pad notifications out to the full 20 byte characteristic, the padding is
+    // ignored.
+    pub fn message(&self) -> Result<VehicleMessage<'_>, AnkiError> {
+        VehicleMessage::parse_padded(&self.data)
+    }
+}
+
+impl AsRef<[u8]> for Notification {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+pub type NotificationStream = Pin<Box<dyn Stream<Item = Notification> + Send>>;
+
+// Reads every frame from a notification source as a `VehicleMessage`, padding and all. A
+// malformed frame comes through as an error and the stream carries on, it only ends with the source.
+pub struct MessageStream<S> {
+    frames: S,
+}
+
+impl<S> MessageStream<S> {
+    pub fn new(frames: S) -> MessageStream<S> {
+        MessageStream { frames }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.frames
+    }
+}
+
+impl<S> Stream for MessageStream<S>
+where
+    S: Stream + Unpin,
+    S::Item: AsRef<[u8]>,
+{
+    type Item = Result<VehicleMessage<'static>, AnkiError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frames.poll_next_unpin(cx).map(|frame| {
+            frame.map(|frame| {
+                VehicleMessage::parse_padded(frame.as_ref()).map(VehicleMessage::into_owned)
+            })
+        })
+    }
+}
+
+pub struct VehicleConnection {
+    address: BtAddress,
+    peripheral: Peripheral,
+    read_chr: Characteristic,
+    write_chr: Characteristic,
+}
+
+impl VehicleConnection {
+    async fn open(peripheral: Peripheral) -> Result<VehicleConnection, TransportError> {
+        peripheral.connect().await?;
+        peripheral.discover_services().await?;
+        let characteristics = peripheral.characteristics();
+        let find = |uuid| {
+            characteristics
+                .iter()
+                .find(|chr| chr.uuid == uuid)
+                .cloned()
+                .ok_or(TransportError::MissingCharacteristic(uuid))
+        };
+        let read_chr = find(ANKI_CHR_READ_UUID)?;
+        let write_chr = find(ANKI_CHR_WRITE_UUID)?;
+        peripheral.subscribe(&read_chr).await?;
+
+        let address = BtAddress::new(peripheral.address().into_inner());
+        trace_event!(target: TARGET_TRANSPORT, info, vehicle = %address, "Connected");
+        Ok(VehicleConnection {
+            address,
+            peripheral,
+            read_chr,
+            write_chr,
+        })
+    }
+
+    pub fn address(&self) -> BtAddress {
+        self.address
+    }
+
+    pub async fn is_connected(&self) -> Result<bool, TransportError> {
+        Ok(self.peripheral.is_connected().await?)
+    }
+
+    pub async fn write(&self, data: &[u8]) -> Result<(), TransportError> {
+        self.peripheral
+            .write(&self.write_chr, data, WriteType::WithoutResponse)
+            .await?;
+        Ok(())
+    }
+
+    // Encodes and writes one command, e.g. `connection.send(AnkiVehicleData::stop())`.
+    pub async fn send(&self, command: impl WireMessage) -> Result<(), TransportError> {
+        self.write(&command.encode()).await
+    }
+
+    // Sends the SDK mode and initial requests, see `AnkiVehicleData::configure`.
+    pub async fn configure(&self, vehicle: &mut AnkiVehicleData) -> Result<(), TransportError> {
+        for command in vehicle.configure() {
+            self.write(&Command::from(command)).await?;
+        }
+        Ok(())
+    }
+
+    // Every notification from the vehicle from now on, `Notification::message` parses one.
+    pub async fn notifications(&self) -> Result<NotificationStream, TransportError> {
+        let read_uuid = self.read_chr.uuid;
+        let notifications = self.peripheral.notifications().await?;
+        Ok(Box::pin(notifications.filter_map(move |notification| {
+            let frame = (notification.uuid == read_uuid).then_some(Notification {
+                data: notification.value,
+            });
+            async move { frame }
+        })))
+    }
+
+    // Every notification from now on, already parsed.
+    pub async fn messages(&self) -> Result<MessageStream<NotificationStream>, TransportError> {
+        Ok(MessageStream::new(self.notifications().await?))
+    }
+
+    pub async fn disconnect(&self) -> Result<(), TransportError> {
+        // Best effort, the vehicle may already be gone.
+        let _ = self.peripheral.unsubscribe(&self.read_chr).await;
+        self.peripheral.disconnect().await?;
+        Ok(())
+    }
+
+    // Hands the connection over to two tasks on the current tokio runtime, one writing commands
+    // in the order they were sent and one collecting notifications, and returns the
+    // `VehicleTransport` that talks to them. Up to `TELEMETRY_CHANNEL_DEFAULT_CAPACITY`
+    // notifications are kept for the transport, see `into_transport_with`. The connection is
+    // closed when the transport is dropped or a write fails.
+    pub async fn into_transport(self) -> Result<BleTransport, TransportError> {
+        self.into_transport_with(TELEMETRY_CHANNEL_DEFAULT_CAPACITY, DropPolicy::default())
+            .await
+    }
+
+    // Same as `into_transport`, keeping up to `capacity` notifications that haven't been polled
+    // yet and making room for more according to `policy`.
+    pub async fn into_transport_with(
+        self,
+        capacity: usize,
+        policy: DropPolicy,
+    ) -> Result<BleTransport, TransportError> {
+        let address = self.address;
+        let mut frames = self.notifications().await?;
+        let (notification_tx, notifications) = bounded_channel(capacity, policy);
+        tokio::spawn(async move {
+            while let Some(notification) = frames.next().await {
+                let notification = HostNotification {
+                    vehicle: address.into(),
+                    data: notification.data,
+                };
+                if notification_tx.send(notification).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (writes, mut write_rx) = mpsc::channel::<Vec<u8>>(BLE_TRANSPORT_WRITE_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(data) = write_rx.recv().await {
+                if let Err(_e) = self.write(&data).await {
+                    trace_event!(target: TARGET_TRANSPORT, warn, vehicle = %self.address, error = %_e, "Write failed, closing the connection");
+                    break;
+                }
+            }
+            let _ = self.disconnect().await;
+        });
+        Ok(BleTransport {
+            address,
+            writes,
+            notifications,
+        })
+    }
+}
+
+// See `VehicleConnection::into_transport`.
+pub struct BleTransport {
+    address: BtAddress,
+    writes: mpsc::Sender<Vec<u8>>,
+    notifications: BoundedReceiver,
+}
+
+impl BleTransport {
+    pub fn address(&self) -> BtAddress {
+        self.address
+    }
+
+    // Notifications lost because they weren't polled in time.
+    pub fn dropped(&self) -> u64 {
+        self.notifications.dropped() + self.notifications.coalesced()
+    }
+}
+
+impl VehicleTransport for BleTransport {
+    type Error = TransportError;
+
+    // Queued for the writer task. Fails once the connection has been closed, or when
+    // `BLE_TRANSPORT_WRITE_CAPACITY` writes are already waiting for a vehicle that has stopped
+    // taking them.
+    fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.writes.try_send(data.to_vec()).map_err(|e| match e {
+            TrySendError::Full(_) => TransportError::QueueFull(self.address),
+            TrySendError::Closed(_) => TransportError::NotConnected(self.address),
+        })
+    }
+
+    fn poll_notification(&mut self) -> Option<Vec<u8>> {
+        self.notifications
+            .try_recv()
+            .ok()
+            .map(|notification| notification.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        AnkiVehicleMsgType, ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE,
+        ANKI_VEHICLE_MSG_MAX_SIZE,
+    };
+
+    #[test]
+    fn notification_message_test() {
+        let notification = Notification {
+            data: vec![
+                3,
+                AnkiVehicleMsgType::V2CBatteryLevelResponse.into(),
+                0x10,
+                0x0e,
+            ],
+        };
+        let Ok(VehicleMessage::BatteryLevelResponse(response)) = notification.message() else {
+            panic!("Expected a battery level response");
+        };
+        assert_eq!(0x0e10, response.battery_level);
+        assert!(Notification { data: vec![] }.message().is_err());
+    }
+
+    #[test]
+    fn message_stream_test() {
+        let frames = futures::stream::iter([
+            vec![1, AnkiVehicleMsgType::V2CPingResponse.into()],
+            vec![3, AnkiVehicleMsgType::V2CBatteryLevelResponse.into()],
+            vec![3, 0xee, 1, 2],
+        ]);
+        let messages: Vec<_> =
+            futures::executor::block_on(MessageStream::new(frames).collect::<Vec<_>>());
+        assert_eq!(3, messages.len());
+        assert!(matches!(messages[0], Ok(VehicleMessage::PingResponse(_))));
+        assert!(messages[1].is_err());
+        assert_eq!(
+            AnkiVehicleMsgType::Unknown,
+            messages[2].as_ref().unwrap().msg_type()
+        );
+    }
+
+    #[test]
+    fn message_stream_padded_test() {
+        let mut frame = vec![
+            ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE as u8 - 1,
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate.into(),
+            0x0a,
+            0x0b,
+        ];
+        frame.resize(ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE, 0);
+        frame.resize(ANKI_VEHICLE_MSG_MAX_SIZE, 0);
+        assert_eq!(20, frame.len());
+
+        let messages: Vec<_> = futures::executor::block_on(
+            MessageStream::new(futures::stream::iter([frame])).collect::<Vec<_>>(),
+        );
+        let [Ok(VehicleMessage::PositionUpdate(update))] = &messages[..] else {
+            panic!("Expected one position update, got {:?}", messages);
+        };
+        assert_eq!(0x0b, update.road_piece_id);
+    }
+}
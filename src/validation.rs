@@ -0,0 +1,208 @@
+use scroll::Pread;
+
+use crate::error::{check_frame_len, AnkiError};
+use crate::protocol::{
+    AnkiVehicleMsg, AnkiVehicleMsgLocalisationIntersectionUpdate, AnkiVehicleMsgType,
+    IntersectionCode, ProtocolError, ANKI_VEHICLE_MSG_BASE_SIZE,
+    ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE, ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE,
+    ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE,
+    ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE,
+    ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE,
+    ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE, ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE,
+    ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE, WIRE_ENDIAN,
+};
+
+// By default notifications are read the way vehicles have always been read: an id nobody knows
+// becomes `AnkiVehicleMsgType::Unknown`, the size byte is only used to cut off padding after the
+// message and messages that carry no state are let through unchecked. Strict mode turns all of
+// that into errors, for deployments that would rather drop a frame than act on one that doesn't
+// add up.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ValidationMode {
+    #[default]
+    Lenient,
+    Strict,
+}
+
+// Size of a notification a vehicle sends, None for ids that aren't notifications.
+pub fn notification_size(msg_id: &AnkiVehicleMsgType) -> Option<usize> {
+    match msg_id {
+        AnkiVehicleMsgType::V2CPingResponse
+        | AnkiVehicleMsgType::V2CVehicleDelocalized
+        | AnkiVehicleMsgType::V2CCollisionDetected
+        | AnkiVehicleMsgType::V2CCycleOvertime => Some(ANKI_VEHICLE_MSG_BASE_SIZE),
+        AnkiVehicleMsgType::V2CVersionResponse => Some(ANKI_VEHICLE_MSG_VERSION_RESPONSE_SIZE),
+        AnkiVehicleMsgType::V2CBatteryLevelResponse => {
+            Some(ANKI_VEHICLE_MSG_BATTERY_LEVEL_RESPONSE_SIZE)
+        }
+        AnkiVehicleMsgType::V2CLocalisationPositionUpdate => {
+            Some(ANKI_VEHICLE_MSG_LOCALISATION_POSITION_UPDATE_SIZE)
+        }
+        AnkiVehicleMsgType::V2CLocalisationTransitionUpdate => {
+            Some(ANKI_VEHICLE_MSG_LOCALISATION_TRANSITION_UPDATE_SIZE)
+        }
+        AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate => {
+            Some(ANKI_VEHICLE_MSG_LOCALISATION_INTERSECTION_UPDATE_SIZE)
+        }
+        AnkiVehicleMsgType::V2COffsetFromRoadCentreUpdate => {
+            Some(ANKI_VEHICLE_MSG_OFFSET_FROM_ROAD_CENTRE_UPDATE_SIZE)
+        }
+        AnkiVehicleMsgType::V2CSpeedUpdate => Some(ANKI_VEHICLE_MSG_SPEED_UPDATE_SIZE),
+        AnkiVehicleMsgType::V2CChargerInfo => Some(ANKI_VEHICLE_MSG_CHARGER_INFO_SIZE),
+        _ => None,
+    }
+}
+
+// The strict mode checks, for a raw notification. Passing them says nothing about the values
+// themselves, only that the frame is one a vehicle could have sent.
+pub fn validate_notification(data: &[u8]) -> Result<AnkiVehicleMsgType, AnkiError> {
+    let msg = data.pread_with::<AnkiVehicleMsg>(0, WIRE_ENDIAN)?;
+    if msg.msg_id == AnkiVehicleMsgType::Unknown {
+        return Err(ProtocolError::UnknownMsgType(data[1]).into());
+    }
+    let expected = notification_size(&msg.msg_id)
+        .ok_or_else(|| AnkiError::UnexpectedMsg(msg.msg_id.clone()))?;
+    check_frame_len(data, expected)?;
+    let declared = data[0] as usize + 1;
+    if declared != data.len() {
+        return Err(ProtocolError::SizeMismatch {
+            declared,
+            got: data.len(),
+        }
+        .into());
+    }
+    if msg.msg_id == AnkiVehicleMsgType::V2CLocalisationIntersectionUpdate {
+        let update =
+            data.pread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(0, WIRE_ENDIAN)?;
+        // The raw byte, the codec has already turned an unknown code into `None`.
+        let intersection_code = data[7];
+        if IntersectionCode::try_from(intersection_code).is_err() {
+            return Err(ProtocolError::InvalidEnumValue {
+                field: "intersection_code",
+                value: intersection_code as u32,
+            }
+            .into());
+        }
+        if update.is_exiting > 1 {
+            return Err(ProtocolError::FieldOutOfRange {
+                field: "is_exiting",
+                value: update.is_exiting as u32,
+            }
+            .into());
+        }
+    }
+    Ok(msg.msg_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::WireMessage;
+    use crate::protocol::{VehicleMessage, ANKI_VEHICLE_MSG_MAX_SIZE};
+    use crate::sim::vehicle::SimulatedVehicle;
+    use crate::AnkiVehicleData;
+    use std::time::Duration;
+
+    #[test]
+    fn validate_notification_test() {
+        assert!(matches!(
+            validate_notification(&[0x01, 0x7f]),
+            Err(AnkiError::Protocol(ProtocolError::UnknownMsgType(0x7f)))
+        ));
+        assert!(matches!(
+            validate_notification(&[0x01, 0x24]),
+            Err(AnkiError::UnexpectedMsg(AnkiVehicleMsgType::C2VSetSpeed))
+        ));
+        assert!(matches!(
+            validate_notification(&[0x03, 0x19, 0x76]),
+            Err(AnkiError::Protocol(ProtocolError::TooShort {
+                expected: 4,
+                got: 3
+            }))
+        ));
+        assert!(matches!(
+            validate_notification(&[0x05, 0x19, 0x76, 0x26]),
+            Err(AnkiError::Protocol(ProtocolError::SizeMismatch {
+                declared: 6,
+                got: 4
+            }))
+        ));
+        let intersection = [
+            0x0c, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x50, 0x00, 0x00, 0x00,
+        ];
+        assert!(matches!(
+            validate_notification(&intersection),
+            Err(AnkiError::Protocol(ProtocolError::FieldOutOfRange {
+                field: "is_exiting",
+                value: 2
+            }))
+        ));
+        let intersection = [
+            0x0c, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0x50, 0x00, 0x00, 0x00,
+        ];
+        assert!(matches!(
+            validate_notification(&intersection),
+            Err(AnkiError::Protocol(ProtocolError::InvalidEnumValue {
+                field: "intersection_code",
+                value: 0xff
+            }))
+        ));
+        assert_eq!(
+            AnkiVehicleMsgType::V2CVersionResponse,
+            validate_notification(&[0x03, 0x19, 0x76, 0x26]).unwrap()
+        );
+    }
+
+    #[test]
+    fn lenient_intersection_code_test() {
+        let intersection = [
+            0x0c, 0x2a, 0x01, 0x00, 0x00, 0x00, 0x00, 0xff, 0x01, 0x50, 0x00, 0x00, 0x00,
+        ];
+        let update = intersection
+            .pread_with::<AnkiVehicleMsgLocalisationIntersectionUpdate>(0, WIRE_ENDIAN)
+            .unwrap();
+        assert_eq!(IntersectionCode::None, update.intersection_code);
+        assert!(VehicleMessage::parse_padded(&intersection).is_ok());
+
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.process_notification(&intersection).unwrap();
+        assert_eq!(IntersectionCode::None, vehicle.intersection_code);
+
+        vehicle.set_validation_mode(ValidationMode::Strict);
+        assert!(vehicle.process_notification(&intersection).is_err());
+    }
+
+    #[test]
+    fn strict_mode_test() {
+        let mut vehicle = AnkiVehicleData::default();
+        vehicle.set_validation_mode(ValidationMode::Strict);
+        let mut sim = SimulatedVehicle::new();
+        for command in vehicle.configure() {
+            sim.handle_command(&command.encode()).unwrap();
+        }
+        sim.handle_command(&AnkiVehicleData::set_speed(500, 1000))
+            .unwrap();
+        for _ in 0..500 {
+            sim.advance(Duration::from_millis(10));
+            for frame in sim.drain_notifications() {
+                vehicle.process_notification(&frame).unwrap();
+            }
+        }
+        assert!(vehicle.process_notification(&[0x01, 0x7f]).is_err());
+
+        let mut padded = [0u8; ANKI_VEHICLE_MSG_MAX_SIZE];
+        padded[..4].copy_from_slice(&[0x03, 0x1b, 0x10, 0x0e]);
+        assert!(vehicle.process_notification(&padded).is_err());
+
+        vehicle.set_validation_mode(ValidationMode::Lenient);
+        assert_eq!(
+            AnkiVehicleMsgType::Unknown,
+            vehicle.process_notification(&[0x01, 0x7f]).unwrap()
+        );
+        assert_eq!(
+            AnkiVehicleMsgType::V2CBatteryLevelResponse,
+            vehicle.process_notification(&padded).unwrap()
+        );
+        assert_eq!(0x0e10, vehicle.battery_level());
+    }
+}
@@ -1,14 +1,85 @@
-#![allow(unused)]
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
 
-use uuid::{uuid, Uuid};
-
-pub const ANKI_SERVICE_UUID: Uuid = uuid!["BE15BEEF-6186-407E-8381-0BD89C4D8DF4"];
+// The vehicle has one service with two characteristics. The 128-bit forms are always there, the
+// `Uuid` ones come with the `uuid` feature.
 pub const ANKI_U128_SERVICE_UUID: u128 = 0xBE15BEEF6186407E83810BD89C4D8DF4;
-
-pub const ANKI_CHR_READ_UUID: Uuid = uuid!["BE15BEE0-6186-407E-8381-0BD89C4D8DF4"];
 pub const ANKI_U128_CHR_READ_UUID: u128 = 0xBE15BEE06186407E83810BD89C4D8DF4;
+pub const ANKI_U128_CHR_WRITE_UUID: u128 = 0xBE15BEE16186407E83810BD89C4D8DF4;
+
+#[cfg(feature = "uuid")]
+pub const ANKI_SERVICE_UUID: Uuid = Uuid::from_u128(ANKI_U128_SERVICE_UUID);
+#[cfg(feature = "uuid")]
+pub const ANKI_CHR_READ_UUID: Uuid = Uuid::from_u128(ANKI_U128_CHR_READ_UUID);
+#[cfg(feature = "uuid")]
+pub const ANKI_CHR_WRITE_UUID: Uuid = Uuid::from_u128(ANKI_U128_CHR_WRITE_UUID);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CharacteristicRole {
+    // The vehicle notifies on it, subscribe to get its messages.
+    Read,
+    // Commands are written to it, without response.
+    Write,
+}
+
+impl CharacteristicRole {
+    pub const fn as_u128(&self) -> u128 {
+        match self {
+            CharacteristicRole::Read => ANKI_U128_CHR_READ_UUID,
+            CharacteristicRole::Write => ANKI_U128_CHR_WRITE_UUID,
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    pub const fn uuid(&self) -> Uuid {
+        Uuid::from_u128(self.as_u128())
+    }
+}
+
+// Takes the 128-bit form, a `Uuid` gives it with `as_u128`.
+pub const fn is_anki_service(uuid: u128) -> bool {
+    uuid == ANKI_U128_SERVICE_UUID
+}
+
+// None for a characteristic that isn't one of the vehicle's.
+pub const fn characteristic_role(uuid: u128) -> Option<CharacteristicRole> {
+    match uuid {
+        ANKI_U128_CHR_READ_UUID => Some(CharacteristicRole::Read),
+        ANKI_U128_CHR_WRITE_UUID => Some(CharacteristicRole::Write),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub const ANKI_CHR_WRITE_UUID: Uuid = uuid!["BE15BEE1-6186-407E-8381-0BD89C4D8DF4"];
-pub const ANKI_U128_CHR_WRITE_UUID: u128 = 0xBE15BEE06186407E83810BD89C4D8DF4;
+    #[test]
+    fn characteristic_role_test() {
+        assert!(is_anki_service(ANKI_U128_SERVICE_UUID));
+        assert!(!is_anki_service(ANKI_U128_CHR_READ_UUID));
+        assert_eq!(
+            Some(CharacteristicRole::Read),
+            characteristic_role(ANKI_U128_CHR_READ_UUID)
+        );
+        assert_eq!(
+            Some(CharacteristicRole::Write),
+            characteristic_role(CharacteristicRole::Write.as_u128())
+        );
+        assert_eq!(None, characteristic_role(ANKI_U128_SERVICE_UUID));
+    }
 
-//TODO: implement comparators for uuids, could do this in its own module like original drive sdk.
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn characteristic_uuid_test() {
+        assert_eq!(
+            "be15bee1-6186-407e-8381-0bd89c4d8df4",
+            CharacteristicRole::Write.uuid().hyphenated().to_string()
+        );
+        assert_eq!(
+            Some(CharacteristicRole::Read),
+            characteristic_role(ANKI_CHR_READ_UUID.as_u128())
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::advertisement::AnkiVehicleAdvMfgData;
+use crate::bt_address::BtAddress;
+
+// Names one vehicle across the fleet, race and telemetry APIs. Usually the Bluetooth address or
+// the identifier the vehicle advertises, but any string the host picks will do as long as it
+// stays the same for as long as the vehicle is connected. It borrows as a `str`, so maps keyed
+// by it can still be looked up with a plain string.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct VehicleId(String);
+
+impl VehicleId {
+    pub fn new<S: Into<String>>(id: S) -> VehicleId {
+        VehicleId(id.into())
+    }
+
+    // Most significant byte first, see `BtAddress`.
+    pub fn from_address(address: [u8; 6]) -> VehicleId {
+        BtAddress::new(address).into()
+    }
+
+    // The identifier stays the same across connections, unlike the random address some
+    // platforms hand out instead of the real one.
+    pub fn from_adv(mfg_data: &AnkiVehicleAdvMfgData) -> VehicleId {
+        VehicleId(format!("{:08x}", mfg_data.identifier))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Display for VehicleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for VehicleId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for VehicleId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for VehicleId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for VehicleId {
+    fn from(id: &str) -> VehicleId {
+        VehicleId(id.to_string())
+    }
+}
+
+impl From<String> for VehicleId {
+    fn from(id: String) -> VehicleId {
+        VehicleId(id)
+    }
+}
+
+impl From<&String> for VehicleId {
+    fn from(id: &String) -> VehicleId {
+        VehicleId(id.clone())
+    }
+}
+
+impl From<VehicleId> for String {
+    fn from(id: VehicleId) -> String {
+        id.0
+    }
+}
+
+impl PartialEq<str> for VehicleId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for VehicleId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for VehicleId {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<VehicleId> for str {
+    fn eq(&self, other: &VehicleId) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<VehicleId> for &str {
+    fn eq(&self, other: &VehicleId) -> bool {
+        *self == other.0
+    }
+}
+
+impl PartialEq<VehicleId> for String {
+    fn eq(&self, other: &VehicleId) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::WIRE_ENDIAN;
+    use scroll::Pread;
+    use std::collections::HashMap;
+
+    #[test]
+    fn vehicle_id_test() {
+        let id = VehicleId::from_address([0xc2, 0x4a, 0x01, 0x9e, 0x33, 0x0f]);
+        assert_eq!("C2:4A:01:9E:33:0F", id);
+        assert_eq!("C2:4A:01:9E:33:0F", id.to_string());
+
+        let mfg_data = [0xef, 0xcd, 0xab, 0x89, 0x08, 0x00, 0x01, 0x00]
+            .pread_with::<AnkiVehicleAdvMfgData>(0, WIRE_ENDIAN)
+            .unwrap();
+        assert_eq!("89abcdef", VehicleId::from_adv(&mfg_data));
+
+        let mut laps: HashMap<VehicleId, u16> = HashMap::new();
+        laps.insert("skull".into(), 3);
+        assert_eq!(Some(&3), laps.get("skull"));
+
+        let mut ids = [VehicleId::from("skull"), VehicleId::from("grip")];
+        ids.sort();
+        assert_eq!("grip", ids[0]);
+    }
+}
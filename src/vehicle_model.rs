@@ -0,0 +1,111 @@
+use std::fmt;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::advertisement::AnkiVehicleAdvMfgData;
+use crate::dialect::LAST_DRIVE_MODEL_ID;
+
+// The model ids from the manufacturer data of the advertisement. The first seven are the Drive
+// cars, the rest came with Overdrive. 0x0d was never sold.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[repr(u8)]
+pub enum VehicleModel {
+    Kourai = 0x01,
+    Boson = 0x02,
+    Rho = 0x03,
+    Katal = 0x04,
+    Hadion = 0x05,
+    Spektrix = 0x06,
+    Corax = 0x07,
+    GroundShock = 0x08,
+    Skull = 0x09,
+    Thermo = 0x0a,
+    Nuke = 0x0b,
+    Guardian = 0x0c,
+    BigBang = 0x0e,
+    Freewheel = 0x0f,
+    X52 = 0x10,
+    X52Ice = 0x11,
+    Mxt = 0x12,
+    IceCharger = 0x13,
+}
+
+impl VehicleModel {
+    // The name the vehicle was sold under.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VehicleModel::Kourai => "Kourai",
+            VehicleModel::Boson => "Boson",
+            VehicleModel::Rho => "Rho",
+            VehicleModel::Katal => "Katal",
+            VehicleModel::Hadion => "Hadion",
+            VehicleModel::Spektrix => "Spektrix",
+            VehicleModel::Corax => "Corax",
+            VehicleModel::GroundShock => "Ground Shock",
+            VehicleModel::Skull => "Skull",
+            VehicleModel::Thermo => "Thermo",
+            VehicleModel::Nuke => "Nuke",
+            VehicleModel::Guardian => "Guardian",
+            VehicleModel::BigBang => "Big Bang",
+            VehicleModel::Freewheel => "Freewheel",
+            VehicleModel::X52 => "X52",
+            VehicleModel::X52Ice => "X52 Ice",
+            VehicleModel::Mxt => "MXT",
+            VehicleModel::IceCharger => "Ice Charger",
+        }
+    }
+
+    pub fn model_id(&self) -> u8 {
+        (*self).into()
+    }
+
+    // Whether the model was first sold with Drive, those can be updated to Overdrive firmware.
+    pub fn is_drive(&self) -> bool {
+        self.model_id() <= LAST_DRIVE_MODEL_ID
+    }
+}
+
+impl fmt::Display for VehicleModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl AnkiVehicleAdvMfgData {
+    // None for a model id the crate doesn't know.
+    pub fn model(&self) -> Option<VehicleModel> {
+        VehicleModel::try_from(self.model_id).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::advertisement::ANKI_VEHICLE_ADV_MFG_DATA_SIZE;
+    use crate::protocol::WIRE_ENDIAN;
+    use scroll::Pread;
+
+    #[test]
+    fn vehicle_model_test() {
+        let data: &[u8; ANKI_VEHICLE_ADV_MFG_DATA_SIZE] =
+            &[0xEF, 0xCD, 0xAB, 0x89, 0x09, 0x00, 0xEF, 0xCD];
+        let mfg_data = data
+            .gread_with::<AnkiVehicleAdvMfgData>(&mut 0, WIRE_ENDIAN)
+            .unwrap();
+        assert_eq!(Some(VehicleModel::Skull), mfg_data.model());
+        assert_eq!("Skull", VehicleModel::Skull.to_string());
+        assert!(!VehicleModel::Skull.is_drive());
+
+        assert_eq!(Ok(VehicleModel::BigBang), VehicleModel::try_from(0x0e));
+        assert_eq!("Big Bang", VehicleModel::BigBang.name());
+        assert!(VehicleModel::Kourai.is_drive());
+        assert!(VehicleModel::try_from(0x0d).is_err());
+        assert!(VehicleModel::try_from(0x00).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use crate::advertisement::AnkiVehicleState;
+use crate::lap::LapCompleted;
+use crate::protocol::{IntersectionCode, ParsingFlags};
+use crate::AnkiVehicleData;
+
+// Everything known about a vehicle at one point, copied out of `AnkiVehicleData` so it can be
+// kept, compared or sent on while the vehicle carries on updating.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VehicleState {
+    pub name: String,
+    pub version: u16,
+    pub battery_level: u16,
+    pub low_battery: bool,
+    pub full_battery: bool,
+    pub on_charger: bool,
+
+    pub speed_mm_per_sec: u16,
+    pub offset_from_road_centre_mm: f32,
+    pub location_id: u8,
+    pub is_localized: bool,
+    pub parsing_flags: ParsingFlags,
+    pub last_desired_speed_mm_per_sec: u16,
+    pub last_desired_lane_change_speed_mm_per_sec: u16,
+
+    pub road_piece_idx: i8,
+    pub road_piece_idx_prev: i8,
+    pub uphill_counter: u8,
+    pub downhill_counter: u8,
+    pub left_wheel_dist_cm: u8,
+    pub right_wheel_dist_cm: u8,
+
+    pub intersection_code: IntersectionCode,
+    pub is_exiting_intersection: bool,
+    pub mm_since_last_transition_bar: u16,
+    pub mm_since_last_intersection_code: u16,
+}
+
+impl From<&AnkiVehicleData> for VehicleState {
+    fn from(data: &AnkiVehicleData) -> Self {
+        VehicleState {
+            name: data.name.clone(),
+            version: data.version,
+            battery_level: data.battery_level,
+            low_battery: data.state.low_battery,
+            full_battery: data.state.full_battery,
+            on_charger: data.state.on_charger,
+            speed_mm_per_sec: data.speed_mm_per_sec,
+            offset_from_road_centre_mm: data.offset_from_road_centre_mm,
+            location_id: data.location_id,
+            is_localized: data.localized,
+            parsing_flags: data.parsing_flags,
+            last_desired_speed_mm_per_sec: data.last_desired_speed_mm_per_sec,
+            last_desired_lane_change_speed_mm_per_sec: data
+                .last_desired_lane_change_speed_mm_per_sec,
+            road_piece_idx: data.road_piece_idx,
+            road_piece_idx_prev: data.road_piece_idx_prev,
+            uphill_counter: data.uphill_counter,
+            downhill_counter: data.downhill_counter,
+            left_wheel_dist_cm: data.left_wheel_dist_cm,
+            right_wheel_dist_cm: data.right_wheel_dist_cm,
+            intersection_code: data.intersection_code.clone(),
+            is_exiting_intersection: data.is_exiting_intersection != 0,
+            mm_since_last_transition_bar: data.mm_since_last_transition_bar,
+            mm_since_last_intersection_code: data.mm_since_last_intersection_code,
+        }
+    }
+}
+
+// What a message changed in the vehicle state, returned by `AnkiVehicleData::process_message`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StateChange {
+    // The message carries no vehicle state, an unknown id or a ping response to no known ping.
+    None,
+    Version(u16),
+    Battery(u16),
+    // The charger and battery flags, from a charger info notification.
+    Charger(AnkiVehicleState),
+    Position {
+        location_id: u8,
+        speed_mm_per_sec: u16,
+        offset_from_road_centre_mm: f32,
+    },
+    Transition {
+        road_piece_idx: i8,
+        road_piece_idx_prev: i8,
+    },
+    Intersection {
+        intersection_code: IntersectionCode,
+        is_exiting: bool,
+    },
+    Offset(f32),
+    // A position update that crossed the finish line and completed a lap, along with the
+    // position it carries.
+    LapCompleted {
+        lap: LapCompleted,
+        location_id: u8,
+        speed_mm_per_sec: u16,
+        offset_from_road_centre_mm: f32,
+    },
+    // The round trip of the ping a ping response answers.
+    Latency(Duration),
+    // The vehicle has lost the track, the position is cleared until it finds it again.
+    Delocalized,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::BatteryFlags;
+    use crate::freshness::StateGroup;
+    use crate::protocol::{AnkiVehicleMsgType, VehicleMessage};
+    use std::time::Instant;
+
+    #[test]
+    fn vehicle_state_test() {
+        let mut vehicle = AnkiVehicleData::default().with_name("Skull");
+        vehicle
+            .process_notification(&[
+                16,
+                AnkiVehicleMsgType::V2CLocalisationPositionUpdate.into(),
+                7,
+                33,
+                0,
+                0,
+                200,
+                66,
+                0xf4,
+                0x01,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x58,
+                0x02,
+            ])
+            .unwrap();
+
+        let state = vehicle.state();
+        assert_eq!("Skull", state.name);
+        assert_eq!(7, state.location_id);
+        assert_eq!(100.0, state.offset_from_road_centre_mm);
+        assert_eq!(500, state.speed_mm_per_sec);
+        assert_eq!(600, state.last_desired_speed_mm_per_sec);
+        assert_eq!(state.speed_mm_per_sec, vehicle.speed_mm_per_sec());
+        assert_eq!(IntersectionCode::None, state.intersection_code);
+        assert!(!state.is_exiting_intersection);
+        assert!(state.is_localized);
+
+        vehicle
+            .process_raw(&[1, AnkiVehicleMsgType::V2CVehicleDelocalized.into()])
+            .unwrap();
+        assert!(!vehicle.is_localized());
+        assert_eq!(0, vehicle.location_id());
+        assert_eq!(500, vehicle.speed_mm_per_sec());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn serde_round_trip_test() {
+        let frame = [
+            16,
+            AnkiVehicleMsgType::V2CLocalisationPositionUpdate.into(),
+            7,
+            33,
+            0,
+            0,
+            200,
+            66,
+            0xf4,
+            0x01,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0x58,
+            0x02,
+        ];
+        let msg = VehicleMessage::parse(&frame).unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(msg, serde_json::from_str(&json).unwrap());
+
+        let mut vehicle = AnkiVehicleData::default().with_name("Skull");
+        let change = vehicle.process_raw(&frame).unwrap();
+        let json = serde_json::to_string(&change).unwrap();
+        assert!(json.starts_with(r#"{"position":{"location_id":7,"#));
+        assert_eq!(change, serde_json::from_str(&json).unwrap());
+        let state = vehicle.state();
+        assert_eq!(
+            state,
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn process_message_test() {
+        let mut vehicle = AnkiVehicleData::default();
+        assert_eq!(
+            StateChange::Battery(0x0e10),
+            vehicle
+                .process_raw(&[
+                    3,
+                    AnkiVehicleMsgType::V2CBatteryLevelResponse.into(),
+                    0x10,
+                    0x0e
+                ])
+                .unwrap()
+        );
+        assert_eq!(0x0e10, vehicle.battery_level());
+        assert!(vehicle
+            .freshness()
+            .updated_at(StateGroup::Battery)
+            .is_some());
+
+        assert_eq!(
+            StateChange::Delocalized,
+            vehicle
+                .process_raw(&[1, AnkiVehicleMsgType::V2CVehicleDelocalized.into()])
+                .unwrap()
+        );
+        assert_eq!(
+            StateChange::None,
+            vehicle
+                .process_raw(&[1, AnkiVehicleMsgType::V2CPingResponse.into()])
+                .unwrap()
+        );
+        assert!(vehicle.process_raw(&[]).is_err());
+
+        assert!(!vehicle.is_on_charger());
+        vehicle
+            .process_raw(&[5, AnkiVehicleMsgType::V2CChargerInfo.into(), 0, 1, 0, 1])
+            .unwrap();
+        assert!(vehicle.is_on_charger());
+        assert!(vehicle.is_charged());
+        assert_eq!(Some(BatteryFlags::default()), vehicle.battery_flags());
+
+        let t0 = Instant::now();
+        let pong = [1, AnkiVehicleMsgType::V2CPingResponse.into()];
+        vehicle.ping_at(t0);
+        assert_eq!(
+            StateChange::Latency(Duration::from_millis(20)),
+            vehicle.process_message_at(
+                VehicleMessage::parse(&pong).unwrap(),
+                t0 + Duration::from_millis(20)
+            )
+        );
+    }
+}
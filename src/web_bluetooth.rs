@@ -0,0 +1,122 @@
+use js_sys::{JsString, Uint8Array};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    BluetoothDevice, BluetoothLeScanFilterInit, BluetoothRemoteGattCharacteristic,
+    BluetoothRemoteGattServer, RequestDeviceOptions,
+};
+
+use crate::command::WireMessage;
+use crate::vehicle_gatt_profile::{ANKI_CHR_READ_UUID, ANKI_CHR_WRITE_UUID, ANKI_SERVICE_UUID};
+use crate::AnkiVehicleData;
+
+// Web Bluetooth wants lowercase UUID strings.
+fn uuid_str(uuid: &uuid::Uuid) -> String {
+    uuid.hyphenated().to_string()
+}
+
+// Browser transport: the page asks the user to pick a vehicle, after that commands are written
+// to the vehicle's write characteristic and notifications are handed to a callback as raw frames.
+pub struct WebBluetoothTransport {
+    device: BluetoothDevice,
+    server: BluetoothRemoteGattServer,
+    read_chr: BluetoothRemoteGattCharacteristic,
+    write_chr: BluetoothRemoteGattCharacteristic,
+    // Kept alive for as long as notifications are delivered.
+    on_notify: Option<Closure<dyn FnMut(JsValue)>>,
+}
+
+impl WebBluetoothTransport {
+    // Must be called from a user gesture (e.g. a click handler), browsers reject it otherwise.
+    pub async fn request() -> Result<WebBluetoothTransport, JsValue> {
+        let bluetooth = web_sys::window()
+            .and_then(|window| window.navigator().bluetooth())
+            .ok_or_else(|| JsValue::from_str("Web Bluetooth is not available"))?;
+
+        let filter = BluetoothLeScanFilterInit::new();
+        filter.set_services(&[JsString::from(uuid_str(&ANKI_SERVICE_UUID))]);
+        let options = RequestDeviceOptions::new();
+        options.set_filters(&[filter]);
+
+        let device = bluetooth.request_device(&options).await?;
+        WebBluetoothTransport::connect(device).await
+    }
+
+    pub async fn connect(device: BluetoothDevice) -> Result<WebBluetoothTransport, JsValue> {
+        let server = device
+            .gatt()
+            .ok_or_else(|| JsValue::from_str("Device has no GATT server"))?
+            .connect()
+            .await?;
+        let service = server
+            .get_primary_service_with_str(&uuid_str(&ANKI_SERVICE_UUID))
+            .await?;
+        let read_chr = service
+            .get_characteristic_with_str(&uuid_str(&ANKI_CHR_READ_UUID))
+            .await?;
+        let write_chr = service
+            .get_characteristic_with_str(&uuid_str(&ANKI_CHR_WRITE_UUID))
+            .await?;
+
+        Ok(WebBluetoothTransport {
+            device,
+            server,
+            read_chr,
+            write_chr,
+            on_notify: None,
+        })
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.device.name()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.server.connected()
+    }
+
+    pub async fn write(&self, data: &[u8]) -> Result<(), JsValue> {
+        self.write_chr
+            .write_value_without_response_with_u8_slice(data)?
+            .await?;
+        Ok(())
+    }
+
+    // Sends the SDK mode and initial requests, see `AnkiVehicleData::configure`.
+    pub async fn configure(&self, vehicle: &mut AnkiVehicleData) -> Result<(), JsValue> {
+        for command in vehicle.configure() {
+            self.write(&command.encode()).await?;
+        }
+        Ok(())
+    }
+
+    // Every notification from the vehicle is passed to `callback` as a raw frame, ready for
+    // the scroll readers in `protocol`. Replaces any previously registered callback.
+    pub async fn subscribe<F: FnMut(Vec<u8>) + 'static>(
+        &mut self,
+        mut callback: F,
+    ) -> Result<(), JsValue> {
+        let read_chr = self.read_chr.clone();
+        let on_notify = Closure::<dyn FnMut(JsValue)>::new(move |_event: JsValue| {
+            if let Some(value) = read_chr.value() {
+                let bytes = Uint8Array::new_with_byte_offset_and_length(
+                    &value.buffer(),
+                    value.byte_offset() as u32,
+                    value.byte_length() as u32,
+                );
+                callback(bytes.to_vec());
+            }
+        });
+        self.read_chr
+            .set_oncharacteristicvaluechanged(Some(on_notify.as_ref().unchecked_ref()));
+        self.on_notify = Some(on_notify);
+        self.read_chr.start_notifications().await?;
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        self.read_chr.set_oncharacteristicvaluechanged(None);
+        self.on_notify = None;
+        self.server.disconnect();
+    }
+}